@@ -0,0 +1,226 @@
+//! Queries derived from the observed data
+//!
+//! The checks of the endpoints tell whether the records match the model, not
+//! whether the beacon finds them when asked. With `--adaptive`, the verifier
+//! derives realistic queries from the first record of each entity: a filter on
+//! one of its ontology terms (e.g. the sex of an individual) and, for the
+//! genomic variants, a range query at their position. The record must be among
+//! the results, which closes the loop between the data a beacon exposes and the
+//! answers to its queries.
+
+use std::collections::BTreeSet;
+
+use url::Url;
+
+use crate::client::Client;
+use crate::error::VerifierError;
+use crate::model::Entity;
+use crate::output::{Category, EndpointReport};
+use crate::{formats, utils, Json};
+
+/// Name of the checks, after the name of the entity
+pub const CHECK_NAMES: [&str; 2] = ["findable by filter", "findable by position"];
+
+/// Id of the checks in the catalog
+const CHECK_IDS: [&str; 2] = ["framework.adaptive.filter", "framework.adaptive.position"];
+
+/// First ontology term of `json` (an object with a CURIE `id`, e.g. `{"id": "NCIT:C16576", "label": "female"}`)
+fn term(json: &Json) -> Option<String> {
+	match json {
+		Json::Object(object) => object
+			.get("id")
+			.and_then(Json::as_str)
+			.filter(|id| formats::is_curie(id))
+			.map(String::from)
+			.or_else(|| object.values().find_map(term)),
+		Json::Array(values) => values.iter().find_map(term),
+		_ => None,
+	}
+}
+
+/// Ontology term of a record to filter on, not its own id
+fn filter(record: &Json, id_field: &str) -> Option<String> {
+	record
+		.as_object()?
+		.iter()
+		.filter(|(property, _)| *property != id_field)
+		.find_map(|(_, value)| term(value))
+}
+
+/// Reference name, start and end of a genomic variant (VRS location of its `variation`)
+pub fn location(record: &Json) -> Option<(String, u64, u64)> {
+	let location = record.pointer("/variation/location")?;
+	// e.g. `refseq:NC_000001.11` or `HGVSid:1:g.12345A>G`
+	let sequence_id = location.get("sequence_id")?.as_str()?;
+	let reference_name = sequence_id.split(':').nth(1).unwrap_or(sequence_id);
+	let start = location.pointer("/interval/start/value")?.as_u64()?;
+	let end = location
+		.pointer("/interval/end/value")
+		.and_then(Json::as_u64)
+		.unwrap_or(start + 1);
+	Some((reference_name.to_string(), start, end))
+}
+
+/// Parameters of a range query at the position of a genomic variant
+fn position(record: &Json) -> Option<Vec<(&'static str, String)>> {
+	let (reference_name, start, end) = location(record)?;
+	Some(vec![
+		("referenceName", reference_name),
+		("start", start.to_string()),
+		("end", end.to_string()),
+	])
+}
+
+/// Derives queries from the first record of the endpoint with all the entries of `entity`, which must find it
+pub fn check(client: &Client, root_url: &Url, entity: &Entity) -> Vec<EndpointReport> {
+	let url = utils::url_join(root_url, &entity.url);
+	let report = |check: usize, url: &Url| {
+		EndpointReport::new(
+			&entity.name,
+			&format!("{} {}", entity.name, CHECK_NAMES[check]),
+			url.clone(),
+		)
+		.category(Category::Framework)
+		.check(CHECK_IDS[check])
+	};
+	let sample = client
+		.query(&entity.name, &url)
+		.and_then(|response| utils::records(&response.json))
+		.map(|records| {
+			records
+				.into_iter()
+				.find_map(|record| utils::record_id(&record, &entity.id_field).map(|id| (id, record)))
+		});
+	let (id, record) = match sample {
+		Ok(Some(sample)) => sample,
+		Ok(None) => return vec![report(0, &url).skip("no records to derive queries from", Json::Null)],
+		Err(e) => return vec![report(0, &url).null(e)],
+	};
+
+	let mut reports = Vec::new();
+	match filter(&record, &entity.id_field) {
+		Some(term) => {
+			let mut url = url.clone();
+			url.query_pairs_mut().append_pair("filters", &term);
+			reports.push(find(client, entity, report(0, &url), &url, &id));
+		},
+		None => {
+			let report = report(0, &url);
+			reports.push(report.skip("no ontology term in the sampled record", record.clone()));
+		},
+	}
+	if let Some(params) = position(&record) {
+		let mut url = url.clone();
+		for (key, value) in params {
+			url.query_pairs_mut().append_pair(key, &value);
+		}
+		reports.push(find(client, entity, report(1, &url), &url, &id));
+	}
+	reports
+}
+
+/// Runs the derived query, which must find the record `id`
+fn find(client: &Client, entity: &Entity, report: EndpointReport, url: &Url, id: &str) -> EndpointReport {
+	log::info!("Validating {:?}", report.name);
+	let answer = client.get_raw(url);
+	let json = match answer {
+		Ok((status, Some(json))) if status.is_success() => json,
+		Ok((status, _)) => {
+			return report.error(VerifierError::NotFindable(format!(
+				"the query was not answered (status {})",
+				status
+			)))
+		},
+		Err(e) => return report.null(e),
+	};
+	let records = utils::records(&json).unwrap_or_default();
+	let ids = records
+		.iter()
+		.filter_map(|record| utils::record_id(record, &entity.id_field))
+		.collect::<BTreeSet<_>>();
+	let total = json.pointer("/responseSummary/numTotalResults").and_then(Json::as_u64);
+	let exists = json.pointer("/responseSummary/exists").and_then(Json::as_bool);
+	if ids.contains(id) {
+		report.ok(None)
+	}
+	else if records.is_empty() && exists == Some(true) {
+		// Only a boolean or a count at the granularity granted to the verifier
+		report.ok(None)
+	}
+	else if total.map_or(false, |total| total > records.len() as u64) {
+		report.skip("the record may be on a later page of the results", json)
+	}
+	else {
+		report.error(VerifierError::NotFindable(format!(
+			"'{}' is not among the {} results of the query",
+			id,
+			records.len()
+		)))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use serde_json::json;
+	use wiremock::{Request, ResponseTemplate};
+
+	use crate::adaptive::{self, CHECK_NAMES};
+	use crate::output::{BeaconOutput, EndpointOutput};
+	use crate::testing::{Behavior, MockBeacon};
+
+	fn report<'a>(output: &'a BeaconOutput, entity: &str, name: &str) -> &'a EndpointOutput {
+		output.entities[entity]
+			.iter()
+			.find(|report| report.name == format!("{} {}", entity, name))
+			.unwrap()
+	}
+
+	#[test]
+	fn test_derived_queries() {
+		let record = json!({
+			"id": "var1",
+			"variation": {
+				"location": {
+					"sequence_id": "refseq:NC_000017.11",
+					"interval": { "start": { "value": 43044294 }, "end": { "value": 43044295 } },
+				},
+			},
+			"caseLevelData": [{
+				"biosampleId": "bio1",
+				"zygosity": { "id": "GENO:0000458", "label": "simple heterozygous" },
+			}],
+		});
+		assert_eq!(adaptive::filter(&record, "id"), Some("GENO:0000458".into()));
+		assert_eq!(adaptive::filter(&json!({ "id": "EGA:EGAN00001" }), "id"), None);
+		let params = adaptive::position(&record).unwrap();
+		assert_eq!(params[0], ("referenceName", "NC_000017.11".into()));
+		assert_eq!(params[1], ("start", "43044294".into()));
+		assert!(adaptive::position(&json!({ "id": "ind1" })).is_none());
+	}
+
+	#[test]
+	fn test_findable() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let output = beacon.beacon().adaptive().validate();
+		let individual = report(&output, "Individual", CHECK_NAMES[0]);
+		assert_eq!(individual.valid, Some(true), "{:?}", individual.error);
+		assert!(individual.url.query().unwrap().contains("filters=NCIT%3AC16576"));
+		assert_eq!(report(&output, "Biosample", CHECK_NAMES[0]).valid, Some(true));
+		assert!(report(&output, "Dataset", CHECK_NAMES[0]).skipped);
+
+		// The filter finds nothing
+		let individuals = beacon.response("/individuals");
+		beacon.mount("/individuals", move |request: &Request| {
+			let mut response = individuals.clone();
+			if request.url.query_pairs().any(|(key, _)| key == "filters") {
+				response["responseSummary"] = json!({ "exists": false, "numTotalResults": 0 });
+				response["response"]["resultSets"][0]["results"] = json!([]);
+			}
+			ResponseTemplate::new(200).set_body_json(response)
+		});
+		let output = beacon.beacon().adaptive().validate();
+		let e = report(&output, "Individual", CHECK_NAMES[0]).error.clone().unwrap();
+		assert_eq!(e, "Record not findable: 'ind1' is not among the 0 results of the query");
+	}
+}
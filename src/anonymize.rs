@@ -0,0 +1,222 @@
+//! Anonymization of the record ids in the logs
+//!
+//! Sample identifiers can be sensitive by themselves. With `--anonymize-ids`,
+//! the ids harvested from the beacon are replaced by a short hash in the
+//! console logs (progress, errors and summary). The hash is stable across runs,
+//! so the lines of a record can still be correlated. The log sites display the
+//! ids, and the urls holding them, through [`id`] and [`url`], which only hash
+//! them while the console logger formats its lines: the reports and the HTTP
+//! log file (`--http-log`), meant for debugging, keep the full ids. The errors
+//! of the summary, which quote the responses, are replaced by their public
+//! message.
+
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use url::Url;
+
+use crate::utils;
+
+thread_local! {
+	/// Anonymizer of the line the console logger is formatting on this thread, if any
+	static FORMATTING: RefCell<Option<Anonymizer>> = RefCell::new(None);
+}
+
+/// How an id is displayed once anonymized (e.g. `id:5d1c0f2a`)
+pub fn display_id(id: &str) -> String {
+	format!("id:{:08x}", utils::fnv1a(id.as_bytes()) >> 32)
+}
+
+/// Ids harvested from the beacon, shared by the beacon that harvests them and the logger
+#[derive(Debug, Clone, Default)]
+pub struct Anonymizer {
+	ids: Arc<Mutex<BTreeSet<String>>>,
+}
+
+impl Anonymizer {
+	pub fn register(&self, id: &str) {
+		self.ids
+			.lock()
+			.unwrap_or_else(|e| e.into_inner())
+			.insert(id.to_string());
+	}
+
+	fn is_registered(&self, id: &str) -> bool {
+		self.ids.lock().unwrap_or_else(|e| e.into_inner()).contains(id)
+	}
+
+	/// Runs `format` with the ids anonymized, for the console logger
+	pub fn anonymized<T>(&self, format: impl FnOnce() -> T) -> T {
+		FORMATTING.with(|formatting| *formatting.borrow_mut() = Some(self.clone()));
+		let formatted = format();
+		FORMATTING.with(|formatting| *formatting.borrow_mut() = None);
+		formatted
+	}
+}
+
+fn anonymizing() -> bool {
+	FORMATTING.with(|formatting| formatting.borrow().is_some())
+}
+
+/// Whether `value` is a harvested id, while a line is anonymized
+fn is_registered(value: &str) -> bool {
+	FORMATTING.with(|formatting| {
+		formatting
+			.borrow()
+			.as_ref()
+			.map_or(false, |anonymizer| anonymizer.is_registered(value))
+	})
+}
+
+/// Id of a record, as the logs display it
+pub struct Id<'a>(&'a str);
+
+impl fmt::Display for Id<'_> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		if anonymizing() {
+			f.write_str(&display_id(self.0))
+		}
+		else {
+			f.write_str(self.0)
+		}
+	}
+}
+
+pub fn id(id: &str) -> Id<'_> {
+	Id(id)
+}
+
+/// Url, as the logs display it: the segments of its path and the values of its query that are harvested ids are
+/// anonymized
+pub struct AnonymizedUrl<'a>(&'a Url);
+
+impl fmt::Display for AnonymizedUrl<'_> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		if !anonymizing() {
+			return write!(f, "{}", self.0);
+		}
+		let anonymize = |value: &str| {
+			if is_registered(value) {
+				display_id(value)
+			}
+			else {
+				value.to_string()
+			}
+		};
+		let mut url = self.0.clone();
+		let segments = self.0.path_segments().map(|segments| {
+			segments
+				.map(|segment| {
+					let decoded = percent_encoding::percent_decode_str(segment).decode_utf8_lossy();
+					if is_registered(&decoded) {
+						display_id(&decoded)
+					}
+					else {
+						segment.to_string()
+					}
+				})
+				.collect::<Vec<_>>()
+		});
+		if let Some(segments) = segments {
+			url.set_path(&segments.join("/"));
+		}
+		if self.0.query().is_some() {
+			let pairs = self
+				.0
+				.query_pairs()
+				.map(|(name, value)| (name.into_owned(), anonymize(&value)))
+				.collect::<Vec<_>>();
+			url.query_pairs_mut().clear().extend_pairs(pairs);
+		}
+		write!(f, "{}", url)
+	}
+}
+
+pub fn url(url: &Url) -> AnonymizedUrl<'_> {
+	AnonymizedUrl(url)
+}
+
+/// Error of a request, as the logs display it, with its url anonymized
+pub struct RequestError<'a>(&'a reqwest::Error);
+
+impl fmt::Display for RequestError<'_> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self.0.url() {
+			Some(request_url) if anonymizing() => {
+				let message = self.0.to_string();
+				f.write_str(&message.replace(request_url.as_str(), &url(request_url).to_string()))
+			},
+			_ => write!(f, "{}", self.0),
+		}
+	}
+}
+
+pub fn request_error(e: &reqwest::Error) -> RequestError<'_> {
+	RequestError(e)
+}
+
+/// Text quoting the responses, as the logs display it: its public form once anonymized
+pub struct Excerpt<'a> {
+	text: &'a str,
+	public: &'a str,
+}
+
+impl fmt::Display for Excerpt<'_> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(if anonymizing() { self.public } else { self.text })
+	}
+}
+
+pub fn excerpt<'a>(text: &'a str, public: &'a str) -> Excerpt<'a> {
+	Excerpt { text, public }
+}
+
+#[cfg(test)]
+mod tests {
+
+	use url::Url;
+
+	use crate::anonymize::{self, display_id, Anonymizer};
+	use crate::testing::{Behavior, MockBeacon};
+
+	#[test]
+	fn test_display() {
+		let anonymizer = Anonymizer::default();
+		anonymizer.register("ind1");
+		anonymizer.register("ind 10");
+		let url = Url::parse("https://beacon.example.org/api/individuals/ind1/biosamples?id=ind%2010&skip=1").unwrap();
+		let line = || format!("Querying {} for {}", anonymize::url(&url), anonymize::id("bio2"));
+
+		assert_eq!(line(), format!("Querying {} for bio2", url));
+		assert_eq!(
+			anonymizer.anonymized(line),
+			format!(
+				"Querying https://beacon.example.org/api/individuals/{}/biosamples?id={}&skip=1 for {}",
+				display_id("ind1"),
+				display_id("ind 10").replace(':', "%3A"),
+				display_id("bio2")
+			)
+		);
+		assert_ne!(display_id("ind1"), display_id("ind10"));
+		assert_eq!(display_id("ind1").len(), 11);
+	}
+
+	#[test]
+	fn test_harvested_ids() {
+		let anonymizer = Anonymizer::default();
+		MockBeacon::start(Behavior::Conformant)
+			.beacon()
+			.anonymizer(anonymizer.clone())
+			.validate();
+		let url = Url::parse("http://localhost/individuals/ind1?skip=0").unwrap();
+		let line = anonymizer.anonymized(|| anonymize::url(&url).to_string());
+		assert_eq!(
+			line,
+			format!("http://localhost/individuals/{}?skip=0", display_id("ind1"))
+		);
+		let error = anonymize::excerpt("Id ind1 is not listed", "Inconsistent ids");
+		assert_eq!(anonymizer.anonymized(|| error.to_string()), "Inconsistent ids");
+	}
+}
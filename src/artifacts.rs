@@ -0,0 +1,241 @@
+//! Files written by a run
+//!
+//! The files are written next to their final path, then renamed over it, so a
+//! crash never leaves a truncated report, history or state for the automation
+//! reading them. With `--output-dir`, the artifacts of every run go to a
+//! directory of their own, named after the start of the run: it is filled as
+//! a hidden `.<run>.partial` directory, renamed when the run completes, and
+//! `latest` then points to it. `--keep-runs` removes the oldest complete runs.
+//! The partial directories of the runs started over a day earlier were left by
+//! a crash and are removed when a new run starts.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+
+use crate::error::VerifierError;
+
+/// Link to the last complete run of an output directory
+pub const LATEST: &str = "latest";
+
+const PARTIAL_SUFFIX: &str = ".partial";
+
+const TIMESTAMP_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+/// Age of the partial directory of a run after which it was left by a crash
+const STALE_PARTIAL_HOURS: i64 = 24;
+
+/// Writes `contents` to a temporary file next to `path`, then renames it to `path`
+pub fn write_atomic(path: &Path, contents: &[u8]) -> Result<(), VerifierError> {
+	let file_name = path.file_name().and_then(|name| name.to_str()).unwrap_or("output");
+	let temporary = path.with_file_name(format!(".{}.tmp", file_name));
+	fs::write(&temporary, contents)?;
+	fs::rename(&temporary, path).map_err(|e| {
+		let _ = fs::remove_file(&temporary);
+		e.into()
+	})
+}
+
+/// Directory of the artifacts of one run, under the output directory
+pub struct RunDirectory {
+	root: PathBuf,
+	name: String,
+}
+
+impl RunDirectory {
+	/// Creates the partial directory of the run started at `started`
+	pub fn create(root: &Path, started: DateTime<Utc>) -> Result<Self, VerifierError> {
+		fs::create_dir_all(root)?;
+		remove_stale_partials(root, started)?;
+		let timestamp = started.format(TIMESTAMP_FORMAT).to_string();
+		let name = (1..)
+			.map(|n| {
+				if n == 1 {
+					timestamp.clone()
+				}
+				else {
+					format!("{}-{}", timestamp, n)
+				}
+			})
+			.find(|name| !root.join(name).exists() && !root.join(partial(name)).exists())
+			.unwrap_or(timestamp);
+		fs::create_dir(root.join(partial(&name)))?;
+		Ok(Self {
+			root: root.to_path_buf(),
+			name,
+		})
+	}
+
+	/// Path of an artifact of the run, for the artifacts written by their own module
+	pub fn path(&self, file_name: &str) -> PathBuf {
+		self.root.join(partial(&self.name)).join(file_name)
+	}
+
+	/// Writes an artifact of the run
+	pub fn write(&self, file_name: &str, contents: &[u8]) -> Result<(), VerifierError> {
+		write_atomic(&self.path(file_name), contents)
+	}
+
+	/// Completes the run, pointing `latest` to it and keeping only the last `keep` runs, returning its directory
+	pub fn finish(self, keep: Option<usize>) -> Result<PathBuf, VerifierError> {
+		let directory = self.root.join(&self.name);
+		fs::rename(self.root.join(partial(&self.name)), &directory)?;
+		link_latest(&self.root, &self.name)?;
+		if let Some(keep) = keep {
+			prune(&self.root, keep)?;
+		}
+		Ok(directory)
+	}
+}
+
+fn partial(name: &str) -> String {
+	format!(".{}{}", name, PARTIAL_SUFFIX)
+}
+
+/// Start of the run named `name`, and its number among the runs started in the same second
+fn parse_name(name: &str) -> Option<(NaiveDateTime, usize)> {
+	let (timestamp, n) = match name.split_once('-') {
+		Some((timestamp, n)) => (timestamp, n.parse().ok()?),
+		None => (name, 1),
+	};
+	Some((NaiveDateTime::parse_from_str(timestamp, TIMESTAMP_FORMAT).ok()?, n))
+}
+
+/// Removes the partial directories of the runs started long before `started`
+fn remove_stale_partials(root: &Path, started: DateTime<Utc>) -> Result<(), VerifierError> {
+	let stale = started.naive_utc() - Duration::hours(STALE_PARTIAL_HOURS);
+	for entry in fs::read_dir(root)?.filter_map(Result::ok) {
+		let file_name = entry.file_name();
+		let name = file_name
+			.to_str()
+			.and_then(|name| name.strip_prefix('.'))
+			.and_then(|name| name.strip_suffix(PARTIAL_SUFFIX));
+		if let Some((run_started, _)) = name.and_then(parse_name) {
+			if run_started < stale {
+				log::debug!(
+					"Removing the partial run {} of {}",
+					entry.path().display(),
+					root.display()
+				);
+				fs::remove_dir_all(entry.path())?;
+			}
+		}
+	}
+	Ok(())
+}
+
+#[cfg(unix)]
+fn link_latest(root: &Path, name: &str) -> Result<(), VerifierError> {
+	let temporary = root.join(format!(".{}.tmp", LATEST));
+	let _ = fs::remove_file(&temporary);
+	std::os::unix::fs::symlink(name, &temporary)?;
+	Ok(fs::rename(&temporary, root.join(LATEST))?)
+}
+
+/// Without symbolic links, `latest` is a file holding the name of the run
+#[cfg(not(unix))]
+fn link_latest(root: &Path, name: &str) -> Result<(), VerifierError> {
+	write_atomic(&root.join(LATEST), format!("{}\n", name).as_bytes())
+}
+
+/// Names of the complete runs of the output directory, the oldest first
+pub fn runs(root: &Path) -> Result<Vec<String>, VerifierError> {
+	let mut runs = fs::read_dir(root)?
+		.filter_map(Result::ok)
+		.filter(|entry| entry.file_type().map_or(false, |file_type| file_type.is_dir()))
+		.filter_map(|entry| entry.file_name().into_string().ok())
+		.filter(|name| !name.starts_with('.') && name != LATEST)
+		.collect::<Vec<_>>();
+	// By start, then by number: the 10th run of a second comes after the 2nd
+	runs.sort_by_cached_key(|name| (parse_name(name), name.clone()));
+	Ok(runs)
+}
+
+/// Removes the oldest complete runs, keeping the last `keep` ones
+fn prune(root: &Path, keep: usize) -> Result<(), VerifierError> {
+	let runs = runs(root)?;
+	for name in &runs[..runs.len().saturating_sub(keep)] {
+		log::debug!("Removing the run {} of {}", name, root.display());
+		fs::remove_dir_all(root.join(name))?;
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+	use std::fs;
+
+	use chrono::{TimeZone, Utc};
+
+	use crate::artifacts::{self, RunDirectory, LATEST};
+
+	#[test]
+	fn test_write_atomic() {
+		let directory = tempfile::tempdir().unwrap();
+		let path = directory.path().join("report.json");
+		artifacts::write_atomic(&path, b"{}\n").unwrap();
+		artifacts::write_atomic(&path, b"[]\n").unwrap();
+		assert_eq!(fs::read(&path).unwrap(), b"[]\n");
+		assert_eq!(fs::read_dir(directory.path()).unwrap().count(), 1);
+	}
+
+	#[test]
+	fn test_run_directories() {
+		let directory = tempfile::tempdir().unwrap();
+		let root = directory.path().join("runs");
+		let started = Utc.ymd(2022, 5, 1).and_hms(12, 0, 0);
+
+		let run = RunDirectory::create(&root, started).unwrap();
+		run.write("report.json", b"{}\n").unwrap();
+		// Nothing complete before the end of the run
+		assert!(artifacts::runs(&root).unwrap().is_empty());
+		assert!(root.join(".20220501T120000Z.partial/report.json").is_file());
+		let first = run.finish(None).unwrap();
+		assert_eq!(first, root.join("20220501T120000Z"));
+		assert_eq!(fs::read(root.join(LATEST).join("report.json")).unwrap(), b"{}\n");
+
+		// Same second
+		let second = RunDirectory::create(&root, started).unwrap().finish(Some(2)).unwrap();
+		assert_eq!(second, root.join("20220501T120000Z-2"));
+		let run = RunDirectory::create(&root, started + chrono::Duration::hours(1)).unwrap();
+		run.write("report.html", b"<html></html>\n").unwrap();
+		run.finish(Some(2)).unwrap();
+		assert_eq!(
+			artifacts::runs(&root).unwrap(),
+			vec!["20220501T120000Z-2".to_string(), "20220501T130000Z".to_string()]
+		);
+		assert!(root.join(LATEST).join("report.html").is_file());
+	}
+
+	#[test]
+	fn test_run_order() {
+		let directory = tempfile::tempdir().unwrap();
+		let started = Utc.ymd(2022, 5, 1).and_hms(12, 0, 0);
+		for _ in 0..10 {
+			RunDirectory::create(directory.path(), started)
+				.unwrap()
+				.finish(None)
+				.unwrap();
+		}
+		let runs = artifacts::runs(directory.path()).unwrap();
+		assert_eq!(runs[1], "20220501T120000Z-2");
+		assert_eq!(runs[9], "20220501T120000Z-10");
+	}
+
+	#[test]
+	fn test_stale_partials() {
+		let directory = tempfile::tempdir().unwrap();
+		let root = directory.path();
+		let started = Utc.ymd(2022, 5, 1).and_hms(12, 0, 0);
+		// A crashed run, and one still running
+		let crashed = RunDirectory::create(root, started).unwrap();
+		crashed.write("report.json", b"{}\n").unwrap();
+		RunDirectory::create(root, started + chrono::Duration::hours(30)).unwrap();
+		let run = RunDirectory::create(root, started + chrono::Duration::hours(31)).unwrap();
+		assert!(!root.join(".20220501T120000Z.partial").exists());
+		assert!(root.join(".20220502T180000Z.partial").is_dir());
+		run.finish(None).unwrap();
+	}
+}
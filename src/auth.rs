@@ -0,0 +1,480 @@
+//! Credentials of the beacons: static tokens, basic authentication, API keys
+//! and OAuth2 access tokens
+//!
+//! A static bearer token (`--token`) or basic authentication credentials
+//! (`--basic-auth`) are sent as is with every request.
+//!
+//! An API key is sent with every request in a header, a cookie or, for some
+//! legacy deployments, a query parameter (e.g. `?apikey=`). It is added when the
+//! request is sent, so it never shows up in the urls of the report, and it is
+//! masked in every log.
+//!
+//! OAuth2 access tokens are refreshed whenever the beacon answers 401
+//! (Unauthorized), and before they expire when the identity provider tells
+//! when (`expires_in`), so long runs are not interrupted. With a refresh token
+//! the `refresh_token` grant is used, otherwise the `client_credentials` grant.
+//! Every refresh is recorded so it shows up in the report of the run.
+
+use std::collections::BTreeSet;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::{NaiveDateTime, SubsecRound};
+use reqwest::header::{HeaderName, HeaderValue, COOKIE};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::error::VerifierError;
+
+/// What the secrets are replaced with in the logs
+pub const MASK: &str = "<redacted>";
+
+/// Access tokens are refreshed when they expire within this time
+const EXPIRY_MARGIN: Duration = Duration::from_secs(30);
+
+/// Credentials of the basic authentication scheme
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BasicAuth {
+	pub username: String,
+	pub password: Option<String>,
+}
+
+impl FromStr for BasicAuth {
+	type Err = String;
+
+	/// Parses `USERNAME:PASSWORD`, or `USERNAME` without a password
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let (username, password) = match s.split_once(':') {
+			Some((username, password)) => (username, Some(password.to_string())),
+			None => (s, None),
+		};
+		if username.is_empty() {
+			return Err("expected USERNAME:PASSWORD".into());
+		}
+		Ok(Self {
+			username: username.to_string(),
+			password,
+		})
+	}
+}
+
+/// Static credentials sent with every request
+#[derive(Debug, Clone, PartialEq)]
+pub enum Credentials {
+	Bearer(String),
+	Basic(BasicAuth),
+}
+
+/// Where the API key is sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ApiKeyStyle {
+	Query,
+	Cookie,
+	Header,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ApiKey {
+	pub style: ApiKeyStyle,
+	/// Name of the query parameter, cookie or header (e.g. `apikey`)
+	pub name: String,
+	pub key: String,
+}
+
+impl ApiKey {
+	/// Header carrying the key, none if it is sent in the query
+	pub fn header(&self) -> Result<Option<(HeaderName, HeaderValue)>, VerifierError> {
+		let (name, value) = match self.style {
+			ApiKeyStyle::Query => return Ok(None),
+			ApiKeyStyle::Cookie => (COOKIE, format!("{}={}", self.name, self.key)),
+			ApiKeyStyle::Header => {
+				let name = HeaderName::from_bytes(self.name.as_bytes())
+					.map_err(|_| VerifierError::BadConfig(format!("invalid header name '{}'", self.name)))?;
+				(name, self.key.clone())
+			},
+		};
+		let mut value = HeaderValue::from_str(&value)
+			.map_err(|_| VerifierError::BadConfig(format!("invalid API key for '{}'", self.name)))?;
+		value.set_sensitive(true);
+		Ok(Some((name, value)))
+	}
+
+	/// Name and value of the query parameter carrying the key, if it is sent in the query
+	pub fn query_parameter(&self) -> Option<(&str, &str)> {
+		(self.style == ApiKeyStyle::Query).then(|| (self.name.as_str(), self.key.as_str()))
+	}
+}
+
+/// Secrets of the configuration, masked in every log
+#[derive(Debug, Clone, Default)]
+pub struct Secrets {
+	secrets: Arc<Mutex<BTreeSet<String>>>,
+}
+
+impl Secrets {
+	/// Masks `secret`, as is and as encoded in the urls
+	pub fn register(&self, secret: &str) {
+		if let Ok(mut secrets) = self.secrets.lock() {
+			let encoded = url::form_urlencoded::byte_serialize(secret.as_bytes()).collect::<String>();
+			secrets.extend(
+				[secret.to_string(), encoded]
+					.into_iter()
+					.filter(|secret| !secret.is_empty()),
+			);
+		}
+	}
+
+	pub fn mask(&self, text: &str) -> String {
+		let mut secrets = match self.secrets.lock() {
+			Ok(secrets) => secrets.iter().cloned().collect::<Vec<_>>(),
+			Err(_) => return text.to_string(),
+		};
+		// Longest first, so a secret does not mask the beginning of another one
+		secrets.sort_by_key(|secret| std::cmp::Reverse(secret.len()));
+		secrets
+			.iter()
+			.fold(text.to_string(), |text, secret| text.replace(secret.as_str(), MASK))
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct AuthConfig {
+	/// Token endpoint of the identity provider
+	pub token_url: Url,
+	pub client_id: String,
+	pub client_secret: Option<String>,
+	pub refresh_token: Option<String>,
+	pub scope: Option<String>,
+}
+
+/// Refresh of the access token, triggered by a 401 response or by its expiry
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenRefresh {
+	pub at: NaiveDateTime,
+	/// Endpoint that answered 401, or about to be queried when the token was expiring
+	pub endpoint: String,
+	pub ok: bool,
+	pub error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+	access_token: String,
+	refresh_token: Option<String>,
+	/// Lifetime of the access token, in seconds
+	expires_in: Option<u64>,
+}
+
+/// Tokens shared by the workers querying the beacon
+pub struct TokenSource {
+	config: AuthConfig,
+	access_token: Mutex<Option<String>>,
+	/// When the access token expires, if the identity provider told
+	expires_at: Mutex<Option<Instant>>,
+	refresh_token: Mutex<Option<String>>,
+	/// When the access token was last got
+	refreshed_at: Mutex<Option<Instant>>,
+	refreshes: Mutex<Vec<TokenRefresh>>,
+}
+
+impl TokenSource {
+	pub fn new(config: AuthConfig) -> Self {
+		Self {
+			refresh_token: Mutex::new(config.refresh_token.clone()),
+			config,
+			access_token: Mutex::new(None),
+			expires_at: Mutex::new(None),
+			refreshed_at: Mutex::new(None),
+			refreshes: Mutex::new(Vec::new()),
+		}
+	}
+
+	/// Access token got last, without refreshing it
+	pub fn current(&self) -> Option<String> {
+		self.access_token.lock().ok().and_then(|token| token.clone())
+	}
+
+	/// Access token to query `endpoint` with, refreshed first if it is about to expire
+	pub fn access_token(&self, http: &reqwest::blocking::Client, endpoint: &str) -> Option<String> {
+		let now = Instant::now();
+		let expiring = self
+			.expires_at
+			.lock()
+			.ok()
+			.and_then(|expires_at| *expires_at)
+			.map_or(false, |expires_at| expires_at <= now + EXPIRY_MARGIN);
+		if expiring {
+			// On failure the expired token gets a 401, which is handled as usual
+			let _ = self.request_refresh(http, endpoint, now, "before it expires");
+		}
+		self.access_token.lock().ok().and_then(|token| token.clone())
+	}
+
+	/// Gets a new access token after `endpoint` answered 401 to the request sent at `sent`, recording the refresh
+	pub fn refresh(
+		&self,
+		http: &reqwest::blocking::Client,
+		endpoint: &str,
+		sent: Instant,
+	) -> Result<(), VerifierError> {
+		self.request_refresh(http, endpoint, sent, &format!("{} answered 401", endpoint))
+	}
+
+	/// Gets a new access token, unless one was got since `needed`
+	fn request_refresh(
+		&self,
+		http: &reqwest::blocking::Client,
+		endpoint: &str,
+		needed: Instant,
+		reason: &str,
+	) -> Result<(), VerifierError> {
+		// One refresh at a time, even when several workers need one
+		let mut refresh_token = self.refresh_token.lock().map_err(|_| {
+			VerifierError::TokenRefresh("another refresh of the access token failed unexpectedly".into())
+		})?;
+		// The workers that waited for the refresh of another one use its token
+		let refreshed_at = *self.refreshed_at.lock().unwrap_or_else(|e| e.into_inner());
+		if refreshed_at.map_or(false, |refreshed_at| refreshed_at > needed) {
+			return Ok(());
+		}
+		log::info!("Refreshing the access token ({})", reason);
+		let result = self.request_token(http, refresh_token.clone());
+		if let Ok(mut refreshes) = self.refreshes.lock() {
+			refreshes.push(TokenRefresh {
+				at: chrono::offset::Utc::now().naive_utc().round_subsecs(6),
+				endpoint: endpoint.to_string(),
+				ok: result.is_ok(),
+				error: result.as_ref().err().map(ToString::to_string),
+			});
+		}
+		let token = result?;
+		if let Ok(mut access_token) = self.access_token.lock() {
+			*access_token = Some(token.access_token);
+		}
+		if let Ok(mut expires_at) = self.expires_at.lock() {
+			*expires_at = token
+				.expires_in
+				.map(|expires_in| Instant::now() + Duration::from_secs(expires_in));
+		}
+		*self.refreshed_at.lock().unwrap_or_else(|e| e.into_inner()) = Some(Instant::now());
+		// Providers may rotate the refresh tokens
+		if token.refresh_token.is_some() {
+			*refresh_token = token.refresh_token;
+		}
+		Ok(())
+	}
+
+	fn request_token(
+		&self,
+		http: &reqwest::blocking::Client,
+		refresh_token: Option<String>,
+	) -> Result<TokenResponse, VerifierError> {
+		let mut form = vec![("client_id", self.config.client_id.clone())];
+		match refresh_token {
+			Some(refresh_token) => {
+				form.push(("grant_type", "refresh_token".into()));
+				form.push(("refresh_token", refresh_token));
+			},
+			None => form.push(("grant_type", "client_credentials".into())),
+		}
+		if let Some(client_secret) = &self.config.client_secret {
+			form.push(("client_secret", client_secret.clone()));
+		}
+		if let Some(scope) = &self.config.scope {
+			form.push(("scope", scope.clone()));
+		}
+
+		let response = http.post(self.config.token_url.clone()).form(&form).send()?;
+		if !response.status().is_success() {
+			return Err(VerifierError::TokenRefresh(format!(
+				"the token endpoint answered {}",
+				response.status()
+			)));
+		}
+		response
+			.json()
+			.map_err(|e| VerifierError::TokenRefresh(format!("bad token response ({})", e)))
+	}
+
+	pub fn refreshes(&self) -> Vec<TokenRefresh> {
+		self.refreshes
+			.lock()
+			.map(|refreshes| refreshes.clone())
+			.unwrap_or_default()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use url::Url;
+	use wiremock::matchers::{body_string_contains, header, method, path, query_param};
+	use wiremock::{Mock, ResponseTemplate};
+
+	use crate::auth::{AuthConfig, BasicAuth, Secrets, MASK};
+	use crate::client::Client;
+	use crate::testing::{Behavior, MockBeacon};
+
+	fn protected_beacon() -> MockBeacon {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let info = beacon.response("/info");
+		// Mocks of the same priority match in the order they are mounted
+		beacon.mount_mock(
+			Mock::given(method("GET"))
+				.and(path("/info"))
+				.and(header("authorization", "Bearer fresh"))
+				.respond_with(ResponseTemplate::new(200).set_body_json(info))
+				.with_priority(1),
+		);
+		beacon.mount("/info", ResponseTemplate::new(401));
+		beacon
+	}
+
+	fn auth(beacon: &MockBeacon) -> AuthConfig {
+		AuthConfig {
+			token_url: beacon.url().join("token").unwrap(),
+			client_id: "verifier".into(),
+			client_secret: None,
+			refresh_token: Some("refresh".into()),
+			scope: None,
+		}
+	}
+
+	#[test]
+	fn test_refresh_on_unauthorized() {
+		let beacon = protected_beacon();
+		beacon.mount_mock(
+			Mock::given(method("POST"))
+				.and(path("/token"))
+				.and(body_string_contains("grant_type=refresh_token"))
+				.respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "access_token": "fresh" }))),
+		);
+		let client = Client::new().auth(auth(&beacon));
+		assert!(client.query("Info", &beacon.url().join("info").unwrap()).is_ok());
+		// The new token is kept for the next requests
+		assert!(client.query("Info", &beacon.url().join("info").unwrap()).is_ok());
+		let refreshes = client.token_refreshes();
+		assert_eq!(refreshes.len(), 1);
+		assert!(refreshes[0].ok);
+		assert_eq!(refreshes[0].endpoint, "Info");
+	}
+
+	#[test]
+	fn test_failed_refresh() {
+		let beacon = protected_beacon();
+		beacon.mount_method("POST", "/token", ResponseTemplate::new(400));
+		let client = Client::new().auth(auth(&beacon));
+		assert!(client.query("Info", &beacon.url().join("info").unwrap()).is_err());
+		let refreshes = client.token_refreshes();
+		assert_eq!(refreshes.len(), 1);
+		assert!(!refreshes[0].ok);
+		assert!(refreshes[0].error.as_ref().unwrap().contains("400"));
+	}
+
+	#[test]
+	fn test_refresh_before_expiry() {
+		let beacon = protected_beacon();
+		// Already within the margin when it is received
+		beacon.mount_method(
+			"POST",
+			"/token",
+			ResponseTemplate::new(200).set_body_json(serde_json::json!({ "access_token": "fresh", "expires_in": 0 })),
+		);
+		let client = Client::new().auth(auth(&beacon));
+		assert!(client.query("Info", &beacon.url().join("info").unwrap()).is_ok());
+		assert!(client.query("Info", &beacon.url().join("info").unwrap()).is_ok());
+		// After the 401, then before each request, as the tokens always expire
+		let refreshes = client.token_refreshes();
+		assert_eq!(refreshes.len(), 3);
+		assert!(refreshes.iter().all(|refresh| refresh.ok));
+	}
+
+	#[test]
+	fn test_concurrent_refreshes() {
+		let beacon = protected_beacon();
+		beacon.mount_method(
+			"POST",
+			"/token",
+			ResponseTemplate::new(200)
+				.set_body_json(serde_json::json!({ "access_token": "fresh" }))
+				.set_delay(std::time::Duration::from_millis(300)),
+		);
+		let client = Client::new().auth(auth(&beacon));
+		let url = beacon.url().join("info").unwrap();
+		// Both get a 401, the second one waiting for the refresh of the first one
+		let workers = (0..2)
+			.map(|_| {
+				let (client, url) = (client.fork(), url.clone());
+				std::thread::spawn(move || client.query("Info", &url).is_ok())
+			})
+			.collect::<Vec<_>>();
+		assert!(workers.into_iter().all(|worker| worker.join().unwrap()));
+		assert_eq!(client.token_refreshes().len(), 1);
+	}
+
+	#[test]
+	fn test_static_credentials() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let info = beacon.response("/info");
+		for authorization in ["Bearer s3cr3t", "Basic dmVyaWZpZXI6czNjcjN0"] {
+			beacon.mount_mock(
+				Mock::given(method("GET"))
+					.and(path("/info"))
+					.and(header("authorization", authorization))
+					.respond_with(ResponseTemplate::new(200).set_body_json(info.clone()))
+					.with_priority(1),
+			);
+		}
+		beacon.mount("/info", ResponseTemplate::new(401));
+		let url = beacon.url().join("info").unwrap();
+		assert!(Client::new().bearer_token("s3cr3t").query("Info", &url).is_ok());
+		let basic_auth = "verifier:s3cr3t".parse::<BasicAuth>().unwrap();
+		assert!(Client::new().basic_auth(basic_auth).query("Info", &url).is_ok());
+		let wrong = "verifier:wrong".parse::<BasicAuth>().unwrap();
+		assert!(Client::new().basic_auth(wrong).query("Info", &url).is_err());
+
+		let no_password = "verifier".parse::<BasicAuth>().unwrap();
+		assert_eq!(no_password.password, None);
+		assert!(":s3cr3t".parse::<BasicAuth>().is_err());
+	}
+
+	#[test]
+	fn test_api_key_parameter() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let info = beacon.response("/info");
+		beacon.mount_mock(
+			Mock::given(method("GET"))
+				.and(path("/info"))
+				.and(query_param("apikey", "s3cr3t"))
+				.respond_with(ResponseTemplate::new(200).set_body_json(info))
+				.with_priority(1),
+		);
+		beacon.mount("/info", ResponseTemplate::new(401));
+		let url = beacon.url().join("info").unwrap();
+		let client = Client::new().api_key_parameter("apikey", "s3cr3t");
+		assert!(client.query("Info", &url).is_ok());
+		assert!(Client::new().query("Info", &url).is_err());
+
+		// Nothing listens on the port
+		let url = Url::parse("http://127.0.0.1:9/info").unwrap();
+		let e = client.query("Info", &url).unwrap_err().to_string();
+		assert!(!e.contains("s3cr3t") && e.contains(MASK), "{}", e);
+	}
+
+	#[test]
+	fn test_secrets() {
+		let secrets = Secrets::default();
+		assert_eq!(secrets.mask("GET /info?apikey=a+b/c"), "GET /info?apikey=a+b/c");
+		secrets.clone().register("a b/c");
+		assert_eq!(
+			secrets.mask("GET /info?apikey=a+b%2Fc"),
+			format!("GET /info?apikey={}", MASK)
+		);
+		assert_eq!(secrets.mask("key 'a b/c'"), format!("key '{}'", MASK));
+	}
+}
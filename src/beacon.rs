@@ -1,36 +1,311 @@
+use std::collections::{BTreeMap, BTreeSet};
 use std::path::Path;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
 
 use chrono::SubsecRound;
+use jsonschema::JSONSchema;
+use reqwest::Method;
 use url::Url;
 
+use crate::anonymize::{self, Anonymizer};
+use crate::catalog::Severity;
+use crate::client::Client;
+use crate::consistency::IdConsistency;
+use crate::discovery::Discovery;
 use crate::error::VerifierError;
-use crate::framework::Framework;
-use crate::model::Model;
-use crate::output::{BeaconOutput, EndpointReport, Output};
-use crate::{utils, Json};
+use crate::formats::Formats;
+use crate::framework::{EndpointSchemas, Framework};
+use crate::incremental::{DataVersion, Incremental};
+use crate::interface::{Granularity, Organization};
+use crate::interrupt::Interrupt;
+use crate::latency::{self, LatencyBudget};
+use crate::limits::DeclaredLimits;
+use crate::model::{Entity, Model, Sampling};
+use crate::ontologies::{self, OntologyResolver};
+use crate::output::{BeaconOutput, Category, EndpointReport, Output, RunConfiguration};
+use crate::pool::ValidationPool;
+use crate::protocol::{self, HttpVersion, TlsVersion};
+use crate::sandbox::{BeaconSchemas, SchemaLimits};
+use crate::scheduler::Scheduler;
+use crate::scope::Scope;
+use crate::usage::ResourceUsage;
+use crate::{
+	adaptive, collections, consistency, entry_types, filters, isolation, negative, pagination, post, references,
+	robustness, shape, utils, variants, Json,
+};
 
 pub struct Beacon {
 	name: String,
+	organization: Option<Organization>,
 	url: Url,
 	model: Option<Model>,
 	framework: Framework,
+	/// Shared by the workers checking the endpoints
+	client: Arc<Client>,
+	formats: Formats,
+	only: Option<Category>,
+	observed_data: bool,
+	public_report: bool,
+	configuration: Option<RunConfiguration>,
+	jobs: usize,
+	info: Json,
+	/// Data version of the previous run, with `Some` enabling the incremental verification
+	previous: Option<Option<DataVersion>>,
+	/// Checks (entity and name) retried when their endpoint does not respond
+	flaky: BTreeSet<(String, String)>,
+	retries: usize,
+	time_budget: Option<Duration>,
+	robustness: bool,
+	beacon_schemas: BeaconSchemas,
+	anonymizer: Option<Anonymizer>,
+	pagination: bool,
+	slow: Duration,
+	latency_budgets: Vec<LatencyBudget>,
+	limits: bool,
+	adaptive: bool,
+	filters: bool,
+	post_queries: bool,
+	negative: bool,
+	references: bool,
+	ontologies: Option<OntologyResolver>,
+	variant_queries: bool,
+	sampling: Sampling,
+	head_support: bool,
+	discover: bool,
+	interrupt: Interrupt,
+	min_tls_version: Option<TlsVersion>,
+	min_http_version: Option<HttpVersion>,
 }
 
 impl Beacon {
-	pub fn new(model: Option<Model>, framework: Framework, url: &Url) -> Result<Self, VerifierError> {
+	pub fn new(model: Option<Model>, framework: Framework, client: Client, url: &Url) -> Result<Self, VerifierError> {
 		let mut info_url = url.clone();
 		info_url.set_path(Path::new(url.path()).join("info").to_str().unwrap_or(""));
-		let info: Json = reqwest::blocking::get(&info_url.to_string())?.json().unwrap();
+		let response = client.query("Info", &info_url)?;
+		let info = response.json;
 		log::trace!("{}", info);
 
 		Ok(Self {
 			name: Self::get_name(&info, url),
+			organization: Self::get_organization(&info),
 			url: url.clone(),
 			model,
 			framework,
+			client: Arc::new(client),
+			formats: Formats::default(),
+			only: None,
+			observed_data: false,
+			public_report: false,
+			configuration: None,
+			jobs: num_cpus::get(),
+			info,
+			previous: None,
+			flaky: BTreeSet::new(),
+			retries: 0,
+			time_budget: None,
+			robustness: false,
+			beacon_schemas: BeaconSchemas::default(),
+			anonymizer: None,
+			pagination: false,
+			slow: latency::DEFAULT_SLOW,
+			latency_budgets: Vec::new(),
+			limits: false,
+			adaptive: false,
+			filters: false,
+			post_queries: false,
+			negative: false,
+			references: false,
+			ontologies: None,
+			variant_queries: false,
+			sampling: Sampling::default(),
+			head_support: false,
+			discover: false,
+			interrupt: Interrupt::default(),
+			min_tls_version: None,
+			min_http_version: None,
 		})
 	}
 
+	/// Formats enforced when validating the responses against the framework
+	pub fn formats(mut self, formats: Formats) -> Self {
+		self.formats = formats;
+		self
+	}
+
+	/// Restricts the verification to the checks of one layer of the specification
+	pub fn only(mut self, category: Category) -> Self {
+		self.only = Some(category);
+		self
+	}
+
+	/// Appends a summary of the data observed per entity to the output
+	pub fn with_observed_data(mut self) -> Self {
+		self.observed_data = true;
+		self
+	}
+
+	/// Strips record-level data, ids and response excerpts from the output
+	pub fn public_report(mut self) -> Self {
+		self.public_report = true;
+		self
+	}
+
+	/// Number of endpoints checked at once, each by a thread fetching and validating its response, which bounds
+	/// the requests in flight (one per core by default)
+	pub fn jobs(mut self, jobs: usize) -> Self {
+		self.jobs = jobs;
+		self
+	}
+
+	/// Only runs the data-dependent checks if the data changed since the run that saw `previous`
+	pub fn incremental(mut self, previous: Option<DataVersion>) -> Self {
+		self.previous = Some(previous);
+		self
+	}
+
+	/// Runs the flaky checks up to `retries` more times before failing them
+	pub fn retry_flaky(mut self, flaky: BTreeSet<(String, String)>, retries: usize) -> Self {
+		self.flaky = flaky;
+		self.retries = retries;
+		self
+	}
+
+	/// Stops querying the endpoints of the model once the run took `time_budget`, reporting the
+	/// remaining ones as not verified
+	pub fn time_budget(mut self, time_budget: Duration) -> Self {
+		self.time_budget = Some(time_budget);
+		self
+	}
+
+	/// Also checks that the beacon handles the encodings of the POST bodies
+	pub fn robustness(mut self) -> Self {
+		self.robustness = true;
+		self
+	}
+
+	/// Fetches the schemas the responses return by their url within `limits`
+	pub fn schema_limits(mut self, limits: SchemaLimits) -> Self {
+		self.beacon_schemas = BeaconSchemas::new(limits);
+		self
+	}
+
+	/// Responses taking `slow` or more are classified as slow
+	pub fn slow_threshold(mut self, slow: Duration) -> Self {
+		self.slow = slow;
+		self
+	}
+
+	/// Checks that the entities answer within their latency budgets
+	pub fn latency_budgets(mut self, latency_budgets: Vec<LatencyBudget>) -> Self {
+		self.latency_budgets = latency_budgets;
+		self
+	}
+
+	/// Checks that the beacon is served with at least this version of TLS
+	pub fn min_tls_version(mut self, version: TlsVersion) -> Self {
+		self.min_tls_version = Some(version);
+		self
+	}
+
+	/// Checks that the beacon is served with at least this version of HTTP
+	pub fn min_http_version(mut self, version: HttpVersion) -> Self {
+		self.min_http_version = Some(version);
+		self
+	}
+
+	/// Stops the run once `interrupt` is triggered, reporting the endpoints left as not verified
+	pub fn interrupt(mut self, interrupt: Interrupt) -> Self {
+		self.interrupt = interrupt;
+		self
+	}
+
+	/// Also checks that the records are findable with queries derived from them
+	pub fn adaptive(mut self) -> Self {
+		self.adaptive = true;
+		self
+	}
+
+	/// Also POSTs a query with a request body to every entity, checking its response and its echo of the request
+	pub fn post_queries(mut self) -> Self {
+		self.post_queries = true;
+		self
+	}
+
+	/// Also queries the entities with and without a filtering term the beacon advertises, checking that the results
+	/// are consistent with the filter
+	pub fn filters(mut self) -> Self {
+		self.filters = true;
+		self
+	}
+
+	/// Also sends malformed queries to every entity, which must be rejected with error responses
+	pub fn negative(mut self) -> Self {
+		self.negative = true;
+		self
+	}
+
+	/// Also follows the references of the records to the records of the other entities
+	pub fn references(mut self) -> Self {
+		self.references = true;
+		self
+	}
+
+	/// Also looks the ontology terms of the records and of the filtering terms up with `resolver`
+	pub fn resolve_ontologies(mut self, resolver: OntologyResolver) -> Self {
+		self.ontologies = Some(resolver);
+		self
+	}
+
+	/// Also sends the sequence, range, bracket and geneId queries to the entities of genomic variants
+	pub fn variant_queries(mut self) -> Self {
+		self.variant_queries = true;
+		self
+	}
+
+	/// Records whose record-level endpoints are checked for each entity (the first one by default)
+	pub fn sampling(mut self, sampling: Sampling) -> Self {
+		self.sampling = sampling;
+		self
+	}
+
+	/// Also probes whether the endpoints of the entities support HEAD requests
+	pub fn head_support(mut self) -> Self {
+		self.head_support = true;
+		self
+	}
+
+	/// Queries the entities at the urls advertised in the `/map` of the beacon, skipping the ones it does not advertise
+	pub fn discover(mut self) -> Self {
+		self.discover = true;
+		self
+	}
+
+	/// Also reports the limits declared by the beacon and checks the endpoints at their boundaries
+	pub fn limits(mut self) -> Self {
+		self.limits = true;
+		self
+	}
+
+	/// Also checks the snapshot-consistent pagination of the endpoints with all the entries and the pages of the
+	/// collections
+	pub fn pagination(mut self) -> Self {
+		self.pagination = true;
+		self
+	}
+
+	/// Registers the harvested ids in `anonymizer`, to keep them out of the logs
+	pub fn anonymizer(mut self, anonymizer: Anonymizer) -> Self {
+		self.anonymizer = Some(anonymizer);
+		self
+	}
+
+	/// Embeds the configuration of the run in the output
+	pub fn configuration(mut self, configuration: RunConfiguration) -> Self {
+		self.configuration = Some(configuration);
+		self
+	}
+
 	fn get_name(info: &Json, url: &Url) -> String {
 		let name_json = info.get("response").map_or_else(
 			|| {
@@ -64,86 +339,898 @@ impl Beacon {
 		name
 	}
 
-	fn validate_against_framework(&self, entity_name: &str, location: &str, schema: &Json) -> EndpointReport {
+	fn get_organization(info: &Json) -> Option<Organization> {
+		let organization = info.pointer("/response/organization")?;
+		match serde_json::from_value(organization.clone()) {
+			Ok(organization) => Some(organization),
+			Err(e) => {
+				log::error!("{}", VerifierError::BadInfo(format!("Bad 'organization': {}", e)));
+				None
+			},
+		}
+	}
+
+	/// Default granularity declared in `/info`, or else in `/configuration`
+	fn declared_granularity(&self) -> Option<Granularity> {
+		let declared = Granularity::declared(&self.info).or_else(|| {
+			let mut url = self.url.clone();
+			url.set_path(Path::new(self.url.path()).join("configuration").to_str().unwrap_or(""));
+			let response = self.client.query("Configuration", &url).ok()?;
+			Granularity::declared(&response.json)
+		});
+		if let Some(granularity) = declared {
+			log::debug!("The beacon declares the {} granularity", granularity);
+		}
+		declared
+	}
+
+	/// Validates the response of the endpoint at `location` with the check `check` of the catalog
+	fn validate_against_framework(
+		&self,
+		entity_name: &str,
+		location: &str,
+		check: &str,
+		schema: Result<&JSONSchema, &str>,
+	) -> EndpointReport {
 		let mut url = self.url.clone();
-		url.set_path(Path::new(self.url.path()).join(&location).to_str().unwrap_or(""));
-		let report = match utils::ping_url(&url) {
-			Ok(beacon_map_json) => {
-				let json_schema = match jsonschema::JSONSchema::options().with_meta_schemas().compile(schema) {
-					Ok(schema) => schema,
+		url.set_path(Path::new(self.url.path()).join(location).to_str().unwrap_or(""));
+		let report = match self.client.query(entity_name, &url) {
+			Ok(response) => {
+				let beacon_map_json = response.json;
+				let content_hash = utils::content_hash(&beacon_map_json);
+				let responded = |report: EndpointReport| {
+					report
+						.method(response.method.clone(), response.fallback)
+						.content_hash(content_hash.clone())
+						.timings(response.timings.clone())
+						.warnings(response.warnings.clone())
+						.elapsed(response.elapsed)
+				};
+				match schema {
+					Ok(json_schema) => {
+						let valid = shape::check(&beacon_map_json)
+							.and_then(|()| utils::valid_schema(json_schema, &beacon_map_json));
+						match valid {
+							Ok(output) => responded(
+								EndpointReport::new(entity_name, &self.name, self.url.clone()).ok(Some(output)),
+							),
+							Err(e) => {
+								responded(EndpointReport::new(entity_name, &self.name, self.url.clone()).error(e))
+							},
+						}
+					},
 					Err(e) => {
-						log::error!("{:?}", e);
-						return EndpointReport::new(entity_name, &self.name, self.url.clone())
-							.null(VerifierError::BadSchema);
+						log::error!("{}", e);
+						EndpointReport::new(entity_name, &self.name, self.url.clone())
+							.null(VerifierError::BadSchema(e.to_string()))
 					},
-				};
-				match utils::valid_schema(&json_schema, &beacon_map_json) {
-					Ok(output) => EndpointReport::new(entity_name, &self.name, self.url.clone()).ok(Some(output)),
-					Err(e) => EndpointReport::new(entity_name, &self.name, self.url.clone()).error(e),
 				}
 			},
+			// Only /info is required, the other endpoints of the framework are optional
+			Err(e @ VerifierError::MissingEndpoint(_)) if entity_name != "Info" => {
+				log::warn!("{}", e);
+				EndpointReport::new(entity_name, &self.name, self.url.clone())
+					.null(e)
+					.severity(Severity::Warning)
+			},
 			Err(e) => {
 				log::error!("{}", e);
 				EndpointReport::new(entity_name, &self.name, self.url.clone()).null(e)
 			},
 		};
-		report.url(url)
+		report.url(url).check(check)
 	}
 
-	pub fn validate(self) -> BeaconOutput {
-		let mut output = Output::new();
+	/// Whether the checks of `category` run
+	fn runs(&self, category: Category) -> bool {
+		self.only.map_or(true, |only| only == category)
+	}
 
-		// Validate info
-		log::info!("Validating \"Info\"");
-		let report = self.validate_against_framework("Info", "info", &self.framework.info_json);
-		output.push(report);
+	/// Runs the check `name` of `entity` if it is `enabled` and its `category` runs, isolated from the other checks
+	///
+	/// No new requests once the run is interrupted: the check is reported as not verified.
+	fn follow_up<F>(
+		&self,
+		output: &mut Output,
+		enabled: bool,
+		category: Category,
+		entity: &Entity,
+		name: &str,
+		check: F,
+	) where
+		F: FnOnce() -> Vec<EndpointReport>,
+	{
+		if !enabled || !self.runs(category) {
+			return;
+		}
+		if self.interrupt.is_interrupted() {
+			let url = utils::url_join(&self.url, &entity.url);
+			let error = VerifierError::NotVerified("the run was interrupted".into());
+			output.push(
+				EndpointReport::new(&entity.name, name, url)
+					.category(category)
+					.null(error),
+			);
+			return;
+		}
+		log::info!("Validating {:?}", name);
+		isolation::check(&entity.name, name, &self.url, check)
+			.into_iter()
+			.for_each(|report| output.push(report));
+	}
 
-		// Validate configuration
-		log::info!("Validating \"Configuration\"");
-		let report =
-			self.validate_against_framework("Configuration", "configuration", &self.framework.configuration_json);
-		output.push(report);
+	pub fn validate(mut self) -> BeaconOutput {
+		let started = Instant::now();
+		let mut output = if self.public_report {
+			Output::public()
+		}
+		else {
+			Output::new()
+		}
+		.slow(self.slow);
 
-		// Validate beacon map
-		log::info!("Validating \"Beacon Map\"");
-		let report = self.validate_against_framework("BeaconMap", "map", &self.framework.beacon_map_json);
-		output.push(report);
+		if self.only != Some(Category::Model) {
+			// Compiled once, with the references resolved by the store of the framework
+			let schemas = self
+				.framework
+				.endpoint_schemas(&self.formats)
+				.map_err(|e| e.to_string());
+			let schema =
+				|compiled: fn(&EndpointSchemas) -> &JSONSchema| schemas.as_ref().map(compiled).map_err(String::as_str);
+			let framework_endpoints = [
+				("Info", "info", "framework.info", schema(|schemas| &schemas.info)),
+				(
+					"Configuration",
+					"configuration",
+					"framework.configuration",
+					schema(|schemas| &schemas.configuration),
+				),
+				("BeaconMap", "map", "framework.map", schema(|schemas| &schemas.map)),
+				(
+					"EntryTypes",
+					"entry_types",
+					"framework.entry-types",
+					schema(|schemas| &schemas.entry_types),
+				),
+			];
+			for (entity_name, location, check, schema) in framework_endpoints {
+				log::info!("Validating {:?}", entity_name);
+				isolation::check(entity_name, &self.name, &self.url, || {
+					vec![self.validate_against_framework(entity_name, location, check, schema)]
+				})
+				.into_iter()
+				.for_each(|report| output.push(report));
+			}
 
-		// Validate entry types
-		log::info!("Validating \"Entry Types\"");
-		let report = self.validate_against_framework("EntryTypes", "entry_types", &self.framework.entry_types_json);
-		output.push(report);
+			if self.robustness {
+				log::info!("Validating the encodings of the POST bodies");
+				isolation::check(robustness::ENTITY_NAME, &self.name, &self.url, || {
+					robustness::check(&self.client, &self.url, &self.name)
+				})
+				.into_iter()
+				.for_each(|report| output.push(report));
+			}
+		}
 
 		// Validate endpoints configuration
 		// TODO: Validate OpenAPI 3.0
 
+		// Skip the entities if their data did not change
+		let incremental = self.previous.as_ref().map(|previous| {
+			let data_version = DataVersion::fetch(&self.client, &self.url, &self.info, self.model.as_ref());
+			Incremental::compare(previous.as_ref(), data_version)
+		});
+		// Beacons answering at boolean or count granularity have no records to check
+		let declared = if self.model.is_some() {
+			self.declared_granularity()
+		}
+		else {
+			None
+		};
+		let model = match &incremental {
+			Some(incremental) if !incremental.changed => {
+				log::info!("The data did not change since the previous run, skipping the entities");
+				None
+			},
+			_ => self.model.take(),
+		};
+
+		// The schemas of the responses compiled when the framework was loaded, a framework built otherwise fails here
+		let model = match model.map(|model| (model, self.framework.response_schemas(&self.formats))) {
+			Some((model, Ok(schemas))) => Some((model, schemas)),
+			Some((_, Err(e))) => {
+				log::error!("{}", e);
+				output.push(
+					EndpointReport::new("Framework", "Response schemas", self.url.clone())
+						.category(Category::Framework)
+						.null(e),
+				);
+				None
+			},
+			None => None,
+		};
+
 		// Validate entities
-		if let Some(model) = self.model {
-			let boolean_json = utils::compile_schema(&self.framework.boolean_json);
-			let count_json = utils::compile_schema(&self.framework.count_json);
-			let result_sets_json = utils::compile_schema(&self.framework.result_sets_json);
-			let collections_json = utils::compile_schema(&self.framework.collections_json);
-			model
-				.endpoints(&self.url)
-				.into_iter()
-				.map(|endpoint| {
-					log::info!("Validating {:?}", endpoint.name);
-					endpoint.validate(
-						&self.url,
-						&boolean_json,
-						&count_json,
-						&result_sets_json,
-						&collections_json,
+		let mut limits = None;
+		let mut head_support = BTreeMap::new();
+		if let Some((mut model, schemas)) = model {
+			let not_advertised = if self.discover {
+				match Discovery::fetch(&self.client, &self.url, &self.framework, &self.formats) {
+					Ok(discovery) => discovery.apply(&mut model, &self.url),
+					Err(e) => {
+						log::error!("Could not discover the endpoints, using the map of the model: {}", e);
+						Vec::new()
+					},
+				}
+			}
+			else {
+				Vec::new()
+			};
+			// Responses are fetched and validated by the pool, with as many requests in flight as workers
+			let pool = ValidationPool::new(self.jobs);
+			let ids = (self.only != Some(Category::Framework))
+				.then(|| Arc::new(Mutex::new(IdConsistency::new(&model, &self.url))));
+			// Entry types answered with collections instead of result sets
+			let collection_types = Arc::new(Mutex::new(BTreeSet::new()));
+			// Round-robin across the entities, until the time budget runs out
+			// The follow-up checks run on every entity, once the pool is done
+			let entities = model.entities.clone();
+			let budgeted = if self.runs(Category::Framework) {
+				self.latency_budgets
+					.iter()
+					.filter_map(
+						|budget| match model.entities.iter().find(|entity| entity.name == budget.entity) {
+							Some(entity) => Some((budget, entity.clone())),
+							None => {
+								log::warn!("No entity {:?} in the model for its latency budget", budget.entity);
+								None
+							},
+						},
 					)
+					.collect()
+			}
+			else {
+				Vec::new()
+			};
+			let mut endpoints = model.endpoints(&self.client, &self.url, declared, self.sampling);
+			endpoints.extend(not_advertised);
+			if self.only != Some(Category::Framework) {
+				let default_schemas = entry_types::default_schemas(&self.client, &self.url);
+				endpoints = endpoints
+					.into_iter()
+					.map(|endpoint| {
+						let declared_schema = default_schemas.get(&endpoint.entry_type).cloned();
+						endpoint
+							.declared_schema(declared_schema)
+							.beacon_schemas(self.beacon_schemas.clone())
+					})
+					.collect();
+			}
+			if let Some(anonymizer) = &self.anonymizer {
+				endpoints
+					.iter()
+					.filter_map(|endpoint| endpoint.record_id.as_deref())
+					.for_each(|id| anonymizer.register(id));
+			}
+			let mut scheduler = Scheduler::new(endpoints).interrupt(self.interrupt.clone());
+			let deadline = self.time_budget.map(|time_budget| started + time_budget);
+			if let Some(deadline) = deadline {
+				scheduler = scheduler.deadline(deadline);
+			}
+			for endpoint in &mut scheduler {
+				let retries = if endpoint.skipped.is_none()
+					&& self.flaky.contains(&(endpoint.entity_name.clone(), endpoint.key()))
+				{
+					self.retries
+				}
+				else {
+					0
+				};
+				let (client, url, schemas, formats, only, ids, interrupt) = (
+					Arc::clone(&self.client),
+					self.url.clone(),
+					schemas.clone(),
+					self.formats.clone(),
+					self.only,
+					ids.clone(),
+					self.interrupt.clone(),
+				);
+				let collection_types = Arc::clone(&collection_types);
+				// Waits for a free worker
+				pool.submit(move || {
+					// The endpoints still queued are not queried after the scheduler stopped
+					if interrupt.is_interrupted() {
+						return endpoint.not_verified(&url, only, "the run was interrupted");
+					}
+					if deadline.map_or(false, |deadline| Instant::now() >= deadline) {
+						return endpoint.not_verified(&url, only, "the time budget ran out");
+					}
+					log::info!("Validating {:?}", endpoint.name);
+					// Fetches and validates the response, keeping its JSON for the ids
+					let check = || {
+						let response = isolation::catch(|| endpoint.fetch(&client, &url, only))
+							.unwrap_or_else(|message| Err(endpoint.internal_error(&url, only, &message)))?;
+						let json = response.json.clone();
+						let (elapsed, start) = (response.elapsed, Instant::now());
+						let reports = isolation::catch(|| endpoint.validate(response, &url, &schemas, &formats, only))
+							.unwrap_or_else(|message| endpoint.internal_error(&url, only, &message));
+						Ok((json, isolation::timed(elapsed + start.elapsed(), reports)))
+					};
+					let failed = |checked: &Result<(Json, Vec<EndpointReport>), Vec<EndpointReport>>| match checked {
+						Ok((_, reports)) => reports
+							.iter()
+							.any(|report| report.valid == Some(false) || report.error.is_some()),
+						Err(_) => true,
+					};
+					// A flaky check is run again when the request fails as well as when the response does not pass
+					let mut checked = check();
+					for attempt in 1..=retries {
+						if !failed(&checked) {
+							break;
+						}
+						log::info!("Retrying the flaky {:?} ({}/{})", endpoint.name, attempt, retries);
+						checked = check();
+					}
+					let (json, reports) = match checked {
+						Ok(checked) => checked,
+						Err(reports) => return reports,
+					};
+					if collections::is_collections_response(&json) {
+						let mut collection_types = collection_types.lock().unwrap_or_else(PoisonError::into_inner);
+						collection_types.insert(endpoint.entry_type.clone());
+					}
+					// Only the ids of the response kept are observed
+					if let Some(ids) = &ids {
+						let observe = || {
+							ids.lock()
+								.unwrap_or_else(PoisonError::into_inner)
+								.observe(&endpoint, &json)
+						};
+						if let Err(message) = isolation::catch(observe) {
+							return endpoint.internal_error(&url, only, &message);
+						}
+					}
+					reports
+				});
+			}
+			let reason = if self.interrupt.is_interrupted() {
+				"the run was interrupted"
+			}
+			else {
+				"the time budget ran out"
+			};
+			for endpoint in scheduler.remaining() {
+				endpoint
+					.not_verified(&self.url, self.only, reason)
+					.into_iter()
+					.for_each(|report| output.push(report));
+			}
+			pool.finish().into_iter().for_each(|report| output.push(report));
+			let collection_types = collection_types.lock().unwrap_or_else(PoisonError::into_inner).clone();
+			// Every worker is done with the ids, left incomplete by a panic while observing them
+			let ids = ids.and_then(|ids| Arc::try_unwrap(ids).ok()).map(|ids| {
+				ids.into_inner()
+					.map_err(|_| "the ids of a response could not be observed".to_string())
+			});
+			// No new requests once interrupted
+			for entity in &entities {
+				let name = format!("{} {}", entity.name, pagination::SNAPSHOT_CHECK_NAME);
+				self.follow_up(&mut output, self.pagination, Category::Framework, entity, &name, || {
+					vec![pagination::check_snapshot(&self.client, &self.url, entity)]
+				});
+				if collection_types.contains(&entity.entry_type) {
+					let name = format!("{} {}", entity.name, collections::CHECK_NAME);
+					self.follow_up(&mut output, self.pagination, Category::Framework, entity, &name, || {
+						vec![collections::check(&self.client, &self.url, entity)]
+					});
+					continue;
+				}
+				let methods = if self.post_queries {
+					vec![Method::GET, Method::POST]
+				}
+				else {
+					vec![Method::GET]
+				};
+				for (method, check_name) in methods.into_iter().zip(pagination::CHECK_NAMES) {
+					let name = format!("{} {}", entity.name, check_name);
+					self.follow_up(&mut output, self.pagination, Category::Framework, entity, &name, || {
+						vec![pagination::check(&self.client, &self.url, entity, method.clone())]
+					});
+				}
+			}
+			for (budget, entity) in &budgeted {
+				self.follow_up(&mut output, true, Category::Framework, entity, &budget.name(), || {
+					vec![budget.check(&self.client, &self.url, entity)]
+				});
+			}
+			if self.limits && self.runs(Category::Framework) {
+				// No new requests once interrupted
+				let declared = (!self.interrupt.is_interrupted())
+					.then(|| DeclaredLimits::fetch(&self.client, &self.url, &entities));
+				for entity in &entities {
+					let name = format!("{} declared limits", entity.name);
+					self.follow_up(&mut output, true, Category::Framework, entity, &name, || {
+						declared
+							.as_ref()
+							.map(|declared| declared.check(&self.client, &self.url, entity))
+							.unwrap_or_default()
+					});
+				}
+				limits = declared;
+			}
+			for entity in &entities {
+				let name = format!("{} {}", entity.name, adaptive::CHECK_NAMES[0]);
+				self.follow_up(&mut output, self.adaptive, Category::Framework, entity, &name, || {
+					adaptive::check(&self.client, &self.url, entity)
+				});
+			}
+			let probed = self.head_support && self.runs(Category::Framework);
+			// Not a check, nothing to report once interrupted
+			for entity in entities.iter().filter(|_| probed && !self.interrupt.is_interrupted()) {
+				let url = utils::url_join(&self.url, &entity.url);
+				match self.client.head(&entity.name, &url) {
+					Ok(support) => {
+						head_support.insert(entity.name.clone(), support);
+					},
+					Err(e) => log::warn!("Probing HEAD on {} failed: {}", anonymize::url(&url), e),
+				}
+			}
+			for entity in &entities {
+				let name = format!("{} {}", entity.name, filters::CHECK_NAME);
+				self.follow_up(&mut output, self.filters, Category::Framework, entity, &name, || {
+					vec![filters::check(&self.client, &self.url, entity)]
+				});
+			}
+			for entity in &entities {
+				let name = format!("{} {}", entity.name, post::CHECK_NAMES[0]);
+				self.follow_up(
+					&mut output,
+					self.post_queries,
+					Category::Framework,
+					entity,
+					&name,
+					|| post::check(&self.client, &self.url, entity, &schemas, &self.formats, self.only),
+				);
+			}
+			for entity in &entities {
+				let name = format!("{} {}", entity.name, negative::CHECK_NAMES[0]);
+				self.follow_up(&mut output, self.negative, Category::Framework, entity, &name, || {
+					negative::check(&self.client, &self.url, entity, &schemas)
+				});
+			}
+			for entity in entities
+				.iter()
+				.filter(|entity| entity.entry_type == variants::ENTRY_TYPE)
+			{
+				let name = format!("{} {}", entity.name, variants::CHECK_NAMES[0]);
+				let category = self.only.unwrap_or(Category::Framework);
+				self.follow_up(&mut output, self.variant_queries, category, entity, &name, || {
+					variants::check(&self.client, &self.url, entity, &schemas, &self.formats, self.only)
+				});
+			}
+			if let Some(resolver) = &self.ontologies {
+				for entity in &entities {
+					let name = format!("{} {}", entity.name, ontologies::CHECK_NAME);
+					self.follow_up(&mut output, true, Category::Model, entity, &name, || {
+						vec![ontologies::check(&self.client, &self.url, entity, resolver)]
+					});
+				}
+				resolver.save();
+			}
+			for entity in &entities {
+				let name = format!("{} {}", entity.name, references::CHECK_NAME);
+				self.follow_up(&mut output, self.references, Category::Model, entity, &name, || {
+					vec![references::check(&self.client, &self.url, entity, &entities)]
+				});
+			}
+			match ids {
+				Some(Ok(ids)) => {
+					if let Some(anonymizer) = &self.anonymizer {
+						ids.ids().iter().for_each(|id| anonymizer.register(id));
+					}
+					isolation::check("Model", consistency::CHECK_NAME, &self.url, || ids.reports())
+						.into_iter()
+						.for_each(|report| output.push(report));
+				},
+				Some(Err(message)) => output.push(
+					EndpointReport::new("Model", consistency::CHECK_NAME, self.url.clone())
+						.category(Category::Framework)
+						.null(VerifierError::Internal(message)),
+				),
+				None => (),
+			}
+		}
+
+		// Once every host has been contacted
+		let protocols = self.client.protocols();
+		if self.only != Some(Category::Model) {
+			for (host, (url, protocol)) in &protocols {
+				isolation::check(protocol::ENTITY_NAME, &self.name, &self.url, || {
+					protocol.check(host, url, self.min_tls_version, self.min_http_version)
 				})
+				.into_iter()
 				.for_each(|report| output.push(report));
+			}
 		}
-
-		BeaconOutput {
+		let protocols = protocols
+			.into_iter()
+			.map(|(host, (_, protocol))| (host, protocol))
+			.collect();
+		let data_use = output.data_use();
+		let mut beacon_output = BeaconOutput {
 			name: self.name,
+			organization: self.organization,
 			url: self.url,
 			last_updated: chrono::offset::Utc::now().naive_utc().round_subsecs(6),
+			observed: if self.observed_data {
+				Some(output.observed())
+			}
+			else {
+				None
+			},
 			entities: output.finish(),
+			configuration: self.configuration,
+			token_refreshes: self.client.token_refreshes(),
+			incremental,
+			scope: None,
+			status: None,
+			policy: None,
+			limits,
+			interrupted: self.interrupt.is_interrupted(),
+			protocols,
+			data_use,
+			head_support,
+			resource_usage: Some(ResourceUsage::measure(self.client.traffic())),
+			error: None,
+		};
+		beacon_output.scope = beacon_output
+			.configuration
+			.as_ref()
+			.map(|configuration| Scope::new(configuration, &beacon_output));
+		beacon_output
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use std::collections::{BTreeMap, BTreeSet};
+	use std::time::{Duration, Instant};
+
+	use serde_json::json;
+	use wiremock::matchers::{method, path};
+	use wiremock::{Mock, ResponseTemplate};
+
+	use crate::client::MethodPolicy;
+	use crate::incremental::DataVersion;
+	use crate::output::{BeaconOutput, Category, RunConfiguration, SpecSource};
+	use crate::scope::Access;
+	use crate::spec::SpecVersion;
+	use crate::testing::{fixture_url, Behavior, MockBeacon};
+	use crate::{adaptive, render};
+
+	const FRAMEWORK_ENTITIES: [&str; 4] = ["Info", "Configuration", "BeaconMap", "EntryTypes"];
+
+	#[test]
+	fn test_conformant_framework() {
+		let output = MockBeacon::start(Behavior::Conformant).verify();
+		assert_eq!(output.name, "Mock Beacon");
+		for entity in FRAMEWORK_ENTITIES {
+			let reports = &output.entities[entity];
+			assert!(reports.iter().all(|report| report.valid == Some(true)), "{}", entity);
+		}
+	}
+
+	#[test]
+	fn test_framework_references() {
+		// The organization of the /info schema of the fixture is a relative reference
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let mut info = beacon.response("/info");
+		info["response"]["organization"] = json!({ "id": "org1" });
+		beacon.mount("/info", ResponseTemplate::new(200).set_body_json(info));
+		let output = beacon.verify();
+		let report = &output.entities["Info"][0];
+		assert_eq!(report.valid, Some(false), "{:?}", report.error);
+		assert!(
+			report
+				.error
+				.as_deref()
+				.unwrap()
+				.contains("\"name\" is a required property"),
+			"{:?}",
+			report.error
+		);
+	}
+
+	#[test]
+	fn test_broken_framework_schema() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let report = beacon
+			.beacon()
+			.validate_against_framework("Info", "info", "framework.info", Err("broken"));
+		assert_eq!(report.valid, None);
+		assert_eq!(report.check.as_deref(), Some("framework.info"));
+		assert_eq!(report.url.as_ref().map(url::Url::path), Some("/info"));
+	}
+
+	#[test]
+	fn test_jobs() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let sequential = beacon.beacon().jobs(1).validate();
+		let parallel = beacon.beacon().jobs(4).validate();
+		let summary = |output: &BeaconOutput| {
+			output
+				.entities
+				.iter()
+				.map(|(entity, reports)| {
+					let reports = reports
+						.iter()
+						.map(|report| (report.name.clone(), report.category, report.valid))
+						.collect::<Vec<_>>();
+					(entity.clone(), reports)
+				})
+				.collect::<Vec<_>>()
+		};
+		assert_eq!(summary(&sequential), summary(&parallel));
+	}
+
+	#[test]
+	fn test_requests_in_flight() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let delay = Duration::from_millis(400);
+		let routes = [
+			"/individuals/ind1",
+			"/individuals/ind1/biosamples",
+			"/biosamples/bio1",
+			"/datasets/ds1",
+		];
+		for route in routes {
+			beacon.mount(
+				route,
+				ResponseTemplate::new(200)
+					.set_body_json(beacon.response(route))
+					.set_delay(delay),
+			);
 		}
+		let elapsed = |jobs: usize| {
+			let beacon = beacon.beacon().jobs(jobs);
+			let start = Instant::now();
+			let output = beacon.validate();
+			assert!(output.entities["Individual"]
+				.iter()
+				.all(|report| report.valid == Some(true)));
+			start.elapsed()
+		};
+		let sequential = elapsed(1);
+		assert!(sequential >= delay * routes.len() as u32);
+		// The slow endpoints are queried at once
+		let parallel = elapsed(4);
+		assert!(parallel + delay * 2 < sequential, "{:?} vs {:?}", parallel, sequential);
+	}
+
+	#[test]
+	fn test_retry_flaky() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let all_entries = |output: &BeaconOutput| {
+			output.entities["Biosample"]
+				.iter()
+				.find(|report| report.name == "Biosample all entries" && report.category == Category::Framework)
+				.and_then(|report| report.valid)
+		};
+		// The endpoint fails twice: when harvesting the ids and when fetched for its check
+		beacon.mount_mock(
+			Mock::given(method("GET"))
+				.and(path("/biosamples"))
+				.respond_with(ResponseTemplate::new(503))
+				.up_to_n_times(2)
+				.with_priority(1),
+		);
+		let flaky = BTreeSet::from([("Biosample".to_string(), "Biosample all entries".to_string())]);
+		let output = beacon.beacon().retry_flaky(flaky.clone(), 1).validate();
+		assert_eq!(all_entries(&output), Some(true));
+
+		beacon.mount_mock(
+			Mock::given(method("GET"))
+				.and(path("/biosamples"))
+				.respond_with(ResponseTemplate::new(503))
+				.up_to_n_times(2)
+				.with_priority(1),
+		);
+		let output = beacon.beacon().retry_flaky(flaky.clone(), 0).validate();
+		assert_eq!(all_entries(&output), None);
+
+		// The check is run again when the response does not pass too
+		beacon.mount_mock(
+			Mock::given(method("GET"))
+				.and(path("/biosamples"))
+				.respond_with(ResponseTemplate::new(200).set_body_json(json!({ "meta": {} })))
+				.up_to_n_times(2)
+				.with_priority(1),
+		);
+		let output = beacon.beacon().retry_flaky(flaky, 1).validate();
+		assert_eq!(all_entries(&output), Some(true));
+	}
+
+	#[test]
+	fn test_time_budget() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let output = beacon.beacon().time_budget(Duration::ZERO).validate();
+		assert_eq!(output.entities["Info"][0].valid, Some(true));
+		let reports = &output.entities["Individual"];
+		assert!(!reports.is_empty());
+		assert!(reports.iter().all(
+			|report| report.valid.is_none() && report.error.as_deref().unwrap_or_default().contains("time budget")
+		));
+	}
+
+	#[test]
+	fn test_incremental() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let first = beacon.beacon().incremental(None).validate();
+		let incremental = first.incremental.unwrap();
+		assert!(incremental.changed);
+		assert_eq!(incremental.data_version.info.as_deref(), Some("2022-03-01T12:00:00Z"));
+		assert_eq!(incremental.data_version.datasets["ds1"], "2022-03-01T12:00:00Z");
+		assert!(first.entities.contains_key("Individual"));
+
+		let second = beacon
+			.beacon()
+			.incremental(Some(incremental.data_version.clone()))
+			.validate();
+		assert!(!second.incremental.unwrap().changed);
+		assert!(!second.entities.contains_key("Individual"));
+		assert!(second.entities.contains_key("Info"));
+
+		let mut outdated = incremental.data_version;
+		outdated.datasets.insert("ds1".into(), "2022-01-01T00:00:00Z".into());
+		let third = beacon.beacon().incremental(Some(outdated)).validate();
+		assert!(third.incremental.unwrap().changed);
+		assert!(third.entities.contains_key("Individual"));
+		assert!(beacon.verify().incremental.is_none());
+
+		// No previous run, or a corrupt state
+		let directory = tempfile::tempdir().unwrap();
+		let state = directory.path().join("data-version.json");
+		assert_eq!(DataVersion::load(&state).unwrap(), None);
+		std::fs::write(&state, "{\"info\": ").unwrap();
+		assert!(DataVersion::load(&state).is_err());
+	}
+
+	#[test]
+	fn test_missing_framework_endpoint() {
+		let output = MockBeacon::start(Behavior::MissingEndpoints).verify();
+		let report = &output.entities["Configuration"][0];
+		assert_eq!(report.valid, None);
+		assert!(report.error.is_some());
+		assert_eq!(output.entities["Info"][0].valid, Some(true));
+	}
+
+	#[test]
+	fn test_bad_framework_response() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		beacon.mount(
+			"/map",
+			wiremock::ResponseTemplate::new(200).set_body_json(serde_json::json!({ "meta": {} })),
+		);
+		let output = beacon.verify();
+		let report = &output.entities["BeaconMap"][0];
+		assert_eq!(report.valid, Some(false));
+		assert!(report.error.is_some());
+	}
+
+	#[test]
+	fn test_observed_data() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let output = beacon.beacon().with_observed_data().validate();
+		let observed = output.observed.unwrap();
+		// All entries and single entry, the biosample related with the individual being a biosample
+		assert_eq!(observed["individual"].records, 3);
+		assert_eq!(observed["biosample"].records, 4);
+		// All entries and single entry
+		assert_eq!(observed["dataset"].records, 2);
+		assert!(observed["biosample"].datasets.contains("ds1"));
+		assert!(MockBeacon::start(Behavior::Conformant).verify().observed.is_none());
+	}
+
+	#[test]
+	fn test_configuration_echo() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let configuration = RunConfiguration {
+			version: env!("CARGO_PKG_VERSION").into(),
+			target: None,
+			url: beacon.url(),
+			framework: SpecSource {
+				location: fixture_url("framework"),
+				revision: None,
+			},
+			model: None,
+			spec_version: Some(SpecVersion::V2_0),
+			entities: vec!["Individual".into()],
+			only: Some(Category::Model),
+			method_policy: MethodPolicy::Post,
+			endpoint_method_policies: BTreeMap::new(),
+			access: Access::Anonymous,
+			disabled_formats: vec!["hgvs".into()],
+			observed_data: false,
+			public_report: false,
+			timings: false,
+			incremental: false,
+			history: false,
+			retries: 0,
+			time_budget_secs: None,
+			robustness: false,
+			pagination: false,
+			limits: false,
+			adaptive: false,
+			filters: false,
+			post_queries: false,
+			negative: false,
+			references: false,
+			resolve_ontologies: false,
+			variant_queries: false,
+			max_instances: None,
+			sample_seed: None,
+			head: false,
+			discover: false,
+			min_tls_version: None,
+			min_http_version: None,
+			slow_ms: None,
+			timeout_ms: None,
+			request_retries: 0,
+			request_backoff_ms: None,
+			output_format: render::DEFAULT.into(),
+		};
+		let output = beacon.beacon().configuration(configuration.clone()).validate();
+		let payload = serde_json::to_value(&output).unwrap();
+		assert_eq!(payload["configuration"]["method_policy"], "post");
+		assert_eq!(payload["configuration"]["only"], "model");
+		assert_eq!(output.configuration, Some(configuration));
+		assert!(beacon.verify().configuration.is_none());
+	}
+
+	#[test]
+	fn test_public_report() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let mut response = beacon.response("/individuals/ind1");
+		response["response"]["resultSets"][0]["results"][0]["sex"] = serde_json::json!("secret-value");
+		beacon.mount(
+			"/individuals/ind1",
+			wiremock::ResponseTemplate::new(200).set_body_json(response),
+		);
+		let output = beacon.beacon().with_observed_data().public_report().validate();
+		let payload = serde_json::to_string(&output).unwrap();
+		assert!(!payload.contains("ind1"));
+		assert!(!payload.contains("ds1"));
+		assert!(!payload.contains("secret-value"));
+		assert_eq!(output.observed.unwrap()["individual"].num_datasets, 1);
+		let single_entry = output.entities["Individual"]
+			.iter()
+			.find(|report| report.name == "Individual single entry" && report.valid == Some(false))
+			.unwrap();
+		assert_eq!(
+			single_entry.error.as_deref(),
+			Some("Response does not match the schema (1 errors)")
+		);
+
+		// The failed adaptive checks do not tell the id of the record
+		let individuals = beacon.response("/individuals");
+		beacon.mount("/individuals", move |request: &wiremock::Request| {
+			let mut response = individuals.clone();
+			if request.url.query_pairs().any(|(key, _)| key == "filters") {
+				response["responseSummary"] = serde_json::json!({ "exists": false, "numTotalResults": 0 });
+				response["response"]["resultSets"][0]["results"] = serde_json::json!([]);
+			}
+			wiremock::ResponseTemplate::new(200).set_body_json(response)
+		});
+		let output = beacon.beacon().adaptive().public_report().validate();
+		assert!(!serde_json::to_string(&output).unwrap().contains("ind1"));
+		let findable = output.entities["Individual"]
+			.iter()
+			.find(|report| report.name == format!("Individual {}", adaptive::CHECK_NAMES[0]))
+			.unwrap();
+		assert_eq!(
+			findable.error.as_deref(),
+			Some("Record not findable by a derived query")
+		);
 	}
 }
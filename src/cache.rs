@@ -0,0 +1,208 @@
+//! Cache of the repositories of the schemas
+//!
+//! The framework and the models hosted on GitHub are cloned at every run, so
+//! the verifier cannot run where github.com is not reachable (e.g. the
+//! air-gapped network of a hospital). With `--schema-cache`, each repository is
+//! cloned once into the directory and read from there by the next runs. With
+//! `--offline`, the repositories are only read from the cache and a missing one
+//! is an error instead of a download: the cache is filled with `cache-schemas`
+//! on a connected machine, then copied to the offline one. The repositories are
+//! cloned through the proxy of `--proxy` (a SOCKS one cannot clone them), or else
+//! the one of the configuration of git or of `HTTPS_PROXY`. The remote schemas referenced by the ones of
+//! the repositories are kept in the cache too (see [`crate::resolver`]).
+
+use std::path::{Path, PathBuf};
+
+use git2::build::RepoBuilder;
+use git2::{FetchOptions, ProxyOptions};
+use tempfile::TempDir;
+use url::Url;
+
+use crate::error::VerifierError;
+use crate::{anonymize, utils};
+
+/// Where the schemas are read from, without a cache by default
+#[derive(Debug, Clone, Default)]
+pub struct SchemaCache {
+	dir: Option<PathBuf>,
+	offline: bool,
+	proxy: Option<Url>,
+}
+
+/// Clone of a repository, removed with the temporary directory if it is not cached
+struct Checkout {
+	path: PathBuf,
+	_temporary: Option<TempDir>,
+}
+
+impl SchemaCache {
+	pub fn new(dir: PathBuf) -> Self {
+		Self {
+			dir: Some(dir),
+			offline: false,
+			proxy: None,
+		}
+	}
+
+	/// Downloads the schemas through the HTTP(S) or SOCKS5 proxy at `proxy`
+	pub fn proxy(mut self, proxy: Url) -> Self {
+		self.proxy = Some(proxy);
+		self
+	}
+
+	pub fn proxy_url(&self) -> Option<&Url> {
+		self.proxy.as_ref()
+	}
+
+	/// Never downloads the schemas
+	pub fn offline(mut self) -> Self {
+		self.offline = true;
+		self
+	}
+
+	pub fn is_offline(&self) -> bool {
+		self.offline
+	}
+
+	/// Path of the copy of the remote schema at `url` in the cache (under `documents/<host>/<path>`), if any cache
+	pub fn document_path(&self, url: &Url) -> Option<PathBuf> {
+		let mut path = self.dir.as_ref()?.join("documents").join(url.host_str()?);
+		path.extend(url.path_segments()?.filter(|segment| !segment.is_empty()));
+		Some(path)
+	}
+
+	/// Copies the schemas at `location` (a `file://` directory or a directory of a GitHub repository) to `dest`,
+	/// returning the commit they were read from
+	pub fn fetch(&self, location: &Url, dest: &Path) -> Result<Option<String>, VerifierError> {
+		if location.scheme() == "file" {
+			log::debug!("COPYING {} to {:?}", location.path(), dest);
+			utils::copy_dir_recursively(location.path(), dest)?;
+			return Ok(utils::git_revision(location.path()));
+		}
+
+		// Parse model repo URL
+		if location.domain() != Some("github.com") {
+			return Err(VerifierError::BadSchemaLocation(format!(
+				"{} (only repos hosted on github.com are supported)",
+				location
+			)));
+		}
+		let mut url_iter = Path::new(location.path()).components().skip(1);
+		let mut next = || {
+			url_iter
+				.next()
+				.map(|component| component.as_os_str().to_string_lossy().to_string())
+				.ok_or_else(|| VerifierError::BadSchemaLocation(format!("{} (no owner and repo)", location)))
+		};
+		let (owner, repo) = (next()?, next()?);
+		let path: PathBuf = url_iter.collect();
+		log::debug!("Path inside repo = {:?}", path);
+
+		let checkout = self.checkout(&owner, &repo)?;
+		log::debug!("Copying from {:?} to {:?}", checkout.path.join(&path), dest);
+		utils::copy_dir_recursively(checkout.path.join(path), dest)?;
+		Ok(utils::git_revision(&checkout.path))
+	}
+
+	/// Clone of the repository, from the cache if it is there
+	fn checkout(&self, owner: &str, repo: &str) -> Result<Checkout, VerifierError> {
+		let cached = self
+			.dir
+			.as_ref()
+			.map(|dir| dir.join("github.com").join(owner).join(repo));
+		match &cached {
+			Some(cached) if cached.is_dir() => {
+				log::debug!("Reading repo {} from {} in the cache {:?}", repo, owner, cached);
+				return Ok(Checkout {
+					path: cached.clone(),
+					_temporary: None,
+				});
+			},
+			_ if self.offline => {
+				return Err(VerifierError::NotCached(format!(
+					"https://github.com/{}/{}",
+					owner, repo
+				)));
+			},
+			_ => (),
+		}
+
+		log::debug!("Downloading repo {} from {}", repo, owner);
+		let repo_url = format!("https://github.com/{owner}/{repo}", owner = owner, repo = repo);
+		let parent = match &cached {
+			Some(cached) => {
+				let parent = cached.parent().unwrap_or(cached);
+				std::fs::create_dir_all(parent)?;
+				tempfile::tempdir_in(parent)?
+			},
+			None => tempfile::tempdir()?,
+		};
+		if let Some(proxy) = self.proxy.as_ref().filter(|proxy| proxy.scheme().starts_with("socks")) {
+			return Err(VerifierError::BadSchemaLocation(format!(
+				"{} cannot be cloned through the SOCKS proxy {}, fill the schema cache through an HTTP(S) one",
+				repo_url,
+				anonymize::url(proxy)
+			)));
+		}
+		let mut proxy_options = ProxyOptions::new();
+		match &self.proxy {
+			Some(proxy) => proxy_options.url(proxy.as_str()),
+			None => proxy_options.auto(),
+		};
+		let mut fetch_options = FetchOptions::new();
+		fetch_options.proxy_options(proxy_options);
+		RepoBuilder::new()
+			.fetch_options(fetch_options)
+			.clone(&repo_url, parent.path())
+			.map_err(|e| VerifierError::BadSchemaLocation(format!("{} could not be cloned: {}", repo_url, e)))?;
+		match cached {
+			// Renamed once complete, so an interrupted download is not taken for a cached repository
+			Some(cached) => {
+				std::fs::rename(parent.into_path(), &cached)?;
+				log::info!("Cached {} in {:?}", repo_url, cached);
+				Ok(Checkout {
+					path: cached,
+					_temporary: None,
+				})
+			},
+			None => Ok(Checkout {
+				path: parent.path().to_path_buf(),
+				_temporary: Some(parent),
+			}),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use url::Url;
+
+	use crate::cache::SchemaCache;
+	use crate::error::VerifierError;
+
+	#[test]
+	fn test_offline() {
+		let cache = tempfile::tempdir().unwrap();
+		let schemas = cache.path().join("github.com/owner/repo/framework/json");
+		std::fs::create_dir_all(&schemas).unwrap();
+		std::fs::write(schemas.join("info.json"), "{}").unwrap();
+		let cache = SchemaCache::new(cache.path().to_path_buf()).offline();
+
+		let dest = tempfile::tempdir().unwrap();
+		let location = Url::parse("https://github.com/owner/repo/framework/json").unwrap();
+		cache.fetch(&location, dest.path()).unwrap();
+		assert!(dest.path().join("info.json").is_file());
+
+		let location = Url::parse("https://github.com/owner/other/framework").unwrap();
+		match cache.fetch(&location, dest.path()) {
+			Err(VerifierError::NotCached(repo)) => assert_eq!(repo, "https://github.com/owner/other"),
+			result => panic!("{:?}", result),
+		}
+		let location = Url::parse("https://gitlab.com/owner/repo").unwrap();
+		assert!(matches!(
+			cache.fetch(&location, dest.path()),
+			Err(VerifierError::BadSchemaLocation(_))
+		));
+	}
+}
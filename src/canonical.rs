@@ -0,0 +1,74 @@
+//! Canonical JSON for diff-friendly reports
+//!
+//! The reports are meant to be stored in git and compared with standard tools,
+//! which the layout of the structs gets in the way of. In the canonical form
+//! (`--canonical`), the keys of the objects are sorted, the reports of each
+//! entity are in a stable order whatever the order the validation workers
+//! finished in, and the floats are rounded to a fixed number of decimals. The
+//! measures of the run (`lastUpdated`, `elapsed_ms`, ...) are kept as is.
+
+use serde::Serialize;
+
+use crate::error::VerifierError;
+use crate::Json;
+
+/// Decimals the floats are rounded to
+pub const DECIMALS: i32 = 6;
+
+/// Rounds the floats of `json`, at any depth
+fn round_floats(json: &mut Json) {
+	let factor = 10f64.powi(DECIMALS);
+	match json {
+		Json::Number(number) if number.is_f64() => {
+			if let Some(rounded) = number
+				.as_f64()
+				.and_then(|float| serde_json::Number::from_f64((float * factor).round() / factor))
+			{
+				*number = rounded;
+			}
+		},
+		Json::Array(values) => values.iter_mut().for_each(round_floats),
+		Json::Object(object) => object.values_mut().for_each(round_floats),
+		_ => (),
+	}
+}
+
+/// Pretty-printed canonical JSON of `value`, whose arrays are expected in a stable order already
+pub fn to_json<T: Serialize>(value: &T) -> Result<String, VerifierError> {
+	// Through a string, since the objects of `Json` keep their keys sorted and the timings are u128
+	let mut json = serde_json::from_str::<Json>(&serde_json::to_string(value)?)?;
+	round_floats(&mut json);
+	Ok(serde_json::to_string_pretty(&json)?)
+}
+
+#[cfg(test)]
+mod tests {
+
+	use serde_json::json;
+
+	use crate::canonical;
+	use crate::testing::{Behavior, MockBeacon};
+
+	#[test]
+	fn test_canonical() {
+		let json = canonical::to_json(&json!({ "b": [2.0 / 3.0, 1], "a": { "d": 0.1, "c": true } })).unwrap();
+		assert_eq!(
+			json.split_whitespace().collect::<String>(),
+			r#"{"a":{"c":true,"d":0.1},"b":[0.666667,1]}"#
+		);
+
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let mut output = beacon.beacon().jobs(4).validate();
+		let canonical = output.to_canonical_json().unwrap();
+		let keys = canonical
+			.lines()
+			.filter(|line| line.starts_with("  \""))
+			.map(|line| line.trim().split('"').nth(1).unwrap())
+			.collect::<Vec<_>>();
+		assert!(keys.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", keys);
+		assert!(keys.contains(&"entities") && keys.contains(&"name"));
+		// The same whatever the order of the reports
+		output.entities.values_mut().for_each(|reports| reports.reverse());
+		assert_eq!(output.to_canonical_json().unwrap(), canonical);
+	}
+}
@@ -0,0 +1,539 @@
+//! Catalog of the checks run by the verifier
+//!
+//! Documentation sites and the policy documents of the Beacon network are
+//! generated from this catalog (`--export-check-catalog json`) instead of
+//! keeping their own lists of checks.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::formats::Format;
+use crate::output::Category;
+use crate::robustness::BodyEncoding;
+
+const FRAMEWORK: &str = "beacon-framework-v2";
+
+const MODEL: &str = "beacon-v2-Models";
+
+/// JSON text exchanged between systems must be UTF-8, a byte order mark may be ignored
+const RFC_8259: &str = "RFC 8259, section 8.1";
+
+/// Responses carry the date they were generated at, from a clock synchronized with UTC
+const RFC_9110: &str = "RFC 9110, section 6.6.1";
+
+/// Format of the exported catalog
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ArgEnum)]
+pub enum CatalogFormat {
+	Json,
+}
+
+/// Effect of a check on the results, from the least to the most serious
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, clap::ArgEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+	/// The check is reported in its `notes`, without failing nor warning
+	Info,
+	/// The check is reported in its `warnings`, without failing
+	Warning,
+	/// The check fails
+	Error,
+}
+
+impl std::fmt::Display for Severity {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Info => write!(f, "info"),
+			Self::Warning => write!(f, "warning"),
+			Self::Error => write!(f, "error"),
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CheckInfo {
+	pub id: String,
+	pub category: Category,
+	pub severity: Severity,
+	/// Part of the specification the check enforces
+	pub spec_reference: String,
+	pub description: String,
+	/// Option enabling the check, when it does not run by default
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub option: Option<String>,
+}
+
+fn check(id: &str, category: Category, severity: Severity, spec_reference: &str, description: &str) -> CheckInfo {
+	CheckInfo {
+		id: id.into(),
+		category,
+		severity,
+		spec_reference: spec_reference.into(),
+		description: description.into(),
+		option: None,
+	}
+}
+
+/// Every check of the verifier
+pub fn checks() -> Vec<CheckInfo> {
+	let framework_response = |id: &str, endpoint: &str, schema: &str| {
+		check(
+			id,
+			Category::Framework,
+			Severity::Error,
+			&format!("{}: responses/{}", FRAMEWORK, schema),
+			&format!("{} answers with a response of the framework", endpoint),
+		)
+	};
+	let mut checks = vec![
+		framework_response("framework.info", "/info", "beaconInfoResponse.json"),
+		framework_response(
+			"framework.configuration",
+			"/configuration",
+			"beaconConfigurationResponse.json",
+		),
+		framework_response("framework.map", "/map", "beaconMapResponse.json"),
+		framework_response("framework.entry-types", "/entry_types", "beaconEntryTypesResponse.json"),
+		check(
+			"framework.entity-response",
+			Category::Framework,
+			Severity::Error,
+			&format!(
+				"{}: responses/beaconBooleanResponse.json, beaconCountResponse.json, beaconResultsetsResponse.json, \
+				 beaconCollectionsResponse.json",
+				FRAMEWORK
+			),
+			"Each endpoint of the entities answers with the response of the framework for its granularity, with a \
+			 meta and a response summary",
+		),
+		check(
+			"framework.counts",
+			Category::Framework,
+			Severity::Error,
+			&format!(
+				"{}: responses/sections/beaconSummaryResponseSection.json, beaconResultsets.json",
+				FRAMEWORK
+			),
+			"The exists, numTotalResults and resultsCount of the result set responses agree with each other and with \
+			 the records returned",
+		),
+		check(
+			"framework.response-shape",
+			Category::Framework,
+			Severity::Error,
+			&format!("{}: meta of the responses", FRAMEWORK),
+			"The responses are not serialized twice (a JSON string holding the JSON of the response) nor wrapped in \
+			 an extra envelope or array",
+		),
+	];
+	checks.extend(BodyEncoding::ALL.into_iter().map(|encoding| CheckInfo {
+		option: Some("--robustness".into()),
+		..check(
+			&format!("framework.post-body.{}", encoding.id()),
+			Category::Framework,
+			Severity::Error,
+			RFC_8259,
+			&format!("/info handles a {}", encoding.name().to_lowercase()),
+		)
+	}));
+	checks.push(CheckInfo {
+		option: Some("--robustness".into()),
+		..check(
+			"security.error-responses",
+			Category::Framework,
+			Severity::Warning,
+			"OWASP ASVS 4.0, V7.4.1",
+			"The error responses to the robustness checks show no stack trace, SQL fragment nor debug page",
+		)
+	});
+	checks.push(check(
+		"security.echoes",
+		Category::Framework,
+		Severity::Warning,
+		"OWASP ASVS 4.0, V7.1.1",
+		"The meta.receivedRequestSummary of the responses and the error responses echo neither the credentials of the \
+		 verifier nor the personal identifiers supplied in the request",
+	));
+	checks.push(CheckInfo {
+		option: Some("--pagination".into()),
+		..check(
+			"framework.snapshot-pagination",
+			Category::Framework,
+			Severity::Error,
+			"Snapshot token in the meta of the responses (not part of the specification yet)",
+			"The pages of the endpoint with all the entries do not change nor overlap when queried with the snapshot \
+			 token of the first page",
+		)
+	});
+	checks.push(CheckInfo {
+		option: Some("--pagination".into()),
+		..check(
+			"framework.collections-pagination",
+			Category::Framework,
+			Severity::Error,
+			&format!(
+				"{}: responses/beaconCollectionsResponse.json, responseSummary",
+				FRAMEWORK
+			),
+			"The endpoint with all the collections of the datasets and cohorts returns at most one collection per \
+			 page of limit 1, pages that do not overlap, and counts that agree with the collections returned",
+		)
+	});
+	checks.push(CheckInfo {
+		option: Some("--pagination".into()),
+		..check(
+			"framework.pagination",
+			Category::Framework,
+			Severity::Error,
+			&format!("{}: requests/beaconRequestBody.json, pagination", FRAMEWORK),
+			"The endpoint with all the entries of the entities answered with result sets returns at most one record \
+			 per page of limit 1 (skip and limit in the query), no record twice across the pages, and a resultsCount \
+			 that does not change from a page to the next and adds up to the records of all the pages",
+		)
+	});
+	checks.push(CheckInfo {
+		option: Some("--pagination --post-queries".into()),
+		..check(
+			"framework.post-pagination",
+			Category::Framework,
+			Severity::Error,
+			&format!("{}: requests/beaconRequestBody.json, pagination", FRAMEWORK),
+			"The same pages, requested with the pagination of the body of POST queries",
+		)
+	});
+	let limit = |id: &str, description: &str| CheckInfo {
+		option: Some("--limits".into()),
+		..check(
+			id,
+			Category::Framework,
+			Severity::Error,
+			"Limits declared in /configuration or in the meta of the responses (receivedRequestSummary.pagination)",
+			description,
+		)
+	};
+	checks.extend([
+		limit(
+			"framework.limits.default-page-size",
+			"A query without a limit returns at most the default page size",
+		),
+		limit(
+			"framework.limits.page-size",
+			"A query at the largest page size is answered with at most that many records, and one just beyond it is \
+			 rejected (4xx) or capped",
+		),
+		limit(
+			"framework.limits.filters",
+			"A query with the most filters allowed is answered, and one with a filter more is rejected (4xx) or \
+			 answered, without a server error",
+		),
+	]);
+	let adaptive = |id: &str, description: &str| CheckInfo {
+		option: Some("--adaptive".into()),
+		..check(
+			id,
+			Category::Framework,
+			Severity::Error,
+			"Records returned by the endpoint with all the entries (not part of the specification)",
+			description,
+		)
+	};
+	checks.extend([
+		adaptive(
+			"framework.adaptive.filter",
+			"A query filtering on an ontology term of the first record of the entity finds the record",
+		),
+		adaptive(
+			"framework.adaptive.position",
+			"A range query at the position of the first genomic variant finds the variant",
+		),
+	]);
+	checks.push(CheckInfo {
+		option: Some("--filters".into()),
+		..check(
+			"framework.filters.consistency",
+			Category::Framework,
+			Severity::Error,
+			&format!("{}: filteringTerms, requests/filteringTerms.json", FRAMEWORK),
+			"A query filtering on a term advertised for the entity counts no more results than without the filter, \
+			 and returns records that carry the term",
+		)
+	});
+	let post = |id: &str, description: &str| CheckInfo {
+		option: Some("--post-queries".into()),
+		..check(
+			id,
+			Category::Framework,
+			Severity::Error,
+			&format!("{}: requests/beaconRequestBody.json", FRAMEWORK),
+			description,
+		)
+	};
+	checks.extend([
+		post(
+			"framework.post.query",
+			"A query POSTed with a request body (filters, pagination and requested granularity) is answered with a \
+			 response that follows the framework and the model",
+		),
+		post(
+			"framework.post.echo",
+			"The response echoes the granularity, the pagination and the filters of the request in \
+			 meta.receivedRequestSummary",
+		),
+	]);
+	let negative = |id: &str, description: &str| CheckInfo {
+		option: Some("--negative".into()),
+		..check(
+			id,
+			Category::Framework,
+			Severity::Error,
+			&format!("{}: responses/beaconErrorResponse.json", FRAMEWORK),
+			description,
+		)
+	};
+	checks.extend([
+		negative(
+			"framework.negative.filter",
+			"A request body with a malformed filter is rejected with 400 and an error response that follows the \
+			 framework",
+		),
+		negative(
+			"framework.negative.pagination",
+			"A query with a negative skip and a limit that is not a number is rejected with 400 and an error response \
+			 that follows the framework",
+		),
+		negative(
+			"framework.negative.unknown-id",
+			"The single entry url of an id that no record has answers 404 with an error response that follows the \
+			 framework",
+		),
+	]);
+	let variants = |id: &str, description: &str| CheckInfo {
+		option: Some("--variant-queries".into()),
+		..check(
+			id,
+			Category::Framework,
+			Severity::Error,
+			&format!("{}: endpoints of the genomic variations (g_variants)", FRAMEWORK),
+			description,
+		)
+	};
+	checks.extend([
+		variants(
+			"framework.variants.query",
+			"The sequence, range, bracket and geneId queries at the first genomic variant are answered with a \
+			 response that follows the framework",
+		),
+		variants(
+			"framework.variants.no-results",
+			"The same queries beyond the end of any chromosome, or with a gene that does not exist, find nothing",
+		),
+	]);
+	let protocol = |id: &str, option: &str, description: &str| CheckInfo {
+		option: Some(option.into()),
+		..check(
+			id,
+			Category::Framework,
+			Severity::Error,
+			"Versions negotiated with the host of the beacon (not part of the specification)",
+			description,
+		)
+	};
+	checks.extend([
+		protocol(
+			"framework.protocol.tls-version",
+			"--min-tls-version",
+			"The beacon is served over TLS, with at least the given version",
+		),
+		protocol(
+			"framework.protocol.http-version",
+			"--min-http-version",
+			"The host of the beacon supports at least the given version of HTTP (offered with ALPN over TLS)",
+		),
+	]);
+	checks.push(CheckInfo {
+		option: Some("latency-budgets (configuration file)".into()),
+		..check(
+			"framework.latency-budget",
+			Category::Framework,
+			Severity::Error,
+			"Service level of the beacon (not part of the specification)",
+			"The endpoint with all the entries of the entity answers a query, at the given granularity, within its \
+			 latency budget",
+		)
+	});
+	checks.push(check(
+		"model.returned-schema",
+		Category::Model,
+		Severity::Error,
+		&format!("{}: returnedSchemas of the response meta", FRAMEWORK),
+		"The schemas returned by each endpoint of the entities are schemas of the model for the entry type",
+	));
+	checks.push(check(
+		"model.declared-schema",
+		Category::Model,
+		Severity::Error,
+		&format!(
+			"{}: defaultSchema of the entry types of beaconEntryTypesResponse.json",
+			FRAMEWORK
+		),
+		"The schema returned by each endpoint of the entities for the entry type is the default schema the beacon \
+		 declares for it in /entry_types",
+	));
+	checks.push(check(
+		"model.entity",
+		Category::Model,
+		Severity::Error,
+		&format!("{}: <entity>/defaultSchema.json", MODEL),
+		"The records returned by each endpoint of the entities match the schema of the entity",
+	));
+	checks.push(check(
+		"model.result-sets",
+		Category::Model,
+		Severity::Error,
+		&format!("{}: responses/sections/beaconResultsets.json", FRAMEWORK),
+		"At boolean and count granularity, the result sets of the endpoint with all the entries of each entity have \
+		 an id, a set type, whether they have matches and, at count granularity, their number, but no records",
+	));
+	checks.push(check(
+		"model.id-consistency",
+		Category::Model,
+		Severity::Error,
+		&format!("{}: id of the records and <entryType>Id references", MODEL),
+		"A record has the same id in the endpoint with all the entries, in its single entry endpoint, in the related \
+		 endpoints and in the references of the other entities",
+	));
+	checks.push(CheckInfo {
+		option: Some("--references".into()),
+		..check(
+			"model.references",
+			Category::Model,
+			Severity::Error,
+			&format!("{}: <entryType>Id references of the records", MODEL),
+			"The records referenced by the first records of each entity (e.g. the individualId of a biosample) are \
+			 returned by the single entry url of their entity, and match its schema",
+		)
+	});
+	checks.extend(Format::ALL.into_iter().map(|format| {
+		check(
+			&format!("model.format.{}", format.keyword()),
+			Category::Model,
+			Severity::Error,
+			&format!("{}: format '{}'", MODEL, format.keyword()),
+			&format!(
+				"Values with the format '{}' are a {}",
+				format.keyword(),
+				format.description()
+			),
+		)
+	}));
+	checks.push(check(
+		"model.deprecated",
+		Category::Model,
+		Severity::Info,
+		&format!("{}: properties annotated as deprecated", MODEL),
+		"The records returned by each endpoint of the entities do not use the properties their schema deprecates",
+	));
+	checks.push(check(
+		"model.numeric-ranges",
+		Category::Model,
+		Severity::Error,
+		&format!("{}: frequencies and percentages of the entities", MODEL),
+		"The frequencies and proportions of the records are between 0 and 1, their percentages between 0 and 100, and \
+		 none of them is a NaN or an Infinity encoded as a string",
+	));
+	checks.push(check(
+		"model.identifiers",
+		Category::Model,
+		Severity::Error,
+		&format!("{}: identifiers and dates of the entities", MODEL),
+		"The ids of the ontology terms of the records are CURIEs, their iso8601duration values ISO 8601 durations, \
+		 their dates and timestamps ISO 8601 dates, and the HGVS identifiers of the genomic variants HGVS expressions",
+	));
+	checks.push(check(
+		"quality.charset",
+		Category::Framework,
+		Severity::Warning,
+		RFC_8259,
+		"Responses are UTF-8, and declare no other charset",
+	));
+	checks.push(check(
+		"quality.clock-skew",
+		Category::Framework,
+		Severity::Warning,
+		RFC_9110,
+		"The Date header of the responses is within 5 minutes of the clock of the verifier, and no timestamp of the \
+		 responses is in the future",
+	));
+	checks.push(check(
+		"quality.data-use",
+		Category::Model,
+		Severity::Warning,
+		&format!("{}: datasets/defaultSchema.json, dataUseConditions", MODEL),
+		"The datasets declare the conditions their data can be used under, with the terms of the Data Use Ontology",
+	));
+	checks.push(CheckInfo {
+		option: Some("--resolve-ontologies".into()),
+		..check(
+			"quality.ontology-terms",
+			Category::Model,
+			Severity::Warning,
+			"Ontology Lookup Service (OLS) of EMBL-EBI",
+			"The ontology terms of the records and of the filtering terms exist, are not obsolete and have the label \
+			 of their ontology",
+		)
+	});
+	checks.push(check(
+		"quality.mojibake",
+		Category::Framework,
+		Severity::Warning,
+		RFC_8259,
+		"The text of the responses has no replacement characters nor UTF-8 read as Latin-1",
+	));
+	checks
+}
+
+/// Severity of each check, by id
+pub fn severities() -> BTreeMap<String, Severity> {
+	checks().into_iter().map(|check| (check.id, check.severity)).collect()
+}
+
+pub fn export(format: CatalogFormat) -> String {
+	match format {
+		CatalogFormat::Json => serde_json::to_string_pretty(&checks()).expect("The catalog is always valid JSON"),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use std::collections::BTreeSet;
+
+	use crate::catalog::{checks, export, severities, CatalogFormat, Severity};
+	use crate::Json;
+
+	#[test]
+	fn test_catalog() {
+		let checks = checks();
+		let ids = checks.iter().map(|check| check.id.as_str()).collect::<BTreeSet<_>>();
+		assert_eq!(ids.len(), checks.len());
+		assert!(ids.contains("framework.info"));
+		assert!(ids.contains("framework.post-body.invalid"));
+		assert!(ids.contains("model.format.hgvs"));
+		assert!(checks
+			.iter()
+			.filter(|check| check.id.starts_with("quality."))
+			.all(|check| check.severity == Severity::Warning));
+		assert!(Severity::Info < Severity::Warning && Severity::Warning < Severity::Error);
+		assert_eq!(severities()["model.deprecated"], Severity::Info);
+
+		let json: Json = serde_json::from_str(&export(CatalogFormat::Json)).unwrap();
+		assert_eq!(json[0]["id"], "framework.info");
+		assert_eq!(json[0]["category"], "framework");
+		assert!(json[0]["specReference"]
+			.as_str()
+			.unwrap()
+			.ends_with("beaconInfoResponse.json"));
+		assert!(json[0].get("option").is_none());
+	}
+}
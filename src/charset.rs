@@ -0,0 +1,152 @@
+//! Data-quality checks of the text of the responses
+//!
+//! The responses must be UTF-8. Text that was decoded with the wrong charset
+//! somewhere along the way still validates against the schemas, so the strings
+//! are also searched for replacement characters (`\u{FFFD}`) and for the marks
+//! of UTF-8 read as Latin-1 or Windows-1252 (e.g. `Ã©` for `é`).
+
+use std::borrow::Cow;
+
+use crate::Json;
+
+/// Number of affected fields listed in a warning
+const MAX_FIELDS: usize = 5;
+
+/// Characters of Windows-1252 in the range 0x80-0x9F, following `Ã` or `Â` in double-encoded text
+const CP1252_HIGH: &str = "€‚ƒ„…†‡ˆ‰Š‹ŒŽ‘’“”•–—˜™š›œžŸ";
+
+/// Charset of a `Content-Type` header, in lowercase
+pub fn declared(content_type: &str) -> Option<String> {
+	content_type.split(';').skip(1).find_map(|parameter| {
+		let (name, value) = parameter.split_once('=')?;
+		name.trim()
+			.eq_ignore_ascii_case("charset")
+			.then(|| value.trim().trim_matches('"').to_lowercase())
+	})
+}
+
+/// Decodes the body as UTF-8 (replacing the invalid sequences), warning about a declared charset
+/// other than UTF-8 and about invalid sequences
+pub fn decode<'a>(content_type: Option<&str>, body: &'a [u8]) -> (Cow<'a, str>, Vec<String>) {
+	let mut warnings = Vec::new();
+	if let Some(charset) = content_type.and_then(declared) {
+		if charset != "utf-8" && charset != "utf8" {
+			warnings.push(format!("Declared charset is {}, expected UTF-8", charset));
+		}
+	}
+	let text = match std::str::from_utf8(body) {
+		Ok(text) => Cow::Borrowed(text),
+		Err(e) => {
+			warnings.push(format!("Response is not valid UTF-8 (from byte {})", e.valid_up_to()));
+			String::from_utf8_lossy(body)
+		},
+	};
+	(text, warnings)
+}
+
+/// Whether the text contains replacement characters or double-encoded characters
+pub fn is_mojibake(text: &str) -> bool {
+	let mut chars = text.chars().peekable();
+	while let Some(c) = chars.next() {
+		let next = chars.peek().copied();
+		let double_encoded = match (c, next) {
+			('\u{FFFD}', _) => true,
+			('Ã' | 'Â', Some(next)) => ('\u{80}'..='\u{BF}').contains(&next) || CP1252_HIGH.contains(next),
+			('â', Some('€')) => true,
+			_ => false,
+		};
+		if double_encoded {
+			return true;
+		}
+	}
+	false
+}
+
+fn find_mojibake(json: &Json, pointer: String, found: &mut Vec<String>) {
+	match json {
+		Json::String(text) if is_mojibake(text) => found.push(pointer),
+		Json::Array(items) => {
+			for (i, item) in items.iter().enumerate() {
+				find_mojibake(item, format!("{}/{}", pointer, i), found);
+			}
+		},
+		Json::Object(fields) => {
+			for (name, value) in fields {
+				let name = name.replace('~', "~0").replace('/', "~1");
+				find_mojibake(value, format!("{}/{}", pointer, name), found);
+			}
+		},
+		_ => (),
+	}
+}
+
+/// Warning listing the fields (JSON pointers) of the response with mojibake, if any
+pub fn mojibake(json: &Json) -> Option<String> {
+	let mut found = Vec::new();
+	find_mojibake(json, String::new(), &mut found);
+	if found.is_empty() {
+		return None;
+	}
+	let more = found.len().saturating_sub(MAX_FIELDS);
+	found.truncate(MAX_FIELDS);
+	let mut warning = format!("Replacement or double-encoded characters in {}", found.join(", "));
+	if more > 0 {
+		warning.push_str(&format!(" and {} more fields", more));
+	}
+	Some(warning)
+}
+
+#[cfg(test)]
+mod tests {
+
+	use serde_json::json;
+	use wiremock::ResponseTemplate;
+
+	use crate::charset::{declared, decode, is_mojibake, mojibake};
+	use crate::client::Client;
+	use crate::testing::{Behavior, MockBeacon};
+
+	#[test]
+	fn test_mojibake() {
+		assert!(is_mojibake("CaractÃ©risation"));
+		assert!(is_mojibake("donorâ€™s sample"));
+		assert!(is_mojibake("Ã‰tude"));
+		assert!(is_mojibake("Unknown \u{FFFD}"));
+		assert!(!is_mojibake("Caractérisation à Zürich, Ångström"));
+		assert!(!is_mojibake("São Paulo, Ã"));
+	}
+
+	#[test]
+	fn test_decode() {
+		assert_eq!(
+			declared("application/json; charset=\"ISO-8859-1\""),
+			Some("iso-8859-1".into())
+		);
+		assert_eq!(declared("application/json"), None);
+		assert!(decode(Some("application/json; charset=utf-8"), b"{}").1.is_empty());
+		let (text, warnings) = decode(Some("application/json; charset=latin1"), b"\"Caract\xe9risation\"");
+		assert_eq!(text, "\"Caract\u{FFFD}risation\"");
+		assert_eq!(warnings.len(), 2);
+		assert!(warnings[1].contains("byte 7"));
+	}
+
+	#[test]
+	fn test_warnings() {
+		let json = json!({ "response": { "collections": [{ "id": "ds1", "description": "CaractÃ©risation" }] } });
+		assert_eq!(
+			mojibake(&json).as_deref(),
+			Some("Replacement or double-encoded characters in /response/collections/0/description")
+		);
+		assert_eq!(mojibake(&json!({ "description": "Caractérisation" })), None);
+
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		beacon.mount(
+			"/info",
+			ResponseTemplate::new(200).set_body_raw(b"{\"name\": \"Caract\xe9risation\"}".to_vec(), "application/json"),
+		);
+		let response = Client::new()
+			.query("Info", &beacon.url().join("info").unwrap())
+			.unwrap();
+		assert_eq!(response.warnings.len(), 2);
+	}
+}
@@ -0,0 +1,156 @@
+//! Clock skew between the beacon and the verifier
+//!
+//! A beacon whose clock is off issues or checks tokens that expire too early
+//! or too late, failures that look like bugs of the beacon. The `Date` header
+//! of every response is compared to the clock of the verifier, and the
+//! timestamps of the responses (e.g. `updateDateTime`) must not be in the
+//! future. Both are warnings, since they tell about the host of the beacon
+//! rather than about its compliance.
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+
+use crate::Json;
+
+/// Skew tolerated between the clocks, in seconds
+pub const MAX_SKEW_SECS: i64 = 300;
+
+/// Number of future-dated fields listed in a warning
+const MAX_FIELDS: usize = 5;
+
+/// Warning about the `Date` header of a response received at `now`, if it is off by more than the tolerated skew
+pub fn skew(date: Option<&str>, now: DateTime<Utc>) -> Option<String> {
+	let date = DateTime::parse_from_rfc2822(date?.trim()).ok()?;
+	let skew = date.with_timezone(&Utc) - now;
+	if skew.num_seconds().abs() <= MAX_SKEW_SECS {
+		return None;
+	}
+	Some(format!(
+		"The Date header is {} s {} the clock of the verifier",
+		skew.num_seconds().abs(),
+		if skew.num_seconds() > 0 { "ahead of" } else { "behind" }
+	))
+}
+
+/// Whether the property holds a timestamp (e.g. `createDateTime`, `timestamp`)
+fn is_timestamp(property: &str) -> bool {
+	let property = property.to_lowercase();
+	property.ends_with("datetime") || property.ends_with("timestamp")
+}
+
+/// Whether the timestamp is later than `now`, beyond the tolerated skew
+fn is_future(value: &str, now: DateTime<Utc>) -> bool {
+	let latest = now + chrono::Duration::seconds(MAX_SKEW_SECS);
+	if let Ok(datetime) = DateTime::parse_from_rfc3339(value) {
+		return datetime.with_timezone(&Utc) > latest;
+	}
+	if let Ok(datetime) = NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%.f") {
+		return datetime > latest.naive_utc();
+	}
+	// A date without a time zone is at most one day ahead of UTC somewhere
+	match NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+		Ok(date) => date > latest.naive_utc().date().succ(),
+		Err(_) => false,
+	}
+}
+
+fn find_future(json: &Json, pointer: String, now: DateTime<Utc>, found: &mut Vec<String>) {
+	match json {
+		Json::Array(items) => {
+			for (i, item) in items.iter().enumerate() {
+				find_future(item, format!("{}/{}", pointer, i), now, found);
+			}
+		},
+		Json::Object(fields) => {
+			for (name, value) in fields {
+				let field = format!("{}/{}", pointer, name.replace('~', "~0").replace('/', "~1"));
+				match value {
+					Json::String(value) if is_timestamp(name) && is_future(value, now) => found.push(field),
+					_ => find_future(value, field, now, found),
+				}
+			}
+		},
+		_ => (),
+	}
+}
+
+/// Warning listing the timestamps (JSON pointers) of the response later than `now`, if any
+pub fn future_dates(json: &Json, now: DateTime<Utc>) -> Option<String> {
+	let mut found = Vec::new();
+	find_future(json, String::new(), now, &mut found);
+	if found.is_empty() {
+		return None;
+	}
+	let more = found.len().saturating_sub(MAX_FIELDS);
+	found.truncate(MAX_FIELDS);
+	let mut warning = format!("Timestamps in the future in {}", found.join(", "));
+	if more > 0 {
+		warning.push_str(&format!(" and {} more fields", more));
+	}
+	Some(warning)
+}
+
+#[cfg(test)]
+mod tests {
+
+	use chrono::{TimeZone, Utc};
+	use serde_json::json;
+	use wiremock::ResponseTemplate;
+
+	use crate::client::Client;
+	use crate::clock::{future_dates, skew};
+	use crate::testing::{Behavior, MockBeacon};
+
+	#[test]
+	fn test_skew() {
+		let now = Utc.ymd(2022, 5, 1).and_hms(12, 0, 0);
+		assert_eq!(skew(Some("Sun, 01 May 2022 12:04:00 GMT"), now), None);
+		assert_eq!(
+			skew(Some("Sun, 01 May 2022 12:10:00 GMT"), now).as_deref(),
+			Some("The Date header is 600 s ahead of the clock of the verifier")
+		);
+		assert!(skew(Some("Sun, 01 May 2022 11:00:00 GMT"), now)
+			.unwrap()
+			.contains("3600 s behind"));
+		assert_eq!(skew(Some("yesterday"), now), None);
+		assert_eq!(skew(None, now), None);
+	}
+
+	#[test]
+	fn test_future_dates() {
+		let now = Utc.ymd(2022, 5, 1).and_hms(12, 0, 0);
+		let json = json!({
+			"meta": { "createDateTime": "2022-05-01T11:00:00Z" },
+			"response": { "collections": [
+				{ "id": "ds1", "updateDateTime": "2023-01-01T00:00:00+01:00" },
+				{ "id": "ds2", "createDateTime": "2022-05-02", "updateDateTime": "2022-05-03" },
+				{ "id": "ds3", "timestamp": "2022-05-01T13:00:00", "description": "2030-01-01T00:00:00Z" }
+			] }
+		});
+		assert_eq!(
+			future_dates(&json, now).as_deref(),
+			Some(
+				"Timestamps in the future in /response/collections/0/updateDateTime, \
+				 /response/collections/1/updateDateTime, /response/collections/2/timestamp"
+			)
+		);
+		assert_eq!(future_dates(&json, Utc.ymd(2023, 1, 1).and_hms(0, 0, 0)), None);
+	}
+
+	#[test]
+	fn test_warnings() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let info = beacon.response("/info");
+		let url = beacon.url().join("info").unwrap();
+		assert!(Client::new().query("Info", &url).unwrap().warnings.is_empty());
+
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let template = ResponseTemplate::new(200)
+			.insert_header("Date", "Wed, 21 Oct 2015 07:28:00 GMT")
+			.set_body_json(info);
+		beacon.mount("/info", template);
+		let url = beacon.url().join("info").unwrap();
+		let warnings = Client::new().query("Info", &url).unwrap().warnings;
+		assert_eq!(warnings.len(), 1, "{:?}", warnings);
+		assert!(warnings[0].contains("behind the clock of the verifier"));
+	}
+}
@@ -0,0 +1,235 @@
+//! Pagination of the collections
+//!
+//! The datasets and the cohorts are answered with `response.collections`
+//! instead of result sets, with their own count in the response summary. The
+//! entities are told apart by the shape of their responses, whatever their
+//! names: the entry types answered with collections are the collections. With
+//! `--pagination`, the endpoint with all the collections of these entities is
+//! also paged one collection at a time: each page must hold at most one
+//! collection, the pages must not overlap, and the counts must agree with the
+//! collections returned, whether the response is paged or not.
+
+use std::collections::BTreeSet;
+
+use url::Url;
+
+use crate::client::Client;
+use crate::error::VerifierError;
+use crate::model::Entity;
+use crate::output::{Category, EndpointReport};
+use crate::{utils, Json};
+
+/// Name of the checks, after the name of the entity
+pub const CHECK_NAME: &str = "collections pagination";
+
+/// Whether the response holds collections instead of result sets
+pub fn is_collections_response(response_json: &Json) -> bool {
+	response_json
+		.pointer("/response/collections")
+		.map_or(false, Json::is_array)
+}
+
+struct Page {
+	ids: Vec<String>,
+	exists: Option<bool>,
+	total: Option<u64>,
+}
+
+impl Page {
+	/// Problems of the counts of the page against its collections
+	fn count_problems(&self, name: &str) -> Vec<String> {
+		let mut problems = Vec::new();
+		if let (Some(exists), Some(total)) = (self.exists, self.total) {
+			if exists != (total > 0) {
+				problems.push(format!("{} has exists {} with numTotalResults {}", name, exists, total));
+			}
+		}
+		if self.exists == Some(false) && !self.ids.is_empty() {
+			problems.push(format!("{} has exists false with {} collections", name, self.ids.len()));
+		}
+		if let Some(total) = self.total.filter(|total| *total < self.ids.len() as u64) {
+			problems.push(format!(
+				"{} has numTotalResults {} with {} collections",
+				name,
+				total,
+				self.ids.len()
+			));
+		}
+		problems
+	}
+}
+
+fn page(client: &Client, entity: &Entity, url: &Url, skip: Option<usize>) -> Result<Page, VerifierError> {
+	let mut url = url.clone();
+	if let Some(skip) = skip {
+		url.query_pairs_mut()
+			.append_pair("skip", &skip.to_string())
+			.append_pair("limit", "1");
+	}
+	let json = client.query(&entity.name, &url)?.json;
+	let collections = json
+		.pointer("/response/collections")
+		.and_then(Json::as_array)
+		.ok_or_else(|| VerifierError::BadEnvelope("No 'response.collections' array was found".into()))?;
+	Ok(Page {
+		ids: collections
+			.iter()
+			.filter_map(|collection| utils::record_id(collection, &entity.id_field))
+			.collect(),
+		exists: json.pointer("/responseSummary/exists").and_then(Json::as_bool),
+		total: json.pointer("/responseSummary/numTotalResults").and_then(Json::as_u64),
+	})
+}
+
+/// Pages the endpoint with all the collections of `entity` and checks its counts
+pub fn check(client: &Client, root_url: &Url, entity: &Entity) -> EndpointReport {
+	let url = utils::url_join(root_url, &entity.url);
+	let report = EndpointReport::new(&entity.name, &format!("{} {}", entity.name, CHECK_NAME), url.clone())
+		.category(Category::Framework)
+		.check("framework.collections-pagination");
+	let problems = paginate(client, entity, &url);
+	match problems {
+		Ok(None) => report.skip("no collections to paginate", Json::Null),
+		Ok(Some(problems)) if problems.is_empty() => report.ok(None),
+		Ok(Some(problems)) => report.error(VerifierError::BadResponse(problems.join("\n"))),
+		Err(e) => {
+			log::error!("{}", e);
+			report.null(e)
+		},
+	}
+}
+
+/// Problems of the pages of collections, `None` if there are no collections
+fn paginate(client: &Client, entity: &Entity, url: &Url) -> Result<Option<Vec<String>>, VerifierError> {
+	let all = page(client, entity, url, None)?;
+	let mut problems = all.count_problems("the response");
+	if all.ids.is_empty() && all.total.map_or(true, |total| total == 0) {
+		return Ok(None);
+	}
+	let total = all.total.unwrap_or(all.ids.len() as u64);
+
+	let first = page(client, entity, url, Some(0))?;
+	problems.extend(first.count_problems("the first page"));
+	if first.ids.len() > 1 {
+		problems.push(format!(
+			"the first page has {} collections with limit 1",
+			first.ids.len()
+		));
+	}
+	if first.total.map_or(false, |first_total| first_total != total) {
+		problems.push(format!(
+			"the first page has numTotalResults {} instead of {}",
+			first.total.unwrap_or_default(),
+			total
+		));
+	}
+	if total > 1 {
+		let second = page(client, entity, url, Some(1))?;
+		problems.extend(second.count_problems("the second page"));
+		if second.ids.is_empty() {
+			problems.push(format!("the second page is empty with numTotalResults {}", total));
+		}
+		let first_ids = first.ids.iter().collect::<BTreeSet<_>>();
+		let overlap = second
+			.ids
+			.iter()
+			.filter(|id| first_ids.contains(id))
+			.map(|id| format!("'{}'", id))
+			.collect::<Vec<_>>();
+		if !overlap.is_empty() {
+			problems.push(format!(
+				"the first and second pages both returned {}",
+				overlap.join(", ")
+			));
+		}
+	}
+	Ok(Some(problems))
+}
+
+#[cfg(test)]
+mod tests {
+
+	use serde_json::json;
+	use wiremock::{Request, ResponseTemplate};
+
+	use crate::collections::CHECK_NAME;
+	use crate::output::{BeaconOutput, EndpointOutput};
+	use crate::testing::{Behavior, MockBeacon};
+
+	fn pagination(output: &BeaconOutput) -> &EndpointOutput {
+		output.entities["Dataset"]
+			.iter()
+			.find(|report| report.name == format!("Dataset {}", CHECK_NAME))
+			.unwrap()
+	}
+
+	#[test]
+	fn test_collections_pagination() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let output = beacon.beacon().pagination().validate();
+		let report = pagination(&output);
+		assert_eq!(report.valid, Some(true), "{:?}", report.error);
+		// Only the collections
+		assert!(output.entities["Individual"]
+			.iter()
+			.all(|report| !report.name.ends_with(CHECK_NAME)));
+
+		// Two datasets, the skip and limit ignored
+		let mut datasets = beacon.response("/datasets");
+		let ds2 = json!({ "id": "ds2", "name": "Dataset 2" });
+		datasets["response"]["collections"].as_array_mut().unwrap().push(ds2);
+		datasets["responseSummary"]["numTotalResults"] = json!(2);
+		beacon.mount("/datasets", ResponseTemplate::new(200).set_body_json(datasets.clone()));
+		let output = beacon.beacon().pagination().validate();
+		let error = pagination(&output).error.clone().unwrap();
+		assert!(
+			error.contains("the first page has 2 collections with limit 1"),
+			"{}",
+			error
+		);
+		assert!(
+			error.contains("the first and second pages both returned 'ds1', 'ds2'"),
+			"{}",
+			error
+		);
+
+		// Paged, but counting the page
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		beacon.mount("/datasets", move |request: &Request| {
+			let skip = request.url.query_pairs().find(|(key, _)| key == "skip");
+			let mut page = datasets.clone();
+			if let Some((_, skip)) = skip {
+				let skip = skip.parse::<usize>().unwrap();
+				let collections = page["response"]["collections"].as_array().cloned().unwrap();
+				page["response"]["collections"] = json!(collections[skip..=skip]);
+				page["responseSummary"]["numTotalResults"] = json!(1);
+			}
+			ResponseTemplate::new(200).set_body_json(page)
+		});
+		let output = beacon.beacon().pagination().validate();
+		let error = pagination(&output).error.clone().unwrap();
+		assert_eq!(error.lines().count(), 1, "{}", error);
+		assert!(
+			error.contains("the first page has numTotalResults 1 instead of 2"),
+			"{}",
+			error
+		);
+	}
+
+	#[test]
+	fn test_collections_by_shape() {
+		// Whatever the name of the entity, its responses tell whether it is a collection
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let mut individuals = beacon.response("/individuals");
+		let records = individuals["response"]["resultSets"][0]["results"].clone();
+		individuals["response"] = json!({ "collections": records });
+		beacon.mount("/individuals", ResponseTemplate::new(200).set_body_json(individuals));
+		let output = beacon.beacon().pagination().validate();
+		let names = output.entities["Individual"]
+			.iter()
+			.map(|report| report.name.as_str())
+			.collect::<Vec<_>>();
+		assert!(names.contains(&"Individual collections pagination"), "{:?}", names);
+		assert!(!names.contains(&"Individual pagination"), "{:?}", names);
+	}
+}
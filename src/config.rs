@@ -0,0 +1,614 @@
+//! Configuration file with named beacon targets
+//!
+//! ```toml
+//! [beacons.production]
+//! url = "https://beacon.example.org/api"
+//! method-policy = "post"
+//! disabled-formats = ["hgvs"]
+//! timeout-ms = 10000
+//! request-retries = 3
+//! request-backoff-ms = 1000
+//! entities = ["individual", "biosample"]
+//! output-format = "junit"
+//!
+//! [beacons.production.headers]
+//! X-Api-Key = "<key>"
+//!
+//! [beacons.internal]
+//! url = "https://internal.example.org/api"
+//! token = "<token>"
+//!
+//! [beacons.legacy.api-key]
+//! style = "query"
+//! name = "apikey"
+//! key = "<key>"
+//!
+//! [beacons.production.auth]
+//! token-url = "https://login.example.org/oauth2/token"
+//! client-id = "beacon-verifier"
+//! refresh-token = "<token>"
+//!
+//! [[beacons.production.webhooks]]
+//! url = "https://hooks.example.org/schema-drift"
+//! categories = ["model"]
+//! template = '{"text": "{count} model checks failed on {beacon}"}'
+//!
+//! [[beacons.production.webhooks]]
+//! url = "https://hooks.example.org/security"
+//! checks = ["security"]
+//!
+//! [[beacons.production.maintenance]]
+//! schedule = "0 2 * * 0"
+//! duration-minutes = 120
+//!
+//! [[beacons.production.latency-budgets]]
+//! entity = "GenomicVariation"
+//! granularity = "count"
+//! budget-ms = 5000
+//!
+//! [beacons.staging]
+//! url = "https://staging.beacon.example.org/api"
+//! only-framework = true
+//!
+//! [beacons.network]
+//! network = ["https://beacon.example.org/api", "https://beacon.example.com/api"]
+//! ```
+//!
+//! The file can be written in YAML too (with a `.yaml` or `.yml` extension),
+//! with the same keys. It is checked against the JSON Schema of [`schema`]
+//! before it is read, so that every mistake is reported at once with the key
+//! it is under and its line (see [`crate::syntax`]).
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::str::FromStr;
+
+use chrono::NaiveDateTime;
+use clap::ArgEnum;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde::Deserialize;
+use serde_json::json;
+use url::Url;
+
+use crate::auth::{ApiKey, AuthConfig, BasicAuth};
+use crate::client::MethodPolicy;
+use crate::error::VerifierError;
+use crate::formats::Format;
+use crate::latency::LatencyBudget;
+use crate::maintenance::MaintenanceWindow;
+use crate::notify::Webhook;
+use crate::output::Category;
+use crate::preset::ModelPreset;
+use crate::spec::SpecVersion;
+use crate::syntax::Syntax;
+use crate::Json;
+
+/// JSON Schema of the configuration file
+pub fn schema() -> Json {
+	let method_policy = json!({
+		"enum": MethodPolicy::value_variants()
+			.iter()
+			.map(|policy| serde_json::to_value(policy).unwrap_or_default())
+			.collect::<Vec<_>>()
+	});
+	let format = json!({ "enum": Format::ALL.iter().map(|format| format.keyword()).collect::<Vec<_>>() });
+	let category = json!({ "enum": Category::ALL });
+	let model_preset = json!({
+		"enum": ModelPreset::value_variants().iter().map(|preset| preset.name()).collect::<Vec<_>>()
+	});
+	let spec_version = json!({
+		"enum": SpecVersion::value_variants().iter().map(SpecVersion::to_string).collect::<Vec<_>>()
+	});
+	json!({
+		"type": "object",
+		"additionalProperties": false,
+		"properties": {
+			"beacons": {
+				"type": "object",
+				"additionalProperties": {
+					"type": "object",
+					"additionalProperties": false,
+					"properties": {
+						"url": { "type": "string", "format": "uri" },
+						"network": { "type": "array", "items": { "type": "string", "format": "uri" }, "minItems": 1 },
+						"model": { "type": "string", "format": "uri" },
+						"model-preset": model_preset,
+						"framework": { "type": "string", "format": "uri" },
+						"spec-version": spec_version,
+						"only-framework": { "type": "boolean" },
+						"only-model": { "type": "boolean" },
+						"entities": { "type": "array", "items": { "type": "string", "minLength": 1 } },
+						"skip-entities": { "type": "array", "items": { "type": "string", "minLength": 1 } },
+						"output-format": { "type": "string", "minLength": 1 },
+						"method-policy": method_policy,
+						"endpoint-method-policies": { "type": "object", "additionalProperties": method_policy },
+						"disabled-formats": { "type": "array", "items": format },
+						"headers": { "type": "object", "additionalProperties": { "type": "string" } },
+						"timeout-ms": { "type": "integer", "minimum": 1 },
+						"request-retries": { "type": "integer", "minimum": 0 },
+						"request-backoff-ms": { "type": "integer", "minimum": 0 },
+						"proxy": { "type": "string", "pattern": "^(https?|socks5h?)://" },
+						"token": { "type": "string" },
+						"basic-auth": {
+							"type": "object",
+							"additionalProperties": false,
+							"required": ["username"],
+							"properties": {
+								"username": { "type": "string", "minLength": 1 },
+								"password": { "type": "string" }
+							}
+						},
+						"api-key": {
+							"type": "object",
+							"additionalProperties": false,
+							"required": ["style", "name", "key"],
+							"properties": {
+								"style": { "enum": ["query", "cookie", "header"] },
+								"name": { "type": "string", "minLength": 1 },
+								"key": { "type": "string" }
+							}
+						},
+						"auth": {
+							"type": "object",
+							"additionalProperties": false,
+							"required": ["token-url", "client-id"],
+							"properties": {
+								"token-url": { "type": "string", "format": "uri" },
+								"client-id": { "type": "string" },
+								"client-secret": { "type": "string" },
+								"refresh-token": { "type": "string" },
+								"scope": { "type": "string" }
+							}
+						},
+						"webhooks": {
+							"type": "array",
+							"items": {
+								"type": "object",
+								"additionalProperties": false,
+								"required": ["url"],
+								"properties": {
+									"url": { "type": "string", "format": "uri" },
+									"categories": { "type": "array", "items": category },
+									"checks": { "type": "array", "items": { "type": "string" } },
+									"template": { "type": "string" }
+								}
+							}
+						},
+						"maintenance": {
+							"type": "array",
+							"items": {
+								"type": "object",
+								"additionalProperties": false,
+								"required": ["schedule", "duration-minutes"],
+								"properties": {
+									"schedule": { "type": "string" },
+									"duration-minutes": { "type": "integer", "minimum": 0 }
+								}
+							}
+						},
+						"latency-budgets": {
+							"type": "array",
+							"items": {
+								"type": "object",
+								"additionalProperties": false,
+								"required": ["entity", "budget-ms"],
+								"properties": {
+									"entity": { "type": "string" },
+									"granularity": { "enum": ["boolean", "count", "aggregated", "record"] },
+									"budget-ms": { "type": "integer", "minimum": 0 }
+								}
+							}
+						}
+					}
+				}
+			}
+		}
+	})
+}
+
+/// Problems of the configuration file against its schema, each one after the key it is under, with its line
+/// (e.g. `beacons.production.method-policy: "put" is not one of ["get","post","get-then-post"] (line 3, column 1)`)
+pub fn problems(content: &str, syntax: Syntax) -> Result<Vec<String>, VerifierError> {
+	crate::syntax::problems(content, syntax, &schema()).map_err(VerifierError::BadConfig)
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+	#[serde(default)]
+	pub beacons: BTreeMap<String, Profile>,
+}
+
+/// Options of one beacon target, used where the command line does not set them
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Profile {
+	pub url: Option<Url>,
+	/// Urls of the members of a Beacon network, verified in one run instead of `url`
+	pub network: Vec<Url>,
+	pub model: Option<Url>,
+	/// Curated model of non-human beacons, when there is no `model`
+	pub model_preset: Option<ModelPreset>,
+	pub framework: Option<Url>,
+	/// Version of the specification, instead of the one declared by the beacon
+	pub spec_version: Option<SpecVersion>,
+	pub only_framework: bool,
+	pub only_model: bool,
+	/// Entities to verify, by name, entry type or endpoint (all of them if empty)
+	pub entities: Vec<String>,
+	/// Entities left out, by name, entry type or endpoint
+	pub skip_entities: Vec<String>,
+	/// Renderer of the output (e.g. `junit`)
+	pub output_format: Option<String>,
+	pub method_policy: Option<MethodPolicy>,
+	/// Method policy per endpoint, identified by its entity name
+	pub endpoint_method_policies: BTreeMap<String, MethodPolicy>,
+	pub disabled_formats: Vec<Format>,
+	/// Headers sent with every request (e.g. `Authorization`)
+	pub headers: BTreeMap<String, String>,
+	/// Timeout of the requests, in milliseconds
+	pub timeout_ms: Option<u64>,
+	/// Retries of the requests failing transiently
+	pub request_retries: Option<u32>,
+	/// Wait before the first retry, in milliseconds
+	pub request_backoff_ms: Option<u64>,
+	/// HTTP(S) or SOCKS5 proxy of the requests, instead of the one of `HTTP_PROXY` and `HTTPS_PROXY`
+	pub proxy: Option<Url>,
+	/// Bearer token sent with every request
+	pub token: Option<String>,
+	/// Credentials of the basic scheme sent with every request
+	pub basic_auth: Option<BasicAuth>,
+	/// API key sent with every request, in a query parameter, a cookie or a header
+	pub api_key: Option<ApiKey>,
+	/// OAuth2 flow to refresh the access token when it expires
+	pub auth: Option<AuthConfig>,
+	/// Webhooks notified of the failed checks
+	pub webhooks: Vec<Webhook>,
+	/// Planned downtime, when failures do not alert
+	pub maintenance: Vec<MaintenanceWindow>,
+	/// Time each entity has to answer a query
+	pub latency_budgets: Vec<LatencyBudget>,
+}
+
+impl Profile {
+	/// Whether `at` (UTC) falls in a maintenance window
+	pub fn in_maintenance(&self, at: NaiveDateTime) -> bool {
+		self.maintenance.iter().any(|window| window.contains(at))
+	}
+}
+
+impl ConfigFile {
+	/// Reads the configuration file at `path`, in YAML after its extension and in TOML otherwise
+	pub fn load(path: &Path) -> Result<Self, VerifierError> {
+		let content = std::fs::read_to_string(path)?;
+		Self::parse(&content, Syntax::of(path, Syntax::Toml))
+	}
+
+	pub fn parse(content: &str, syntax: Syntax) -> Result<Self, VerifierError> {
+		let problems = problems(content, syntax)?;
+		if !problems.is_empty() {
+			return Err(VerifierError::BadConfig(problems.join("\n")));
+		}
+		// What the schema cannot tell (e.g. the cron expressions) is found while reading the file
+		syntax.deserialize(content).map_err(VerifierError::BadConfig)
+	}
+
+	pub fn profile(&self, target: &str) -> Result<Profile, VerifierError> {
+		self.beacons.get(target).cloned().ok_or_else(|| {
+			VerifierError::BadConfig(format!(
+				"no target '{}' (available: {:?})",
+				target,
+				self.beacons.keys().collect::<Vec<_>>()
+			))
+		})
+	}
+}
+
+/// Header of the command line, parsed from `NAME: VALUE` (e.g. `X-Api-Key: <key>`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestHeader {
+	pub name: String,
+	pub value: String,
+}
+
+impl FromStr for RequestHeader {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let (name, value) = s
+			.split_once(':')
+			.filter(|(name, _)| !name.trim().is_empty())
+			.ok_or_else(|| format!("expected NAME: VALUE, got '{}'", s))?;
+		Ok(Self {
+			name: name.trim().to_string(),
+			value: value.trim().to_string(),
+		})
+	}
+}
+
+/// Whether the header carries credentials, after its name
+fn is_credential(name: &HeaderName) -> bool {
+	let name = name.as_str();
+	name == "authorization"
+		|| name == "proxy-authorization"
+		|| name == "cookie"
+		|| ["key", "token", "secret", "auth", "session"]
+			.iter()
+			.any(|part| name.contains(part))
+}
+
+impl Profile {
+	/// Profile with the headers of the command line, which replace the ones of the same name
+	pub fn with_headers(mut self, headers: &[RequestHeader]) -> Self {
+		for header in headers {
+			self.headers.retain(|name, _| !name.eq_ignore_ascii_case(&header.name));
+			self.headers.insert(header.name.clone(), header.value.clone());
+		}
+		self
+	}
+
+	/// Headers sent with every request, with the API key if it is sent in a header or a cookie (the ones carrying
+	/// credentials being sensitive)
+	pub fn header_map(&self) -> Result<HeaderMap, VerifierError> {
+		let mut headers = self
+			.headers
+			.iter()
+			.map(|(name, value)| {
+				let name = HeaderName::from_bytes(name.as_bytes())
+					.map_err(|_| VerifierError::BadConfig(format!("invalid header name '{}'", name)))?;
+				let mut value = HeaderValue::from_str(value)
+					.map_err(|_| VerifierError::BadConfig(format!("invalid value for header '{}'", name)))?;
+				value.set_sensitive(is_credential(&name));
+				Ok((name, value))
+			})
+			.collect::<Result<HeaderMap, VerifierError>>()?;
+		if let Some((name, value)) = self.api_key.as_ref().map(ApiKey::header).transpose()?.flatten() {
+			headers.append(name, value);
+		}
+		Ok(headers)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use chrono::NaiveDate;
+	use wiremock::matchers::{header, method, path};
+	use wiremock::{Mock, ResponseTemplate};
+
+	use crate::client::{Client, MethodPolicy};
+	use crate::config::{self, ConfigFile, RequestHeader};
+	use crate::formats::Format;
+	use crate::preset::ModelPreset;
+	use crate::spec::SpecVersion;
+	use crate::syntax::Syntax;
+	use crate::testing::{Behavior, MockBeacon};
+
+	const CONFIG: &str = r#"
+		[beacons.production]
+		url = "https://beacon.example.org/api"
+		method-policy = "post"
+		disabled-formats = ["iso-country-code"]
+		timeout-ms = 10000
+		request-retries = 3
+		entities = ["individual", "Biosample"]
+		skip-entities = ["g_variants"]
+		output-format = "junit"
+
+		[beacons.production.endpoint-method-policies]
+		individual = "get"
+
+		[beacons.production.headers]
+		Authorization = "Bearer secret"
+
+		[[beacons.production.maintenance]]
+		schedule = "0 2 * * 0"
+		duration-minutes = 120
+
+		[[beacons.production.latency-budgets]]
+		entity = "GenomicVariation"
+		granularity = "count"
+		budget-ms = 5000
+
+		[beacons.internal]
+		token = "s3cr3t"
+
+		[beacons.intranet.basic-auth]
+		username = "verifier"
+		password = "s3cr3t"
+
+		[beacons.staging]
+		url = "https://staging.beacon.example.org/api"
+		model-preset = "pathogen"
+		spec-version = "2.1"
+		only-framework = true
+
+		[beacons.network]
+		network = ["https://beacon.example.org/api", "https://beacon.example.com/api"]
+	"#;
+
+	#[test]
+	fn test_profiles() {
+		let config = ConfigFile::parse(CONFIG, Syntax::Toml).unwrap();
+		let production = config.profile("production").unwrap();
+		assert_eq!(production.url.as_ref().unwrap().host_str(), Some("beacon.example.org"));
+		assert_eq!(production.method_policy, Some(MethodPolicy::Post));
+		assert_eq!(production.endpoint_method_policies["individual"], MethodPolicy::Get);
+		assert_eq!(production.disabled_formats, vec![Format::IsoCountryCode]);
+		assert_eq!(
+			(production.timeout_ms, production.request_retries),
+			(Some(10000), Some(3))
+		);
+		assert_eq!(production.request_backoff_ms, None);
+		assert_eq!(production.entities, vec!["individual", "Biosample"]);
+		assert_eq!(production.skip_entities, vec!["g_variants"]);
+		assert_eq!(production.output_format.as_deref(), Some("junit"));
+		assert_eq!(production.header_map().unwrap()["authorization"], "Bearer secret");
+		// 2022-05-01 is a Sunday
+		assert!(production.in_maintenance(NaiveDate::from_ymd(2022, 5, 1).and_hms(3, 0, 0)));
+		assert!(!production.in_maintenance(NaiveDate::from_ymd(2022, 5, 1).and_hms(4, 0, 0)));
+		assert_eq!(
+			production.latency_budgets[0].name(),
+			"GenomicVariation latency budget (count)"
+		);
+		let staging = config.profile("staging").unwrap();
+		assert!(staging.only_framework);
+		assert_eq!(staging.model_preset, Some(ModelPreset::Pathogen));
+		assert_eq!(staging.spec_version, Some(SpecVersion::V2_1));
+		assert!(staging.headers.is_empty());
+		assert!(config.profile("development").is_err());
+		assert_eq!(config.profile("internal").unwrap().token.as_deref(), Some("s3cr3t"));
+		let basic_auth = config.profile("intranet").unwrap().basic_auth.unwrap();
+		assert_eq!(basic_auth, "verifier:s3cr3t".parse().unwrap());
+		let network = config.profile("network").unwrap();
+		assert_eq!(network.network.len(), 2);
+		assert_eq!(network.url, None);
+	}
+
+	#[test]
+	fn test_bad_config() {
+		let parse = |content: &str| ConfigFile::parse(content, Syntax::Toml);
+		assert!(parse("[beacons.production]\nmethod-policy = \"put\"").is_err());
+		assert!(parse("[beacons.production]\nunknown = true").is_err());
+		assert!(parse("[beacons.production]\nmodel-preset = \"fungus\"").is_err());
+		assert!(parse("[beacons.production]\nrequest-retries = -1").is_err());
+		assert!(parse("[beacons.production]\nnetwork = []").is_err());
+		assert!(parse("[beacons.production]\nproxy = \"socks4://proxy:1080\"").is_err());
+		assert!(parse("[beacons.production.basic-auth]\npassword = \"s3cr3t\"").is_err());
+		assert!(parse("[[beacons.production.maintenance]]\nschedule = \"0 2 *\"\nduration-minutes = 5").is_err());
+		let config = parse("[beacons.production.headers]\n\"Bad Header\" = \"x\"").unwrap();
+		assert!(config.profile("production").unwrap().header_map().is_err());
+	}
+
+	#[test]
+	fn test_api_key() {
+		let content = |style: &str| {
+			format!(
+				"[beacons.legacy.api-key]\nstyle = \"{}\"\nname = \"apikey\"\nkey = \"s3cr3t\"",
+				style
+			)
+		};
+		let profile = |style: &str| {
+			ConfigFile::parse(&content(style), Syntax::Toml)
+				.unwrap()
+				.profile("legacy")
+				.unwrap()
+		};
+		let query = profile("query");
+		assert!(query.header_map().unwrap().is_empty());
+		assert_eq!(
+			query.api_key.as_ref().unwrap().query_parameter(),
+			Some(("apikey", "s3cr3t"))
+		);
+		assert_eq!(profile("cookie").header_map().unwrap()["cookie"], "apikey=s3cr3t");
+		assert_eq!(profile("header").header_map().unwrap()["apikey"], "s3cr3t");
+		assert!(profile("header").api_key.unwrap().query_parameter().is_none());
+		assert!(ConfigFile::parse(&content("body"), Syntax::Toml).is_err());
+	}
+
+	#[test]
+	fn test_schema_problems() {
+		let content = "[beacons.production]\nurl = \"not a url\"\nmethod-policy = \"put\"\nunknown = \
+		               true\n\n[[beacons.production.maintenance]]\nschedule = \"0 2 * * 0\"";
+		let problems = config::problems(content, Syntax::Toml).unwrap();
+		assert_eq!(problems.len(), 4, "{:?}", problems);
+		let reported = |key: &str, text: &str, line: usize| {
+			problems.iter().any(|problem| {
+				problem.starts_with(&format!("{}: ", key))
+					&& problem.contains(text)
+					&& problem.ends_with(&format!("(line {}, column 1)", line))
+			})
+		};
+		assert!(
+			reported("beacons.production.method-policy", "\"put\"", 3),
+			"{:?}",
+			problems
+		);
+		assert!(reported("beacons.production.url", "uri", 2), "{:?}", problems);
+		assert!(
+			reported("beacons.production", "'unknown' was unexpected", 4),
+			"{:?}",
+			problems
+		);
+		assert!(
+			reported("beacons.production.maintenance.0", "duration-minutes", 6),
+			"{:?}",
+			problems
+		);
+		let e = ConfigFile::parse(content, Syntax::Toml).unwrap_err().to_string();
+		assert_eq!(e.lines().count(), 4, "{}", e);
+
+		assert!(config::problems(CONFIG, Syntax::Toml).unwrap().is_empty());
+		// Syntax errors come with their line
+		let e = config::problems("[beacons.production\nurl = 1", Syntax::Toml)
+			.unwrap_err()
+			.to_string();
+		assert!(e.contains("line 1"), "{}", e);
+	}
+
+	#[test]
+	fn test_yaml() {
+		let content = "beacons:\n  production:\n    url: https://beacon.example.org/api\n    method-policy: post\n    \
+		               headers:\n      X-Api-Key: secret\n    maintenance:\n      - schedule: \"0 2 * * 0\"\n        \
+		               duration-minutes: 120\n";
+		let directory = tempfile::tempdir().unwrap();
+		let path = directory.path().join("beacon-verifier.yaml");
+		std::fs::write(&path, content).unwrap();
+		let production = ConfigFile::load(&path).unwrap().profile("production").unwrap();
+		assert_eq!(production.method_policy, Some(MethodPolicy::Post));
+		assert_eq!(production.header_map().unwrap()["x-api-key"], "secret");
+		assert_eq!(production.maintenance.len(), 1);
+
+		let problems = config::problems(&content.replace("post", "put"), Syntax::Yaml).unwrap();
+		assert_eq!(problems.len(), 1, "{:?}", problems);
+		assert!(
+			problems[0].starts_with("beacons.production.method-policy: \"put\""),
+			"{:?}",
+			problems
+		);
+		assert!(problems[0].ends_with("(line 4, column 5)"), "{:?}", problems);
+		let e = ConfigFile::parse("beacons:\n  production: [", Syntax::Yaml)
+			.unwrap_err()
+			.to_string();
+		assert!(e.contains("line 3"), "{}", e);
+	}
+
+	#[test]
+	fn test_profile_headers() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let info = beacon.response("/info");
+		// Mocks of the same priority match in the order they are mounted
+		beacon.mount_mock(
+			Mock::given(method("GET"))
+				.and(path("/info"))
+				.and(header("authorization", "Bearer secret"))
+				.respond_with(ResponseTemplate::new(200).set_body_json(info))
+				.with_priority(1),
+		);
+		beacon.mount("/info", ResponseTemplate::new(401));
+		let config = ConfigFile::parse(CONFIG, Syntax::Toml).unwrap();
+		let headers = config.profile("production").unwrap().header_map().unwrap();
+		let client = Client::new().headers(headers);
+		assert!(client.query("Info", &beacon.url().join("info").unwrap()).is_ok());
+		assert!(Client::new()
+			.query("Info", &beacon.url().join("info").unwrap())
+			.is_err());
+	}
+
+	#[test]
+	fn test_command_line_headers() {
+		let header = "X-Api-Key: abc:def".parse::<RequestHeader>().unwrap();
+		assert_eq!((header.name.as_str(), header.value.as_str()), ("X-Api-Key", "abc:def"));
+		assert!("X-Api-Key".parse::<RequestHeader>().is_err());
+		assert!(": value".parse::<RequestHeader>().is_err());
+
+		let config = ConfigFile::parse(CONFIG, Syntax::Toml).unwrap();
+		let headers = ["authorization: Bearer other", "X-Tenant: lab"].map(|header| header.parse().unwrap());
+		let profile = config.profile("production").unwrap().with_headers(&headers);
+		assert_eq!(profile.headers.len(), 2);
+		let header_map = profile.header_map().unwrap();
+		assert_eq!(header_map["authorization"], "Bearer other");
+		assert!(header_map["authorization"].is_sensitive());
+		assert!(!header_map["x-tenant"].is_sensitive());
+	}
+}
@@ -0,0 +1,315 @@
+//! Consistency of the ids of the records across the endpoints
+//!
+//! The same record must have the same id in the endpoint with all the entries,
+//! in its single entry endpoint and in the references of the other entities
+//! (e.g. the `individualId` of the biosamples). Beacons exposing internal
+//! surrogate keys in some routes and public ids in others break those links.
+//! Since the endpoint with all the entries may only return its first page, the
+//! references are compared by the format of the ids rather than by value.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use url::Url;
+
+use crate::endpoint::BeaconEndpoint;
+use crate::error::VerifierError;
+use crate::model::Model;
+use crate::output::{Category, EndpointReport};
+use crate::{formats, utils, Json};
+
+/// Name of the checks, after the name of the entity
+pub const CHECK_NAME: &str = "id consistency";
+
+/// Kind of an id, telling a public id and a surrogate key apart
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum IdFormat {
+	/// Only digits (e.g. `42`)
+	Integer,
+	/// UUID (e.g. `123e4567-e89b-12d3-a456-426614174000`)
+	Uuid,
+	/// Compact URI, with its prefix (e.g. `EGA:EGAN00001`)
+	Curie(String),
+	/// Anything else, with the runs of letters and digits collapsed (e.g. `a-9` for `IND-0001`)
+	Other(String),
+}
+
+impl IdFormat {
+	pub fn of(id: &str) -> Self {
+		let is_hex = |s: &str, len: usize| s.len() == len && s.chars().all(|c| c.is_ascii_hexdigit());
+		let groups = id.split('-').collect::<Vec<_>>();
+		if !id.is_empty() && id.chars().all(|c| c.is_ascii_digit()) {
+			Self::Integer
+		}
+		else if groups.len() == 5
+			&& groups
+				.iter()
+				.zip([8, 4, 4, 4, 12])
+				.all(|(group, len)| is_hex(group, len))
+		{
+			Self::Uuid
+		}
+		else if let Some((prefix, _)) = id.split_once(':').filter(|_| formats::is_curie(id)) {
+			Self::Curie(prefix.to_string())
+		}
+		else {
+			let mut shape = String::new();
+			for c in id.chars() {
+				let class = if c.is_ascii_digit() {
+					'9'
+				}
+				else if c.is_alphabetic() {
+					'a'
+				}
+				else {
+					c
+				};
+				if !(shape.ends_with(class) && (class == '9' || class == 'a')) {
+					shape.push(class);
+				}
+			}
+			Self::Other(shape)
+		}
+	}
+}
+
+impl std::fmt::Display for IdFormat {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Integer => write!(f, "integer"),
+			Self::Uuid => write!(f, "UUID"),
+			Self::Curie(prefix) => write!(f, "'{}:' CURIE", prefix),
+			Self::Other(shape) => write!(f, "'{}'", shape),
+		}
+	}
+}
+
+struct EntityIds {
+	name: String,
+	id_field: String,
+	url: Url,
+	/// Ids returned by the endpoint with all the entries
+	listed: BTreeSet<String>,
+	/// Whether any endpoint returned records of the entity
+	observed: bool,
+}
+
+/// Collects the ids of the responses, to check them once every endpoint was queried
+pub struct IdConsistency {
+	/// Entities by entry type
+	entities: BTreeMap<String, EntityIds>,
+	/// References to other entities: entry type of the record, property, referenced entry type and id
+	references: BTreeSet<(String, String, String, String)>,
+	/// Problems by entry type
+	problems: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl IdConsistency {
+	pub fn new(model: &Model, root_url: &Url) -> Self {
+		Self {
+			entities: model
+				.entities
+				.iter()
+				.map(|entity| {
+					let ids = EntityIds {
+						name: entity.name.clone(),
+						id_field: entity.id_field.clone(),
+						url: utils::url_join(root_url, &entity.url),
+						listed: BTreeSet::new(),
+						observed: false,
+					};
+					(entity.entry_type.clone(), ids)
+				})
+				.collect(),
+			references: BTreeSet::new(),
+			problems: BTreeMap::new(),
+		}
+	}
+
+	/// Records the ids of a response of `endpoint`, if it returned records
+	pub fn observe(&mut self, endpoint: &BeaconEndpoint, response_json: &Json) {
+		if response_json
+			.pointer("/meta/returnedGranularity")
+			.and_then(Json::as_str)
+			!= Some("record")
+		{
+			return;
+		}
+		let records = utils::records(response_json).unwrap_or_default();
+		let parent = self
+			.entities
+			.iter()
+			.find(|(_, entity)| entity.name == endpoint.entity_name)
+			.map(|(entry_type, _)| entry_type.clone());
+		let entity = match self.entities.get_mut(&endpoint.entry_type) {
+			Some(entity) if !records.is_empty() => entity,
+			_ => return,
+		};
+		entity.observed = true;
+		let ids = records
+			.iter()
+			.filter_map(|record| utils::record_id(record, &entity.id_field))
+			.collect::<BTreeSet<_>>();
+
+		match (&endpoint.record_id, &parent) {
+			(None, _) => entity.listed.extend(ids),
+			// Single entry endpoint
+			(Some(record_id), Some(parent)) if *parent == endpoint.entry_type => {
+				if !ids.is_empty() && !ids.contains(record_id) {
+					self.problems
+						.entry(endpoint.entry_type.clone())
+						.or_default()
+						.insert(format!(
+							"{} returned the ids {} instead of '{}'",
+							endpoint.name,
+							ids.iter().map(|id| format!("'{}'", id)).collect::<Vec<_>>().join(", "),
+							record_id
+						));
+				}
+			},
+			// Related endpoint, whose records may reference the record they are related with
+			(Some(record_id), Some(parent)) => {
+				let property = format!("{}Id", parent);
+				let mismatches = records
+					.iter()
+					.filter_map(|record| record.get(&property).and_then(Json::as_str))
+					.filter(|id| id != record_id)
+					.collect::<BTreeSet<_>>();
+				for id in mismatches {
+					self.problems
+						.entry(endpoint.entry_type.clone())
+						.or_default()
+						.insert(format!(
+							"{} returned records with the {} '{}' instead of '{}'",
+							endpoint.name, property, id, record_id
+						));
+				}
+			},
+			(Some(_), None) => (),
+		}
+
+		for record in &records {
+			for entry_type in self
+				.entities
+				.keys()
+				.filter(|entry_type| **entry_type != endpoint.entry_type)
+			{
+				let property = format!("{}Id", entry_type);
+				if let Some(id) = record.get(&property).and_then(Json::as_str) {
+					self.references.insert((
+						endpoint.entry_type.clone(),
+						property,
+						entry_type.clone(),
+						id.to_string(),
+					));
+				}
+			}
+		}
+	}
+
+	/// Every id seen so far, of the records and of their references
+	pub fn ids(&self) -> BTreeSet<String> {
+		self.entities
+			.values()
+			.flat_map(|entity| entity.listed.iter().cloned())
+			.chain(self.references.iter().map(|(_, _, _, id)| id.clone()))
+			.collect()
+	}
+
+	/// One report per entity whose records were returned
+	pub fn reports(mut self) -> Vec<EndpointReport> {
+		for (entry_type, property, referenced, id) in &self.references {
+			let listed = match self.entities.get(referenced) {
+				Some(entity) if !entity.listed.is_empty() => entity,
+				_ => continue,
+			};
+			let formats = listed.listed.iter().map(|id| IdFormat::of(id)).collect::<BTreeSet<_>>();
+			let format = IdFormat::of(id);
+			if !formats.contains(&format) {
+				self.problems.entry(entry_type.clone()).or_default().insert(format!(
+					"'{}' has the {} id '{}', while the {} endpoint lists {} ids",
+					property,
+					format,
+					id,
+					listed.name,
+					formats.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ")
+				));
+			}
+		}
+
+		self.entities
+			.iter()
+			.filter(|(_, entity)| entity.observed)
+			.map(|(entry_type, entity)| {
+				let name = format!("{} {}", entity.name, CHECK_NAME);
+				let report = EndpointReport::new(&entity.name, &name, entity.url.clone())
+					.category(Category::Model)
+					.check("model.id-consistency");
+				match self.problems.get(entry_type) {
+					Some(problems) => report.error(VerifierError::InconsistentIds(
+						problems.iter().cloned().collect::<Vec<_>>().join("\n"),
+					)),
+					None => report.ok(None),
+				}
+			})
+			.collect()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use serde_json::json;
+	use wiremock::ResponseTemplate;
+
+	use crate::consistency::{IdFormat, CHECK_NAME};
+	use crate::output::{BeaconOutput, Category, EndpointOutput};
+	use crate::testing::{Behavior, MockBeacon};
+
+	fn consistency<'a>(output: &'a BeaconOutput, entity: &str) -> &'a EndpointOutput {
+		output.entities[entity]
+			.iter()
+			.find(|report| report.name == format!("{} {}", entity, CHECK_NAME))
+			.unwrap()
+	}
+
+	#[test]
+	fn test_id_format() {
+		assert_eq!(IdFormat::of("42"), IdFormat::Integer);
+		assert_eq!(IdFormat::of("123e4567-e89b-12d3-a456-426614174000"), IdFormat::Uuid);
+		assert_eq!(IdFormat::of("EGA:EGAN00001"), IdFormat::Curie("EGA".into()));
+		assert_eq!(IdFormat::of("IND-0001"), IdFormat::Other("a-9".into()));
+		assert_eq!(IdFormat::of("ind1"), IdFormat::of("ind22"));
+	}
+
+	#[test]
+	fn test_consistent_ids() {
+		let output = MockBeacon::start(Behavior::Conformant).verify();
+		for entity in ["Individual", "Biosample", "Dataset"] {
+			let report = consistency(&output, entity);
+			assert_eq!(report.category, Category::Model);
+			assert_eq!(report.valid, Some(true), "{}: {:?}", entity, report.error);
+		}
+	}
+
+	#[test]
+	fn test_inconsistent_ids() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let mut single = beacon.response("/individuals/ind1");
+		single["response"]["resultSets"][0]["results"][0]["id"] = json!("1001");
+		beacon.mount("/individuals/ind1", ResponseTemplate::new(200).set_body_json(single));
+		let mut biosamples = beacon.response("/biosamples");
+		biosamples["response"]["resultSets"][0]["results"][1]["individualId"] = json!("1002");
+		beacon.mount("/biosamples", ResponseTemplate::new(200).set_body_json(biosamples));
+
+		let output = beacon.verify();
+		let error = consistency(&output, "Individual").error.clone().unwrap();
+		assert!(error.contains("returned the ids '1001' instead of 'ind1'"), "{}", error);
+		let error = consistency(&output, "Biosample").error.clone().unwrap();
+		assert!(
+			error.contains("'individualId' has the integer id '1002', while the Individual endpoint lists 'a9' ids"),
+			"{}",
+			error
+		);
+		assert_eq!(consistency(&output, "Dataset").valid, Some(true));
+	}
+}
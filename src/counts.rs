@@ -0,0 +1,149 @@
+//! Consistency of the counts of the result set responses
+//!
+//! The framework schemas type `responseSummary.exists`,
+//! `responseSummary.numTotalResults` and the `exists`, `resultsCount` and
+//! `results` of each result set, but cannot relate them: a response that
+//! says nothing exists while returning records is valid against them. Once the
+//! response follows the framework, its counts must agree: `exists` is whether
+//! `numTotalResults` is above zero, a page holds no more records than the
+//! total, and a result set holds no more records than its `resultsCount` nor
+//! any record when it does not exist.
+
+use crate::error::VerifierError;
+use crate::interface::Granularity;
+use crate::Json;
+
+/// Id of the check in the catalog
+pub const CHECK_ID: &str = "framework.counts";
+
+/// Disagreements between the counts of a result set response at the granularity `granularity`
+pub fn problems(response_json: &Json, granularity: Granularity) -> Vec<String> {
+	let mut problems = Vec::new();
+	let exists = response_json.pointer("/responseSummary/exists").and_then(Json::as_bool);
+	let total = response_json
+		.pointer("/responseSummary/numTotalResults")
+		.and_then(Json::as_u64);
+	if let (Some(exists), Some(total)) = (exists, total) {
+		if exists != (total > 0) {
+			problems.push(format!(
+				"responseSummary.exists is {} with numTotalResults {}",
+				exists, total
+			));
+		}
+	}
+
+	let result_sets = response_json
+		.pointer("/response/resultSets")
+		.and_then(Json::as_array)
+		.map(Vec::as_slice)
+		.unwrap_or_default();
+	let mut records = 0;
+	for (i, result_set) in result_sets.iter().enumerate() {
+		let name = format!("resultSets[{}]", i);
+		let set_exists = result_set.get("exists").and_then(Json::as_bool);
+		let count = result_set.get("resultsCount").and_then(Json::as_u64);
+		let results = result_set.get("results").and_then(Json::as_array).map_or(0, Vec::len);
+		records += results;
+		if set_exists == Some(false) && results > 0 {
+			problems.push(format!("{} has exists false with {} records", name, results));
+		}
+		if let Some(count) = count.filter(|count| results as u64 > *count) {
+			problems.push(format!("{} has {} records with resultsCount {}", name, results, count));
+		}
+		// The result sets of the other granularities are checked with the model
+		if granularity == Granularity::Record {
+			if let (Some(set_exists), Some(count)) = (set_exists, count) {
+				if set_exists != (count > 0) {
+					problems.push(format!(
+						"{} has exists {} with resultsCount {}",
+						name, set_exists, count
+					));
+				}
+			}
+		}
+		if exists == Some(false) && set_exists == Some(true) {
+			problems.push(format!("{} exists while responseSummary.exists is false", name));
+		}
+	}
+	if exists == Some(false) && records > 0 {
+		problems.push(format!("responseSummary.exists is false with {} records", records));
+	}
+	if let Some(total) = total.filter(|total| records as u64 > *total) {
+		problems.push(format!("{} records with numTotalResults {}", records, total));
+	}
+	let any_set_exists = result_sets
+		.iter()
+		.any(|result_set| result_set.get("exists").and_then(Json::as_bool) != Some(false));
+	if exists == Some(true) && !result_sets.is_empty() && !any_set_exists {
+		problems.push("responseSummary.exists is true while no result set exists".into());
+	}
+	problems
+}
+
+/// Checks the counts of a result set response, which must agree
+pub fn check(response_json: &Json, granularity: Granularity) -> Result<(), VerifierError> {
+	let problems = problems(response_json, granularity);
+	if problems.is_empty() {
+		Ok(())
+	}
+	else {
+		Err(VerifierError::InconsistentCounts(problems.join(", ")))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use serde_json::json;
+	use wiremock::ResponseTemplate;
+
+	use crate::counts::{self, CHECK_ID};
+	use crate::interface::Granularity;
+	use crate::output::Category;
+	use crate::testing::{Behavior, MockBeacon};
+
+	#[test]
+	fn test_count_problems() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let mut response = beacon.response("/individuals");
+		assert!(counts::problems(&response, Granularity::Record).is_empty());
+
+		response["responseSummary"] = json!({ "exists": false, "numTotalResults": 1 });
+		response["response"]["resultSets"][0]["resultsCount"] = json!(1);
+		assert_eq!(
+			counts::problems(&response, Granularity::Record),
+			vec![
+				"responseSummary.exists is false with numTotalResults 1",
+				"resultSets[0] has 2 records with resultsCount 1",
+				"resultSets[0] exists while responseSummary.exists is false",
+				"responseSummary.exists is false with 2 records",
+				"2 records with numTotalResults 1",
+			]
+		);
+		let count = json!({
+			"responseSummary": { "exists": true, "numTotalResults": 3 },
+			"response": { "resultSets": [{ "exists": false, "resultsCount": 3 }] },
+		});
+		assert_eq!(
+			counts::problems(&count, Granularity::Count),
+			vec!["responseSummary.exists is true while no result set exists"]
+		);
+	}
+
+	#[test]
+	fn test_inconsistent_counts() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let mut response = beacon.response("/individuals");
+		response["responseSummary"] = json!({ "exists": false, "numTotalResults": 0 });
+		beacon.mount("/individuals", ResponseTemplate::new(200).set_body_json(response));
+		let output = beacon.verify();
+		let report = output.entities["Individual"]
+			.iter()
+			.find(|report| report.name == "Individual all entries" && report.category == Category::Framework)
+			.unwrap();
+		assert_eq!(report.valid, Some(false));
+		assert_eq!(report.check.as_deref(), Some(CHECK_ID));
+		let e = report.error.as_deref().unwrap();
+		assert!(e.contains("responseSummary.exists is false with 2 records"), "{}", e);
+	}
+}
@@ -0,0 +1,169 @@
+//! Data use conditions of the datasets
+//!
+//! The datasets declare the conditions their data can be used under in
+//! `dataUseConditions`, with the terms of the Data Use Ontology (DUO): one
+//! permission (e.g. `DUO:0000042`, general research use) and its modifiers
+//! (e.g. `DUO:0000019`, publication required). The verifier summarizes them per
+//! dataset, with the consent codes of the terms (e.g. `GRU`, `PUB`), and warns
+//! about the datasets that declare none.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::Json;
+
+/// Consent codes of the DUO terms
+const CONSENT_CODES: [(&str, &str); 23] = [
+	("DUO:0000004", "NRES"),
+	("DUO:0000006", "HMB"),
+	("DUO:0000007", "DS"),
+	("DUO:0000011", "POA"),
+	("DUO:0000012", "RS"),
+	("DUO:0000015", "NMDS"),
+	("DUO:0000016", "GSO"),
+	("DUO:0000018", "NPUNCU"),
+	("DUO:0000019", "PUB"),
+	("DUO:0000020", "COL"),
+	("DUO:0000021", "IRB"),
+	("DUO:0000022", "GS"),
+	("DUO:0000024", "MOR"),
+	("DUO:0000025", "TS"),
+	("DUO:0000026", "US"),
+	("DUO:0000027", "PS"),
+	("DUO:0000028", "IS"),
+	("DUO:0000029", "RTN"),
+	("DUO:0000042", "GRU"),
+	("DUO:0000043", "CC"),
+	("DUO:0000044", "NPOA"),
+	("DUO:0000045", "NPU"),
+	("DUO:0000046", "NCU"),
+];
+
+/// Data use conditions declared by a dataset
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DataUse {
+	/// DUO terms of the permissions and of their modifiers
+	pub conditions: BTreeSet<String>,
+	/// Consent codes of the known terms (e.g. `GRU`)
+	pub consent_codes: BTreeSet<String>,
+}
+
+impl DataUse {
+	/// Conditions of a dataset, from its `dataUseConditions`
+	pub fn from_dataset(dataset: &Json) -> Self {
+		let mut data_use = Self::default();
+		let terms = dataset
+			.pointer("/dataUseConditions/duoDataUse")
+			.and_then(Json::as_array)
+			.map_or(&[][..], Vec::as_slice);
+		for term in terms {
+			let modifiers = term
+				.get("modifiers")
+				.and_then(Json::as_array)
+				.map_or(&[][..], Vec::as_slice);
+			for id in std::iter::once(term)
+				.chain(modifiers)
+				.filter_map(|term| term.get("id").and_then(Json::as_str))
+			{
+				data_use.conditions.insert(id.to_string());
+				if let Some((_, code)) = CONSENT_CODES.iter().find(|(duo_id, _)| *duo_id == id) {
+					data_use.consent_codes.insert(code.to_string());
+				}
+			}
+		}
+		data_use
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.conditions.is_empty()
+	}
+}
+
+/// Data use conditions of the datasets of a response with collections, by dataset id
+pub fn from_response(response_json: &Json) -> BTreeMap<String, DataUse> {
+	response_json
+		.pointer("/response/collections")
+		.and_then(Json::as_array)
+		.map_or(&[][..], Vec::as_slice)
+		.iter()
+		.filter_map(|dataset| {
+			let id = dataset.get("id").and_then(Json::as_str)?;
+			Some((id.to_string(), DataUse::from_dataset(dataset)))
+		})
+		.collect()
+}
+
+/// Warning about the datasets without any data use condition, if there are, listing their ids if `with_ids`
+pub fn warning(data_use: &BTreeMap<String, DataUse>, with_ids: bool) -> Option<String> {
+	let undeclared = data_use
+		.iter()
+		.filter(|(_, data_use)| data_use.is_empty())
+		.map(|(id, _)| format!("'{}'", id))
+		.collect::<Vec<_>>();
+	match undeclared.len() {
+		0 => None,
+		_ if with_ids => Some(format!(
+			"Datasets without data use conditions: {}",
+			undeclared.join(", ")
+		)),
+		count => Some(format!("{} datasets without data use conditions", count)),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use serde_json::json;
+
+	use crate::datause::{self, DataUse};
+	use crate::testing::{Behavior, MockBeacon};
+
+	#[test]
+	fn test_data_use() {
+		let response = json!({
+			"response": {
+				"collections": [
+					{
+						"id": "ds1",
+						"dataUseConditions": {
+							"duoDataUse": [{
+								"id": "DUO:0000007",
+								"label": "disease specific research",
+								"version": "17-07-2016",
+								"modifiers": [{ "id": "DUO:0000019" }, { "id": "DUO:0000099" }],
+							}],
+						},
+					},
+					{ "id": "ds2" },
+				],
+			},
+		});
+		let data_use = datause::from_response(&response);
+		let ds1 = &data_use["ds1"];
+		assert_eq!(ds1.conditions.len(), 3);
+		assert_eq!(ds1.consent_codes.iter().collect::<Vec<_>>(), vec!["DS", "PUB"]);
+		assert_eq!(data_use["ds2"], DataUse::default());
+		assert_eq!(
+			datause::warning(&data_use, true).unwrap(),
+			"Datasets without data use conditions: 'ds2'"
+		);
+		assert_eq!(
+			datause::warning(&data_use, false).unwrap(),
+			"1 datasets without data use conditions"
+		);
+	}
+
+	#[test]
+	fn test_data_use_summary() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let output = beacon.verify();
+		// The mock dataset declares none
+		assert!(output.data_use["ds1"].is_empty());
+		let reports = &output.entities["Dataset"];
+		assert!(reports
+			.iter()
+			.any(|report| report.warnings.iter().any(|warning| warning.contains("'ds1'"))));
+		assert!(beacon.beacon().public_report().validate().data_use.is_empty());
+	}
+}
@@ -0,0 +1,160 @@
+//! Deprecated properties of the records
+//!
+//! The schemas of the model mark the properties on their way out with the
+//! `deprecated` annotation of JSON Schema. The records that still use them are
+//! valid, so they are not reported as failures nor as warnings, but in the
+//! notes of the check of the records, at the info severity. The properties are
+//! searched in the schema of the entity and in the schemas it references, the
+//! items of an array standing for the array.
+
+use std::collections::BTreeSet;
+
+use url::Url;
+
+use crate::resolver::SchemaStore;
+use crate::Json;
+
+/// Nesting of the schemas searched at most, the schemas referencing each other without end
+const MAX_DEPTH: usize = 32;
+
+/// Paths (e.g. `diseases.ageOfOnset`) of the deprecated properties of `schema`, which has a base uri
+pub fn properties(schema: &Json, store: &SchemaStore) -> Vec<String> {
+	let base = schema
+		.get("$id")
+		.or_else(|| schema.get("id"))
+		.and_then(Json::as_str)
+		.and_then(|id| Url::parse(id).ok());
+	let mut found = BTreeSet::new();
+	if let Some(base) = base {
+		walk(schema, &base, &[], 0, store, &mut found);
+	}
+	found.into_iter().collect()
+}
+
+fn walk(schema: &Json, base: &Url, path: &[&str], depth: usize, store: &SchemaStore, found: &mut BTreeSet<String>) {
+	if depth > MAX_DEPTH {
+		return;
+	}
+	let base = match schema.get("$id").and_then(Json::as_str) {
+		Some(id) => base.join(id).unwrap_or_else(|_| base.clone()),
+		None => base.clone(),
+	};
+	if let Some(reference) = schema.get("$ref").and_then(Json::as_str) {
+		if let Ok(url) = base.join(reference) {
+			let mut document_url = url.clone();
+			document_url.set_fragment(None);
+			if let Ok(document) = store.resolve_url(&document_url) {
+				let target = match url.fragment() {
+					Some(pointer) if !pointer.is_empty() => document.pointer(pointer),
+					_ => Some(document.as_ref()),
+				};
+				if let Some(target) = target {
+					walk(target, &document_url, path, depth + 1, store, found);
+				}
+			}
+		}
+	}
+	if let Some(properties) = schema.get("properties").and_then(Json::as_object) {
+		for (name, property) in properties {
+			let property_path = [path, &[name.as_str()]].concat();
+			if property.get("deprecated").and_then(Json::as_bool) == Some(true) {
+				found.insert(property_path.join("."));
+			}
+			walk(property, &base, &property_path, depth + 1, store, found);
+		}
+	}
+	let items = match schema.get("items") {
+		Some(Json::Array(items)) => items.iter().collect(),
+		Some(items) => vec![items],
+		None => Vec::new(),
+	};
+	let combined = ["allOf", "anyOf", "oneOf"]
+		.iter()
+		.filter_map(|keyword| schema.get(*keyword).and_then(Json::as_array))
+		.flatten();
+	for subschema in items.into_iter().chain(combined) {
+		walk(subschema, &base, path, depth + 1, store, found);
+	}
+}
+
+/// Whether `json` has a value at `path`, through the items of the arrays
+fn has(json: &Json, path: &[&str]) -> bool {
+	match (json, path.split_first()) {
+		(_, None) => true,
+		(Json::Array(items), Some(_)) => items.iter().any(|item| has(item, path)),
+		(Json::Object(object), Some((name, rest))) => object.get(*name).map_or(false, |value| has(value, rest)),
+		_ => false,
+	}
+}
+
+/// Notes about the deprecated properties (paths in `properties`) used by the records
+pub fn notes(records: &[&Json], properties: &[String]) -> Vec<String> {
+	properties
+		.iter()
+		.filter_map(|property| {
+			let path = property.split('.').collect::<Vec<_>>();
+			match records.iter().filter(|record| has(record, &path)).count() {
+				0 => None,
+				count => Some(format!(
+					"Deprecated: {} of the records use the property '{}'",
+					count, property
+				)),
+			}
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+
+	use std::collections::BTreeMap;
+	use std::path::Path;
+
+	use serde_json::json;
+	use url::Url;
+
+	use crate::cache::SchemaCache;
+	use crate::deprecated;
+	use crate::resolver::SchemaStore;
+
+	#[test]
+	fn test_deprecated_properties() {
+		let root = Path::new("/model");
+		let mut files = BTreeMap::new();
+		files.insert(
+			root.join("individuals/defaultSchema.json"),
+			json!({
+				"properties": {
+					"id": { "type": "string" },
+					"ethnicity": { "type": "string", "deprecated": true },
+					"diseases": { "type": "array", "items": { "$ref": "../common/disease.json" } },
+					"info": { "$ref": "#/definitions/Info" }
+				},
+				"definitions": {
+					"Info": { "properties": { "legacyId": { "deprecated": true } } }
+				}
+			}),
+		);
+		files.insert(
+			root.join("common/disease.json"),
+			json!({ "properties": { "ageOfOnset": { "deprecated": true }, "diseaseCode": {} } }),
+		);
+		let location = Url::parse("file:///model").unwrap();
+		let store = SchemaStore::new(root, &mut files, &location, &SchemaCache::default().offline());
+		let properties = deprecated::properties(&files[&root.join("individuals/defaultSchema.json")], &store);
+		assert_eq!(properties, vec!["diseases.ageOfOnset", "ethnicity", "info.legacyId"]);
+
+		let records = [
+			json!({ "id": "ind1", "ethnicity": "unknown", "diseases": [{ "diseaseCode": "x" }] }),
+			json!({ "id": "ind2", "diseases": [{ "diseaseCode": "x" }, { "ageOfOnset": "P30Y" }] }),
+			json!({ "id": "ind3", "diseases": [], "ethnicity": "unknown" }),
+		];
+		assert_eq!(
+			deprecated::notes(&records.iter().collect::<Vec<_>>(), &properties),
+			vec![
+				"Deprecated: 1 of the records use the property 'diseases.ageOfOnset'",
+				"Deprecated: 2 of the records use the property 'ethnicity'",
+			]
+		);
+	}
+}
@@ -0,0 +1,190 @@
+//! Comparison of two reports of the verifier
+//!
+//! The `diff` subcommand reads two JSON reports of the same beacon (e.g. the
+//! ones of the last release and of a release candidate) and sorts the checks
+//! that failed in either: the newly broken ones (failing in the new report,
+//! and succeeding or absent in the old one), the newly fixed ones (failing in
+//! the old report and succeeding in the new one) and the failures in both. A
+//! check is identified by its entity, name and layer, so the same check on a
+//! record with another id in the new report is the same check; when several
+//! reports share them, the check failed if any of them did. The checks that
+//! are no longer in the new report are left out.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::error::VerifierError;
+use crate::output::{BeaconOutput, Category, Outcome};
+
+/// Check that failed in at least one of the reports
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ChangedCheck {
+	pub entity: String,
+	pub name: String,
+	pub category: Category,
+	/// Id of the check in the catalog
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub check: Option<String>,
+	/// Outcome in the old report, none if the check was not there
+	pub before: Option<Outcome>,
+	/// Outcome in the new report
+	pub after: Outcome,
+	/// Error of the failure, in the new report if the check still fails
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ReportDiff {
+	pub newly_broken: Vec<ChangedCheck>,
+	pub newly_fixed: Vec<ChangedCheck>,
+	pub unchanged_failures: Vec<ChangedCheck>,
+}
+
+/// Outcome of a check, with its id and the error of its first failure
+type Check = (Outcome, Option<String>, Option<String>);
+
+/// Checks of `output` by entity, name and layer, failed if any of their reports failed
+fn checks(output: &BeaconOutput) -> BTreeMap<(String, String, Category), Check> {
+	let mut checks = BTreeMap::<_, Check>::new();
+	for (entity_name, reports) in &output.entities {
+		for report in reports {
+			let key = (entity_name.clone(), report.key(), report.category);
+			let outcome = report.outcome();
+			match checks.get_mut(&key) {
+				Some(check) if check.0.is_success() && !outcome.is_success() => {
+					*check = (outcome, report.check.clone(), report.error.clone());
+				},
+				Some(_) => (),
+				None => {
+					checks.insert(key, (outcome, report.check.clone(), report.error.clone()));
+				},
+			}
+		}
+	}
+	checks
+}
+
+/// Reads a JSON report of the verifier
+pub fn load(path: &Path) -> Result<BeaconOutput, VerifierError> {
+	BeaconOutput::from_json(&std::fs::read_to_string(path)?)
+}
+
+/// Checks that failed in `old` or in `new`, sorted by how they changed
+pub fn diff(old: &BeaconOutput, new: &BeaconOutput) -> ReportDiff {
+	let (old, new) = (checks(old), checks(new));
+	let mut diff = ReportDiff::default();
+	for ((entity, name, category), (outcome, check, error)) in &new {
+		let before = old.get(&(entity.clone(), name.clone(), *category));
+		if outcome.is_success() && before.map_or(true, |before| before.0.is_success()) {
+			continue;
+		}
+		let changed = ChangedCheck {
+			entity: entity.clone(),
+			name: name.clone(),
+			category: *category,
+			check: check.clone().or_else(|| before.and_then(|before| before.1.clone())),
+			before: before.map(|before| before.0),
+			after: *outcome,
+			error: if outcome.is_success() {
+				before.and_then(|before| before.2.clone())
+			}
+			else {
+				error.clone()
+			},
+		};
+		match (before.map(|before| before.0.is_success()), outcome.is_success()) {
+			(Some(false), true) => diff.newly_fixed.push(changed),
+			(Some(false), false) => diff.unchanged_failures.push(changed),
+			_ => diff.newly_broken.push(changed),
+		}
+	}
+	diff
+}
+
+impl ReportDiff {
+	/// Logs the changed checks, the newly broken ones as errors
+	pub fn summary(&self) {
+		let line = |check: &ChangedCheck| {
+			let error = check
+				.error
+				.as_deref()
+				.map(|e| format!(": {}", e.trim_end()))
+				.unwrap_or_default();
+			format!("{} / {} ({}){}", check.entity, check.name, check.category, error)
+		};
+		for check in &self.newly_broken {
+			log::error!("Newly broken: {}", line(check));
+		}
+		for check in &self.newly_fixed {
+			log::info!("Newly fixed: {}", line(check));
+		}
+		for check in &self.unchanged_failures {
+			log::warn!("Still failing: {}", line(check));
+		}
+		log::info!(
+			"{} newly broken, {} newly fixed, {} unchanged failures",
+			self.newly_broken.len(),
+			self.newly_fixed.len(),
+			self.unchanged_failures.len()
+		);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use serde_json::json;
+	use wiremock::ResponseTemplate;
+
+	use crate::diff;
+	use crate::output::{Category, Outcome};
+	use crate::testing::{Behavior, MockBeacon};
+
+	#[test]
+	fn test_diff() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let before = beacon.verify();
+		let mut response = beacon.response("/individuals");
+		response["response"]["resultSets"][0]["results"][0]["sex"] = json!("female");
+		beacon.mount("/individuals", ResponseTemplate::new(200).set_body_json(response));
+		let broken = beacon.verify();
+
+		let report = diff::diff(&before, &broken);
+		assert!(report.newly_fixed.is_empty());
+		assert!(report.unchanged_failures.is_empty());
+		let individuals = report
+			.newly_broken
+			.iter()
+			.find(|check| check.entity == "Individual" && check.category == Category::Model)
+			.unwrap();
+		assert_eq!(individuals.before, Some(Outcome::Passed));
+		assert_eq!(individuals.after, Outcome::Failed);
+		assert_eq!(individuals.check.as_deref(), Some("model.entity"));
+		assert!(individuals.error.is_some());
+
+		let report = diff::diff(&broken, &before);
+		assert!(report.newly_broken.is_empty());
+		assert_eq!(
+			report.newly_fixed.len(),
+			diff::diff(&before, &broken).newly_broken.len()
+		);
+		assert!(report.newly_fixed.iter().all(|check| check.after == Outcome::Passed));
+
+		let report = diff::diff(&broken, &broken);
+		assert!(report.newly_broken.is_empty() && report.newly_fixed.is_empty());
+		assert!(!report.unchanged_failures.is_empty());
+		assert_eq!(diff::diff(&before, &before), diff::ReportDiff::default());
+
+		// The saved reports
+		let dir = tempfile::tempdir().unwrap();
+		let path = dir.path().join("report.json");
+		std::fs::write(&path, broken.to_json().unwrap()).unwrap();
+		assert_eq!(
+			diff::diff(&before, &diff::load(&path).unwrap()),
+			diff::diff(&before, &broken)
+		);
+	}
+}
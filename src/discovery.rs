@@ -0,0 +1,347 @@
+//! Endpoints advertised by the beacon
+//!
+//! The entities are queried at the urls of the beacon map of the model. With
+//! `--discover`, the `/map` of the beacon drives the verification instead:
+//! every entity is queried at the urls the beacon advertises for it, and the
+//! entry types of its `/configuration` are checked against them. The `/map`
+//! and the `/configuration` must follow the schemas of the framework to be
+//! used. The entities of the model the beacon does not advertise are reported
+//! as skipped, so an advertised but broken endpoint fails while a missing one
+//! is only `not advertised`. The verifier only queries the beacon under its
+//! root url, so an entity advertised elsewhere (e.g. on another host) is
+//! reported as not verified, at the url the beacon advertises.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use url::Url;
+
+use crate::client::Client;
+use crate::endpoint::BeaconEndpoint;
+use crate::error::VerifierError;
+use crate::formats::Formats;
+use crate::framework::Framework;
+use crate::interface::Endpoint;
+use crate::model::{Entity, Model};
+use crate::{shape, utils, Json};
+
+/// Reason of the skipped checks of the entities the beacon does not advertise
+pub const NOT_ADVERTISED: &str = "not advertised";
+
+/// Endpoint sets of the `/map` of the beacon, by entry type
+#[derive(Debug, Clone)]
+pub struct Discovery {
+	endpoint_sets: BTreeMap<String, Endpoint>,
+	/// Response of `/map`, the evidence of the entities not advertised
+	map_json: Json,
+}
+
+impl Discovery {
+	/// Fetches the `/map` and the `/configuration` of the beacon, validated against the schemas of `framework`
+	pub fn fetch(
+		client: &Client,
+		root_url: &Url,
+		framework: &Framework,
+		formats: &Formats,
+	) -> Result<Self, VerifierError> {
+		let map_json = client.query("BeaconMap", &Self::url(root_url, "map"))?.json;
+		validate(&map_json, &framework.beacon_map_json, framework, formats)?;
+		let endpoint_sets = Self::endpoint_sets(&map_json)?;
+		let configuration = client
+			.query("Configuration", &Self::url(root_url, "configuration"))
+			.and_then(|response| {
+				validate(&response.json, &framework.configuration_json, framework, formats).map(|()| response)
+			});
+		match configuration {
+			Ok(response) => {
+				let entry_types = response
+					.json
+					.pointer("/response/entryTypes")
+					.and_then(Json::as_object)
+					.map(|entry_types| entry_types.keys().cloned().collect::<BTreeSet<_>>())
+					.unwrap_or_default();
+				for entry_type in endpoint_sets
+					.keys()
+					.filter(|entry_type| !entry_types.contains(*entry_type))
+				{
+					log::warn!("The entry type {:?} of /map is not in /configuration", entry_type);
+				}
+			},
+			Err(e) => log::warn!("Could not check the entry types of /map against /configuration: {}", e),
+		}
+		Ok(Self {
+			endpoint_sets,
+			map_json,
+		})
+	}
+
+	/// Entry types the `/map` of the beacon advertises, without validating it (e.g. to generate a configuration)
+	pub fn advertised_entry_types(client: &Client, root_url: &Url) -> Result<Vec<String>, VerifierError> {
+		let map_json = client.query("BeaconMap", &Self::url(root_url, "map"))?.json;
+		Ok(Self::endpoint_sets(&map_json)?.into_keys().collect())
+	}
+
+	/// Endpoint sets of a `/map`, by entry type
+	fn endpoint_sets(map_json: &Json) -> Result<BTreeMap<String, Endpoint>, VerifierError> {
+		let endpoint_sets = map_json
+			.pointer("/response/endpointSets")
+			.cloned()
+			.ok_or_else(|| VerifierError::BadEnvelope("No response.endpointSets in /map".into()))?;
+		Ok(serde_json::from_value::<BTreeMap<String, Endpoint>>(endpoint_sets)?
+			.into_values()
+			.map(|endpoint| (endpoint.entry_type.clone(), endpoint))
+			.collect())
+	}
+
+	fn url(root_url: &Url, location: &str) -> Url {
+		let mut url = root_url.clone();
+		url.set_path(Path::new(root_url.path()).join(location).to_str().unwrap_or(""));
+		url
+	}
+
+	/// Whether the map of the beacon has an endpoint set for the entry type
+	pub fn advertises(&self, entry_type: &str) -> bool {
+		self.endpoint_sets.contains_key(entry_type)
+	}
+
+	/// Points the entities of the model to the urls advertised by the beacon, returning the endpoints of the
+	/// entities it does not advertise, skipped, and of the ones it advertises outside of `root_url`, not verified
+	pub fn apply(&self, model: &mut Model, root_url: &Url) -> Vec<BeaconEndpoint> {
+		for entry_type in self.endpoint_sets.keys() {
+			if !model.entities.iter().any(|entity| &entity.entry_type == entry_type) {
+				log::warn!(
+					"The beacon advertises the entry type {:?}, unknown to the model",
+					entry_type
+				);
+			}
+		}
+		let (advertised, not_advertised) = model
+			.entities
+			.drain(..)
+			.partition::<Vec<_>, _>(|entity| self.advertises(&entity.entry_type));
+		let (reachable, outside) = advertised
+			.into_iter()
+			.partition::<Vec<_>, _>(|entity| self.outside(&entity.entry_type, root_url).is_none());
+		model.entities = reachable
+			.into_iter()
+			.map(|entity| self.rebase(entity, root_url))
+			.collect();
+		let outside = outside.into_iter().map(|entity| {
+			let advertised = self
+				.outside(&entity.entry_type, root_url)
+				.cloned()
+				.unwrap_or_else(|| entity.url.clone());
+			log::warn!(
+				"The beacon advertises {} outside of its root url, at {}",
+				entity.name,
+				advertised
+			);
+			Model::build_endpoint(
+				entity.name.clone(),
+				entity.entry_type,
+				entity.schemas,
+				format!("{} all entries", entity.name),
+				&entity.url,
+				None,
+			)
+			.outside_root(advertised)
+		});
+		not_advertised
+			.into_iter()
+			.map(|entity| {
+				log::info!("The beacon does not advertise {}, skipping its checks", entity.name);
+				Model::build_endpoint(
+					entity.name.clone(),
+					entity.entry_type,
+					entity.schemas,
+					format!("{} all entries", entity.name),
+					&entity.url,
+					None,
+				)
+				.skip(NOT_ADVERTISED, self.map_json.clone())
+			})
+			.chain(outside)
+			.collect()
+	}
+
+	/// First url of the endpoint set of `entry_type` that is not under `root_url` (e.g. on another host)
+	fn outside(&self, entry_type: &str, root_url: &Url) -> Option<&Url> {
+		let endpoint = &self.endpoint_sets[entry_type];
+		std::iter::once(&endpoint.root_url)
+			.chain(endpoint.single_entry_url.as_ref())
+			.chain(
+				endpoint
+					.endpoints
+					.iter()
+					.flat_map(|endpoints| endpoints.values().map(|related| &related.url)),
+			)
+			.find(|url| !is_under(url, root_url))
+	}
+
+	/// Entity with the urls of its endpoint set, the ones the beacon does not advertise kept from the model
+	fn rebase(&self, mut entity: Entity, root_url: &Url) -> Entity {
+		let endpoint = &self.endpoint_sets[&entity.entry_type];
+		entity.url = relative(&entity.url, &endpoint.root_url, root_url);
+		if let Some(single_entry_url) = &endpoint.single_entry_url {
+			let template = entity.url_single.as_ref().unwrap_or(&entity.url);
+			entity.url_single = Some(relative(template, single_entry_url, root_url));
+		}
+		if let Some(endpoints) = &endpoint.endpoints {
+			let related = entity.related_endpoints.get_or_insert_with(BTreeMap::new);
+			for (name, related_endpoint) in endpoints {
+				let mut related_endpoint = related_endpoint.clone();
+				related_endpoint.url = relative(&entity.url, &related_endpoint.url, root_url);
+				related.insert(name.clone(), related_endpoint);
+			}
+		}
+		entity
+	}
+}
+
+/// Validates a response of the framework against its schema, resolving its references in the framework
+fn validate(
+	response_json: &Json,
+	schema: &Json,
+	framework: &Framework,
+	formats: &Formats,
+) -> Result<(), VerifierError> {
+	let json_schema = utils::compile_schema(schema, formats, framework.store())?;
+	shape::check(response_json).and_then(|()| utils::valid_schema(&json_schema, response_json).map(|_| ()))
+}
+
+/// Whether `advertised` is on the origin of `root_url`, under its path
+fn is_under(advertised: &Url, root_url: &Url) -> bool {
+	let root_path = root_url.path().trim_end_matches('/');
+	advertised.origin() == root_url.origin()
+		&& advertised
+			.path()
+			.strip_prefix(root_path)
+			.map_or(false, |rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// `base` with the path of `advertised` (under `root_url`) relative to the root of the beacon, the way the urls of
+/// the model are joined to it
+fn relative(base: &Url, advertised: &Url, root_url: &Url) -> Url {
+	let root_path = root_url.path().trim_end_matches('/');
+	let path = advertised
+		.path()
+		.strip_prefix(root_path)
+		.unwrap_or_else(|| advertised.path());
+	let mut url = base.clone();
+	url.set_path(path);
+	url
+}
+
+#[cfg(test)]
+mod tests {
+
+	use serde_json::json;
+	use url::Url;
+	use wiremock::ResponseTemplate;
+
+	use crate::discovery::{self, NOT_ADVERTISED};
+	use crate::output::Category;
+	use crate::scope::{Depth, Scope};
+	use crate::testing::{self, Behavior, MockBeacon};
+
+	#[test]
+	fn test_relative() {
+		let base = Url::parse("https://example.org/individuals").unwrap();
+		let root_url = Url::parse("https://beacon.example.org/api/").unwrap();
+		let advertised = Url::parse("https://beacon.example.org/api/v2/individuals/%7Bid%7D").unwrap();
+		let url = discovery::relative(&base, &advertised, &root_url);
+		assert_eq!(url.as_str(), "https://example.org/v2/individuals/%7Bid%7D");
+		assert!(discovery::is_under(&advertised, &root_url));
+		// Another host, or another path of the host
+		let advertised = Url::parse("https://cdn.example.org/api/individuals").unwrap();
+		assert!(!discovery::is_under(&advertised, &root_url));
+		let advertised = Url::parse("https://beacon.example.org/apis/individuals").unwrap();
+		assert!(!discovery::is_under(&advertised, &root_url));
+	}
+
+	#[test]
+	fn test_discover() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let url = |path: &str| beacon.url().join(path).unwrap().to_string();
+		let mut map = beacon.response("/map");
+		map["response"]["endpointSets"] = json!({
+			"individual": {
+				"entryType": "individual",
+				"rootUrl": url("v2/individuals"),
+				"singleEntryUrl": url("v2/individuals/{id}"),
+			},
+			"dataset": { "entryType": "dataset", "rootUrl": url("datasets") },
+		});
+		beacon.mount("/map", ResponseTemplate::new(200).set_body_json(map));
+		beacon.mount(
+			"/v2/individuals",
+			ResponseTemplate::new(200).set_body_json(beacon.response("/individuals")),
+		);
+		let output = beacon.beacon().discover().validate();
+
+		let all_entries = |entity: &str| {
+			output.entities[entity]
+				.iter()
+				.find(|report| report.name == format!("{} all entries", entity) && report.category == Category::Model)
+				.unwrap()
+		};
+		assert_eq!(all_entries("Individual").valid, Some(true));
+		assert!(all_entries("Individual").url.path().ends_with("/v2/individuals"));
+		// Advertised but broken
+		let single_entry = output.entities["Individual"]
+			.iter()
+			.find(|report| report.name == "Individual single entry")
+			.unwrap();
+		assert!(!single_entry.skipped);
+		assert!(single_entry.error.is_some());
+		assert_eq!(all_entries("Dataset").valid, Some(true));
+		// Not advertised
+		let biosamples = all_entries("Biosample");
+		assert!(biosamples.skipped);
+		assert_eq!(
+			biosamples.error.as_deref(),
+			Some(format!("Skipped: {}", NOT_ADVERTISED).as_str())
+		);
+		assert!(output.entities["Biosample"].iter().all(|report| report.skipped));
+		let configuration = testing::configuration(&beacon);
+		assert_eq!(
+			Scope::new(&configuration, &output).entities["Biosample"],
+			Depth::NotVerified
+		);
+
+		// The map of the model without --discover
+		let output = beacon.verify();
+		assert!(!output.entities["Biosample"].iter().any(|report| report.skipped));
+	}
+
+	#[test]
+	fn test_discover_outside_root() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let url = |path: &str| beacon.url().join(path).unwrap().to_string();
+		let mut map = beacon.response("/map");
+		map["response"]["endpointSets"] = json!({
+			"individual": { "entryType": "individual", "rootUrl": "https://cdn.example.org/individuals" },
+			"biosample": { "entryType": "biosample", "rootUrl": url("biosamples") },
+			"dataset": { "entryType": "dataset", "rootUrl": url("datasets") },
+		});
+		beacon.mount("/map", ResponseTemplate::new(200).set_body_json(map.clone()));
+		let output = beacon.beacon().discover().validate();
+		let individuals = &output.entities["Individual"];
+		assert!(!individuals.is_empty());
+		for report in individuals {
+			assert_eq!(report.url.as_str(), "https://cdn.example.org/individuals");
+			assert_eq!(report.valid, None);
+			assert!(report.error.as_deref().unwrap().contains("outside of the root url"));
+		}
+		assert!(output.entities["Biosample"].iter().all(|report| !report.skipped));
+
+		// A map that does not follow the framework is not used
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		map["meta"] = json!("v2.0.0");
+		beacon.mount("/map", ResponseTemplate::new(200).set_body_json(map));
+		let output = beacon.beacon().discover().validate();
+		assert!(output.entities["Individual"]
+			.iter()
+			.any(|report| report.valid == Some(true)));
+	}
+}
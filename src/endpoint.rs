@@ -1,183 +1,946 @@
-use std::rc::Rc;
+use std::sync::Arc;
 
 use jsonschema::JSONSchema;
 use url::Url;
 
+use crate::catalog::Severity;
+use crate::client::{Client, Response};
 use crate::error::VerifierError;
-use crate::interface::{BeaconMetaGranularityResponse, Granularity};
-use crate::output::EndpointReport;
-use crate::{utils, Json};
+use crate::formats::Formats;
+use crate::framework::ResponseSchemas;
+use crate::interface::{BeaconMetaGranularityResponse, Granularity, ReturnedSchema};
+use crate::model::EntitySchema;
+use crate::output::{self, Category, EndpointReport, ObservedData};
+use crate::sandbox::BeaconSchemas;
+use crate::utils::SchemaErrors;
+use crate::{collections, counts, datause, deprecated, identifiers, numeric, shape, utils, Json};
+
+/// Check of the result sets of the responses without records
+const RESULT_SETS_CHECK: &str = "model.result-sets";
+
+/// Check of the returned schemas against the default schemas of `/entry_types`
+const DECLARED_SCHEMA_CHECK: &str = "model.declared-schema";
 
 pub struct BeaconEndpoint {
 	pub entity_name: String,
-	pub entity_schema: Rc<JSONSchema>,
+	pub entry_type: String,
+	pub entity_schemas: Vec<EntitySchema>,
 	pub name: String,
 	pub url: Url,
+	/// Url before replacing the variables (e.g. `{id}`)
+	pub template_url: Url,
+	/// Id of the record the variables were replaced with
+	pub record_id: Option<String>,
+	/// Why there is no id to query this endpoint (e.g. the entity has no records), with the response showing it
+	pub skipped: Option<(String, Json)>,
+	/// Why the ids of the entity could not be read to query this endpoint (e.g. its records have no id)
+	pub no_ids: Option<String>,
+	/// Url the beacon advertises the endpoint at, outside of its root url, where it is not queried
+	pub outside_root: Option<Url>,
+	/// Id of the default schema the beacon declares for the entry type in `/entry_types`
+	pub declared_schema: Option<String>,
+	/// Schemas the responses return by their url, when the model does not have them
+	pub beacon_schemas: Option<BeaconSchemas>,
 }
 
 impl BeaconEndpoint {
+	/// Skips the checks of this endpoint, keeping `evidence` in the reports
+	pub fn no_data(self, evidence: Json) -> Self {
+		self.skip("no data", evidence)
+	}
+
+	/// Skips the checks of this endpoint for `reason`, keeping `evidence` in the reports
+	pub fn skip(mut self, reason: &str, evidence: Json) -> Self {
+		self.skipped = Some((reason.to_string(), evidence));
+		self
+	}
+
+	/// Fails the checks of this endpoint, which needs an id the endpoint of all the entries did not give
+	pub fn without_ids(mut self, reason: &str) -> Self {
+		self.no_ids = Some(reason.to_string());
+		self
+	}
+
+	/// Reports the endpoint as not verified, the beacon advertising it at `advertised`, outside of its root url
+	pub fn outside_root(mut self, advertised: Url) -> Self {
+		self.outside_root = Some(advertised);
+		self
+	}
+
+	/// Checks the returned schemas against the default schema `id` the beacon declares for the entry type
+	pub fn declared_schema(mut self, id: Option<String>) -> Self {
+		self.declared_schema = id;
+		self
+	}
+
+	/// Validates the records against the schemas returned by their url (e.g. a published extension of the model),
+	/// fetched within the limits of `schemas`, when the model does not have them
+	pub fn beacon_schemas(mut self, schemas: BeaconSchemas) -> Self {
+		self.beacon_schemas = Some(schemas);
+		self
+	}
+
+	/// Queries the endpoint, returning the reports instead if there is no response to validate
+	pub fn fetch(
+		&self,
+		client: &Client,
+		root_url: &Url,
+		only: Option<Category>,
+	) -> Result<Response, Vec<EndpointReport>> {
+		let endpoint_url = utils::url_join(root_url, &self.url);
+
+		let with_framework = only != Some(Category::Model);
+		let with_model = only != Some(Category::Framework);
+		let mut reports = Vec::new();
+
+		// Skip the endpoints that need a record of an empty entity, or of an entity without records to show
+		if let Some((reason, evidence)) = &self.skipped {
+			for category in Category::ALL {
+				if only.map_or(true, |only| only == category) {
+					reports.push(self.report(category, &endpoint_url).skip(reason, evidence.clone()));
+				}
+			}
+			return Err(self.with_template_url(root_url, reports));
+		}
+		if let Some(reason) = &self.no_ids {
+			return Err(self.unchecked(root_url, only, || VerifierError::NoIds(reason.clone())));
+		}
+		// Reported at the url the beacon advertises, not rebased on its root url
+		if let Some(advertised) = &self.outside_root {
+			return Err(Category::ALL
+				.into_iter()
+				.filter(|category| only.map_or(true, |only| only == *category))
+				.map(|category| {
+					self.report(category, advertised)
+						.null(VerifierError::OutsideRoot(advertised.clone()))
+				})
+				.collect());
+		}
+
+		// Get response, the endpoints that are not found being optional
+		client.query(&self.entity_name, &endpoint_url).map_err(|e| {
+			let severity = match e {
+				VerifierError::MissingEndpoint(_) => Severity::Warning,
+				_ => Severity::Error,
+			};
+			if with_model {
+				reports.push(
+					self.report(Category::Model, &endpoint_url)
+						.null(VerifierError::NotVerified("the endpoint did not respond".into()))
+						.severity(severity),
+				);
+			}
+			if with_framework {
+				reports.push(
+					self.report(Category::Framework, &endpoint_url)
+						.null(e)
+						.severity(severity),
+				);
+			}
+			self.with_template_url(root_url, reports)
+		})
+	}
+
+	/// Reports of the endpoint when it was not queried
+	pub fn not_verified(&self, root_url: &Url, only: Option<Category>, reason: &str) -> Vec<EndpointReport> {
+		self.unchecked(root_url, only, || VerifierError::NotVerified(reason.into()))
+	}
+
+	/// Reports of the endpoint when its checks panicked
+	pub fn internal_error(&self, root_url: &Url, only: Option<Category>, message: &str) -> Vec<EndpointReport> {
+		self.unchecked(root_url, only, || VerifierError::Internal(message.into()))
+	}
+
+	fn unchecked<F>(&self, root_url: &Url, only: Option<Category>, error: F) -> Vec<EndpointReport>
+	where
+		F: Fn() -> VerifierError,
+	{
+		let endpoint_url = utils::url_join(root_url, &self.url);
+		let reports = Category::ALL
+			.into_iter()
+			.filter(|category| only.map_or(true, |only| only == *category))
+			.map(|category| self.report(category, &endpoint_url).null(error()))
+			.collect();
+		self.with_template_url(root_url, reports)
+	}
+
+	/// Validates a response of the endpoint (CPU bound, can run on any thread)
 	pub fn validate(
-		self,
+		&self,
+		response: Response,
 		root_url: &Url,
-		boolean_json: &Rc<JSONSchema>,
-		count_json: &Rc<JSONSchema>,
-		result_sets_json: &Rc<JSONSchema>,
-		collections_json: &Rc<JSONSchema>,
-	) -> EndpointReport {
+		schemas: &ResponseSchemas,
+		formats: &Formats,
+		only: Option<Category>,
+	) -> Vec<EndpointReport> {
+		let reports = self.check(response, root_url, schemas, formats, only);
+		self.with_template_url(root_url, reports)
+	}
+
+	fn with_template_url(&self, root_url: &Url, reports: Vec<EndpointReport>) -> Vec<EndpointReport> {
+		let template_url = utils::url_join(root_url, &self.template_url);
+		reports
+			.into_iter()
+			.map(|report| report.template_url(template_url.clone()))
+			.collect()
+	}
+
+	fn check(
+		&self,
+		response: Response,
+		root_url: &Url,
+		schemas: &ResponseSchemas,
+		formats: &Formats,
+		only: Option<Category>,
+	) -> Vec<EndpointReport> {
 		let endpoint_url = utils::url_join(root_url, &self.url);
-		log::debug!("GET {}", endpoint_url);
 
-		// Get response
-		let response_json = match utils::ping_url(&endpoint_url) {
-			Ok(j) => j,
-			Err(e) => {
-				return EndpointReport::new(&self.entity_name, &self.name, endpoint_url).null(e);
-			},
+		let with_framework = only != Some(Category::Model);
+		let with_model = only != Some(Category::Framework);
+		let mut reports = Vec::new();
+
+		let response_json = response.json;
+		let content_hash = utils::content_hash(&response_json);
+		let responded = |report: EndpointReport| {
+			report
+				.method(response.method.clone(), response.fallback)
+				.content_hash(content_hash.clone())
+				.timings(response.timings.clone())
+				.warnings(response.warnings.clone())
+				.elapsed(response.elapsed)
 		};
 
+		// Diagnose the serialization mistakes before the schemas bury them under their errors
+		if let Err(e) = shape::check(&response_json) {
+			if with_model {
+				reports.push(responded(self.report(Category::Model, &endpoint_url).null(
+					VerifierError::NotVerified("the response is not where it should be".into()),
+				)));
+			}
+			if with_framework {
+				reports.push(responded(self.report(Category::Framework, &endpoint_url).error(e)));
+			}
+			return reports;
+		}
+
 		// Test granularity
-		let beacon_meta_response: Result<BeaconMetaGranularityResponse, _> =
-			serde_json::from_value(response_json.clone());
-
-		// Test response
-		match beacon_meta_response {
-			Ok(br) => {
-				let valid_against_framework = match br.meta.returned_granularity {
-					Granularity::Boolean => self.validate_against_framework(&response_json, boolean_json),
-					Granularity::Count => self.validate_against_framework(&response_json, count_json),
-					Granularity::Aggregated | Granularity::Record => match self.entity_name.to_lowercase().as_str() {
-						"dataset" | "cohort" => self.validate_against_framework(&response_json, collections_json),
-						_ => self.validate_against_framework(&response_json, result_sets_json),
-					},
-				};
-				if let Err(e) = valid_against_framework {
-					return EndpointReport::new(&self.entity_name, &self.name, endpoint_url).error(e);
+		let beacon_meta_response: BeaconMetaGranularityResponse = match serde_json::from_value(response_json.clone()) {
+			Ok(br) => br,
+			Err(e) => {
+				if with_model {
+					reports.push(responded(self.report(Category::Model, &endpoint_url).null(
+						VerifierError::NotVerified("the granularity of the response is unknown".into()),
+					)));
 				}
+				if with_framework {
+					reports.push(responded(
+						self.report(Category::Framework, &endpoint_url).error(e.into()),
+					));
+				}
+				return reports;
+			},
+		};
+		let granularity = beacon_meta_response.meta.returned_granularity;
+		let is_collection = collections::is_collections_response(&response_json);
 
-				if Granularity::Record == br.meta.returned_granularity {
-					// Compile entity schema
-					match self.entity_name.to_lowercase().as_str() {
-						"dataset" | "cohort" => self.validate_collections_response(&response_json).url(endpoint_url),
-						_ => self.validate_resultset_response(&response_json).url(endpoint_url),
+		// Test response envelope
+		if with_framework {
+			let response_schema = match granularity {
+				Granularity::Boolean => &schemas.boolean,
+				Granularity::Count => &schemas.count,
+				Granularity::Aggregated | Granularity::Record => {
+					if is_collection {
+						&schemas.collections
 					}
+					else {
+						&schemas.result_sets
+					}
+				},
+			};
+			let report = match self.validate_against_framework(&response_json, response_schema) {
+				// The counts of the collections are checked with the pages of the datasets
+				Ok(()) if is_collection => self.report(Category::Framework, &endpoint_url).ok(None),
+				Ok(()) => match counts::check(&response_json, granularity) {
+					Ok(()) => self.report(Category::Framework, &endpoint_url).ok(None),
+					Err(e) => self
+						.report(Category::Framework, &endpoint_url)
+						.check(counts::CHECK_ID)
+						.error(e),
+				},
+				Err(e) => self.report(Category::Framework, &endpoint_url).error(e),
+			};
+			reports.push(responded(report));
+		}
+
+		// Test entity documents
+		if with_model && Granularity::Record == granularity {
+			let report = match self.select_schema(&beacon_meta_response.meta.returned_schemas, formats) {
+				Ok(entity_schema) if is_collection => {
+					self.validate_collections_response(&response_json, &entity_schema, formats)
+				},
+				Ok(entity_schema) => self.validate_resultset_response(&response_json, &entity_schema, formats),
+				Err(e) => self
+					.report(Category::Model, &endpoint_url)
+					.check(schema_check(&e))
+					.error(e),
+			};
+			let report = responded(report)
+				.url(endpoint_url.clone())
+				.observed(&self.entry_type, ObservedData::from_response(&response_json));
+			reports.push(
+				if self.entry_type == "dataset" {
+					report.data_use(datause::from_response(&response_json))
 				}
 				else {
-					EndpointReport::new(&self.entity_name, &self.name, endpoint_url).ok(Some(response_json))
-				}
-			},
-			Err(e) => EndpointReport::new(&self.entity_name, &self.name, endpoint_url).error(e.into()),
+					report
+				},
+			);
 		}
+
+		// Without records, the result sets of the entity still tell which datasets answered and how
+		if with_model && !granularity.has_individual_records() && !is_collection {
+			let report = match self.select_schema(&beacon_meta_response.meta.returned_schemas, formats) {
+				Ok(_) => self.validate_result_sets_metadata(&response_json, granularity),
+				Err(e) => self
+					.report(Category::Model, &endpoint_url)
+					.check(schema_check(&e))
+					.error(e),
+			};
+			reports.push(responded(report).url(endpoint_url));
+		}
+
+		reports
+	}
+
+	/// Report of the check of the responses (framework) or of the records (model) of the endpoint
+	fn report(&self, category: Category, url: &Url) -> EndpointReport {
+		let check = match category {
+			Category::Framework => "framework.entity-response",
+			Category::Model => "model.entity",
+		};
+		EndpointReport::new(&self.entity_name, &self.name, url.clone())
+			.category(category)
+			.check(check)
+			.record(self.record_id.as_deref())
+	}
+
+	/// Key of the checks of this endpoint in the history, with the record it is queried with
+	pub fn key(&self) -> String {
+		output::check_key(&self.name, self.record_id.as_deref())
 	}
 
 	pub fn validate_against_framework(
 		&self,
 		response_json: &Json,
-		response_schema: &Rc<JSONSchema>,
+		response_schema: &Arc<JSONSchema>,
 	) -> Result<(), VerifierError> {
-		if let Err(e) = utils::valid_schema(response_schema, response_json) {
-			return Err(e);
-		};
+		utils::valid_schema(response_schema, response_json)?;
 		Ok(())
 	}
 
-	pub fn validate_collections_response(self, response_json: &Json) -> EndpointReport {
-		// Case: == 0 results
-		if !response_json
-			.as_object()
-			.expect("JSON is not an object")
-			.get("responseSummary")
-			.expect("No 'responseSummary' property was found")
-			.as_object()
-			.expect("'responseSummary' is not an object")
-			.get("exists")
-			.expect("No 'exists' property found")
-			.as_bool()
-			.expect("'exists' property is not a bool")
+	/// Picks the schema of the model that the response declares in `meta.returnedSchemas`
+	/// for the queried entry type (the default schema if nothing is declared), or else the schema at its url
+	pub fn select_schema(
+		&self,
+		returned_schemas: &[ReturnedSchema],
+		formats: &Formats,
+	) -> Result<EntitySchema, VerifierError> {
+		let default_schema = self.entity_schemas.first().ok_or_else(|| {
+			VerifierError::NoMatchingSchema(format!("the model has no schema for entry type '{}'", self.entry_type))
+		})?;
+
+		if returned_schemas.is_empty() {
+			let declared = self.declared_schema.as_deref().and_then(|declared| {
+				self.entity_schemas
+					.iter()
+					.find(|schema| schema.id.as_deref() == Some(declared))
+			});
+			log::debug!("No returnedSchemas, using the default schema of '{}'", self.entry_type);
+			return Ok(declared.unwrap_or(default_schema).clone());
+		}
+
+		let returned_schema = returned_schemas
+			.iter()
+			.find(|returned_schema| returned_schema.entity_type == self.entry_type)
+			.ok_or_else(|| {
+				VerifierError::NoMatchingSchema(format!(
+					"expected entity type '{}' but the response declares {:?}",
+					self.entry_type,
+					returned_schemas.iter().map(|rs| &rs.entity_type).collect::<Vec<_>>()
+				))
+			})?;
+
+		if let Some(declared) = self
+			.declared_schema
+			.as_deref()
+			.filter(|declared| *declared != returned_schema.schema)
 		{
-			return EndpointReport::new(&self.entity_name, &self.name, self.url).ok(None);
+			return Err(VerifierError::UndeclaredSchema(format!(
+				"the response returns '{}' for entry type '{}', but /entry_types declares '{}'",
+				returned_schema.schema, self.entry_type, declared
+			)));
+		}
+
+		// Models that do not identify their schemas can only be validated with the default one
+		if self.entity_schemas.iter().all(|schema| schema.id.is_none()) {
+			return Ok(default_schema.clone());
+		}
+
+		let in_model = self
+			.entity_schemas
+			.iter()
+			.find(|schema| schema.id.as_deref() == Some(returned_schema.schema.as_str()));
+		let url = Url::parse(&returned_schema.schema)
+			.ok()
+			.filter(|url| matches!(url.scheme(), "http" | "https"));
+		if let (None, Some(beacon_schemas), Some(url)) = (in_model, &self.beacon_schemas, url) {
+			return Ok(EntitySchema {
+				id: Some(returned_schema.schema.clone()),
+				schema: beacon_schemas.get(&url, formats)?,
+				deprecated: Vec::new(),
+			});
+		}
+		in_model.cloned().ok_or_else(|| {
+			VerifierError::NoMatchingSchema(format!(
+				"schema '{}' is not supported for entry type '{}' (supported: {:?})",
+				returned_schema.schema,
+				self.entry_type,
+				self.entity_schemas
+					.iter()
+					.filter_map(|schema| schema.id.as_ref())
+					.collect::<Vec<_>>()
+			))
+		})
+	}
+
+	/// Validates the metadata of the result sets of a boolean or count response: an id, a set type, whether the set
+	/// has matches, their number at count granularity, and no records
+	fn validate_result_sets_metadata(&self, response_json: &Json, granularity: Granularity) -> EndpointReport {
+		let report = self.report(Category::Model, &self.url).check(RESULT_SETS_CHECK);
+		let result_sets = match response_json.pointer("/response/resultSets").and_then(Json::as_array) {
+			Some(result_sets) => result_sets,
+			None => return report.null(VerifierError::NotVerified("the response has no result sets".into())),
+		};
+		let mut problems = Vec::new();
+		for (i, result_set) in result_sets.iter().enumerate() {
+			let name = format!("resultSets[{}]", i);
+			let non_empty = |property: &str| {
+				result_set
+					.get(property)
+					.and_then(Json::as_str)
+					.map_or(false, |value| !value.trim().is_empty())
+			};
+			if !non_empty("id") {
+				problems.push(format!("{} has no id", name));
+			}
+			if !non_empty("setType") {
+				problems.push(format!("{} has no setType", name));
+			}
+			let exists = result_set.get("exists").and_then(Json::as_bool);
+			if exists.is_none() {
+				problems.push(format!("{} has no boolean 'exists'", name));
+			}
+			if matches!(granularity, Granularity::Count | Granularity::Aggregated) {
+				match result_set.get("resultsCount").and_then(Json::as_u64) {
+					Some(count) if exists.map_or(false, |exists| exists != (count > 0)) => {
+						problems.push(format!("{} has {} results but 'exists' is {}", name, count, count == 0));
+					},
+					Some(_) => (),
+					None => problems.push(format!("{} has no valid 'resultsCount'", name)),
+				}
+			}
+			// The results of an aggregated response are aggregations of the records
+			let records = result_set.get("results").and_then(Json::as_array).map_or(0, Vec::len);
+			if records > 0 && granularity != Granularity::Aggregated {
+				problems.push(format!(
+					"{} holds {} records at {} granularity",
+					name, records, granularity
+				));
+			}
+		}
+		if problems.is_empty() {
+			report.ok(None)
+		}
+		else {
+			report.error(VerifierError::BadEnvelope(problems.join(", ")))
+		}
+	}
+
+	fn exists(response_json: &Json) -> Result<bool, VerifierError> {
+		response_json
+			.pointer("/responseSummary/exists")
+			.and_then(Json::as_bool)
+			.ok_or_else(|| VerifierError::BadEnvelope("No boolean 'responseSummary.exists' property was found".into()))
+	}
+
+	/// Validates every instance, grouping the identical errors of the instances together, with notes about the
+	/// deprecated properties they use
+	fn validate_instances<'a>(
+		&self,
+		instances: impl Iterator<Item = &'a Json>,
+		entity_schema: &EntitySchema,
+		formats: &Formats,
+		prefix: &str,
+	) -> EndpointReport {
+		let instances = instances.collect::<Vec<_>>();
+		let mut errors = SchemaErrors::default();
+		for instance in instances.iter().copied() {
+			errors.merge(utils::schema_errors(&entity_schema.schema, instance, prefix));
+			for (path, message) in identifiers::check_record(instance, formats) {
+				errors.push_message(message, &format!("{}{}", prefix, path));
+			}
+			for (path, message) in numeric::check_record(instance) {
+				errors.push_message(message, &format!("{}{}", prefix, path));
+			}
+		}
+		let report = self
+			.report(Category::Model, &self.url)
+			.notes(deprecated::notes(&instances, &entity_schema.deprecated));
+		match utils::check_schema_errors(errors) {
+			Ok(()) => report.ok(None),
+			Err(e) => report.error(e),
+		}
+	}
+
+	pub fn validate_collections_response(
+		&self,
+		response_json: &Json,
+		entity_schema: &EntitySchema,
+		formats: &Formats,
+	) -> EndpointReport {
+		// Case: == 0 results
+		match Self::exists(response_json) {
+			Ok(true) => (),
+			Ok(false) => return self.report(Category::Model, &self.url).ok(None),
+			Err(e) => return self.report(Category::Model, &self.url).null(e),
 		}
 
 		// Case: >= 1 results
 		log::info!("Verifying results...");
-		response_json
-			.as_object()
-			.expect("JSON is not an object")
-			.get("response")
-			.expect("No 'response' property was found")
-			.as_object()
-			.expect("'response' is not an object")
-			.get("collections")
-			.expect("No 'collections' property was found")
-			.as_array()
-			.expect("'collections' property is not an array")
-			.iter()
-			.map(
-				|instance| match utils::valid_schema(&self.entity_schema.clone(), &instance.clone()) {
-					Ok(output) => EndpointReport::new(&self.entity_name, &self.name, self.url.clone()).ok(Some(output)),
-					Err(e) => EndpointReport::new(&self.entity_name, &self.name, self.url.clone()).error(e),
-				},
-			)
-			.fold(
-				EndpointReport::new(&self.entity_name, &self.name, self.url.clone()).ok(None),
-				EndpointReport::join,
-			)
+		match response_json.pointer("/response/collections").and_then(Json::as_array) {
+			Some(collections) => {
+				self.validate_instances(collections.iter(), entity_schema, formats, "/response/collections/*")
+			},
+			None => self.report(Category::Model, &self.url).null(VerifierError::BadEnvelope(
+				"No 'response.collections' array was found".into(),
+			)),
+		}
 	}
 
-	pub fn validate_resultset_response(self, response_json: &Json) -> EndpointReport {
+	pub fn validate_resultset_response(
+		&self,
+		response_json: &Json,
+		entity_schema: &EntitySchema,
+		formats: &Formats,
+	) -> EndpointReport {
 		// Case: == 0 results
-		if !response_json
-			.as_object()
-			.expect("JSON is not an object")
-			.get("responseSummary")
-			.expect("No 'responseSummary' property was found")
-			.as_object()
-			.expect("'responseSummary' is not an object")
-			.get("exists")
-			.expect("No 'exists' property found")
-			.as_bool()
-			.expect("'exists' property is not a bool")
-		{
-			return EndpointReport::new(&self.entity_name, &self.name, self.url).ok(None);
+		match Self::exists(response_json) {
+			Ok(true) => (),
+			Ok(false) => return self.report(Category::Model, &self.url).ok(None),
+			Err(e) => return self.report(Category::Model, &self.url).null(e),
 		}
 
 		// Case: >= 1 results
 		log::info!("Verifying results...");
-		response_json
-			.as_object()
-			.expect("JSON is not an object")
-			.get("response")
-			.expect("No 'response' property was found")
-			.as_object()
-			.expect("'response' is not an object")
-			.get("resultSets")
-			.expect("No 'resultSets' property was found")
-			.as_array()
-			.expect("'resultSets' property is not an array")
+		let result_sets = match response_json.pointer("/response/resultSets").and_then(Json::as_array) {
+			Some(result_sets) => result_sets,
+			None => {
+				return self.report(Category::Model, &self.url).null(VerifierError::BadEnvelope(
+					"No 'response.resultSets' array was found".into(),
+				))
+			},
+		};
+		let mut results = Vec::new();
+		for rs in result_sets {
+			match rs.get("results").and_then(Json::as_array) {
+				Some(rs_results) => results.extend(rs_results),
+				None => {
+					return self.report(Category::Model, &self.url).null(VerifierError::BadEnvelope(
+						"No 'results' array was found in a resultSet".into(),
+					))
+				},
+			}
+		}
+		self.validate_instances(
+			results.into_iter(),
+			entity_schema,
+			formats,
+			"/response/resultSets/*/results/*",
+		)
+	}
+}
+
+/// Check of the catalog failed by an error selecting the schema of the records
+fn schema_check(e: &VerifierError) -> &'static str {
+	match e {
+		VerifierError::UndeclaredSchema(_) => DECLARED_SCHEMA_CHECK,
+		_ => "model.returned-schema",
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use serde_json::json;
+	use url::Url;
+	use wiremock::ResponseTemplate;
+
+	use crate::client::Client;
+	use crate::endpoint::RESULT_SETS_CHECK;
+	use crate::error::VerifierError;
+	use crate::interface::Granularity;
+	use crate::output::{BeaconOutput, Category, EndpointOutput};
+	use crate::sandbox::SchemaLimits;
+	use crate::testing::{Behavior, MockBeacon};
+	use crate::utils::{self, Ids};
+
+	const MODEL_ENTITIES: [&str; 3] = ["Individual", "Biosample", "Dataset"];
+
+	fn find<'a>(output: &'a BeaconOutput, entity: &str, name: &str, category: Category) -> &'a EndpointOutput {
+		output.entities[entity]
 			.iter()
-			.map(|rs| {
-				rs.as_object()
-					.expect("resultSet inside 'resultSets' property is not an object")
-					.get("results")
-					.expect("No 'results' property was found")
-					.as_array()
-					.expect("'results' property is not an array")
-					.iter()
-					.map(
-						|instance| match utils::valid_schema(&self.entity_schema.clone(), &instance.clone()) {
-							Ok(output) => {
-								EndpointReport::new(&self.entity_name, &self.name, self.url.clone()).ok(Some(output))
-							},
-							Err(e) => EndpointReport::new(&self.entity_name, &self.name, self.url.clone()).error(e),
-						},
-					)
-					.fold(
-						EndpointReport::new(&self.entity_name, &self.name, self.url.clone()).ok(None),
-						EndpointReport::join,
-					)
-			})
-			.fold(
-				EndpointReport::new(&self.entity_name, &self.name, self.url.clone()).ok(None),
-				EndpointReport::join,
+			.find(|report| report.name == name && report.category == category)
+			.unwrap()
+	}
+
+	#[test]
+	fn test_conformant_entities() {
+		let output = MockBeacon::start(Behavior::Conformant).verify();
+		for entity in MODEL_ENTITIES {
+			let reports = &output.entities[entity];
+			assert!(!reports.is_empty(), "{}", entity);
+			assert!(reports.iter().all(|report| report.valid == Some(true)), "{}", entity);
+		}
+		// Framework and model checks of all entries, single entry and related biosamples, and the ids
+		assert_eq!(output.entities["Individual"].len(), 7);
+	}
+
+	#[test]
+	fn test_bad_meta() {
+		let output = MockBeacon::start(Behavior::BadMeta).verify();
+		for entity in MODEL_ENTITIES {
+			for report in &output.entities[entity] {
+				match report.category {
+					Category::Framework => assert_eq!(report.valid, Some(false), "{}", entity),
+					Category::Model => assert_eq!(report.valid, None, "{}", entity),
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn test_wrong_counts() {
+		let output = MockBeacon::start(Behavior::WrongCounts).verify();
+		for entity in MODEL_ENTITIES {
+			let (skipped, reports): (Vec<_>, Vec<_>) =
+				output.entities[entity].iter().partition(|report| report.skipped);
+			assert!(reports.iter().all(|report| report.valid == Some(false)), "{}", entity);
+			// There are no records to validate against the model at count granularity, only the result sets
+			let model = reports.iter().filter(|report| report.category == Category::Model);
+			if entity == "Dataset" {
+				assert_eq!(model.count(), 0);
+			}
+			else {
+				assert!(model
+					.map(|report| report.error.as_deref().unwrap())
+					.all(|error| error.contains("holds 2 records at count granularity")));
+			}
+			// Nor to query the record-level endpoints with
+			assert!(!skipped.is_empty(), "{}", entity);
+			assert!(skipped
+				.iter()
+				.all(|report| report.error.as_deref() == Some("Skipped: count granularity")));
+		}
+	}
+
+	#[test]
+	fn test_missing_endpoints() {
+		let output = MockBeacon::start(Behavior::MissingEndpoints).verify();
+		let reports = &output.entities["Biosample"];
+		assert!(reports.iter().all(|report| report.valid.is_none()));
+		assert!(reports.iter().all(|report| report.error.is_some()));
+		assert!(output.entities["Dataset"]
+			.iter()
+			.all(|report| report.valid == Some(true)));
+	}
+
+	#[test]
+	fn test_no_data() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let mut response = beacon.response("/individuals");
+		response["responseSummary"] = json!({ "exists": false, "numTotalResults": 0 });
+		response["response"]["resultSets"][0]["exists"] = json!(false);
+		response["response"]["resultSets"][0]["resultsCount"] = json!(0);
+		response["response"]["resultSets"][0]["results"] = json!([]);
+		beacon.mount(
+			"/individuals",
+			ResponseTemplate::new(200).set_body_json(response.clone()),
+		);
+		let output = beacon.verify();
+		let all_entries = find(&output, "Individual", "Individual all entries", Category::Framework);
+		assert_eq!(all_entries.valid, Some(true));
+		for category in Category::ALL {
+			let single_entry = find(&output, "Individual", "Individual single entry", category);
+			assert!(single_entry.skipped);
+			assert_eq!(single_entry.valid, None);
+			assert_eq!(single_entry.error.as_deref(), Some("Skipped: no data"));
+			assert_eq!(single_entry.evidence.as_ref(), Some(&response));
+		}
+		assert!(
+			find(
+				&output,
+				"Individual",
+				"Biosample related with a Individual",
+				Category::Model
 			)
+			.skipped
+		);
+	}
+
+	#[test]
+	fn test_count_granularity() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let mut response = beacon.response("/individuals");
+		response["meta"]["returnedGranularity"] = json!("count");
+		response["responseSummary"] = json!({ "exists": true, "numTotalResults": 2 });
+		response.as_object_mut().unwrap().remove("response");
+		beacon.mount(
+			"/individuals",
+			ResponseTemplate::new(200).set_body_json(response.clone()),
+		);
+		let output = beacon.verify();
+		for category in Category::ALL {
+			let single_entry = find(&output, "Individual", "Individual single entry", category);
+			assert!(single_entry.skipped);
+			assert_eq!(single_entry.error.as_deref(), Some("Skipped: count granularity"));
+			assert_eq!(single_entry.evidence.as_ref(), Some(&response));
+		}
+		assert!(
+			find(
+				&output,
+				"Individual",
+				"Biosample related with a Individual",
+				Category::Model
+			)
+			.skipped
+		);
+		assert!(!find(&output, "Biosample", "Biosample single entry", Category::Model).skipped);
+
+		// Without a granularity in the response, the declared one
+		response["meta"].as_object_mut().unwrap().remove("returnedGranularity");
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		beacon.mount("/individuals", ResponseTemplate::new(200).set_body_json(response));
+		let url = Url::parse("https://example.org/individuals").unwrap();
+		let client = Client::new();
+		let ids = utils::get_ids(
+			&client,
+			"Individual",
+			&beacon.url(),
+			&url,
+			"id",
+			Some(Granularity::Boolean),
+		);
+		assert!(matches!(ids, Ok(Ids::NoRecords(reason, _)) if reason == "boolean granularity"));
+		// Nor records, which fails the record-level checks
+		let ids = utils::get_ids(&client, "Individual", &beacon.url(), &url, "id", None);
+		assert!(matches!(ids, Err(VerifierError::MissingResultSets)), "{:?}", ids);
+	}
+
+	#[test]
+	fn test_count_result_sets() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let mut response = beacon.response("/individuals");
+		response["meta"]["returnedGranularity"] = json!("count");
+		response["response"]["resultSets"][0]
+			.as_object_mut()
+			.unwrap()
+			.remove("results");
+		beacon.mount(
+			"/individuals",
+			ResponseTemplate::new(200).set_body_json(response.clone()),
+		);
+		let output = beacon.verify();
+		let all_entries = find(&output, "Individual", "Individual all entries", Category::Model);
+		assert_eq!(all_entries.valid, Some(true));
+
+		response["response"]["resultSets"][0]["resultsCount"] = json!(0);
+		response["response"]["resultSets"][0]
+			.as_object_mut()
+			.unwrap()
+			.remove("setType");
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		beacon.mount(
+			"/individuals",
+			ResponseTemplate::new(200).set_body_json(response.clone()),
+		);
+		let output = beacon.verify();
+		let all_entries = find(&output, "Individual", "Individual all entries", Category::Model);
+		assert_eq!(
+			all_entries.error.as_deref(),
+			Some(
+				"Bad response envelope: resultSets[0] has no setType, resultSets[0] has 0 results but 'exists' is true"
+			)
+		);
+
+		// The result sets of another entity
+		response["meta"]["returnedSchemas"][0]["entityType"] = json!("biosample");
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		beacon.mount("/individuals", ResponseTemplate::new(200).set_body_json(response));
+		let output = beacon.verify();
+		let all_entries = find(&output, "Individual", "Individual all entries", Category::Model);
+		assert!(all_entries
+			.error
+			.as_ref()
+			.unwrap()
+			.contains("expected entity type 'individual'"));
+	}
+
+	#[test]
+	fn test_aggregated_granularity() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let mut response = beacon.response("/individuals");
+		response["meta"]["returnedGranularity"] = json!("aggregated");
+		response["response"]["resultSets"][0]["resultsCount"] = json!(2);
+		response["response"]["resultSets"][0]["results"] = json!([{ "sex": "female", "count": 2 }]);
+		beacon.mount(
+			"/individuals",
+			ResponseTemplate::new(200).set_body_json(response.clone()),
+		);
+		let output = beacon.verify();
+		// No ids to harvest from aggregations
+		let single_entry = find(&output, "Individual", "Individual single entry", Category::Model);
+		assert!(single_entry.skipped);
+		assert_eq!(single_entry.error.as_deref(), Some("Skipped: aggregated granularity"));
+		// Their result sets are checked like the ones of a count response, not validated as records
+		let all_entries = find(&output, "Individual", "Individual all entries", Category::Model);
+		assert_eq!(all_entries.valid, Some(true), "{:?}", all_entries.error);
+		assert_eq!(all_entries.check.as_deref(), Some(RESULT_SETS_CHECK));
+
+		response["response"]["resultSets"][0]["resultsCount"] = json!(0);
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		beacon.mount("/individuals", ResponseTemplate::new(200).set_body_json(response));
+		let output = beacon.verify();
+		let all_entries = find(&output, "Individual", "Individual all entries", Category::Model);
+		assert_eq!(
+			all_entries.error.as_deref(),
+			Some("Bad response envelope: resultSets[0] has 0 results but 'exists' is true")
+		);
+	}
+
+	#[test]
+	fn test_record_not_matching_model() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let mut response = beacon.response("/biosamples");
+		response["response"]["resultSets"][0]["results"][0]
+			.as_object_mut()
+			.unwrap()
+			.remove("biosampleStatus");
+		beacon.mount("/biosamples", ResponseTemplate::new(200).set_body_json(response));
+		let output = beacon.verify();
+		let all_entries = find(&output, "Biosample", "Biosample all entries", Category::Model);
+		assert_eq!(all_entries.valid, Some(false));
+		// A broken record does not obscure a valid envelope
+		let all_entries = find(&output, "Biosample", "Biosample all entries", Category::Framework);
+		assert_eq!(all_entries.valid, Some(true));
+	}
+
+	#[test]
+	fn test_only_model() {
+		let beacon = MockBeacon::start(Behavior::BadMeta);
+		let output = beacon.beacon().only(Category::Model).validate();
+		assert!(!output.entities.contains_key("Info"));
+		for entity in MODEL_ENTITIES {
+			let reports = &output.entities[entity];
+			assert!(reports.iter().all(|report| report.category == Category::Model));
+		}
+	}
+
+	#[test]
+	fn test_select_alternative_schema() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let mut response = beacon.response("/individuals");
+		response["meta"]["returnedSchemas"][0]["schema"] = json!("beacon-individual-v2.1.0");
+		beacon.mount("/individuals", ResponseTemplate::new(200).set_body_json(response));
+		let output = beacon.verify();
+		let all_entries = find(&output, "Individual", "Individual all entries", Category::Model);
+		// Mock individuals have no karyotypic sex, required by the alternative schema
+		assert_eq!(all_entries.valid, Some(false));
+		assert!(all_entries.error.as_ref().unwrap().contains("karyotypicSex"));
+	}
+
+	#[test]
+	fn test_unsupported_returned_schema() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let mut response = beacon.response("/individuals");
+		response["meta"]["returnedSchemas"][0]["schema"] = json!("beacon-individual-v9.0.0");
+		beacon.mount("/individuals", ResponseTemplate::new(200).set_body_json(response));
+		let output = beacon.verify();
+		let all_entries = find(&output, "Individual", "Individual all entries", Category::Model);
+		assert_eq!(all_entries.valid, Some(false));
+		assert!(all_entries.error.as_ref().unwrap().contains("beacon-individual-v9.0.0"));
+	}
+
+	#[test]
+	fn test_schema_returned_by_url() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let schema = json!({ "type": "object", "required": ["karyotypicSex"] });
+		beacon.mount(
+			"/schemas/individual.json",
+			ResponseTemplate::new(200).set_body_json(schema),
+		);
+		let mut response = beacon.response("/individuals");
+		response["meta"]["returnedSchemas"][0]["schema"] = json!(beacon.url().join("schemas/individual.json").unwrap());
+		beacon.mount("/individuals", ResponseTemplate::new(200).set_body_json(response));
+		let local = SchemaLimits {
+			allow_local: true,
+			..SchemaLimits::default()
+		};
+		let output = beacon.beacon().schema_limits(local).validate();
+		let all_entries = find(&output, "Individual", "Individual all entries", Category::Model);
+		assert_eq!(all_entries.valid, Some(false));
+		assert!(all_entries.error.as_ref().unwrap().contains("karyotypicSex"));
+
+		// The mock beacon is local
+		let output = beacon.verify();
+		let all_entries = find(&output, "Individual", "Individual all entries", Category::Model);
+		let e = all_entries.error.as_deref().unwrap();
+		assert!(
+			e.contains("Untrusted schema refused") && e.contains("local address"),
+			"{}",
+			e
+		);
+	}
+
+	#[test]
+	fn test_returned_schema_of_other_entity() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let mut response = beacon.response("/biosamples");
+		response["meta"]["returnedSchemas"][0]["entityType"] = json!("individual");
+		beacon.mount("/biosamples", ResponseTemplate::new(200).set_body_json(response));
+		let output = beacon.verify();
+		let all_entries = find(&output, "Biosample", "Biosample all entries", Category::Model);
+		assert_eq!(all_entries.valid, Some(false));
+		assert!(all_entries
+			.error
+			.as_ref()
+			.unwrap()
+			.contains("expected entity type 'biosample'"));
+	}
+
+	#[test]
+	fn test_invalid_hgvs_expression() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let mut response = beacon.response("/individuals");
+		response["response"]["resultSets"][0]["results"][0]["identifiers"] =
+			json!({ "genomicHGVSId": "NC_000017.11:g.43057063G>" });
+		beacon.mount("/individuals", ResponseTemplate::new(200).set_body_json(response));
+		let output = beacon.verify();
+		let all_entries = find(&output, "Individual", "Individual all entries", Category::Model);
+		assert_eq!(all_entries.valid, Some(false));
+		assert!(all_entries
+			.error
+			.as_ref()
+			.unwrap()
+			.contains("Invalid HGVS expression: expected one nucleotide at position 25"));
 	}
 }
@@ -0,0 +1,111 @@
+//! Default schemas the beacon declares for its entry types
+//!
+//! The `/entry_types` of a beacon declares the `defaultSchema` of each entry
+//! type: the schema of the records it returns when the query does not request
+//! another one. The verifier requests none, so the `meta.returnedSchemas` of
+//! the responses must name that schema for the entry type of the entity, and
+//! the records of a response returning no schemas are validated with the
+//! schema of the model that has its id rather than with the first one. The
+//! entry types without a declared default schema (or all of them, when
+//! `/entry_types` cannot be read) are only checked against the model.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use url::Url;
+
+use crate::client::Client;
+use crate::Json;
+
+/// Ids of the default schemas of `/entry_types` (e.g. `beacon-individual-v2.0.0`), by entry type
+pub fn parse(response: &Json) -> BTreeMap<String, String> {
+	response
+		.pointer("/response/entryTypes")
+		.and_then(Json::as_object)
+		.into_iter()
+		.flatten()
+		.filter_map(|(key, entry_type)| {
+			let id = entry_type.get("id").and_then(Json::as_str).unwrap_or(key);
+			let schema = entry_type.pointer("/defaultSchema/id").and_then(Json::as_str)?;
+			Some((id.to_string(), schema.to_string()))
+		})
+		.collect()
+}
+
+/// Fetches the default schemas declared by the beacon, none if its `/entry_types` cannot be read
+pub fn default_schemas(client: &Client, root_url: &Url) -> BTreeMap<String, String> {
+	let mut url = root_url.clone();
+	url.set_path(Path::new(root_url.path()).join("entry_types").to_str().unwrap_or(""));
+	match client.query("EntryTypes", &url) {
+		Ok(response) => parse(&response.json),
+		Err(e) => {
+			log::warn!("Could not read the default schemas of /entry_types: {}", e);
+			BTreeMap::new()
+		},
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use serde_json::json;
+	use wiremock::ResponseTemplate;
+
+	use crate::client::Client;
+	use crate::output::{BeaconOutput, Category, EndpointOutput};
+	use crate::testing::{Behavior, MockBeacon};
+	use crate::{entry_types, Json};
+
+	fn all_entries(output: &BeaconOutput) -> &EndpointOutput {
+		output.entities["Individual"]
+			.iter()
+			.find(|report| report.name == "Individual all entries" && report.category == Category::Model)
+			.unwrap()
+	}
+
+	fn declaring(beacon: &MockBeacon, schema: &str) {
+		let mut response = beacon.response("/entry_types");
+		response["response"]["entryTypes"] = json!({
+			"individual": { "id": "individual", "defaultSchema": { "id": schema, "name": "Individual" } },
+			"biosample": { "id": "biosample", "defaultSchema": { "name": "Biosample" } },
+		});
+		beacon.mount("/entry_types", ResponseTemplate::new(200).set_body_json(response));
+	}
+
+	#[test]
+	fn test_declared_schemas() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		declaring(&beacon, "beacon-individual-v2.0.0");
+		let schemas = entry_types::default_schemas(&Client::new(), &beacon.url());
+		assert_eq!(schemas.len(), 1);
+		assert_eq!(schemas["individual"], "beacon-individual-v2.0.0");
+		assert!(entry_types::parse(&Json::Null).is_empty());
+		let output = beacon.verify();
+		let report = all_entries(&output);
+		assert_eq!(report.valid, Some(true), "{:?}", report.error);
+
+		// The beacon returns another schema than the one it declares
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		declaring(&beacon, "beacon-individual-v2.1.0");
+		let output = beacon.verify();
+		let report = all_entries(&output);
+		assert_eq!(report.valid, Some(false));
+		assert_eq!(report.check.as_deref(), Some("model.declared-schema"));
+		let e = report.error.as_deref().unwrap();
+		assert!(
+			e.contains("'beacon-individual-v2.0.0'") && e.contains("'beacon-individual-v2.1.0'"),
+			"{}",
+			e
+		);
+
+		// Without returned schemas, the records are validated with the declared one
+		let mut response = beacon.response("/individuals");
+		response["meta"].as_object_mut().unwrap().remove("returnedSchemas");
+		beacon.mount("/individuals", ResponseTemplate::new(200).set_body_json(response));
+		let output = beacon.verify();
+		let report = all_entries(&output);
+		assert_eq!(report.check.as_deref(), Some("model.entity"));
+		// Mock individuals have no karyotypic sex, required by the declared schema
+		assert!(report.error.as_deref().unwrap().contains("karyotypicSex"));
+	}
+}
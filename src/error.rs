@@ -2,11 +2,17 @@ use std::path::PathBuf;
 
 use thiserror::Error;
 
+use crate::anonymize;
+
 #[derive(Error, Debug)]
 pub enum VerifierError {
-	#[error("Request error {0}")]
+	#[error("Request error {}", anonymize::request_error(.0))]
 	RequestError(#[from] reqwest::Error),
 
+	/// Request error whose url had an API key, masked in the message
+	#[error("Request error {message}")]
+	MaskedRequestError { message: String, timeout: bool },
+
 	#[error("IO Error")]
 	IoError(#[from] std::io::Error),
 
@@ -16,27 +22,173 @@ pub enum VerifierError {
 	#[error("Bad /info endpoint: {0}")]
 	BadInfo(String),
 
-	#[error("Endpoint {0} did not respond")]
+	#[error("Endpoint {} did not respond", anonymize::url(.0))]
 	UnresponsiveEndpoint(url::Url),
 
+	#[error("Endpoint {} was not found", anonymize::url(.0))]
+	MissingEndpoint(url::Url),
+
+	#[error(
+		"Not verified: the endpoint is advertised outside of the root url of the beacon, at {}",
+		anonymize::url(.0)
+	)]
+	OutsideRoot(url::Url),
+
 	#[error("Bad response format (JSON could not be parsed)")]
 	ResponseIsNotJson,
 
-	#[error("Unable to compile the schema (use the --model option)")]
-	BadSchema,
+	#[error("Unable to compile the schema: {0}")]
+	BadSchema(String),
+
+	/// Schemas of the specification that cannot be used, one per line
+	#[error("Broken schemas (use the --framework and --model options):\n{0}")]
+	BrokenSchemas(String),
 
 	#[error("Bad framework (use the --framework option)")]
 	BadFramework,
 
+	#[error("Bad location of the schemas: {0}")]
+	BadSchemaLocation(String),
+
+	#[error("The schemas of {0} are not in the cache (fill it with cache-schemas)")]
+	NotCached(String),
+
+	#[error("Untrusted schema refused: {0}")]
+	UntrustedSchema(String),
+
+	#[error("The {0} output format renders a single beacon, not the members of a network")]
+	SingleBeaconFormat(String),
+
+	#[error("Unknown model preset: {0}")]
+	UnknownModelPreset(String),
+
+	#[error("Bad configuration file: {0}")]
+	BadConfig(String),
+
+	#[error("Unable to refresh the access token: {0}")]
+	TokenRefresh(String),
+
 	#[error("Response does not match the schema: {0}")]
 	BadResponse(String),
 
+	#[error("No schema of the model matches the returned schemas: {0}")]
+	NoMatchingSchema(String),
+
+	#[error("The returned schema is not the default schema declared by the beacon: {0}")]
+	UndeclaredSchema(String),
+
+	#[error("Bad response shape: {0}")]
+	BadShape(String),
+
+	#[error("Bad response envelope: {0}")]
+	BadEnvelope(String),
+
+	#[error("Inconsistent ids across the endpoints: {0}")]
+	InconsistentIds(String),
+
+	#[error("Broken references to other entities: {0}")]
+	BrokenReferences(String),
+
+	#[error("Bad protocol: {0}")]
+	BadProtocol(String),
+
+	#[error("Bad TLS settings: {0}")]
+	BadTls(String),
+
+	#[error("Record not findable: {0}")]
+	NotFindable(String),
+
+	#[error("The counts of the response are not consistent: {0}")]
+	InconsistentCounts(String),
+
+	#[error("The results of the filtered query are not consistent with the filter: {0}")]
+	InconsistentFilter(String),
+
+	#[error("The query cannot match any record, yet it returned results: {0}")]
+	UnexpectedResults(String),
+
+	#[error("Latency budget exceeded: {0}")]
+	OverBudget(String),
+
+	#[error("Internal error of the verifier: {0}")]
+	Internal(String),
+
+	#[error("Not verified: {0}")]
+	NotVerified(String),
+
+	#[error("Skipped: {0}")]
+	Skipped(String),
+
+	#[error("{0}")]
+	Redacted(String),
+
+	#[error("Method {0} not allowed")]
+	MethodNotAllowed(String),
+
 	#[error("Unexpected HTTP status code")]
 	BadStatus,
 
-	// TODO: Use errors
-	// #[error("No ids were extracted from the main entity endpoint")]
-	// NoIds,
+	#[error("No ids were extracted from the main entity endpoint: {0}")]
+	NoIds(String),
+
+	#[error("No record has the id property '{0}'")]
+	MissingIdProperty(String),
+
+	#[error("The response has neither 'response.resultSets' nor 'response.collections'")]
+	MissingResultSets,
+
+	#[error("'response.resultSets' is not an array of result sets")]
+	MalformedResultSets,
+
+	#[error("The result set {0} of the response has no 'results'")]
+	MissingResults(usize),
+
+	#[error("The 'results' of the result set {0} of the response is not an array")]
+	MalformedResults(usize),
+
 	#[error("Error deserializing JSON: {0}")]
 	SerdeJsonError(#[from] serde_json::Error),
 }
+
+impl VerifierError {
+	/// Whether the request got no response in time
+	pub fn is_timeout(&self) -> bool {
+		match self {
+			Self::RequestError(e) => e.is_timeout(),
+			Self::MaskedRequestError { timeout, .. } => *timeout,
+			_ => false,
+		}
+	}
+
+	/// Message without any excerpt of the responses (values, paths, ids or urls)
+	pub fn public_message(&self) -> String {
+		match self {
+			Self::RequestError(_) | Self::MaskedRequestError { .. } => "Request error".into(),
+			Self::BadInfo(_) => "Bad /info endpoint".into(),
+			Self::UnresponsiveEndpoint(_) => "Endpoint did not respond".into(),
+			Self::MissingEndpoint(_) => "Endpoint was not found".into(),
+			Self::OutsideRoot(_) => "Not verified: the endpoint is advertised outside of the root url".into(),
+			Self::NoMatchingSchema(_) => "No schema of the model matches the returned schemas".into(),
+			Self::BadResponse(errors) => {
+				format!("Response does not match the schema ({} errors)", errors.lines().count())
+			},
+			Self::InconsistentIds(problems) => {
+				format!(
+					"Inconsistent ids across the endpoints ({} problems)",
+					problems.lines().count()
+				)
+			},
+			Self::BrokenReferences(problems) => {
+				format!(
+					"Broken references to other entities ({} problems)",
+					problems.lines().count()
+				)
+			},
+			Self::NoIds(_) => "No ids were extracted from the main entity endpoint".into(),
+			Self::NotFindable(_) => "Record not findable by a derived query".into(),
+			Self::SerdeJsonError(_) => "Error deserializing JSON".into(),
+			Self::Internal(_) => "Internal error of the verifier".into(),
+			_ => self.to_string(),
+		}
+	}
+}
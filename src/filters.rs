@@ -0,0 +1,271 @@
+//! Consistency of the results of a filtered query
+//!
+//! A beacon that ignores the filters it does not support answers the filtered
+//! queries with all its records, which looks like a valid response. With
+//! `--filters`, every entity is queried with and without one of the filtering
+//! terms the beacon advertises for its entry type (in the filtering terms url
+//! of the entity, or else in `/filtering_terms`). The filtered query must not
+//! count more results than the unfiltered one, and its records must carry the
+//! term. Since a filter also matches the descendants of its term, a record
+//! without the term only fails the check when no record has it, or when the
+//! filter left the results unchanged, and is otherwise a warning.
+
+use std::path::Path;
+
+use url::Url;
+
+use crate::client::Client;
+use crate::error::VerifierError;
+use crate::model::Entity;
+use crate::output::{Category, EndpointReport};
+use crate::{formats, utils, Json};
+
+/// Name of the check, after the name of the entity
+pub const CHECK_NAME: &str = "filter consistency";
+
+/// Whether `json` has the term `id` (as the id of an ontology term or as a value)
+fn carries(json: &Json, id: &str) -> bool {
+	match json {
+		Json::String(value) => value == id,
+		Json::Object(object) => object.values().any(|value| carries(value, id)),
+		Json::Array(values) => values.iter().any(|value| carries(value, id)),
+		_ => false,
+	}
+}
+
+/// Url of the filtering terms of `entity`, the ones of the beacon if it has none
+pub fn terms_url(root_url: &Url, entity: &Entity) -> Url {
+	match &entity.filtering_terms_url {
+		Some(filtering_terms_url) => utils::url_join(root_url, filtering_terms_url),
+		None => {
+			let mut terms_url = root_url.clone();
+			terms_url.set_path(
+				Path::new(root_url.path())
+					.join("filtering_terms")
+					.to_str()
+					.unwrap_or(""),
+			);
+			terms_url
+		},
+	}
+}
+
+/// Ontology terms of the filtering terms that apply to `entry_type` (the terms without scopes apply to all)
+pub fn ontology_terms<'a>(filtering_terms: &'a Json, entry_type: &str) -> Vec<&'a Json> {
+	filtering_terms
+		.pointer("/response/filteringTerms")
+		.and_then(Json::as_array)
+		.into_iter()
+		.flatten()
+		.filter(|term| {
+			term.get("type")
+				.and_then(Json::as_str)
+				.map_or(true, |t| t == "ontologyTerm")
+		})
+		.filter(|term| {
+			// `scopes` in Beacon v2, `scope` in its drafts
+			match term.get("scopes").or_else(|| term.get("scope")) {
+				Some(Json::Array(scopes)) => scopes.iter().any(|scope| scope.as_str() == Some(entry_type)),
+				Some(Json::String(scope)) => scope == entry_type,
+				_ => true,
+			}
+		})
+		.filter(|term| term.get("id").and_then(Json::as_str).map_or(false, formats::is_curie))
+		.collect()
+}
+
+/// First ontology term of the filtering terms that applies to `entry_type`
+pub fn term(filtering_terms: &Json, entry_type: &str) -> Option<String> {
+	ontology_terms(filtering_terms, entry_type)
+		.first()
+		.and_then(|term| term.get("id").and_then(Json::as_str))
+		.map(String::from)
+}
+
+/// Results of a query: the number of results it counts, if it tells, and the records it returns
+struct Results {
+	total: Option<u64>,
+	records: Vec<Json>,
+}
+
+fn results(client: &Client, entity: &Entity, url: &Url) -> Result<Results, VerifierError> {
+	let response = client.query(&entity.name, url)?;
+	let total = if utils::has_no_data(&response.json) {
+		Some(0)
+	}
+	else {
+		response
+			.json
+			.pointer("/responseSummary/numTotalResults")
+			.and_then(Json::as_u64)
+	};
+	Ok(Results {
+		total,
+		records: utils::records(&response.json).unwrap_or_default(),
+	})
+}
+
+/// Queries `entity` with and without a filtering term the beacon advertises, whose results must be consistent
+pub fn check(client: &Client, root_url: &Url, entity: &Entity) -> EndpointReport {
+	let url = utils::url_join(root_url, &entity.url);
+	let report = EndpointReport::new(&entity.name, &format!("{} {}", entity.name, CHECK_NAME), url.clone())
+		.category(Category::Framework)
+		.check("framework.filters.consistency");
+	let advertised = match client.query(&entity.name, &terms_url(root_url, entity)) {
+		Ok(response) => term(&response.json, &entity.entry_type),
+		Err(VerifierError::MissingEndpoint(_)) => None,
+		Err(e) => return report.null(e),
+	};
+	let term = match advertised {
+		Some(term) => term,
+		None => {
+			let reason = format!("the beacon advertises no filtering term for {}", entity.entry_type);
+			return report.skip(&reason, Json::Null);
+		},
+	};
+	let mut filtered_url = url.clone();
+	filtered_url.query_pairs_mut().append_pair("filters", &term);
+	let (unfiltered, filtered) = match (results(client, entity, &url), results(client, entity, &filtered_url)) {
+		(Ok(unfiltered), Ok(filtered)) => (unfiltered, filtered),
+		(Err(e), _) | (_, Err(e)) => return report.null(e),
+	};
+	let report = report.url(filtered_url);
+
+	if let (Some(unfiltered), Some(filtered)) = (unfiltered.total, filtered.total) {
+		if filtered > unfiltered {
+			return report.error(VerifierError::InconsistentFilter(format!(
+				"the filter {} counts {} results, more than the {} results without it",
+				term, filtered, unfiltered
+			)));
+		}
+	}
+	let without_term = filtered.records.iter().filter(|record| !carries(record, &term)).count();
+	if without_term == 0 {
+		return match (filtered.total, filtered.records.is_empty()) {
+			(None, true) => report.skip(
+				"the responses tell neither the number of results nor the records",
+				Json::Null,
+			),
+			_ => report.ok(None),
+		};
+	}
+	let unchanged = filtered.total.is_some() && filtered.total == unfiltered.total;
+	if without_term == filtered.records.len() || unchanged {
+		report.error(VerifierError::InconsistentFilter(format!(
+			"{} of the {} records returned with the filter {} do not carry it{}",
+			without_term,
+			filtered.records.len(),
+			term,
+			if unchanged {
+				", and the filter leaves the results unchanged"
+			}
+			else {
+				""
+			}
+		)))
+	}
+	else {
+		report
+			.warnings(vec![format!(
+				"{} of the {} records returned with the filter {} do not carry it (they may carry a descendant term)",
+				without_term,
+				filtered.records.len(),
+				term
+			)])
+			.ok(None)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use serde_json::json;
+	use wiremock::{Request, ResponseTemplate};
+
+	use crate::filters::{self, CHECK_NAME};
+	use crate::output::{BeaconOutput, Category, EndpointOutput};
+	use crate::testing::{Behavior, MockBeacon};
+	use crate::Json;
+
+	fn consistency<'a>(output: &'a BeaconOutput, entity: &str) -> &'a EndpointOutput {
+		output.entities[entity]
+			.iter()
+			.find(|report| report.name == format!("{} {}", entity, CHECK_NAME))
+			.unwrap()
+	}
+
+	fn filtering_terms() -> Json {
+		json!({
+			"meta": { "beaconId": "org.example.beacon", "apiVersion": "v2.0.0", "returnedSchemas": [] },
+			"response": { "filteringTerms": [
+				{ "id": "age", "type": "alphanumeric", "scopes": ["individual"] },
+				{ "id": "EFO:0009654", "type": "ontologyTerm", "scopes": ["biosample"] },
+				{ "id": "NCIT:C16576", "type": "ontologyTerm", "scopes": ["individual"] },
+			] },
+		})
+	}
+
+	/// Serves a female and a male individual, only the female one with the filter unless `ignore_filters`
+	fn serve(beacon: &MockBeacon, ignore_filters: bool) {
+		beacon.mount(
+			"/filtering_terms",
+			ResponseTemplate::new(200).set_body_json(filtering_terms()),
+		);
+		let mut individuals = beacon.response("/individuals");
+		individuals["response"]["resultSets"][0]["results"][1]["sex"] = json!({ "id": "NCIT:C20197", "label": "male" });
+		beacon.mount("/individuals", move |request: &Request| {
+			let mut response = individuals.clone();
+			if !ignore_filters && request.url.query_pairs().any(|(key, _)| key == "filters") {
+				response["responseSummary"]["numTotalResults"] = json!(1);
+				response["response"]["resultSets"][0]["results"]
+					.as_array_mut()
+					.unwrap()
+					.pop();
+			}
+			ResponseTemplate::new(200).set_body_json(response)
+		});
+	}
+
+	#[test]
+	fn test_filter_term() {
+		assert_eq!(
+			filters::term(&filtering_terms(), "individual").as_deref(),
+			Some("NCIT:C16576")
+		);
+		assert_eq!(filters::term(&filtering_terms(), "run"), None);
+		let unscoped = json!({ "response": { "filteringTerms": [{ "id": "HP:0000118" }] } });
+		assert_eq!(filters::term(&unscoped, "run").as_deref(), Some("HP:0000118"));
+		assert!(filters::carries(
+			&json!({ "sex": { "id": "NCIT:C16576" } }),
+			"NCIT:C16576"
+		));
+	}
+
+	#[test]
+	fn test_filter_consistency() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		serve(&beacon, false);
+		let output = beacon.beacon().filters().validate();
+		let individual = consistency(&output, "Individual");
+		assert_eq!(individual.category, Category::Framework);
+		assert_eq!(individual.valid, Some(true), "{:?}", individual.error);
+		assert!(individual.url.query().unwrap().contains("filters=NCIT%3AC16576"));
+		assert_eq!(consistency(&output, "Biosample").valid, Some(true));
+		// No term advertised for the datasets
+		assert!(consistency(&output, "Dataset").skipped);
+
+		// The beacon ignores the filter
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		serve(&beacon, true);
+		let output = beacon.beacon().filters().validate();
+		let e = consistency(&output, "Individual").error.clone().unwrap();
+		assert_eq!(
+			e,
+			"The results of the filtered query are not consistent with the filter: 1 of the 2 records returned with \
+			 the filter NCIT:C16576 do not carry it, and the filter leaves the results unchanged"
+		);
+		assert!(!beacon.verify().entities["Individual"]
+			.iter()
+			.any(|report| report.name.ends_with(CHECK_NAME)));
+	}
+}
@@ -0,0 +1,451 @@
+//! Responses recorded during a run, to replay them later
+//!
+//! With `--history`, the responses of the beacon are recorded along with the
+//! outcomes of the run. `--replay-from-history` runs the current checks on the
+//! responses of a past run instead of the beacon: the requests are sent to a
+//! local server, with the origin they were meant for, and the server answers
+//! them with the recorded responses (in turn, when the same request was sent
+//! several times). A request that was not recorded gets a 404. The bodies are
+//! kept as text, so a response that was not UTF-8 is replayed with replacement
+//! characters.
+//!
+//! `--dump-responses` writes the recorded responses of a run to a directory, as
+//! HTTP messages (status line, headers and body), one file per response named
+//! after the check of its url (its entity and name), with an `index.json` of
+//! the requests they answer.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use chrono::{DateTime, Utc};
+use reqwest::header::{CONNECTION, CONTENT_ENCODING, CONTENT_LENGTH, TRANSFER_ENCODING};
+use reqwest::{Method, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use url::{Position, Url};
+
+use crate::error::VerifierError;
+use crate::output::BeaconOutput;
+use crate::{anonymize, artifacts, utils};
+
+/// Header of the requests sent to the replay server, with the origin they were meant for
+pub const ORIGIN_HEADER: &str = "x-replay-origin";
+
+/// Response of the beacon to a request
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Fixture {
+	pub method: String,
+	pub url: String,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub request_body: Option<String>,
+	pub status: u16,
+	pub headers: Vec<(String, String)>,
+	pub body: String,
+	/// Start of the request (of its first attempt, when it was retried)
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub started: Option<DateTime<Utc>>,
+	/// Time to get the whole response, in milliseconds
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub elapsed_ms: Option<u64>,
+	/// HTTP version of the response (e.g. `HTTP/1.1`)
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub http_version: Option<String>,
+}
+
+/// Start of a request, to time its response
+#[derive(Debug, Clone, Copy)]
+pub struct Started {
+	at: DateTime<Utc>,
+	instant: Instant,
+}
+
+impl Started {
+	pub fn now() -> Self {
+		Self {
+			at: Utc::now(),
+			instant: Instant::now(),
+		}
+	}
+}
+
+/// Url of the fixture of a request to `url`, without its fragment
+fn key(url: &Url) -> String {
+	format!(
+		"{}{}",
+		url.origin().ascii_serialization(),
+		&url[Position::BeforePath..Position::AfterQuery]
+	)
+}
+
+/// Responses recorded so far, shared by the clones
+#[derive(Debug, Clone, Default)]
+pub struct Recorder {
+	fixtures: Arc<Mutex<Vec<Fixture>>>,
+}
+
+impl Recorder {
+	/// Records the response to a request, which is read and given back as it was
+	pub fn record(
+		&self,
+		method: &Method,
+		url: &Url,
+		body: Option<&[u8]>,
+		started: Started,
+		result: reqwest::Result<reqwest::blocking::Response>,
+	) -> reqwest::Result<reqwest::blocking::Response> {
+		let response = result?;
+		let (status, version, headers) = (response.status(), response.version(), response.headers().clone());
+		let bytes = response.bytes()?;
+		let fixture = Fixture {
+			method: method.to_string(),
+			url: key(url),
+			request_body: body.map(|body| String::from_utf8_lossy(body).into_owned()),
+			status: status.as_u16(),
+			headers: headers
+				.iter()
+				.map(|(name, value)| (name.to_string(), String::from_utf8_lossy(value.as_bytes()).into_owned()))
+				.collect(),
+			body: String::from_utf8_lossy(&bytes).into_owned(),
+			started: Some(started.at),
+			elapsed_ms: Some(started.instant.elapsed().as_millis() as u64),
+			http_version: Some(format!("{:?}", version)),
+		};
+		if let Ok(mut fixtures) = self.fixtures.lock() {
+			fixtures.push(fixture);
+		}
+		let mut replayed = http::Response::builder().status(status).version(version);
+		if let Some(replayed_headers) = replayed.headers_mut() {
+			*replayed_headers = headers;
+		}
+		Ok(replayed
+			.body(bytes.to_vec())
+			.expect("The recorded response is not valid")
+			.into())
+	}
+
+	pub fn fixtures(&self) -> Vec<Fixture> {
+		self.fixtures
+			.lock()
+			.map(|fixtures| fixtures.clone())
+			.unwrap_or_default()
+	}
+}
+
+pub fn save(path: &Path, fixtures: &[Fixture]) -> Result<(), VerifierError> {
+	if let Some(parent) = path.parent() {
+		std::fs::create_dir_all(parent)?;
+	}
+	artifacts::write_atomic(path, &serde_json::to_vec(fixtures)?)
+}
+
+pub fn load(path: &Path) -> Result<Vec<Fixture>, VerifierError> {
+	Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
+}
+
+/// Name of the file of a response to `fixture`, after the first check of its url in `output` (else after its path)
+fn dump_name(index: usize, fixture: &Fixture, output: &BeaconOutput) -> String {
+	let check = output
+		.entities
+		.iter()
+		.flat_map(|(entity_name, reports)| reports.iter().map(move |report| (entity_name, report)))
+		.find(|(_, report)| key(&report.url) == fixture.url);
+	let name = match check {
+		Some((entity_name, report)) => format!("{}-{}", utils::slug(entity_name), utils::slug(&report.name)),
+		None => utils::slug(
+			Url::parse(&fixture.url)
+				.map(|url| url.path().to_string())
+				.as_deref()
+				.unwrap_or(""),
+		),
+	};
+	format!("{:03}-{}-{}.http", index + 1, fixture.method.to_lowercase(), name)
+}
+
+/// Writes the responses to `dir` as HTTP messages, with an `index.json` of their requests
+pub fn dump(dir: &Path, fixtures: &[Fixture], output: &BeaconOutput) -> Result<(), VerifierError> {
+	std::fs::create_dir_all(dir)?;
+	let mut index = Vec::new();
+	for (i, fixture) in fixtures.iter().enumerate() {
+		let name = dump_name(i, fixture, output);
+		let reason = StatusCode::from_u16(fixture.status)
+			.ok()
+			.and_then(|status| status.canonical_reason())
+			.unwrap_or_default();
+		let mut message = format!("HTTP/1.1 {} {}\n", fixture.status, reason);
+		for (header, value) in &fixture.headers {
+			message.push_str(&format!("{}: {}\n", header, value));
+		}
+		message.push('\n');
+		message.push_str(&fixture.body);
+		artifacts::write_atomic(&dir.join(&name), message.as_bytes())?;
+		index.push(json!({
+			"file": name,
+			"method": fixture.method,
+			"url": fixture.url,
+			"request_body": fixture.request_body,
+			"status": fixture.status,
+		}));
+	}
+	artifacts::write_atomic(&dir.join("index.json"), &serde_json::to_vec_pretty(&index)?)
+}
+
+#[derive(Debug, Default)]
+struct Responses {
+	fixtures: Vec<Fixture>,
+	/// Responses already given to each request (method, url and body)
+	served: Mutex<BTreeMap<(String, String, String), usize>>,
+}
+
+impl Responses {
+	/// Next recorded response to the request, the last one again once they were all given
+	fn next(&self, method: &str, url: &str, body: &str) -> Option<&Fixture> {
+		let candidates = self
+			.fixtures
+			.iter()
+			.filter(|fixture| {
+				fixture.method == method && fixture.url == url && fixture.request_body.as_deref().unwrap_or("") == body
+			})
+			.collect::<Vec<_>>();
+		let mut served = self.served.lock().ok()?;
+		let count = served.entry((method.into(), url.into(), body.into())).or_default();
+		let fixture = candidates
+			.get((*count).min(candidates.len().saturating_sub(1)))
+			.copied();
+		*count += 1;
+		fixture
+	}
+}
+
+/// Local server answering the requests with the recorded responses, for as long as the verifier runs
+#[derive(Debug, Clone)]
+pub struct Replay {
+	url: Url,
+}
+
+impl Replay {
+	pub fn start(fixtures: Vec<Fixture>) -> Result<Self, VerifierError> {
+		let listener = TcpListener::bind("127.0.0.1:0")?;
+		let url = Url::parse(&format!("http://{}/", listener.local_addr()?))
+			.map_err(|e| VerifierError::Internal(e.to_string()))?;
+		let responses = Arc::new(Responses {
+			fixtures,
+			served: Mutex::default(),
+		});
+		log::debug!("Replaying {} responses on {}", responses.fixtures.len(), url);
+		std::thread::spawn(move || {
+			for stream in listener.incoming().flatten() {
+				let responses = Arc::clone(&responses);
+				std::thread::spawn(move || {
+					if let Err(e) = serve(&responses, stream) {
+						log::debug!("Replaying a response failed: {}", e);
+					}
+				});
+			}
+		});
+		Ok(Self { url })
+	}
+
+	/// Url of the request to `url` sent to the server instead
+	pub fn target(&self, url: &Url) -> Url {
+		let mut target = self.url.clone();
+		target.set_path(url.path());
+		target.set_query(url.query());
+		target
+	}
+}
+
+fn serve(responses: &Responses, mut stream: TcpStream) -> std::io::Result<()> {
+	let mut reader = BufReader::new(stream.try_clone()?);
+	let mut request_line = String::new();
+	reader.read_line(&mut request_line)?;
+	let mut parts = request_line.split_whitespace();
+	let (method, target) = (parts.next().unwrap_or_default(), parts.next().unwrap_or_default());
+	let (mut origin, mut length) = (String::new(), 0);
+	loop {
+		let mut line = String::new();
+		if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+			break;
+		}
+		if let Some((name, value)) = line.split_once(':') {
+			match name.trim().to_lowercase().as_str() {
+				"content-length" => length = value.trim().parse().unwrap_or_default(),
+				ORIGIN_HEADER => origin = value.trim().to_string(),
+				_ => (),
+			}
+		}
+	}
+	let mut body = vec![0; length];
+	reader.read_exact(&mut body)?;
+	let url = format!("{}{}", origin, target);
+	let (status, headers, body) = match responses.next(method, &url, &String::from_utf8_lossy(&body)) {
+		Some(fixture) => (fixture.status, fixture.headers.as_slice(), fixture.body.as_str()),
+		None => {
+			match Url::parse(&url) {
+				Ok(parsed) => log::warn!("No recorded response to {} {}", method, anonymize::url(&parsed)),
+				Err(_) => log::warn!("No recorded response to {} {}", method, url),
+			}
+			(StatusCode::NOT_FOUND.as_u16(), &[][..], "")
+		},
+	};
+	let reason = StatusCode::from_u16(status)
+		.ok()
+		.and_then(|status| status.canonical_reason())
+		.unwrap_or_default();
+	let mut head = format!("HTTP/1.1 {} {}\r\n", status, reason);
+	for (name, value) in headers {
+		let skipped = [CONNECTION, CONTENT_ENCODING, CONTENT_LENGTH, TRANSFER_ENCODING];
+		if !skipped.iter().any(|header| header.as_str().eq_ignore_ascii_case(name)) {
+			head.push_str(&format!("{}: {}\r\n", name, value));
+		}
+	}
+	head.push_str(&format!("content-length: {}\r\nconnection: close\r\n\r\n", body.len()));
+	stream.write_all(head.as_bytes())?;
+	stream.write_all(body.as_bytes())?;
+	stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+
+	use std::sync::atomic::{AtomicUsize, Ordering};
+
+	use reqwest::StatusCode;
+	use serde_json::json;
+	use url::Url;
+	use wiremock::{Request, ResponseTemplate};
+
+	use crate::beacon::Beacon;
+	use crate::client::Client;
+	use crate::fixtures::{self, Recorder, Replay};
+	use crate::output::EndpointOutput;
+	use crate::testing::{self, Behavior, MockBeacon};
+	use crate::Json;
+
+	#[test]
+	fn test_record_and_replay() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let individuals = ResponseTemplate::new(200).set_body_json(beacon.response("/individuals"));
+		beacon.mount_method("POST", "/individuals", individuals);
+		let recorder = Recorder::default();
+		let client = Client::new().record(recorder.clone());
+		let info_url = beacon.url().join("info").unwrap();
+		let info = client.query("info", &info_url).unwrap().json;
+		let body = json!({ "query": { "requestedGranularity": "count" } });
+		let individuals_url = beacon.url().join("individuals").unwrap();
+		client.post_query("individuals", &individuals_url, &body).unwrap();
+		let missing_url = beacon.url().join("missing").unwrap();
+		let (status, _) = client.get_raw(&missing_url).unwrap();
+		assert_eq!(status, StatusCode::NOT_FOUND);
+		let recorded = recorder.fixtures();
+		assert_eq!(recorded.len(), 3);
+		assert_eq!(recorded[1].method, "POST");
+		assert_eq!(recorded[1].request_body.as_deref(), Some(body.to_string().as_str()));
+
+		let dir = tempfile::tempdir().unwrap();
+		let path = dir.path().join("fixtures/run.json");
+		fixtures::save(&path, &recorded).unwrap();
+		assert_eq!(fixtures::load(&path).unwrap(), recorded);
+
+		// Without the beacon
+		let beacon_url = beacon.url();
+		drop(beacon);
+		let replay = Replay::start(recorded).unwrap();
+		let client = Client::new().replay(replay);
+		assert_eq!(client.query("info", &info_url).unwrap().json, info);
+		assert!(client.post_query("individuals", &individuals_url, &body).is_ok());
+		let other = json!({ "query": { "requestedGranularity": "record" } });
+		assert!(client.post_query("individuals", &individuals_url, &other).is_err());
+		let unknown = Url::parse("https://other.example.org/info").unwrap();
+		assert_eq!(client.get_raw(&unknown).unwrap().0, StatusCode::NOT_FOUND);
+		assert_eq!(
+			client.get_raw(&beacon_url.join("missing").unwrap()).unwrap().0,
+			StatusCode::NOT_FOUND
+		);
+	}
+
+	#[test]
+	fn test_replay_in_turn() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let calls = AtomicUsize::new(0);
+		beacon.mount("/calls", move |_: &Request| {
+			let call = calls.fetch_add(1, Ordering::SeqCst);
+			ResponseTemplate::new(200).set_body_json(json!({ "call": call }))
+		});
+		let recorder = Recorder::default();
+		let client = Client::new().record(recorder.clone());
+		let calls_url = beacon.url().join("calls").unwrap();
+		for _ in 0..2 {
+			client.query("calls", &calls_url).unwrap();
+		}
+		let client = Client::new().replay(Replay::start(recorder.fixtures()).unwrap());
+		let calls = (0..3)
+			.map(|_| client.query("calls", &calls_url).unwrap().json["call"].clone())
+			.collect::<Vec<_>>();
+		assert_eq!(calls, vec![json!(0), json!(1), json!(1)]);
+	}
+
+	#[test]
+	fn test_dump() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let recorder = Recorder::default();
+		let output = beacon
+			.beacon_with_client(Client::new().record(recorder.clone()))
+			.validate();
+		let dir = tempfile::tempdir().unwrap();
+		let recorded = recorder.fixtures();
+		fixtures::dump(dir.path(), &recorded, &output).unwrap();
+
+		let index: Json = serde_json::from_slice(&std::fs::read(dir.path().join("index.json")).unwrap()).unwrap();
+		let index = index.as_array().unwrap();
+		assert_eq!(index.len(), recorded.len());
+		let individuals = index
+			.iter()
+			.find(|response| response["url"].as_str().unwrap().ends_with("/individuals"))
+			.unwrap();
+		let file = individuals["file"].as_str().unwrap();
+		assert!(
+			file.ends_with("-get-individual-individual-all-entries.http"),
+			"{}",
+			file
+		);
+		let message = std::fs::read_to_string(dir.path().join(file)).unwrap();
+		assert!(message.starts_with("HTTP/1.1 200 OK\n"), "{}", message);
+		assert!(message.contains("content-type: application/json\n"));
+		let body = message.split_once("\n\n").unwrap().1;
+		assert_eq!(
+			serde_json::from_str::<Json>(body).unwrap(),
+			beacon.response("/individuals")
+		);
+	}
+
+	#[test]
+	fn test_replay_run() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let recorder = Recorder::default();
+		let output = beacon
+			.beacon_with_client(Client::new().record(recorder.clone()))
+			.validate();
+		let url = beacon.url();
+		drop(beacon);
+
+		let client = Client::new().replay(Replay::start(recorder.fixtures()).unwrap());
+		let replayed = Beacon::new(Some(testing::model()), testing::framework(), client, &url)
+			.unwrap()
+			.validate();
+		assert_eq!(replayed.name, output.name);
+		for (entity, reports) in &output.entities {
+			let outcomes = |reports: &[EndpointOutput]| {
+				reports
+					.iter()
+					.map(|report| (report.name.clone(), report.outcome()))
+					.collect::<Vec<_>>()
+			};
+			assert_eq!(outcomes(&replayed.entities[entity]), outcomes(reports), "{}", entity);
+		}
+	}
+}
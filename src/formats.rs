@@ -0,0 +1,244 @@
+use std::collections::BTreeSet;
+
+use jsonschema::CompilationOptions;
+use serde::Deserialize;
+
+use crate::{hgvs, identifiers};
+
+/// ISO 3166-1 alpha-2 country codes
+const ISO_COUNTRY_CODES: &str =
+	"AD AE AF AG AI AL AM AO AQ AR AS AT AU AW AX AZ BA BB BD BE BF BG BH BI BJ BL BM BN BO BQ BR BS BT BV BW BY BZ \
+	 CA CC CD CF CG CH CI CK CL CM CN CO CR CU CV CW CX CY CZ DE DJ DK DM DO DZ EC EE EG EH ER ES ET FI FJ FK FM FO \
+	 FR GA GB GD GE GF GG GH GI GL GM GN GP GQ GR GS GT GU GW GY HK HM HN HR HT HU ID IE IL IM IN IO IQ IR IS IT JE \
+	 JM JO JP KE KG KH KI KM KN KP KR KW KY KZ LA LB LC LI LK LR LS LT LU LV LY MA MC MD ME MF MG MH MK ML MM MN MO \
+	 MP MQ MR MS MT MU MV MW MX MY MZ NA NC NE NF NG NI NL NO NP NR NU NZ OM PA PE PF PG PH PK PL PM PN PR PS PT PW \
+	 PY QA RE RO RS RU RW SA SB SC SD SE SG SH SI SJ SK SL SM SN SO SR SS ST SV SX SY SZ TC TD TF TG TH TJ TK TL TM \
+	 TN TO TR TT TV TW TZ UA UG UM US UY UZ VA VC VE VG VI VN VU WF WS YE YT ZA ZM ZW";
+
+/// RefSeq accession prefixes (https://www.ncbi.nlm.nih.gov/books/NBK21091/table/ch18.T.refseq_accession_numbers_and_mole/)
+const REFSEQ_PREFIXES: [&str; 14] = [
+	"AC", "AP", "NC", "NG", "NM", "NP", "NR", "NT", "NW", "NZ", "XM", "XP", "XR", "WP",
+];
+
+/// Value formats enforced on top of the formats of the JSON Schema specification
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, clap::ArgEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Format {
+	/// Compact URI (e.g. `NCIT:C20197`)
+	Curie,
+	/// HGVS expression (e.g. `NC_000017.11:g.43057063G>A`)
+	Hgvs,
+	/// ISO 3166-1 alpha-2 country code (e.g. `ES`)
+	IsoCountryCode,
+	/// ISO 8601 duration (e.g. `P32Y6M`)
+	Iso8601Duration,
+	/// ORCID identifier (e.g. `0000-0002-1825-0097`)
+	Orcid,
+	/// RefSeq accession (e.g. `NC_000017.11`)
+	RefseqAccession,
+}
+
+impl Format {
+	pub const ALL: [Self; 6] = [
+		Self::Curie,
+		Self::Hgvs,
+		Self::IsoCountryCode,
+		Self::Iso8601Duration,
+		Self::Orcid,
+		Self::RefseqAccession,
+	];
+
+	/// Value of the `format` keyword in the schemas
+	pub fn keyword(self) -> &'static str {
+		match self {
+			Self::Curie => "curie",
+			Self::Hgvs => "hgvs",
+			Self::IsoCountryCode => "iso-country-code",
+			Self::Iso8601Duration => "iso8601-duration",
+			Self::Orcid => "orcid",
+			Self::RefseqAccession => "refseq-accession",
+		}
+	}
+
+	/// What the values of the format are
+	pub fn description(self) -> &'static str {
+		match self {
+			Self::Curie => "compact URI (e.g. `NCIT:C20197`)",
+			Self::Hgvs => "HGVS expression (e.g. `NC_000017.11:g.43057063G>A`)",
+			Self::IsoCountryCode => "ISO 3166-1 alpha-2 country code (e.g. `ES`)",
+			Self::Iso8601Duration => "ISO 8601 duration (e.g. `P32Y6M`)",
+			Self::Orcid => "ORCID identifier (e.g. `0000-0002-1825-0097`)",
+			Self::RefseqAccession => "RefSeq accession (e.g. `NC_000017.11`)",
+		}
+	}
+
+	pub fn validator(self) -> fn(&str) -> bool {
+		match self {
+			Self::Curie => is_curie,
+			Self::Hgvs => is_hgvs,
+			Self::IsoCountryCode => is_iso_country_code,
+			Self::Iso8601Duration => identifiers::is_iso8601_duration,
+			Self::Orcid => is_orcid,
+			Self::RefseqAccession => is_refseq_accession,
+		}
+	}
+}
+
+/// Registry of the enabled formats
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Formats {
+	enabled: BTreeSet<Format>,
+}
+
+impl Default for Formats {
+	fn default() -> Self {
+		Self {
+			enabled: Format::ALL.into_iter().collect(),
+		}
+	}
+}
+
+impl Formats {
+	pub fn disable(mut self, format: Format) -> Self {
+		self.enabled.remove(&format);
+		self
+	}
+
+	pub fn is_enabled(&self, format: Format) -> bool {
+		self.enabled.contains(&format)
+	}
+
+	/// Registers the enabled formats in the schema compilation options
+	pub fn register(&self, options: &mut CompilationOptions) {
+		for format in &self.enabled {
+			options.with_format(format.keyword(), format.validator());
+		}
+	}
+}
+
+pub fn is_curie(value: &str) -> bool {
+	match value.split_once(':') {
+		Some((prefix, reference)) => {
+			prefix.chars().next().map_or(false, |c| c.is_alphanumeric() || c == '_')
+				&& !reference.is_empty()
+				&& !value.chars().any(char::is_whitespace)
+		},
+		None => false,
+	}
+}
+
+pub fn is_refseq_accession(value: &str) -> bool {
+	let (prefix, number) = match value.split_once('_') {
+		Some(parts) => parts,
+		None => return false,
+	};
+	let (number, version) = match number.split_once('.') {
+		Some((number, version)) => (number, Some(version)),
+		None => (number, None),
+	};
+	let is_number = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+	REFSEQ_PREFIXES.contains(&prefix) && is_number(number) && version.map_or(true, is_number)
+}
+
+pub fn is_hgvs(value: &str) -> bool {
+	hgvs::parse(value).is_ok()
+}
+
+pub fn is_iso_country_code(value: &str) -> bool {
+	value.len() == 2 && ISO_COUNTRY_CODES.split_whitespace().any(|code| code == value)
+}
+
+pub fn is_orcid(value: &str) -> bool {
+	let id = value
+		.strip_prefix("https://orcid.org/")
+		.or_else(|| value.strip_prefix("orcid:"))
+		.unwrap_or(value);
+	let groups = id.split('-').collect::<Vec<_>>();
+	if groups.len() != 4 || groups.iter().any(|group| group.len() != 4) {
+		return false;
+	}
+	let chars = groups.concat().chars().collect::<Vec<_>>();
+	let (digits, check) = chars.split_at(15);
+	if !digits.iter().all(char::is_ascii_digit) {
+		return false;
+	}
+	// ISO 7064 11,2 checksum
+	let total = digits
+		.iter()
+		.fold(0, |total, digit| (total + digit.to_digit(10).unwrap_or(0)) * 2);
+	let result = (12 - total % 11) % 11;
+	let expected = if result == 10 {
+		'X'
+	}
+	else {
+		char::from_digit(result, 10).unwrap_or('?')
+	};
+	check[0] == expected
+}
+
+#[cfg(test)]
+mod tests {
+
+	use serde_json::json;
+
+	use crate::formats::{is_curie, is_hgvs, is_iso_country_code, is_orcid, is_refseq_accession, Format, Formats};
+	use crate::resolver::SchemaStore;
+	use crate::utils::compile_schema;
+
+	#[test]
+	fn test_curie() {
+		assert!(is_curie("NCIT:C20197"));
+		assert!(is_curie("HP:0000118"));
+		assert!(!is_curie("NCIT C20197"));
+		assert!(!is_curie("NCIT:"));
+		assert!(!is_curie(":C20197"));
+	}
+
+	#[test]
+	fn test_refseq_accession() {
+		assert!(is_refseq_accession("NC_000017.11"));
+		assert!(is_refseq_accession("NM_007294"));
+		assert!(!is_refseq_accession("NQ_000017.11"));
+		assert!(!is_refseq_accession("NC_000017."));
+		assert!(!is_refseq_accession("chr17"));
+	}
+
+	#[test]
+	fn test_hgvs() {
+		assert!(is_hgvs("NC_000017.11:g.43057063G>A"));
+		assert!(is_hgvs("NM_007294.4:c.5266dup"));
+		assert!(!is_hgvs("NC_000017.11:43057063G>A"));
+		assert!(!is_hgvs("17:g.43057063G>A x"));
+	}
+
+	#[test]
+	fn test_iso_country_code() {
+		assert!(is_iso_country_code("ES"));
+		assert!(!is_iso_country_code("es"));
+		assert!(!is_iso_country_code("XX"));
+		assert!(!is_iso_country_code("ESP"));
+	}
+
+	#[test]
+	fn test_orcid() {
+		assert!(is_orcid("0000-0002-1825-0097"));
+		assert!(is_orcid("https://orcid.org/0000-0001-5109-3700"));
+		assert!(is_orcid("0000-0002-1694-233X"));
+		assert!(!is_orcid("0000-0002-1825-0098"));
+		assert!(!is_orcid("0000-0002-1825"));
+	}
+
+	#[test]
+	fn test_toggle_formats() {
+		let schema = json!({ "type": "string", "format": "curie" });
+		let enabled = compile_schema(&schema, &Formats::default(), &SchemaStore::default()).unwrap();
+		assert!(!enabled.is_valid(&json!("not a curie")));
+		let disabled = compile_schema(
+			&schema,
+			&Formats::default().disable(Format::Curie),
+			&SchemaStore::default(),
+		)
+		.unwrap();
+		assert!(disabled.is_valid(&json!("not a curie")));
+	}
+}
@@ -2,11 +2,15 @@ use std::collections::BTreeMap;
 use std::ffi::OsStr;
 use std::fs::File;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-use git2::Repository;
+use jsonschema::JSONSchema;
 use url::Url;
 
+use crate::cache::SchemaCache;
 use crate::error::VerifierError;
+use crate::formats::Formats;
+use crate::resolver::SchemaStore;
 use crate::{utils, Json};
 
 #[derive(Debug, Clone)]
@@ -19,40 +23,39 @@ pub struct Framework {
 	pub boolean_json: Json,
 	pub count_json: Json,
 	pub collections_json: Json,
+	/// Schema of the error responses, if the framework has one
+	pub error_json: Option<Json>,
+	/// Commit of the repository the framework was loaded from
+	pub revision: Option<String>,
 	files: BTreeMap<PathBuf, Json>,
+	/// Documents the references of the schemas are resolved to
+	store: SchemaStore,
+}
+
+/// Compiled schemas of the responses of the endpoints of the framework
+#[derive(Clone)]
+pub struct EndpointSchemas {
+	pub info: Arc<JSONSchema>,
+	pub configuration: Arc<JSONSchema>,
+	pub map: Arc<JSONSchema>,
+	pub entry_types: Arc<JSONSchema>,
+}
+
+/// Compiled schemas of the responses of the entity endpoints
+#[derive(Clone)]
+pub struct ResponseSchemas {
+	pub boolean: Arc<JSONSchema>,
+	pub count: Arc<JSONSchema>,
+	pub result_sets: Arc<JSONSchema>,
+	pub collections: Arc<JSONSchema>,
+	pub error: Option<Arc<JSONSchema>>,
 }
 
 impl Framework {
-	pub fn load(location: &Url) -> Result<Self, VerifierError> {
+	pub fn load(location: &Url, cache: &SchemaCache) -> Result<Self, VerifierError> {
 		let dir = tempfile::tempdir().expect("Could not create temporary directory");
 
-		if location.scheme() == "file" {
-			log::debug!("COPYING {} to {:?}", location.path(), dir.path());
-			utils::copy_dir_recursively(location.path(), &dir).expect("Copy dir recursively failed");
-		}
-		else {
-			// Parse model repo URL
-			assert_eq!(
-				location.domain().unwrap_or(""),
-				"github.com",
-				"Only repos hosted on github.com are supported"
-			);
-			let mut url_iter = Path::new(location.path()).components().skip(1);
-			let owner = url_iter.next().unwrap().as_os_str().to_string_lossy().to_string();
-			let repo = url_iter.next().unwrap().as_os_str().to_string_lossy().to_string();
-			let path: PathBuf = url_iter.collect();
-
-			log::debug!("Downloading repo {} from {}", repo, owner);
-			log::debug!("Path inside repo = {:?}", path);
-
-			// Clone repo to tempdir
-			let repo_url = format!("https://github.com/{owner}/{repo}", owner = owner, repo = repo);
-			let full_git_dir = tempfile::tempdir().expect("Could not create temporary directory");
-			Repository::clone(&repo_url, full_git_dir.path()).expect("Unable to clone repository");
-
-			// Copy subfolder to the final tempdir
-			utils::copy_dir_recursively(full_git_dir.path().join(path), dir.path()).unwrap();
-		}
+		let revision = cache.fetch(location, dir.path())?;
 
 		let mut framework = Self {
 			info_json: Json::Null,
@@ -63,7 +66,10 @@ impl Framework {
 			boolean_json: Json::Null,
 			count_json: Json::Null,
 			collections_json: Json::Null,
+			error_json: None,
+			revision,
 			files: BTreeMap::new(),
+			store: SchemaStore::default(),
 		};
 
 		// Load files
@@ -73,12 +79,83 @@ impl Framework {
 			}
 		}
 
+		framework.store = SchemaStore::new(dir.path(), &mut framework.files, location, cache);
+
 		// Load configuration
 		framework.load_configuration(dir.path());
 
+		// The schemas are compiled again with the formats of the run, which do not change whether they compile
+		framework.endpoint_schemas(&Formats::default())?;
+		framework.response_schemas(&Formats::default())?;
+
 		Ok(framework)
 	}
 
+	pub fn store(&self) -> &SchemaStore {
+		&self.store
+	}
+
+	/// Compiled schemas of the responses of `/info`, `/configuration`, `/map` and `/entry_types`, failing with all
+	/// the schemas that do not compile
+	pub fn endpoint_schemas(&self, formats: &Formats) -> Result<EndpointSchemas, VerifierError> {
+		let compile = |schema: &Json| utils::compile_schema(schema, formats, &self.store);
+		match (
+			compile(&self.info_json),
+			compile(&self.configuration_json),
+			compile(&self.beacon_map_json),
+			compile(&self.entry_types_json),
+		) {
+			(Ok(info), Ok(configuration), Ok(map), Ok(entry_types)) => Ok(EndpointSchemas {
+				info,
+				configuration,
+				map,
+				entry_types,
+			}),
+			(info, configuration, map, entry_types) => {
+				let broken = [info.err(), configuration.err(), map.err(), entry_types.err()]
+					.into_iter()
+					.flatten()
+					.map(|e| e.to_string())
+					.collect::<Vec<_>>();
+				Err(VerifierError::BrokenSchemas(broken.join("\n")))
+			},
+		}
+	}
+
+	/// Compiled schemas of the responses, failing with all the schemas that do not compile
+	pub fn response_schemas(&self, formats: &Formats) -> Result<ResponseSchemas, VerifierError> {
+		let compile = |schema: &Json| utils::compile_schema(schema, formats, &self.store);
+		match (
+			compile(&self.boolean_json),
+			compile(&self.count_json),
+			compile(&self.result_sets_json),
+			compile(&self.collections_json),
+			self.error_json.as_ref().map(compile).transpose(),
+		) {
+			(Ok(boolean), Ok(count), Ok(result_sets), Ok(collections), Ok(error)) => Ok(ResponseSchemas {
+				boolean,
+				count,
+				result_sets,
+				collections,
+				error,
+			}),
+			(boolean, count, result_sets, collections, error) => {
+				let broken = [
+					boolean.err(),
+					count.err(),
+					result_sets.err(),
+					collections.err(),
+					error.err(),
+				]
+				.into_iter()
+				.flatten()
+				.map(|e| e.to_string())
+				.collect::<Vec<_>>();
+				Err(VerifierError::BrokenSchemas(broken.join("\n")))
+			},
+		}
+	}
+
 	fn add(&mut self, path: &Path) -> Result<(), VerifierError> {
 		log::debug!("Adding JSON file: {:?}", path);
 		let file = File::open(path).unwrap();
@@ -128,5 +205,10 @@ impl Framework {
 			.get(&base_path.join("responses").join("beaconCollectionsResponse.json"))
 			.expect("beaconCollectionsResponse.json not found")
 			.clone();
+		// Optional, the frameworks written before it have no schema of the error responses
+		self.error_json = self
+			.files
+			.get(&base_path.join("responses").join("beaconErrorResponse.json"))
+			.cloned();
 	}
 }
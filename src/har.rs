@@ -0,0 +1,144 @@
+//! Traffic of a run in the HTTP Archive (HAR 1.2) format
+//!
+//! With `--har`, the responses of the beacon are recorded (the way of
+//! `--history`) and written at the end of the run as the entries of a HAR log,
+//! which the network panel of the browsers and other tools open. The headers of
+//! the requests are left out but for the content type of their bodies, so the
+//! credentials of the beacon are not exported, and only the final response of
+//! a retried request is kept, its time including the retries.
+
+use reqwest::StatusCode;
+use serde_json::json;
+use url::Url;
+
+use crate::fixtures::Fixture;
+use crate::Json;
+
+/// Name and value objects of HAR
+fn pairs<'a>(pairs: impl Iterator<Item = (&'a str, &'a str)>) -> Vec<Json> {
+	pairs
+		.map(|(name, value)| json!({ "name": name, "value": value }))
+		.collect()
+}
+
+fn header<'a>(fixture: &'a Fixture, name: &str) -> Option<&'a str> {
+	fixture
+		.headers
+		.iter()
+		.find(|(header, _)| header.eq_ignore_ascii_case(name))
+		.map(|(_, value)| value.as_str())
+}
+
+fn entry(fixture: &Fixture) -> Json {
+	let url = Url::parse(&fixture.url).ok();
+	let query: Vec<(String, String)> = url
+		.as_ref()
+		.map(|url| {
+			url.query_pairs()
+				.map(|(name, value)| (name.to_string(), value.to_string()))
+				.collect()
+		})
+		.unwrap_or_default();
+	let http_version = fixture.http_version.as_deref().unwrap_or("HTTP/1.1");
+	let elapsed_ms = fixture.elapsed_ms.unwrap_or_default();
+	let mut request = json!({
+		"method": fixture.method,
+		"url": fixture.url,
+		"httpVersion": http_version,
+		"cookies": [],
+		"headers": [],
+		"queryString": pairs(query.iter().map(|(name, value)| (name.as_str(), value.as_str()))),
+		"headersSize": -1,
+		"bodySize": fixture.request_body.as_ref().map_or(0, String::len),
+	});
+	if let Some(body) = &fixture.request_body {
+		request["headers"] = json!(pairs(std::iter::once(("content-type", "application/json"))));
+		request["postData"] = json!({ "mimeType": "application/json", "text": body });
+	}
+	let status_text = StatusCode::from_u16(fixture.status)
+		.ok()
+		.and_then(|status| status.canonical_reason())
+		.unwrap_or_default();
+	json!({
+		"startedDateTime": fixture.started.map(|started| started.to_rfc3339()),
+		"time": elapsed_ms,
+		"request": request,
+		"response": {
+			"status": fixture.status,
+			"statusText": status_text,
+			"httpVersion": http_version,
+			"cookies": [],
+			"headers": pairs(fixture.headers.iter().map(|(name, value)| (name.as_str(), value.as_str()))),
+			"content": {
+				"size": fixture.body.len(),
+				"mimeType": header(fixture, "content-type").unwrap_or_default(),
+				"text": fixture.body,
+			},
+			"redirectURL": header(fixture, "location").unwrap_or_default(),
+			"headersSize": -1,
+			"bodySize": fixture.body.len(),
+		},
+		"cache": {},
+		"timings": { "send": 0, "wait": elapsed_ms, "receive": 0 },
+	})
+}
+
+/// HAR log of the responses, in the order of the requests
+pub fn har(fixtures: &[Fixture]) -> Json {
+	json!({
+		"log": {
+			"version": "1.2",
+			"creator": { "name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION") },
+			"entries": fixtures.iter().map(entry).collect::<Vec<_>>(),
+		}
+	})
+}
+
+#[cfg(test)]
+mod tests {
+
+	use serde_json::json;
+	use wiremock::ResponseTemplate;
+
+	use crate::client::Client;
+	use crate::fixtures::Recorder;
+	use crate::testing::{Behavior, MockBeacon};
+	use crate::{har, Json};
+
+	#[test]
+	fn test_har() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let individuals = ResponseTemplate::new(200).set_body_json(beacon.response("/individuals"));
+		beacon.mount_method("POST", "/individuals", individuals);
+		let recorder = Recorder::default();
+		let client = Client::new().bearer_token("s3cr3t").record(recorder.clone());
+		let info_url = beacon.url().join("info?requestedSchema=x").unwrap();
+		client.query("info", &info_url).unwrap();
+		let body = json!({ "query": { "requestedGranularity": "count" } });
+		client
+			.post_query("individuals", &beacon.url().join("individuals").unwrap(), &body)
+			.unwrap();
+
+		let log = har::har(&recorder.fixtures());
+		assert_eq!(log["log"]["version"], "1.2");
+		let entries = log["log"]["entries"].as_array().unwrap();
+		assert_eq!(entries.len(), 2);
+		let info = &entries[0];
+		assert!(info["startedDateTime"].is_string());
+		assert_eq!(info["request"]["method"], "GET");
+		assert_eq!(
+			info["request"]["queryString"],
+			json!([{ "name": "requestedSchema", "value": "x" }])
+		);
+		assert_eq!(info["response"]["status"], 200);
+		assert_eq!(info["response"]["statusText"], "OK");
+		assert_eq!(info["response"]["content"]["mimeType"], "application/json");
+		let text = info["response"]["content"]["text"].as_str().unwrap();
+		assert_eq!(serde_json::from_str::<Json>(text).unwrap(), beacon.response("/info"));
+
+		let individuals = &entries[1];
+		assert_eq!(individuals["request"]["method"], "POST");
+		assert_eq!(individuals["request"]["postData"]["text"], body.to_string());
+		assert!(!log.to_string().contains("s3cr3t"));
+	}
+}
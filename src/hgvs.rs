@@ -0,0 +1,404 @@
+//! Syntactic validation of HGVS expressions (https://varnomen.hgvs.org/)
+//!
+//! Only the syntax is verified: the reference sequences are not fetched, so
+//! an expression can be well formed and still describe an impossible change.
+
+use thiserror::Error;
+
+use crate::Json;
+
+/// Three-letter amino acid codes (and `Ter` for the stop codon)
+const AMINO_ACIDS: [&str; 24] = [
+	"Ala", "Arg", "Asn", "Asp", "Cys", "Gln", "Glu", "Gly", "His", "Ile", "Leu", "Lys", "Met", "Phe", "Pro", "Ser",
+	"Thr", "Trp", "Tyr", "Val", "Sec", "Pyl", "Xaa", "Ter",
+];
+
+/// Fields of the genomic variants holding HGVS expressions
+const HGVS_FIELDS: [&str; 3] = [
+	"/identifiers/genomicHGVSId",
+	"/identifiers/transcriptHGVSIds",
+	"/identifiers/proteinHGVSIds",
+];
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("{message} at position {position}")]
+pub struct HgvsError {
+	/// Position (in characters, starting at 0) where the expression stops being valid
+	pub position: usize,
+	pub message: String,
+}
+
+pub fn parse(expression: &str) -> Result<(), HgvsError> {
+	Parser {
+		chars: expression.chars().collect(),
+		pos: 0,
+	}
+	.expression()
+}
+
+/// Checks the HGVS fields of a genomic variant, returning the path of each invalid expression
+pub fn check_variant(variant: &Json) -> Vec<(String, HgvsError)> {
+	let mut errors = Vec::new();
+	for field in HGVS_FIELDS {
+		match variant.pointer(field) {
+			Some(Json::String(expression)) => {
+				if let Err(e) = parse(expression) {
+					errors.push((field.to_string(), e));
+				}
+			},
+			Some(Json::Array(expressions)) => {
+				for expression in expressions.iter().filter_map(Json::as_str) {
+					if let Err(e) = parse(expression) {
+						errors.push((format!("{}/*", field), e));
+					}
+				}
+			},
+			_ => (),
+		}
+	}
+	errors
+}
+
+struct Parser {
+	chars: Vec<char>,
+	pos: usize,
+}
+
+impl Parser {
+	fn error<T>(&self, message: &str) -> Result<T, HgvsError> {
+		Err(HgvsError {
+			position: self.pos,
+			message: message.to_string(),
+		})
+	}
+
+	fn peek(&self) -> Option<char> {
+		self.chars.get(self.pos).copied()
+	}
+
+	fn eat(&mut self, c: char) -> bool {
+		if self.peek() == Some(c) {
+			self.pos += 1;
+			true
+		}
+		else {
+			false
+		}
+	}
+
+	fn eat_str(&mut self, s: &str) -> bool {
+		let len = s.chars().count();
+		if self.chars.len() >= self.pos + len && self.chars[self.pos..self.pos + len].iter().copied().eq(s.chars()) {
+			self.pos += len;
+			true
+		}
+		else {
+			false
+		}
+	}
+
+	fn expect(&mut self, c: char) -> Result<(), HgvsError> {
+		if self.eat(c) {
+			Ok(())
+		}
+		else {
+			self.error(&format!("expected '{}'", c))
+		}
+	}
+
+	fn digits(&mut self) -> Result<(), HgvsError> {
+		let start = self.pos;
+		while self.peek().map_or(false, |c| c.is_ascii_digit()) {
+			self.pos += 1;
+		}
+		if self.pos == start {
+			self.error("expected a number")
+		}
+		else {
+			Ok(())
+		}
+	}
+
+	fn while_matches(&mut self, f: impl Fn(char) -> bool) -> usize {
+		let start = self.pos;
+		while self.peek().map_or(false, &f) {
+			self.pos += 1;
+		}
+		self.pos - start
+	}
+
+	fn expression(&mut self) -> Result<(), HgvsError> {
+		self.reference()?;
+		self.expect(':')?;
+		let kind = match self.peek() {
+			Some(kind @ ('g' | 'c' | 'n' | 'm' | 'o' | 'r' | 'p')) => kind,
+			_ => return self.error("expected a coordinate type (g, c, n, m, o, r or p)"),
+		};
+		self.pos += 1;
+		self.expect('.')?;
+		if kind == 'p' {
+			self.protein_description()?;
+		}
+		else {
+			self.nucleotide_description(kind)?;
+		}
+		if self.pos < self.chars.len() {
+			return self.error("unexpected character");
+		}
+		Ok(())
+	}
+
+	fn reference(&mut self) -> Result<(), HgvsError> {
+		if !self.peek().map_or(false, |c| c.is_ascii_alphanumeric()) {
+			return self.error("expected a reference sequence");
+		}
+		self.while_matches(|c| c.is_ascii_alphanumeric() || c == '_');
+		if self.eat('.') {
+			self.digits()?;
+		}
+		if self.eat('(') {
+			if self.while_matches(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') == 0 {
+				return self.error("expected a gene symbol");
+			}
+			self.expect(')')?;
+		}
+		Ok(())
+	}
+
+	fn nucleotide_description(&mut self, kind: char) -> Result<(), HgvsError> {
+		if self.eat('[') {
+			loop {
+				self.nucleotide_variant(kind)?;
+				if !self.eat(';') {
+					break;
+				}
+			}
+			return self.expect(']');
+		}
+		self.nucleotide_variant(kind)
+	}
+
+	fn nucleotide_variant(&mut self, kind: char) -> Result<(), HgvsError> {
+		if self.eat('=') {
+			return Ok(());
+		}
+		let range = self.location(kind)?;
+		self.nucleotide_edit(kind, range)
+	}
+
+	/// Parses a position or a range of positions, returning whether it was a range
+	fn location(&mut self, kind: char) -> Result<bool, HgvsError> {
+		self.position(kind)?;
+		if self.eat('_') {
+			self.position(kind)?;
+			return Ok(true);
+		}
+		Ok(false)
+	}
+
+	fn position(&mut self, kind: char) -> Result<(), HgvsError> {
+		// Uncertain positions, e.g. (123_125)
+		if self.eat('(') {
+			self.position(kind)?;
+			if self.eat('_') {
+				self.position(kind)?;
+			}
+			return self.expect(')');
+		}
+		if self.eat('?') {
+			return Ok(());
+		}
+		let transcript = matches!(kind, 'c' | 'n' | 'r');
+		if transcript && !self.eat('*') {
+			self.eat('-');
+		}
+		self.digits()?;
+		// Intronic offsets, e.g. 123+4
+		if transcript && (self.eat('+') || self.eat('-')) && !self.eat('?') {
+			self.digits()?;
+		}
+		Ok(())
+	}
+
+	fn nucleotide_sequence(&mut self, kind: char) -> usize {
+		if kind == 'r' {
+			self.while_matches(|c| matches!(c, 'a' | 'c' | 'g' | 'u' | 'n'))
+		}
+		else {
+			self.while_matches(|c| matches!(c, 'A' | 'C' | 'G' | 'T' | 'N'))
+		}
+	}
+
+	fn nucleotide_edit(&mut self, kind: char, range: bool) -> Result<(), HgvsError> {
+		if self.eat_str("delins") {
+			if self.nucleotide_sequence(kind) == 0 {
+				return self.error("expected the inserted sequence");
+			}
+			return Ok(());
+		}
+		if self.eat_str("del") || self.eat_str("dup") {
+			self.nucleotide_sequence(kind);
+			return Ok(());
+		}
+		if self.eat_str("ins") {
+			if !range {
+				return self.error("an insertion needs a range of two flanking positions");
+			}
+			if self.nucleotide_sequence(kind) == 0 && self.digits().is_err() {
+				return self.error("expected the inserted sequence");
+			}
+			return Ok(());
+		}
+		if self.eat_str("inv") || self.eat('=') {
+			return Ok(());
+		}
+		let sequence = self.nucleotide_sequence(kind);
+		if self.eat('>') {
+			if sequence != 1 {
+				return self.error("a substitution replaces exactly one nucleotide");
+			}
+			if self.nucleotide_sequence(kind) != 1 {
+				return self.error("expected one nucleotide");
+			}
+			return Ok(());
+		}
+		// Repeated sequences, e.g. 123_125CAG[23]
+		if self.eat('[') {
+			self.digits()?;
+			return self.expect(']');
+		}
+		self.error("expected an edit (>, del, dup, ins, delins, inv, = or [n])")
+	}
+
+	fn amino_acid(&mut self) -> bool {
+		for amino_acid in AMINO_ACIDS {
+			if self.eat_str(amino_acid) {
+				return true;
+			}
+		}
+		if self.peek().map_or(false, |c| c.is_ascii_uppercase() || c == '*') {
+			self.pos += 1;
+			return true;
+		}
+		false
+	}
+
+	fn amino_acid_position(&mut self) -> Result<(), HgvsError> {
+		if !self.amino_acid() {
+			return self.error("expected an amino acid");
+		}
+		self.digits()
+	}
+
+	fn protein_description(&mut self) -> Result<(), HgvsError> {
+		if self.eat('=') || self.eat('?') || self.eat('0') {
+			return Ok(());
+		}
+		// Predicted consequences, e.g. p.(Arg97Gly)
+		if self.eat('(') {
+			self.protein_variant()?;
+			return self.expect(')');
+		}
+		self.protein_variant()
+	}
+
+	fn protein_variant(&mut self) -> Result<(), HgvsError> {
+		self.amino_acid_position()?;
+		let range = self.eat('_');
+		if range {
+			self.amino_acid_position()?;
+		}
+		if self.eat('=') || self.eat('?') || self.eat_str("dup") {
+			return Ok(());
+		}
+		if self.eat_str("delins") || self.eat_str("ins") {
+			if !self.amino_acid() {
+				return self.error("expected the inserted amino acids");
+			}
+			while self.amino_acid() {}
+			return Ok(());
+		}
+		if self.eat_str("del") {
+			return Ok(());
+		}
+		// Substitutions (and frame shifts when followed by fs)
+		let substitution = self.amino_acid();
+		if self.eat_str("fs") {
+			if (self.eat_str("Ter") || self.eat('*')) && !self.eat('?') {
+				self.digits()?;
+			}
+			return Ok(());
+		}
+		if self.eat_str("ext") {
+			self.while_matches(|c| c.is_ascii_alphanumeric() || matches!(c, '*' | '-' | '?'));
+			return Ok(());
+		}
+		if substitution && !range {
+			return Ok(());
+		}
+		self.error("expected an amino acid change (substitution, del, dup, ins, delins, fs or ext)")
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use serde_json::json;
+
+	use crate::hgvs::{check_variant, parse};
+
+	#[test]
+	fn test_valid_expressions() {
+		for expression in [
+			"NC_000017.11:g.43057063G>A",
+			"NC_000017.11:g.43057063_43057065del",
+			"NC_000017.11:g.43057063_43057064insTT",
+			"NC_000017.11:g.43057063delinsAG",
+			"NC_000017.11:g.[43057063G>A;43057070dup]",
+			"NC_000023.11:g.(31060227_31100351)_(33274278_33417151)dup",
+			"NM_007294.4:c.5266dup",
+			"NM_004006.2(DMD):c.357+1G>A",
+			"NM_004006.2:c.-14G>C",
+			"NM_004006.2:c.*32_*33insA",
+			"NM_004006.2:r.123c>g",
+			"NP_003997.1:p.Trp24Ter",
+			"NP_003997.1:p.(Arg97Gly)",
+			"NP_003997.1:p.R97G",
+			"NP_003997.1:p.Arg97ProfsTer23",
+			"NP_003997.1:p.Lys23_Val25del",
+			"NP_003997.1:p.His4_Gln5insAla",
+			"NP_003997.1:p.Cys28delinsTrpVal",
+			"NP_003997.1:p.Gly56=",
+			"NP_003997.1:p.?",
+		] {
+			assert_eq!(parse(expression), Ok(()), "{}", expression);
+		}
+	}
+
+	#[test]
+	fn test_error_positions() {
+		let e = parse("NC_000017.11:43057063G>A").unwrap_err();
+		assert_eq!(e.position, 13);
+		let e = parse("NC_000017.11:g.43057063G>").unwrap_err();
+		assert_eq!(e.to_string(), "expected one nucleotide at position 25");
+		let e = parse("NC_000017.11:g.43057063insT").unwrap_err();
+		assert_eq!(e.position, 26);
+		let e = parse("NC_000017.11:g.43057063G>A extra").unwrap_err();
+		assert_eq!(e.to_string(), "unexpected character at position 26");
+		let e = parse("chr17 43057063G>A").unwrap_err();
+		assert_eq!(e.position, 5);
+	}
+
+	#[test]
+	fn test_check_variant() {
+		let variant = json!({
+			"identifiers": {
+				"genomicHGVSId": "NC_000017.11:g.43057063G>A",
+				"proteinHGVSIds": ["NP_009225.1:p.Glu1038Gly", "NP_009225.1:Glu1038Gly"],
+			},
+		});
+		let errors = check_variant(&variant);
+		assert_eq!(errors.len(), 1);
+		assert_eq!(errors[0].0, "/identifiers/proteinHGVSIds/*");
+	}
+}
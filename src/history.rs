@@ -0,0 +1,286 @@
+//! History of the outcomes of the checks across runs, to tell flaky checks apart
+//!
+//! A check is flaky when it went from success to failure (or back) at least
+//! twice over the recent runs: transient issues of the beacon infrastructure
+//! rather than a change of its compliance. The responses of each run are kept
+//! next to the history (in a `.fixtures` directory), to replay them through the
+//! checks of a later version of the verifier.
+
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+use crate::artifacts;
+use crate::error::VerifierError;
+use crate::maintenance::Status;
+use crate::output::{BeaconOutput, Category, Outcome};
+use crate::usage::ResourceUsage;
+
+/// Runs kept in the history file
+const MAX_RUNS: usize = 20;
+
+/// Recent runs looked at to find the flaky checks
+const FLAKY_WINDOW: usize = 5;
+
+/// Changes between success and failure that make a check flaky
+const FLAKY_CHANGES: usize = 2;
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct CheckOutcome {
+	pub entity: String,
+	pub check: String,
+	pub category: Category,
+	pub outcome: Outcome,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunRecord {
+	pub at: NaiveDateTime,
+	#[serde(default)]
+	pub status: Option<Status>,
+	pub outcomes: Vec<CheckOutcome>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub resource_usage: Option<ResourceUsage>,
+}
+
+fn outcomes(output: &BeaconOutput) -> Vec<CheckOutcome> {
+	output
+		.entities
+		.iter()
+		.flat_map(|(entity_name, reports)| {
+			reports.iter().map(move |report| CheckOutcome {
+				entity: entity_name.clone(),
+				check: report.key(),
+				category: report.category,
+				outcome: report.outcome(),
+			})
+		})
+		.collect()
+}
+
+impl RunRecord {
+	/// Id of the run, after its start (e.g. `20220301T120000`)
+	pub fn id(&self) -> String {
+		self.at.format("%Y%m%dT%H%M%S").to_string()
+	}
+
+	/// Outcomes of `output` that are failures or errors, when the checks had succeeded in the run or did not exist
+	pub fn caught(&self, output: &BeaconOutput) -> Vec<CheckOutcome> {
+		outcomes(output)
+			.into_iter()
+			.filter(|outcome| !outcome.outcome.is_success())
+			.filter(|outcome| {
+				!self.outcomes.iter().any(|o| {
+					o.entity == outcome.entity
+						&& o.check == outcome.check
+						&& o.category == outcome.category
+						&& !o.outcome.is_success()
+				})
+			})
+			.collect()
+	}
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct History {
+	/// Oldest run first
+	pub runs: Vec<RunRecord>,
+}
+
+impl History {
+	/// Loads the history, empty if the file does not exist yet
+	pub fn load(path: &Path) -> Result<Self, VerifierError> {
+		if !path.exists() {
+			return Ok(Self::default());
+		}
+		let file = File::open(path)?;
+		Ok(serde_json::from_reader(file)?)
+	}
+
+	/// Saves the history, removing the responses of the runs it no longer has
+	pub fn save(&self, path: &Path) -> Result<(), VerifierError> {
+		artifacts::write_atomic(path, &serde_json::to_vec_pretty(self)?)?;
+		let dir = fixtures_dir(path);
+		if dir.is_dir() {
+			let ids = self.runs.iter().map(RunRecord::id).collect::<BTreeSet<_>>();
+			for entry in std::fs::read_dir(dir)? {
+				let path = entry?.path();
+				let id = path
+					.file_stem()
+					.map(|stem| stem.to_string_lossy().to_string())
+					.unwrap_or_default();
+				if !ids.contains(&id) {
+					log::debug!("Removing the responses of the run {}", id);
+					std::fs::remove_file(path)?;
+				}
+			}
+		}
+		Ok(())
+	}
+
+	/// Run with the id `id`
+	pub fn run(&self, id: &str) -> Option<&RunRecord> {
+		self.runs.iter().find(|run| run.id() == id)
+	}
+
+	/// Adds the outcomes of a run, forgetting the oldest runs
+	pub fn record(&mut self, output: &BeaconOutput) {
+		let outcomes = outcomes(output);
+		if let (Some(previous), Some(status)) = (self.runs.last().and_then(|run| run.status), output.status) {
+			if previous != status {
+				log::info!("The beacon went from {} to {}", previous, status);
+			}
+		}
+		self.runs.push(RunRecord {
+			at: output.last_updated,
+			status: output.status,
+			outcomes,
+			resource_usage: output.resource_usage.clone(),
+		});
+		let excess = self.runs.len().saturating_sub(MAX_RUNS);
+		self.runs.drain(..excess);
+	}
+
+	/// Checks (entity, check and category) that alternated between success and failure in the recent runs
+	/// (outside of the maintenance windows)
+	pub fn flaky(&self) -> BTreeSet<(String, String, Category)> {
+		let runs = self
+			.runs
+			.iter()
+			.filter(|run| run.status != Some(Status::Maintenance))
+			.collect::<Vec<_>>();
+		let recent = &runs[runs.len().saturating_sub(FLAKY_WINDOW)..];
+		let checks = recent
+			.iter()
+			.flat_map(|run| run.outcomes.iter())
+			.map(|outcome| (outcome.entity.clone(), outcome.check.clone(), outcome.category))
+			.collect::<BTreeSet<_>>();
+		checks
+			.into_iter()
+			.filter(|(entity, check, category)| {
+				let successes = recent
+					.iter()
+					.filter_map(|run| {
+						run.outcomes
+							.iter()
+							.find(|o| o.entity == *entity && o.check == *check && o.category == *category)
+					})
+					.map(|o| o.outcome.is_success())
+					.collect::<Vec<_>>();
+				successes.windows(2).filter(|pair| pair[0] != pair[1]).count() >= FLAKY_CHANGES
+			})
+			.collect()
+	}
+
+	/// Flags the flaky checks of the output
+	pub fn mark_flaky(&self, output: &mut BeaconOutput) {
+		let flaky = self.flaky();
+		for (entity_name, reports) in output.entities.iter_mut() {
+			for report in reports.iter_mut() {
+				report.flaky = flaky.contains(&(entity_name.clone(), report.key(), report.category));
+			}
+		}
+	}
+}
+
+fn fixtures_dir(history: &Path) -> PathBuf {
+	history.with_extension("fixtures")
+}
+
+/// File of the responses of the run `id` of the history at `history`
+pub fn fixtures_path(history: &Path, id: &str) -> PathBuf {
+	fixtures_dir(history).join(format!("{}.json", id))
+}
+
+#[cfg(test)]
+mod tests {
+
+	use crate::history::{self, History};
+	use crate::maintenance::Status;
+	use crate::output::{BeaconOutput, Category};
+	use crate::testing::{Behavior, MockBeacon};
+
+	fn run(output: &BeaconOutput, configuration_valid: bool) -> BeaconOutput {
+		let mut output = output.clone();
+		let report = output.entities.get_mut("Configuration").unwrap().get_mut(0).unwrap();
+		report.valid = Some(configuration_valid);
+		output
+	}
+
+	#[test]
+	fn test_flaky() {
+		let output = MockBeacon::start(Behavior::Conformant).verify();
+		let mut history = History::default();
+		for valid in [true, false, true] {
+			history.record(&run(&output, valid));
+		}
+		let flaky = history.flaky();
+		assert_eq!(flaky.len(), 1);
+		assert!(flaky.contains(&("Configuration".into(), output.name.clone(), Category::Framework)));
+
+		let mut last = run(&output, true);
+		history.mark_flaky(&mut last);
+		assert!(last.entities["Configuration"][0].flaky);
+		assert!(!last.entities["Info"][0].flaky);
+
+		// A lasting failure is not flaky
+		for _ in 0..5 {
+			history.record(&run(&output, false));
+		}
+		assert!(history.flaky().is_empty());
+
+		// Failures during the maintenance windows do not count
+		let mut history = History::default();
+		for (valid, maintenance) in [(true, false), (false, true), (true, false), (false, false)] {
+			let mut output = run(&output, valid);
+			output.status = Some(Status::new(valid, maintenance));
+			history.record(&output);
+		}
+		assert_eq!(history.flaky().len(), 0);
+	}
+
+	#[test]
+	fn test_max_runs() {
+		let output = MockBeacon::start(Behavior::Conformant).verify();
+		let mut history = History::default();
+		for _ in 0..25 {
+			history.record(&output);
+		}
+		assert_eq!(history.runs.len(), 20);
+		assert!(history.runs.iter().all(|run| run.resource_usage.is_some()));
+		let dir = tempfile::tempdir().unwrap();
+		let path = dir.path().join("history.json");
+		assert_eq!(History::load(&path).unwrap(), History::default());
+		history.save(&path).unwrap();
+		assert_eq!(History::load(&path).unwrap(), history);
+	}
+
+	#[test]
+	fn test_replayed_runs() {
+		let output = MockBeacon::start(Behavior::Conformant).verify();
+		let mut history = History::default();
+		history.record(&run(&output, true));
+		let recorded = &history.runs[0];
+		assert_eq!(history.run(&recorded.id()), Some(recorded));
+		assert!(recorded.caught(&run(&output, true)).is_empty());
+		let caught = recorded.caught(&run(&output, false));
+		assert_eq!(caught.len(), 1);
+		assert_eq!(caught[0].entity, "Configuration");
+
+		// The responses of the runs no longer in the history are removed with it
+		let dir = tempfile::tempdir().unwrap();
+		let path = dir.path().join("history.json");
+		let kept = history::fixtures_path(&path, &recorded.id());
+		let removed = history::fixtures_path(&path, "20000101T000000");
+		for fixtures in [&kept, &removed] {
+			std::fs::create_dir_all(fixtures.parent().unwrap()).unwrap();
+			std::fs::write(fixtures, "[]").unwrap();
+		}
+		history.save(&path).unwrap();
+		assert!(kept.is_file());
+		assert!(!removed.exists());
+	}
+}
@@ -0,0 +1,231 @@
+//! Conformance report of a run as a self-contained HTML page
+//!
+//! The page is what the implementers of a beacon get instead of the logs of
+//! the terminal: the specification the beacon was verified against, a matrix
+//! of the results per entity and layer, the scope of the run (what it did not
+//! verify), then the checks of every entity with their response times and
+//! their errors, expandable. It has no external
+//! resources, so it can be attached to an email or published as is.
+
+use std::fmt::Write;
+
+use crate::humanize::TimeStyle;
+use crate::junit::escape;
+use crate::output::{BeaconOutput, Category, EndpointOutput, Outcome};
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em;color:#222}table{border-collapse:collapse;margin:1em \
+                     0}th,td{border:1px solid #ccc;padding:.3em \
+                     .6em;text-align:left}.passed{background:#d4edda}.failed{background:#f8d7da}.error{background:#\
+                     fff3cd}.skipped{background:#e2e3e5}pre{white-space:pre-wrap;margin:.3em 0}summary{cursor:pointer}";
+
+/// Worst outcome of `reports`, the one shown in the matrix
+fn worst(reports: &[&EndpointOutput]) -> Option<Outcome> {
+	[Outcome::Failed, Outcome::Error, Outcome::Passed, Outcome::Skipped]
+		.into_iter()
+		.find(|outcome| reports.iter().any(|report| report.outcome() == *outcome))
+}
+
+fn spec(html: &mut String, output: &BeaconOutput) {
+	let configuration = match &output.configuration {
+		Some(configuration) => configuration,
+		None => return,
+	};
+	let _ = writeln!(html, "<table>");
+	let _ = writeln!(
+		html,
+		"<tr><th>Verifier</th><td>{}</td></tr>",
+		escape(&configuration.version)
+	);
+	let model = configuration.model.as_ref().map(|model| ("Model", model));
+	let sources = std::iter::once(("Framework", &configuration.framework)).chain(model);
+	for (layer, source) in sources {
+		let revision = source
+			.revision
+			.as_deref()
+			.map(|revision| format!(" ({})", escape(revision)))
+			.unwrap_or_default();
+		let _ = writeln!(
+			html,
+			"<tr><th>{}</th><td>{}{}</td></tr>",
+			layer,
+			escape(source.location.as_str()),
+			revision
+		);
+	}
+	let _ = writeln!(html, "</table>");
+}
+
+fn matrix(html: &mut String, output: &BeaconOutput) {
+	let _ = writeln!(
+		html,
+		"<h2>Results</h2>\n<table>\n<tr><th>Entity</th><th>Framework</th><th>Model</th></tr>"
+	);
+	for (entity_name, reports) in &output.entities {
+		let _ = write!(html, "<tr><td><a href=\"#{0}\">{0}</a></td>", escape(entity_name));
+		for category in Category::ALL {
+			let reports = reports
+				.iter()
+				.filter(|report| report.category == category)
+				.collect::<Vec<_>>();
+			let passed = reports.iter().filter(|report| report.outcome().is_success()).count();
+			match worst(&reports) {
+				Some(outcome) => {
+					let _ = write!(html, "<td class=\"{}\">{}/{}</td>", outcome, passed, reports.len());
+				},
+				None => {
+					let _ = write!(html, "<td></td>");
+				},
+			}
+		}
+		let _ = writeln!(html, "</tr>");
+	}
+	let _ = writeln!(html, "</table>");
+}
+
+fn scope(html: &mut String, output: &BeaconOutput) {
+	let scope = match &output.scope {
+		Some(scope) => scope,
+		None => return,
+	};
+	let _ = writeln!(
+		html,
+		"<h2>Scope</h2>\n<p>{} checks, {} access</p>",
+		scope.layers(),
+		scope.access
+	);
+	let _ = writeln!(html, "<table>\n<tr><th>Entity</th><th>Depth</th></tr>");
+	for (entity_name, depth) in &scope.entities {
+		let _ = writeln!(html, "<tr><td>{}</td><td>{}</td></tr>", escape(entity_name), depth);
+	}
+	let _ = writeln!(html, "</table>");
+	if !scope.not_verified.is_empty() {
+		let _ = writeln!(html, "<p>Not verified:</p>\n<ul>");
+		for not_verified in &scope.not_verified {
+			let _ = writeln!(html, "<li>{}</li>", escape(not_verified));
+		}
+		let _ = writeln!(html, "</ul>");
+	}
+}
+
+fn checks(html: &mut String, entity_name: &str, reports: &[EndpointOutput], time_style: TimeStyle) {
+	let _ = writeln!(html, "<h3 id=\"{0}\">{0}</h3>", escape(entity_name));
+	let _ = writeln!(
+		html,
+		"<table>\n<tr><th>Check</th><th>Layer</th><th>Result</th><th>Time</th><th>Details</th></tr>"
+	);
+	for report in reports {
+		let outcome = report.outcome();
+		let _ = write!(
+			html,
+			"<tr><td>{}</td><td>{}</td><td class=\"{2}\">{2}</td><td>{3}</td><td>",
+			escape(&report.name),
+			report.category,
+			outcome,
+			report.elapsed_ms.map(|ms| time_style.millis(ms)).unwrap_or_default()
+		);
+		if let Some(error) = report.error.as_deref().map(str::trim_end) {
+			let _ = write!(
+				html,
+				"<details><summary>{}</summary><pre>{}</pre></details>",
+				escape(error.lines().next().unwrap_or_default()),
+				escape(error)
+			);
+		}
+		for warning in &report.warnings {
+			let _ = write!(html, "<div>Warning: {}</div>", escape(warning));
+		}
+		let _ = writeln!(html, "</td></tr>");
+	}
+	let _ = writeln!(html, "</table>");
+}
+
+/// Renders the conformance report of the output as one HTML page
+pub fn html(output: &BeaconOutput, time_style: TimeStyle) -> String {
+	let mut html = String::new();
+	let title = format!("Conformance report of {}", escape(&output.name));
+	let _ = writeln!(
+		html,
+		"<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">"
+	);
+	let _ = writeln!(
+		html,
+		"<title>{}</title>\n<style>{}</style>\n</head>\n<body>",
+		title, STYLE
+	);
+	let _ = writeln!(html, "<h1>{}</h1>", title);
+	let _ = writeln!(
+		html,
+		"<p><a href=\"{0}\">{0}</a>, verified on {1}</p>",
+		escape(output.url.as_str()),
+		time_style.timestamp(output.last_updated)
+	);
+	spec(&mut html, output);
+	let all = output.entities.values().flatten().collect::<Vec<_>>();
+	let counts = Outcome::ALL
+		.into_iter()
+		.map(|outcome| {
+			let count = all.iter().filter(|report| report.outcome() == outcome).count();
+			format!("{} {}", count, outcome)
+		})
+		.collect::<Vec<_>>();
+	let _ = writeln!(html, "<p>{} checks: {}</p>", all.len(), counts.join(", "));
+	matrix(&mut html, output);
+	scope(&mut html, output);
+	let _ = writeln!(html, "<h2>Checks</h2>");
+	for (entity_name, reports) in &output.entities {
+		checks(&mut html, entity_name, reports, time_style);
+	}
+	let _ = writeln!(html, "</body>\n</html>");
+	html
+}
+
+#[cfg(test)]
+mod tests {
+
+	use serde_json::json;
+	use wiremock::ResponseTemplate;
+
+	use crate::html;
+	use crate::humanize::TimeStyle;
+	use crate::testing::{self, Behavior, MockBeacon};
+
+	#[test]
+	fn test_html() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let mut response = beacon.response("/individuals");
+		response["response"]["resultSets"][0]["results"][0]["sex"] = json!("female");
+		beacon.mount("/individuals", ResponseTemplate::new(200).set_body_json(response));
+		let output = beacon
+			.beacon()
+			.configuration(testing::configuration(&beacon))
+			.validate();
+		let page = html::html(&output, TimeStyle::Utc);
+		assert!(page.starts_with("<!DOCTYPE html>"));
+		assert!(page.ends_with("</html>\n"));
+		// Self-contained
+		assert!(!page.contains("<script") && !page.contains("<link"));
+		assert!(page.contains("<tr><td><a href=\"#Individual\">Individual</a></td><td class=\"passed\">"));
+		assert!(page.contains("<td class=\"failed\">3/4</td>"), "{}", page);
+		assert!(page.contains("<tr><th>Framework</th><td>file://"));
+		let summary = "<details><summary>Response does not match the schema: value is not of type &quot;object&quot;";
+		assert!(page.contains(summary), "{}", page);
+		assert_eq!(page.matches("<h3 id=").count(), output.entities.len());
+		let verified = format!("verified on {}</p>", TimeStyle::Utc.timestamp(output.last_updated));
+		assert!(page.contains(&verified), "{}", page);
+		assert!(
+			page.contains("<h2>Scope</h2>\n<p>framework and model checks, anonymous access</p>"),
+			"{}",
+			page
+		);
+		assert!(
+			page.contains("<tr><td>Individual</td><td>sampled</td></tr>"),
+			"{}",
+			page
+		);
+		assert!(
+			page.contains("<li>authenticated access (no credentials)</li>"),
+			"{}",
+			page
+		);
+	}
+}
@@ -0,0 +1,120 @@
+//! Timestamps and durations read by people
+//!
+//! The reports keep the values of the machines (`last_updated` in UTC,
+//! `elapsed_ms`, ...), but the summary of a run and the HTML report are read
+//! by the reviewers: their timestamps are in the local timezone and their
+//! durations humanized (e.g. `1 min 5 s`). With `--time-style utc`, e.g. in
+//! CI, both are ISO 8601 instead: `2022-03-01T12:00:00Z` and `PT65.2S`.
+
+use std::time::Duration;
+
+use chrono::{Local, NaiveDateTime, TimeZone};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ArgEnum)]
+pub enum TimeStyle {
+	/// Local timezone and humanized durations
+	Local,
+	/// UTC timestamps and durations in ISO 8601
+	Utc,
+}
+
+impl Default for TimeStyle {
+	fn default() -> Self {
+		Self::Local
+	}
+}
+
+impl TimeStyle {
+	/// Renders a timestamp in UTC, as the ones of the output
+	pub fn timestamp(self, at: NaiveDateTime) -> String {
+		match self {
+			Self::Local => Local.from_utc_datetime(&at).format("%Y-%m-%d %H:%M:%S %:z").to_string(),
+			Self::Utc => at.format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+		}
+	}
+
+	pub fn duration(self, duration: Duration) -> String {
+		match self {
+			Self::Local => humanized(duration),
+			Self::Utc => iso(duration),
+		}
+	}
+
+	pub fn millis(self, ms: u64) -> String {
+		self.duration(Duration::from_millis(ms))
+	}
+}
+
+/// Milliseconds under a second, tenths of seconds under a minute, else the two largest units (e.g. `2 h 5 min`)
+fn humanized(duration: Duration) -> String {
+	let ms = duration.as_millis();
+	if ms < 1000 {
+		return format!("{} ms", ms);
+	}
+	if ms < 60_000 {
+		return format!("{:.1} s", duration.as_secs_f64());
+	}
+	let secs = duration.as_secs();
+	let units = [
+		(secs / 86400, "d"),
+		(secs / 3600 % 24, "h"),
+		(secs / 60 % 60, "min"),
+		(secs % 60, "s"),
+	];
+	let first = units.iter().position(|(value, _)| *value > 0).unwrap_or(0);
+	units[first..]
+		.iter()
+		.take(2)
+		.filter(|(value, _)| *value > 0)
+		.map(|(value, unit)| format!("{} {}", value, unit))
+		.collect::<Vec<_>>()
+		.join(" ")
+}
+
+/// Seconds, with the milliseconds if any (e.g. `PT65.2S`)
+fn iso(duration: Duration) -> String {
+	let millis = duration.subsec_millis();
+	if millis == 0 {
+		format!("PT{}S", duration.as_secs())
+	}
+	else {
+		let fraction = format!("{:03}", millis);
+		format!("PT{}.{}S", duration.as_secs(), fraction.trim_end_matches('0'))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use std::time::Duration;
+
+	use chrono::NaiveDate;
+
+	use crate::humanize::TimeStyle;
+
+	#[test]
+	fn test_duration() {
+		let human = |ms: u64| TimeStyle::Local.millis(ms);
+		assert_eq!(human(350), "350 ms");
+		assert_eq!(human(1240), "1.2 s");
+		assert_eq!(human(65_000), "1 min 5 s");
+		assert_eq!(human(7_500_000), "2 h 5 min");
+		assert_eq!(human(90_000_000), "1 d 1 h");
+		assert_eq!(human(3_600_000), "1 h");
+		assert_eq!(TimeStyle::Utc.millis(65_200), "PT65.2S");
+		assert_eq!(TimeStyle::Utc.millis(350), "PT0.35S");
+		assert_eq!(TimeStyle::Utc.duration(Duration::from_secs(3)), "PT3S");
+	}
+
+	#[test]
+	fn test_timestamp() {
+		let at = NaiveDate::from_ymd(2022, 3, 1).and_hms(12, 0, 0);
+		assert_eq!(TimeStyle::Utc.timestamp(at), "2022-03-01T12:00:00Z");
+		let local = TimeStyle::Local.timestamp(at);
+		assert!(
+			chrono::DateTime::parse_from_str(&local, "%Y-%m-%d %H:%M:%S %:z").is_ok(),
+			"{}",
+			local
+		);
+	}
+}
@@ -0,0 +1,282 @@
+//! Format of the identifiers and of the temporal values of the records
+//!
+//! The schemas only declare the format of a few values, and a beacon serving
+//! its own schemas may declare none. On top of the schema, the records are
+//! linted wherever the values are: the id of every ontology term (an object
+//! with an `id` and a `label`) must be a CURIE, every `iso8601duration` (the
+//! ages, the age ranges, the ages of onset) an ISO 8601 duration, the dates
+//! (`collectionDate`, `runDate`, ...) and timestamps ISO 8601 dates or date
+//! times, and the HGVS identifiers of the genomic variants HGVS expressions.
+//! The other custom formats are found by property too: the values prefixed
+//! with `refseq:` (e.g. the `sequence_id` of a VRS location) and the ones of
+//! the properties named after RefSeq must be RefSeq accessions, the ones
+//! prefixed with `orcid:` and the ones of the properties named after ORCID
+//! ORCID identifiers, and the ones of the properties named `country` or after
+//! a country code ISO 3166-1 alpha-2 country codes. Each violation is reported
+//! with its path. Disabling a format also disables its lint.
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+
+use crate::formats::{self, Format, Formats};
+use crate::{hgvs, Json};
+
+/// Whether the values of `property` are dates or date times
+fn is_temporal(property: &str) -> bool {
+	let property = property.to_lowercase();
+	property.ends_with("date") || property.ends_with("datetime") || property == "timestamp"
+}
+
+/// Custom format of the values of `property` (or of `value` by its prefix), with the part of `value` in the format
+fn format_of<'a>(property: &str, value: &'a str) -> Option<(Format, &'a str)> {
+	let name = property.to_lowercase().replace('_', "");
+	let prefix = value
+		.split_once(':')
+		.map(|(prefix, rest)| (prefix.to_lowercase(), rest));
+	match prefix {
+		Some((prefix, accession)) if prefix == "refseq" => Some((Format::RefseqAccession, accession)),
+		_ if name.contains("refseq") => Some((Format::RefseqAccession, value)),
+		Some((prefix, _)) if prefix == "orcid" => Some((Format::Orcid, value)),
+		_ if name.contains("orcid") => Some((Format::Orcid, value)),
+		_ if name == "country" || name.ends_with("countrycode") => Some((Format::IsoCountryCode, value)),
+		_ => None,
+	}
+}
+
+/// Problem of a value not in its custom format
+fn expected(format: Format) -> &'static str {
+	match format {
+		Format::RefseqAccession => "Expected a RefSeq accession (e.g. NC_000017.11)",
+		Format::Orcid => "Expected an ORCID identifier (e.g. 0000-0002-1825-0097)",
+		Format::IsoCountryCode => "Expected an ISO 3166-1 alpha-2 country code (e.g. ES)",
+		Format::Curie => "Expected a CURIE",
+		Format::Hgvs => "Expected an HGVS expression",
+		Format::Iso8601Duration => "Expected an ISO 8601 duration (e.g. P32Y6M)",
+	}
+}
+
+/// Whether `value` is an ISO 8601 date (e.g. `2021-09-24`) or date time (e.g. `2021-09-24T10:15:00Z`)
+pub fn is_iso8601_date(value: &str) -> bool {
+	NaiveDate::parse_from_str(value, "%Y-%m-%d").is_ok()
+		|| DateTime::parse_from_rfc3339(value).is_ok()
+		|| NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%.f").is_ok()
+}
+
+/// Whether `value` is an ISO 8601 duration (e.g. `P32Y6M`, `PT12H`, `P3W`)
+pub fn is_iso8601_duration(value: &str) -> bool {
+	let rest = match value.strip_prefix('P') {
+		Some(rest) if !rest.is_empty() => rest,
+		_ => return false,
+	};
+	let (date, time) = match rest.split_once('T') {
+		Some((_, "")) => return false,
+		Some((date, time)) => (date, Some(time)),
+		None => (rest, None),
+	};
+	let date = components(date, &['Y', 'M', 'W', 'D']);
+	let time = time.map_or(Some(0), |time| components(time, &['H', 'M', 'S']));
+	matches!((date, time), (Some(date), Some(time)) if date + time > 0)
+}
+
+/// Number of the components of `part`, each a number followed by one of `designators` in their order
+fn components(part: &str, designators: &[char]) -> Option<usize> {
+	let mut remaining = designators;
+	let mut count = 0;
+	let mut number = String::new();
+	for c in part.chars() {
+		if c.is_ascii_digit() || (matches!(c, '.' | ',') && !number.is_empty()) {
+			number.push(c);
+			continue;
+		}
+		let position = remaining.iter().position(|designator| *designator == c)?;
+		if number.is_empty() || number.ends_with(['.', ',']) {
+			return None;
+		}
+		remaining = &remaining[position + 1..];
+		number.clear();
+		count += 1;
+	}
+	number.is_empty().then(|| count)
+}
+
+/// Lints the identifiers and temporal values of a record, returning the path of each invalid one with the problem
+pub fn check_record(record: &Json, formats: &Formats) -> Vec<(String, String)> {
+	let mut problems = Vec::new();
+	walk(record, "", None, formats, &mut problems);
+	if formats.is_enabled(Format::Hgvs) {
+		for (path, e) in hgvs::check_variant(record) {
+			log::debug!("Invalid HGVS expression at {}: {}", path, e);
+			problems.push((path, format!("Invalid HGVS expression: {}", e)));
+		}
+	}
+	problems
+}
+
+fn walk(json: &Json, path: &str, property: Option<&str>, formats: &Formats, problems: &mut Vec<(String, String)>) {
+	match json {
+		Json::Object(object) => {
+			let is_ontology_term = object.len() == 2 && object.get("label").map_or(false, Json::is_string);
+			if let (true, Some(Json::String(id))) = (is_ontology_term, object.get("id")) {
+				if formats.is_enabled(Format::Curie) && !formats::is_curie(id) {
+					log::debug!("The id of the ontology term at {} is {:?}", path, id);
+					problems.push((
+						format!("{}/id", path),
+						"Expected a CURIE as the id of the ontology term".into(),
+					));
+				}
+			}
+			for (property, value) in object {
+				walk(
+					value,
+					&format!("{}/{}", path, property),
+					Some(property),
+					formats,
+					problems,
+				);
+			}
+		},
+		// Lists of dates keep their property
+		Json::Array(values) => values
+			.iter()
+			.for_each(|value| walk(value, &format!("{}/*", path), property, formats, problems)),
+		Json::String(value) => match property {
+			Some("iso8601duration") if formats.is_enabled(Format::Iso8601Duration) && !is_iso8601_duration(value) => {
+				log::debug!("The duration at {} is {:?}", path, value);
+				problems.push((path.to_string(), "Expected an ISO 8601 duration (e.g. P32Y6M)".into()));
+			},
+			Some(property) if is_temporal(property) && !is_iso8601_date(value) => {
+				log::debug!("The date at {} is {:?}", path, value);
+				problems.push((path.to_string(), "Expected an ISO 8601 date (e.g. 2021-09-24)".into()));
+			},
+			Some(property) => match format_of(property, value) {
+				Some((format, part)) if formats.is_enabled(format) && !format.validator()(part) => {
+					log::debug!("The {} at {} is {:?}", format.keyword(), path, value);
+					problems.push((path.to_string(), expected(format).into()));
+				},
+				_ => (),
+			},
+			None => (),
+		},
+		_ => (),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use serde_json::json;
+	use wiremock::ResponseTemplate;
+
+	use crate::formats::{Format, Formats};
+	use crate::identifiers::{self, is_iso8601_date, is_iso8601_duration};
+	use crate::testing::{Behavior, MockBeacon};
+
+	#[test]
+	fn test_iso8601() {
+		assert!(is_iso8601_duration("P32Y6M"));
+		assert!(is_iso8601_duration("P3W"));
+		assert!(is_iso8601_duration("PT12H30M"));
+		assert!(is_iso8601_duration("P1DT0.5S"));
+		assert!(!is_iso8601_duration("P"));
+		assert!(!is_iso8601_duration("P1DT"));
+		assert!(!is_iso8601_duration("32Y"));
+		assert!(!is_iso8601_duration("P6M32Y"));
+		assert!(!is_iso8601_duration("P32 years"));
+
+		assert!(is_iso8601_date("2021-09-24"));
+		assert!(is_iso8601_date("2021-09-24T10:15:00Z"));
+		assert!(is_iso8601_date("2021-09-24T10:15:00"));
+		assert!(!is_iso8601_date("2021-02-30"));
+		assert!(!is_iso8601_date("24/09/2021"));
+	}
+
+	#[test]
+	fn test_lint_record() {
+		let individual = json!({
+			"id": "ind1",
+			"sex": { "id": "NCIT C16576", "label": "female" },
+			"diseases": [
+				{ "diseaseCode": { "id": "HP:0000118", "label": "Phenotypic abnormality" }, "ageOfOnset": {
+					"age": { "iso8601duration": "32 years" },
+				} },
+			],
+			"info": { "collectionDate": "24/09/2021", "runDate": "2021-09-24" },
+		});
+		let problems = identifiers::check_record(&individual, &Formats::default());
+		assert_eq!(
+			problems,
+			vec![
+				(
+					"/diseases/*/ageOfOnset/age/iso8601duration".to_string(),
+					"Expected an ISO 8601 duration (e.g. P32Y6M)".to_string()
+				),
+				(
+					"/info/collectionDate".to_string(),
+					"Expected an ISO 8601 date (e.g. 2021-09-24)".to_string()
+				),
+				(
+					"/sex/id".to_string(),
+					"Expected a CURIE as the id of the ontology term".to_string()
+				),
+			]
+		);
+		let formats = Formats::default()
+			.disable(Format::Curie)
+			.disable(Format::Iso8601Duration);
+		assert_eq!(identifiers::check_record(&individual, &formats).len(), 1);
+
+		let run = json!({
+			"id": "run1",
+			"info": { "submitterOrcid": "0000-0002-1825-0098", "country": "Spain", "countryCode": "ES" },
+			"location": { "sequence_id": "refseq:NQ_000017.11", "start": 1 },
+			"refseqIds": ["NC_000017.11", "chr17"],
+		});
+		let problems = identifiers::check_record(&run, &Formats::default());
+		assert_eq!(
+			problems,
+			vec![
+				(
+					"/info/country".to_string(),
+					"Expected an ISO 3166-1 alpha-2 country code (e.g. ES)".to_string()
+				),
+				(
+					"/info/submitterOrcid".to_string(),
+					"Expected an ORCID identifier (e.g. 0000-0002-1825-0097)".to_string()
+				),
+				(
+					"/location/sequence_id".to_string(),
+					"Expected a RefSeq accession (e.g. NC_000017.11)".to_string()
+				),
+				(
+					"/refseqIds/*".to_string(),
+					"Expected a RefSeq accession (e.g. NC_000017.11)".to_string()
+				),
+			]
+		);
+		assert!(
+			identifiers::check_record(&run, &Formats::default().disable(Format::Orcid))
+				.iter()
+				.all(|(path, _)| path != "/info/submitterOrcid")
+		);
+
+		let variant = json!({ "identifiers": { "genomicHGVSId": "NC_000017.11:43057063G>A" } });
+		let problems = identifiers::check_record(&variant, &Formats::default());
+		assert_eq!(problems[0].0, "/identifiers/genomicHGVSId");
+		assert!(problems[0].1.starts_with("Invalid HGVS expression: "));
+	}
+
+	#[test]
+	fn test_invalid_identifier() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let mut response = beacon.response("/individuals");
+		response["response"]["resultSets"][0]["results"][1]["sex"]["id"] = json!("female");
+		beacon.mount("/individuals", ResponseTemplate::new(200).set_body_json(response));
+		let output = beacon.verify();
+		let all_entries = output.entities["Individual"]
+			.iter()
+			.find(|report| report.name == "Individual all entries" && report.error.is_some())
+			.unwrap();
+		let e = all_entries.error.as_deref().unwrap();
+		assert!(e.contains("Expected a CURIE as the id of the ontology term"), "{}", e);
+		assert!(e.contains("/sex/id"), "{}", e);
+	}
+}
@@ -0,0 +1,96 @@
+//! Incremental verification: the checks that depend on the data only run again
+//! when the beacon reports that its data changed (`updateDateTime` of `/info`
+//! and of the datasets, or `updatedAt`)
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::ErrorKind;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::client::Client;
+use crate::error::VerifierError;
+use crate::model::Model;
+use crate::{artifacts, utils, Json};
+
+/// Last update of the data, as reported by the beacon
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DataVersion {
+	pub info: Option<String>,
+	/// Last update per dataset id
+	pub datasets: BTreeMap<String, String>,
+}
+
+/// Outcome of the comparison with the previous run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Incremental {
+	pub data_version: DataVersion,
+	/// Whether the data changed since the previous run (the data-dependent checks only run if it did)
+	pub changed: bool,
+}
+
+fn updated(json: &Json) -> Option<String> {
+	json.get("updateDateTime")
+		.or_else(|| json.get("updatedAt"))
+		.and_then(Json::as_str)
+		.map(String::from)
+}
+
+impl DataVersion {
+	/// Reads the last updates from `/info` and from the datasets endpoint of the model
+	pub fn fetch(client: &Client, root_url: &Url, info: &Json, model: Option<&Model>) -> Self {
+		let mut version = Self {
+			info: info.get("response").and_then(updated),
+			datasets: BTreeMap::new(),
+		};
+
+		let datasets = model.and_then(|model| model.entities.iter().find(|entity| entity.entry_type == "dataset"));
+		if let Some(datasets) = datasets {
+			match client.query(&datasets.name, &utils::url_join(root_url, &datasets.url)) {
+				Ok(response) => {
+					let collections = response.json.pointer("/response/collections").and_then(Json::as_array);
+					for dataset in collections.map_or(&[][..], Vec::as_slice) {
+						if let (Some(id), Some(updated)) = (dataset.get("id").and_then(Json::as_str), updated(dataset))
+						{
+							version.datasets.insert(id.to_string(), updated);
+						}
+					}
+				},
+				Err(e) => log::warn!("Unable to read the last update of the datasets: {}", e),
+			}
+		}
+
+		version
+	}
+
+	/// Whether the beacon reports any update date at all
+	pub fn is_known(&self) -> bool {
+		self.info.is_some() || !self.datasets.is_empty()
+	}
+
+	/// Data version saved by the previous run, none before the first one
+	pub fn load(path: &Path) -> Result<Option<Self>, VerifierError> {
+		let file = match File::open(path) {
+			Ok(file) => file,
+			Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+			Err(e) => return Err(e.into()),
+		};
+		Ok(Some(serde_json::from_reader(file)?))
+	}
+
+	pub fn save(&self, path: &Path) -> Result<(), VerifierError> {
+		artifacts::write_atomic(path, &serde_json::to_vec_pretty(self)?)
+	}
+}
+
+impl Incremental {
+	/// Without any update date the data is assumed to have changed
+	pub fn compare(previous: Option<&DataVersion>, data_version: DataVersion) -> Self {
+		Self {
+			changed: !data_version.is_known() || previous != Some(&data_version),
+			data_version,
+		}
+	}
+}
@@ -0,0 +1,232 @@
+//! Starter configuration of a beacon
+//!
+//! `init` adds the target of a beacon to the configuration file, prefilled
+//! from what the beacon answers: the version of the specification declared by
+//! `/info`, the style of the credentials asked for when `/info` is refused and
+//! the entry types advertised by `/map`, listed to set their method policies.
+//! The credentials are placeholders, to replace before the first run.
+
+use std::path::Path;
+
+use reqwest::StatusCode;
+use url::Url;
+
+use crate::client::Client;
+use crate::config::ConfigFile;
+use crate::discovery::Discovery;
+use crate::error::VerifierError;
+use crate::spec::SpecVersion;
+use crate::syntax::Syntax;
+use crate::{artifacts, utils, Json};
+
+/// Credentials asked for by the beacon
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthStyle {
+	Bearer,
+	Basic,
+	ApiKey,
+}
+
+impl AuthStyle {
+	/// Style of the scheme of a `WWW-Authenticate` header, an API key without one
+	fn from_challenge(scheme: Option<&str>) -> Self {
+		match scheme.map(str::to_lowercase).as_deref() {
+			Some("bearer") => Self::Bearer,
+			Some("basic") => Self::Basic,
+			_ => Self::ApiKey,
+		}
+	}
+}
+
+/// Target of a beacon, as probed
+#[derive(Debug, Clone, PartialEq)]
+pub struct Starter {
+	/// Name of the target, after the id of the beacon or else its host
+	pub name: String,
+	pub url: Url,
+	pub spec_version: Option<SpecVersion>,
+	pub auth: Option<AuthStyle>,
+	/// Entry types of the endpoint sets of `/map`
+	pub entry_types: Vec<String>,
+}
+
+fn quoted(value: &str) -> String {
+	toml::Value::String(value.to_string()).to_string()
+}
+
+impl Starter {
+	pub fn probe(client: &Client, url: &Url) -> Result<Self, VerifierError> {
+		let mut info_url = url.clone();
+		info_url.set_path(Path::new(url.path()).join("info").to_str().unwrap_or(""));
+		let (status, info) = client.get_raw(&info_url)?;
+		let auth = if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN {
+			let (_, scheme) = client.challenge(&info_url)?;
+			let style = AuthStyle::from_challenge(scheme.as_deref());
+			log::info!("The beacon asks for credentials ({:?})", style);
+			Some(style)
+		}
+		else {
+			None
+		};
+		let info = info.filter(|_| status.is_success());
+		let spec_version = info.as_ref().and_then(SpecVersion::declared);
+		let name = info
+			.as_ref()
+			.and_then(|info| info.pointer("/response/id"))
+			.and_then(Json::as_str)
+			.map(utils::slug)
+			.filter(|name| !name.is_empty())
+			.unwrap_or_else(|| utils::slug(url.host_str().unwrap_or("beacon")));
+		let entry_types = match Discovery::advertised_entry_types(client, url) {
+			Ok(entry_types) => entry_types,
+			Err(e) => {
+				log::warn!("Could not read the entry types of /map: {}", e);
+				Vec::new()
+			},
+		};
+		Ok(Self {
+			name,
+			url: url.clone(),
+			spec_version,
+			auth,
+			entry_types,
+		})
+	}
+
+	/// Target in the syntax of the configuration file
+	pub fn to_toml(&self) -> String {
+		let table = format!("beacons.{}", self.name);
+		let mut lines = vec![
+			format!("# Target of {}, generated by `beacon-verifier init`", self.url),
+			format!("[{}]", table),
+			format!("url = {}", quoted(self.url.as_str())),
+		];
+		match self.spec_version {
+			Some(version) => lines.push(format!("spec-version = {}", quoted(&version.to_string()))),
+			None => lines.push(format!(
+				"# spec-version = {} (not declared by /info)",
+				quoted(&SpecVersion::DEFAULT.to_string())
+			)),
+		}
+		match self.auth {
+			Some(AuthStyle::Bearer) => lines.push("token = \"<token>\"".into()),
+			Some(AuthStyle::Basic) => lines.extend([
+				format!("\n[{}.basic-auth]", table),
+				"username = \"<username>\"".into(),
+				"password = \"<password>\"".into(),
+			]),
+			Some(AuthStyle::ApiKey) => lines.extend([
+				format!("\n[{}.api-key]", table),
+				"style = \"header\"".into(),
+				"name = \"X-API-Key\"".into(),
+				"key = \"<key>\"".into(),
+			]),
+			None => (),
+		}
+		if !self.entry_types.is_empty() {
+			lines.push("\n# Method policies of the entry types of /map (get, post or get-then-post)".into());
+			lines.push(format!("# [{}.endpoint-method-policies]", table));
+			lines.extend(
+				self.entry_types
+					.iter()
+					.map(|entry_type| format!("# {} = \"get-then-post\"", entry_type)),
+			);
+		}
+		let mut toml = lines.join("\n");
+		toml.push('\n');
+		toml
+	}
+
+	/// Appends the target to the configuration file at `path`, created if missing, which must be in TOML
+	pub fn write(&self, path: &Path) -> Result<(), VerifierError> {
+		if Syntax::of(path, Syntax::Toml) != Syntax::Toml {
+			return Err(VerifierError::BadConfig(format!(
+				"{:?} is not in TOML, the syntax of init",
+				path
+			)));
+		}
+		let mut content = if path.exists() {
+			std::fs::read_to_string(path)?
+		}
+		else {
+			String::new()
+		};
+		if ConfigFile::parse(&content, Syntax::Toml)?
+			.beacons
+			.contains_key(&self.name)
+		{
+			return Err(VerifierError::BadConfig(format!(
+				"the target '{}' already exists (choose another name with --name)",
+				self.name
+			)));
+		}
+		if !content.is_empty() {
+			content.push_str(if content.ends_with('\n') { "\n" } else { "\n\n" });
+		}
+		content.push_str(&self.to_toml());
+		ConfigFile::parse(&content, Syntax::Toml)?;
+		artifacts::write_atomic(path, content.as_bytes())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use wiremock::ResponseTemplate;
+
+	use crate::client::Client;
+	use crate::config::ConfigFile;
+	use crate::init::{AuthStyle, Starter};
+	use crate::spec::SpecVersion;
+	use crate::syntax::Syntax;
+	use crate::testing::{Behavior, MockBeacon};
+
+	#[test]
+	fn test_probe() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let starter = Starter::probe(&Client::new(), &beacon.url()).unwrap();
+		assert_eq!(starter.spec_version, Some(SpecVersion::V2_0));
+		assert_eq!(starter.auth, None);
+		assert_eq!(starter.name, "org-example-beacon");
+
+		let dir = tempfile::tempdir().unwrap();
+		let path = dir.path().join("beacon-verifier.toml");
+		starter.write(&path).unwrap();
+		let config = ConfigFile::load(&path).unwrap();
+		let profile = config.profile(&starter.name).unwrap();
+		assert_eq!(profile.url, Some(beacon.url()));
+		assert_eq!(profile.spec_version, Some(SpecVersion::V2_0));
+		// Added once
+		assert!(starter.write(&path).unwrap_err().to_string().contains("already exists"));
+
+		let starter = Starter {
+			name: "other".into(),
+			auth: Some(AuthStyle::Basic),
+			entry_types: vec!["individual".into()],
+			..starter
+		};
+		starter.write(&path).unwrap();
+		let config = ConfigFile::load(&path).unwrap();
+		assert_eq!(config.beacons.len(), 2);
+		assert_eq!(
+			config.beacons["other"].basic_auth.as_ref().unwrap().username,
+			"<username>"
+		);
+		assert!(starter.to_toml().contains("# individual = \"get-then-post\""));
+	}
+
+	#[test]
+	fn test_auth_style() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		beacon.mount(
+			"/info",
+			ResponseTemplate::new(401).insert_header("WWW-Authenticate", "Bearer realm=\"beacon\""),
+		);
+		let starter = Starter::probe(&Client::new(), &beacon.url()).unwrap();
+		assert_eq!(starter.auth, Some(AuthStyle::Bearer));
+		assert_eq!(starter.spec_version, None);
+		assert_eq!(starter.name, "127-0-0-1");
+		let config = ConfigFile::parse(&starter.to_toml(), Syntax::Toml).unwrap();
+		assert_eq!(config.beacons[&starter.name].token.as_deref(), Some("<token>"));
+	}
+}
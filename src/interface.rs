@@ -4,6 +4,8 @@ use std::path::PathBuf;
 use serde::{Deserialize, Serialize};
 use url::Url;
 
+use crate::Json;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Endpoint {
@@ -22,6 +24,15 @@ pub struct RelatedEndpoint {
 	pub url: Url,
 }
 
+/// Organization running the beacon, as described in `/info`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Organization {
+	pub id: Option<String>,
+	pub name: String,
+	pub logo_url: Option<Url>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct EntryType {
@@ -30,6 +41,8 @@ pub struct EntryType {
 	pub ontology_term_for_this_type: OntologyTerm,
 	pub part_of_specification: String,
 	pub default_schema: DefaultSchema,
+	#[serde(default)]
+	pub additionally_supported_schemas: Vec<DefaultSchema>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -42,27 +55,10 @@ pub struct OntologyTerm {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct DefaultSchema {
+	pub id: Option<String>,
 	pub reference_to_schema_definition: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub enum FilteringTermType {
-	OntologyTerm,
-	Alphanumeric,
-	Custom,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct FilteringTerm {
-	ft_type: FilteringTermType,
-	pub url: Url,
-	id: String,
-	label: Option<String>,
-	scope: Option<String>,
-}
-
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum Granularity {
@@ -72,38 +68,32 @@ pub enum Granularity {
 	Record,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct BeaconResultSetResponse {
-	pub response: ResultSetResponse,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct ResultSetResponse {
-	pub result_sets: Vec<ResultSet>,
+impl std::fmt::Display for Granularity {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Boolean => write!(f, "boolean"),
+			Self::Count => write!(f, "count"),
+			Self::Aggregated => write!(f, "aggregated"),
+			Self::Record => write!(f, "record"),
+		}
+	}
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ResultSet {
-	pub results: Vec<EntityResult>,
-}
+impl Granularity {
+	/// Whether the responses at this granularity hold the individual records, not only counts or aggregations of them
+	pub fn has_individual_records(self) -> bool {
+		self == Self::Record
+	}
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(untagged)]
-#[serde(rename_all = "camelCase")]
-pub enum EntityResult {
-	General { id: String },
-	Variant { variant_internal_id: String },
-	Cohort { cohort_id: String },
-}
+	/// Default granularity declared in the `securityAttributes` of `/info` or `/configuration`
+	pub fn declared(response_json: &Json) -> Option<Self> {
+		let granularity = response_json.pointer("/response/securityAttributes/defaultGranularity")?;
+		serde_json::from_value(granularity.clone()).ok()
+	}
 
-impl EntityResult {
-	pub fn id(&self) -> String {
-		match self {
-			EntityResult::General { id } => id.clone(),
-			EntityResult::Variant { variant_internal_id } => variant_internal_id.clone(),
-			EntityResult::Cohort { cohort_id } => cohort_id.clone(),
-		}
+	/// Granularity of a response, read from its `meta`
+	pub fn returned(response_json: &Json) -> Option<Self> {
+		serde_json::from_value(response_json.pointer("/meta/returnedGranularity")?.clone()).ok()
 	}
 }
 
@@ -118,4 +108,13 @@ pub struct BeaconMetaGranularityResponse {
 #[serde(rename_all = "camelCase")]
 pub struct MetaGranularityResponse {
 	pub returned_granularity: Granularity,
+	#[serde(default)]
+	pub returned_schemas: Vec<ReturnedSchema>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReturnedSchema {
+	pub entity_type: String,
+	pub schema: String,
 }
@@ -0,0 +1,129 @@
+//! Graceful shutdown on SIGINT and SIGTERM
+//!
+//! Once the handlers are installed, the first signal stops handing out
+//! endpoints: the requests in flight end (at the latest at their timeout), the
+//! endpoints and follow-up checks left are reported as not verified and the
+//! partial report, marked as `interrupted`, is written before the verifier
+//! exits with [`EXIT_CODE`]. A second signal exits at once.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Exit code of an interrupted run (128 + SIGINT, as shells report it)
+pub const EXIT_CODE: i32 = 130;
+
+/// Set by the signal handlers
+static SIGNALLED: AtomicBool = AtomicBool::new(false);
+
+/// Request to stop the run, by a signal or by the caller
+#[derive(Debug, Clone, Default)]
+pub struct Interrupt {
+	flag: Arc<AtomicBool>,
+}
+
+impl Interrupt {
+	/// Stops the run as a signal would, for the callers driving it themselves
+	pub fn interrupt(&self) {
+		self.flag.store(true, Ordering::SeqCst);
+	}
+
+	pub fn is_interrupted(&self) -> bool {
+		self.flag.load(Ordering::SeqCst) || SIGNALLED.load(Ordering::SeqCst)
+	}
+}
+
+#[cfg(unix)]
+extern "C" fn handle(_signal: libc::c_int) {
+	// Only async-signal-safe calls here
+	if SIGNALLED.swap(true, Ordering::SeqCst) {
+		unsafe { libc::_exit(EXIT_CODE) };
+	}
+}
+
+/// Interrupts the runs on SIGINT and SIGTERM instead of killing the process
+pub fn install() {
+	#[cfg(unix)]
+	for signal in [libc::SIGINT, libc::SIGTERM] {
+		// The handler only touches an atomic, or exits
+		unsafe { libc::signal(signal, handle as *const () as libc::sighandler_t) };
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use wiremock::matchers::{method, path, query_param};
+	use wiremock::{Mock, Request, ResponseTemplate};
+
+	use crate::interrupt::Interrupt;
+	use crate::testing::{Behavior, MockBeacon};
+
+	#[test]
+	fn test_interrupt() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let interrupt = Interrupt::default();
+		let individual = beacon.response("/individuals/ind1");
+		let trigger = interrupt.clone();
+		beacon.mount("/individuals/ind1", move |_: &Request| {
+			trigger.interrupt();
+			ResponseTemplate::new(200).set_body_json(individual.clone())
+		});
+		let output = beacon.beacon().interrupt(interrupt).validate();
+		assert!(output.interrupted);
+		assert_eq!(output.entities["Info"][0].valid, Some(true));
+		// The endpoint in flight is checked, the next ones are not
+		let reports = output.entities.values().flatten().collect::<Vec<_>>();
+		assert!(reports
+			.iter()
+			.any(|report| report.url.path() == "/individuals/ind1" && report.valid == Some(true)));
+		let interrupted = reports
+			.iter()
+			.filter(|report| report.error.as_deref() == Some("Not verified: the run was interrupted"))
+			.count();
+		assert!(interrupted > 0);
+
+		assert!(!beacon.verify().interrupted);
+	}
+
+	#[test]
+	fn test_interrupt_follow_ups() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let interrupt = Interrupt::default();
+		let biosamples = beacon.response("/biosamples");
+		let trigger = interrupt.clone();
+		// Interrupted by the first page of the pagination check
+		beacon.mount_mock(
+			Mock::given(method("GET"))
+				.and(path("/biosamples"))
+				.and(query_param("skip", "0"))
+				.respond_with(move |_: &Request| {
+					trigger.interrupt();
+					ResponseTemplate::new(200).set_body_json(biosamples.clone())
+				})
+				.with_priority(1),
+		);
+		let output = beacon
+			.beacon()
+			.pagination()
+			.filters()
+			.references()
+			.interrupt(interrupt)
+			.validate();
+		assert!(output.interrupted);
+		let not_verified = |name: &str| {
+			output
+				.entities
+				.values()
+				.flatten()
+				.find(|report| report.name == name)
+				.map_or(false, |report| {
+					report.error.as_deref() == Some("Not verified: the run was interrupted")
+				})
+		};
+		assert!(!not_verified("Biosample snapshot pagination"));
+		assert!(not_verified("Biosample pagination"));
+		assert!(not_verified("Individual snapshot pagination"));
+		assert!(not_verified("Individual filter consistency"));
+		assert!(not_verified("Dataset references"));
+	}
+}
@@ -0,0 +1,114 @@
+//! Isolation of the checks of each entity
+//!
+//! The checks of the entities run behind `catch_unwind`: a bug of the verifier
+//! (a panic) in one of them is reported as an internal error of the check it
+//! happened in, and the validation worker goes on with the other checks, so
+//! unattended runs always end with a report.
+//!
+//! The checks are also timed here, once: the duration of a check is shared
+//! between the reports it returns, so that the time of a response validated
+//! against both the framework and the model is not counted twice.
+
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::time::{Duration, Instant};
+
+use url::Url;
+
+use crate::error::VerifierError;
+use crate::output::{Category, EndpointReport};
+
+/// Message of a panic, when it has one
+fn message(payload: &(dyn Any + Send)) -> String {
+	payload
+		.downcast_ref::<&str>()
+		.map(|message| message.to_string())
+		.or_else(|| payload.downcast_ref::<String>().cloned())
+		.unwrap_or_else(|| "panic without a message".into())
+}
+
+/// Runs `check`, returning the message of its panic if it panicked
+pub fn catch<T, F>(check: F) -> Result<T, String>
+where
+	F: FnOnce() -> T,
+{
+	// The state a check mutates is only used for its own reports, which are dropped on panic
+	panic::catch_unwind(AssertUnwindSafe(check)).map_err(|payload| {
+		let message = message(payload.as_ref());
+		log::error!("Internal error of the verifier: {}", message);
+		message
+	})
+}
+
+/// Runs the check `name` of `entity_name`, reporting a panic as an internal error of the check
+pub fn check<F>(entity_name: &str, name: &str, url: &Url, check: F) -> Vec<EndpointReport>
+where
+	F: FnOnce() -> Vec<EndpointReport>,
+{
+	let start = Instant::now();
+	let reports = catch(check).unwrap_or_else(|message| {
+		vec![EndpointReport::new(entity_name, name, url.clone())
+			.category(Category::Framework)
+			.null(VerifierError::Internal(message))]
+	});
+	timed(start.elapsed(), reports)
+}
+
+/// Shares the `duration` of a check between its reports
+pub fn timed(duration: Duration, reports: Vec<EndpointReport>) -> Vec<EndpointReport> {
+	let share = duration / reports.len().max(1) as u32;
+	reports.into_iter().map(|report| report.duration(share)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+
+	use url::Url;
+
+	use crate::isolation;
+	use crate::output::EndpointReport;
+	use crate::pool::ValidationPool;
+
+	#[test]
+	fn test_catch() {
+		assert_eq!(isolation::catch(|| 1), Ok(1));
+		assert_eq!(
+			isolation::catch(|| -> usize { panic!("boom") }),
+			Err("boom".to_string())
+		);
+		let id = "ind1";
+		assert_eq!(
+			isolation::catch(|| -> usize { panic!("no record {}", id) }),
+			Err("no record ind1".to_string())
+		);
+	}
+
+	#[test]
+	fn test_panicking_check() {
+		let url = Url::parse("https://beacon.example.org/individuals").unwrap();
+		let pool = ValidationPool::new(1);
+		for entity_name in ["Individual", "Biosample", "Run"] {
+			let url = url.clone();
+			pool.submit(move || {
+				let name = format!("{} all entries", entity_name);
+				isolation::check(entity_name, &name, &url, || {
+					if entity_name == "Biosample" {
+						panic!("unexpected response");
+					}
+					vec![EndpointReport::new(entity_name, &name, url.clone()).ok(None)]
+				})
+			});
+		}
+		// The only worker survived the panic
+		let mut reports = pool.finish();
+		reports.sort_by_key(|report| report.entity_name.clone());
+		assert_eq!(reports.len(), 3);
+		assert_eq!(reports[0].entity_name, "Biosample");
+		assert_eq!(reports[0].valid, None);
+		assert_eq!(
+			reports[0].error.as_ref().unwrap().to_string(),
+			"Internal error of the verifier: unexpected response"
+		);
+		assert!(reports[1..].iter().all(|report| report.valid == Some(true)));
+	}
+}
@@ -0,0 +1,204 @@
+//! Report of a run in the JUnit XML format
+//!
+//! CI servers (GitLab, Jenkins, ...) show the test reports of the pipelines in
+//! this format. Each entity is a test suite and each of its checks a test case,
+//! failed, in error or skipped like the check, so the failures of a beacon
+//! show up in the pipeline next to the ones of its code. The scope of the run
+//! is a test suite of its own, with what was not verified as skipped test
+//! cases, and the depth of the checks of each entity is a property of its suite.
+
+use std::fmt::Write;
+
+use crate::output::{BeaconOutput, EndpointOutput, Outcome};
+use crate::scope::Scope;
+
+/// Name of the test suite of the scope of the run
+const SCOPE: &str = "Scope";
+
+/// Escapes the text and the attribute values of XML (and HTML) documents
+pub fn escape(value: &str) -> String {
+	value
+		.replace('&', "&amp;")
+		.replace('<', "&lt;")
+		.replace('>', "&gt;")
+		.replace('"', "&quot;")
+		.replace('\'', "&apos;")
+}
+
+/// Time of a check, in seconds
+fn seconds(report: &EndpointOutput) -> f64 {
+	report.duration_ms.or(report.elapsed_ms).unwrap_or_default() as f64 / 1000.0
+}
+
+fn count(reports: &[&EndpointOutput], outcome: Outcome) -> usize {
+	reports.iter().filter(|report| report.outcome() == outcome).count()
+}
+
+/// Attributes counting the test cases of `reports`, and the `not_verified` ones of the scope
+fn counts(reports: &[&EndpointOutput], not_verified: usize) -> String {
+	format!(
+		"tests=\"{}\" failures=\"{}\" errors=\"{}\" skipped=\"{}\" time=\"{:.3}\"",
+		reports.len() + not_verified,
+		count(reports, Outcome::Failed),
+		count(reports, Outcome::Error),
+		count(reports, Outcome::Skipped) + not_verified,
+		reports.iter().map(|report| seconds(report)).sum::<f64>()
+	)
+}
+
+fn properties(xml: &mut String, properties: &[(&str, String)]) {
+	let _ = writeln!(xml, "    <properties>");
+	for (name, value) in properties {
+		let _ = writeln!(xml, "      <property name=\"{}\" value=\"{}\"/>", name, escape(value));
+	}
+	let _ = writeln!(xml, "    </properties>");
+}
+
+fn scope(xml: &mut String, scope: &Scope, timestamp: &str) {
+	let _ = writeln!(
+		xml,
+		"  <testsuite name=\"{}\" timestamp=\"{}\" {}>",
+		SCOPE,
+		timestamp,
+		counts(&[], scope.not_verified.len())
+	);
+	properties(xml, &[("layers", scope.layers()), ("access", scope.access.to_string())]);
+	for not_verified in &scope.not_verified {
+		let _ = writeln!(
+			xml,
+			"    <testcase name=\"{}\" classname=\"{}\" time=\"0.000\">",
+			escape(not_verified),
+			SCOPE
+		);
+		let _ = writeln!(xml, "      <skipped message=\"Not verified\"/>\n    </testcase>");
+	}
+	let _ = writeln!(xml, "  </testsuite>");
+}
+
+fn testcase(xml: &mut String, entity_name: &str, report: &EndpointOutput) {
+	let _ = write!(
+		xml,
+		"    <testcase name=\"{}\" classname=\"{}.{}\" time=\"{:.3}\"",
+		escape(&report.name),
+		escape(entity_name),
+		report.category,
+		seconds(report)
+	);
+	let error = report.error.as_deref().unwrap_or_default().trim_end();
+	// The first line is shown in the lists of the CI servers, the whole error in the details
+	let message = escape(error.lines().next().unwrap_or_default());
+	let result = match report.outcome() {
+		Outcome::Passed => None,
+		Outcome::Failed => Some(format!("<failure message=\"{}\">{}</failure>", message, escape(error))),
+		Outcome::Error => Some(format!("<error message=\"{}\">{}</error>", message, escape(error))),
+		Outcome::Skipped => Some(format!("<skipped message=\"{}\"/>", message)),
+	};
+	if result.is_none() && report.warnings.is_empty() {
+		let _ = writeln!(xml, "/>");
+		return;
+	}
+	let _ = writeln!(xml, ">");
+	if let Some(result) = result {
+		let _ = writeln!(xml, "      {}", result);
+	}
+	if !report.warnings.is_empty() {
+		let _ = writeln!(
+			xml,
+			"      <system-out>{}</system-out>",
+			escape(&report.warnings.join("\n"))
+		);
+	}
+	let _ = writeln!(xml, "    </testcase>");
+}
+
+/// Renders the checks of the output as JUnit test cases, one test suite per entity
+pub fn junit(output: &BeaconOutput) -> String {
+	let mut xml = String::new();
+	let _ = writeln!(xml, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>");
+	let all = output.entities.values().flatten().collect::<Vec<_>>();
+	let not_verified = output.scope.as_ref().map_or(0, |scope| scope.not_verified.len());
+	let _ = writeln!(
+		xml,
+		"<testsuites name=\"{}\" {}>",
+		escape(output.url.as_str()),
+		counts(&all, not_verified)
+	);
+	let timestamp = output.last_updated.format("%Y-%m-%dT%H:%M:%S").to_string();
+	if let Some(scope) = &output.scope {
+		self::scope(&mut xml, scope, &timestamp);
+	}
+	for (entity_name, reports) in &output.entities {
+		let reports = reports.iter().collect::<Vec<_>>();
+		let _ = writeln!(
+			xml,
+			"  <testsuite name=\"{}\" timestamp=\"{}\" {}>",
+			escape(entity_name),
+			timestamp,
+			counts(&reports, 0)
+		);
+		if let Some(depth) = output.scope.as_ref().and_then(|scope| scope.entities.get(entity_name)) {
+			properties(&mut xml, &[("depth", depth.to_string())]);
+		}
+		for report in reports {
+			testcase(&mut xml, entity_name, report);
+		}
+		let _ = writeln!(xml, "  </testsuite>");
+	}
+	let _ = writeln!(xml, "</testsuites>");
+	xml
+}
+
+#[cfg(test)]
+mod tests {
+
+	use serde_json::json;
+	use wiremock::ResponseTemplate;
+
+	use crate::junit;
+	use crate::testing::{self, Behavior, MockBeacon};
+
+	#[test]
+	fn test_junit() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let mut response = beacon.response("/individuals");
+		response["response"]["resultSets"][0]["results"][0]["sex"] = json!("female");
+		beacon.mount("/individuals", ResponseTemplate::new(200).set_body_json(response));
+		let output = beacon.verify();
+		let xml = junit::junit(&output);
+		assert!(xml.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<testsuites name=\"http://"));
+		assert!(xml.ends_with("</testsuites>\n"));
+		assert_eq!(xml.matches("<testsuite ").count(), output.entities.len());
+		let cases = output.entities.values().flatten().count();
+		assert_eq!(xml.matches("<testcase ").count(), cases);
+		assert!(xml.contains("<testcase name=\"Individual all entries\" classname=\"Individual.model\""));
+		let failure = "<failure message=\"Response does not match the schema: value is not of type &quot;object&quot;";
+		assert!(xml.contains(failure), "{}", xml);
+		assert!(xml.contains("tests=\"7\" failures=\"1\" errors=\"0\" skipped=\"0\""));
+
+		// What was not verified is skipped
+		let output = beacon
+			.beacon()
+			.configuration(testing::configuration(&beacon))
+			.validate();
+		let scope = output.scope.clone().unwrap();
+		let xml = junit::junit(&output);
+		assert_eq!(xml.matches("<testsuite ").count(), output.entities.len() + 1);
+		assert_eq!(
+			xml.matches("<skipped message=\"Not verified\"/>").count(),
+			scope.not_verified.len()
+		);
+		let counts = format!(
+			"tests=\"{0}\" failures=\"0\" errors=\"0\" skipped=\"{0}\"",
+			scope.not_verified.len()
+		);
+		assert!(xml.contains("<testsuite name=\"Scope\" timestamp="), "{}", xml);
+		assert!(xml.contains(&counts), "{}", xml);
+		assert!(
+			xml.contains("<property name=\"access\" value=\"anonymous\"/>"),
+			"{}",
+			xml
+		);
+		assert!(xml.contains("<property name=\"depth\" value=\"sampled\"/>"), "{}", xml);
+		assert_eq!(junit::escape("'<a & b>'"), "&apos;&lt;a &amp; b&gt;&apos;");
+	}
+}
@@ -0,0 +1,334 @@
+//! Classification of the latency of the requests
+//!
+//! Every request that got a response is fast or slow against the `--slow-ms`
+//! threshold, and the ones that got no response within `--timeout-ms` timed
+//! out. The summary rolls them up per entity, apart from the outcome of the
+//! checks, as a first performance picture without a load test.
+//!
+//! Every check also reports how long it took (`duration_ms`, its requests and
+//! the validation of the responses, shared between its reports), and the summary lists the slowest ones and
+//! the time spent per category, to point at where a run spends its time.
+//!
+//! The targets of the configuration file can also declare latency budgets per
+//! entity (and granularity), checked with a query of their own:
+//!
+//! ```toml
+//! [[beacons.production.latency-budgets]]
+//! entity = "GenomicVariation"
+//! granularity = "count"
+//! budget-ms = 5000
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::client::Client;
+use crate::error::VerifierError;
+use crate::humanize::TimeStyle;
+use crate::interface::Granularity;
+use crate::model::Entity;
+use crate::output::{BeaconOutput, Category, EndpointOutput, EndpointReport};
+use crate::utils;
+
+/// Name of the latency budget checks, after the name of the entity
+pub const BUDGET_CHECK_NAME: &str = "latency budget";
+
+/// Default threshold of the slow requests
+pub const DEFAULT_SLOW: Duration = Duration::from_secs(1);
+
+/// Checks listed in the slowest checks of the summary
+pub const SLOWEST: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Latency {
+	Fast,
+	Slow,
+	Timeout,
+}
+
+impl Latency {
+	pub const ALL: [Self; 3] = [Self::Fast, Self::Slow, Self::Timeout];
+
+	/// Latency of a request that took `elapsed` to respond, if it did
+	pub fn classify(elapsed: Option<Duration>, timed_out: bool, slow: Duration) -> Option<Self> {
+		match elapsed {
+			_ if timed_out => Some(Self::Timeout),
+			Some(elapsed) if elapsed >= slow => Some(Self::Slow),
+			Some(_) => Some(Self::Fast),
+			None => None,
+		}
+	}
+}
+
+impl std::fmt::Display for Latency {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Fast => write!(f, "fast"),
+			Self::Slow => write!(f, "slow"),
+			Self::Timeout => write!(f, "timeout"),
+		}
+	}
+}
+
+/// Time an entity has to answer a query
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct LatencyBudget {
+	/// Name of the entity (e.g. `GenomicVariation`)
+	pub entity: String,
+	/// Requested granularity, the default one of the beacon if none
+	#[serde(default)]
+	pub granularity: Option<Granularity>,
+	pub budget_ms: u64,
+}
+
+impl LatencyBudget {
+	pub fn name(&self) -> String {
+		match self.granularity {
+			Some(granularity) => format!("{} {} ({})", self.entity, BUDGET_CHECK_NAME, granularity),
+			None => format!("{} {}", self.entity, BUDGET_CHECK_NAME),
+		}
+	}
+
+	/// Queries the endpoint with all the entries of `entity`, reporting how long it took to answer
+	pub fn check(&self, client: &Client, root_url: &Url, entity: &Entity) -> EndpointReport {
+		let mut url = utils::url_join(root_url, &entity.url);
+		if let Some(granularity) = self.granularity {
+			url.query_pairs_mut()
+				.append_pair("requestedGranularity", &granularity.to_string());
+		}
+		let report = EndpointReport::new(&entity.name, &self.name(), url.clone())
+			.category(Category::Framework)
+			.check("framework.latency-budget");
+		let budget = Duration::from_millis(self.budget_ms);
+		match client.query(&entity.name, &url) {
+			Ok(response) if response.elapsed > budget => report
+				.error(VerifierError::OverBudget(format!(
+					"answered in {} ms, over the budget of {} ms",
+					response.elapsed.as_millis(),
+					self.budget_ms
+				)))
+				.elapsed(response.elapsed),
+			Ok(response) => report.ok(None).elapsed(response.elapsed),
+			Err(e) if e.is_timeout() => report.error(VerifierError::OverBudget(format!(
+				"no answer within the timeout, over the budget of {} ms",
+				self.budget_ms
+			))),
+			Err(e) => report.null(e),
+		}
+	}
+}
+
+/// Number of requests of each latency per entity, counting once the endpoints checked in both categories
+pub fn rollup(output: &BeaconOutput) -> BTreeMap<&str, BTreeMap<Latency, usize>> {
+	output
+		.entities
+		.iter()
+		.filter_map(|(entity_name, reports)| {
+			let requests = reports
+				.iter()
+				.filter_map(|report| report.latency.map(|latency| (report.name.as_str(), latency)))
+				.collect::<BTreeSet<_>>();
+			if requests.is_empty() {
+				return None;
+			}
+			let mut counts = Latency::ALL
+				.iter()
+				.map(|latency| (*latency, 0))
+				.collect::<BTreeMap<_, _>>();
+			for (_, latency) in requests {
+				*counts.entry(latency).or_default() += 1;
+			}
+			Some((entity_name.as_str(), counts))
+		})
+		.collect()
+}
+
+/// Timed checks from the slowest, with the time of the endpoints checked in both categories added up
+pub fn slowest(output: &BeaconOutput) -> Vec<(&str, &EndpointOutput, Duration)> {
+	let mut durations = BTreeMap::<_, (&EndpointOutput, Duration)>::new();
+	for (entity_name, reports) in &output.entities {
+		for report in reports {
+			if let Some(duration_ms) = report.duration_ms {
+				let key = (entity_name.as_str(), report.name.as_str(), report.url.as_str());
+				durations.entry(key).or_insert((report, Duration::ZERO)).1 += Duration::from_millis(duration_ms);
+			}
+		}
+	}
+	let mut checks = durations
+		.into_iter()
+		.map(|((entity_name, _, _), (report, duration))| (entity_name, report, duration))
+		.collect::<Vec<_>>();
+	checks.sort_by_key(|(_, _, duration)| std::cmp::Reverse(*duration));
+	checks.truncate(SLOWEST);
+	checks
+}
+
+/// Time spent on the checks of each category, the time of a check shared between the categories of its reports
+pub fn category_totals(output: &BeaconOutput) -> BTreeMap<Category, Duration> {
+	let mut totals = BTreeMap::<_, Duration>::new();
+	for report in output.entities.values().flatten() {
+		if let Some(duration_ms) = report.duration_ms {
+			*totals.entry(report.category).or_default() += Duration::from_millis(duration_ms);
+		}
+	}
+	totals
+}
+
+pub fn summary(output: &BeaconOutput, time_style: TimeStyle) {
+	for (entity_name, counts) in rollup(output) {
+		let counts = counts
+			.iter()
+			.map(|(latency, count)| format!("{} {}", count, latency))
+			.collect::<Vec<_>>()
+			.join(", ");
+		log::info!("{} latency: {}", entity_name, counts);
+	}
+	let slowest = slowest(output);
+	if !slowest.is_empty() {
+		log::info!("Slowest checks:");
+		for (entity_name, report, duration) in slowest {
+			log::info!("\t{} {} {}", time_style.duration(duration), entity_name, report.name);
+		}
+	}
+	for (category, total) in category_totals(output) {
+		log::info!("Time spent on the {} checks: {}", category, time_style.duration(total));
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use std::time::Duration;
+
+	use wiremock::ResponseTemplate;
+
+	use crate::client::Client;
+	use crate::interface::Granularity;
+	use crate::latency::{self, Latency, LatencyBudget};
+	use crate::output::Category;
+	use crate::testing::{Behavior, MockBeacon};
+
+	#[test]
+	fn test_classify() {
+		let slow = Duration::from_millis(500);
+		assert_eq!(
+			Latency::classify(Some(Duration::from_millis(20)), false, slow),
+			Some(Latency::Fast)
+		);
+		assert_eq!(Latency::classify(Some(slow), false, slow), Some(Latency::Slow));
+		assert_eq!(Latency::classify(None, true, slow), Some(Latency::Timeout));
+		assert_eq!(Latency::classify(None, false, slow), None);
+	}
+
+	#[test]
+	fn test_rollup() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let template = beacon.response("/biosamples");
+		beacon.mount(
+			"/biosamples",
+			ResponseTemplate::new(200)
+				.set_body_json(template)
+				.set_delay(Duration::from_millis(300)),
+		);
+		beacon.mount(
+			"/biosamples/bio1",
+			ResponseTemplate::new(200).set_delay(Duration::from_secs(2)),
+		);
+		let output = beacon
+			.beacon_with_client(Client::new().timeout(Duration::from_secs(1)))
+			.slow_threshold(Duration::from_millis(200))
+			.validate();
+		let rollup = latency::rollup(&output);
+		// All entries (framework and model checks) and related biosamples, then the single entry
+		assert_eq!(rollup["Individual"][&Latency::Slow], 0);
+		assert_eq!(rollup["Biosample"][&Latency::Slow], 1);
+		assert_eq!(rollup["Biosample"][&Latency::Timeout], 1);
+		assert_eq!(rollup["Biosample"][&Latency::Fast], 0);
+	}
+
+	#[test]
+	fn test_slowest() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let template = beacon.response("/biosamples");
+		beacon.mount(
+			"/biosamples",
+			ResponseTemplate::new(200)
+				.set_body_json(template)
+				.set_delay(Duration::from_millis(300)),
+		);
+		let output = beacon.verify();
+		let slowest = latency::slowest(&output);
+		assert!(slowest.len() <= latency::SLOWEST);
+		let (entity_name, report, duration) = slowest[0];
+		assert_eq!(
+			(entity_name, report.name.as_str()),
+			("Biosample", "Biosample all entries")
+		);
+		assert!(duration >= Duration::from_millis(300));
+		// Listed once, with the time of both categories
+		assert!(slowest[1..].iter().all(|(_, other, _)| other.name != report.name));
+		let all_entries = output.entities["Biosample"]
+			.iter()
+			.filter(|other| other.name == report.name && other.url == report.url)
+			.collect::<Vec<_>>();
+		assert_eq!(all_entries.len(), 2);
+		assert!(all_entries.iter().all(|report| report.duration_ms.unwrap() < 300));
+		// The response is counted once across the categories
+		let totals = latency::category_totals(&output);
+		assert!(totals[&Category::Framework] + totals[&Category::Model] >= Duration::from_millis(300));
+		let every_check = output
+			.entities
+			.values()
+			.flatten()
+			.filter_map(|report| report.duration_ms)
+			.sum::<u64>();
+		assert_eq!(totals.values().sum::<Duration>(), Duration::from_millis(every_check));
+	}
+
+	#[test]
+	fn test_latency_budgets() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let template = beacon.response("/biosamples");
+		beacon.mount(
+			"/biosamples",
+			ResponseTemplate::new(200)
+				.set_body_json(template)
+				.set_delay(Duration::from_millis(300)),
+		);
+		let budget = |entity: &str, budget_ms| LatencyBudget {
+			entity: entity.into(),
+			granularity: Some(Granularity::Count),
+			budget_ms,
+		};
+		let output = beacon
+			.beacon()
+			.latency_budgets(vec![
+				budget("Individual", 1000),
+				budget("Biosample", 100),
+				budget("Run", 100),
+			])
+			.validate();
+		let report = |entity: &str| {
+			output.entities[entity]
+				.iter()
+				.find(|report| report.name == format!("{} latency budget (count)", entity))
+				.unwrap()
+		};
+		assert_eq!(report("Individual").valid, Some(true));
+		assert!(report("Individual")
+			.url
+			.query()
+			.unwrap()
+			.contains("requestedGranularity=count"));
+		let biosample = report("Biosample");
+		assert_eq!(biosample.valid, Some(false));
+		assert!(biosample.elapsed_ms.unwrap() >= 300);
+		assert!(biosample.error.as_ref().unwrap().contains("over the budget of 100 ms"));
+		assert!(!output.entities.contains_key("Run"));
+	}
+}
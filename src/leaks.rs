@@ -0,0 +1,113 @@
+//! Internals shown by the error responses
+//!
+//! An error response (4xx or 5xx) must not tell more than what went wrong for
+//! the client: stack traces, SQL fragments or the debug pages of web frameworks
+//! give away the stack of the beacon, its database schema and sometimes its
+//! configuration. The bodies of the error responses to the negative tests are
+//! searched for the marks of these, and the findings are reported as security
+//! warnings of the check that got them.
+
+use crate::Json;
+
+/// Marks of the internals, found when every fragment of one is in the body
+const MARKS: [(&str, &[&str]); 22] = [
+	("Java stack trace", &["\tat ", ".java:"]),
+	("Python traceback", &["Traceback (most recent call last)"]),
+	(".NET stack trace", &[" at ", ".cs:line "]),
+	("Node.js stack trace", &["    at ", ".js:"]),
+	("Go panic", &["goroutine ", "[running]"]),
+	("PHP stack trace", &["Stack trace:", "#0 "]),
+	("Ruby backtrace", &[".rb:", ":in `"]),
+	("Rust panic", &["panicked at"]),
+	("SQL error", &["SQLSTATE"]),
+	("SQL error", &["You have an error in your SQL syntax"]),
+	("SQL error", &["syntax error at or near"]),
+	("SQL error", &["ORA-0"]),
+	("SQL error", &["psycopg2."]),
+	("SQL error", &["sqlite3.OperationalError"]),
+	("SQL query", &["SELECT ", " FROM ", " WHERE "]),
+	("Django debug page", &["DEBUG = True"]),
+	("Werkzeug debugger", &["Werkzeug Debugger"]),
+	("Spring Boot error page", &["Whitelabel Error Page"]),
+	("Laravel debug page", &["Whoops, looks like something went wrong"]),
+	("Rails debug page", &["Action Controller: Exception caught"]),
+	("ASP.NET error page", &["Server Error in '", "' Application"]),
+	("Tomcat error page", &["Apache Tomcat/", "Exception"]),
+];
+
+/// Strings of `json` unescaped, one per line
+pub fn strings(json: &Json) -> String {
+	fn collect<'a>(json: &'a Json, found: &mut Vec<&'a str>) {
+		match json {
+			Json::String(string) => found.push(string),
+			Json::Array(values) => values.iter().for_each(|value| collect(value, found)),
+			Json::Object(object) => object.values().for_each(|value| collect(value, found)),
+			_ => (),
+		}
+	}
+	let mut found = Vec::new();
+	collect(json, &mut found);
+	found.join("\n")
+}
+
+/// Text of `body`, the strings of a JSON body unescaped (a stack trace is often in a `trace` property)
+pub fn text(body: &[u8]) -> String {
+	match serde_json::from_slice::<Json>(body) {
+		Ok(json) => strings(&json),
+		Err(_) => String::from_utf8_lossy(body).into_owned(),
+	}
+}
+
+/// Internals shown in `body` (e.g. `Python traceback`), each once
+pub fn scan(body: &[u8]) -> Vec<&'static str> {
+	let text = text(body);
+	let mut leaks = Vec::new();
+	for (leak, fragments) in MARKS {
+		if !leaks.contains(&leak) && fragments.iter().all(|fragment| text.contains(fragment)) {
+			leaks.push(leak);
+		}
+	}
+	leaks
+}
+
+/// Security warnings about the internals shown by a response, if it is an error response
+pub fn warnings(status: reqwest::StatusCode, body: &[u8]) -> Vec<String> {
+	if !status.is_client_error() && !status.is_server_error() {
+		return Vec::new();
+	}
+	scan(body)
+		.into_iter()
+		.map(|leak| format!("Security: the error response ({}) shows a {}", status.as_u16(), leak))
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+
+	use reqwest::StatusCode;
+	use serde_json::json;
+
+	use crate::leaks;
+
+	#[test]
+	fn test_scan() {
+		let traceback = "Traceback (most recent call last):\n  File \"app.py\", line 12, in info\nKeyError: 'meta'";
+		assert_eq!(leaks::scan(traceback.as_bytes()), vec!["Python traceback"]);
+		let body = json!({
+			"error": { "errorCode": 500, "errorMessage": "Internal error" },
+			"trace": "org.postgresql.util.PSQLException: ERROR: syntax error at or near \"'\"\n\tat \
+					  org.beacon.Repository.find(Repository.java:42)",
+		});
+		let body = serde_json::to_vec(&body).unwrap();
+		assert_eq!(leaks::scan(&body), vec!["Java stack trace", "SQL error"]);
+
+		let clean = json!({ "error": { "errorCode": 400, "errorMessage": "Bad request: the body is not JSON" } });
+		assert!(leaks::scan(&serde_json::to_vec(&clean).unwrap()).is_empty());
+		// Only the error responses
+		assert!(leaks::warnings(StatusCode::OK, traceback.as_bytes()).is_empty());
+		assert_eq!(
+			leaks::warnings(StatusCode::INTERNAL_SERVER_ERROR, traceback.as_bytes()),
+			vec!["Security: the error response (500) shows a Python traceback"]
+		);
+	}
+}
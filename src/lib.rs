@@ -0,0 +1,113 @@
+//! Verification of the compliance of Beacon v2 implementations
+//!
+//! The checks of the `beacon-verifier` command are available to the services
+//! embedding them (e.g. a registry of beacons): [`Verifier`] loads the schemas
+//! of the specification and verifies a beacon with them, and the report it
+//! returns is the [`BeaconOutput`] the command prints. The modules give access
+//! to the parts of the verification (the client, the framework and the model,
+//! the checks and the renderers of the reports) for finer control.
+//!
+//! ```no_run
+//! use beacon_verifier::Verifier;
+//! use url::Url;
+//!
+//! let url = Url::parse("https://beacon-url.com/").unwrap();
+//! let output = Verifier::new(&url).jobs(4).verify().unwrap();
+//! println!("{}", output.to_json().unwrap());
+//! ```
+
+#![allow(
+	clippy::module_name_repetitions,
+	clippy::unused_self,
+	clippy::missing_const_for_fn, // TODO: Remove when #![feature(const_precise_live_drops)] gets stabilized
+	clippy::struct_excessive_bools
+)]
+// The JSON Schema of the configuration file nests deeper than `json!` expands by default
+#![recursion_limit = "256"]
+
+pub mod adaptive;
+pub mod anonymize;
+pub mod artifacts;
+pub mod auth;
+pub mod beacon;
+pub mod cache;
+pub mod canonical;
+pub mod catalog;
+pub mod charset;
+pub mod client;
+pub mod clock;
+pub mod collections;
+pub mod config;
+pub mod consistency;
+pub mod counts;
+pub mod datause;
+pub mod deprecated;
+pub mod diff;
+pub mod discovery;
+pub mod echoes;
+pub mod endpoint;
+pub mod entry_types;
+pub mod error;
+pub mod filters;
+pub mod fixtures;
+pub mod formats;
+pub mod framework;
+pub mod har;
+pub mod hgvs;
+pub mod history;
+pub mod html;
+pub mod humanize;
+pub mod identifiers;
+pub mod incremental;
+pub mod init;
+pub mod interface;
+pub mod interrupt;
+pub mod isolation;
+pub mod junit;
+pub mod latency;
+pub mod leaks;
+pub mod limits;
+pub mod logging;
+pub mod maintenance;
+pub mod metrics;
+pub mod model;
+pub mod negative;
+pub mod network;
+pub mod notify;
+pub mod numeric;
+pub mod offline;
+pub mod ontologies;
+pub mod output;
+pub mod pagination;
+pub mod policy;
+pub mod pool;
+pub mod post;
+pub mod preset;
+pub mod probe;
+pub mod protocol;
+pub mod references;
+pub mod render;
+pub mod resolver;
+pub mod robustness;
+pub mod sandbox;
+pub mod sarif;
+pub mod scheduler;
+pub mod scope;
+pub mod shape;
+pub mod spec;
+pub mod syntax;
+#[cfg(any(test, feature = "test-support"))]
+#[allow(dead_code)]
+pub mod testing;
+pub mod timing;
+pub mod tls;
+pub mod usage;
+pub mod utils;
+pub mod variants;
+pub mod verifier;
+
+pub use crate::error::VerifierError;
+pub use crate::output::BeaconOutput;
+pub use crate::verifier::Verifier;
+
+pub type Json = serde_json::Value;
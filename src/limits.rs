@@ -0,0 +1,324 @@
+//! Limits declared by the beacon, and its behavior at their boundaries
+//!
+//! Beacons cap the size of the pages and the number of filters of a query.
+//! They declare those limits in the response of `/configuration` or in the
+//! `meta` of the responses (the `limit` of the `receivedRequestSummary` being
+//! the default page size when the query sets none). The verifier reports them
+//! and queries the endpoints with all the entries exactly at and just beyond
+//! each limit: at the limit the query must be answered, beyond it the beacon
+//! must reject it (4xx) or cap it, but never crash or return more.
+
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::client::Client;
+use crate::error::VerifierError;
+use crate::model::Entity;
+use crate::output::{Category, EndpointReport};
+use crate::{echoes, utils, Json};
+
+/// Properties declaring the largest page
+const MAX_PAGE_SIZE_PROPERTIES: [&str; 2] = ["maxLimit", "maxPageSize"];
+
+/// Properties declaring the page size of the queries without a limit
+const DEFAULT_PAGE_SIZE_PROPERTIES: [&str; 2] = ["defaultLimit", "defaultPageSize"];
+
+/// Properties declaring the most filters of a query
+const MAX_FILTERS_PROPERTIES: [&str; 1] = ["maxFilters"];
+
+/// Name of the checks, after the name of the entity
+pub const CHECK_NAMES: [&str; 5] = [
+	"default page size",
+	"page size at the limit",
+	"page size beyond the limit",
+	"filters at the limit",
+	"filters beyond the limit",
+];
+
+/// Id of the checks in the catalog, in the order of their names
+const CHECK_IDS: [&str; 5] = [
+	"framework.limits.default-page-size",
+	"framework.limits.page-size",
+	"framework.limits.page-size",
+	"framework.limits.filters",
+	"framework.limits.filters",
+];
+
+/// Filter repeated in the queries with many filters, any term works since only the count matters
+const FILTER: &str = "NCIT:C16576";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeclaredLimits {
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub default_page_size: Option<u64>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub max_page_size: Option<u64>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub max_filters: Option<u64>,
+}
+
+/// First of the `properties` found in `json`, at any depth
+fn declared(json: &Json, properties: &[&str]) -> Option<u64> {
+	match json {
+		Json::Object(object) => properties
+			.iter()
+			.find_map(|property| object.get(*property).and_then(Json::as_u64))
+			.or_else(|| object.values().find_map(|value| declared(value, properties))),
+		Json::Array(values) => values.iter().find_map(|value| declared(value, properties)),
+		_ => None,
+	}
+}
+
+impl DeclaredLimits {
+	/// Reads the limits of the responses, the first ones that declare them winning
+	pub fn parse<'a>(responses: impl IntoIterator<Item = &'a Json>) -> Self {
+		responses.into_iter().fold(Self::default(), |limits, json| {
+			let received = json
+				.pointer("/meta/receivedRequestSummary/pagination/limit")
+				.and_then(Json::as_u64)
+				.filter(|limit| *limit > 0);
+			Self {
+				default_page_size: limits
+					.default_page_size
+					.or(received)
+					.or_else(|| declared(json, &DEFAULT_PAGE_SIZE_PROPERTIES)),
+				max_page_size: limits
+					.max_page_size
+					.or_else(|| declared(json, &MAX_PAGE_SIZE_PROPERTIES)),
+				max_filters: limits.max_filters.or_else(|| declared(json, &MAX_FILTERS_PROPERTIES)),
+			}
+		})
+	}
+
+	/// Fetches `/configuration` and the endpoint with all the entries of the first entity
+	pub fn fetch(client: &Client, root_url: &Url, entities: &[Entity]) -> Self {
+		let mut configuration_url = root_url.clone();
+		configuration_url.set_path(&format!("{}/configuration", root_url.path().trim_end_matches('/')));
+		let entity_url = entities
+			.first()
+			.map(|entity| (entity, utils::url_join(root_url, &entity.url)));
+		let responses = std::iter::once(("Configuration", configuration_url))
+			.chain(entity_url.map(|(entity, url)| (entity.name.as_str(), url)))
+			.filter_map(|(endpoint, url)| client.query(endpoint, &url).ok())
+			.map(|response| response.json)
+			.collect::<Vec<_>>();
+		Self::parse(&responses)
+	}
+
+	pub fn is_empty(&self) -> bool {
+		*self == Self::default()
+	}
+
+	pub fn summary(&self) {
+		if self.is_empty() {
+			log::info!("Declared limits: none");
+			return;
+		}
+		let limits = [
+			("default page size", self.default_page_size),
+			("max page size", self.max_page_size),
+			("max filters", self.max_filters),
+		]
+		.iter()
+		.filter_map(|(name, limit)| limit.map(|limit| format!("{} {}", name, limit)))
+		.collect::<Vec<_>>();
+		log::info!("Declared limits: {}", limits.join(", "));
+	}
+
+	/// Queries at and beyond the declared limits on the endpoint with all the entries of `entity`
+	pub fn check(&self, client: &Client, root_url: &Url, entity: &Entity) -> Vec<EndpointReport> {
+		let url = utils::url_join(root_url, &entity.url);
+		let mut reports = Vec::new();
+		if let Some(size) = self.default_page_size {
+			let query = Query::new(entity, &url, 0, &[]);
+			reports.push(query.run(client, |answer| answer.at_most(size)));
+		}
+		if let Some(size) = self.max_page_size {
+			let at = Query::new(entity, &url, 1, &[("limit", size.to_string())]);
+			reports.push(at.run(client, |answer| answer.at_most(size)));
+			let beyond = Query::new(entity, &url, 2, &[("limit", (size + 1).to_string())]);
+			reports.push(beyond.run(client, |answer| answer.rejected_or(|answer| answer.at_most(size))));
+		}
+		if let Some(count) = self.max_filters {
+			let filters = |count| vec![FILTER; count as usize].join(",");
+			let at = Query::new(entity, &url, 3, &[("filters", filters(count))]);
+			reports.push(at.run(client, |answer| answer.answered().map(|_| ())));
+			let beyond = Query::new(entity, &url, 4, &[("filters", filters(count + 1))]);
+			reports.push(beyond.run(client, |answer| {
+				answer.rejected_or(|answer| answer.answered().map(|_| ()))
+			}));
+		}
+		reports
+	}
+}
+
+/// Query of a boundary test
+struct Query {
+	report: EndpointReport,
+	url: Url,
+}
+
+/// Response of the beacon to a boundary test
+struct Answer {
+	status: StatusCode,
+	json: Option<Json>,
+}
+
+impl Answer {
+	/// The query was answered, with a JSON response
+	fn answered(&self) -> Result<&Json, String> {
+		if !self.status.is_success() {
+			return Err(format!("did not answer the query (status {})", self.status));
+		}
+		self.json
+			.as_ref()
+			.ok_or_else(|| "answered, but the response is not JSON".to_string())
+	}
+
+	/// The query was answered with at most `size` records
+	fn at_most(&self, size: u64) -> Result<(), String> {
+		match utils::records(self.answered()?) {
+			Ok(records) if records.len() as u64 > size => Err(format!(
+				"returned {} records, more than the {} declared",
+				records.len(),
+				size
+			)),
+			_ => Ok(()),
+		}
+	}
+
+	/// The query was rejected with a client error, or `otherwise` holds
+	fn rejected_or(&self, otherwise: impl Fn(&Self) -> Result<(), String>) -> Result<(), String> {
+		if self.status.is_client_error() {
+			Ok(())
+		}
+		else {
+			otherwise(self)
+		}
+	}
+}
+
+impl Query {
+	/// Query of the check at `check` in [`CHECK_NAMES`]
+	fn new(entity: &Entity, url: &Url, check: usize, params: &[(&str, String)]) -> Self {
+		let mut url = url.clone();
+		for (key, value) in params {
+			url.query_pairs_mut().append_pair(key, value);
+		}
+		let report = EndpointReport::new(
+			&entity.name,
+			&format!("{} {}", entity.name, CHECK_NAMES[check]),
+			url.clone(),
+		)
+		.category(Category::Framework)
+		.check(CHECK_IDS[check]);
+		Self { report, url }
+	}
+
+	fn run(mut self, client: &Client, expect: impl Fn(&Answer) -> Result<(), String>) -> EndpointReport {
+		log::info!("Validating {:?}", self.report.name);
+		let answer = client.get_raw(&self.url);
+		if let Ok((status, Some(json))) = &answer {
+			let body = serde_json::to_vec(json).unwrap_or_default();
+			let echoes = echoes::error_response(*status, &body, &client.credentials(), &self.url);
+			self.report = self.report.warnings(echoes);
+		}
+		match answer {
+			Ok((status, json)) => match expect(&Answer { status, json }) {
+				Ok(()) => self.report.ok(None),
+				Err(e) => self
+					.report
+					.error(VerifierError::BadResponse(format!("The beacon {}", e))),
+			},
+			Err(e) => self.report.null(e),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use serde_json::json;
+	use wiremock::{Request, ResponseTemplate};
+
+	use crate::limits::DeclaredLimits;
+	use crate::output::EndpointOutput;
+	use crate::testing::{Behavior, MockBeacon};
+
+	fn report<'a>(reports: &'a [EndpointOutput], name: &str) -> &'a EndpointOutput {
+		reports.iter().find(|report| report.name == name).unwrap()
+	}
+
+	#[test]
+	fn test_parse() {
+		let configuration = json!({ "response": { "pagination": { "maxLimit": 2 }, "maxFilters": 3 } });
+		let entity = json!({ "meta": { "receivedRequestSummary": { "pagination": { "skip": 0, "limit": 1 } } } });
+		let limits = DeclaredLimits::parse(&[configuration, entity]);
+		assert_eq!(limits.default_page_size, Some(1));
+		assert_eq!(limits.max_page_size, Some(2));
+		assert_eq!(limits.max_filters, Some(3));
+		assert!(DeclaredLimits::parse(&[json!({ "meta": {} })]).is_empty());
+	}
+
+	#[test]
+	fn test_boundaries() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let mut configuration = beacon.response("/configuration");
+		configuration["response"]["maxLimit"] = json!(1);
+		configuration["response"]["maxFilters"] = json!(2);
+		beacon.mount(
+			"/configuration",
+			ResponseTemplate::new(200).set_body_json(configuration),
+		);
+		// Ignores the limit, and crashes with too many filters
+		let individuals = beacon.response("/individuals");
+		beacon.mount("/individuals", move |request: &Request| {
+			let filters = request
+				.url
+				.query_pairs()
+				.find(|(key, _)| key == "filters")
+				.map_or(0, |(_, filters)| filters.split(',').count());
+			if filters > 2 {
+				ResponseTemplate::new(500)
+			}
+			else {
+				ResponseTemplate::new(200).set_body_json(individuals.clone())
+			}
+		});
+		let output = beacon.beacon().limits().validate();
+		assert_eq!(
+			output.limits,
+			Some(DeclaredLimits {
+				default_page_size: None,
+				max_page_size: Some(1),
+				max_filters: Some(2),
+			})
+		);
+		let reports = &output.entities["Individual"];
+		let e = report(reports, "Individual page size at the limit")
+			.error
+			.clone()
+			.unwrap();
+		assert!(e.contains("returned 2 records, more than the 1 declared"), "{}", e);
+		assert_eq!(
+			report(reports, "Individual page size beyond the limit").valid,
+			Some(false)
+		);
+		assert_eq!(report(reports, "Individual filters at the limit").valid, Some(true));
+		let e = report(reports, "Individual filters beyond the limit")
+			.error
+			.clone()
+			.unwrap();
+		assert!(e.contains("did not answer the query (status 500"), "{}", e);
+		// The biosamples do not mind the filters
+		let reports = &output.entities["Biosample"];
+		assert_eq!(report(reports, "Biosample filters beyond the limit").valid, Some(true));
+
+		let output = MockBeacon::start(Behavior::Conformant).beacon().limits().validate();
+		assert_eq!(output.limits, Some(DeclaredLimits::default()));
+		assert!(output.entities["Individual"]
+			.iter()
+			.all(|report| !report.name.contains("limit")));
+	}
+}
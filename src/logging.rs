@@ -0,0 +1,274 @@
+//! Logger with per-module filters and an optional file for the HTTP logs
+//!
+//! Filters follow the syntax of `RUST_LOG` (e.g. `http=debug,validation=warn`),
+//! where the first part of each directive can be one of the groups of modules
+//! below or a module path. A bare name (e.g. `hgvs` or `reqwest`) applies both
+//! to the module of the verifier and to the crate of that name.
+
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+
+use log::{LevelFilter, Log, Metadata, Record};
+
+use crate::anonymize::Anonymizer;
+use crate::auth::Secrets;
+
+/// Groups of modules that can be filtered together
+const GROUPS: [(&str, &[&str]); 5] = [
+	("http", &HTTP_MODULES),
+	(
+		"validation",
+		&[
+			"beacon_verifier::beacon",
+			"beacon_verifier::robustness",
+			"beacon_verifier::pagination",
+			"beacon_verifier::collections",
+			"beacon_verifier::counts",
+			"beacon_verifier::limits",
+			"beacon_verifier::adaptive",
+			"beacon_verifier::filters",
+			"beacon_verifier::ontologies",
+			"beacon_verifier::post",
+			"beacon_verifier::negative",
+			"beacon_verifier::variants",
+			"beacon_verifier::discovery",
+			"beacon_verifier::entry_types",
+			"beacon_verifier::scheduler",
+			"beacon_verifier::interrupt",
+			"beacon_verifier::isolation",
+			"beacon_verifier::endpoint",
+			"beacon_verifier::shape",
+			"beacon_verifier::utils",
+			"beacon_verifier::formats",
+			"beacon_verifier::sandbox",
+			"beacon_verifier::resolver",
+			"beacon_verifier::spec",
+			"beacon_verifier::cache",
+			"beacon_verifier::init",
+			"beacon_verifier::hgvs",
+			"beacon_verifier::identifiers",
+			"beacon_verifier::numeric",
+			"beacon_verifier::offline",
+			"beacon_verifier::charset",
+			"beacon_verifier::clock",
+			"beacon_verifier::incremental",
+			"beacon_verifier::consistency",
+			"beacon_verifier::references",
+			"beacon_verifier::deprecated",
+			"beacon_verifier::verifier",
+			"beacon_verifier::pool",
+		],
+	),
+	("spec", &["beacon_verifier::framework", "beacon_verifier::model"]),
+	("probe", &["beacon_verifier::probe"]),
+	(
+		"output",
+		&[
+			"beacon_verifier::output",
+			"beacon_verifier::artifacts",
+			"beacon_verifier::canonical",
+			"beacon_verifier::render",
+			"beacon_verifier::sarif",
+			"beacon_verifier::diff",
+			"beacon_verifier::har",
+			"beacon_verifier::network",
+			"beacon_verifier::notify",
+			"beacon_verifier::metrics",
+			"beacon_verifier::history",
+			"beacon_verifier::scope",
+			"beacon_verifier::maintenance",
+			"beacon_verifier::catalog",
+			"beacon_verifier::anonymize",
+			"beacon_verifier::latency",
+			"beacon_verifier::policy",
+			"beacon_verifier::usage",
+		],
+	),
+];
+
+/// Modules whose logs are written to the HTTP log file
+const HTTP_MODULES: [&str; 7] = [
+	"beacon_verifier::client",
+	"beacon_verifier::fixtures",
+	"beacon_verifier::auth",
+	"beacon_verifier::timing",
+	"beacon_verifier::protocol",
+	"reqwest",
+	"hyper",
+];
+
+/// Filters of each verbosity level (`-v`, `-vv`, `-vvv`)
+pub fn verbosity_filters(verbosity: u8) -> &'static str {
+	match verbosity {
+		0 => "info",
+		1 => "info,beacon_verifier=debug",
+		2 => "debug,beacon_verifier=trace",
+		_ => "trace",
+	}
+}
+
+/// Translates the groups and the bare names of the filters into module paths
+pub fn expand_filters(filters: &str) -> String {
+	filters
+		.split(',')
+		.map(str::trim)
+		.filter(|directive| !directive.is_empty())
+		.flat_map(|directive| {
+			let (name, level) = match directive.split_once('=') {
+				Some((name, level)) => (name.trim(), Some(level.trim())),
+				None => (directive, None),
+			};
+			let modules = match GROUPS.iter().find(|(group, _)| *group == name) {
+				Some((_, modules)) => modules.iter().map(|module| module.to_string()).collect(),
+				// A module of the verifier or a crate, which cannot be told apart
+				None if !name.contains("::") && name != "beacon_verifier" => {
+					vec![format!("beacon_verifier::{}", name), name.to_string()]
+				},
+				None => vec![name.to_string()],
+			};
+			match level {
+				Some(level) => modules
+					.into_iter()
+					.map(|module| format!("{}={}", module, level))
+					.collect::<Vec<_>>(),
+				// A bare level applies to every module
+				None => vec![name.to_string()],
+			}
+		})
+		.collect::<Vec<_>>()
+		.join(",")
+}
+
+fn is_http(target: &str) -> bool {
+	HTTP_MODULES
+		.iter()
+		.any(|module| target == *module || target.starts_with(&format!("{}::", module)))
+}
+
+struct Logger {
+	console: Box<dyn Log>,
+	http_log: Option<Mutex<File>>,
+	anonymizer: Option<Anonymizer>,
+	secrets: Secrets,
+}
+
+impl Log for Logger {
+	fn enabled(&self, metadata: &Metadata) -> bool {
+		self.console.enabled(metadata) || (self.http_log.is_some() && is_http(metadata.target()))
+	}
+
+	fn log(&self, record: &Record) {
+		// The secrets are masked everywhere
+		match &self.http_log {
+			Some(file) if is_http(record.target()) => {
+				if record.level() <= LevelFilter::Debug {
+					let text = self.secrets.mask(&record.args().to_string());
+					if let Ok(mut file) = file.lock() {
+						let _ = writeln!(
+							file,
+							"{} {:5} {}: {}",
+							chrono::offset::Utc::now().naive_utc(),
+							record.level(),
+							record.target(),
+							text
+						);
+					}
+				}
+			},
+			_ => {
+				// The ids are anonymized where the record displays them
+				let format = || self.secrets.mask(&record.args().to_string());
+				let text = match &self.anonymizer {
+					Some(anonymizer) => anonymizer.anonymized(format),
+					None => format(),
+				};
+				self.console.log(
+					&Record::builder()
+						.args(format_args!("{}", text))
+						.metadata(record.metadata().clone())
+						.module_path(record.module_path())
+						.file(record.file())
+						.line(record.line())
+						.build(),
+				);
+			},
+		}
+	}
+
+	fn flush(&self) {
+		self.console.flush();
+		if let Some(Ok(mut file)) = self.http_log.as_ref().map(Mutex::lock) {
+			let _ = file.flush();
+		}
+	}
+}
+
+/// Installs the logger: the console gets the records allowed by the verbosity and the filters (without the
+/// ids of `anonymizer`), the HTTP log file (if any) gets the HTTP records up to the debug level instead of the console.
+/// Both mask the `secrets`, even the ones registered later.
+pub fn init(
+	verbosity: u8,
+	filters: Option<&str>,
+	http_log: Option<&Path>,
+	anonymizer: Option<Anonymizer>,
+	secrets: Secrets,
+) {
+	// The last directive for a module wins, so the filters override the verbosity
+	let mut directives = verbosity_filters(verbosity).to_string();
+	if let Some(filters) = filters {
+		directives.push(',');
+		directives.push_str(&expand_filters(filters));
+	}
+	let console = pretty_env_logger::formatted_builder()
+		.parse_filters(&directives)
+		.build();
+	let mut max_level = console.filter();
+
+	let http_log = http_log.map(|path| {
+		max_level = max_level.max(LevelFilter::Debug);
+		Mutex::new(File::create(path).expect("Unable to create the HTTP log file"))
+	});
+
+	log::set_boxed_logger(Box::new(Logger {
+		console: Box::new(console),
+		http_log,
+		anonymizer,
+		secrets,
+	}))
+	.expect("Unable to install the logger");
+	log::set_max_level(max_level);
+}
+
+#[cfg(test)]
+mod tests {
+
+	use crate::logging::{expand_filters, is_http};
+
+	#[test]
+	fn test_expand_filters() {
+		assert_eq!(
+			expand_filters("spec=debug, hgvs=trace,reqwest::connect=warn"),
+			"beacon_verifier::framework=debug,beacon_verifier::model=debug,beacon_verifier::hgvs=trace,hgvs=trace,\
+			 reqwest::connect=warn"
+		);
+		// Without a list of the modules of the verifier
+		assert_eq!(expand_filters("pool=debug"), "beacon_verifier::pool=debug,pool=debug");
+		assert_eq!(
+			expand_filters("reqwest=warn"),
+			"beacon_verifier::reqwest=warn,reqwest=warn"
+		);
+		assert_eq!(expand_filters("beacon_verifier=trace"), "beacon_verifier=trace");
+		assert!(expand_filters("warn,http=debug").starts_with("warn,beacon_verifier::client=debug,"));
+		assert_eq!(expand_filters(""), "");
+	}
+
+	#[test]
+	fn test_http_targets() {
+		assert!(is_http("beacon_verifier::client"));
+		assert!(is_http("hyper::proto::h1::io"));
+		assert!(!is_http("hyperlink"));
+		assert!(!is_http("beacon_verifier::endpoint"));
+	}
+}
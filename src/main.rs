@@ -6,124 +6,1402 @@
 )]
 
 use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::time::Duration;
 
+use beacon_verifier::anonymize::Anonymizer;
+use beacon_verifier::artifacts::RunDirectory;
+use beacon_verifier::auth::{AuthConfig, BasicAuth, Secrets};
+use beacon_verifier::beacon::Beacon;
+use beacon_verifier::cache::SchemaCache;
+use beacon_verifier::catalog::{CatalogFormat, Severity};
+use beacon_verifier::client::{Client, EndpointMethodPolicy, MethodPolicy, RetryPolicy};
+use beacon_verifier::config::{ConfigFile, Profile, RequestHeader};
+use beacon_verifier::fixtures::{Recorder, Replay};
+use beacon_verifier::formats::{Format, Formats};
+use beacon_verifier::framework::Framework;
+use beacon_verifier::history::{History, RunRecord};
+use beacon_verifier::humanize::TimeStyle;
+use beacon_verifier::incremental::DataVersion;
+use beacon_verifier::init::Starter;
+use beacon_verifier::interrupt::Interrupt;
+use beacon_verifier::maintenance::Status;
+use beacon_verifier::model::{Model, Sampling};
+use beacon_verifier::network::NetworkReport;
+use beacon_verifier::offline::SchemaTarget;
+use beacon_verifier::ontologies::OntologyResolver;
+use beacon_verifier::output::{BeaconOutput, Category, RunConfiguration, SpecSource, Totals};
+use beacon_verifier::policy::Policy;
+use beacon_verifier::preset::ModelPreset;
+use beacon_verifier::probe::Probe;
+use beacon_verifier::protocol::{HttpVersion, TlsVersion};
+use beacon_verifier::render::{OpenMetrics, Renderer, Renderers};
+use beacon_verifier::scope::{Access, Scope};
+use beacon_verifier::spec::SpecVersion;
+use beacon_verifier::{
+	artifacts, catalog, client, diff, fixtures, har, history, interrupt, logging, model, notify, offline, render, tls,
+	verifier, Json, VerifierError,
+};
 use chrono::SubsecRound;
-use clap::StructOpt;
+use clap::{ArgEnum, StructOpt};
 use url::Url;
 
-use crate::beacon::Beacon;
-use crate::framework::Framework;
-use crate::model::Model;
-use crate::output::BeaconOutput;
-
-mod beacon;
-mod endpoint;
-mod error;
-mod framework;
-mod interface;
-mod model;
-mod output;
-mod utils;
-
-pub type Json = serde_json::Value;
-
 #[derive(clap::Parser)]
 #[clap(about, version, author)]
+#[clap(setting(clap::AppSettings::SubcommandsNegateReqs))]
 struct Args {
-	/// Sets the level of verbosity
-	#[clap(short, long, conflicts_with("quiet"))]
-	verbose: bool,
+	#[clap(subcommand)]
+	command: Option<Command>,
+
+	/// Sets the level of verbosity (-v, -vv or -vvv)
+	#[clap(short, long, global(true), parse(from_occurrences), conflicts_with("quiet"))]
+	verbose: u8,
+
+	/// Log filters per module or group (http, validation, spec, probe, output), e.g. "http=debug,validation=warn"
+	#[clap(long, global(true))]
+	log: Option<String>,
+
+	/// Write the HTTP logs (up to the debug level) to this file instead of the console
+	#[clap(long = "http-log", global(true))]
+	http_log: Option<PathBuf>,
+
+	/// Replace the ids of the records by a short hash in the logs (the HTTP log file keeps them)
+	#[clap(long = "anonymize-ids", global(true))]
+	anonymize_ids: bool,
 
 	/// Do not print any logs
-	#[clap(short, long, conflicts_with("summary"))]
+	#[clap(short, long, global(true), conflicts_with("summary"))]
 	quiet: bool,
 
 	/// Only log the summary of the results, do not output anything
-	#[clap(short, long, conflicts_with("verbose"))]
+	#[clap(short, long, global(true), conflicts_with("verbose"))]
 	summary: bool,
 
+	/// Configuration file with the beacon targets, in TOML, or in YAML with a .yaml or .yml extension
+	#[clap(long, global(true), default_value = "beacon-verifier.toml")]
+	config: PathBuf,
+
+	/// Beacon target of the configuration file, whose options apply where the command line does not set them
+	#[clap(long, global(true))]
+	target: Option<String>,
+
+	/// Send this bearer token with every request
+	#[clap(long, global(true), conflicts_with("basic-auth"))]
+	token: Option<String>,
+
+	/// Send this header with every request, as "NAME: VALUE" (e.g. "X-Api-Key: <key>"), instead of the header of
+	/// the same name of the target (repeatable)
+	#[clap(long = "header", value_name = "HEADER", global(true), multiple_occurrences(true))]
+	headers: Vec<RequestHeader>,
+
+	/// Authenticate every request with the basic scheme, as USERNAME:PASSWORD
+	#[clap(long, global(true), value_name = "USERNAME:PASSWORD")]
+	basic_auth: Option<BasicAuth>,
+
+	/// Get OAuth2 access tokens from this token endpoint (`client_credentials` grant), refreshed before they expire
+	#[clap(long, global(true), requires("oauth2-client-id"))]
+	oauth2_token_url: Option<Url>,
+
+	/// Client id of the verifier at the OAuth2 identity provider
+	#[clap(long, global(true), requires("oauth2-token-url"))]
+	oauth2_client_id: Option<String>,
+
+	/// Client secret of the verifier at the OAuth2 identity provider
+	#[clap(long, global(true), requires("oauth2-token-url"))]
+	oauth2_client_secret: Option<String>,
+
 	/// Only validate the framework referenced
 	#[clap(long = "only-framework")]
 	only_framework: bool,
 
-	/// Location of the model
+	/// Only verify this entity, by name, entry type or endpoint (e.g. `g_variants`), repeatable or comma-separated
+	/// [default: all the entities of the model]
 	#[clap(
-		short,
-		long,
-		default_value = "https://github.com/MrRobb/beacon-v2-Models/BEACON-V2-draft4-Model"
+		long = "entity",
+		visible_alias("only"),
+		value_name = "ENTITY",
+		multiple_occurrences(true),
+		use_delimiter(true)
 	)]
-	model: Url,
+	entities: Vec<String>,
+
+	/// Leave out this entity, by name, entry type or endpoint (repeatable or comma-separated)
+	#[clap(
+		long = "skip",
+		value_name = "ENTITY",
+		multiple_occurrences(true),
+		use_delimiter(true)
+	)]
+	skipped_entities: Vec<String>,
+
+	/// Only validate the entities against the model (skip the checks of the framework layer)
+	#[clap(long = "only-model", conflicts_with("only-framework"))]
+	only_model: bool,
+
+	/// Append a summary of the data observed per entity (records, datasets, assemblies, filters)
+	#[clap(long = "observed-data")]
+	observed_data: bool,
+
+	/// Strip record-level data, ids and response excerpts from the output so it can be published
+	#[clap(long = "public-report")]
+	public_report: bool,
+
+	/// Shape of the output: the full report (verifier), the support matrix of the Beacon network websites
+	/// (network), the final metrics (openmetrics), a JUnit XML report for the CI servers (junit), a SARIF log for the
+	/// code scanning dashboards (sarif) or a self-contained HTML page for the implementers (html) [default: verifier]
+	#[clap(long = "output-format", value_name = "FORMAT")]
+	output_format: Option<String>,
+
+	/// Write the output to this file instead of the standard output
+	#[clap(long, value_name = "FILE")]
+	report_file: Option<PathBuf>,
+
+	/// Also write the report (and the metrics) of the run to a directory of its own in this directory, named after
+	/// the start of the run and renamed only once the run is complete, with a `latest` link to the last one
+	#[clap(long, value_name = "DIRECTORY")]
+	output_dir: Option<PathBuf>,
+
+	/// Only keep this many runs in the output directory, removing the oldest ones
+	#[clap(long, value_name = "RUNS", requires = "output-dir")]
+	keep_runs: Option<usize>,
+
+	/// Timestamps and durations of the summary and of the HTML report: in the local timezone and humanized (local), or
+	/// in UTC and ISO 8601 (utc, e.g. in CI)
+	#[clap(long, arg_enum, value_name = "STYLE", default_value = "local", global(true))]
+	time_style: TimeStyle,
+
+	/// Print the output in a canonical form, to diff the reports or store them in git: sorted keys, reports in a
+	/// stable order and floats rounded to 6 decimals
+	#[clap(long)]
+	canonical: bool,
+
+	/// Measure the phases of every request (DNS, connect, TLS, time to first byte and download)
+	#[clap(long)]
+	timings: bool,
+
+	/// Keep the outcomes of the runs in this file, flagging the checks that alternate between success and failure
+	#[clap(long, value_name = "FILE")]
+	history: Option<PathBuf>,
+
+	/// Run the flaky checks up to this many more times before failing them
+	#[clap(long, requires = "history")]
+	retries: Option<usize>,
+
+	/// Run the checks on the responses recorded during this run of the history (e.g. 20220301T120000) instead of
+	/// querying the beacon, and list the checks that fail now but did not fail then
+	#[clap(long, value_name = "RUN_ID", requires = "history", conflicts_with_all = &["timings", "incremental"])]
+	replay_from_history: Option<String>,
+
+	/// Write every response of the beacon (status line, headers and body) to this directory, named after the check of
+	/// its endpoint, to reproduce the failures without querying the beacon again
+	#[clap(long, value_name = "DIRECTORY")]
+	dump_responses: Option<PathBuf>,
+
+	/// Write the requests of the run and the responses of the beacon to this file as an HTTP Archive (HAR), to open
+	/// them in the network panel of a browser
+	#[clap(long, value_name = "FILE")]
+	har: Option<PathBuf>,
+
+	/// Stop querying the endpoints of the entities after this many seconds (the checks are spread across the
+	/// entities, so the partial results cover the whole beacon)
+	#[clap(long, value_name = "SECONDS")]
+	time_budget: Option<u64>,
+
+	/// Classify the responses taking this many milliseconds or more as slow [default: 1000]
+	#[clap(long, value_name = "MILLISECONDS")]
+	slow_ms: Option<u64>,
+
+	/// Give up on the requests without a response after this many milliseconds [default: 30000]
+	#[clap(long, value_name = "MILLISECONDS")]
+	timeout_ms: Option<u64>,
+
+	/// Send each request failing transiently (5xx, 429 or a connection reset) again, up to this many times, unlike
+	/// --retries which runs the flaky checks again [default: 0]
+	#[clap(long, value_name = "RETRIES")]
+	request_retries: Option<u32>,
+
+	/// Wait this many milliseconds before the first retry of a request, twice as long before each of the next
+	/// ones, unless the beacon answers with a `Retry-After` [default: 500]
+	#[clap(long, value_name = "MILLISECONDS")]
+	request_backoff_ms: Option<u64>,
+
+	/// Also check that the beacon accepts POST bodies with and without charset or with a byte order mark, and
+	/// rejects the ones that are not UTF-8
+	#[clap(long)]
+	robustness: bool,
+
+	/// Also check that the beacons returning a snapshot token in the meta of the responses keep their pages
+	/// consistent when the token is sent back, and that the datasets and cohorts page and count their collections
+	#[clap(long)]
+	pagination: bool,
+
+	/// Also report the page sizes and number of filters the beacon declares as limits, and check the endpoints
+	/// exactly at and just beyond them
+	#[clap(long)]
+	limits: bool,
+
+	/// Also check that the first record of each entity is found by queries derived from it (a filter on one of its
+	/// ontology terms, a range query at the position of a variant)
+	#[clap(long)]
+	adaptive: bool,
+
+	/// Also probe whether the endpoints of the entities support HEAD requests, recorded in the support matrix
+	#[clap(long)]
+	head: bool,
+
+	/// Also query every entity with and without a filtering term the beacon advertises, checking that the filter
+	/// counts no more results and that the records returned carry the term
+	#[clap(long)]
+	filters: bool,
+
+	/// Also POST a query with a request body (filters, pagination and requested granularity) to every entity,
+	/// checking the response and its echo of the request in `meta.receivedRequestSummary`
+	#[clap(long)]
+	post_queries: bool,
+
+	/// Also send malformed queries to every entity (a malformed filter, an invalid pagination, an unknown id), which
+	/// must be rejected with 400 or 404 and an error response following `beaconErrorResponse`
+	#[clap(long)]
+	negative: bool,
+
+	/// Also follow the references of the first records of each entity (e.g. the `individualId` of the biosamples)
+	/// to the single entry urls of the referenced entities, which must return the referenced records
+	#[clap(long)]
+	references: bool,
+
+	/// Also look the ontology terms of the records and of the filtering terms up in the Ontology Lookup Service of
+	/// EBI, warning of the unknown and obsolete terms and of the labels that are not theirs
+	#[clap(long)]
+	resolve_ontologies: bool,
+
+	/// Keep the ontology terms looked up with --resolve-ontologies in this file, between the runs
+	#[clap(long, value_name = "FILE", requires = "resolve-ontologies")]
+	ontology_cache: Option<PathBuf>,
+
+	/// Also send sequence, range, bracket and geneId queries to the genomic variants, and the same queries beyond
+	/// the end of any chromosome, which must find nothing
+	#[clap(long)]
+	variant_queries: bool,
+
+	/// Check the record-level endpoints (single entry and related endpoints) with up to N records of each entity,
+	/// among the first page of its records [default: 1]
+	#[clap(long, value_name = "N", parse(try_from_str = model::parse_max_instances))]
+	max_instances: Option<usize>,
+
+	/// Sample the records of --max-instances with this seed instead of taking the first ones, the same seed
+	/// checking the same records whatever the order the beacon returns them in
+	#[clap(long, value_name = "SEED")]
+	sample_seed: Option<u64>,
+
+	/// Query the entities at the urls advertised in the `/map` of the beacon instead of the map of the model,
+	/// reporting the entities it does not advertise as skipped
+	#[clap(long)]
+	discover: bool,
+
+	/// Check that the beacon is served with at least this version of TLS (the negotiated versions are always reported)
+	#[clap(long, arg_enum, value_name = "VERSION")]
+	min_tls_version: Option<TlsVersion>,
+
+	/// Check that the beacon supports at least this version of HTTP
+	#[clap(long, arg_enum, value_name = "VERSION")]
+	min_http_version: Option<HttpVersion>,
+
+	/// Send the requests (to the beacon, the identity provider and the hosts of the schemas) through this HTTP(S) or
+	/// SOCKS5 proxy, instead of the one of HTTP_PROXY and HTTPS_PROXY
+	#[clap(long, value_name = "URL", global(true), parse(try_from_str = client::parse_proxy))]
+	proxy: Option<Url>,
+
+	/// Trust the certificates of this file (PEM, or DER for a single one) besides the ones of the system, e.g. of the
+	/// private authority of the beacons
+	#[clap(long, value_name = "FILE", global(true))]
+	ca_cert: Option<PathBuf>,
+
+	/// Present this client certificate to the beacons asking for one (mutual TLS): a PKCS #12 file, or a PEM
+	/// certificate with --client-key
+	#[clap(long, value_name = "FILE", global(true))]
+	client_cert: Option<PathBuf>,
+
+	/// PEM key of the client certificate
+	#[clap(long, value_name = "FILE", global(true), requires = "client-cert")]
+	client_key: Option<PathBuf>,
 
-	/// Location of the framework
-	#[clap(short, long, default_value = "https://github.com/MrRobb/beacon-framework-v2")]
-	framework: Url,
+	/// Password of the PKCS #12 client certificate or of its PEM key
+	#[clap(long, value_name = "PASSWORD", global(true), requires = "client-cert")]
+	client_key_password: Option<String>,
 
-	/// Url to the Beacon implementation
-	url: Url,
+	/// Evaluate the run against the policy of a network in this file (required checks, minimum score and allowed
+	/// failures), adding a pass/fail verdict to the output. In JSON, or in YAML with a .yaml or .yml extension
+	#[clap(long, value_name = "FILE", global(true))]
+	policy: Option<PathBuf>,
+
+	/// Write the metrics of the run to this file, in the OpenMetrics text format
+	#[clap(long, value_name = "FILE")]
+	metrics_out: Option<PathBuf>,
+
+	/// Only validate the entities again if the beacon reports that its data changed since the run that saved this file
+	#[clap(long)]
+	incremental: Option<PathBuf>,
+
+	/// Number of endpoints checked at once, bounding the requests in flight [default: number of cores]
+	#[clap(short, long)]
+	jobs: Option<usize>,
+
+	/// HTTP methods used to query the endpoints [default: get-then-post]
+	#[clap(long = "method-policy", arg_enum, global(true))]
+	method_policy: Option<MethodPolicy>,
+
+	/// HTTP methods used to query one endpoint, as ENDPOINT=POLICY (e.g. "individual=post")
+	#[clap(long = "endpoint-method-policy", global(true), multiple_occurrences(true))]
+	endpoint_method_policies: Vec<EndpointMethodPolicy>,
+
+	/// Do not enforce a value format (repeatable)
+	#[clap(long = "disable-format", arg_enum, multiple_occurrences(true))]
+	disabled_formats: Vec<Format>,
+
+	/// Location of the model [default: the model of the version of the specification, for 2.0
+	/// https://github.com/MrRobb/beacon-v2-Models/BEACON-V2-draft4-Model]
+	#[clap(short, long)]
+	model: Option<Url>,
+
+	/// Curated model of non-human beacons, instead of the default model
+	#[clap(long = "model-preset", arg_enum, conflicts_with("model"))]
+	model_preset: Option<ModelPreset>,
+
+	/// Location of the framework [default: the framework of the version of the specification, for 2.0
+	/// https://github.com/MrRobb/beacon-framework-v2]
+	#[clap(short, long)]
+	framework: Option<Url>,
+
+	/// Clone the repositories of the framework and the model hosted on GitHub once into this directory, and read them
+	/// from there in the next runs
+	#[clap(long, value_name = "DIRECTORY", global(true))]
+	schema_cache: Option<PathBuf>,
+
+	/// Only read the framework and the model from --schema-cache (or local directories), never from GitHub
+	#[clap(long, requires = "schema-cache", global(true))]
+	offline: bool,
+
+	/// Version of the specification to verify the beacon against, selecting the framework and the model [default:
+	/// the version of the `apiVersion` of /info, or else 2.0]
+	#[clap(long = "spec-version", arg_enum, value_name = "VERSION")]
+	spec_version: Option<SpecVersion>,
+
+	/// Url to the Beacon implementation, or the urls of the members of a Beacon network to compare in one report
+	#[clap(required_unless_present_any(&["target", "export-check-catalog"]))]
+	urls: Vec<Url>,
+
+	/// Exit with code 1 when a check fails, warns or notes at this severity or above (`error` for the failures but
+	/// the ones of the optional endpoints that are not found, which are warnings)
+	#[clap(long = "fail-on", arg_enum, value_name = "SEVERITY", default_value = "error")]
+	fail_on: Severity,
+
+	/// Print the catalog of the checks (ids, categories, severities, spec references and descriptions) and exit
+	#[clap(long = "export-check-catalog", arg_enum, value_name = "FORMAT")]
+	export_check_catalog: Option<CatalogFormat>,
 }
 
+#[derive(clap::Subcommand)]
+enum Command {
+	/// Only check that the beacon answers `/info` (and optionally one query) within a latency budget
+	Probe {
+		/// Maximum time allowed for each request, in milliseconds
+		#[clap(long = "budget-ms", default_value = "2000")]
+		budget_ms: u64,
+
+		/// Also run a boolean query on this endpoint (e.g. "individuals")
+		#[clap(long)]
+		query: Option<String>,
+
+		/// Send a HEAD request to the endpoint of --query instead of the query if it supports HEAD, to spare the
+		/// bandwidth
+		#[clap(long, requires = "query")]
+		head: bool,
+
+		/// Url to the Beacon implementation (required without --target)
+		url: Option<Url>,
+	},
+	/// Validate a response saved to a file (or read from the standard input) against a schema of the framework or
+	/// the model, with the checks of a live run
+	ValidateResponse {
+		/// Entity of the model (e.g. "individual"), framework response (info, configuration, map or entry-types) or
+		/// path to a JSON Schema
+		#[clap(long)]
+		schema: String,
+
+		/// File with the response (the standard input if absent or "-")
+		file: Option<PathBuf>,
+	},
+	/// Download the frameworks and the default models of every version of the specification (and --framework and
+	/// --model) into --schema-cache, to verify the beacons with --offline where GitHub is not reachable
+	CacheSchemas,
+	/// Add the target of a beacon to the configuration file (see --config), prefilled from its /info and /map
+	Init {
+		/// Name of the target [default: after the id of the beacon]
+		#[clap(long)]
+		name: Option<String>,
+
+		/// Url to the Beacon implementation (asked if absent)
+		url: Option<Url>,
+	},
+	/// Compare two JSON reports of a beacon and print the newly broken checks, the newly fixed ones and the failures
+	/// in both
+	Diff {
+		/// Report of the earlier run
+		old: PathBuf,
+
+		/// Report of the later run
+		new: PathBuf,
+	},
+	/// Manage the configuration file (see --config)
+	Config {
+		#[clap(subcommand)]
+		command: ConfigCommand,
+	},
+}
+
+#[derive(clap::Subcommand)]
+enum ConfigCommand {
+	/// Check the configuration file (and the policy file of --policy, if given) against its schema and report every
+	/// problem with the key it is under and its line
+	Validate,
+}
+
+/// Exit code of the runs where a check is at the severity of `--fail-on` (or a command found problems)
+const EXIT_FAILURES: i32 = 1;
+
+/// Exit code of the runs the verifier could not complete (bad configuration, unreachable beacon, internal error...)
+const EXIT_ERROR: i32 = 2;
+
 fn main() {
+	// The panics are errors of the verifier, not failures of the beacon
+	if std::panic::catch_unwind(run).is_err() {
+		std::process::exit(EXIT_ERROR);
+	}
+}
+
+fn run() {
 	// Get args
 	let matches = Args::parse();
 
 	// Verbose
 
+	let anonymizer = matches.anonymize_ids.then(Anonymizer::default);
+	let secrets = Secrets::default();
+	logging::init(
+		matches.verbose,
+		matches.log.as_deref(),
+		matches.http_log.as_deref(),
+		anonymizer.clone(),
+		secrets.clone(),
+	);
 	if matches.quiet || matches.summary {
-		std::env::set_var("RUST_LOG", "info");
-		pretty_env_logger::init();
 		log::set_max_level(log::LevelFilter::Off);
 	}
-	else if matches.verbose {
-		std::env::set_var("RUST_LOG", "debug");
-		pretty_env_logger::init();
+
+	if let Some(format) = matches.export_check_catalog {
+		println!("{}", catalog::export(format));
+		return;
+	}
+
+	if let Some(Command::Config {
+		command: ConfigCommand::Validate,
+	}) = &matches.command
+	{
+		let mut valid = match ConfigFile::load(&matches.config) {
+			Ok(config) => {
+				log::info!("{:?} is valid ({} targets)", matches.config, config.beacons.len());
+				true
+			},
+			Err(e) => {
+				log::error!("{:?} is not valid:\n{}", matches.config, e);
+				false
+			},
+		};
+		if let Some(path) = &matches.policy {
+			match Policy::load(path) {
+				Ok(policy) => log::info!("{:?} is valid (policy {})", path, policy.name),
+				Err(e) => {
+					log::error!("{:?} is not valid:\n{}", path, e);
+					valid = false;
+				},
+			}
+		}
+		if !valid {
+			std::process::exit(EXIT_ERROR);
+		}
+		return;
+	}
+
+	if let Some(Command::Diff { old, new }) = &matches.command {
+		let load = |path: &PathBuf| {
+			diff::load(path).unwrap_or_else(|e| {
+				log::error!("Reading the report {:?} failed: {}", path, e);
+				std::process::exit(EXIT_ERROR);
+			})
+		};
+		let report = diff::diff(&load(old), &load(new));
+		if matches.summary {
+			log::set_max_level(log::LevelFilter::Trace);
+			report.summary();
+		}
+		else {
+			report.summary();
+			println!("{}", serde_json::to_string_pretty(&report).unwrap());
+		}
+		if !report.newly_broken.is_empty() {
+			std::process::exit(EXIT_FAILURES);
+		}
+		return;
+	}
+
+	// Load target
+	let profile = match &matches.target {
+		Some(target) => {
+			log::debug!("Loading target {} from: {:?}", target, matches.config);
+			ConfigFile::load(&matches.config)
+				.and_then(|config| config.profile(target))
+				.expect("Loading configuration failed")
+		},
+		None => Profile::default(),
+	}
+	.with_headers(&matches.headers);
+
+	// Renderer of the output, the command line taking precedence over the target
+	let output_format = matches
+		.output_format
+		.clone()
+		.or_else(|| profile.output_format.clone())
+		.unwrap_or_else(|| render::DEFAULT.to_string());
+	let renderers = Renderers::builtin(matches.canonical, matches.time_style);
+	let renderer = match renderers.get(&output_format) {
+		Some(renderer) => renderer,
+		None => {
+			log::error!(
+				"Unknown output format {:?} (available: {})",
+				output_format,
+				renderers.names().join(", ")
+			);
+			std::process::exit(EXIT_ERROR);
+		},
+	};
+
+	// Planned downtime, as of the start of the run
+	let in_maintenance = profile.in_maintenance(chrono::offset::Utc::now().naive_utc());
+
+	// The command line takes precedence over the target
+	let method_policy = matches.method_policy.or(profile.method_policy).unwrap_or_default();
+	let endpoint_method_policies = profile
+		.endpoint_method_policies
+		.iter()
+		.map(|(endpoint, policy)| (endpoint.to_lowercase(), *policy))
+		.chain(
+			matches
+				.endpoint_method_policies
+				.iter()
+				.map(|policy| (policy.endpoint.clone(), policy.policy)),
+		)
+		.collect::<BTreeMap<_, _>>();
+	let disabled_formats = profile
+		.disabled_formats
+		.iter()
+		.chain(&matches.disabled_formats)
+		.copied()
+		.collect::<Vec<_>>();
+	let only_framework = matches.only_framework || profile.only_framework;
+	let timeout_ms = matches.timeout_ms.or(profile.timeout_ms);
+	let retry = RetryPolicy {
+		max_retries: matches.request_retries.or(profile.request_retries).unwrap_or_default(),
+		backoff: matches
+			.request_backoff_ms
+			.or(profile.request_backoff_ms)
+			.map_or(RetryPolicy::default().backoff, Duration::from_millis),
+	};
+	let only_model = !only_framework && (matches.only_model || profile.only_model);
+	let entities = if matches.entities.is_empty() {
+		profile.entities.clone()
+	}
+	else {
+		matches.entities.clone()
+	};
+	let skipped_entities = if matches.skipped_entities.is_empty() {
+		profile.skip_entities.clone()
+	}
+	else {
+		matches.skipped_entities.clone()
+	};
+
+	let proxy = matches.proxy.clone().or_else(|| profile.proxy.clone());
+	if let Some(password) = proxy.as_ref().and_then(Url::password) {
+		secrets.register(password);
+	}
+	let headers = profile.header_map().expect("Loading configuration failed");
+	for value in headers.values().filter(|value| value.is_sensitive()) {
+		secrets.register(value.to_str().unwrap_or_default());
+	}
+	let client = endpoint_method_policies.iter().fold(
+		Client::new().method_policy(method_policy).retry(retry).headers(headers),
+		|client, (endpoint, policy)| {
+			client.endpoint_method_policy(EndpointMethodPolicy {
+				endpoint: endpoint.clone(),
+				policy: *policy,
+			})
+		},
+	);
+	let client = match &proxy {
+		Some(proxy) => client.proxy(proxy.clone()),
+		None => client,
+	};
+	let client = match &matches.ca_cert {
+		Some(path) => client.root_certificates(tls::ca_certificates(path).expect("Loading the CA certificates failed")),
+		None => client,
+	};
+	if let Some(password) = &matches.client_key_password {
+		secrets.register(password);
+	}
+	let client = match &matches.client_cert {
+		Some(cert) => client.identity(
+			tls::identity(
+				cert,
+				matches.client_key.as_deref(),
+				matches.client_key_password.as_deref(),
+			)
+			.expect("Loading the client certificate failed"),
+		),
+		None => client,
+	};
+	let auth = match (&matches.oauth2_token_url, &matches.oauth2_client_id) {
+		(Some(token_url), Some(client_id)) => Some(AuthConfig {
+			token_url: token_url.clone(),
+			client_id: client_id.clone(),
+			client_secret: matches.oauth2_client_secret.clone(),
+			refresh_token: None,
+			scope: None,
+		}),
+		_ => profile.auth.clone(),
+	};
+	let client = match auth.clone() {
+		Some(auth) => {
+			for secret in auth.client_secret.iter().chain(&auth.refresh_token) {
+				secrets.register(secret);
+			}
+			client.auth(auth)
+		},
+		None => client,
+	};
+	// The static credentials of the command line replace the ones of the target
+	let token = matches.token.clone().or_else(|| match matches.basic_auth {
+		Some(_) => None,
+		None => profile.token.clone(),
+	});
+	let basic_auth = matches.basic_auth.clone().or_else(|| match matches.token {
+		Some(_) => None,
+		None => profile.basic_auth.clone(),
+	});
+	let client = match (&token, &basic_auth) {
+		(Some(token), _) => {
+			secrets.register(token);
+			client.bearer_token(token)
+		},
+		(None, Some(basic_auth)) => {
+			if let Some(password) = &basic_auth.password {
+				secrets.register(password);
+			}
+			client.basic_auth(basic_auth.clone())
+		},
+		(None, None) => client,
+	};
+	let client = match &profile.api_key {
+		Some(api_key) => {
+			secrets.register(&api_key.key);
+			match api_key.query_parameter() {
+				Some((name, key)) => client.api_key_parameter(name, key),
+				None => client,
+			}
+		},
+		None => client,
+	};
+
+	// The responses of a run of the history replace the beacon, the responses of the other runs are recorded
+	let mut replayed_run: Option<RunRecord> = None;
+	let mut recorder: Option<Recorder> = None;
+	let client = match (&matches.history, &matches.replay_from_history) {
+		(Some(path), Some(id)) => {
+			let history = History::load(path).expect("Loading the history failed");
+			let run = history.run(id).cloned().unwrap_or_else(|| {
+				let ids = history.runs.iter().map(RunRecord::id).collect::<Vec<_>>();
+				log::error!("There is no run {} in the history (runs: {})", id, ids.join(", "));
+				std::process::exit(EXIT_ERROR);
+			});
+			let fixtures = fixtures::load(&history::fixtures_path(path, id))
+				.expect("Loading the recorded responses of the run failed");
+			log::info!("Replaying the {} responses of the run {}", fixtures.len(), id);
+			replayed_run = Some(run);
+			client.replay(Replay::start(fixtures).expect("Starting the replay failed"))
+		},
+		(Some(_), None) => {
+			let new_recorder = Recorder::default();
+			recorder = Some(new_recorder.clone());
+			client.record(new_recorder)
+		},
+		_ => client,
+	};
+	let client = match (matches.dump_responses.is_some() || matches.har.is_some(), &recorder) {
+		(true, None) => {
+			let new_recorder = Recorder::default();
+			recorder = Some(new_recorder.clone());
+			client.record(new_recorder)
+		},
+		_ => client,
+	};
+
+	if let Some(Command::Init { name, url }) = &matches.command {
+		let url = match url {
+			Some(url) => url.clone(),
+			None => {
+				eprint!("Url of the beacon: ");
+				let mut line = String::new();
+				std::io::stdin().read_line(&mut line).expect("Reading the url failed");
+				Url::parse(line.trim()).expect("Bad url of the beacon")
+			},
+		};
+		log::info!("Probing {}", url);
+		let mut starter = Starter::probe(&client, &url).expect("Probing the beacon failed");
+		if let Some(name) = name {
+			starter.name = name.clone();
+		}
+		match starter.write(&matches.config) {
+			Ok(()) => log::info!("Target {} added to {:?}", starter.name, matches.config),
+			Err(e) => {
+				log::error!("Adding the target to {:?} failed: {}", matches.config, e);
+				std::process::exit(EXIT_ERROR);
+			},
+		}
+		if starter.auth.is_some() {
+			log::warn!(
+				"Replace the placeholders of the credentials of {} before the first run",
+				starter.name
+			);
+		}
+		return;
+	}
+
+	if let Some(Command::Probe {
+		budget_ms,
+		query,
+		head,
+		url,
+	}) = matches.command
+	{
+		let url = url
+			.or(profile.url)
+			.expect("The url of the beacon is required (or a --target with a url)");
+		log::info!("Probing {}", url);
+		let mut probe = Probe::new(client, &url, Duration::from_millis(budget_ms));
+		if let Some(location) = query {
+			probe = probe.query(&location);
+		}
+		if head {
+			probe = probe.head();
+		}
+		let mut output = probe.run();
+		output.maintenance = in_maintenance;
+		if matches.summary {
+			log::set_max_level(log::LevelFilter::Trace);
+			output.summary(matches.time_style);
+		}
+		else {
+			output.summary(matches.time_style);
+			println!("{}", serde_json::to_string_pretty(&output).unwrap());
+		}
+		if !output.alive && !output.maintenance {
+			std::process::exit(EXIT_FAILURES);
+		}
+		return;
+	}
+
+	// Load the policy before the run, so that a bad file does not waste it
+	let policy = matches
+		.policy
+		.as_ref()
+		.map(|path| Policy::load(path).expect("Loading the policy failed"));
+
+	let formats = disabled_formats
+		.iter()
+		.fold(Formats::default(), |formats, format| formats.disable(*format));
+
+	// Beacons to verify, the members of a network being verified in turn
+	let beacon_urls = if !matches.urls.is_empty() {
+		matches.urls.clone()
+	}
+	else if !profile.network.is_empty() {
+		profile.network.clone()
 	}
 	else {
-		std::env::set_var("RUST_LOG", "info");
-		pretty_env_logger::init();
+		profile.url.iter().cloned().collect()
+	};
+
+	// The version of the specification selects the framework and the model that are not given (the one of the first
+	// member of a network)
+	let spec_version = matches.spec_version.or(profile.spec_version).or_else(|| {
+		let url = beacon_urls.first().filter(|_| matches.command.is_none())?;
+		SpecVersion::detect(&client, url)
+	});
+	let default_version = spec_version.unwrap_or(SpecVersion::DEFAULT);
+
+	let cache = match &matches.schema_cache {
+		Some(dir) if matches.offline => SchemaCache::new(dir.clone()).offline(),
+		Some(dir) => SchemaCache::new(dir.clone()),
+		None => SchemaCache::default(),
+	};
+	let cache = match proxy {
+		Some(proxy) => cache.proxy(proxy),
+		None => cache,
+	};
+
+	if let Some(Command::CacheSchemas) = &matches.command {
+		if matches.schema_cache.is_none() {
+			log::error!("The cache-schemas command requires --schema-cache");
+			std::process::exit(EXIT_ERROR);
+		}
+		let frameworks = SpecVersion::value_variants()
+			.iter()
+			.map(|version| version.framework())
+			.chain(matches.framework.clone())
+			.map(|location| {
+				(
+					Framework::load(&location, &cache).map(|framework| framework.store().clone()),
+					location,
+				)
+			});
+		let models = SpecVersion::value_variants()
+			.iter()
+			.map(|version| version.model())
+			.chain(matches.model.clone())
+			.map(|location| {
+				(
+					Model::load(&location, &formats, &cache).map(|model| model.store().clone()),
+					location,
+				)
+			});
+		// With the remote schemas they reference
+		for (store, location) in frameworks.chain(models) {
+			match store.and_then(|store| store.prefetch()) {
+				Ok(referenced) => log::info!("{} is cached, with {} referenced schemas", location, referenced),
+				Err(e) => {
+					log::error!("Caching {} failed: {}", location, e);
+					std::process::exit(EXIT_ERROR);
+				},
+			}
+		}
+		return;
 	}
 
 	// Load framework
-	let framework_location = matches.framework;
+	let framework_location = matches
+		.framework
+		.or(profile.framework)
+		.unwrap_or_else(|| default_version.framework());
 	log::debug!("Loading framework from: {}", &framework_location);
-	let framework = Framework::load(&framework_location).expect("Loading framework failed");
-	log::debug!("Framework loaded");
+	let framework = Framework::load(&framework_location, &cache);
 
 	// Load model
-	let model = if matches.only_framework {
-		None
-	}
-	else {
-		let model_location = matches.model;
+	let model_location = matches
+		.model
+		.or_else(|| matches.model_preset.map(ModelPreset::location))
+		.or(profile.model)
+		.or_else(|| profile.model_preset.map(ModelPreset::location))
+		.unwrap_or_else(|| default_version.model());
+	let model = (!only_framework).then(|| {
 		log::debug!("Loading model from: {}", model_location);
-		let model = Model::load(&model_location).expect("Loading model failed");
-		log::info!("Number of entities of the model: {}", model.entities.len());
-		Some(model)
+		Model::load(&model_location, &formats, &cache)
+	});
+
+	// The broken schemas of the framework and of the model are reported together, before the beacon is queried
+	let (framework, mut model) = match verifier::loaded(framework, model) {
+		Ok(loaded) => loaded,
+		Err(e @ VerifierError::BrokenSchemas(_)) => {
+			log::error!("{}", e);
+			std::process::exit(EXIT_ERROR);
+		},
+		Err(e) => panic!("Loading the framework and the model failed: {:?}", e),
 	};
+	log::debug!("Framework loaded");
+	if let Some(model) = &mut model {
+		if !entities.is_empty() {
+			model.retain_entities(&entities);
+		}
+		if !skipped_entities.is_empty() {
+			model.skip_entities(&skipped_entities);
+		}
+		log::info!("Number of entities of the model: {}", model.entities.len());
+	}
+
+	if let Some(Command::ValidateResponse { schema, file }) = &matches.command {
+		let (content, source) = match file.as_ref().filter(|file| file.as_os_str() != "-") {
+			Some(file) => {
+				let path = std::fs::canonicalize(file).expect("Reading the response failed");
+				let source = Url::from_file_path(&path).expect("Bad path of the response");
+				(
+					std::fs::read_to_string(path).expect("Reading the response failed"),
+					source,
+				)
+			},
+			None => {
+				let mut content = String::new();
+				std::io::stdin()
+					.read_to_string(&mut content)
+					.expect("Reading the response failed");
+				(content, Url::parse("file:///dev/stdin").unwrap())
+			},
+		};
+		let json = serde_json::from_str::<Json>(&content).expect("The response is not JSON");
+		let target = SchemaTarget::parse(schema);
+		let results = offline::validate(json, &source, &target, &framework, model.as_ref(), &formats)
+			.expect("Validating the response failed");
+		let failures = results
+			.values()
+			.flatten()
+			.filter(|report| !report.outcome().is_success())
+			.collect::<Vec<_>>();
+		for report in &failures {
+			log::error!("{}: {}", report.name, report.error.as_deref().unwrap_or_default());
+		}
+		println!("{}", serde_json::to_string_pretty(&results).unwrap());
+		if !failures.is_empty() {
+			std::process::exit(EXIT_FAILURES);
+		}
+		return;
+	}
 
 	// Load beacon
-	let beacon_url = matches.url;
-	log::info!("Validating implementation on {}", beacon_url);
-
-	let output = match Beacon::new(model, framework, &beacon_url) {
-		Ok(beacon) => beacon.validate(),
-		Err(e) => BeaconOutput {
-			name: format!("Unknown Beacon ({})", e),
-			url: beacon_url,
-			last_updated: chrono::offset::Utc::now().naive_utc().round_subsecs(6),
-			entities: BTreeMap::new(),
+	let beacon_url = beacon_urls
+		.first()
+		.cloned()
+		.expect("The url of the beacon is required (or a --target with a url)");
+	let network = beacon_urls.len() > 1;
+	if network
+		&& (matches.history.is_some()
+			|| matches.incremental.is_some()
+			|| matches.output_dir.is_some()
+			|| matches.metrics_out.is_some()
+			|| matches.dump_responses.is_some()
+			|| matches.har.is_some())
+	{
+		log::error!(
+			"--history, --incremental, --output-dir, --metrics-out, --dump-responses and --har follow a single \
+			 beacon, not a network"
+		);
+		std::process::exit(EXIT_ERROR);
+	}
+	if network && !renderer.renders_network() {
+		log::error!("{}", VerifierError::SingleBeaconFormat(output_format));
+		std::process::exit(EXIT_ERROR);
+	}
+
+	let configuration = RunConfiguration {
+		version: env!("CARGO_PKG_VERSION").into(),
+		target: matches.target.clone(),
+		url: beacon_url.clone(),
+		framework: SpecSource {
+			location: framework_location,
+			revision: framework.revision.clone(),
+		},
+		model: model.as_ref().map(|model| SpecSource {
+			location: model_location,
+			revision: model.revision.clone(),
+		}),
+		spec_version,
+		entities: model
+			.as_ref()
+			.map(|model| model.entities.iter().map(|entity| entity.name.clone()).collect())
+			.unwrap_or_default(),
+		only: if only_framework {
+			Some(Category::Framework)
+		}
+		else if only_model {
+			Some(Category::Model)
+		}
+		else {
+			None
 		},
+		method_policy,
+		endpoint_method_policies,
+		access: if auth.is_some() {
+			Access::OAuth2
+		}
+		else if token.is_some() {
+			Access::Token
+		}
+		else if basic_auth.is_some() {
+			Access::Basic
+		}
+		else if profile.api_key.is_some() {
+			Access::ApiKey
+		}
+		else if !profile.headers.is_empty() {
+			Access::Headers
+		}
+		else {
+			Access::Anonymous
+		},
+		disabled_formats: disabled_formats
+			.iter()
+			.map(|format| format.keyword().to_string())
+			.collect(),
+		observed_data: matches.observed_data,
+		public_report: matches.public_report,
+		timings: matches.timings,
+		incremental: matches.incremental.is_some(),
+		history: matches.history.is_some(),
+		retries: matches.retries.unwrap_or_default(),
+		time_budget_secs: matches.time_budget,
+		robustness: matches.robustness,
+		pagination: matches.pagination,
+		limits: matches.limits,
+		adaptive: matches.adaptive,
+		filters: matches.filters,
+		post_queries: matches.post_queries,
+		negative: matches.negative,
+		references: matches.references,
+		resolve_ontologies: matches.resolve_ontologies,
+		variant_queries: matches.variant_queries,
+		max_instances: matches.max_instances,
+		sample_seed: matches.sample_seed,
+		head: matches.head,
+		discover: matches.discover,
+		min_tls_version: matches.min_tls_version,
+		min_http_version: matches.min_http_version,
+		slow_ms: matches.slow_ms,
+		timeout_ms,
+		request_retries: retry.max_retries,
+		request_backoff_ms: matches.request_backoff_ms.or(profile.request_backoff_ms),
+		output_format,
 	};
 
+	let client = if matches.timings { client.with_timings() } else { client };
+	let client = match timeout_ms {
+		Some(timeout) => client.timeout(Duration::from_millis(timeout)),
+		None => client,
+	};
+	// To the hosts other than the beacons (the ontology lookup service and the webhooks)
+	let http = client.http();
+
+	let mut history = matches
+		.history
+		.as_ref()
+		.map(|path| History::load(path).expect("Loading the history failed"));
+	// A corrupt state would run the data-dependent checks again forever
+	let previous_data_version = matches
+		.incremental
+		.as_ref()
+		.map(|state| DataVersion::load(state).expect("Loading the data version failed"));
+
+	let run_directory = matches.output_dir.as_ref().map(|output_dir| {
+		RunDirectory::create(output_dir, chrono::offset::Utc::now()).expect("Creating the run directory failed")
+	});
+
+	// Write a partial report instead of losing the results on SIGINT or SIGTERM
+	interrupt::install();
+	let interrupt = Interrupt::default();
+
+	// Verifies a beacon with a client of its own, the members of a network in turn
+	let verify = |beacon_url: &Url, client: Client| {
+		log::info!("Validating implementation on {}", beacon_url);
+		let configuration = RunConfiguration {
+			url: beacon_url.clone(),
+			..configuration.clone()
+		};
+		let mut output = match Beacon::new(model.clone(), framework.clone(), client, beacon_url) {
+			Ok(mut beacon) => {
+				beacon = beacon
+					.interrupt(interrupt.clone())
+					.formats(formats.clone())
+					.configuration(configuration.clone())
+					.latency_budgets(profile.latency_budgets.clone());
+				if let Some(jobs) = matches.jobs {
+					beacon = beacon.jobs(jobs);
+				}
+				if let Some(slow) = matches.slow_ms {
+					beacon = beacon.slow_threshold(Duration::from_millis(slow));
+				}
+				if let Some(time_budget) = matches.time_budget {
+					beacon = beacon.time_budget(Duration::from_secs(time_budget));
+				}
+				if let Some(previous) = &previous_data_version {
+					beacon = beacon.incremental(previous.clone());
+				}
+				if let Some(history) = &history {
+					let flaky = history
+						.flaky()
+						.into_iter()
+						.map(|(entity, check, _)| (entity, check))
+						.collect();
+					beacon = beacon.retry_flaky(flaky, matches.retries.unwrap_or_default());
+				}
+				if only_model {
+					beacon = beacon.only(Category::Model);
+				}
+				if matches.observed_data {
+					beacon = beacon.with_observed_data();
+				}
+				if matches.public_report {
+					beacon = beacon.public_report();
+				}
+				if matches.robustness {
+					beacon = beacon.robustness();
+				}
+				if matches.pagination {
+					beacon = beacon.pagination();
+				}
+				if matches.limits {
+					beacon = beacon.limits();
+				}
+				if matches.adaptive {
+					beacon = beacon.adaptive();
+				}
+				if matches.post_queries {
+					beacon = beacon.post_queries();
+				}
+				if matches.negative {
+					beacon = beacon.negative();
+				}
+				if matches.references {
+					beacon = beacon.references();
+				}
+				if matches.filters {
+					beacon = beacon.filters();
+				}
+				if matches.resolve_ontologies {
+					let mut resolver = OntologyResolver::default().http(http.clone());
+					if let Some(path) = &matches.ontology_cache {
+						resolver = resolver.cache_file(path.clone());
+					}
+					beacon = beacon.resolve_ontologies(resolver);
+				}
+				if matches.variant_queries {
+					beacon = beacon.variant_queries();
+				}
+				beacon = beacon.sampling(Sampling {
+					max_instances: matches.max_instances.unwrap_or(1),
+					seed: matches.sample_seed,
+				});
+				if matches.head {
+					beacon = beacon.head_support();
+				}
+				if matches.discover {
+					beacon = beacon.discover();
+				}
+				if let Some(version) = matches.min_tls_version {
+					beacon = beacon.min_tls_version(version);
+				}
+				if let Some(version) = matches.min_http_version {
+					beacon = beacon.min_http_version(version);
+				}
+				if let Some(anonymizer) = &anonymizer {
+					beacon = beacon.anonymizer(anonymizer.clone());
+				}
+				beacon.validate()
+			},
+			Err(e) => BeaconOutput {
+				name: format!("Unknown Beacon ({})", e),
+				organization: None,
+				url: beacon_url.clone(),
+				last_updated: chrono::offset::Utc::now().naive_utc().round_subsecs(6),
+				entities: BTreeMap::new(),
+				observed: None,
+				configuration: Some(configuration),
+				token_refreshes: Vec::new(),
+				incremental: None,
+				scope: None,
+				status: None,
+				policy: None,
+				limits: None,
+				interrupted: false,
+				protocols: BTreeMap::new(),
+				data_use: BTreeMap::new(),
+				head_support: BTreeMap::new(),
+				resource_usage: None,
+				error: Some(e.to_string()),
+			},
+		};
+		if output.scope.is_none() {
+			output.scope = output
+				.configuration
+				.as_ref()
+				.map(|configuration| Scope::new(configuration, &output));
+		}
+		output.status = Some(Status::new(output.failures_by_category().is_empty(), in_maintenance));
+		output.policy = policy.as_ref().map(|policy| policy.evaluate(&output));
+		output
+	};
+
+	if network {
+		let mut reports = Vec::new();
+		for beacon_url in &beacon_urls {
+			let output = verify(beacon_url, client.fork());
+			let interrupted = output.interrupted;
+			if !interrupted {
+				notify::notify(&profile.webhooks, &output, &http);
+			}
+			reports.push(output);
+			if interrupted {
+				break;
+			}
+		}
+		let report = NetworkReport::new(reports);
+		if matches.summary {
+			log::set_max_level(log::LevelFilter::Trace);
+		}
+		else if !matches.quiet {
+			eprintln!();
+		}
+		for output in &report.reports {
+			output.summary(matches.time_style);
+		}
+		report.summary();
+		if !matches.summary {
+			let payload = renderer.render_network(&report).expect("Rendering the output failed");
+			match &matches.report_file {
+				Some(report_file) => artifacts::write_atomic(report_file, &payload).expect("Writing the report failed"),
+				None => std::io::stdout()
+					.write_all(&payload)
+					.expect("Writing the output failed"),
+			}
+		}
+		exit(&report.reports, matches.fail_on);
+	}
+
+	let mut output = verify(&beacon_url, client);
+
+	// The data-dependent checks only run again once the data changes, so the data version of a run that did not
+	// complete or did not pass is not saved
+	let passed = !output.interrupted && output.error.is_none() && output.failures_by_category().is_empty();
+	if let (Some(state), Some(incremental), true) = (&matches.incremental, &output.incremental, passed) {
+		incremental
+			.data_version
+			.save(state)
+			.expect("Saving the data version failed");
+	}
+
+	// A partial run would count the endpoints it did not reach as failures
+	// A replay only tells what the current checks make of a past run
+	if !output.interrupted && replayed_run.is_none() {
+		if let (Some(path), Some(history)) = (&matches.history, &mut history) {
+			history.record(&output);
+			history.mark_flaky(&mut output);
+			if let (Some(recorder), Some(run)) = (&recorder, history.runs.last()) {
+				fixtures::save(&history::fixtures_path(path, &run.id()), &recorder.fixtures())
+					.expect("Saving the responses of the run failed");
+			}
+			history.save(path).expect("Saving the history failed");
+		}
+
+		notify::notify(&profile.webhooks, &output, &http);
+	}
+
+	if let (Some(dir), Some(recorder)) = (&matches.dump_responses, &recorder) {
+		let fixtures = recorder.fixtures();
+		if let Some(run_directory) = &run_directory {
+			fixtures::dump(&run_directory.path("responses"), &fixtures, &output).expect("Writing the responses failed");
+		}
+		fixtures::dump(dir, &fixtures, &output).expect("Writing the responses failed");
+		log::info!("{} responses written to {}", fixtures.len(), dir.display());
+	}
+	if let (Some(path), Some(recorder)) = (&matches.har, &recorder) {
+		let log = har::har(&recorder.fixtures());
+		let har = serde_json::to_vec_pretty(&log).expect("Rendering the HAR failed");
+		if let Some(run_directory) = &run_directory {
+			run_directory
+				.write("traffic.har", &har)
+				.expect("Writing the HAR failed");
+		}
+		artifacts::write_atomic(path, &har).expect("Writing the HAR failed");
+		log::info!("Traffic of the run written to {}", path.display());
+	}
+
+	if let Some(metrics_out) = &matches.metrics_out {
+		let metrics = OpenMetrics.render(&output).expect("Rendering the metrics failed");
+		if let Some(run_directory) = &run_directory {
+			run_directory
+				.write("metrics.txt", &metrics)
+				.expect("Writing the metrics failed");
+		}
+		artifacts::write_atomic(metrics_out, &metrics).expect("Writing the metrics failed");
+	}
+
+	if let Some(run_directory) = run_directory {
+		let payload = renderer.render(&output).expect("Rendering the output failed");
+		run_directory
+			.write(&format!("report.{}", renderer.extension()), &payload)
+			.expect("Writing the report failed");
+		let directory = run_directory
+			.finish(matches.keep_runs)
+			.expect("Completing the run directory failed");
+		log::info!("Artifacts of the run written to {}", directory.display());
+	}
+
 	if matches.summary {
 		log::set_max_level(log::LevelFilter::Trace);
-		output.summary();
+		output.summary(matches.time_style);
 	}
 	else {
 		if !matches.quiet {
 			eprintln!();
 		}
-		output.summary();
-		let payload = serde_json::to_string_pretty(&output).unwrap();
-		println!("{}", payload);
+		output.summary(matches.time_style);
+		let payload = renderer.render(&output).expect("Rendering the output failed");
+		match &matches.report_file {
+			Some(report_file) => artifacts::write_atomic(report_file, &payload).expect("Writing the report failed"),
+			None => std::io::stdout()
+				.write_all(&payload)
+				.expect("Writing the output failed"),
+		}
+	}
+
+	if let Some(run) = &replayed_run {
+		let caught = run.caught(&output);
+		if caught.is_empty() {
+			log::info!(
+				"No check fails on the responses of the run {} that did not fail then",
+				run.id()
+			);
+		}
+		else {
+			log::warn!(
+				"{} checks fail on the responses of the run {} but did not fail then:",
+				caught.len(),
+				run.id()
+			);
+			for outcome in &caught {
+				log::warn!("  {} / {} ({})", outcome.entity, outcome.check, outcome.outcome);
+			}
+		}
+	}
+
+	exit(std::slice::from_ref(&output), matches.fail_on);
+}
+
+/// Logs the totals of the checks of the runs and exits with the code of their results: interrupted, not verified,
+/// with a check at `fail_on` or above, or else conformant
+fn exit(outputs: &[BeaconOutput], fail_on: Severity) -> ! {
+	let mut totals = Totals::default();
+	outputs.iter().for_each(|output| totals.add(output));
+	let code = if outputs.iter().any(|output| output.interrupted) {
+		interrupt::EXIT_CODE
+	}
+	else if outputs.iter().any(|output| output.error.is_some()) {
+		EXIT_ERROR
 	}
+	else if outputs
+		.iter()
+		.any(|output| output.severity().map_or(false, |severity| severity >= fail_on))
+	{
+		EXIT_FAILURES
+	}
+	else {
+		0
+	};
+	log::info!("{} (exit code {}, --fail-on {})", totals, code, fail_on);
+	std::process::exit(code)
 }
@@ -0,0 +1,201 @@
+//! Planned maintenance windows of a beacon
+//!
+//! A window starts at every time matching its cron schedule (in UTC) and lasts
+//! `duration-minutes`. Failures during a window are recorded as maintenance:
+//! they do not alert, the probe does not fail and the history does not count
+//! them when looking for flaky checks.
+//!
+//! ```toml
+//! [[beacons.production.maintenance]]
+//! # Sundays at 02:00 UTC, for two hours
+//! schedule = "0 2 * * 0"
+//! duration-minutes = 120
+//! ```
+
+use std::convert::TryFrom;
+
+use chrono::{Datelike, Duration, NaiveDateTime, Timelike};
+use serde::{Deserialize, Serialize};
+
+use crate::error::VerifierError;
+
+/// Longest window, to bound the search of its start
+const MAX_DURATION_MINUTES: u32 = 7 * 24 * 60;
+
+/// Schedule with the 5 fields of cron (minute, hour, day of month, month, day of week), where each
+/// field is `*`, a value, a range (`1-5`), a step (`*/15`, `0-30/10`) or a list of those (`0,30`)
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Schedule {
+	minutes: Vec<bool>,
+	hours: Vec<bool>,
+	days: Vec<bool>,
+	months: Vec<bool>,
+	weekdays: Vec<bool>,
+	/// Whether the days of month or of week are restricted, then a time matches any of them (as in cron)
+	any_day: bool,
+}
+
+fn parse_field(field: &str, min: u32, max: u32) -> Result<Vec<bool>, VerifierError> {
+	let bad = || VerifierError::BadConfig(format!("bad cron field '{}' (values from {} to {})", field, min, max));
+	let mut values = vec![false; max as usize + 1];
+	for part in field.split(',') {
+		let (range, step) = match part.split_once('/') {
+			Some((range, step)) => (range, step.parse::<u32>().map_err(|_| bad())?),
+			None => (part, 1),
+		};
+		let (start, end) = match range {
+			"*" => (min, max),
+			range => match range.split_once('-') {
+				Some((start, end)) => (start.parse().map_err(|_| bad())?, end.parse().map_err(|_| bad())?),
+				None => {
+					let value = range.parse().map_err(|_| bad())?;
+					// A step from a single value runs to the end (e.g. `5/15`)
+					(value, if part.contains('/') { max } else { value })
+				},
+			},
+		};
+		if step == 0 || start < min || end > max || start > end {
+			return Err(bad());
+		}
+		for value in (start..=end).step_by(step as usize) {
+			values[value as usize] = true;
+		}
+	}
+	Ok(values)
+}
+
+impl TryFrom<String> for Schedule {
+	type Error = VerifierError;
+
+	fn try_from(schedule: String) -> Result<Self, Self::Error> {
+		let fields = schedule.split_whitespace().collect::<Vec<_>>();
+		if fields.len() != 5 {
+			return Err(VerifierError::BadConfig(format!(
+				"cron schedule '{}' must have 5 fields",
+				schedule
+			)));
+		}
+		let mut weekdays = parse_field(fields[4], 0, 7)?;
+		// Both 0 and 7 are Sunday
+		weekdays[0] |= weekdays[7];
+		Ok(Self {
+			minutes: parse_field(fields[0], 0, 59)?,
+			hours: parse_field(fields[1], 0, 23)?,
+			days: parse_field(fields[2], 1, 31)?,
+			months: parse_field(fields[3], 1, 12)?,
+			weekdays,
+			any_day: fields[2] != "*" && fields[4] != "*",
+		})
+	}
+}
+
+impl Schedule {
+	pub fn matches(&self, at: NaiveDateTime) -> bool {
+		let day = self.days[at.day() as usize];
+		let weekday = self.weekdays[at.weekday().num_days_from_sunday() as usize];
+		let day = if self.any_day { day || weekday } else { day && weekday };
+		self.minutes[at.minute() as usize] && self.hours[at.hour() as usize] && self.months[at.month() as usize] && day
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct MaintenanceWindow {
+	pub schedule: Schedule,
+	pub duration_minutes: u32,
+}
+
+impl MaintenanceWindow {
+	/// Whether a window started less than `duration-minutes` before `at`
+	pub fn contains(&self, at: NaiveDateTime) -> bool {
+		let start = at.with_second(0).and_then(|at| at.with_nanosecond(0)).unwrap_or(at);
+		(0..self.duration_minutes.min(MAX_DURATION_MINUTES))
+			.map(|minutes| start - Duration::minutes(i64::from(minutes)))
+			.any(|start| self.schedule.matches(start))
+	}
+}
+
+/// Status of a beacon after a run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+	/// Every check passed
+	Up,
+	/// Some check failed outside of the maintenance windows
+	Down,
+	/// The run happened during a maintenance window
+	Maintenance,
+}
+
+impl Status {
+	pub fn new(success: bool, maintenance: bool) -> Self {
+		match (success, maintenance) {
+			(_, true) => Self::Maintenance,
+			(true, false) => Self::Up,
+			(false, false) => Self::Down,
+		}
+	}
+
+	/// Whether the status pages the operators: failures alert until they are fixed, also when they
+	/// outlast a maintenance window, but never during one
+	pub fn alerts(self) -> bool {
+		self == Self::Down
+	}
+}
+
+impl std::fmt::Display for Status {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Up => write!(f, "up"),
+			Self::Down => write!(f, "down"),
+			Self::Maintenance => write!(f, "maintenance"),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use std::convert::TryFrom;
+
+	use chrono::NaiveDate;
+
+	use crate::maintenance::{MaintenanceWindow, Schedule, Status};
+
+	fn at(day: u32, hour: u32, minute: u32) -> chrono::NaiveDateTime {
+		// 2022-05-01 is a Sunday
+		NaiveDate::from_ymd(2022, 5, day).and_hms(hour, minute, 30)
+	}
+
+	#[test]
+	fn test_schedule() {
+		let sundays = Schedule::try_from("0 2 * * 7".to_string()).unwrap();
+		assert!(sundays.matches(at(1, 2, 0)));
+		assert!(!sundays.matches(at(2, 2, 0)));
+		let quarters = Schedule::try_from("*/15 9-17 1,15 * 1-5".to_string()).unwrap();
+		assert!(quarters.matches(at(1, 9, 45)));
+		assert!(quarters.matches(at(4, 17, 0)));
+		assert!(!quarters.matches(at(7, 12, 0)));
+		assert!(!quarters.matches(at(4, 12, 10)));
+		assert!(Schedule::try_from("0 2 * *".to_string()).is_err());
+		assert!(Schedule::try_from("60 2 * * *".to_string()).is_err());
+		assert!(Schedule::try_from("*/0 2 * * *".to_string()).is_err());
+	}
+
+	#[test]
+	fn test_window() {
+		let window = MaintenanceWindow {
+			schedule: Schedule::try_from("30 23 * * 0".to_string()).unwrap(),
+			duration_minutes: 60,
+		};
+		assert!(!window.contains(at(1, 23, 29)));
+		assert!(window.contains(at(1, 23, 30)));
+		// Over midnight
+		assert!(window.contains(at(2, 0, 29)));
+		assert!(!window.contains(at(2, 0, 30)));
+		assert_eq!(Status::new(false, true), Status::Maintenance);
+		assert!(!Status::new(false, true).alerts());
+		assert!(Status::new(false, false).alerts());
+	}
+}
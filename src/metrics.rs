@@ -0,0 +1,289 @@
+//! Final metrics of a run in the OpenMetrics text format
+//!
+//! The file can be read by the textfile collector of `node_exporter` or pushed
+//! to a Prometheus pushgateway, so one-shot runs (e.g. in CI) are monitored
+//! without a running daemon.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::maintenance::Status;
+use crate::output::{BeaconOutput, Category, Outcome};
+use crate::scope::Depth;
+
+const PREFIX: &str = "beacon_verifier";
+
+fn escape(value: &str) -> String {
+	value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn labels(labels: &[(&str, &str)]) -> String {
+	let labels = labels
+		.iter()
+		.map(|(name, value)| format!("{}=\"{}\"", name, escape(value)))
+		.collect::<Vec<_>>();
+	format!("{{{}}}", labels.join(","))
+}
+
+fn family(metrics: &mut String, name: &str, kind: &str, help: &str) {
+	let _ = writeln!(metrics, "# TYPE {}_{} {}", PREFIX, name, kind);
+	let _ = writeln!(metrics, "# HELP {}_{} {}", PREFIX, name, help);
+}
+
+/// Renders the metrics of the output, ending with `# EOF`
+pub fn openmetrics(output: &BeaconOutput) -> String {
+	let mut metrics = String::new();
+	let beacon = [("beacon", output.url.as_str())];
+
+	family(
+		&mut metrics,
+		"checks",
+		"gauge",
+		"Number of checks per category and result.",
+	);
+	let mut checks = BTreeMap::new();
+	for category in Category::ALL {
+		for outcome in Outcome::ALL {
+			checks.insert((category, outcome), 0);
+		}
+	}
+	for report in output.entities.values().flatten() {
+		*checks.entry((report.category, report.outcome())).or_default() += 1;
+	}
+	for ((category, outcome), count) in checks {
+		let (category, result) = (category.to_string(), outcome.to_string());
+		let _ = writeln!(
+			metrics,
+			"{}_checks{} {}",
+			PREFIX,
+			labels(&[beacon[0], ("category", &category), ("result", &result)]),
+			count
+		);
+	}
+
+	family(
+		&mut metrics,
+		"check_valid",
+		"gauge",
+		"Whether each check passed (1) or not (0).",
+	);
+	for (entity_name, reports) in &output.entities {
+		for report in reports.iter().filter(|report| !report.skipped) {
+			let category = report.category.to_string();
+			let mut check_labels = vec![beacon[0], ("entity", entity_name), ("check", &report.name)];
+			check_labels.extend(report.record.as_deref().map(|record| ("record", record)));
+			check_labels.push(("category", &category));
+			let _ = writeln!(
+				metrics,
+				"{}_check_valid{} {}",
+				PREFIX,
+				labels(&check_labels),
+				u8::from(report.valid == Some(true))
+			);
+		}
+	}
+
+	let timings = output
+		.entities
+		.iter()
+		.flat_map(|(entity_name, reports)| reports.iter().map(move |report| (entity_name, report)))
+		.filter_map(|(entity_name, report)| report.timings.as_ref().map(|timings| (entity_name, report, timings)))
+		.collect::<Vec<_>>();
+	if !timings.is_empty() {
+		family(
+			&mut metrics,
+			"check_ttfb_seconds",
+			"gauge",
+			"Time to the first byte of the response of each check.",
+		);
+		for (entity_name, report, timings) in timings {
+			let mut check_labels = vec![beacon[0], ("entity", entity_name), ("check", &report.name)];
+			check_labels.extend(report.record.as_deref().map(|record| ("record", record)));
+			let _ = writeln!(
+				metrics,
+				"{}_check_ttfb_seconds{} {}",
+				PREFIX,
+				labels(&check_labels),
+				timings.ttfb_ms as f64 / 1000.0
+			);
+		}
+	}
+
+	family(
+		&mut metrics,
+		"score",
+		"gauge",
+		"Share of the checks that passed, without the skipped ones.",
+	);
+	let _ = writeln!(metrics, "{}_score{} {}", PREFIX, labels(&beacon), output.score());
+
+	if let Some(scope) = &output.scope {
+		family(
+			&mut metrics,
+			"entity_depth",
+			"stateset",
+			"How deep the checks of each entity went.",
+		);
+		for (entity_name, depth) in &scope.entities {
+			for state in Depth::ALL {
+				let state_name = state.to_string();
+				let _ = writeln!(
+					metrics,
+					"{}_entity_depth{} {}",
+					PREFIX,
+					labels(&[
+						beacon[0],
+						("entity", entity_name),
+						("beacon_verifier_entity_depth", &state_name)
+					]),
+					u8::from(*depth == state)
+				);
+			}
+		}
+		family(
+			&mut metrics,
+			"not_verified",
+			"gauge",
+			"Number of the parts of the specification not verified.",
+		);
+		let _ = writeln!(
+			metrics,
+			"{}_not_verified{} {}",
+			PREFIX,
+			labels(&beacon),
+			scope.not_verified.len()
+		);
+	}
+
+	if let Some(status) = output.status {
+		family(
+			&mut metrics,
+			"maintenance",
+			"gauge",
+			"Whether the run happened during a maintenance window.",
+		);
+		let _ = writeln!(
+			metrics,
+			"{}_maintenance{} {}",
+			PREFIX,
+			labels(&beacon),
+			u8::from(status == Status::Maintenance)
+		);
+	}
+
+	family(
+		&mut metrics,
+		"token_refreshes",
+		"gauge",
+		"Number of refreshes of the access token during the run.",
+	);
+	let _ = writeln!(
+		metrics,
+		"{}_token_refreshes{} {}",
+		PREFIX,
+		labels(&beacon),
+		output.token_refreshes.len()
+	);
+
+	if let Some(usage) = &output.resource_usage {
+		let gauges = [
+			(
+				"peak_rss_bytes",
+				"Peak resident set size of the verifier.",
+				usage.peak_rss_bytes.map(|bytes| bytes as f64),
+			),
+			(
+				"cpu_seconds",
+				"CPU time of the verifier.",
+				usage.cpu_ms.map(|ms| ms as f64 / 1000.0),
+			),
+			(
+				"requests",
+				"Number of requests sent during the run.",
+				Some(usage.requests as f64),
+			),
+			(
+				"sent_bytes",
+				"Bytes of the bodies of the requests.",
+				Some(usage.bytes_sent as f64),
+			),
+			(
+				"received_bytes",
+				"Bytes of the bodies of the responses.",
+				Some(usage.bytes_received as f64),
+			),
+		];
+		for (name, help, value) in gauges {
+			if let Some(value) = value {
+				family(&mut metrics, name, "gauge", help);
+				let _ = writeln!(metrics, "{}_{}{} {}", PREFIX, name, labels(&beacon), value);
+			}
+		}
+	}
+
+	family(
+		&mut metrics,
+		"last_run_timestamp_seconds",
+		"gauge",
+		"Time of the run, in seconds since the epoch.",
+	);
+	let _ = writeln!(
+		metrics,
+		"{}_last_run_timestamp_seconds{} {}",
+		PREFIX,
+		labels(&beacon),
+		output.last_updated.timestamp()
+	);
+
+	metrics.push_str("# EOF\n");
+	metrics
+}
+
+#[cfg(test)]
+mod tests {
+
+	use crate::metrics::{escape, openmetrics};
+	use crate::testing::{self, Behavior, MockBeacon};
+
+	#[test]
+	fn test_openmetrics() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let metrics = openmetrics(&beacon.verify());
+		let label = format!("beacon=\"{}\"", beacon.url());
+		assert!(metrics.ends_with("\n# EOF\n"));
+		assert!(metrics.contains("# TYPE beacon_verifier_checks gauge\n"));
+		assert!(metrics.contains(&format!(
+			"beacon_verifier_checks{{{},category=\"framework\",result=\"failed\"}} 0\n",
+			label
+		)));
+		assert!(metrics.contains(&format!(
+			"beacon_verifier_check_valid{{{},entity=\"Individual\",check=\"Individual all \
+			 entries\",category=\"model\"}} 1\n",
+			label
+		)));
+		assert!(!metrics.contains("check_ttfb_seconds"));
+		assert!(metrics.contains("# TYPE beacon_verifier_requests gauge\n"));
+		assert!(!metrics.contains("entity_depth"));
+
+		let output = beacon
+			.beacon()
+			.configuration(testing::configuration(&beacon))
+			.validate();
+		let metrics = openmetrics(&output);
+		assert!(metrics.contains("# TYPE beacon_verifier_entity_depth stateset\n"));
+		assert!(metrics.contains(&format!(
+			"beacon_verifier_entity_depth{{{},entity=\"Individual\",beacon_verifier_entity_depth=\"sampled\"}} 1\n",
+			label
+		)));
+		assert!(metrics.contains(&format!(
+			"beacon_verifier_not_verified{{{}}} {}\n",
+			label,
+			output.scope.unwrap().not_verified.len()
+		)));
+	}
+
+	#[test]
+	fn test_escape() {
+		assert_eq!(escape("a \"b\"\\\nc"), "a \\\"b\\\"\\\\\\nc");
+	}
+}
@@ -1,29 +1,103 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::ffi::OsStr;
 use std::fs::File;
 use std::path::{Path, PathBuf};
-use std::rc::Rc;
+use std::sync::Arc;
 
-use git2::Repository;
 use jsonschema::JSONSchema;
 use url::Url;
 
+use crate::cache::SchemaCache;
+use crate::client::Client;
 use crate::endpoint::BeaconEndpoint;
 use crate::error::VerifierError;
-use crate::interface::{Endpoint, EntryType, RelatedEndpoint};
-use crate::utils::replace_vars;
-use crate::{utils, Json};
+use crate::formats::Formats;
+use crate::interface::{Endpoint, EntryType, Granularity, RelatedEndpoint};
+use crate::preset::{self, ModelPreset};
+use crate::resolver::{self, SchemaStore};
+use crate::utils::{replace_vars, Ids};
+use crate::{deprecated, utils, Json};
+
+#[derive(Debug, Clone)]
+pub struct EntitySchema {
+	pub id: Option<String>,
+	pub schema: Arc<JSONSchema>,
+	/// Paths of the properties the schema deprecates
+	pub deprecated: Vec<String>,
+}
 
 #[derive(Debug, Clone)]
 pub struct Entity {
 	pub name: String,
+	pub entry_type: String,
 	pub url: Url,
 	pub url_single: Option<Url>,
-	pub schema: Rc<JSONSchema>,
+	/// Property of the records with their id
+	pub id_field: String,
+	/// Default schema first, followed by the additionally supported schemas
+	pub schemas: Vec<EntitySchema>,
 	pub filtering_terms_url: Option<Url>,
 	pub related_endpoints: Option<BTreeMap<String, RelatedEndpoint>>,
 }
 
+impl Entity {
+	/// Whether `name` is the name, the entry type or the last segment of the url of the entity (e.g. `g_variants`),
+	/// case insensitive
+	pub fn is_named(&self, name: &str) -> bool {
+		let segment = self
+			.url
+			.path_segments()
+			.and_then(|segments| segments.rev().find(|s| !s.is_empty()));
+		self.name.eq_ignore_ascii_case(name)
+			|| self.entry_type.eq_ignore_ascii_case(name)
+			|| segment.map_or(false, |segment| segment.eq_ignore_ascii_case(name))
+	}
+}
+
+/// Records whose record-level endpoints are checked, among the ids of the first page of each entity
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sampling {
+	/// Records checked at most for each entity
+	pub max_instances: usize,
+	/// Seed of the sample, the first records being checked without one
+	pub seed: Option<u64>,
+}
+
+impl Default for Sampling {
+	fn default() -> Self {
+		Self {
+			max_instances: 1,
+			seed: None,
+		}
+	}
+}
+
+/// Parses the records checked at most for each entity, at least one
+pub fn parse_max_instances(s: &str) -> Result<usize, String> {
+	match s.parse::<usize>() {
+		Ok(0) => Err("at least one record must be checked".into()),
+		Ok(max_instances) => Ok(max_instances),
+		Err(e) => Err(e.to_string()),
+	}
+}
+
+impl Sampling {
+	/// Ids of the records to check, the same for the same seed and ids whatever the order of the response
+	pub fn sample<'a>(&self, ids: &'a [String]) -> Vec<&'a str> {
+		let mut seen = BTreeSet::new();
+		let mut ids = ids
+			.iter()
+			.map(String::as_str)
+			.filter(|id| seen.insert(*id))
+			.collect::<Vec<_>>();
+		if let Some(seed) = self.seed {
+			ids.sort_by_key(|id| (utils::fnv1a(format!("{}:{}", seed, id).as_bytes()), *id));
+		}
+		ids.truncate(self.max_instances);
+		ids
+	}
+}
+
 #[derive(Debug, Clone)]
 pub struct Model {
 	pub entities: Vec<Entity>,
@@ -31,41 +105,26 @@ pub struct Model {
 	pub configuration_json: Json,
 	pub beacon_map_json: Json,
 	pub endpoints_json: Json,
+	/// Commit of the repository the model was loaded from
+	pub revision: Option<String>,
 	files: BTreeMap<PathBuf, Json>,
+	/// Documents the references of the schemas are resolved to
+	store: SchemaStore,
 }
 
 impl Model {
-	pub fn load(location: &Url) -> Result<Self, VerifierError> {
+	pub fn load(location: &Url, formats: &Formats, cache: &SchemaCache) -> Result<Self, VerifierError> {
 		let dir = tempfile::tempdir().expect("Could not create temporary directory");
 
-		if location.scheme() == "file" {
-			log::debug!("COPYING {} to {:?}", location.path(), dir.path());
-			utils::copy_dir_recursively(location.path(), &dir).expect("Copy dir recursively failed");
+		let revision = if location.scheme() == preset::SCHEME {
+			log::debug!("Extracting the model preset {} to {:?}", location.path(), dir.path());
+			ModelPreset::from_location(location)?.extract(dir.path())?;
+			// Versioned with the verifier
+			None
 		}
 		else {
-			// Parse model repo URL
-			assert_eq!(
-				location.domain().unwrap_or(""),
-				"github.com",
-				"Only repos hosted on github.com are supported"
-			);
-			let mut url_iter = Path::new(location.path()).components().skip(1);
-			let owner = url_iter.next().unwrap().as_os_str().to_string_lossy().to_string();
-			let repo = url_iter.next().unwrap().as_os_str().to_string_lossy().to_string();
-			let path: PathBuf = url_iter.collect();
-
-			log::debug!("Downloading repo {} from {}", repo, owner);
-			log::debug!("Path inside repo = {:?}", path);
-
-			// Clone repo to tempdir
-			let repo_url = format!("https://github.com/{owner}/{repo}", owner = owner, repo = repo);
-			let full_git_dir = tempfile::tempdir().expect("Could not create temporary directory");
-			Repository::clone(&repo_url, full_git_dir.path()).expect("Unable to clone repository");
-
-			// Copy subfolder to the final tempdir
-			log::debug!("Copying from {:?} to {:?}", full_git_dir.path().join(&path), dir.path());
-			utils::copy_dir_recursively(full_git_dir.path().join(path), dir.path()).unwrap();
-		}
+			cache.fetch(location, dir.path())?
+		};
 
 		let mut model = Self {
 			entities: Vec::new(),
@@ -73,7 +132,9 @@ impl Model {
 			configuration_json: Json::Null,
 			beacon_map_json: Json::Null,
 			endpoints_json: Json::Null,
+			revision,
 			files: BTreeMap::new(),
+			store: SchemaStore::default(),
 		};
 
 		// Load files
@@ -83,15 +144,21 @@ impl Model {
 			}
 		}
 
+		model.store = SchemaStore::new(dir.path(), &mut model.files, location, cache);
+
 		// Load configuration
 		model.load_configuration(dir.path());
 
 		// Load entitites
-		model.load_entities(dir.path());
+		model.load_entities(formats)?;
 
 		Ok(model)
 	}
 
+	pub fn store(&self) -> &SchemaStore {
+		&self.store
+	}
+
 	fn add(&mut self, path: &Path) -> Result<(), VerifierError> {
 		log::debug!("Adding JSON file: {:?}", path);
 		let file = File::open(path).unwrap();
@@ -118,154 +185,505 @@ impl Model {
 			.clone();
 	}
 
-	fn load_entities(&mut self, base_path: &Path) {
+	/// Schema at `reference`, relative to the root of the model or the url of a published copy
+	fn load_schema(&self, reference: &str) -> Result<Json, VerifierError> {
+		let base = Url::parse(&format!("{}:///", resolver::SCHEME)).expect("Bad base of the schemas");
+		let url = base
+			.join(reference)
+			.map_err(|e| VerifierError::BadSchemaLocation(format!("{}: {}", reference, e)))?;
+		log::debug!("Loading schema on {}", url);
+		let schema = self
+			.store
+			.resolve_url(&url)
+			.map_err(|e| VerifierError::BadSchemaLocation(format!("{}: {}", reference, e)))?;
+		Ok(Json::clone(&schema))
+	}
+
+	/// Loads the entities, failing with all the schemas that cannot be loaded or compiled
+	fn load_entities(&mut self, formats: &Formats) -> Result<(), VerifierError> {
 		let mut entities_names = BTreeMap::new();
 
-		let entities_schemas = self.configuration_json["entryTypes"]
+		let mut default_schemas = BTreeMap::new();
+
+		let mut broken = Vec::new();
+		let entry_types = self.configuration_json["entryTypes"]
 			.as_object()
-			.unwrap()
+			.ok_or_else(|| VerifierError::BrokenSchemas("the configuration of the model has no entryTypes".into()))?;
+		let entry_types = entry_types
+			.iter()
+			.filter_map(|(key, val)| match serde_json::from_value::<EntryType>(val.clone()) {
+				Ok(entry_type) => Some(entry_type),
+				Err(e) => {
+					broken.push(format!("entry type {} of the configuration: {}", key, e));
+					None
+				},
+			})
+			.collect::<Vec<_>>();
+		let entities_schemas = entry_types
 			.into_iter()
-			.map(|(_, val)| {
-				let entry_type: EntryType = serde_json::from_value(val.clone()).unwrap();
+			.map(|entry_type| {
 				entities_names.insert(entry_type.id.clone(), entry_type.name);
-				let mut schema_rel_path = entry_type.default_schema.reference_to_schema_definition;
-				if schema_rel_path.starts_with("http") {
-					let schema_rel_path_url = Url::parse(&schema_rel_path).unwrap();
-					schema_rel_path = Path::new(schema_rel_path_url.path())
-						.components()
-						.skip(1)
-						.collect::<PathBuf>()
-						.to_string_lossy()
-						.to_string();
+				// The default schema goes first, then the alternatives
+				let schemas = std::iter::once(entry_type.default_schema)
+					.chain(entry_type.additionally_supported_schemas)
+					.filter_map(
+						|schema| match self.load_schema(&schema.reference_to_schema_definition) {
+							Ok(json) => Some((schema.id, json)),
+							Err(e) => {
+								broken.push(e.to_string());
+								None
+							},
+						},
+					)
+					.collect::<Vec<_>>();
+				if let Some((_, default_schema)) = schemas.first() {
+					default_schemas.insert(entry_type.id.clone(), default_schema.clone());
 				}
-				log::debug!("Loading schema on {:?} + {:?}", base_path, schema_rel_path);
-				let schema_abs_path = base_path.join(schema_rel_path);
-				log::debug!("Loading schema on {:?}", schema_abs_path);
-				let schema_file = File::open(schema_abs_path.canonicalize().unwrap()).expect("File not found");
-				let schema_json = serde_json::from_reader(schema_file).expect("Bad json schema");
-				(entry_type.id, schema_json)
+				let schemas = schemas
+					.into_iter()
+					.filter_map(
+						|(id, schema)| match utils::compile_schema(&schema, formats, &self.store) {
+							Ok(compiled) => Some(EntitySchema {
+								id,
+								schema: compiled,
+								// After the compilation, which fetched the remote schemas it references
+								deprecated: deprecated::properties(&schema, &self.store),
+							}),
+							Err(e) => {
+								broken.push(e.to_string());
+								None
+							},
+						},
+					)
+					.collect::<Vec<EntitySchema>>();
+				(entry_type.id, schemas)
 			})
-			.collect::<BTreeMap<String, Json>>();
+			.collect::<BTreeMap<String, Vec<EntitySchema>>>();
 
 		self.entities_names = entities_names;
 
-		for (_, entity) in self.beacon_map_json["endpointSets"].as_object().unwrap() {
-			let endpoint: Endpoint = serde_json::from_value(entity.clone()).unwrap();
-			let entity_schema = entities_schemas
-				.get(&endpoint.entry_type)
-				.unwrap_or_else(|| {
-					log::error!(
-						"No schema for entry type {}, available schemas = {:?}",
-						&endpoint.entry_type,
-						entities_schemas.keys()
-					);
-					panic!();
-				})
-				.clone();
+		let endpoint_sets = self.beacon_map_json["endpointSets"]
+			.as_object()
+			.ok_or_else(|| VerifierError::BrokenSchemas("the beacon map of the model has no endpointSets".into()))?;
+		for (key, entity) in endpoint_sets {
+			let endpoint: Endpoint = match serde_json::from_value(entity.clone()) {
+				Ok(endpoint) => endpoint,
+				Err(e) => {
+					broken.push(format!("endpoint set {} of the beacon map: {}", key, e));
+					continue;
+				},
+			};
+			let entity_schemas = match entities_schemas.get(&endpoint.entry_type) {
+				Some(entity_schemas) => entity_schemas.clone(),
+				None => {
+					broken.push(format!(
+						"no schema for the entry type {} of the beacon map, only for {:?}",
+						endpoint.entry_type,
+						entities_schemas.keys().collect::<Vec<_>>()
+					));
+					continue;
+				},
+			};
+			let url_single = endpoint
+				.single_entry_url
+				.or_else(|| self.single_entry_template(&endpoint.root_url));
+			let id_field = Self::id_field(
+				default_schemas.get(&endpoint.entry_type).unwrap_or(&Json::Null),
+				&url_single.as_ref().map(utils::template_vars).unwrap_or_default(),
+			);
+			log::debug!("Ids of {} in '{}'", endpoint.entry_type, id_field);
 			self.entities.push(Entity {
-				schema: utils::compile_schema(&entity_schema),
+				schemas: entity_schemas,
 				name: self
 					.entities_names
 					.get(&endpoint.entry_type)
 					.unwrap_or(&String::from("Unknown entity name"))
 					.clone(),
+				entry_type: endpoint.entry_type,
 				url: endpoint.root_url,
-				url_single: endpoint.single_entry_url,
+				url_single,
+				id_field,
 				filtering_terms_url: endpoint.filtering_terms_url,
 				related_endpoints: endpoint.endpoints,
 			});
 		}
+		if !broken.is_empty() {
+			return Err(VerifierError::BrokenSchemas(broken.join("\n")));
+		}
+		Ok(())
+	}
+
+	/// Template of the single entry endpoint declared in `endpoints.json` (e.g. `/individuals/{id}`),
+	/// for the models whose beacon map does not have it
+	fn single_entry_template(&self, root_url: &Url) -> Option<Url> {
+		let root_path = root_url.path().trim_end_matches('/');
+		let path = self.endpoints_json["paths"].as_object()?.keys().find(|path| {
+			path.strip_prefix(root_path)
+				.and_then(|rest| rest.strip_prefix("/{"))
+				.map_or(false, |var| var.ends_with('}') && !var.contains('/'))
+		})?;
+		let mut url = root_url.clone();
+		url.set_path(path);
+		Some(url)
 	}
 
-	fn build_endpoint(
+	/// Property of the records with their id, derived from the default schema of the entry type:
+	/// the variable of the single entry url if the schema has it, else `id`, else the first required
+	/// property ending in `Id` (e.g. `variantInternalId`)
+	fn id_field(default_schema: &Json, template_vars: &[String]) -> String {
+		let properties = default_schema["properties"].as_object();
+		let has_property = |name: &str| properties.map_or(false, |properties| properties.contains_key(name));
+		let required = default_schema["required"]
+			.as_array()
+			.map(|required| required.iter().filter_map(Json::as_str).collect::<Vec<_>>())
+			.unwrap_or_default();
+
+		template_vars
+			.iter()
+			.find(|var| has_property(var))
+			.map(String::as_str)
+			.or_else(|| has_property("id").then(|| "id"))
+			.or_else(|| required.into_iter().find(|name| name.ends_with("Id")))
+			.unwrap_or("id")
+			.to_string()
+	}
+
+	/// Only keeps the entities named (see [`Entity::is_named`]), warning of the unknown names
+	pub fn retain_entities(&mut self, names: &[String]) {
+		self.warn_unknown(names);
+		self.entities
+			.retain(|entity| names.iter().any(|name| entity.is_named(name)));
+	}
+
+	/// Leaves out the entities named (see [`Entity::is_named`]), warning of the unknown names
+	pub fn skip_entities(&mut self, names: &[String]) {
+		self.warn_unknown(names);
+		self.entities
+			.retain(|entity| !names.iter().any(|name| entity.is_named(name)));
+	}
+
+	fn warn_unknown(&self, names: &[String]) {
+		for name in names {
+			if !self.entities.iter().any(|entity| entity.is_named(name)) {
+				log::warn!("No entity {:?} in the model", name);
+			}
+		}
+	}
+
+	pub fn build_endpoint(
 		entity_name: String,
-		entity_schema: Rc<JSONSchema>,
+		entry_type: String,
+		entity_schemas: Vec<EntitySchema>,
 		name: String,
 		url: &Url,
-		vars: Vec<(&str, &str)>,
+		id: Option<&str>,
 	) -> BeaconEndpoint {
-		let replaced_url = replace_vars(url, vars);
+		// Every variable of the template takes the id of the record
+		let vars = utils::template_vars(url);
+		let replaced_url = replace_vars(
+			url,
+			id.map(|id| vars.iter().map(|var| (var.as_str(), id)).collect())
+				.unwrap_or_default(),
+		);
 		BeaconEndpoint {
 			entity_name,
-			entity_schema,
+			entry_type,
+			entity_schemas,
 			name,
 			url: replaced_url,
+			template_url: url.clone(),
+			record_id: id.map(String::from),
+			skipped: None,
+			no_ids: None,
+			outside_root: None,
+			declared_schema: None,
+			beacon_schemas: None,
 		}
 	}
 
-	pub fn endpoints(self, root_url: &Url) -> Vec<BeaconEndpoint> {
+	/// Endpoints to check, the record-level ones with the ids of their entity in `sampling` (`declared` is the
+	/// default granularity of the beacon, used when the responses do not tell theirs)
+	pub fn endpoints(
+		self,
+		client: &Client,
+		root_url: &Url,
+		declared: Option<Granularity>,
+		sampling: Sampling,
+	) -> Vec<BeaconEndpoint> {
 		self.entities
 			.iter()
 			.flat_map(|entity| {
 				let mut endpoints = Vec::new();
-				let entity_schema = &entity.schema;
 
 				endpoints.push(Self::build_endpoint(
 					entity.name.clone(),
-					entity_schema.clone(),
+					entity.entry_type.clone(),
+					entity.schemas.clone(),
 					format!("{} all entries", entity.name.clone()),
 					&entity.url,
-					vec![],
+					None,
 				));
 
-				let ids = utils::get_ids(root_url, &entity.url);
-
-				if let Ok(ids) = ids {
-					if let Some(url_single) = &entity.url_single {
-						endpoints.extend(ids.iter().take(1).map(|id| {
-							Self::build_endpoint(
-								entity.name.clone(),
-								entity_schema.clone(),
-								format!("{} single entry", entity.name.clone()),
-								url_single,
-								vec![("id", id)],
-							)
-						}));
-					}
-
-					// TODO: Filtering terms
-					// if let Some(filtering_terms_url) = &entity.filtering_terms_url {
-					// 	let available_filtering_terms = utils::get_filtering_terms(filtering_terms_url);
-					// 	endpoints.extend(available_filtering_terms.iter().take(1).map(|filtering_term| {
-					// 		Model::build_endpoint(
-					// 			entity.name,
-					// 			entity.schema,
-					// 			format!("{} filtering terms", entity.name.clone()),
-					// 			filtering_terms_url,
-					// 			vec![("id", &id)]
-					// 		)
-					// 	}));
-					// }
-
-					if let Some(related_endpoints) = &entity.related_endpoints {
-						endpoints.extend(related_endpoints.iter().flat_map(|(_, related_endpoint)| {
-							ids.iter().take(1).map(|id| {
-								let default_entity_name = "Unknown entity".to_string();
-								let related_entity_name = self
-									.entities_names
-									.get(&related_endpoint.returned_entry_type)
-									.unwrap_or(&default_entity_name);
-								let name = format!("{} related with a {}", related_entity_name, entity.name.clone());
-								let related_entity_schema = &self
-									.entities
-									.iter()
-									.find(|e| &e.name == related_entity_name)
-									.unwrap()
-									.schema;
-								Self::build_endpoint(
-									entity.name.clone(),
-									related_entity_schema.clone(),
-									name,
-									&related_endpoint.url,
-									vec![("id", id)],
-								)
-							})
-						}));
-					}
+				match utils::get_ids(client, &entity.name, root_url, &entity.url, &entity.id_field, declared) {
+					Ok(Ids::Found(ids)) => {
+						for id in sampling.sample(&ids) {
+							endpoints.extend(self.record_endpoints(entity, Some(id)));
+						}
+					},
+					Ok(Ids::NoData(evidence)) => {
+						log::info!("{} has no records, skipping the record-level checks", entity.name);
+						endpoints.extend(
+							self.record_endpoints(entity, None)
+								.into_iter()
+								.map(|endpoint| endpoint.no_data(evidence.clone())),
+						);
+					},
+					Ok(Ids::NoRecords(reason, evidence)) => {
+						log::info!("{} returns {}, skipping the record-level checks", entity.name, reason);
+						endpoints.extend(
+							self.record_endpoints(entity, None)
+								.into_iter()
+								.map(|endpoint| endpoint.skip(&reason, evidence.clone())),
+						);
+					},
+					// The endpoint of all the entries is optional, and reported as such
+					Err(VerifierError::MissingEndpoint(_)) => (),
+					Err(e) => {
+						log::error!("The ids of {} could not be read: {}", entity.name, e);
+						endpoints.extend(
+							self.record_endpoints(entity, None)
+								.into_iter()
+								.map(|endpoint| endpoint.without_ids(&e.to_string())),
+						);
+					},
 				}
 
 				endpoints
 			})
 			.collect()
 	}
+
+	/// Endpoints that need the id of a record (single entry and related endpoints)
+	fn record_endpoints(&self, entity: &Entity, id: Option<&str>) -> Vec<BeaconEndpoint> {
+		let mut endpoints = Vec::new();
+
+		if let Some(url_single) = &entity.url_single {
+			endpoints.push(Self::build_endpoint(
+				entity.name.clone(),
+				entity.entry_type.clone(),
+				entity.schemas.clone(),
+				format!("{} single entry", entity.name.clone()),
+				url_single,
+				id,
+			));
+		}
+
+		if let Some(related_endpoints) = &entity.related_endpoints {
+			endpoints.extend(related_endpoints.values().map(|related_endpoint| {
+				let default_entity_name = "Unknown entity".to_string();
+				let related_entity_name = self
+					.entities_names
+					.get(&related_endpoint.returned_entry_type)
+					.unwrap_or(&default_entity_name);
+				let name = format!("{} related with a {}", related_entity_name, entity.name.clone());
+				let related_entity_schemas = self
+					.entities
+					.iter()
+					.find(|e| e.entry_type == related_endpoint.returned_entry_type)
+					.map(|e| e.schemas.clone())
+					.unwrap_or_default();
+				Self::build_endpoint(
+					entity.name.clone(),
+					related_endpoint.returned_entry_type.clone(),
+					related_entity_schemas,
+					name,
+					&related_endpoint.url,
+					id,
+				)
+			}));
+		}
+
+		endpoints
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use serde_json::json;
+	use url::Url;
+	use wiremock::ResponseTemplate;
+
+	use crate::cache::SchemaCache;
+	use crate::client::Client;
+	use crate::error::VerifierError;
+	use crate::formats::Formats;
+	use crate::metrics;
+	use crate::model::{self, Model, Sampling};
+	use crate::output::{Category, Outcome};
+	use crate::testing::{self, Behavior, MockBeacon};
+	use crate::utils::{self, Ids};
+
+	#[test]
+	fn test_retain_entities() {
+		let mut model = testing::model();
+		model.retain_entities(&["individual".into(), "Biosample".into(), "Cohort".into()]);
+		let names = model
+			.entities
+			.iter()
+			.map(|entity| entity.name.as_str())
+			.collect::<Vec<_>>();
+		assert_eq!(names, vec!["Biosample", "Individual"]);
+
+		// By the path of their endpoint too
+		let mut model = testing::model();
+		model.skip_entities(&["datasets".into(), "g_variants".into()]);
+		let names = model
+			.entities
+			.iter()
+			.map(|entity| entity.name.as_str())
+			.collect::<Vec<_>>();
+		assert_eq!(names, vec!["Biosample", "Individual"]);
+		let mut model = testing::model();
+		model.retain_entities(&["BIOSAMPLES".into()]);
+		assert_eq!(model.entities.len(), 1);
+	}
+
+	#[test]
+	fn test_bad_configuration() {
+		let dir = tempfile::tempdir().unwrap();
+		utils::copy_dir_recursively(testing::fixture_url("model").to_file_path().unwrap(), dir.path()).unwrap();
+		let path = dir.path().join("beaconConfiguration.json");
+		let mut configuration: serde_json::Value = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+		let entry_types = configuration["entryTypes"].as_object_mut().unwrap();
+		entry_types.remove("biosample");
+		entry_types["dataset"].as_object_mut().unwrap().remove("defaultSchema");
+		std::fs::write(&path, configuration.to_string()).unwrap();
+		let location = Url::from_directory_path(dir.path()).unwrap();
+		let e = Model::load(&location, &Formats::default(), &SchemaCache::default().offline()).unwrap_err();
+		let message = e.to_string();
+		assert!(matches!(e, VerifierError::BrokenSchemas(_)), "{}", message);
+		// All the problems at once
+		assert!(message.contains("entry type dataset of the configuration: missing field `defaultSchema`"));
+		assert!(message.contains("no schema for the entry type biosample of the beacon map"));
+	}
+
+	#[test]
+	fn test_sampling() {
+		let ids = ["v1", "v2", "v2", "v3", "v4"].map(String::from);
+		assert_eq!(Sampling::default().sample(&ids), vec!["v1"]);
+		let first = Sampling {
+			max_instances: 3,
+			seed: None,
+		};
+		assert_eq!(first.sample(&ids), vec!["v1", "v2", "v3"]);
+		// The same records whatever the order of the response, others with another seed
+		let seeded = |seed| Sampling {
+			max_instances: 2,
+			seed: Some(seed),
+		};
+		let sample = seeded(7).sample(&ids);
+		assert_eq!(sample.len(), 2);
+		let mut reversed = ids.clone();
+		reversed.reverse();
+		assert_eq!(seeded(7).sample(&reversed), sample);
+		assert!((0..8).any(|seed| seeded(seed).sample(&ids) != sample));
+		assert!(model::parse_max_instances("0").is_err());
+
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let output = beacon.beacon().sampling(first).validate();
+		let single_entries = output.entities["Individual"]
+			.iter()
+			.filter(|report| report.name == "Individual single entry" && report.category == Category::Model)
+			.collect::<Vec<_>>();
+		assert_eq!(single_entries.len(), 2);
+		assert!(single_entries[0].url.path().ends_with("/individuals/ind1"));
+		assert_eq!(single_entries[0].valid, Some(true));
+		// The mock beacon does not serve the second individual
+		assert!(single_entries[1].url.path().ends_with("/individuals/ind2"));
+		assert_eq!(single_entries[1].valid, None);
+		// Told apart by their record in the history, the diffs and the metrics
+		assert_eq!(single_entries[0].key(), "Individual single entry [ind1]");
+		assert_eq!(single_entries[1].key(), "Individual single entry [ind2]");
+		let metrics = metrics::openmetrics(&output);
+		assert!(
+			metrics.contains("check=\"Individual single entry\",record=\"ind2\""),
+			"{}",
+			metrics
+		);
+	}
+
+	#[test]
+	fn test_id_field() {
+		let variant = json!({
+			"properties": { "variantInternalId": {}, "variation": {} },
+			"required": ["variation", "variantInternalId"],
+		});
+		assert_eq!(Model::id_field(&variant, &["id".into()]), "variantInternalId");
+		let cohort = json!({ "properties": { "id": {}, "cohortId": {} }, "required": ["cohortId"] });
+		assert_eq!(Model::id_field(&cohort, &["cohortId".into()]), "cohortId");
+		assert_eq!(Model::id_field(&cohort, &[]), "id");
+		assert_eq!(Model::id_field(&json!({}), &[]), "id");
+		assert!(testing::model().entities.iter().all(|entity| entity.id_field == "id"));
+	}
+
+	#[test]
+	fn test_single_entry_template() {
+		let mut model = testing::model();
+		model.endpoints_json = json!({
+			"paths": { "/cohorts": {}, "/cohorts/{cohortId}/individuals": {}, "/cohorts/{cohortId}": {} },
+		});
+		let template = model.single_entry_template(&Url::parse("https://example.org/cohorts").unwrap());
+		assert_eq!(template.unwrap().as_str(), "https://example.org/cohorts/%7BcohortId%7D");
+		assert!(model
+			.single_entry_template(&Url::parse("https://example.org/runs").unwrap())
+			.is_none());
+	}
+
+	#[test]
+	fn test_custom_ids() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		beacon.mount(
+			"/samples",
+			ResponseTemplate::new(200).set_body_json(json!({
+				"meta": { "returnedGranularity": "record" },
+				"responseSummary": { "exists": true, "numTotalResults": 2 },
+				"response": { "resultSets": [{ "results": [{ "sampleCode": "S1" }, { "sampleCode": 2 }] }] },
+			})),
+		);
+		let url = Url::parse("https://example.org/samples").unwrap();
+		match utils::get_ids(&Client::new(), "Sample", &beacon.url(), &url, "sampleCode", None).unwrap() {
+			Ids::Found(ids) => assert_eq!(ids, vec!["S1", "2"]),
+			Ids::NoData(_) | Ids::NoRecords(..) => panic!("expected ids"),
+		}
+	}
+
+	#[test]
+	fn test_missing_ids() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let url = Url::parse("https://example.org/individuals").unwrap();
+		let ids = utils::get_ids(&Client::new(), "Individual", &beacon.url(), &url, "individualId", None);
+		assert!(matches!(ids, Err(VerifierError::MissingIdProperty(field)) if field == "individualId"));
+		beacon.mount("/individuals", ResponseTemplate::new(500));
+		let ids = utils::get_ids(&Client::new(), "Individual", &beacon.url(), &url, "id", None);
+		assert!(matches!(ids, Err(VerifierError::UnresponsiveEndpoint(_))));
+
+		// The endpoints needing an id fail instead of being left out
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let mut response = beacon.response("/individuals");
+		for record in response["response"]["resultSets"][0]["results"].as_array_mut().unwrap() {
+			record.as_object_mut().unwrap().remove("id");
+		}
+		beacon.mount("/individuals", ResponseTemplate::new(200).set_body_json(response));
+		let output = beacon.verify();
+		let single_entry = output.entities["Individual"]
+			.iter()
+			.find(|report| report.name == "Individual single entry" && report.category == Category::Model)
+			.unwrap();
+		assert_eq!(single_entry.outcome(), Outcome::Error);
+		assert_eq!(
+			single_entry.error.as_deref(),
+			Some("No ids were extracted from the main entity endpoint: No record has the id property 'id'")
+		);
+	}
 }
@@ -0,0 +1,257 @@
+//! Negative tests: the answers of the beacon to the queries it must reject
+//!
+//! The other checks only send valid queries. With `--negative`, every entity is
+//! also sent queries that are wrong on purpose: a request body whose filter is
+//! malformed, a pagination that is not a page (a negative `skip` and a `limit`
+//! that is not a number) and, on the single entry url, an id that no record
+//! has. The beacon must reject the malformed queries with 400 (Bad Request) and
+//! the unknown id with 404 (Not Found), and its error responses must follow
+//! the `beaconErrorResponse` of the framework (when the framework has one). Like
+//! the other error responses, they must not show the internals of the beacon
+//! nor echo its credentials.
+
+use jsonschema::JSONSchema;
+use reqwest::StatusCode;
+use serde_json::json;
+use url::Url;
+
+use crate::client::{Client, MethodPolicy};
+use crate::error::VerifierError;
+use crate::framework::ResponseSchemas;
+use crate::model::Entity;
+use crate::output::{Category, EndpointReport};
+use crate::{echoes, leaks, utils, Json};
+
+/// Name of the checks, after the name of the entity
+pub const CHECK_NAMES: [&str; 3] = ["malformed filter", "invalid pagination", "unknown id"];
+
+/// Id of the checks in the catalog
+const CHECK_IDS: [&str; 3] = [
+	"framework.negative.filter",
+	"framework.negative.pagination",
+	"framework.negative.unknown-id",
+];
+
+/// Id of no record, queried on the single entry url
+pub const UNKNOWN_ID: &str = "beacon-verifier-unknown-id";
+
+/// Request body of a boolean query whose filter has neither an id nor a valid operator
+pub fn malformed_filter() -> Json {
+	json!({
+		"meta": { "apiVersion": "v2.0" },
+		"query": {
+			"filters": [{ "operator": "~~", "value": 12 }],
+			"requestedGranularity": "boolean"
+		}
+	})
+}
+
+/// Response of the beacon to a negative test
+struct Answer {
+	status: StatusCode,
+	json: Option<Json>,
+}
+
+impl Answer {
+	/// The query was rejected with `expected`, with an error response following `schema`
+	fn rejected(&self, expected: StatusCode, schema: Option<&JSONSchema>) -> Result<(), VerifierError> {
+		if self.status.is_success() {
+			return Err(VerifierError::BadResponse(format!(
+				"The beacon answered the query (status {}) instead of rejecting it with {}",
+				self.status, expected
+			)));
+		}
+		if self.status != expected {
+			return Err(VerifierError::BadResponse(format!(
+				"The beacon rejected the query with the status {} instead of {}",
+				self.status, expected
+			)));
+		}
+		match (&self.json, schema) {
+			(None, _) => Err(VerifierError::BadResponse("The error response is not JSON".into())),
+			(Some(json), Some(schema)) => utils::check_schema_errors(utils::schema_errors(schema, json, "")),
+			(Some(_), None) => Ok(()),
+		}
+	}
+}
+
+/// Sends the malformed queries to `entity`, which must be rejected with error responses following the framework
+pub fn check(client: &Client, root_url: &Url, entity: &Entity, schemas: &ResponseSchemas) -> Vec<EndpointReport> {
+	let url = utils::url_join(root_url, &entity.url);
+	let report = |check: usize, url: &Url| {
+		EndpointReport::new(
+			&entity.name,
+			&format!("{} {}", entity.name, CHECK_NAMES[check]),
+			url.clone(),
+		)
+		.category(Category::Framework)
+		.check(CHECK_IDS[check])
+	};
+	let schema = schemas.error.as_deref();
+	let mut reports = Vec::new();
+
+	let filter = report(0, &url);
+	if client.policy_for(&entity.name) == MethodPolicy::Get {
+		reports.push(filter.skip(
+			"the method policy of the entity does not allow POST requests",
+			Json::Null,
+		));
+	}
+	else {
+		let body = serde_json::to_vec(&malformed_filter()).unwrap_or_default();
+		reports.push(run(client, filter, &url, StatusCode::BAD_REQUEST, schema, || {
+			client.post_raw(&url, "application/json", body.clone())
+		}));
+	}
+
+	let mut paginated = url.clone();
+	paginated
+		.query_pairs_mut()
+		.append_pair("skip", "-1")
+		.append_pair("limit", "abc");
+	reports.push(run(
+		client,
+		report(1, &paginated),
+		&paginated,
+		StatusCode::BAD_REQUEST,
+		schema,
+		|| get(client, &paginated),
+	));
+
+	if let Some(url_single) = &entity.url_single {
+		let template = utils::url_join(root_url, url_single);
+		let vars = utils::template_vars(&template);
+		let unknown = utils::replace_vars(&template, vars.iter().map(|var| (var.as_str(), UNKNOWN_ID)).collect());
+		reports.push(run(
+			client,
+			report(2, &unknown),
+			&unknown,
+			StatusCode::NOT_FOUND,
+			schema,
+			|| get(client, &unknown),
+		));
+	}
+	reports
+}
+
+/// Sends a GET request, returning the status code and the body of the response
+fn get(client: &Client, url: &Url) -> Result<(StatusCode, Vec<u8>), VerifierError> {
+	let (status, json) = client.get_error(url)?;
+	Ok((
+		status,
+		json.map(|json| serde_json::to_vec(&json).unwrap_or_default())
+			.unwrap_or_default(),
+	))
+}
+
+fn run(
+	client: &Client,
+	report: EndpointReport,
+	url: &Url,
+	expected: StatusCode,
+	schema: Option<&JSONSchema>,
+	send: impl Fn() -> Result<(StatusCode, Vec<u8>), VerifierError>,
+) -> EndpointReport {
+	log::info!("Validating {:?}", report.name);
+	let answer = send();
+	match answer {
+		Ok((status, body)) => {
+			let mut warnings = leaks::warnings(status, &body);
+			warnings.extend(echoes::error_response(status, &body, &client.credentials(), url));
+			let report = report.warnings(warnings);
+			let answer = Answer {
+				status,
+				json: serde_json::from_slice(&body).ok(),
+			};
+			match answer.rejected(expected, schema) {
+				Ok(()) => report.ok(None),
+				Err(e) => report.error(e),
+			}
+		},
+		Err(e) => report.null(e),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use serde_json::json;
+	use wiremock::{Request, ResponseTemplate};
+
+	use crate::negative::{CHECK_NAMES, UNKNOWN_ID};
+	use crate::output::{BeaconOutput, EndpointOutput};
+	use crate::testing::{Behavior, MockBeacon};
+
+	fn report<'a>(output: &'a BeaconOutput, entity: &str, name: &str) -> &'a EndpointOutput {
+		output.entities[entity]
+			.iter()
+			.find(|report| report.name == format!("{} {}", entity, name))
+			.unwrap()
+	}
+
+	fn error(code: u16) -> ResponseTemplate {
+		ResponseTemplate::new(code).set_body_json(json!({
+			"meta": { "beaconId": "org.example.beacon", "apiVersion": "v2.0.0" },
+			"error": { "errorCode": code, "errorMessage": "Bad request" },
+		}))
+	}
+
+	#[test]
+	fn test_negative() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let individuals = beacon.response("/individuals");
+		beacon.mount_method("POST", "/individuals", error(400));
+		// Rejects the negative skip, but ignores the limit it cannot parse
+		beacon.mount("/individuals", move |request: &Request| {
+			match request.url.query_pairs().find(|(key, _)| key == "skip") {
+				Some((_, skip)) if skip.starts_with('-') => error(400),
+				_ => ResponseTemplate::new(200).set_body_json(individuals.clone()),
+			}
+		});
+		beacon.mount(&format!("/individuals/{}", UNKNOWN_ID), error(404));
+		// Answers the unknown id with a bare error
+		beacon.mount(&format!("/biosamples/{}", UNKNOWN_ID), ResponseTemplate::new(404));
+		let output = beacon.beacon().negative().validate();
+
+		for name in CHECK_NAMES {
+			let individual = report(&output, "Individual", name);
+			assert_eq!(individual.valid, Some(true), "{}: {:?}", name, individual.error);
+		}
+		let unknown = report(&output, "Biosample", CHECK_NAMES[2]);
+		assert_eq!(
+			unknown.error.as_deref(),
+			Some("Response does not match the schema: The error response is not JSON")
+		);
+		let pagination = report(&output, "Biosample", CHECK_NAMES[1]);
+		let e = pagination.error.as_deref().unwrap();
+		assert!(
+			e.contains("answered the query (status 200 OK) instead of rejecting it with 400"),
+			"{}",
+			e
+		);
+		// The POST requests are not served
+		let filter = report(&output, "Biosample", CHECK_NAMES[0]);
+		let e = filter.error.as_deref().unwrap();
+		assert!(
+			e.contains("rejected the query with the status 404 Not Found instead of 400"),
+			"{}",
+			e
+		);
+
+		// An error response that does not follow the framework
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let body = json!({ "error": { "errorCode": "404" } });
+		beacon.mount(
+			&format!("/individuals/{}", UNKNOWN_ID),
+			ResponseTemplate::new(404).set_body_json(body),
+		);
+		let output = beacon.beacon().negative().validate();
+		let unknown = report(&output, "Individual", CHECK_NAMES[2]);
+		assert_eq!(unknown.valid, Some(false));
+		let e = unknown.error.as_deref().unwrap();
+		assert!(e.contains("meta") && e.contains("errorCode"), "{}", e);
+		assert!(!beacon.verify().entities["Individual"]
+			.iter()
+			.any(|report| report.name.ends_with(CHECK_NAMES[2])));
+	}
+}
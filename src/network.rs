@@ -0,0 +1,194 @@
+//! Support matrix in the format consumed by the Beacon network websites
+//!
+//! The members of a network can also be verified in one run (several urls, or
+//! the `network` of a target), whose report compares them side by side.
+
+use std::collections::BTreeMap;
+
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::client::HeadSupport;
+use crate::output::BeaconOutput;
+
+/// Entry of a beacon in the member pages of a Beacon network
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NetworkEntry {
+	pub name: String,
+	pub url: Url,
+	pub organization: Option<String>,
+	pub logo_url: Option<Url>,
+	pub last_updated: NaiveDateTime,
+	/// Whether every check of the entity passed, per entity
+	pub endpoints: BTreeMap<String, bool>,
+	/// Whether the endpoint of each entity supports HEAD requests, if probed
+	#[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+	pub head_support: BTreeMap<String, bool>,
+	/// What the verification did not cover
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub not_verified: Vec<String>,
+}
+
+impl From<&BeaconOutput> for NetworkEntry {
+	fn from(output: &BeaconOutput) -> Self {
+		Self {
+			name: output.name.clone(),
+			url: output.url.clone(),
+			organization: output
+				.organization
+				.as_ref()
+				.map(|organization| organization.name.clone()),
+			logo_url: output
+				.organization
+				.as_ref()
+				.and_then(|organization| organization.logo_url.clone()),
+			last_updated: output.last_updated,
+			endpoints: output
+				.entities
+				.keys()
+				.map(|entity_name| {
+					let outcomes = output.outcomes_for_endpoint(entity_name);
+					let supported = outcomes.iter().all(|(_, _, outcome)| outcome.is_success());
+					(entity_name.clone(), supported)
+				})
+				.collect(),
+			head_support: output
+				.head_support
+				.iter()
+				.map(|(entity_name, support)| (entity_name.clone(), *support == HeadSupport::Supported))
+				.collect(),
+			not_verified: output
+				.scope
+				.as_ref()
+				.map(|scope| scope.not_verified.clone())
+				.unwrap_or_default(),
+		}
+	}
+}
+
+/// Members of a Beacon network verified in one run, side by side
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NetworkReport {
+	/// Entries of the members, in the order of the columns of the matrix
+	pub members: Vec<NetworkEntry>,
+	/// Share of the checks each member passed
+	pub scores: Vec<f64>,
+	/// Whether each member passed every check of the entity, by entity (none if the member was not verified on it)
+	pub matrix: BTreeMap<String, Vec<Option<bool>>>,
+	pub reports: Vec<BeaconOutput>,
+}
+
+impl NetworkReport {
+	pub fn new(reports: Vec<BeaconOutput>) -> Self {
+		let members = reports.iter().map(NetworkEntry::from).collect::<Vec<_>>();
+		let mut matrix = BTreeMap::new();
+		for entity_name in members.iter().flat_map(|member| member.endpoints.keys()) {
+			matrix.entry(entity_name.clone()).or_insert_with(|| {
+				members
+					.iter()
+					.map(|member| member.endpoints.get(entity_name).copied())
+					.collect()
+			});
+		}
+		Self {
+			scores: reports.iter().map(BeaconOutput::score).collect(),
+			members,
+			matrix,
+			reports,
+		}
+	}
+
+	/// Logs the matrix, an entity per line
+	pub fn summary(&self) {
+		for (i, member) in self.members.iter().enumerate() {
+			log::info!(
+				"Member {}: {} ({}), {:.0}% passed",
+				i + 1,
+				member.name,
+				member.url,
+				self.scores[i] * 100.0
+			);
+		}
+		for (entity_name, supported) in &self.matrix {
+			let columns = supported
+				.iter()
+				.enumerate()
+				.map(|(i, supported)| {
+					let outcome = match supported {
+						Some(true) => "passed",
+						Some(false) => "failed",
+						None => "-",
+					};
+					format!("{} {}", i + 1, outcome)
+				})
+				.collect::<Vec<_>>();
+			log::info!("{}: {}", entity_name, columns.join(", "));
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use wiremock::ResponseTemplate;
+
+	use crate::client::HeadSupport;
+	use crate::network::{NetworkEntry, NetworkReport};
+	use crate::testing::{Behavior, MockBeacon};
+
+	#[test]
+	fn test_conformant_entry() {
+		let output = MockBeacon::start(Behavior::Conformant).verify();
+		let entry = NetworkEntry::from(&output);
+		assert_eq!(entry.name, "Mock Beacon");
+		assert_eq!(entry.organization.as_deref(), Some("Example Organization"));
+		assert_eq!(entry.logo_url.unwrap().as_str(), "https://example.org/logo.png");
+		assert!(entry.endpoints["Info"]);
+		assert!(entry.endpoints["Individual"]);
+	}
+
+	#[test]
+	fn test_unsupported_endpoints() {
+		let output = MockBeacon::start(Behavior::MissingEndpoints).verify();
+		let entry = NetworkEntry::from(&output);
+		assert!(entry.endpoints["Info"]);
+		assert!(!entry.endpoints["Configuration"]);
+		assert!(!entry.endpoints["Biosample"]);
+		let payload = serde_json::to_value(&entry).unwrap();
+		assert!(payload.get("logoUrl").is_some());
+		assert!(payload.get("notVerified").is_none());
+		assert!(payload.get("headSupport").is_none());
+	}
+
+	#[test]
+	fn test_head_support() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		beacon.mount_method("HEAD", "/individuals", ResponseTemplate::new(200));
+		beacon.mount_method("HEAD", "/biosamples", ResponseTemplate::new(405));
+		let output = beacon.beacon().head_support().validate();
+		assert_eq!(output.head_support["Individual"], HeadSupport::Supported);
+		assert_eq!(output.head_support["Biosample"], HeadSupport::Unsupported);
+		// Not found
+		assert_eq!(output.head_support["Dataset"], HeadSupport::Unsupported);
+		let entry = NetworkEntry::from(&output);
+		assert!(entry.head_support["Individual"] && !entry.head_support["Biosample"]);
+		assert!(beacon.verify().head_support.is_empty());
+	}
+
+	#[test]
+	fn test_network_report() {
+		let conformant = MockBeacon::start(Behavior::Conformant).verify();
+		let missing = MockBeacon::start(Behavior::MissingEndpoints).verify();
+		let report = NetworkReport::new(vec![conformant, missing]);
+		assert_eq!(report.members.len(), 2);
+		assert_eq!(report.matrix["Info"], vec![Some(true), Some(true)]);
+		assert_eq!(report.matrix["Biosample"], vec![Some(true), Some(false)]);
+		assert!(report.scores[0] > report.scores[1]);
+		let payload = serde_json::to_value(&report).unwrap();
+		assert_eq!(payload["reports"].as_array().unwrap().len(), 2);
+		assert_eq!(payload["members"][1]["endpoints"]["Configuration"], false);
+	}
+}
@@ -0,0 +1,237 @@
+//! Webhook notifications of the failed checks, routed by category
+//!
+//! Each webhook receives the failures of the categories it subscribes to (all of
+//! them by default), one request per category, so that e.g. the framework and the
+//! model failures can reach different channels. The failures can be narrowed down
+//! further by the prefixes of their check ids in the catalog (`security`,
+//! `framework.latency-budget`, ...), to route the security or the performance
+//! failures to their own channel. The payload is a JSON template
+//! where these placeholders are replaced:
+//!
+//! - `{beacon}`: name of the beacon
+//! - `{url}`: url of the beacon
+//! - `{category}`: category of the failed checks
+//! - `{count}`: number of failed checks
+//! - `{checks}`: failed checks, one per line (`Entity: check`)
+//!
+//! The webhooks are posted to through the proxy of the run, trusting its
+//! certificates.
+
+use serde::Deserialize;
+use url::Url;
+
+use crate::maintenance::Status;
+use crate::output::{BeaconOutput, Category};
+
+const DEFAULT_TEMPLATE: &str = r#"{"text": "{beacon} ({url}): {count} {category} checks failed\n{checks}"}"#;
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Webhook {
+	pub url: Url,
+	/// Categories of checks routed to this webhook, all of them if empty
+	#[serde(default)]
+	pub categories: Vec<Category>,
+	/// Prefixes of the ids of the checks routed to this webhook (e.g. `security`), all of them if empty
+	#[serde(default)]
+	pub checks: Vec<String>,
+	/// JSON payload with placeholders
+	pub template: Option<String>,
+}
+
+impl Webhook {
+	fn routes(&self, category: Category) -> bool {
+		self.categories.is_empty() || self.categories.contains(&category)
+	}
+
+	/// Whether the check with the id `check` is routed, the checks without an id only when no prefix is given
+	fn routes_check(&self, check: Option<&str>) -> bool {
+		self.checks.is_empty()
+			|| check.map_or(false, |check| {
+				self.checks.iter().any(|prefix| {
+					check
+						.strip_prefix(prefix.as_str())
+						.map_or(false, |rest| rest.is_empty() || rest.starts_with('.'))
+				})
+			})
+	}
+
+	fn payload(&self, output: &BeaconOutput, category: Category, checks: &[String]) -> String {
+		// The values are inserted in JSON strings, so they are escaped as such
+		let escape = |value: &str| {
+			let quoted = serde_json::to_string(value).unwrap_or_default();
+			quoted[1..quoted.len() - 1].to_string()
+		};
+		self.template
+			.as_deref()
+			.unwrap_or(DEFAULT_TEMPLATE)
+			.replace("{beacon}", &escape(&output.name))
+			.replace("{url}", &escape(output.url.as_str()))
+			.replace("{category}", &category.to_string())
+			.replace("{count}", &checks.len().to_string())
+			.replace("{checks}", &escape(&checks.join("\n")))
+	}
+}
+
+/// Posts the failures of each category to the webhooks routing it with `http` (the client of the run, for its proxy
+/// and certificates)
+pub fn notify(webhooks: &[Webhook], output: &BeaconOutput, http: &reqwest::blocking::Client) {
+	if output.status.map_or(false, |status| !status.alerts()) {
+		log::debug!(
+			"Not notifying the webhooks, the beacon is {}",
+			output.status.unwrap_or(Status::Up)
+		);
+		return;
+	}
+	for (category, failures) in output.failures_by_category() {
+		for webhook in webhooks.iter().filter(|webhook| webhook.routes(category)) {
+			let checks = failures
+				.iter()
+				.filter(|(_, report)| webhook.routes_check(report.check.as_deref()))
+				.map(|(entity_name, report)| format!("{}: {}", entity_name, report.name))
+				.collect::<Vec<_>>();
+			if checks.is_empty() {
+				continue;
+			}
+			log::debug!("Notifying {} of {} {} failures", webhook.url, checks.len(), category);
+			let result = http
+				.post(webhook.url.clone())
+				.header(reqwest::header::CONTENT_TYPE, "application/json")
+				.body(webhook.payload(output, category, &checks))
+				.send();
+			match result {
+				Ok(response) if response.status().is_success() => (),
+				Ok(response) => log::warn!("Webhook {} answered {}", webhook.url, response.status()),
+				Err(e) => log::warn!("Unable to notify {}: {}", webhook.url, e),
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use wiremock::ResponseTemplate;
+
+	use crate::client::Client;
+	use crate::maintenance::Status;
+	use crate::notify::{notify, Webhook};
+	use crate::output::Category;
+	use crate::testing::{Behavior, MockBeacon};
+
+	fn webhook(beacon: &MockBeacon, route: &str, categories: Vec<Category>, template: Option<&str>) -> Webhook {
+		beacon.mount_method("POST", route, ResponseTemplate::new(200));
+		Webhook {
+			url: beacon.url().join(route).unwrap(),
+			categories,
+			checks: Vec::new(),
+			template: template.map(String::from),
+		}
+	}
+
+	#[test]
+	fn test_routing() {
+		let beacon = MockBeacon::start(Behavior::BadMeta);
+		let mut output = beacon.verify();
+		// Only the framework checks fail
+		output
+			.entities
+			.values_mut()
+			.for_each(|reports| reports.retain(|report| report.category == Category::Framework));
+		let webhooks = vec![
+			webhook(
+				&beacon,
+				"/framework",
+				vec![Category::Framework],
+				Some(r#"{"failed": {count}}"#),
+			),
+			webhook(&beacon, "/model", vec![Category::Model], None),
+			webhook(&beacon, "/all", Vec::new(), None),
+		];
+		notify(&webhooks, &output, &Client::new().http());
+
+		let received = beacon.received_requests();
+		let framework = received
+			.iter()
+			.filter(|request| request.url.path() == "/framework")
+			.collect::<Vec<_>>();
+		assert_eq!(framework.len(), 1);
+		let payload: serde_json::Value = serde_json::from_slice(&framework[0].body).unwrap();
+		assert!(payload["failed"].as_u64().unwrap() > 0);
+		assert!(received.iter().all(|request| request.url.path() != "/model"));
+		let all = received.iter().find(|request| request.url.path() == "/all").unwrap();
+		let payload: serde_json::Value = serde_json::from_slice(&all.body).unwrap();
+		assert!(payload["text"].as_str().unwrap().contains("framework checks failed\n"));
+	}
+
+	#[test]
+	fn test_routing_by_check() {
+		let beacon = MockBeacon::start(Behavior::BadMeta);
+		let mut output = beacon.verify();
+		let failed = output
+			.failures_by_category()
+			.values()
+			.flatten()
+			.filter_map(|(_, report)| report.check.clone())
+			.collect::<Vec<_>>();
+		let check = failed[0].clone();
+		// A security failure among the framework ones
+		let security = output
+			.entities
+			.values_mut()
+			.flatten()
+			.find(|report| report.outcome().is_success())
+			.unwrap();
+		security.check = Some("security.error-responses".into());
+		security.valid = Some(false);
+		let mut by_check = webhook(&beacon, "/check", Vec::new(), Some(r#"{"failed": {count}}"#));
+		by_check.checks = vec![check.clone()];
+		let mut by_family = webhook(&beacon, "/security", Vec::new(), Some(r#"{"checks": "{checks}"}"#));
+		by_family.checks = vec!["security".into()];
+		let mut none = webhook(&beacon, "/none", Vec::new(), None);
+		none.checks = vec![format!("{}-other", check)];
+		notify(&[by_check, by_family, none], &output, &Client::new().http());
+
+		let received = beacon.received_requests();
+		let payload = |path: &str| {
+			let request = received.iter().find(|request| request.url.path() == path).unwrap();
+			serde_json::from_slice::<serde_json::Value>(&request.body).unwrap()
+		};
+		let expected = failed.iter().filter(|failed| **failed == check).count();
+		assert_eq!(payload("/check")["failed"].as_u64().unwrap() as usize, expected);
+		let checks = payload("/security")["checks"].as_str().unwrap().to_string();
+		assert_eq!(checks.lines().count(), 1, "{}", checks);
+		assert!(received.iter().all(|request| request.url.path() != "/none"));
+	}
+
+	#[test]
+	fn test_no_failures() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let output = beacon.verify();
+		notify(
+			&[webhook(&beacon, "/all", Vec::new(), None)],
+			&output,
+			&Client::new().http(),
+		);
+		assert!(beacon
+			.received_requests()
+			.iter()
+			.all(|request| request.url.path() != "/all"));
+	}
+
+	#[test]
+	fn test_maintenance() {
+		let beacon = MockBeacon::start(Behavior::BadMeta);
+		let mut output = beacon.verify();
+		output.status = Some(Status::Maintenance);
+		notify(
+			&[webhook(&beacon, "/all", Vec::new(), None)],
+			&output,
+			&Client::new().http(),
+		);
+		assert!(beacon
+			.received_requests()
+			.iter()
+			.all(|request| request.url.path() != "/all"));
+	}
+}
@@ -0,0 +1,145 @@
+//! Range checks of the statistical values of the records
+//!
+//! The schemas type the allele frequencies and the percentages as numbers
+//! without bounds, so a frequency of 17 (1700%) is valid against them. On top
+//! of the schema, the values of the statistical properties are checked against
+//! the range their name implies: the frequencies and proportions are between 0
+//! and 1, the percentages between 0 and 100. Their `NaN` and `Infinity`, which
+//! JSON cannot represent as numbers, must not be encoded as strings either.
+
+use crate::Json;
+
+/// Strings some serializers write for the numbers JSON cannot represent
+const NON_FINITE: [&str; 6] = ["NaN", "Infinity", "-Infinity", "inf", "-inf", "nan"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Scale {
+	/// Between 0 and 1 (e.g. `alleleFrequency`)
+	Ratio,
+	/// Between 0 and 100 (e.g. `percentage`)
+	Percentage,
+}
+
+impl Scale {
+	/// Scale of the values of `property`, if it is a statistical one
+	fn of(property: &str) -> Option<Self> {
+		let property = property.to_lowercase();
+		if property.contains("percent") {
+			Some(Self::Percentage)
+		}
+		else if property.ends_with("frequency") || property.ends_with("proportion") {
+			Some(Self::Ratio)
+		}
+		else {
+			None
+		}
+	}
+
+	fn max(self) -> f64 {
+		match self {
+			Self::Ratio => 1.0,
+			Self::Percentage => 100.0,
+		}
+	}
+
+	fn name(self) -> &'static str {
+		match self {
+			Self::Ratio => "frequency",
+			Self::Percentage => "percentage",
+		}
+	}
+}
+
+/// Checks the statistical values of a record, returning the path of each invalid one with the problem
+pub fn check_record(record: &Json) -> Vec<(String, String)> {
+	let mut problems = Vec::new();
+	walk(record, "", None, &mut problems);
+	problems
+}
+
+fn walk(json: &Json, path: &str, scale: Option<Scale>, problems: &mut Vec<(String, String)>) {
+	match json {
+		Json::Object(object) => {
+			for (property, value) in object {
+				walk(value, &format!("{}/{}", path, property), Scale::of(property), problems);
+			}
+		},
+		// Lists of frequencies keep the scale of their property
+		Json::Array(values) => values
+			.iter()
+			.for_each(|value| walk(value, &format!("{}/*", path), scale, problems)),
+		Json::Number(number) => {
+			if let (Some(scale), Some(value)) = (scale, number.as_f64()) {
+				if !(0.0..=scale.max()).contains(&value) {
+					log::debug!("The {} at {} is {}", scale.name(), path, value);
+					let message = format!("Expected a {} between 0 and {}", scale.name(), scale.max());
+					problems.push((path.to_string(), message));
+				}
+			}
+		},
+		Json::String(string) if scale.is_some() && NON_FINITE.contains(&string.as_str()) => {
+			problems.push((
+				path.to_string(),
+				format!("Non-finite number encoded as a string ({})", string),
+			));
+		},
+		_ => (),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use serde_json::json;
+	use wiremock::ResponseTemplate;
+
+	use crate::numeric;
+	use crate::testing::{Behavior, MockBeacon};
+
+	#[test]
+	fn test_ranges() {
+		let variant = json!({
+			"variantInternalId": "var1",
+			"frequencyInPopulations": [{
+				"source": "gnomAD",
+				"frequencies": [
+					{ "population": "NFE", "alleleFrequency": 0.0017 },
+					{ "population": "AFR", "alleleFrequency": 17 },
+					{ "population": "EAS", "alleleFrequency": "NaN" },
+				],
+			}],
+			"caseLevelData": [{ "percentage": 42.5, "depth": 1700 }],
+		});
+		let problems = numeric::check_record(&variant);
+		assert_eq!(problems.len(), 2, "{:?}", problems);
+		let path = "/frequencyInPopulations/*/frequencies/*/alleleFrequency";
+		assert_eq!(
+			problems[0],
+			(path.to_string(), "Expected a frequency between 0 and 1".to_string())
+		);
+		assert_eq!(problems[1].1, "Non-finite number encoded as a string (NaN)");
+
+		assert_eq!(numeric::check_record(&json!({ "cellularityPercentage": 170 })).len(), 1);
+		assert!(numeric::check_record(&json!({ "cellularityPercentage": 100 })).is_empty());
+		// Not a statistical property
+		assert!(numeric::check_record(&json!({ "notes": "NaN", "age": -1 })).is_empty());
+	}
+
+	#[test]
+	fn test_out_of_range_record() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let mut response = beacon.response("/individuals");
+		response["response"]["resultSets"][0]["results"][0]["alleleFrequency"] = json!(17);
+		beacon.mount("/individuals", ResponseTemplate::new(200).set_body_json(response));
+		let output = beacon.verify();
+		let all_entries = output.entities["Individual"]
+			.iter()
+			.find(|report| report.name == "Individual all entries" && report.error.is_some())
+			.unwrap();
+		assert!(all_entries
+			.error
+			.as_ref()
+			.unwrap()
+			.contains("Expected a frequency between 0 and 1"));
+	}
+}
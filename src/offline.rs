@@ -0,0 +1,213 @@
+//! Validation of responses saved to files
+//!
+//! `validate-response` checks a response without a beacon to query, so the
+//! implementers can iterate on their payloads before wiring up HTTP. The
+//! response of an entity goes through the same checks as in a live run (the
+//! envelope of its granularity, then its records against the model), the
+//! responses of the framework endpoints against their schemas, and any file
+//! against a JSON Schema given by path. The reports are the ones of a run.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Duration;
+
+use reqwest::Method;
+use url::Url;
+
+use crate::client::Response;
+use crate::error::VerifierError;
+use crate::formats::Formats;
+use crate::framework::Framework;
+use crate::model::{Entity, Model};
+use crate::output::{Category, EndpointOutput, EndpointReport, Output};
+use crate::resolver::SchemaStore;
+use crate::{shape, utils, Json};
+
+/// Responses of the framework endpoints, by the name given to `--schema`
+const FRAMEWORK_RESPONSES: [&str; 4] = ["info", "configuration", "map", "entry-types"];
+
+/// Schema a saved response is validated against
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SchemaTarget {
+	/// Response of a framework endpoint (e.g. `info`)
+	Framework(String),
+	/// Entity of the model, by name or entry type (e.g. `individual`)
+	Entity(String),
+	/// JSON Schema file
+	File(String),
+}
+
+impl SchemaTarget {
+	pub fn parse(schema: &str) -> Self {
+		if FRAMEWORK_RESPONSES.contains(&schema) {
+			Self::Framework(schema.to_string())
+		}
+		else if schema.ends_with(".json") || Path::new(schema).is_file() {
+			Self::File(schema.to_string())
+		}
+		else {
+			Self::Entity(schema.to_string())
+		}
+	}
+}
+
+fn find_entity<'a>(model: &'a Model, name: &str) -> Option<&'a Entity> {
+	model
+		.entities
+		.iter()
+		.find(|entity| entity.name.eq_ignore_ascii_case(name) || entity.entry_type.eq_ignore_ascii_case(name))
+}
+
+/// Reports of the checks of `json`, saved at `source`, against `target`
+pub fn validate(
+	json: Json,
+	source: &Url,
+	target: &SchemaTarget,
+	framework: &Framework,
+	model: Option<&Model>,
+	formats: &Formats,
+) -> Result<BTreeMap<String, Vec<EndpointOutput>>, VerifierError> {
+	let reports = match target {
+		SchemaTarget::Framework(name) => {
+			let (schema, entity_name, check) = match name.as_str() {
+				"info" => (&framework.info_json, "Info", "framework.info"),
+				"configuration" => (
+					&framework.configuration_json,
+					"Configuration",
+					"framework.configuration",
+				),
+				"map" => (&framework.beacon_map_json, "BeaconMap", "framework.map"),
+				_ => (&framework.entry_types_json, "EntryTypes", "framework.entry-types"),
+			};
+			let report = EndpointReport::new(entity_name, entity_name, source.clone())
+				.category(Category::Framework)
+				.check(check);
+			let schema = utils::compile_schema(schema, formats, framework.store())?;
+			vec![
+				match shape::check(&json).and_then(|()| utils::valid_schema(&schema, &json)) {
+					Ok(output) => report.ok(Some(output)),
+					Err(e) => report.error(e),
+				},
+			]
+		},
+		SchemaTarget::File(path) => {
+			let schema = serde_json::from_str::<Json>(&std::fs::read_to_string(path)?)?;
+			let report = EndpointReport::new("Schema", path, source.clone()).category(Category::Model);
+			let schema = utils::compile_schema(&schema, formats, &SchemaStore::default())?;
+			vec![match utils::valid_schema(&schema, &json) {
+				Ok(output) => report.ok(Some(output)),
+				Err(e) => report.error(e),
+			}]
+		},
+		SchemaTarget::Entity(name) => {
+			let model = model.ok_or_else(|| VerifierError::NotVerified("no model to validate the entities".into()))?;
+			let entity = find_entity(model, name).ok_or_else(|| {
+				VerifierError::NoMatchingSchema(format!(
+					"no entity '{}' in the model (entities: {:?}, or info, configuration, map, entry-types or the \
+					 path to a JSON Schema)",
+					name,
+					model.entities.iter().map(|entity| &entity.name).collect::<Vec<_>>()
+				))
+			})?;
+			let endpoint = Model::build_endpoint(
+				entity.name.clone(),
+				entity.entry_type.clone(),
+				entity.schemas.clone(),
+				format!("{} all entries", entity.name),
+				&entity.url,
+				None,
+			);
+			let response = Response {
+				json,
+				method: Method::GET,
+				fallback: false,
+				timings: None,
+				warnings: Vec::new(),
+				elapsed: Duration::ZERO,
+				http_version: reqwest::Version::HTTP_11,
+			};
+			let schemas = framework.response_schemas(formats)?;
+			endpoint
+				.validate(response, source, &schemas, formats, None)
+				.into_iter()
+				.map(|report| report.url(source.clone()))
+				.collect()
+		},
+	};
+	let mut output = Output::new();
+	reports.into_iter().for_each(|report| output.push(report));
+	Ok(output.finish())
+}
+
+#[cfg(test)]
+mod tests {
+
+	use std::io::Write;
+
+	use serde_json::json;
+	use url::Url;
+
+	use crate::formats::Formats;
+	use crate::offline::{self, SchemaTarget};
+	use crate::testing::{self, Behavior, MockBeacon};
+
+	#[test]
+	fn test_schema_target() {
+		assert_eq!(SchemaTarget::parse("info"), SchemaTarget::Framework("info".into()));
+		assert_eq!(
+			SchemaTarget::parse("individual"),
+			SchemaTarget::Entity("individual".into())
+		);
+		assert_eq!(
+			SchemaTarget::parse("schemas/sample.json"),
+			SchemaTarget::File("schemas/sample.json".into())
+		);
+	}
+
+	#[test]
+	fn test_validate_response() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let (framework, model, formats) = (testing::framework(), testing::model(), Formats::default());
+		let source = Url::parse("file:///tmp/individuals.json").unwrap();
+		let validate = |json, target: &str| {
+			offline::validate(
+				json,
+				&source,
+				&SchemaTarget::parse(target),
+				&framework,
+				Some(&model),
+				&formats,
+			)
+		};
+
+		let reports = validate(beacon.response("/individuals"), "individual").unwrap();
+		let reports = &reports["Individual"];
+		// Envelope and records, as in a live run
+		assert_eq!(reports.len(), 2);
+		assert!(reports
+			.iter()
+			.all(|report| report.valid == Some(true) && report.url == source));
+
+		let mut response = beacon.response("/individuals");
+		response["response"]["resultSets"][0]["results"][0]["sex"] = json!("female");
+		let reports = validate(response, "Individual").unwrap();
+		let error = reports["Individual"]
+			.iter()
+			.find_map(|report| report.error.clone())
+			.unwrap();
+		assert!(error.contains("/response/resultSets/*/results/*/sex"), "{}", error);
+
+		let reports = validate(beacon.response("/info"), "info").unwrap();
+		assert_eq!(reports["Info"][0].valid, Some(true));
+		assert!(validate(beacon.response("/info"), "cohort").is_err());
+
+		let mut schema = tempfile::NamedTempFile::new().unwrap();
+		write!(schema, "{}", json!({ "type": "object", "required": ["meta"] })).unwrap();
+		let path = schema.path().to_str().unwrap();
+		assert_eq!(
+			validate(json!({ "meta": {} }), path).unwrap()["Schema"][0].valid,
+			Some(true)
+		);
+		assert_eq!(validate(json!({}), path).unwrap()["Schema"][0].valid, Some(false));
+	}
+}
@@ -0,0 +1,342 @@
+//! Resolution of the ontology terms with the Ontology Lookup Service
+//!
+//! The schemas only check that the id of an ontology term is a CURIE, so a
+//! beacon can use terms that do not exist, that were made obsolete, or with
+//! the label of another term. With `--resolve-ontologies`, the ontology terms
+//! of the first records of each entity (the objects with a CURIE `id` and a
+//! `label`) and the ontology terms the beacon advertises for its entry type in
+//! its filtering terms are looked up in the OLS API of EBI. The unknown and
+//! obsolete terms, and the labels that are not the label of their term (case
+//! insensitive), are reported as warnings. The terms are looked up once per
+//! run, at most 5 per second, through the proxy of the run, and kept between the
+//! runs in the file of `--ontology-cache`.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::client::Client;
+use crate::error::VerifierError;
+use crate::model::Entity;
+use crate::output::{Category, EndpointReport};
+use crate::{artifacts, filters, formats, utils, Json};
+
+/// Name of the check, after the name of the entity
+pub const CHECK_NAME: &str = "ontology terms";
+
+/// API of the Ontology Lookup Service
+pub const OLS_API: &str = "https://www.ebi.ac.uk/ols4/api/";
+
+/// Time between two requests to the API
+const INTERVAL: Duration = Duration::from_millis(200);
+
+/// Terms looked up at most for each entity
+const MAX_TERMS: usize = 50;
+
+/// Term as the API knows it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Term {
+	/// Labels of the term in the ontologies defining or importing it
+	pub labels: Vec<String>,
+	pub obsolete: bool,
+}
+
+/// Looks the terms up in the API, each one once
+pub struct OntologyResolver {
+	http: reqwest::blocking::Client,
+	api: Url,
+	cache_file: Option<PathBuf>,
+	/// Terms looked up, `None` for the unknown ones
+	terms: Mutex<BTreeMap<String, Option<Term>>>,
+	last_request: Mutex<Option<Instant>>,
+	interval: Duration,
+}
+
+impl Default for OntologyResolver {
+	fn default() -> Self {
+		Self {
+			http: reqwest::blocking::Client::new(),
+			api: Url::parse(OLS_API).expect("the url of the API is valid"),
+			cache_file: None,
+			terms: Mutex::new(BTreeMap::new()),
+			last_request: Mutex::new(None),
+			interval: INTERVAL,
+		}
+	}
+}
+
+impl OntologyResolver {
+	/// Looks the terms up with `http` (e.g. the one of the client of the run, for its proxy and certificates)
+	pub fn http(mut self, http: reqwest::blocking::Client) -> Self {
+		self.http = http;
+		self
+	}
+
+	/// API of another instance of OLS (e.g. a mirror)
+	pub fn api(mut self, api: Url) -> Self {
+		self.api = api;
+		self
+	}
+
+	/// Keeps the terms looked up in this file, reading the ones of the previous runs from it
+	pub fn cache_file(mut self, path: PathBuf) -> Self {
+		match std::fs::read(&path).map(|bytes| serde_json::from_slice(&bytes)) {
+			Ok(Ok(terms)) => self.terms = Mutex::new(terms),
+			Ok(Err(e)) => log::warn!("Ignoring the ontology cache {}: {}", path.display(), e),
+			// Created at the end of the run
+			Err(_) => (),
+		}
+		self.cache_file = Some(path);
+		self
+	}
+
+	/// Time between two requests to the API
+	pub fn interval(mut self, interval: Duration) -> Self {
+		self.interval = interval;
+		self
+	}
+
+	/// The term `curie`, `None` if the API does not know it
+	pub fn resolve(&self, curie: &str) -> Result<Option<Term>, VerifierError> {
+		if let Some(term) = self.terms.lock().unwrap_or_else(|e| e.into_inner()).get(curie) {
+			return Ok(term.clone());
+		}
+		self.wait();
+		let mut url = self
+			.api
+			.join("terms")
+			.map_err(|e| VerifierError::Internal(e.to_string()))?;
+		url.query_pairs_mut().append_pair("obo_id", curie);
+		log::debug!("Looking up {} at {}", curie, url);
+		let response = self.http.get(url.clone()).send()?;
+		let term = match response.status() {
+			reqwest::StatusCode::NOT_FOUND => None,
+			status if status.is_success() => Self::parse(&response.json::<Json>()?),
+			status => {
+				log::warn!("{} answered {}", url, status);
+				return Err(VerifierError::UnresponsiveEndpoint(url));
+			},
+		};
+		self.terms
+			.lock()
+			.unwrap_or_else(|e| e.into_inner())
+			.insert(curie.to_string(), term.clone());
+		Ok(term)
+	}
+
+	/// Term of a response of the API, `None` if it has no term
+	fn parse(response: &Json) -> Option<Term> {
+		let terms = response.pointer("/_embedded/terms").and_then(Json::as_array)?;
+		if terms.is_empty() {
+			return None;
+		}
+		Some(Term {
+			labels: terms
+				.iter()
+				.filter_map(|term| term.get("label").and_then(Json::as_str))
+				.map(String::from)
+				.collect::<BTreeSet<_>>()
+				.into_iter()
+				.collect(),
+			obsolete: terms
+				.iter()
+				.all(|term| term.get("is_obsolete").and_then(Json::as_bool).unwrap_or(false)),
+		})
+	}
+
+	/// Waits for the interval since the previous request
+	fn wait(&self) {
+		let mut last_request = self.last_request.lock().unwrap_or_else(|e| e.into_inner());
+		if let Some(elapsed) = last_request.map(|last_request| last_request.elapsed()) {
+			if elapsed < self.interval {
+				std::thread::sleep(self.interval - elapsed);
+			}
+		}
+		*last_request = Some(Instant::now());
+	}
+
+	/// Writes the terms looked up to the cache file, if any
+	pub fn save(&self) {
+		if let Some(path) = &self.cache_file {
+			let terms = self.terms.lock().unwrap_or_else(|e| e.into_inner());
+			let written = serde_json::to_vec_pretty(&*terms)
+				.map_err(VerifierError::from)
+				.and_then(|bytes| artifacts::write_atomic(path, &bytes));
+			if let Err(e) = written {
+				log::warn!("Unable to write the ontology cache {}: {}", path.display(), e);
+			}
+		}
+	}
+}
+
+/// Ontology terms of `json` (objects with a CURIE id and a label), with their labels
+fn collect(json: &Json, terms: &mut BTreeMap<String, BTreeSet<String>>) {
+	match json {
+		Json::Object(object) => {
+			let id = object
+				.get("id")
+				.and_then(Json::as_str)
+				.filter(|id| formats::is_curie(id));
+			if let (Some(id), Some(label)) = (id, object.get("label").and_then(Json::as_str)) {
+				terms.entry(id.to_string()).or_default().insert(label.to_string());
+			}
+			object.values().for_each(|value| collect(value, terms));
+		},
+		Json::Array(values) => values.iter().for_each(|value| collect(value, terms)),
+		_ => (),
+	}
+}
+
+/// Looks up the ontology terms of the first records of `entity` and of its filtering terms
+pub fn check(client: &Client, root_url: &Url, entity: &Entity, resolver: &OntologyResolver) -> EndpointReport {
+	let url = utils::url_join(root_url, &entity.url);
+	let report = EndpointReport::new(&entity.name, &format!("{} {}", entity.name, CHECK_NAME), url.clone())
+		.category(Category::Model)
+		.check("quality.ontology-terms");
+	let records = match client
+		.query(&entity.name, &url)
+		.and_then(|response| utils::records(&response.json))
+	{
+		Ok(records) => records,
+		Err(e) => return report.null(e),
+	};
+	let mut terms = BTreeMap::new();
+	records.iter().for_each(|record| collect(record, &mut terms));
+	// The filtering terms are optional
+	if let Ok(response) = client.query(&entity.name, &filters::terms_url(root_url, entity)) {
+		for term in filters::ontology_terms(&response.json, &entity.entry_type) {
+			let id = term.get("id").and_then(Json::as_str).unwrap_or_default();
+			let labels = terms.entry(id.to_string()).or_default();
+			labels.extend(term.get("label").and_then(Json::as_str).map(String::from));
+		}
+	}
+	if terms.is_empty() {
+		return report.skip(
+			"no ontology terms in the records nor in the filtering terms",
+			Json::Null,
+		);
+	}
+
+	let mut warnings = Vec::new();
+	let mut notes = Vec::new();
+	if terms.len() > MAX_TERMS {
+		notes.push(format!(
+			"only {} of the {} ontology terms were looked up",
+			MAX_TERMS,
+			terms.len()
+		));
+	}
+	let mut resolved = 0;
+	let mut last_error = None;
+	for (id, labels) in terms.iter().take(MAX_TERMS) {
+		match resolver.resolve(id) {
+			Ok(None) => warnings.push(format!("{} is not a known ontology term", id)),
+			Ok(Some(term)) => {
+				if term.obsolete {
+					warnings.push(format!("{} is an obsolete ontology term", id));
+				}
+				for label in labels {
+					if !term.labels.iter().any(|known| known.eq_ignore_ascii_case(label)) {
+						let known = term.labels.join("', '");
+						warnings.push(format!("'{}' is not the label of {} ('{}')", label, id, known));
+					}
+				}
+			},
+			Err(e) => {
+				log::warn!("Unable to look up {}: {}", id, e);
+				last_error = Some(e);
+				continue;
+			},
+		}
+		resolved += 1;
+	}
+	match last_error {
+		Some(e) if resolved == 0 => report.null(e),
+		Some(_) => {
+			notes.push("some ontology terms could not be looked up".into());
+			report.warnings(warnings).notes(notes).ok(None)
+		},
+		None => report.warnings(warnings).notes(notes).ok(None),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use std::time::Duration;
+
+	use serde_json::json;
+	use wiremock::matchers::{method, path, query_param};
+	use wiremock::{Mock, ResponseTemplate};
+
+	use crate::ontologies::{OntologyResolver, CHECK_NAME};
+	use crate::output::{BeaconOutput, EndpointOutput};
+	use crate::testing::{Behavior, MockBeacon};
+
+	fn terms<'a>(output: &'a BeaconOutput, entity: &str) -> &'a EndpointOutput {
+		output.entities[entity]
+			.iter()
+			.find(|report| report.name == format!("{} {}", entity, CHECK_NAME))
+			.unwrap()
+	}
+
+	/// Mocks the API, knowing the sex of the individuals under another label and an obsolete sample status
+	fn ols(beacon: &MockBeacon) {
+		let term = |label: &str, obsolete: bool| {
+			ResponseTemplate::new(200).set_body_json(json!({
+				"_embedded": { "terms": [{ "label": label, "is_obsolete": obsolete }] },
+			}))
+		};
+		for (id, response) in [
+			("NCIT:C16576", term("Woman", false)),
+			("EFO:0009654", term("reference sample", true)),
+		] {
+			beacon.mount_mock(
+				Mock::given(method("GET"))
+					.and(path("/ols/terms"))
+					.and(query_param("obo_id", id))
+					.respond_with(response),
+			);
+		}
+		beacon.mount_mock(
+			Mock::given(method("GET"))
+				.and(path("/ols/terms"))
+				.respond_with(ResponseTemplate::new(200).set_body_json(json!({ "page": { "totalElements": 0 } }))),
+		);
+	}
+
+	#[test]
+	fn test_ontology_terms() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		ols(&beacon);
+		let dir = tempfile::tempdir().unwrap();
+		let cache = dir.path().join("ontologies.json");
+		let resolver = || {
+			OntologyResolver::default()
+				.api(beacon.url().join("/ols/").unwrap())
+				.cache_file(cache.clone())
+				.interval(Duration::ZERO)
+		};
+		let output = beacon.beacon().resolve_ontologies(resolver()).validate();
+		let individual = terms(&output, "Individual");
+		assert_eq!(individual.valid, Some(true));
+		assert_eq!(
+			individual.warnings,
+			vec!["'female' is not the label of NCIT:C16576 ('Woman')"]
+		);
+		let biosample = terms(&output, "Biosample");
+		assert_eq!(biosample.warnings, vec!["EFO:0009654 is an obsolete ontology term"]);
+		assert!(terms(&output, "Dataset").skipped);
+
+		// The next runs read the terms from the cache
+		let resolver = resolver();
+		let lookups = beacon.received_requests().len();
+		assert!(resolver.resolve("NCIT:C16576").unwrap().is_some());
+		assert_eq!(beacon.received_requests().len(), lookups);
+		assert_eq!(resolver.resolve("NCIT:C99999").unwrap(), None);
+	}
+}
@@ -1,85 +1,744 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Duration;
 
 use chrono::NaiveDateTime;
+use reqwest::Method;
 use serde::{Deserialize, Serialize};
 use url::Url;
 
+use crate::auth::TokenRefresh;
+use crate::catalog::{self, Severity};
+use crate::client::{HeadSupport, MethodPolicy};
+use crate::datause::{self, DataUse};
 use crate::error::VerifierError;
-use crate::Json;
+use crate::humanize::TimeStyle;
+use crate::incremental::Incremental;
+use crate::interface::Organization;
+use crate::latency::{self, Latency};
+use crate::limits::DeclaredLimits;
+use crate::maintenance::Status;
+use crate::policy::PolicyVerdict;
+use crate::protocol::{HttpVersion, Protocol, TlsVersion};
+use crate::scope::{Access, Scope};
+use crate::spec::SpecVersion;
+use crate::timing::Timings;
+use crate::usage::ResourceUsage;
+use crate::{anonymize, canonical, Json};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BeaconOutput {
 	pub name: String,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub organization: Option<Organization>,
 	pub url: Url,
 	pub last_updated: NaiveDateTime,
 	pub entities: BTreeMap<String, Vec<EndpointOutput>>,
+	/// Appendix with the data observed per entry type (of the records, whichever entity asked for them)
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub observed: Option<BTreeMap<String, ObservedData>>,
+	/// Effective configuration that produced this output
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub configuration: Option<RunConfiguration>,
+	/// Refreshes of the access token during the run
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub token_refreshes: Vec<TokenRefresh>,
+	/// Data version of the beacon (with `--incremental`)
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub incremental: Option<Incremental>,
+	/// What was and was not verified (with the configuration of the run)
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub scope: Option<Scope>,
+	/// Status of the beacon, taking the maintenance windows into account
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub status: Option<Status>,
+	/// Verdict of the run against the policy of a network (with `--policy`)
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub policy: Option<PolicyVerdict>,
+	/// Limits declared by the beacon (with `--limits`)
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub limits: Option<DeclaredLimits>,
+	/// Whether the run was interrupted (SIGINT or SIGTERM), leaving the endpoints not reached as not verified
+	#[serde(default, skip_serializing_if = "std::ops::Not::not")]
+	pub interrupted: bool,
+	/// Versions of the protocols of each host (`host:port`) of the beacon
+	#[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+	pub protocols: BTreeMap<String, Protocol>,
+	/// Data use conditions declared by each dataset, by id (not in the public reports)
+	#[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+	pub data_use: BTreeMap<String, DataUse>,
+	/// Whether the endpoint with all the entries of each entity supports HEAD requests, with `--head`
+	#[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+	pub head_support: BTreeMap<String, HeadSupport>,
+	/// Resources used by the verifier during the run
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub resource_usage: Option<ResourceUsage>,
+	/// Why the beacon could not be verified at all (e.g. `/info` could not be read), none if it was
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub error: Option<String>,
 }
 
+/// Report of a run, stable across the releases: fields are only added, with defaults
 impl BeaconOutput {
-	pub fn summary(&self) {
-		self.entities.iter().for_each(|(entity_name, output)| {
-			if output.iter().all(|report| report.valid == Some(true)) {
-				log::info!("{} \u{2713}", entity_name);
+	pub fn to_json(&self) -> Result<String, VerifierError> {
+		Ok(serde_json::to_string_pretty(self)?)
+	}
+
+	/// Diff-friendly report: sorted keys, reports in a stable order and rounded floats
+	pub fn to_canonical_json(&self) -> Result<String, VerifierError> {
+		canonical::to_json(&self.sorted())
+	}
+
+	/// Copy of the output with the reports of each entity in a stable order
+	pub fn sorted(&self) -> Self {
+		let mut output = self.clone();
+		for reports in output.entities.values_mut() {
+			// Reports of the same check are told apart by their url, or else their whole content
+			reports.sort_by_cached_key(|report| {
+				(
+					report.name.clone(),
+					report.category,
+					report.url.to_string(),
+					serde_json::to_string(report).unwrap_or_default(),
+				)
+			});
+		}
+		output
+	}
+
+	/// Reads a report written by `to_json` (or printed by the verifier)
+	pub fn from_json(json: &str) -> Result<Self, VerifierError> {
+		Ok(serde_json::from_str(json)?)
+	}
+
+	/// Checks that did not succeed, per category, with the entity they belong to
+	pub fn failures_by_category(&self) -> BTreeMap<Category, Vec<(&str, &EndpointOutput)>> {
+		let mut failures = BTreeMap::<_, Vec<_>>::new();
+		for (entity_name, reports) in &self.entities {
+			for report in reports.iter().filter(|report| !report.outcome().is_success()) {
+				failures
+					.entry(report.category)
+					.or_default()
+					.push((entity_name.as_str(), report));
+			}
+		}
+		failures
+	}
+
+	/// Most serious of the failures, warnings and notes of the checks (none if there are none)
+	pub fn severity(&self) -> Option<Severity> {
+		self.entities
+			.values()
+			.flatten()
+			.filter_map(|report| report.severity)
+			.max()
+	}
+
+	/// Outcome of each check of an entity (none if the entity was not verified)
+	pub fn outcomes_for_endpoint(&self, entity_name: &str) -> Vec<(&str, Category, Outcome)> {
+		self.entities
+			.get(entity_name)
+			.map(|reports| {
+				reports
+					.iter()
+					.map(|report| (report.name.as_str(), report.category, report.outcome()))
+					.collect()
+			})
+			.unwrap_or_default()
+	}
+
+	/// Share of the checks that passed, between 0 and 1 (the skipped checks do not count)
+	pub fn score(&self) -> f64 {
+		let outcomes = self
+			.entities
+			.values()
+			.flatten()
+			.map(EndpointOutput::outcome)
+			.filter(|outcome| *outcome != Outcome::Skipped)
+			.collect::<Vec<_>>();
+		if outcomes.is_empty() {
+			return 1.0;
+		}
+		let passed = outcomes.iter().filter(|outcome| **outcome == Outcome::Passed).count();
+		passed as f64 / outcomes.len() as f64
+	}
+
+	pub fn summary(&self, time_style: TimeStyle) {
+		log::info!("{} verified on {}", self.name, time_style.timestamp(self.last_updated));
+		for refresh in &self.token_refreshes {
+			match &refresh.error {
+				None => log::info!(
+					"Access token refreshed at {} ({})",
+					time_style.timestamp(refresh.at),
+					refresh.endpoint
+				),
+				Some(error) => log::error!(
+					"Access token refresh failed at {} ({}): {}",
+					time_style.timestamp(refresh.at),
+					refresh.endpoint,
+					error
+				),
+			}
+		}
+		for (id, data_use) in &self.data_use {
+			log::info!(
+				"Dataset {}: data use conditions {:?}, consent codes {:?}",
+				anonymize::id(id),
+				data_use.conditions,
+				data_use.consent_codes
+			);
+		}
+		if let Some(observed) = &self.observed {
+			for (entry_type, data) in observed {
+				log::info!(
+					"{}: {} records, datasets {:?}, assemblies {:?}, filters {:?}",
+					entry_type,
+					data.records,
+					data.datasets,
+					data.assemblies,
+					data.filters
+				);
 			}
-			else {
-				log::error!("{} \u{2717}", entity_name);
-				for error in output.iter().filter_map(|report| report.error.clone()) {
-					log::error!("\t{}", error.trim());
+		}
+		self.entities.iter().for_each(|(entity_name, output)| {
+			for category in Category::ALL {
+				let reports = output
+					.iter()
+					.filter(|report| report.category == category)
+					.collect::<Vec<_>>();
+				if reports.is_empty() {
+					continue;
+				}
+				if reports.iter().all(|report| report.outcome().is_success()) {
+					log::info!("{} ({}) \u{2713}", entity_name, category);
+				}
+				else {
+					log::error!("{} ({}) \u{2717}", entity_name, category);
+					for report in reports.iter().filter(|report| !report.skipped) {
+						if let Some(error) = &report.error {
+							// The errors quote the responses, and the ids with them
+							let public = report.public_error.as_deref().unwrap_or("Failed");
+							log::error!("\t{}", anonymize::excerpt(error.trim(), public));
+						}
+					}
+				}
+				for report in reports.iter().filter(|report| report.flaky) {
+					log::warn!("{} ({}) {} is flaky", entity_name, category, report.name);
+				}
+				for report in reports {
+					for warning in &report.warnings {
+						log::warn!("{} ({}) {}: {}", entity_name, category, report.name, warning);
+					}
+					for note in &report.notes {
+						log::info!("{} ({}) {}: {}", entity_name, category, report.name, note);
+					}
 				}
 			}
 		});
+		latency::summary(self, time_style);
+		log::info!("Score: {:.1}%", self.score() * 100.0);
+		if let Some(scope) = &self.scope {
+			scope.summary();
+		}
+		if let Some(limits) = &self.limits {
+			limits.summary();
+		}
+		if let Some(policy) = &self.policy {
+			policy.summary();
+		}
+		if let Some(resource_usage) = &self.resource_usage {
+			resource_usage.summary(time_style);
+		}
+		if self.interrupted {
+			log::warn!("The run was interrupted, the results are partial");
+		}
+		if self.status == Some(Status::Maintenance) {
+			log::info!("Maintenance window: the failures do not alert");
+		}
+	}
+}
+
+/// Number of checks of one or more runs per outcome and per severity
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Totals {
+	pub outcomes: BTreeMap<Outcome, usize>,
+	pub severities: BTreeMap<Severity, usize>,
+}
+
+impl Totals {
+	pub fn add(&mut self, output: &BeaconOutput) {
+		for report in output.entities.values().flatten() {
+			*self.outcomes.entry(report.outcome()).or_default() += 1;
+			if let Some(severity) = report.severity {
+				*self.severities.entry(severity).or_default() += 1;
+			}
+		}
+	}
+}
+
+impl std::fmt::Display for Totals {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let count = |outcome| self.outcomes.get(&outcome).copied().unwrap_or_default();
+		let severity = |severity| self.severities.get(&severity).copied().unwrap_or_default();
+		write!(
+			f,
+			"{} checks: {} passed, {} failed, {} in error, {} skipped; {} at error, {} at warning, {} at info",
+			self.outcomes.values().sum::<usize>(),
+			count(Outcome::Passed),
+			count(Outcome::Failed),
+			count(Outcome::Error),
+			count(Outcome::Skipped),
+			severity(Severity::Error),
+			severity(Severity::Warning),
+			severity(Severity::Info)
+		)
+	}
+}
+
+/// Effective configuration of a run, enough to reproduce its results
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunConfiguration {
+	/// Version of the verifier
+	pub version: String,
+	/// Target of the configuration file
+	pub target: Option<String>,
+	pub url: Url,
+	pub framework: SpecSource,
+	pub model: Option<SpecSource>,
+	/// Version of the specification the beacon was verified against, given or declared by the beacon
+	#[serde(default)]
+	pub spec_version: Option<SpecVersion>,
+	/// Entities of the model that were verified
+	pub entities: Vec<String>,
+	pub only: Option<Category>,
+	pub method_policy: MethodPolicy,
+	pub endpoint_method_policies: BTreeMap<String, MethodPolicy>,
+	#[serde(default)]
+	pub access: Access,
+	pub disabled_formats: Vec<String>,
+	pub observed_data: bool,
+	pub public_report: bool,
+	pub timings: bool,
+	pub incremental: bool,
+	pub history: bool,
+	/// Retries of the flaky checks whose endpoint did not respond
+	pub retries: usize,
+	/// Time budget of the run, in seconds
+	#[serde(default)]
+	pub time_budget_secs: Option<u64>,
+	#[serde(default)]
+	pub robustness: bool,
+	#[serde(default)]
+	pub pagination: bool,
+	#[serde(default)]
+	pub limits: bool,
+	#[serde(default)]
+	pub adaptive: bool,
+	#[serde(default)]
+	pub filters: bool,
+	#[serde(default)]
+	pub post_queries: bool,
+	#[serde(default)]
+	pub negative: bool,
+	#[serde(default)]
+	pub references: bool,
+	#[serde(default)]
+	pub resolve_ontologies: bool,
+	#[serde(default)]
+	pub variant_queries: bool,
+	#[serde(default)]
+	pub max_instances: Option<usize>,
+	#[serde(default)]
+	pub sample_seed: Option<u64>,
+	#[serde(default)]
+	pub head: bool,
+	#[serde(default)]
+	pub discover: bool,
+	#[serde(default)]
+	pub min_tls_version: Option<TlsVersion>,
+	#[serde(default)]
+	pub min_http_version: Option<HttpVersion>,
+	/// Threshold of the slow responses, in milliseconds
+	#[serde(default)]
+	pub slow_ms: Option<u64>,
+	/// Timeout of the requests, in milliseconds
+	#[serde(default)]
+	pub timeout_ms: Option<u64>,
+	/// Retries of the requests failing transiently (5xx, 429 or a connection reset)
+	#[serde(default)]
+	pub request_retries: u32,
+	/// Wait before the first of these retries, in milliseconds
+	#[serde(default)]
+	pub request_backoff_ms: Option<u64>,
+	/// Name of the renderer of the document written to stdout
+	pub output_format: String,
+}
+
+/// Where a specification was loaded from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpecSource {
+	pub location: Url,
+	/// Commit of the repository, if the specification lives in one
+	pub revision: Option<String>,
+}
+
+/// Layer of the specification a check belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Category {
+	/// Response envelopes (`meta`, `responseSummary`, ...) defined by the Beacon Framework
+	Framework,
+	/// Entity documents defined by the Beacon Model
+	Model,
+}
+
+impl Category {
+	pub const ALL: [Self; 2] = [Self::Framework, Self::Model];
+}
+
+impl Default for Category {
+	fn default() -> Self {
+		Self::Framework
+	}
+}
+
+impl std::fmt::Display for Category {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Framework => write!(f, "framework"),
+			Self::Model => write!(f, "model"),
+		}
+	}
+}
+
+/// Outcome of a check
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+	Passed,
+	Failed,
+	/// The check could not run (e.g. the endpoint did not respond)
+	Error,
+	/// There was no data to verify
+	Skipped,
+}
+
+impl Outcome {
+	pub const ALL: [Self; 4] = [Self::Passed, Self::Failed, Self::Error, Self::Skipped];
+
+	/// Whether the check passed or had nothing to verify
+	pub fn is_success(self) -> bool {
+		matches!(self, Self::Passed | Self::Skipped)
+	}
+}
+
+impl std::fmt::Display for Outcome {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Passed => write!(f, "passed"),
+			Self::Failed => write!(f, "failed"),
+			Self::Error => write!(f, "error"),
+			Self::Skipped => write!(f, "skipped"),
+		}
+	}
+}
+
+/// Census of the data returned by the endpoints of an entity
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ObservedData {
+	/// Number of records sampled
+	pub records: usize,
+	pub datasets: BTreeSet<String>,
+	pub assemblies: BTreeSet<String>,
+	/// Filtering terms the beacon reports to have applied
+	pub filters: BTreeSet<String>,
+	pub num_datasets: usize,
+}
+
+impl ObservedData {
+	pub fn from_response(response_json: &Json) -> Self {
+		let mut observed = Self::default();
+
+		let result_sets = response_json
+			.pointer("/response/resultSets")
+			.and_then(Json::as_array)
+			.map_or(&[][..], Vec::as_slice);
+		for result_set in result_sets {
+			if result_set.get("setType").and_then(Json::as_str).unwrap_or("dataset") == "dataset" {
+				if let Some(id) = result_set.get("id").and_then(Json::as_str) {
+					observed.datasets.insert(id.to_string());
+				}
+			}
+			let results = result_set.get("results").and_then(Json::as_array);
+			for record in results.map_or(&[][..], Vec::as_slice) {
+				observed.records += 1;
+				Self::find_assemblies(record, &mut observed.assemblies);
+			}
+		}
+
+		let collections = response_json
+			.pointer("/response/collections")
+			.and_then(Json::as_array)
+			.map_or(&[][..], Vec::as_slice);
+		for collection in collections {
+			observed.records += 1;
+			if let Some(id) = collection.get("id").and_then(Json::as_str) {
+				observed.datasets.insert(id.to_string());
+			}
+		}
+
+		observed.num_datasets = observed.datasets.len();
+
+		if let Some(summary) = response_json.pointer("/meta/receivedRequestSummary") {
+			Self::find_assemblies(summary, &mut observed.assemblies);
+			let filters = summary.get("filters").and_then(Json::as_array);
+			for filter in filters.map_or(&[][..], Vec::as_slice) {
+				// Filters are either plain ids or objects with an id
+				let id = filter.as_str().or_else(|| filter.get("id").and_then(Json::as_str));
+				if let Some(id) = id {
+					observed.filters.insert(id.to_string());
+				}
+			}
+		}
+
+		observed
+	}
+
+	fn find_assemblies(json: &Json, assemblies: &mut BTreeSet<String>) {
+		match json {
+			Json::Object(map) => {
+				for (key, value) in map {
+					match value.as_str() {
+						Some(assembly) if key == "assemblyId" => {
+							assemblies.insert(assembly.to_string());
+						},
+						_ => Self::find_assemblies(value, assemblies),
+					}
+				}
+			},
+			Json::Array(values) => values.iter().for_each(|value| Self::find_assemblies(value, assemblies)),
+			_ => (),
+		}
+	}
+
+	pub fn merge(&mut self, other: Self) {
+		self.records += other.records;
+		self.datasets.extend(other.datasets);
+		self.assemblies.extend(other.assemblies);
+		self.filters.extend(other.filters);
+		self.num_datasets = self.datasets.len();
+	}
+
+	/// Keeps only the aggregate numbers
+	pub fn redact(&mut self) {
+		self.num_datasets = self.datasets.len();
+		self.datasets.clear();
 	}
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EndpointOutput {
 	pub name: String,
+	/// Record the endpoint was queried with (single entry and related endpoints), several records of an entity
+	/// being checked with the same check (`--max-instances`)
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub record: Option<String>,
 	pub url: Url,
+	pub category: Category,
+	/// HTTP method that finally worked
+	pub method: Option<String>,
+	/// Whether GET failed and POST was needed
+	pub method_fallback: bool,
 	pub valid: Option<bool>,
 	pub error: Option<String>,
+	/// Error without the excerpts of the response, for the logs that anonymize the ids (not serialized)
+	#[serde(skip)]
+	pub public_error: Option<String>,
+	/// Whether the checks were skipped because there was no data to verify
+	#[serde(default)]
+	pub skipped: bool,
+	/// Response that justifies skipping the checks
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub evidence: Option<Json>,
+	/// Hash of the response without its `meta`, changes whenever the data does
+	#[serde(default)]
+	pub content_hash: Option<String>,
+	/// Timing breakdown of the request (with `--timings`)
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub timings: Option<Timings>,
+	/// Data-quality (charset, mojibake) and security warnings, that do not make the check fail
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub warnings: Vec<String>,
+	/// Informational findings (e.g. deprecated properties), that neither fail the check nor warn
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub notes: Vec<String>,
+	/// Id of the check in the catalog
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub check: Option<String>,
+	/// Severity of the failure of the check, or else of its warnings or of its notes (none if there are none)
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub severity: Option<Severity>,
+	/// Whether the check alternated between success and failure in the recent runs (with `--history`)
+	#[serde(default, skip_serializing_if = "std::ops::Not::not")]
+	pub flaky: bool,
+	/// Time to get the response, in milliseconds
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub elapsed_ms: Option<u64>,
+	/// Whether the response was fast, slow or did not arrive in time
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub latency: Option<Latency>,
+	/// Time spent on the check, its requests and the validation of the responses, in milliseconds
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub duration_ms: Option<u64>,
+}
+
+/// Key of the check `name` queried with the record `record`, telling apart the reports of the sampled records
+pub fn check_key(name: &str, record: Option<&str>) -> String {
+	match record {
+		Some(record) => format!("{} [{}]", name, record),
+		None => name.to_string(),
+	}
+}
+
+impl EndpointOutput {
+	/// Key of the check in the history, the flaky checks and the diffs (its name, with its record if any)
+	pub fn key(&self) -> String {
+		check_key(&self.name, self.record.as_deref())
+	}
+
+	pub fn outcome(&self) -> Outcome {
+		match self.valid {
+			_ if self.skipped => Outcome::Skipped,
+			Some(true) => Outcome::Passed,
+			Some(false) => Outcome::Failed,
+			None => Outcome::Error,
+		}
+	}
 }
 
 pub struct Output {
 	results: BTreeMap<String, Vec<EndpointOutput>>,
+	/// Severity of each check of the catalog, by id
+	severities: BTreeMap<String, Severity>,
+	/// Census of the data returned, by entry type
+	observed: BTreeMap<String, ObservedData>,
+	data_use: BTreeMap<String, DataUse>,
+	public: bool,
+	slow: Duration,
+}
+
+impl Default for Output {
+	fn default() -> Self {
+		Self::new()
+	}
 }
 
 impl Output {
 	pub fn new() -> Self {
 		Self {
 			results: BTreeMap::new(),
+			severities: catalog::severities(),
+			observed: BTreeMap::new(),
+			data_use: BTreeMap::new(),
+			public: false,
+			slow: latency::DEFAULT_SLOW,
 		}
 	}
 
-	pub fn push(&mut self, report: EndpointReport) {
-		match self.results.get_mut(&report.entity_name) {
-			Some(endpoints) => {
-				endpoints.push(EndpointOutput {
-					name: report.name,
-					url: report.url.unwrap(),
-					valid: report.valid,
-					error: report.error.map(|e| e.to_string()),
-				});
-			},
-			None => {
-				self.results.insert(
-					report.entity_name,
-					vec![EndpointOutput {
-						name: report.name,
-						url: report.url.unwrap(),
-						valid: report.valid,
-						error: report.error.map(|e| e.to_string()),
-					}],
-				);
-			},
+	/// Threshold of the slow responses
+	pub fn slow(mut self, slow: Duration) -> Self {
+		self.slow = slow;
+		self
+	}
+
+	/// Output that can be published: no record-level data, ids or response excerpts
+	pub fn public() -> Self {
+		Self {
+			public: true,
+			..Self::new()
 		}
 	}
 
+	pub fn push(&mut self, mut report: EndpointReport) {
+		if self.public {
+			report = report.redact();
+		}
+
+		// Keyed by the entry type of the records (e.g. the biosamples related with an individual are biosamples)
+		if let Some((entry_type, observed)) = report.observed {
+			self.observed.entry(entry_type).or_default().merge(observed);
+		}
+		let mut data_use = std::mem::take(&mut report.data_use);
+		report.warnings.extend(datause::warning(&data_use, !self.public));
+		if !self.public {
+			self.data_use.append(&mut data_use);
+		}
+
+		let timed_out = report.error.as_ref().map_or(false, VerifierError::is_timeout);
+		let severity = if report.valid != Some(true) && !report.skipped {
+			let catalog = report
+				.check
+				.as_ref()
+				.and_then(|check| self.severities.get(check).copied());
+			Some(report.severity.or(catalog).unwrap_or(Severity::Error))
+		}
+		else if !report.warnings.is_empty() {
+			Some(Severity::Warning)
+		}
+		else if !report.notes.is_empty() {
+			Some(Severity::Info)
+		}
+		else {
+			None
+		};
+		self.results
+			.entry(report.entity_name)
+			.or_default()
+			.push(EndpointOutput {
+				name: report.name,
+				record: report.record,
+				url: report.url.unwrap(),
+				category: report.category,
+				method: report.method.map(|method| method.to_string()),
+				method_fallback: report.method_fallback,
+				valid: report.valid,
+				public_error: report.error.as_ref().map(VerifierError::public_message),
+				error: report.error.map(|e| e.to_string()),
+				skipped: report.skipped,
+				evidence: report.evidence,
+				content_hash: report.content_hash,
+				timings: report.timings,
+				warnings: report.warnings,
+				notes: report.notes,
+				check: report.check,
+				severity,
+				flaky: false,
+				elapsed_ms: report.elapsed.map(|elapsed| elapsed.as_millis() as u64),
+				latency: Latency::classify(report.elapsed, timed_out, self.slow),
+				duration_ms: report
+					.duration
+					.or(report.elapsed)
+					.map(|duration| duration.as_millis() as u64),
+			});
+	}
+
+	pub fn data_use(&self) -> BTreeMap<String, DataUse> {
+		self.data_use.clone()
+	}
+
+	pub fn observed(&self) -> BTreeMap<String, ObservedData> {
+		let mut observed = self.observed.clone();
+		if self.public {
+			observed.values_mut().for_each(ObservedData::redact);
+		}
+		observed
+	}
+
 	pub fn finish(self) -> BTreeMap<String, Vec<EndpointOutput>> {
 		log::debug!("Compiling results...");
 		self.results
 			.into_iter()
 			.map(|(entity_name, mut output)| {
-				output.sort_by_key(|k| k.name.clone());
+				output.sort_by_key(|k| (k.name.clone(), k.category));
 				(entity_name, output)
 			})
 			.collect()
@@ -89,11 +748,36 @@ impl Output {
 #[derive(Default)]
 pub struct EndpointReport {
 	pub entity_name: String,
+	pub category: Category,
 	pub valid: Option<bool>,
 	pub error: Option<VerifierError>,
 	pub output: Option<Json>,
 	pub url: Option<Url>,
+	/// Url of the endpoint before replacing the ids
+	pub template_url: Option<Url>,
 	pub name: String,
+	/// Record the endpoint was queried with
+	pub record: Option<String>,
+	/// Census of the records returned, with their entry type
+	pub observed: Option<(String, ObservedData)>,
+	/// Data use conditions of the datasets of the response, by id
+	pub data_use: BTreeMap<String, DataUse>,
+	pub method: Option<Method>,
+	pub method_fallback: bool,
+	pub skipped: bool,
+	pub evidence: Option<Json>,
+	pub content_hash: Option<String>,
+	pub timings: Option<Timings>,
+	pub warnings: Vec<String>,
+	pub notes: Vec<String>,
+	/// Id of the check in the catalog
+	pub check: Option<String>,
+	/// Severity of a failure of the check, the one of the catalog if none
+	pub severity: Option<Severity>,
+	/// Time to get the response
+	pub elapsed: Option<Duration>,
+	/// Time spent on the whole check, the time to get the response if none
+	pub duration: Option<Duration>,
 }
 
 impl EndpointReport {
@@ -124,15 +808,244 @@ impl EndpointReport {
 		self
 	}
 
+	/// Checks not run because of `reason`, with the response that justifies it
+	pub fn skip(mut self, reason: &str, evidence: Json) -> Self {
+		self.valid = None;
+		self.error = Some(VerifierError::Skipped(reason.into()));
+		self.skipped = true;
+		self.evidence = Some(evidence);
+		self
+	}
+
+	pub fn method(mut self, method: Method, fallback: bool) -> Self {
+		self.method = Some(method);
+		self.method_fallback = fallback;
+		self
+	}
+
+	pub fn content_hash(mut self, content_hash: String) -> Self {
+		self.content_hash = Some(content_hash);
+		self
+	}
+
+	pub fn timings(mut self, timings: Option<Timings>) -> Self {
+		self.timings = timings;
+		self
+	}
+
+	pub fn elapsed(mut self, elapsed: Duration) -> Self {
+		self.elapsed = Some(elapsed);
+		self
+	}
+
+	pub fn duration(mut self, duration: Duration) -> Self {
+		self.duration = Some(duration);
+		self
+	}
+
+	/// Data-quality and security warnings, that do not make the check fail
+	pub fn warnings(mut self, warnings: Vec<String>) -> Self {
+		self.warnings = warnings;
+		self
+	}
+
+	/// Informational findings, that neither fail the check nor warn
+	pub fn notes(mut self, notes: Vec<String>) -> Self {
+		self.notes = notes;
+		self
+	}
+
+	/// Id of the check in the catalog, which gives the severity of its failures
+	pub fn check(mut self, check: &str) -> Self {
+		self.check = Some(check.into());
+		self
+	}
+
+	/// Severity of a failure of the check, instead of the one of the catalog
+	pub fn severity(mut self, severity: Severity) -> Self {
+		self.severity = Some(severity);
+		self
+	}
+
+	pub fn template_url(mut self, url: Url) -> Self {
+		self.template_url = Some(url);
+		self
+	}
+
+	/// Drops the response and the evidence, the ids in the url and the excerpts of the error
+	pub fn redact(mut self) -> Self {
+		self.output = None;
+		self.evidence = None;
+		if let Some(mut url) = self.template_url.take().or_else(|| self.url.clone()) {
+			url.set_query(None);
+			self.url = Some(url);
+		}
+		self.error = self.error.map(|e| VerifierError::Redacted(e.public_message()));
+		// Still telling apart the records, with the hash the logs anonymize them with
+		self.record = self.record.map(|record| anonymize::display_id(&record));
+		self
+	}
+
+	/// Record the endpoint was queried with
+	pub fn record(mut self, record: Option<&str>) -> Self {
+		self.record = record.map(String::from);
+		self
+	}
+
+	/// Census of the records of the entry type `entry_type` the endpoint returned
+	pub fn observed(mut self, entry_type: &str, observed: ObservedData) -> Self {
+		self.observed = Some((entry_type.to_string(), observed));
+		self
+	}
+
+	/// Data use conditions of the datasets, the ones without any being reported in the warnings
+	pub fn data_use(mut self, data_use: BTreeMap<String, DataUse>) -> Self {
+		self.data_use = data_use;
+		self
+	}
+
+	pub fn category(mut self, category: Category) -> Self {
+		self.category = category;
+		self
+	}
+
 	pub fn url(mut self, url: Url) -> Self {
 		self.url = Some(url);
 		self
 	}
+}
 
-	pub fn join(self, report2: Self) -> Self {
-		if self.valid == Some(true) && (report2.valid.is_none() || !report2.valid.unwrap()) {
-			return report2;
-		};
-		self
+#[cfg(test)]
+mod tests {
+
+	use serde_json::json;
+
+	use crate::catalog::Severity;
+	use crate::output::{BeaconOutput, Category, ObservedData, Outcome, Totals};
+	use crate::testing::{Behavior, MockBeacon};
+
+	#[test]
+	fn test_observed_data_from_response() {
+		let response = json!({
+			"meta": {
+				"receivedRequestSummary": {
+					"filters": ["NCIT:C20197", { "id": "NCIT:C16576" }],
+					"requestParameters": { "assemblyId": "GRCh38" },
+				},
+			},
+			"response": {
+				"resultSets": [
+					{ "id": "ds1", "setType": "dataset", "results": [{ "id": "v1" }, { "id": "v2" }] },
+					{ "id": "ds2", "results": [{ "id": "v3", "location": { "assemblyId": "GRCh37" } }] },
+				],
+			},
+		});
+		let observed = ObservedData::from_response(&response);
+		assert_eq!(observed.records, 3);
+		assert_eq!(observed.datasets.len(), 2);
+		assert!(observed.assemblies.contains("GRCh37") && observed.assemblies.contains("GRCh38"));
+		assert!(observed.filters.contains("NCIT:C20197") && observed.filters.contains("NCIT:C16576"));
+	}
+
+	#[test]
+	fn test_report_api() {
+		let conformant = MockBeacon::start(Behavior::Conformant).verify();
+		assert!(conformant.failures_by_category().is_empty());
+		assert!((conformant.score() - 1.0).abs() < f64::EPSILON);
+
+		let output = MockBeacon::start(Behavior::MissingEndpoints).verify();
+		let failures = output.failures_by_category();
+		assert!(failures[&Category::Framework]
+			.iter()
+			.any(|(entity_name, _)| *entity_name == "Configuration"));
+		assert!(output.score() < 1.0);
+		let outcomes = output.outcomes_for_endpoint("Configuration");
+		assert_eq!(outcomes.len(), 1);
+		assert_eq!(outcomes[0].2, Outcome::Error);
+		assert!(output.outcomes_for_endpoint("Unknown").is_empty());
+
+		let json = output.to_json().unwrap();
+		let read = BeaconOutput::from_json(&json).unwrap();
+		assert_eq!(read.to_json().unwrap(), json);
+	}
+
+	#[test]
+	fn test_severities() {
+		// The datasets of the mock beacon declare no data use conditions
+		let output = MockBeacon::start(Behavior::Conformant).verify();
+		assert!(output
+			.entities
+			.values()
+			.flatten()
+			.all(|report| report.outcome().is_success()));
+		assert_eq!(output.severity(), Some(Severity::Warning));
+		let dataset = output.entities["Dataset"]
+			.iter()
+			.find(|report| report.category == Category::Model)
+			.unwrap();
+		assert_eq!(dataset.check.as_deref(), Some("model.entity"));
+		assert_eq!(dataset.severity, Some(Severity::Warning));
+
+		// Only /info is required, the other endpoints that are not found are warnings
+		let output = MockBeacon::start(Behavior::MissingEndpoints).verify();
+		let configuration = &output.entities["Configuration"][0];
+		assert_eq!(configuration.check.as_deref(), Some("framework.configuration"));
+		assert_eq!(configuration.outcome(), Outcome::Error);
+		assert_eq!(configuration.severity, Some(Severity::Warning));
+		assert!(output.entities["Biosample"]
+			.iter()
+			.filter(|report| !report.outcome().is_success())
+			.all(|report| report.severity == Some(Severity::Warning)));
+		assert_eq!(output.severity(), Some(Severity::Warning));
+
+		let output = MockBeacon::start(Behavior::BadMeta).verify();
+		let report = output.entities["Individual"]
+			.iter()
+			.find(|report| report.category == Category::Framework && report.valid == Some(false))
+			.unwrap();
+		assert_eq!(report.check.as_deref(), Some("framework.entity-response"));
+		assert_eq!(report.severity, Some(Severity::Error));
+		assert_eq!(output.severity(), Some(Severity::Error));
+	}
+
+	#[test]
+	fn test_totals() {
+		let mut totals = Totals::default();
+		totals.add(&MockBeacon::start(Behavior::MissingEndpoints).verify());
+		// The endpoints that are not found, and the datasets without data use conditions
+		assert_eq!(
+			totals.to_string(),
+			"18 checks: 13 passed, 0 failed, 5 in error, 0 skipped; 0 at error, 7 at warning, 0 at info"
+		);
+		totals.add(&MockBeacon::start(Behavior::BadMeta).verify());
+		assert!(totals.outcomes[&Outcome::Failed] > 0);
+		let failures = totals.outcomes[&Outcome::Failed] + totals.outcomes[&Outcome::Error];
+		assert_eq!(totals.severities[&Severity::Error], failures - 5);
+	}
+
+	#[test]
+	fn test_report_format() {
+		// Reports of previous releases must still be readable
+		let report = BeaconOutput::from_json(
+			r#"{
+				"name": "Old Beacon",
+				"url": "https://beacon.example.org/api",
+				"last_updated": "2022-03-01T12:00:00",
+				"entities": {
+					"Info": [{
+						"name": "Info",
+						"url": "https://beacon.example.org/api/info",
+						"category": "framework",
+						"method": "GET",
+						"method_fallback": false,
+						"valid": false,
+						"error": "Bad info"
+					}]
+				}
+			}"#,
+		)
+		.unwrap();
+		assert_eq!(report.outcomes_for_endpoint("Info")[0].2, Outcome::Failed);
+		assert_eq!(report.score(), 0.0);
 	}
 }
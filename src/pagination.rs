@@ -0,0 +1,475 @@
+//! Pagination of the result sets
+//!
+//! The records of the entities answered with result sets are paged with
+//! `skip` and `limit`, in the query of a GET request or in the `pagination` of
+//! the body of a POST one. With `--pagination`, the endpoint with all the
+//! entries of these entities is paged one record at a time: each page must
+//! hold at most one record, no record may come back on a later page, the
+//! `resultsCount` of the result sets must not change from a page to the next
+//! and, once the last page is read, must add up to the records of all the
+//! pages. Only the first pages are read: when the records do not fit in them,
+//! the total is noted as not verified. With `--post-queries` too, the same
+//! pages are POSTed.
+//!
+//! Some beacons also return, in the `meta` of their responses, a token of the
+//! snapshot of the data the results come from. Sent back with the next pages,
+//! it guarantees that the pages do not change under the client while the
+//! data is updated. The verifier reads the first page of each entity, and if it
+//! has a token, queries the second page and the first one again with it: the
+//! first page must not change and the two pages must not overlap.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use reqwest::Method;
+use url::Url;
+
+use crate::client::Client;
+use crate::error::VerifierError;
+use crate::model::Entity;
+use crate::output::{Category, EndpointReport};
+use crate::{post, utils, Json};
+
+/// Name of the checks, after the name of the entity, with GET and with POST
+pub const CHECK_NAMES: [&str; 2] = ["pagination", "POST pagination"];
+
+/// Id of the checks in the catalog
+const CHECK_IDS: [&str; 2] = ["framework.pagination", "framework.post-pagination"];
+
+/// Name of the snapshot checks, after the name of the entity
+pub const SNAPSHOT_CHECK_NAME: &str = "snapshot pagination";
+
+/// Properties of `meta` holding the token, which is sent back in the query parameter of the same name
+const TOKEN_PROPERTIES: [&str; 2] = ["snapshotToken", "consistencyToken"];
+
+/// Records per page, small enough for the few records of a test beacon to span several pages
+const PAGE_SIZE: usize = 1;
+
+/// Pages read at most, the records beyond them not being counted
+const MAX_PAGES: usize = 10;
+
+struct Page {
+	json: Json,
+	ids: Vec<String>,
+	/// Sum of the `resultsCount` of the result sets, if they all have one
+	results_count: Option<u64>,
+}
+
+fn page(
+	client: &Client,
+	entity: &Entity,
+	url: &Url,
+	method: &Method,
+	skip: usize,
+	token: Option<(&str, &str)>,
+) -> Result<Page, VerifierError> {
+	let json = if *method == Method::POST {
+		let mut body = post::body();
+		body["query"]["pagination"] = serde_json::json!({ "skip": skip, "limit": PAGE_SIZE });
+		client.post_query(&entity.name, url, &body)?.json
+	}
+	else {
+		let mut url = url.clone();
+		url.query_pairs_mut()
+			.append_pair("skip", &skip.to_string())
+			.append_pair("limit", &PAGE_SIZE.to_string());
+		if let Some((property, token)) = token {
+			url.query_pairs_mut().append_pair(property, token);
+		}
+		client.query(&entity.name, &url)?.json
+	};
+	let results_count = json
+		.pointer("/response/resultSets")
+		.and_then(Json::as_array)
+		.and_then(|result_sets| {
+			result_sets
+				.iter()
+				.map(|result_set| result_set.get("resultsCount").and_then(Json::as_u64))
+				.sum::<Option<u64>>()
+		});
+	let ids = utils::records(&json)?
+		.iter()
+		.filter_map(|record| utils::record_id(record, &entity.id_field))
+		.collect();
+	Ok(Page {
+		json,
+		ids,
+		results_count,
+	})
+}
+
+/// Pages the endpoint with all the entries of `entity` with `method` (GET or POST)
+pub fn check(client: &Client, root_url: &Url, entity: &Entity, method: Method) -> EndpointReport {
+	let url = utils::url_join(root_url, &entity.url);
+	let check = if method == Method::POST { 1 } else { 0 };
+	let report = EndpointReport::new(
+		&entity.name,
+		&format!("{} {}", entity.name, CHECK_NAMES[check]),
+		url.clone(),
+	)
+	.category(Category::Framework)
+	.check(CHECK_IDS[check]);
+	match paginate(client, entity, &url, &method) {
+		Ok(None) => report.skip("no records to paginate", Json::Null),
+		Ok(Some(pages)) => {
+			let report = if pages.truncated {
+				report.notes(vec![format!(
+					"Not verified: the records were not added up to the resultsCount, which exceeds the {} pages read",
+					MAX_PAGES
+				)])
+			}
+			else {
+				report
+			};
+			if pages.problems.is_empty() {
+				report.ok(None)
+			}
+			else {
+				report.error(VerifierError::BadResponse(pages.problems.join("\n")))
+			}
+		},
+		Err(VerifierError::MethodNotAllowed(_)) => report.skip("POST not allowed", Json::Null),
+		Err(e) => {
+			log::error!("{}", e);
+			report.null(e)
+		},
+	}
+}
+
+/// Pages of records read
+struct Pages {
+	problems: Vec<String>,
+	/// Whether the records beyond the pages read were not counted
+	truncated: bool,
+}
+
+/// Problems of the pages of records, `None` if there are no records
+fn paginate(client: &Client, entity: &Entity, url: &Url, method: &Method) -> Result<Option<Pages>, VerifierError> {
+	let mut problems = Vec::new();
+	// Page where each record was returned first, by id
+	let mut seen = BTreeMap::<String, usize>::new();
+	let mut results_count = None;
+	let mut last_page_read = false;
+	// Whether the pages ran out before a short one
+	let mut pages_left = true;
+	for skip in (0..MAX_PAGES).map(|page| page * PAGE_SIZE) {
+		let page = page(client, entity, url, method, skip, None)?;
+		if skip == 0 && page.ids.is_empty() {
+			return Ok(None);
+		}
+		let name = format!("the page at skip {}", skip);
+		if page.ids.len() > PAGE_SIZE {
+			problems.push(format!(
+				"{} has {} records with limit {}",
+				name,
+				page.ids.len(),
+				PAGE_SIZE
+			));
+		}
+		let repeated = page
+			.ids
+			.iter()
+			.filter_map(|id| seen.get(id).map(|first| format!("'{}' (first at skip {})", id, first)))
+			.collect::<Vec<_>>();
+		if !repeated.is_empty() {
+			problems.push(format!("{} returned again {}", name, repeated.join(", ")));
+		}
+		match (results_count, page.results_count) {
+			(None, count) => results_count = count,
+			(Some(first), Some(count)) if count != first => {
+				problems.push(format!("{} has resultsCount {} instead of {}", name, count, first));
+			},
+			_ => (),
+		}
+		for id in &page.ids {
+			seen.entry(id.clone()).or_insert(skip);
+		}
+		// A short page is the last one, unless the limit was ignored
+		if page.ids.len() < PAGE_SIZE || !repeated.is_empty() {
+			last_page_read = page.ids.len() < PAGE_SIZE;
+			pages_left = false;
+			break;
+		}
+	}
+	let records = seen.len() as u64;
+	if let Some(results_count) = results_count {
+		if (last_page_read && records != results_count) || records > results_count {
+			problems.push(format!(
+				"the pages returned {} records with resultsCount {}",
+				records, results_count
+			));
+		}
+	}
+	let truncated = pages_left && results_count.map_or(false, |results_count| records < results_count);
+	Ok(Some(Pages { problems, truncated }))
+}
+
+fn token(json: &Json) -> Option<(&'static str, String)> {
+	TOKEN_PROPERTIES.iter().find_map(|property| {
+		let token = json.get("meta")?.get(*property)?;
+		let token = token.as_str().map_or_else(|| token.to_string(), String::from);
+		Some((*property, token))
+	})
+}
+
+fn ids(ids: &BTreeSet<String>) -> String {
+	ids.iter().map(|id| format!("'{}'", id)).collect::<Vec<_>>().join(", ")
+}
+
+/// Replays the pages of the endpoint with all the entries of `entity` with its snapshot token
+pub fn check_snapshot(client: &Client, root_url: &Url, entity: &Entity) -> EndpointReport {
+	let url = utils::url_join(root_url, &entity.url);
+	let report = EndpointReport::new(
+		&entity.name,
+		&format!("{} {}", entity.name, SNAPSHOT_CHECK_NAME),
+		url.clone(),
+	)
+	.category(Category::Framework)
+	.check("framework.snapshot-pagination");
+	let replayed = replay(client, entity, &url);
+	match replayed {
+		Ok(Replay::NoToken(meta)) => report.skip("no snapshot token in the meta of the response", meta),
+		Ok(Replay::NoRecords(response)) => report.skip("no records to paginate", response),
+		Ok(Replay::Consistent) => report.ok(None),
+		Ok(Replay::Inconsistent(problems)) => report.error(VerifierError::BadResponse(problems.join("\n"))),
+		Err(e) => {
+			log::error!("{}", e);
+			report.null(e)
+		},
+	}
+}
+
+enum Replay {
+	NoToken(Json),
+	NoRecords(Json),
+	Consistent,
+	Inconsistent(Vec<String>),
+}
+
+fn replay(client: &Client, entity: &Entity, url: &Url) -> Result<Replay, VerifierError> {
+	let first = page(client, entity, url, &Method::GET, 0, None)?;
+	let (property, token) = match token(&first.json) {
+		Some(token) => token,
+		None => return Ok(Replay::NoToken(first.json.get("meta").cloned().unwrap_or(Json::Null))),
+	};
+	let first_ids = first.ids.iter().cloned().collect::<BTreeSet<_>>();
+	if first_ids.is_empty() {
+		return Ok(Replay::NoRecords(first.json));
+	}
+	let token = Some((property, token.as_str()));
+	let second = page(client, entity, url, &Method::GET, PAGE_SIZE, token)?;
+	let replay = page(client, entity, url, &Method::GET, 0, token)?;
+	let (second_ids, replay_ids) = (
+		second.ids.into_iter().collect::<BTreeSet<_>>(),
+		replay.ids.into_iter().collect::<BTreeSet<_>>(),
+	);
+
+	let mut problems = Vec::new();
+	if replay_ids != first_ids {
+		problems.push(format!(
+			"the first page returned {} with the {} instead of {}",
+			ids(&replay_ids),
+			property,
+			ids(&first_ids)
+		));
+	}
+	let overlap = first_ids.intersection(&second_ids).cloned().collect::<BTreeSet<_>>();
+	if !overlap.is_empty() {
+		problems.push(format!("the first and second pages both returned {}", ids(&overlap)));
+	}
+	if problems.is_empty() {
+		Ok(Replay::Consistent)
+	}
+	else {
+		Ok(Replay::Inconsistent(problems))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use serde_json::json;
+	use wiremock::{Request, ResponseTemplate};
+
+	use crate::output::{BeaconOutput, EndpointOutput};
+	use crate::pagination::{CHECK_NAMES, SNAPSHOT_CHECK_NAME};
+	use crate::testing::{Behavior, MockBeacon};
+	use crate::Json;
+
+	fn pagination<'a>(output: &'a BeaconOutput, name: &str) -> &'a EndpointOutput {
+		output.entities["Individual"]
+			.iter()
+			.find(|report| report.name == format!("Individual {}", name))
+			.unwrap()
+	}
+
+	/// Pages of the individuals, from the query or from the body, with the results count of `results_count`
+	fn paged(individuals: Json, results_count: impl Fn(usize) -> usize) -> impl Fn(&Request) -> ResponseTemplate {
+		move |request: &Request| {
+			let body = serde_json::from_slice::<Json>(&request.body).unwrap_or_default();
+			let param = |name: &str| {
+				body.pointer(&format!("/query/pagination/{}", name))
+					.and_then(Json::as_u64)
+					.map(|value| value as usize)
+					.or_else(|| {
+						request
+							.url
+							.query_pairs()
+							.find(|(key, _)| key == name)
+							.and_then(|(_, value)| value.parse::<usize>().ok())
+					})
+			};
+			let skip = param("skip").unwrap_or(0);
+			let limit = param("limit").unwrap_or(usize::MAX);
+			let mut page = individuals.clone();
+			let results = page["response"]["resultSets"][0]["results"]
+				.as_array()
+				.cloned()
+				.unwrap_or_default();
+			page["response"]["resultSets"][0]["resultsCount"] = json!(results_count(skip));
+			page["response"]["resultSets"][0]["results"] =
+				json!(results.into_iter().skip(skip).take(limit).collect::<Vec<_>>());
+			ResponseTemplate::new(200).set_body_json(page)
+		}
+	}
+
+	#[test]
+	fn test_pagination() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let individuals = beacon.response("/individuals");
+		beacon.mount("/individuals", paged(individuals.clone(), |_| 2));
+		beacon.mount_method("POST", "/individuals", paged(individuals.clone(), |_| 2));
+		let output = beacon.beacon().pagination().post_queries().validate();
+		for name in CHECK_NAMES {
+			let report = pagination(&output, name);
+			assert_eq!(report.valid, Some(true), "{}: {:?}", name, report.error);
+		}
+		// Only with --post-queries
+		let output = beacon.beacon().pagination().validate();
+		assert!(output.entities["Individual"]
+			.iter()
+			.all(|report| !report.name.ends_with(CHECK_NAMES[1])));
+
+		// The skip and limit ignored
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let output = beacon.beacon().pagination().validate();
+		let error = pagination(&output, CHECK_NAMES[0]).error.clone().unwrap();
+		assert!(
+			error.contains("the page at skip 0 has 2 records with limit 1"),
+			"{}",
+			error
+		);
+		assert!(
+			error.contains("the page at skip 1 returned again 'ind1' (first at skip 0)"),
+			"{}",
+			error
+		);
+
+		// Counting the records of the page
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		beacon.mount("/individuals", paged(individuals, |skip| 2 - skip));
+		let output = beacon.beacon().pagination().validate();
+		let error = pagination(&output, CHECK_NAMES[0]).error.clone().unwrap();
+		assert!(
+			error.contains("the page at skip 1 has resultsCount 1 instead of 2"),
+			"{}",
+			error
+		);
+		assert!(pagination(&output, SNAPSHOT_CHECK_NAME).skipped);
+	}
+
+	#[test]
+	fn test_results_count() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		beacon.mount("/individuals", paged(beacon.response("/individuals"), |_| 3));
+		let output = beacon.beacon().pagination().validate();
+		let error = pagination(&output, CHECK_NAMES[0]).error.clone().unwrap();
+		assert_eq!(
+			error,
+			"Response does not match the schema: the pages returned 2 records with resultsCount 3"
+		);
+	}
+
+	#[test]
+	fn test_truncated() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let mut individuals = beacon.response("/individuals");
+		let individual = individuals["response"]["resultSets"][0]["results"][0].clone();
+		let results = (0..12)
+			.map(|i| {
+				let mut individual = individual.clone();
+				individual["id"] = json!(format!("ind{}", i));
+				individual
+			})
+			.collect::<Vec<_>>();
+		individuals["response"]["resultSets"][0]["results"] = json!(results);
+		beacon.mount("/individuals", paged(individuals, |_| 12));
+		let output = beacon.beacon().pagination().validate();
+		let report = pagination(&output, CHECK_NAMES[0]);
+		assert_eq!(report.valid, Some(true), "{:?}", report.error);
+		assert_eq!(
+			report.notes,
+			vec!["Not verified: the records were not added up to the resultsCount, which exceeds the 10 pages read"]
+		);
+	}
+
+	fn snapshot<'a>(output: &'a BeaconOutput, entity: &str) -> &'a EndpointOutput {
+		output.entities[entity]
+			.iter()
+			.find(|report| report.name == format!("{} {}", entity, SNAPSHOT_CHECK_NAME))
+			.unwrap()
+	}
+
+	/// Pages of the individuals, with a snapshot token (the page always starts at `shift` without it)
+	fn paginated(individuals: Json, shift: usize) -> impl Fn(&Request) -> ResponseTemplate {
+		move |request: &Request| {
+			let param = |name: &str| {
+				request
+					.url
+					.query_pairs()
+					.find(|(key, _)| key == name)
+					.and_then(|(_, value)| value.parse::<usize>().ok())
+			};
+			let with_token = request.url.query_pairs().any(|(key, _)| key == "snapshotToken");
+			let skip = param("skip").unwrap_or(0) + if with_token { 0 } else { shift };
+			let limit = param("limit").unwrap_or(usize::MAX);
+			let mut page = individuals.clone();
+			page["meta"]["snapshotToken"] = json!("snapshot-1");
+			let results = page["response"]["resultSets"][0]["results"]
+				.as_array()
+				.cloned()
+				.unwrap_or_default();
+			page["response"]["resultSets"][0]["results"] =
+				json!(results.into_iter().skip(skip).take(limit).collect::<Vec<_>>());
+			ResponseTemplate::new(200).set_body_json(page)
+		}
+	}
+
+	#[test]
+	fn test_no_token() {
+		let output = MockBeacon::start(Behavior::Conformant).beacon().pagination().validate();
+		let report = snapshot(&output, "Individual");
+		assert!(report.skipped);
+		assert!(report.evidence.is_some());
+	}
+
+	#[test]
+	fn test_snapshot_pagination() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		beacon.mount("/individuals", paginated(beacon.response("/individuals"), 0));
+		let output = beacon.beacon().pagination().validate();
+		let report = snapshot(&output, "Individual");
+		assert_eq!(report.valid, Some(true), "{:?}", report.error);
+		assert!(snapshot(&output, "Biosample").skipped);
+
+		// The first page moved since the token was issued
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		beacon.mount("/individuals", paginated(beacon.response("/individuals"), 1));
+		let output = beacon.beacon().pagination().validate();
+		let error = snapshot(&output, "Individual").error.clone().unwrap();
+		assert!(
+			error.contains("the first page returned 'ind1' with the snapshotToken instead of 'ind2'"),
+			"{}",
+			error
+		);
+	}
+}
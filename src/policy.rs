@@ -0,0 +1,295 @@
+//! Verdict of a run against the policy of a network
+//!
+//! A network publishes the requirements of its members in a policy file: the
+//! checks that must pass, the minimum score and the failures it tolerates (known
+//! issues of the specification, for instance). The verifier evaluates the run
+//! against it and adds a pass/fail verdict to the output, distinct from the
+//! outcome of the checks, so that onboarding decisions can be automated.
+//!
+//! ```json
+//! {
+//!     "name": "Example network",
+//!     "minimumScore": 0.9,
+//!     "requiredChecks": [{ "entity": "Individual" }, { "entity": "Biosample", "category": "framework" }],
+//!     "allowedFailures": [{ "entity": "Biosample", "check": "Biosample id consistency" }]
+//! }
+//! ```
+//!
+//! The policy can be written in YAML too (with a `.yaml` or `.yml` extension).
+//! It is checked against the JSON Schema of [`schema`] before it is read, like
+//! the configuration file.
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::error::VerifierError;
+use crate::output::{BeaconOutput, Category, EndpointOutput, Outcome};
+use crate::syntax::{self, Syntax};
+use crate::Json;
+
+/// JSON Schema of the policy files
+pub fn schema() -> Json {
+	let selector = json!({
+		"type": "object",
+		"additionalProperties": false,
+		"required": ["entity"],
+		"properties": {
+			"entity": { "type": "string", "minLength": 1 },
+			"category": { "enum": Category::ALL },
+			"check": { "type": "string", "minLength": 1 }
+		}
+	});
+	json!({
+		"type": "object",
+		"additionalProperties": false,
+		"required": ["name"],
+		"properties": {
+			"name": { "type": "string" },
+			"minimumScore": { "type": "number", "minimum": 0, "maximum": 1 },
+			"requiredChecks": { "type": "array", "items": selector },
+			"allowedFailures": { "type": "array", "items": selector }
+		}
+	})
+}
+
+/// Checks of an entity, all of them or only the ones of a category or with a name
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CheckSelector {
+	pub entity: String,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub category: Option<Category>,
+	/// Name of the check (e.g. `Individual all entries`)
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub check: Option<String>,
+}
+
+impl CheckSelector {
+	fn matches(&self, entity_name: &str, report: &EndpointOutput) -> bool {
+		self.entity == entity_name
+			&& self.category.map_or(true, |category| category == report.category)
+			&& self.check.as_ref().map_or(true, |check| *check == report.name)
+	}
+}
+
+impl std::fmt::Display for CheckSelector {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match (&self.check, self.category) {
+			(Some(check), _) => write!(f, "{}", check),
+			(None, Some(category)) => write!(f, "{} ({})", self.entity, category),
+			(None, None) => write!(f, "{}", self.entity),
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct Policy {
+	pub name: String,
+	/// Share of the checks that must pass, between 0 and 1 (the allowed failures do not count)
+	#[serde(default)]
+	pub minimum_score: f64,
+	/// Checks that must run and pass (or have nothing to verify)
+	#[serde(default)]
+	pub required_checks: Vec<CheckSelector>,
+	/// Checks whose failures are tolerated
+	#[serde(default)]
+	pub allowed_failures: Vec<CheckSelector>,
+}
+
+/// Outcome of the run against a policy
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PolicyVerdict {
+	/// Name of the policy
+	pub policy: String,
+	pub pass: bool,
+	/// Score of the run, without the allowed failures
+	pub score: f64,
+	/// Why the run does not meet the policy
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub violations: Vec<String>,
+}
+
+impl Policy {
+	/// Reads the policy file at `path`, in YAML after its extension and in JSON otherwise
+	pub fn load(path: &Path) -> Result<Self, VerifierError> {
+		syntax::load(path, Syntax::Json, &schema())
+	}
+
+	fn allows_failure(&self, entity_name: &str, report: &EndpointOutput) -> bool {
+		self.allowed_failures
+			.iter()
+			.any(|selector| selector.matches(entity_name, report))
+	}
+
+	pub fn evaluate(&self, output: &BeaconOutput) -> PolicyVerdict {
+		let reports = output
+			.entities
+			.iter()
+			.flat_map(|(entity_name, reports)| reports.iter().map(move |report| (entity_name.as_str(), report)))
+			.collect::<Vec<_>>();
+		let mut violations = Vec::new();
+
+		for selector in &self.required_checks {
+			let required = reports
+				.iter()
+				.filter(|(entity_name, report)| selector.matches(entity_name, report))
+				.collect::<Vec<_>>();
+			if required.is_empty() {
+				violations.push(format!("required check {} did not run", selector));
+			}
+			for (_, report) in required.iter().filter(|(_, report)| !report.outcome().is_success()) {
+				violations.push(format!(
+					"required check {} ({}) did not pass: {}",
+					report.name,
+					report.category,
+					report.outcome()
+				));
+			}
+		}
+
+		// Same as the score of the output, without the allowed failures
+		let outcomes = reports
+			.iter()
+			.map(|(entity_name, report)| (report.outcome(), self.allows_failure(entity_name, report)))
+			.filter(|(outcome, allowed)| *outcome != Outcome::Skipped && (outcome.is_success() || !*allowed))
+			.map(|(outcome, _)| outcome)
+			.collect::<Vec<_>>();
+		let score = if outcomes.is_empty() {
+			1.0
+		}
+		else {
+			outcomes.iter().filter(|outcome| **outcome == Outcome::Passed).count() as f64 / outcomes.len() as f64
+		};
+		if score < self.minimum_score {
+			violations.push(format!(
+				"score {:.1}% is below the minimum of {:.1}%",
+				score * 100.0,
+				self.minimum_score * 100.0
+			));
+		}
+
+		PolicyVerdict {
+			policy: self.name.clone(),
+			pass: violations.is_empty(),
+			score,
+			violations,
+		}
+	}
+}
+
+impl PolicyVerdict {
+	pub fn summary(&self) {
+		if self.pass {
+			log::info!("Policy {}: pass", self.policy);
+		}
+		else {
+			log::error!("Policy {}: fail", self.policy);
+			for violation in &self.violations {
+				log::error!("\t{}", violation);
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use serde_json::json;
+
+	use crate::policy::{self, Policy};
+	use crate::syntax::{self, Syntax};
+	use crate::testing::{Behavior, MockBeacon};
+
+	#[test]
+	fn test_policy() {
+		let output = MockBeacon::start(Behavior::Conformant).verify();
+		let policy = serde_json::from_value::<Policy>(json!({
+			"name": "Network",
+			"minimumScore": 1.0,
+			"requiredChecks": [{ "entity": "Individual" }, { "entity": "Biosample", "category": "model" }]
+		}))
+		.unwrap();
+		let verdict = policy.evaluate(&output);
+		assert!(verdict.pass, "{:?}", verdict.violations);
+		assert_eq!(verdict.score, 1.0);
+
+		let policy = serde_json::from_value::<Policy>(json!({
+			"name": "Network",
+			"requiredChecks": [{ "entity": "Run" }, { "entity": "Individual", "check": "Individual all entries" }]
+		}))
+		.unwrap();
+		let verdict = policy.evaluate(&output);
+		assert!(!verdict.pass);
+		assert_eq!(verdict.violations, vec!["required check Run did not run".to_string()]);
+	}
+
+	#[test]
+	fn test_policy_file() {
+		let content = "name: Network\nminimumScore: 0.9\nrequiredChecks:\n  - entity: Individual\n    category: model";
+		let directory = tempfile::tempdir().unwrap();
+		let path = directory.path().join("policy.yaml");
+		std::fs::write(&path, content).unwrap();
+		let policy = Policy::load(&path).unwrap();
+		assert_eq!(policy.minimum_score, 0.9);
+		assert_eq!(policy.required_checks[0].to_string(), "Individual (model)");
+
+		let content =
+			"{\n  \"name\": \"Network\",\n  \"minimumScore\": 90,\n  \"allowedFailures\": [{ \"check\": \"x\" }]\n}";
+		let problems = syntax::problems(content, Syntax::Json, &policy::schema()).unwrap();
+		assert_eq!(problems.len(), 2, "{:?}", problems);
+		let reported = |key: &str, position: &str| {
+			problems
+				.iter()
+				.any(|problem| problem.starts_with(&format!("{}: ", key)) && problem.ends_with(position))
+		};
+		assert!(reported("minimumScore", "(line 3, column 3)"), "{:?}", problems);
+		assert!(reported("allowedFailures.0", "(line 4, column 25)"), "{:?}", problems);
+		let path = directory.path().join("policy.json");
+		std::fs::write(&path, content).unwrap();
+		assert!(Policy::load(&path).unwrap_err().to_string().contains("line 3"));
+	}
+
+	#[test]
+	fn test_allowed_failures() {
+		let output = MockBeacon::start(Behavior::MissingEndpoints).verify();
+		let failed = output
+			.failures_by_category()
+			.into_values()
+			.flatten()
+			.collect::<Vec<_>>();
+		assert!(!failed.is_empty());
+		let (entity_name, report) = failed[0];
+		let mut policy = serde_json::from_value::<Policy>(json!({
+			"name": "Network",
+			"minimumScore": 1.0,
+			"requiredChecks": [{ "entity": entity_name, "category": report.category, "check": report.name }]
+		}))
+		.unwrap();
+		let verdict = policy.evaluate(&output);
+		assert!(!verdict.pass);
+		assert_eq!(verdict.violations.len(), 2, "{:?}", verdict.violations);
+		assert_eq!(
+			verdict.violations[0],
+			format!(
+				"required check {} ({}) did not pass: {}",
+				report.name,
+				report.category,
+				report.outcome()
+			)
+		);
+
+		// Tolerating every failure lifts the score, but not the requirements
+		policy.allowed_failures = failed
+			.iter()
+			.map(|(entity_name, report)| {
+				serde_json::from_value(json!({ "entity": entity_name, "check": report.name })).unwrap()
+			})
+			.collect();
+		let verdict = policy.evaluate(&output);
+		assert_eq!(verdict.score, 1.0);
+		assert_eq!(verdict.violations.len(), 1, "{:?}", verdict.violations);
+	}
+}
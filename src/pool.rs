@@ -0,0 +1,114 @@
+//! Worker pool checking the endpoints
+//!
+//! Each job fetches the response of an endpoint and validates it, so there are
+//! never more requests in flight than workers. Jobs wait in a bounded queue:
+//! when every worker is busy and the queue is full, submitting a job blocks, so
+//! the scheduler never gets too far ahead of the checks.
+//!
+//! The workers are threads rather than the tasks of an async runtime: the
+//! client, with its authentication, recording and replay, is blocking
+//! (`reqwest::blocking`), and with the requests in flight bounded by `--jobs`,
+//! a thread per request costs little more than a task would.
+
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use crate::output::EndpointReport;
+
+type Job = Box<dyn FnOnce() -> Vec<EndpointReport> + Send>;
+
+pub struct ValidationPool {
+	jobs: Option<SyncSender<Job>>,
+	reports: Receiver<Vec<EndpointReport>>,
+	workers: Vec<JoinHandle<()>>,
+}
+
+impl ValidationPool {
+	/// Starts `workers` threads (at least one), with a queue of as many pending jobs
+	pub fn new(workers: usize) -> Self {
+		let workers = workers.max(1);
+		let (jobs, queue) = mpsc::sync_channel::<Job>(workers);
+		let queue = Arc::new(Mutex::new(queue));
+		let (sender, reports) = mpsc::channel();
+
+		let workers = (0..workers)
+			.map(|_| {
+				let queue = Arc::clone(&queue);
+				let sender = sender.clone();
+				thread::spawn(move || loop {
+					// The queue is only locked while waiting for the next job
+					let job = match queue.lock() {
+						Ok(queue) => queue.recv(),
+						Err(_) => break,
+					};
+					match job.map(|job| sender.send(job())) {
+						Ok(Ok(())) => (),
+						_ => break,
+					}
+				})
+			})
+			.collect();
+
+		Self {
+			jobs: Some(jobs),
+			reports,
+			workers,
+		}
+	}
+
+	/// Queues a job, waiting while the queue is full
+	pub fn submit<F>(&self, job: F)
+	where
+		F: FnOnce() -> Vec<EndpointReport> + Send + 'static,
+	{
+		if let Some(jobs) = &self.jobs {
+			if jobs.send(Box::new(job)).is_err() {
+				log::error!("Every validation worker has stopped");
+			}
+		}
+	}
+
+	/// Waits for every queued job, returning their reports
+	pub fn finish(mut self) -> Vec<EndpointReport> {
+		// Closing the queue stops the workers once it is empty
+		self.jobs.take();
+		for worker in self.workers.drain(..) {
+			if worker.join().is_err() {
+				log::error!("A validation worker panicked");
+			}
+		}
+		self.reports.try_iter().flatten().collect()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use std::sync::atomic::{AtomicUsize, Ordering};
+	use std::sync::Arc;
+
+	use url::Url;
+
+	use crate::output::EndpointReport;
+	use crate::pool::ValidationPool;
+
+	#[test]
+	fn test_every_job_is_run() {
+		let url = Url::parse("https://beacon.example.org/").unwrap();
+		let running = Arc::new(AtomicUsize::new(0));
+		let pool = ValidationPool::new(3);
+		for i in 0..20 {
+			let url = url.clone();
+			let running = Arc::clone(&running);
+			pool.submit(move || {
+				running.fetch_add(1, Ordering::SeqCst);
+				vec![EndpointReport::new("Entity", &i.to_string(), url)]
+			});
+		}
+		let mut names = pool.finish().into_iter().map(|report| report.name).collect::<Vec<_>>();
+		names.sort_by_key(|name| name.parse::<usize>().unwrap());
+		assert_eq!(names, (0..20).map(|i| i.to_string()).collect::<Vec<_>>());
+		assert_eq!(running.load(Ordering::SeqCst), 20);
+	}
+}
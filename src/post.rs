@@ -0,0 +1,216 @@
+//! Queries POSTed with a request body
+//!
+//! Beacon v2 queries are POSTed as a `beaconRequestBody`: the `meta` of the
+//! request and a `query` with its filters, its pagination and the granularity
+//! requested. The other checks only send GET requests (and a bare POST when
+//! GET is not allowed). With `--post-queries`, every entity is also queried
+//! with a valid request body: the response goes through the checks of the GET
+//! queries, and its `meta.receivedRequestSummary` must echo the request.
+
+use serde_json::json;
+use url::Url;
+
+use crate::client::Client;
+use crate::error::VerifierError;
+use crate::formats::Formats;
+use crate::framework::ResponseSchemas;
+use crate::model::{Entity, Model};
+use crate::output::{Category, EndpointReport};
+use crate::{utils, Json};
+
+/// Name of the checks, after the name of the entity
+pub const CHECK_NAMES: [&str; 2] = ["POST query", "POST request echo"];
+
+/// Id of the checks in the catalog
+const CHECK_IDS: [&str; 2] = ["framework.post.query", "framework.post.echo"];
+
+/// Records requested by the query
+const PAGE_LIMIT: u64 = 10;
+
+/// Request body of a query for the first page of records, without filters
+pub fn body() -> Json {
+	json!({
+		"meta": { "apiVersion": "v2.0" },
+		"query": {
+			"filters": [],
+			"includeResultsetResponses": "HIT",
+			"pagination": { "skip": 0, "limit": PAGE_LIMIT },
+			"requestedGranularity": "record",
+			"testMode": false
+		}
+	})
+}
+
+/// Differences between the request and its summary in the meta of the response
+pub fn echo_problems(request: &Json, response: &Json) -> Vec<String> {
+	let summary = match response.pointer("/meta/receivedRequestSummary") {
+		Some(summary) => summary,
+		None => return vec!["the response has no meta.receivedRequestSummary".into()],
+	};
+	let mut problems = Vec::new();
+	for pointer in ["/requestedGranularity", "/pagination/skip", "/pagination/limit"] {
+		let requested = request.pointer(&format!("/query{}", pointer));
+		match summary.pointer(pointer) {
+			None => problems.push(format!(
+				"receivedRequestSummary{} is missing",
+				pointer.replace('/', ".")
+			)),
+			Some(received) if Some(received) != requested => problems.push(format!(
+				"receivedRequestSummary{} is {} instead of {}",
+				pointer.replace('/', "."),
+				received,
+				requested.unwrap_or(&Json::Null)
+			)),
+			Some(_) => (),
+		}
+	}
+	let requested = request
+		.pointer("/query/filters")
+		.and_then(Json::as_array)
+		.map_or(0, Vec::len);
+	if let Some(received) = summary.get("filters").and_then(Json::as_array) {
+		if received.len() != requested {
+			problems.push(format!(
+				"receivedRequestSummary.filters has {} filters instead of {}",
+				received.len(),
+				requested
+			));
+		}
+	}
+	problems
+}
+
+/// POSTs a query for the first page of records of `entity`, checking the response and its echo of the request
+pub fn check(
+	client: &Client,
+	root_url: &Url,
+	entity: &Entity,
+	schemas: &ResponseSchemas,
+	formats: &Formats,
+	only: Option<Category>,
+) -> Vec<EndpointReport> {
+	let url = utils::url_join(root_url, &entity.url);
+	let name = format!("{} {}", entity.name, CHECK_NAMES[0]);
+	let echo = EndpointReport::new(
+		&entity.name,
+		&format!("{} {}", entity.name, CHECK_NAMES[1]),
+		url.clone(),
+	)
+	.category(Category::Framework)
+	.check(CHECK_IDS[1]);
+	let body = body();
+	let response = match client.post_query(&entity.name, &url, &body) {
+		Ok(response) => response,
+		Err(e) => {
+			log::error!("{}", e);
+			let report = EndpointReport::new(&entity.name, &name, url.clone())
+				.category(Category::Framework)
+				.check(CHECK_IDS[0]);
+			return vec![report.error(e)];
+		},
+	};
+	let problems = echo_problems(&body, &response.json);
+	let echo = echo.elapsed(response.elapsed);
+	let echo = if problems.is_empty() {
+		echo.ok(None)
+	}
+	else {
+		echo.error(VerifierError::BadEnvelope(problems.join("\n")))
+	};
+	let endpoint = Model::build_endpoint(
+		entity.name.clone(),
+		entity.entry_type.clone(),
+		entity.schemas.clone(),
+		name,
+		&entity.url,
+		None,
+	);
+	// The records are checked like the ones of the endpoint, the response as the answer to the query
+	let mut reports = endpoint
+		.validate(response, root_url, schemas, formats, only)
+		.into_iter()
+		.map(|report| match report.category {
+			Category::Framework => report.check(CHECK_IDS[0]),
+			Category::Model => report,
+		})
+		.collect::<Vec<_>>();
+	reports.push(echo);
+	reports
+}
+
+#[cfg(test)]
+mod tests {
+
+	use serde_json::json;
+	use wiremock::{Request, ResponseTemplate};
+
+	use crate::output::{BeaconOutput, Category, EndpointOutput};
+	use crate::post::{self, CHECK_NAMES};
+	use crate::testing::{Behavior, MockBeacon};
+	use crate::Json;
+
+	fn report<'a>(output: &'a BeaconOutput, name: &str, category: Category) -> &'a EndpointOutput {
+		output.entities["Individual"]
+			.iter()
+			.find(|report| report.name == format!("Individual {}", name) && report.category == category)
+			.unwrap()
+	}
+
+	#[test]
+	fn test_echo_problems() {
+		let request = post::body();
+		let response = json!({ "meta": { "receivedRequestSummary": request["query"].clone() } });
+		assert!(post::echo_problems(&request, &response).is_empty());
+		let response = json!({ "meta": { "receivedRequestSummary": {
+			"requestedGranularity": "count",
+			"pagination": { "skip": 0 },
+			"filters": [{ "id": "NCIT:C16576" }]
+		} } });
+		assert_eq!(
+			post::echo_problems(&request, &response),
+			vec![
+				"receivedRequestSummary.requestedGranularity is \"count\" instead of \"record\"",
+				"receivedRequestSummary.pagination.limit is missing",
+				"receivedRequestSummary.filters has 1 filters instead of 0",
+			]
+		);
+		assert_eq!(post::echo_problems(&request, &json!({ "meta": {} })).len(), 1);
+	}
+
+	#[test]
+	fn test_post_queries() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let individuals = beacon.response("/individuals");
+		// Echoes the request body, but with a smaller limit
+		beacon.mount_method("POST", "/individuals", move |request: &Request| {
+			let body = match serde_json::from_slice::<Json>(&request.body) {
+				Ok(body) => body,
+				Err(_) => return ResponseTemplate::new(400),
+			};
+			let mut response = individuals.clone();
+			response["meta"]["receivedRequestSummary"] = body["query"].clone();
+			response["meta"]["receivedRequestSummary"]["pagination"]["limit"] = json!(1);
+			ResponseTemplate::new(200).set_body_json(response)
+		});
+		let output = beacon.beacon().post_queries().validate();
+		let query = report(&output, CHECK_NAMES[0], Category::Framework);
+		assert_eq!(query.valid, Some(true), "{:?}", query.error);
+		assert_eq!(query.method.as_deref(), Some("POST"));
+		assert_eq!(report(&output, CHECK_NAMES[0], Category::Model).valid, Some(true));
+		let echo = report(&output, CHECK_NAMES[1], Category::Framework);
+		assert_eq!(
+			echo.error.as_deref(),
+			Some("Bad response envelope: receivedRequestSummary.pagination.limit is 1 instead of 10")
+		);
+		// Not answered
+		let error = output.entities["Biosample"]
+			.iter()
+			.find(|report| report.name == format!("Biosample {}", CHECK_NAMES[0]))
+			.and_then(|report| report.error.clone())
+			.unwrap();
+		assert!(error.contains("biosamples"), "{}", error);
+		assert!(!beacon.verify().entities["Individual"]
+			.iter()
+			.any(|report| report.name.contains("POST")));
+	}
+}
@@ -0,0 +1,190 @@
+//! Curated models of non-human beacons
+//!
+//! The default model describes human beacons (individuals, biosamples, ...),
+//! which the pathogen and plant communities cannot verify their beacons with.
+//! The presets are models of their own entities and default schemas, shipped
+//! with the verifier and selected with `--model-preset`. They are loaded like
+//! any custom model, from the `preset:<name>` location recorded in the report.
+//! The schemas of the entities the presets keep from the default model (the
+//! datasets and the genomic variations) are subsets of their default schemas,
+//! shared by the presets, which only differ by the entity the variants were
+//! observed in (the `caseLevelData.json` of each preset).
+
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::error::VerifierError;
+
+/// Scheme of the locations of the presets (e.g. `preset:pathogen`)
+pub const SCHEME: &str = "preset";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ArgEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum ModelPreset {
+	/// Pathogen isolates and their variants (e.g. SARS-CoV-2 surveillance)
+	Pathogen,
+	/// Plant accessions of genebanks (germplasm) and their variants
+	Plant,
+}
+
+/// Files of a preset, by path in the model
+type Files = &'static [(&'static str, &'static str)];
+
+/// Files of every preset
+const COMMON: Files = &[
+	(
+		"datasets/defaultSchema.json",
+		include_str!("../presets/common/datasets/defaultSchema.json"),
+	),
+	(
+		"genomicVariations/defaultSchema.json",
+		include_str!("../presets/common/genomicVariations/defaultSchema.json"),
+	),
+];
+
+const PATHOGEN: Files = &[
+	(
+		"beaconConfiguration.json",
+		include_str!("../presets/pathogen/beaconConfiguration.json"),
+	),
+	("beaconMap.json", include_str!("../presets/pathogen/beaconMap.json")),
+	("endpoints.json", include_str!("../presets/pathogen/endpoints.json")),
+	(
+		"genomicVariations/caseLevelData.json",
+		include_str!("../presets/pathogen/genomicVariations/caseLevelData.json"),
+	),
+	(
+		"isolates/defaultSchema.json",
+		include_str!("../presets/pathogen/isolates/defaultSchema.json"),
+	),
+];
+
+const PLANT: Files = &[
+	(
+		"beaconConfiguration.json",
+		include_str!("../presets/plant/beaconConfiguration.json"),
+	),
+	("beaconMap.json", include_str!("../presets/plant/beaconMap.json")),
+	("endpoints.json", include_str!("../presets/plant/endpoints.json")),
+	(
+		"genomicVariations/caseLevelData.json",
+		include_str!("../presets/plant/genomicVariations/caseLevelData.json"),
+	),
+	(
+		"germplasm/defaultSchema.json",
+		include_str!("../presets/plant/germplasm/defaultSchema.json"),
+	),
+];
+
+impl ModelPreset {
+	pub fn name(self) -> &'static str {
+		match self {
+			Self::Pathogen => "pathogen",
+			Self::Plant => "plant",
+		}
+	}
+
+	/// Location of the preset, the model to load
+	pub fn location(self) -> Url {
+		Url::parse(&format!("{}:{}", SCHEME, self.name())).expect("Bad location of a model preset")
+	}
+
+	/// Preset at `location` (e.g. `preset:plant`)
+	pub fn from_location(location: &Url) -> Result<Self, VerifierError> {
+		<Self as clap::ArgEnum>::value_variants()
+			.iter()
+			.copied()
+			.find(|preset| location.scheme() == SCHEME && location.path() == preset.name())
+			.ok_or_else(|| VerifierError::UnknownModelPreset(location.path().to_string()))
+	}
+
+	fn files(self) -> Files {
+		match self {
+			Self::Pathogen => PATHOGEN,
+			Self::Plant => PLANT,
+		}
+	}
+
+	/// Writes the files of the model in `dir`
+	pub fn extract(self, dir: &Path) -> Result<(), VerifierError> {
+		for (path, content) in COMMON.iter().chain(self.files()) {
+			let path = dir.join(path);
+			if let Some(parent) = path.parent() {
+				fs::create_dir_all(parent)?;
+			}
+			fs::write(path, content)?;
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use serde_json::json;
+	use url::Url;
+
+	use crate::cache::SchemaCache;
+	use crate::formats::Formats;
+	use crate::model::Model;
+	use crate::preset::ModelPreset;
+
+	#[test]
+	fn test_presets() {
+		assert_eq!(ModelPreset::Plant.location().as_str(), "preset:plant");
+		assert_eq!(
+			ModelPreset::from_location(&ModelPreset::Plant.location()).unwrap(),
+			ModelPreset::Plant
+		);
+		let e = ModelPreset::from_location(&Url::parse("preset:fungus").unwrap()).unwrap_err();
+		assert_eq!(e.to_string(), "Unknown model preset: fungus");
+
+		let location = ModelPreset::Pathogen.location();
+		let model = Model::load(&location, &Formats::default(), &SchemaCache::default()).unwrap();
+		let mut names = model
+			.entities
+			.iter()
+			.map(|entity| entity.name.as_str())
+			.collect::<Vec<_>>();
+		names.sort_unstable();
+		assert_eq!(names, vec!["Dataset", "GenomicVariation", "Isolate"]);
+		assert!(model.revision.is_none());
+		let variants = model
+			.entities
+			.iter()
+			.find(|entity| entity.name == "GenomicVariation")
+			.unwrap();
+		assert_eq!(variants.id_field, "variantInternalId");
+		let isolates = model.entities.iter().find(|entity| entity.name == "Isolate").unwrap();
+		assert_eq!(isolates.related_endpoints.as_ref().unwrap().len(), 1);
+
+		let mut variant = json!({
+			"variantInternalId": "var1",
+			"variation": {
+				"location": { "sequence_id": "refseq:NC_045512.2", "interval": { "start": { "value": 1 } } }
+			},
+			"caseLevelData": [{ "isolateId": "iso1" }],
+		});
+		assert!(variants.schemas[0].schema.is_valid(&variant));
+
+		let model = Model::load(
+			&ModelPreset::Plant.location(),
+			&Formats::default(),
+			&SchemaCache::default(),
+		)
+		.unwrap();
+		assert!(model.entities.iter().any(|entity| entity.name == "Germplasm"));
+		// The genomic variations of the plant preset are observed in accessions
+		let variants = model
+			.entities
+			.iter()
+			.find(|entity| entity.name == "GenomicVariation")
+			.unwrap();
+		assert!(!variants.schemas[0].schema.is_valid(&variant));
+		variant["caseLevelData"][0] = json!({ "germplasmId": "acc1" });
+		assert!(variants.schemas[0].schema.is_valid(&variant));
+	}
+}
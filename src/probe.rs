@@ -0,0 +1,261 @@
+//! Lightweight liveness probe, meant to be run every minute by monitoring systems
+
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use chrono::{NaiveDateTime, SubsecRound};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::client::{Client, HeadSupport};
+use crate::humanize::TimeStyle;
+use crate::Json;
+
+pub struct Probe {
+	client: Client,
+	url: Url,
+	budget: Duration,
+	query: Option<String>,
+	head: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProbeOutput {
+	pub name: Option<String>,
+	pub url: Url,
+	pub last_updated: NaiveDateTime,
+	/// Whether every check answered within the latency budget
+	pub alive: bool,
+	pub budget_ms: u128,
+	pub checks: Vec<ProbeCheck>,
+	/// Whether the probe ran during a maintenance window, when failures do not count
+	#[serde(default)]
+	pub maintenance: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProbeCheck {
+	pub url: Url,
+	pub latency_ms: u128,
+	pub ok: bool,
+	pub error: Option<String>,
+	/// Whether the endpoint answered HEAD, probed instead of the query with `--head`
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub head: Option<HeadSupport>,
+}
+
+impl Probe {
+	/// The client gives up on every request after `budget`
+	pub fn new(client: Client, url: &Url, budget: Duration) -> Self {
+		Self {
+			client: client.timeout(budget),
+			url: url.clone(),
+			budget,
+			query: None,
+			head: false,
+		}
+	}
+
+	/// Also runs a boolean query on the endpoint at `location` (e.g. `individuals`)
+	pub fn query(mut self, location: &str) -> Self {
+		self.query = Some(location.trim_matches('/').to_string());
+		self
+	}
+
+	/// Sends a HEAD request instead of the query, to spare the bandwidth, if the endpoint supports it
+	pub fn head(mut self) -> Self {
+		self.head = true;
+		self
+	}
+
+	fn endpoint_url(&self, location: &str) -> Url {
+		let mut url = self.url.clone();
+		url.set_path(Path::new(self.url.path()).join(location).to_str().unwrap_or(""));
+		url
+	}
+
+	fn check(&self, endpoint: &str, url: Url) -> (ProbeCheck, Option<Json>) {
+		let start = Instant::now();
+		let result = self.client.query(endpoint, &url);
+		let latency = start.elapsed();
+		let (error, json) = match result {
+			Ok(_) if latency > self.budget => (
+				Some(format!(
+					"Exceeded the latency budget ({} ms > {} ms)",
+					latency.as_millis(),
+					self.budget.as_millis()
+				)),
+				None,
+			),
+			Ok(response) => (None, Some(response.json)),
+			Err(e) => (Some(e.to_string()), None),
+		};
+		let check = ProbeCheck {
+			url,
+			latency_ms: latency.as_millis(),
+			ok: error.is_none(),
+			error,
+			head: None,
+		};
+		(check, json)
+	}
+
+	/// Reachability of the endpoint with a HEAD request, `None` if it does not support it
+	fn check_head(&self, endpoint: &str, url: &Url) -> Option<ProbeCheck> {
+		let start = Instant::now();
+		let result = self.client.head(endpoint, url);
+		let latency = start.elapsed();
+		let error = match result {
+			Ok(HeadSupport::Unsupported) => {
+				log::debug!("{} does not support HEAD, falling back to the query", url);
+				return None;
+			},
+			Ok(HeadSupport::Supported) if latency > self.budget => Some(format!(
+				"Exceeded the latency budget ({} ms > {} ms)",
+				latency.as_millis(),
+				self.budget.as_millis()
+			)),
+			Ok(HeadSupport::Supported) => None,
+			Err(e) => Some(e.to_string()),
+		};
+		Some(ProbeCheck {
+			url: url.clone(),
+			latency_ms: latency.as_millis(),
+			ok: error.is_none(),
+			error,
+			head: Some(HeadSupport::Supported),
+		})
+	}
+
+	pub fn run(self) -> ProbeOutput {
+		let mut checks = Vec::new();
+
+		log::info!("Probing \"Info\"");
+		let (check, info) = self.check("Info", self.endpoint_url("info"));
+		checks.push(check);
+
+		if let Some(location) = &self.query {
+			log::info!("Probing {:?}", location);
+			let mut url = self.endpoint_url(location);
+			url.query_pairs_mut().append_pair("requestedGranularity", "boolean");
+			let head = if self.head {
+				self.check_head(location, &url)
+			}
+			else {
+				None
+			};
+			let check = match head {
+				Some(check) => check,
+				None => {
+					let (mut check, _) = self.check(location, url);
+					check.head = self.head.then(|| HeadSupport::Unsupported);
+					check
+				},
+			};
+			checks.push(check);
+		}
+
+		ProbeOutput {
+			name: info
+				.as_ref()
+				.and_then(|info| info.pointer("/response/name"))
+				.and_then(Json::as_str)
+				.map(String::from),
+			url: self.url,
+			last_updated: chrono::offset::Utc::now().naive_utc().round_subsecs(6),
+			alive: checks.iter().all(|check| check.ok),
+			budget_ms: self.budget.as_millis(),
+			checks,
+			maintenance: false,
+		}
+	}
+}
+
+impl ProbeOutput {
+	pub fn summary(&self, time_style: TimeStyle) {
+		for check in &self.checks {
+			let latency = time_style.millis(check.latency_ms as u64);
+			match &check.error {
+				None => log::info!("{} \u{2713} ({})", check.url, latency),
+				Some(error) => log::error!("{} \u{2717} ({}): {}", check.url, latency, error),
+			}
+		}
+		if self.maintenance {
+			log::info!("Maintenance window: the failures do not count");
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use std::time::Duration;
+
+	use wiremock::ResponseTemplate;
+
+	use crate::client::{Client, HeadSupport};
+	use crate::probe::Probe;
+	use crate::testing::{Behavior, MockBeacon};
+
+	#[test]
+	fn test_alive() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let output = Probe::new(Client::new(), &beacon.url(), Duration::from_secs(5))
+			.query("individuals")
+			.run();
+		assert!(output.alive);
+		assert_eq!(output.name.as_deref(), Some("Mock Beacon"));
+		assert_eq!(output.checks.len(), 2);
+		assert_eq!(output.checks[1].url.query(), Some("requestedGranularity=boolean"));
+	}
+
+	#[test]
+	fn test_head() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		beacon.mount_method("HEAD", "/individuals", ResponseTemplate::new(200));
+		let output = Probe::new(Client::new(), &beacon.url(), Duration::from_secs(5))
+			.query("individuals")
+			.head()
+			.run();
+		assert!(output.alive);
+		assert_eq!(output.checks[1].head, Some(HeadSupport::Supported));
+		assert_eq!(output.checks[0].head, None);
+
+		// Falls back to the query
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		beacon.mount_method("HEAD", "/individuals", ResponseTemplate::new(405));
+		let output = Probe::new(Client::new(), &beacon.url(), Duration::from_secs(5))
+			.query("individuals")
+			.head()
+			.run();
+		assert!(output.alive);
+		assert_eq!(output.checks[1].head, Some(HeadSupport::Unsupported));
+	}
+
+	#[test]
+	fn test_missing_query_endpoint() {
+		let beacon = MockBeacon::start(Behavior::MissingEndpoints);
+		let output = Probe::new(Client::new(), &beacon.url(), Duration::from_secs(5))
+			.query("biosamples")
+			.run();
+		assert!(!output.alive);
+		assert!(output.checks[0].ok);
+		assert!(!output.checks[1].ok);
+	}
+
+	#[test]
+	fn test_latency_budget() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let info = beacon.response("/info");
+		beacon.mount(
+			"/info",
+			ResponseTemplate::new(200)
+				.set_body_json(info)
+				.set_delay(Duration::from_millis(500)),
+		);
+		let output = Probe::new(Client::new(), &beacon.url(), Duration::from_millis(100)).run();
+		assert!(!output.alive);
+		assert!(output.checks[0].error.is_some());
+		assert!(output.name.is_none());
+	}
+}
@@ -0,0 +1,537 @@
+//! Versions of the protocols the beacon is served with
+//!
+//! The report records, per host, the HTTP version of the requests of the
+//! verifier and, over TLS, the version, cipher and application protocol (ALPN)
+//! negotiated on a separate, fresh connection offering HTTP/2 and HTTP/1.1.
+//! Every host the verifier got a response from is reported, not only the one
+//! of the beacon. The connection goes the way of the requests: through the
+//! HTTP(S) or SOCKS5 proxy (`--proxy`, else `HTTPS_PROXY` unless `NO_PROXY`
+//! excludes the host), trusting the certificates of `--ca-cert` and presenting
+//! the one of `--client-cert`. `reqwest` exposes none of the TLS details, so
+//! they are only known where OpenSSL is the TLS backend (not on Windows nor
+//! macOS). HTTP/3 is not supported by the client, so it is never reported.
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::error::VerifierError;
+use crate::output::{Category, EndpointReport};
+use crate::{client, tls};
+
+/// Entity the reports of the protocol checks are grouped under
+pub const ENTITY_NAME: &str = "Protocol";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, clap::ArgEnum)]
+pub enum TlsVersion {
+	#[clap(name = "1.0")]
+	#[serde(rename = "1.0")]
+	Tls10,
+	#[clap(name = "1.1")]
+	#[serde(rename = "1.1")]
+	Tls11,
+	#[clap(name = "1.2")]
+	#[serde(rename = "1.2")]
+	Tls12,
+	#[clap(name = "1.3")]
+	#[serde(rename = "1.3")]
+	Tls13,
+}
+
+impl TlsVersion {
+	/// Parses the names of OpenSSL (e.g. `TLSv1.3`)
+	pub fn parse(name: &str) -> Option<Self> {
+		match name {
+			"TLSv1" | "TLSv1.0" => Some(Self::Tls10),
+			"TLSv1.1" => Some(Self::Tls11),
+			"TLSv1.2" => Some(Self::Tls12),
+			"TLSv1.3" => Some(Self::Tls13),
+			_ => None,
+		}
+	}
+}
+
+impl std::fmt::Display for TlsVersion {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Tls10 => write!(f, "TLS 1.0"),
+			Self::Tls11 => write!(f, "TLS 1.1"),
+			Self::Tls12 => write!(f, "TLS 1.2"),
+			Self::Tls13 => write!(f, "TLS 1.3"),
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, clap::ArgEnum)]
+pub enum HttpVersion {
+	#[clap(name = "1.1")]
+	#[serde(rename = "1.1")]
+	Http11,
+	#[clap(name = "2")]
+	#[serde(rename = "2")]
+	Http2,
+}
+
+impl std::fmt::Display for HttpVersion {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Http11 => write!(f, "HTTP/1.1"),
+			Self::Http2 => write!(f, "HTTP/2"),
+		}
+	}
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Protocol {
+	/// HTTP version of the requests of the verifier (e.g. `HTTP/1.1`)
+	pub http_version: String,
+	/// Application protocol chosen by the server when offered HTTP/2 and HTTP/1.1 (e.g. `h2`)
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub alpn: Option<String>,
+	/// Negotiated TLS version (e.g. `TLSv1.3`)
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub tls_version: Option<String>,
+	/// Negotiated cipher suite (e.g. `TLS_AES_256_GCM_SHA384`)
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub cipher: Option<String>,
+}
+
+/// Settings of the client the negotiation connects with
+pub struct Probe<'a> {
+	/// Proxy of the client, if set explicitly
+	pub proxy: Option<&'a Url>,
+	pub root_certificates: &'a [tls::Certificate],
+	pub identity: Option<&'a tls::Identity>,
+	pub timeout: Duration,
+}
+
+impl Probe<'_> {
+	/// Proxy of the requests to `host`: the one of the client, else the one of the environment
+	fn proxy(&self, host: &str) -> Option<Url> {
+		client::proxy_of(self.proxy, "https", host)
+	}
+}
+
+/// `host:port` of `url`, the key of its protocol in the report
+pub fn host(url: &Url) -> String {
+	format!(
+		"{}:{}",
+		url.host_str().unwrap_or_default(),
+		url.port_or_known_default().unwrap_or_default()
+	)
+}
+
+impl Protocol {
+	/// Negotiates TLS with the host of `url` on a fresh connection, `http_version` being the one of the requests
+	///
+	/// The TLS details that cannot be known (plain http, failed connection or handshake) are left empty.
+	pub fn negotiate(url: &Url, http_version: reqwest::Version, probe: &Probe) -> Self {
+		let mut protocol = Self {
+			http_version: format!("{:?}", http_version),
+			..Self::default()
+		};
+		if let ("https", Some(host), Some(port)) = (url.scheme(), url.host_str(), url.port_or_known_default()) {
+			handshake(&mut protocol, host, port, probe);
+		}
+		protocol
+	}
+
+	/// Best HTTP version the server supports, as far as the verifier can tell
+	pub fn best_http_version(&self) -> Option<HttpVersion> {
+		if self.alpn.as_deref() == Some("h2") || self.http_version == "HTTP/2.0" {
+			Some(HttpVersion::Http2)
+		}
+		else if self.http_version == "HTTP/1.1" {
+			Some(HttpVersion::Http11)
+		}
+		else {
+			None
+		}
+	}
+
+	/// Checks the protocol of `host` against the minimum versions
+	pub fn check(
+		&self,
+		host: &str,
+		url: &Url,
+		min_tls: Option<TlsVersion>,
+		min_http: Option<HttpVersion>,
+	) -> Vec<EndpointReport> {
+		let report = |name: &str, check: &str| {
+			EndpointReport::new(ENTITY_NAME, &format!("{} {}", host, name), url.clone())
+				.category(Category::Framework)
+				.check(check)
+		};
+		let mut reports = Vec::new();
+		if let Some(min_tls) = min_tls {
+			let report = report("TLS version", "framework.protocol.tls-version");
+			reports.push(
+				match self.tls_version.as_deref().map(|name| (name, TlsVersion::parse(name))) {
+					_ if url.scheme() != "https" => {
+						report.error(VerifierError::BadProtocol("not served over TLS".into()))
+					},
+					Some((_, Some(version))) if version >= min_tls => report.ok(None),
+					Some((_, Some(version))) => report.error(VerifierError::BadProtocol(format!(
+						"{} is older than {}",
+						version, min_tls
+					))),
+					Some((name, None)) => {
+						report.null(VerifierError::BadProtocol(format!("unknown TLS version {}", name)))
+					},
+					None => report.null(VerifierError::BadProtocol(
+						"the TLS version could not be negotiated".into(),
+					)),
+				},
+			);
+		}
+		if let Some(min_http) = min_http {
+			let report = report("HTTP version", "framework.protocol.http-version");
+			reports.push(match self.best_http_version() {
+				Some(version) if version >= min_http => report.ok(None),
+				Some(version) => report.error(VerifierError::BadProtocol(format!(
+					"{} is older than {}",
+					version, min_http
+				))),
+				None => report.null(VerifierError::BadProtocol(format!(
+					"unknown HTTP version {}",
+					self.http_version
+				))),
+			});
+		}
+		reports
+	}
+}
+
+#[cfg(not(any(target_os = "windows", target_vendor = "apple")))]
+fn handshake(protocol: &mut Protocol, host: &str, port: u16, probe: &Probe) {
+	let connector = match connector(probe).and_then(|mut connector| {
+		// Length-prefixed protocol names
+		connector.set_alpn_protos(b"\x02h2\x08http/1.1")?;
+		Ok(connector.build())
+	}) {
+		Ok(connector) => connector,
+		Err(e) => {
+			log::debug!("Unable to set up TLS: {}", e);
+			return;
+		},
+	};
+	let stream = match connect(host, port, probe) {
+		Ok(stream) => stream,
+		Err(e) => {
+			log::debug!("Unable to connect to {}: {}", host, e);
+			return;
+		},
+	};
+	match connector.connect(host, stream) {
+		Ok(stream) => {
+			let ssl = stream.ssl();
+			protocol.tls_version = Some(ssl.version_str().to_string());
+			protocol.cipher = ssl.current_cipher().map(|cipher| cipher.name().to_string());
+			protocol.alpn = ssl
+				.selected_alpn_protocol()
+				.map(|alpn| String::from_utf8_lossy(alpn).into_owned());
+		},
+		Err(e) => log::debug!("TLS handshake with {} failed: {}", host, e),
+	}
+}
+
+/// Connection the TLS handshake is made on, direct or through a proxy
+#[cfg(not(any(target_os = "windows", target_vendor = "apple")))]
+trait Stream: std::io::Read + std::io::Write + std::fmt::Debug {}
+
+#[cfg(not(any(target_os = "windows", target_vendor = "apple")))]
+impl<S: std::io::Read + std::io::Write + std::fmt::Debug> Stream for S {}
+
+/// TLS connector trusting the certificates of the client and presenting its own
+#[cfg(not(any(target_os = "windows", target_vendor = "apple")))]
+fn connector(probe: &Probe) -> Result<openssl::ssl::SslConnectorBuilder, openssl::error::ErrorStack> {
+	use openssl::pkcs12::Pkcs12;
+	use openssl::ssl::{SslConnector, SslMethod};
+	use openssl::x509::X509;
+
+	let mut connector = SslConnector::builder(SslMethod::tls())?;
+	for certificate in probe.root_certificates {
+		let x509 = if certificate.pem {
+			X509::from_pem(&certificate.encoded)?
+		}
+		else {
+			X509::from_der(&certificate.encoded)?
+		};
+		connector.cert_store_mut().add_cert(x509)?;
+	}
+	if let Some(identity) = probe.identity {
+		let parsed = Pkcs12::from_der(&identity.pkcs12)?.parse(&identity.password)?;
+		connector.set_certificate(&parsed.cert)?;
+		connector.set_private_key(&parsed.pkey)?;
+		for chain_cert in parsed.chain.into_iter().flatten() {
+			connector.add_extra_chain_cert(chain_cert)?;
+		}
+	}
+	Ok(connector)
+}
+
+/// Connects to `host`, tunnelling through the proxy of the requests (over TLS to an https one) if any
+#[cfg(not(any(target_os = "windows", target_vendor = "apple")))]
+fn connect(host: &str, port: u16, probe: &Probe) -> Result<Box<dyn Stream>, String> {
+	let proxy = probe.proxy(host);
+	let (connect_host, connect_port) = match &proxy {
+		Some(proxy) => (
+			proxy.host_str().ok_or("proxy without a host")?,
+			proxy.port_or_known_default().ok_or("proxy without a port")?,
+		),
+		None => (host, port),
+	};
+	let stream = tcp(connect_host, connect_port, probe.timeout)?;
+	let proxy = match &proxy {
+		Some(proxy) => proxy,
+		None => return Ok(Box::new(stream)),
+	};
+	log::debug!("Connecting to {} through the proxy {}", host, connect_host);
+	match proxy.scheme() {
+		"https" => {
+			let connector = connector(probe).map_err(|e| e.to_string())?.build();
+			let stream = connector
+				.connect(connect_host, stream)
+				.map_err(|e| format!("TLS handshake with the proxy failed: {}", e))?;
+			Ok(Box::new(tunnel(stream, proxy, host, port)?))
+		},
+		"socks5" | "socks5h" => Ok(Box::new(socks5(stream, proxy, host, port)?)),
+		_ => Ok(Box::new(tunnel(stream, proxy, host, port)?)),
+	}
+}
+
+#[cfg(not(any(target_os = "windows", target_vendor = "apple")))]
+fn tcp(host: &str, port: u16, timeout: Duration) -> Result<std::net::TcpStream, String> {
+	use std::net::{TcpStream, ToSocketAddrs};
+
+	let addr = (host, port)
+		.to_socket_addrs()
+		.map_err(|e| e.to_string())?
+		.next()
+		.ok_or_else(|| format!("{} does not resolve", host))?;
+	let stream = TcpStream::connect_timeout(&addr, timeout).map_err(|e| e.to_string())?;
+	let _ = stream.set_read_timeout(Some(timeout));
+	let _ = stream.set_write_timeout(Some(timeout));
+	Ok(stream)
+}
+
+/// Opens a tunnel to `host` with a `CONNECT` request to `proxy`, with its credentials if any
+#[cfg(not(any(target_os = "windows", target_vendor = "apple")))]
+fn tunnel<S: Stream>(mut stream: S, proxy: &Url, host: &str, port: u16) -> Result<S, String> {
+	let mut request = format!("CONNECT {0}:{1} HTTP/1.1\r\nHost: {0}:{1}\r\n", host, port);
+	if !proxy.username().is_empty() {
+		let decode = |part: &str| {
+			percent_encoding::percent_decode_str(part)
+				.decode_utf8_lossy()
+				.into_owned()
+		};
+		let credentials = format!(
+			"{}:{}",
+			decode(proxy.username()),
+			decode(proxy.password().unwrap_or_default())
+		);
+		request.push_str(&format!(
+			"Proxy-Authorization: Basic {}\r\n",
+			openssl::base64::encode_block(credentials.as_bytes())
+		));
+	}
+	request.push_str("\r\n");
+	stream.write_all(request.as_bytes()).map_err(|e| e.to_string())?;
+	// Byte by byte, not to read the start of the handshake past the head of the response
+	let mut head = Vec::new();
+	let mut byte = [0];
+	while !head.ends_with(b"\r\n\r\n") {
+		match stream.read(&mut byte) {
+			Ok(0) => return Err("the proxy closed the connection".into()),
+			Ok(_) if head.len() < 8192 => head.push(byte[0]),
+			Ok(_) => return Err("the head of the response of the proxy is too long".into()),
+			Err(e) => return Err(e.to_string()),
+		}
+	}
+	let head = String::from_utf8_lossy(&head);
+	let status_line = head.lines().next().unwrap_or_default();
+	match status_line.split_whitespace().nth(1) {
+		Some(status) if status.starts_with('2') => Ok(stream),
+		_ => Err(format!("the proxy refused the tunnel: {}", status_line)),
+	}
+}
+
+/// Opens a tunnel to `host` through the SOCKS5 `proxy`, with its credentials if any, the name of `host` being
+/// resolved by the proxy with `socks5h` and by the verifier with `socks5`
+#[cfg(not(any(target_os = "windows", target_vendor = "apple")))]
+fn socks5<S: Stream>(mut stream: S, proxy: &Url, host: &str, port: u16) -> Result<S, String> {
+	use std::net::{IpAddr, ToSocketAddrs};
+
+	// Length-prefixed
+	let field = |value: &[u8]| -> Result<Vec<u8>, String> {
+		let length = u8::try_from(value.len()).map_err(|_| "a field of the SOCKS request is too long".to_string())?;
+		Ok([&[length], value].concat())
+	};
+	let decode = |part: &str| {
+		percent_encoding::percent_decode_str(part)
+			.decode_utf8_lossy()
+			.into_owned()
+	};
+	let authenticated = !proxy.username().is_empty();
+	// No authentication, or a username and a password
+	let method = if authenticated { 2 } else { 0 };
+	stream.write_all(&[5, 1, method]).map_err(|e| e.to_string())?;
+	let mut reply = [0; 2];
+	stream.read_exact(&mut reply).map_err(|e| e.to_string())?;
+	if reply != [5, method] {
+		return Err("the SOCKS proxy refused the authentication method".into());
+	}
+	if authenticated {
+		let mut request = vec![1];
+		request.extend(field(decode(proxy.username()).as_bytes())?);
+		request.extend(field(decode(proxy.password().unwrap_or_default()).as_bytes())?);
+		stream.write_all(&request).map_err(|e| e.to_string())?;
+		stream.read_exact(&mut reply).map_err(|e| e.to_string())?;
+		if reply[1] != 0 {
+			return Err("the SOCKS proxy refused the credentials".into());
+		}
+	}
+	let mut request = vec![5, 1, 0];
+	if proxy.scheme() == "socks5h" {
+		request.push(3);
+		request.extend(field(host.as_bytes())?);
+	}
+	else {
+		let addr = (host, port)
+			.to_socket_addrs()
+			.map_err(|e| e.to_string())?
+			.next()
+			.ok_or_else(|| format!("{} does not resolve", host))?;
+		match addr.ip() {
+			IpAddr::V4(ip) => request.extend([&[1], &ip.octets()[..]].concat()),
+			IpAddr::V6(ip) => request.extend([&[4], &ip.octets()[..]].concat()),
+		}
+	}
+	request.extend(port.to_be_bytes());
+	stream.write_all(&request).map_err(|e| e.to_string())?;
+	let mut head = [0; 4];
+	stream.read_exact(&mut head).map_err(|e| e.to_string())?;
+	if head[1] != 0 {
+		return Err(format!("the SOCKS proxy refused the tunnel (reply {})", head[1]));
+	}
+	// Address the proxy connected from, and its port
+	let length = match head[3] {
+		1 => 4,
+		4 => 16,
+		3 => {
+			let mut length = [0];
+			stream.read_exact(&mut length).map_err(|e| e.to_string())?;
+			usize::from(length[0])
+		},
+		kind => {
+			return Err(format!(
+				"unknown kind of address {} in the reply of the SOCKS proxy",
+				kind
+			))
+		},
+	};
+	stream.read_exact(&mut vec![0; length + 2]).map_err(|e| e.to_string())?;
+	Ok(stream)
+}
+
+#[cfg(any(target_os = "windows", target_vendor = "apple"))]
+fn handshake(_protocol: &mut Protocol, host: &str, _port: u16, _probe: &Probe) {
+	log::debug!("The TLS details of {} are only known with OpenSSL", host);
+}
+
+#[cfg(test)]
+mod tests {
+
+	use url::Url;
+
+	use crate::client::Client;
+	use crate::protocol::{self, HttpVersion, Protocol, TlsVersion, ENTITY_NAME};
+	use crate::testing::{Behavior, MockBeacon};
+
+	#[test]
+	fn test_versions() {
+		assert_eq!(TlsVersion::parse("TLSv1.3"), Some(TlsVersion::Tls13));
+		assert!(TlsVersion::parse("TLSv1.1") < Some(TlsVersion::Tls12));
+		let protocol = Protocol {
+			http_version: "HTTP/1.1".into(),
+			alpn: Some("h2".into()),
+			..Protocol::default()
+		};
+		assert_eq!(protocol.best_http_version(), Some(HttpVersion::Http2));
+	}
+
+	#[test]
+	fn test_protocol() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let output = beacon.verify();
+		let protocol = &output.protocols[&protocol::host(&beacon.url())];
+		assert_eq!(protocol.http_version, "HTTP/1.1");
+		// The mock beacon is served over plain http
+		assert!(protocol.tls_version.is_none());
+		assert!(!output.entities.contains_key(ENTITY_NAME));
+
+		let output = beacon
+			.beacon()
+			.min_tls_version(TlsVersion::Tls12)
+			.min_http_version(HttpVersion::Http11)
+			.validate();
+		let reports = &output.entities[ENTITY_NAME];
+		assert_eq!(reports.len(), 2);
+		// Sorted by name
+		assert_eq!(reports[0].valid, Some(true));
+		assert_eq!(reports[1].error.as_deref(), Some("Bad protocol: not served over TLS"));
+		let output = beacon.beacon().min_http_version(HttpVersion::Http2).validate();
+		let e = output.entities[ENTITY_NAME][0].error.clone().unwrap();
+		assert_eq!(e, "Bad protocol: HTTP/1.1 is older than HTTP/2");
+	}
+
+	#[test]
+	fn test_every_host() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let other = MockBeacon::start(Behavior::Conformant);
+		let client = Client::new();
+		let info = |beacon: &MockBeacon| Url::parse(&format!("{}/info", beacon.url().as_str().trim_end_matches('/')));
+		client.query("Info", &info(&beacon).unwrap()).unwrap();
+		// Recorded by the forks too
+		client.fork().query("Info", &info(&other).unwrap()).unwrap();
+		let protocols = client.protocols();
+		assert_eq!(protocols.len(), 2);
+		assert!(protocols.contains_key(&protocol::host(&other.url())));
+	}
+
+	#[cfg(not(any(target_os = "windows", target_vendor = "apple")))]
+	#[test]
+	fn test_socks5() {
+		use std::io::{Read, Write};
+		use std::net::TcpListener;
+
+		// Proxy asking for credentials, then connecting to the name it is given
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let proxy = Url::parse(&format!("socks5h://user:s%40cret@{}", listener.local_addr().unwrap())).unwrap();
+		let server = std::thread::spawn(move || {
+			let (mut stream, _) = listener.accept().unwrap();
+			let mut greeting = [0; 3];
+			stream.read_exact(&mut greeting).unwrap();
+			assert_eq!(greeting, [5, 1, 2]);
+			stream.write_all(&[5, 2]).unwrap();
+			let mut credentials = [0; 13];
+			stream.read_exact(&mut credentials).unwrap();
+			assert_eq!(&credentials, b"\x01\x04user\x06s@cret");
+			stream.write_all(&[1, 0]).unwrap();
+			let mut request = vec![0; 5 + "beacon.invalid".len() + 2];
+			stream.read_exact(&mut request).unwrap();
+			assert_eq!(&request[..5], &[5, 1, 0, 3, 14]);
+			assert_eq!(&request[5..19], b"beacon.invalid");
+			assert_eq!(&request[19..], &443u16.to_be_bytes());
+			stream.write_all(&[5, 0, 0, 1, 127, 0, 0, 1, 0, 80]).unwrap();
+			stream.write_all(b"tunnelled").unwrap();
+		});
+		let stream = std::net::TcpStream::connect(proxy.socket_addrs(|| None).unwrap()[0]).unwrap();
+		let mut stream = protocol::socks5(stream, &proxy, "beacon.invalid", 443).unwrap();
+		let mut tunnelled = String::new();
+		stream.read_to_string(&mut tunnelled).unwrap();
+		assert_eq!(tunnelled, "tunnelled");
+		server.join().unwrap();
+	}
+}
@@ -0,0 +1,201 @@
+//! References between the entities, followed to the records they name
+//!
+//! The records reference the records of other entities by their id, in a
+//! property named after the entry type (e.g. the `individualId` of a biosample,
+//! the `runId` of an analysis). The id consistency only compares the format of
+//! those ids. With `--references`, the verifier follows them: the first records
+//! of each entity are read, and the single entry url of the referenced entity
+//! is queried with each referenced id (a few of each property), which must
+//! return that record, valid against the schema of its entity. A dangling
+//! reference (not found, or another record returned) fails the check.
+
+use std::collections::BTreeSet;
+
+use url::Url;
+
+use crate::client::Client;
+use crate::error::VerifierError;
+use crate::model::Entity;
+use crate::output::{Category, EndpointReport};
+use crate::{utils, Json};
+
+/// Name of the check, after the name of the entity
+pub const CHECK_NAME: &str = "references";
+
+/// Referenced ids followed at most for each property
+const MAX_IDS: usize = 3;
+
+/// Outcome of following a reference
+enum Followed {
+	/// The referenced record was returned, and is valid
+	Resolved,
+	/// Why the reference is dangling, or the referenced record is not valid
+	Broken(String),
+	/// Why the referenced record could not be checked (e.g. the beacon only answers with counts)
+	Unverified(String),
+}
+
+/// Follows the references of the first records of `entity` to the records of the other entities of `entities`
+pub fn check(client: &Client, root_url: &Url, entity: &Entity, entities: &[Entity]) -> EndpointReport {
+	let url = utils::url_join(root_url, &entity.url);
+	let report = EndpointReport::new(&entity.name, &format!("{} {}", entity.name, CHECK_NAME), url.clone())
+		.category(Category::Model)
+		.check("model.references");
+	let records = match client
+		.query(&entity.name, &url)
+		.and_then(|response| utils::records(&response.json))
+	{
+		Ok(records) if records.is_empty() => {
+			return report.skip("no records to follow the references of", Json::Null);
+		},
+		Ok(records) => records,
+		Err(e) => return report.null(e),
+	};
+
+	let mut followed = Vec::new();
+	for referenced in entities
+		.iter()
+		.filter(|referenced| referenced.entry_type != entity.entry_type)
+	{
+		let property = format!("{}Id", referenced.entry_type);
+		let ids = records
+			.iter()
+			.filter_map(|record| record.get(&property).and_then(Json::as_str))
+			.collect::<BTreeSet<_>>();
+		for id in ids.into_iter().take(MAX_IDS) {
+			followed.push(follow(client, root_url, referenced, &property, id));
+		}
+	}
+
+	let broken = followed
+		.iter()
+		.filter_map(|followed| match followed {
+			Followed::Broken(problem) => Some(problem.as_str()),
+			_ => None,
+		})
+		.collect::<Vec<_>>();
+	let unverified = followed
+		.iter()
+		.filter_map(|followed| match followed {
+			Followed::Unverified(reason) => Some(reason.as_str()),
+			_ => None,
+		})
+		.collect::<BTreeSet<_>>();
+	if !broken.is_empty() {
+		report.error(VerifierError::BrokenReferences(broken.join("\n")))
+	}
+	else if followed.is_empty() {
+		report.skip("no references to other entities in the records", Json::Null)
+	}
+	else if unverified.len() == followed.len() {
+		let reasons = unverified.into_iter().collect::<Vec<_>>().join(", ");
+		report.skip(&reasons, Json::Null)
+	}
+	else {
+		report.ok(None)
+	}
+}
+
+/// Queries the single entry url of `referenced` with the id `id`, read from the property `property`
+fn follow(client: &Client, root_url: &Url, referenced: &Entity, property: &str, id: &str) -> Followed {
+	let template = match &referenced.url_single {
+		Some(url_single) => utils::url_join(root_url, url_single),
+		None => return Followed::Unverified(format!("the {} entity has no single entry url", referenced.name)),
+	};
+	let vars = utils::template_vars(&template);
+	let url = utils::replace_vars(&template, vars.iter().map(|var| (var.as_str(), id)).collect());
+	let not_found = || Followed::Broken(format!("{} '{}' is not found at {}", property, id, url));
+	let records = match client.query(&referenced.name, &url) {
+		Ok(response) if utils::has_no_data(&response.json) => return not_found(),
+		Ok(response) => match utils::records(&response.json) {
+			Ok(records) => records,
+			Err(e) => return Followed::Unverified(e.to_string()),
+		},
+		Err(VerifierError::MissingEndpoint(_)) => return not_found(),
+		Err(e) => return Followed::Unverified(e.to_string()),
+	};
+	let record = match records
+		.iter()
+		.find(|record| utils::record_id(record, &referenced.id_field).as_deref() == Some(id))
+	{
+		Some(record) => record,
+		// e.g. at boolean or count granularity
+		None if records.is_empty() => {
+			return Followed::Unverified(format!("the records of {} are not returned", referenced.name));
+		},
+		None => return Followed::Broken(format!("{} '{}' returns other records at {}", property, id, url)),
+	};
+	let schema = match referenced.schemas.first() {
+		Some(schema) => schema,
+		None => return Followed::Resolved,
+	};
+	let errors = utils::schema_errors(&schema.schema, record, "");
+	if errors.is_empty() {
+		Followed::Resolved
+	}
+	else {
+		Followed::Broken(format!(
+			"the {} '{}' of {} does not match the schema: {}",
+			referenced.name,
+			id,
+			property,
+			errors.to_string().lines().collect::<Vec<_>>().join("; ")
+		))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use serde_json::json;
+	use wiremock::ResponseTemplate;
+
+	use crate::output::{BeaconOutput, Category, EndpointOutput};
+	use crate::references::CHECK_NAME;
+	use crate::testing::{Behavior, MockBeacon};
+
+	fn references<'a>(output: &'a BeaconOutput, entity: &str) -> &'a EndpointOutput {
+		output.entities[entity]
+			.iter()
+			.find(|report| report.name == format!("{} {}", entity, CHECK_NAME))
+			.unwrap()
+	}
+
+	#[test]
+	fn test_references() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let output = beacon.beacon().references().validate();
+		let biosample = references(&output, "Biosample");
+		assert_eq!(biosample.category, Category::Model);
+		assert_eq!(biosample.valid, Some(true), "{:?}", biosample.error);
+		assert!(references(&output, "Individual").skipped);
+		assert!(!beacon.verify().entities["Biosample"]
+			.iter()
+			.any(|report| report.name.ends_with(CHECK_NAME)));
+
+		// A dangling reference, and a referenced record that is not valid
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let mut biosamples = beacon.response("/biosamples");
+		biosamples["response"]["resultSets"][0]["results"][1]["individualId"] = json!("ind9");
+		beacon.mount("/biosamples", ResponseTemplate::new(200).set_body_json(biosamples));
+		let mut individual = beacon.response("/individuals/ind1");
+		individual["response"]["resultSets"][0]["results"][0]
+			.as_object_mut()
+			.unwrap()
+			.remove("sex");
+		beacon.mount(
+			"/individuals/ind1",
+			ResponseTemplate::new(200).set_body_json(individual),
+		);
+		let output = beacon.beacon().references().validate();
+		let biosample = references(&output, "Biosample");
+		assert_eq!(biosample.valid, Some(false));
+		let error = biosample.error.as_deref().unwrap();
+		assert!(error.contains("individualId 'ind9' is not found at "), "{}", error);
+		assert!(
+			error.contains("the Individual 'ind1' of individualId does not match the schema: "),
+			"{}",
+			error
+		);
+	}
+}
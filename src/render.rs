@@ -0,0 +1,317 @@
+//! Renderers of the output
+//!
+//! A renderer turns the typed report of a run into the bytes of a document: the
+//! full JSON report, the support matrix of the Beacon network websites, the
+//! OpenMetrics text, the JUnit XML report of the CI servers, the SARIF log of
+//! the code scanning dashboards or the HTML conformance report handed to the
+//! implementers. The renderers are registered by name, the one that
+//! `--output-format` selects, so adding a format is implementing [`Renderer`]
+//! and registering it in [`Renderers::builtin`], without touching the rest of
+//! the verifier. The JSON formats also render the members of a network side by
+//! side; the others hold a single beacon.
+
+use crate::error::VerifierError;
+use crate::humanize::TimeStyle;
+use crate::network::{NetworkEntry, NetworkReport};
+use crate::output::BeaconOutput;
+use crate::{canonical, html, junit, metrics, sarif};
+
+/// Name of the renderer of the full report, the default one
+pub const DEFAULT: &str = "verifier";
+
+pub trait Renderer {
+	/// Name selecting the renderer with `--output-format` (e.g. `verifier`)
+	fn name(&self) -> &'static str;
+
+	/// Renders the whole document, ending with a newline if it is text
+	fn render(&self, output: &BeaconOutput) -> Result<Vec<u8>, VerifierError>;
+
+	/// Extension of the files of the document, in the output directory
+	fn extension(&self) -> &'static str {
+		"txt"
+	}
+
+	/// Whether the document can hold the members of a network
+	fn renders_network(&self) -> bool {
+		false
+	}
+
+	/// Renders the members of a network side by side, if the document can hold them
+	fn render_network(&self, _report: &NetworkReport) -> Result<Vec<u8>, VerifierError> {
+		Err(VerifierError::SingleBeaconFormat(self.name().to_string()))
+	}
+}
+
+/// Pretty-printed JSON of `value` ending with a newline, in the canonical form if `canonical`
+fn json(value: &impl serde::Serialize, canonical: bool) -> Result<Vec<u8>, VerifierError> {
+	let json = if canonical {
+		canonical::to_json(value)?
+	}
+	else {
+		serde_json::to_string_pretty(value)?
+	};
+	Ok(format!("{}\n", json).into_bytes())
+}
+
+/// Full report of the checks
+pub struct VerifierReport {
+	pub canonical: bool,
+}
+
+impl Renderer for VerifierReport {
+	fn name(&self) -> &'static str {
+		DEFAULT
+	}
+
+	fn extension(&self) -> &'static str {
+		"json"
+	}
+
+	fn render(&self, output: &BeaconOutput) -> Result<Vec<u8>, VerifierError> {
+		let json = if self.canonical {
+			output.to_canonical_json()?
+		}
+		else {
+			output.to_json()?
+		};
+		Ok(format!("{}\n", json).into_bytes())
+	}
+
+	fn renders_network(&self) -> bool {
+		true
+	}
+
+	fn render_network(&self, report: &NetworkReport) -> Result<Vec<u8>, VerifierError> {
+		if self.canonical {
+			let mut report = report.clone();
+			report.reports = report.reports.iter().map(BeaconOutput::sorted).collect();
+			json(&report, true)
+		}
+		else {
+			json(report, false)
+		}
+	}
+}
+
+/// Support matrix consumed by the Beacon network websites
+pub struct NetworkMatrix {
+	pub canonical: bool,
+}
+
+impl Renderer for NetworkMatrix {
+	fn name(&self) -> &'static str {
+		"network"
+	}
+
+	fn extension(&self) -> &'static str {
+		"json"
+	}
+
+	fn render(&self, output: &BeaconOutput) -> Result<Vec<u8>, VerifierError> {
+		json(&NetworkEntry::from(output), self.canonical)
+	}
+
+	fn renders_network(&self) -> bool {
+		true
+	}
+
+	/// Entries of the members, in their order
+	fn render_network(&self, report: &NetworkReport) -> Result<Vec<u8>, VerifierError> {
+		json(&report.members, self.canonical)
+	}
+}
+
+/// Final metrics of the run, for Prometheus
+pub struct OpenMetrics;
+
+impl Renderer for OpenMetrics {
+	fn name(&self) -> &'static str {
+		"openmetrics"
+	}
+
+	fn render(&self, output: &BeaconOutput) -> Result<Vec<u8>, VerifierError> {
+		Ok(metrics::openmetrics(output).into_bytes())
+	}
+}
+
+/// Checks as the test cases of a JUnit XML report, for the CI servers
+pub struct JUnitReport;
+
+impl Renderer for JUnitReport {
+	fn name(&self) -> &'static str {
+		"junit"
+	}
+
+	fn extension(&self) -> &'static str {
+		"xml"
+	}
+
+	fn render(&self, output: &BeaconOutput) -> Result<Vec<u8>, VerifierError> {
+		Ok(junit::junit(output).into_bytes())
+	}
+}
+
+/// Failed checks as the results of a SARIF log, for the code scanning dashboards
+pub struct SarifReport {
+	pub canonical: bool,
+}
+
+impl Renderer for SarifReport {
+	fn name(&self) -> &'static str {
+		"sarif"
+	}
+
+	fn extension(&self) -> &'static str {
+		"sarif"
+	}
+
+	fn render(&self, output: &BeaconOutput) -> Result<Vec<u8>, VerifierError> {
+		json(&sarif::sarif(output), self.canonical)
+	}
+
+	fn renders_network(&self) -> bool {
+		true
+	}
+
+	/// Log with a run per member
+	fn render_network(&self, report: &NetworkReport) -> Result<Vec<u8>, VerifierError> {
+		json(&sarif::network(&report.reports), self.canonical)
+	}
+}
+
+/// Self-contained HTML page of the run, for the implementers of the beacon
+pub struct HtmlReport {
+	pub time_style: TimeStyle,
+}
+
+impl Renderer for HtmlReport {
+	fn name(&self) -> &'static str {
+		"html"
+	}
+
+	fn extension(&self) -> &'static str {
+		"html"
+	}
+
+	fn render(&self, output: &BeaconOutput) -> Result<Vec<u8>, VerifierError> {
+		Ok(html::html(output, self.time_style).into_bytes())
+	}
+}
+
+/// Renderers by name
+#[derive(Default)]
+pub struct Renderers {
+	renderers: Vec<Box<dyn Renderer>>,
+}
+
+impl Renderers {
+	/// Built-in renderers, the JSON ones printing the canonical form if `canonical`, the HTML one the times in
+	/// `time_style`
+	pub fn builtin(canonical: bool, time_style: TimeStyle) -> Self {
+		Self::default()
+			.register(VerifierReport { canonical })
+			.register(NetworkMatrix { canonical })
+			.register(OpenMetrics)
+			.register(JUnitReport)
+			.register(SarifReport { canonical })
+			.register(HtmlReport { time_style })
+	}
+
+	/// Adds `renderer`, replacing the one with the same name
+	pub fn register(mut self, renderer: impl Renderer + 'static) -> Self {
+		self.renderers.retain(|registered| registered.name() != renderer.name());
+		self.renderers.push(Box::new(renderer));
+		self
+	}
+
+	pub fn get(&self, name: &str) -> Option<&dyn Renderer> {
+		self.renderers
+			.iter()
+			.find(|renderer| renderer.name() == name)
+			.map(|renderer| renderer.as_ref())
+	}
+
+	pub fn names(&self) -> Vec<&'static str> {
+		self.renderers.iter().map(|renderer| renderer.name()).collect()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use crate::error::VerifierError;
+	use crate::humanize::TimeStyle;
+	use crate::network::NetworkReport;
+	use crate::output::BeaconOutput;
+	use crate::render::{Renderer, Renderers, DEFAULT};
+	use crate::testing::{Behavior, MockBeacon};
+
+	/// Line per entity, as a downstream format would
+	struct Tickets;
+
+	impl Renderer for Tickets {
+		fn name(&self) -> &'static str {
+			"tickets"
+		}
+
+		fn render(&self, output: &BeaconOutput) -> Result<Vec<u8>, VerifierError> {
+			let lines = output
+				.entities
+				.iter()
+				.map(|(entity_name, reports)| format!("{}: {} checks\n", entity_name, reports.len()))
+				.collect::<String>();
+			Ok(lines.into_bytes())
+		}
+	}
+
+	#[test]
+	fn test_renderers() {
+		let output = MockBeacon::start(Behavior::Conformant).verify();
+		let renderers = Renderers::builtin(false, TimeStyle::default());
+		assert_eq!(
+			renderers.names(),
+			vec![DEFAULT, "network", "openmetrics", "junit", "sarif", "html"]
+		);
+		assert!(renderers.get("tickets").is_none());
+
+		let report = renderers.get(DEFAULT).unwrap().render(&output).unwrap();
+		let report = String::from_utf8(report).unwrap();
+		assert_eq!(report, format!("{}\n", output.to_json().unwrap()));
+		let metrics = renderers.get("openmetrics").unwrap().render(&output).unwrap();
+		assert!(String::from_utf8(metrics).unwrap().ends_with("# EOF\n"));
+		assert_eq!(renderers.get("html").unwrap().extension(), "html");
+
+		let renderers = renderers.register(Tickets);
+		let tickets = renderers.get("tickets").unwrap().render(&output).unwrap();
+		assert!(String::from_utf8(tickets).unwrap().contains("Individual: "));
+		assert_eq!(renderers.names().len(), 7);
+		assert_eq!(renderers.get("tickets").unwrap().extension(), "txt");
+	}
+
+	#[test]
+	fn test_network_renderers() {
+		let outputs = vec![
+			MockBeacon::start(Behavior::Conformant).verify(),
+			MockBeacon::start(Behavior::Conformant).verify(),
+		];
+		let report = NetworkReport::new(outputs);
+		let renderers = Renderers::builtin(true, TimeStyle::default());
+
+		let full = renderers.get(DEFAULT).unwrap().render_network(&report).unwrap();
+		let full = serde_json::from_slice::<serde_json::Value>(&full).unwrap();
+		assert_eq!(full["reports"].as_array().unwrap().len(), 2);
+		let members = renderers.get("network").unwrap().render_network(&report).unwrap();
+		let members = serde_json::from_slice::<serde_json::Value>(&members).unwrap();
+		assert_eq!(members.as_array().unwrap().len(), 2);
+		let log = renderers.get("sarif").unwrap().render_network(&report).unwrap();
+		let log = serde_json::from_slice::<serde_json::Value>(&log).unwrap();
+		assert_eq!(log["runs"].as_array().unwrap().len(), 2);
+
+		for name in ["openmetrics", "junit", "html"] {
+			let renderer = renderers.get(name).unwrap();
+			assert!(!renderer.renders_network());
+			let e = renderer.render_network(&report).unwrap_err();
+			assert!(matches!(e, VerifierError::SingleBeaconFormat(_)), "{}", e);
+		}
+	}
+}
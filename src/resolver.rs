@@ -0,0 +1,383 @@
+//! Resolution of the references between the schemas of the specification
+//!
+//! The schemas of the framework and of the model reference each other with
+//! relative paths (e.g. `../common/ontologyTerm.json`) and with the urls of
+//! their published copies (e.g. on raw.githubusercontent.com). Every document
+//! of a repository is given a base uri under [`SCHEME`] after its path in the
+//! repository, so its relative references name other documents of the
+//! repository, which are read from memory. The remote references to the copy
+//! of the repository published on raw.githubusercontent.com (at any branch or
+//! tag, under the directory the schemas were read from) are mapped to its
+//! documents too. The other ones are fetched once (through the proxy of the
+//! cache, if any) and kept for the next schemas and, with `--schema-cache`, in
+//! the cache, where `cache-schemas` stores all of them for `--offline`.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use jsonschema::{SchemaResolver, SchemaResolverError};
+use url::Url;
+
+use crate::cache::SchemaCache;
+use crate::error::VerifierError;
+use crate::{artifacts, Json};
+
+/// Scheme of the base uris of the documents of a repository
+pub const SCHEME: &str = "beacon-schema";
+
+/// Time to fetch a remote schema
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Host of the published copies of the repositories hosted on github.com
+const PUBLISHED_HOST: &str = "raw.githubusercontent.com";
+
+/// Where the documents of a repository are published: `https://raw.githubusercontent.com/<owner>/<repo>/<ref>/<dir>`
+#[derive(Debug)]
+struct Published {
+	owner: String,
+	repo: String,
+	/// Directory of the documents in the repository
+	dir: Vec<String>,
+}
+
+impl Published {
+	/// Published copy of the schemas read from `location`, if it is a directory of a repository of github.com
+	fn of(location: &Url) -> Option<Self> {
+		if location.domain() != Some("github.com") {
+			return None;
+		}
+		let mut segments = location.path_segments()?.filter(|segment| !segment.is_empty());
+		Some(Self {
+			owner: segments.next()?.to_string(),
+			repo: segments.next()?.to_string(),
+			dir: segments.map(String::from).collect(),
+		})
+	}
+
+	/// Path in the repository of the document published at `url`
+	fn path(&self, url: &Url) -> Option<String> {
+		if url.domain() != Some(PUBLISHED_HOST) {
+			return None;
+		}
+		let mut segments = url.path_segments()?;
+		if segments.next()? != self.owner || segments.next()? != self.repo {
+			return None;
+		}
+		// Any branch or tag
+		segments.next()?;
+		for dir in &self.dir {
+			if segments.next()? != dir {
+				return None;
+			}
+		}
+		Some(segments.collect::<Vec<_>>().join("/"))
+	}
+}
+
+#[derive(Debug, Default)]
+struct Documents {
+	/// Documents of the repository, by their path in it
+	local: BTreeMap<String, Arc<Json>>,
+	/// Where the documents of the repository are published, if known
+	published: Option<Published>,
+	/// Remote documents already fetched, by url
+	remote: RwLock<BTreeMap<Url, Arc<Json>>>,
+	cache: SchemaCache,
+}
+
+/// Documents the references of the schemas are resolved to, shared by the schemas compiled with it
+#[derive(Debug, Clone, Default)]
+pub struct SchemaStore {
+	documents: Arc<Documents>,
+}
+
+/// Path of `path` under `root`, with forward slashes
+fn relative(root: &Path, path: &Path) -> Option<String> {
+	let components = path
+		.strip_prefix(root)
+		.ok()?
+		.components()
+		.map(|component| component.as_os_str().to_string_lossy().to_string())
+		.collect::<Vec<_>>();
+	Some(components.join("/"))
+}
+
+/// Gives the document the base uri of its path in the repository, unless it has an id of its own
+pub fn with_base(mut json: Json, path: &str) -> Json {
+	let draft4 = json
+		.get("$schema")
+		.and_then(Json::as_str)
+		.map_or(false, |schema| schema.contains("draft-04"));
+	let key = if draft4 { "id" } else { "$id" };
+	if let Some(document) = json.as_object_mut() {
+		if !document.contains_key(key) {
+			document.insert(key.into(), Json::String(format!("{}:///{}", SCHEME, path)));
+		}
+	}
+	json
+}
+
+impl SchemaStore {
+	/// Store of the documents of the repository read from `location` and extracted at `root`, giving them their base
+	/// uri, fetching the remote ones the way of `cache` (offline, through a proxy or from its directory)
+	pub fn new(root: &Path, files: &mut BTreeMap<PathBuf, Json>, location: &Url, cache: &SchemaCache) -> Self {
+		let mut local = BTreeMap::new();
+		for (path, json) in files.iter_mut() {
+			if let Some(relative) = relative(root, path) {
+				*json = with_base(json.take(), &relative);
+				local.insert(relative, Arc::new(json.clone()));
+			}
+		}
+		Self {
+			documents: Arc::new(Documents {
+				local,
+				published: Published::of(location),
+				remote: RwLock::default(),
+				cache: cache.clone(),
+			}),
+		}
+	}
+
+	/// Document of the repository `url` is the published copy of
+	fn local_copy(&self, url: &Url) -> Option<Arc<Json>> {
+		let path = self.documents.published.as_ref()?.path(url)?;
+		self.documents.local.get(&path).cloned()
+	}
+
+	fn fetch(&self, url: &Url) -> Result<Arc<Json>, VerifierError> {
+		if let Some(document) = self
+			.documents
+			.remote
+			.read()
+			.ok()
+			.and_then(|remote| remote.get(url).cloned())
+		{
+			return Ok(document);
+		}
+		let cached = match url.scheme() {
+			"http" | "https" => self.documents.cache.document_path(url),
+			_ => None,
+		};
+		let document = match (url.scheme(), &cached) {
+			(_, Some(cached)) if cached.is_file() => {
+				log::debug!("Reading the referenced schema {} from {:?}", url, cached);
+				serde_json::from_reader(std::fs::File::open(cached)?)?
+			},
+			("file", _) => {
+				let path = url
+					.to_file_path()
+					.map_err(|()| VerifierError::BadSchemaLocation(format!("{} is not a path", url)))?;
+				serde_json::from_reader(std::fs::File::open(path)?)?
+			},
+			("http" | "https", _) if self.documents.cache.is_offline() => {
+				return Err(VerifierError::NotCached(url.to_string()))
+			},
+			("http" | "https", _) => {
+				log::debug!("Fetching the referenced schema {}", url);
+				let mut builder = reqwest::blocking::Client::builder().timeout(TIMEOUT);
+				if let Some(proxy) = self.documents.cache.proxy_url() {
+					builder = builder.proxy(reqwest::Proxy::all(proxy.clone())?);
+				}
+				let http = builder.build()?;
+				let document = http.get(url.clone()).send()?.error_for_status()?.json()?;
+				if let Some(cached) = &cached {
+					if let Some(parent) = cached.parent() {
+						std::fs::create_dir_all(parent)?;
+					}
+					artifacts::write_atomic(cached, &serde_json::to_vec_pretty(&document)?)?;
+					log::debug!("Cached {} in {:?}", url, cached);
+				}
+				document
+			},
+			_ => return Err(VerifierError::BadSchemaLocation(format!("{} cannot be resolved", url))),
+		};
+		let document = Arc::new(document);
+		if let Ok(mut remote) = self.documents.remote.write() {
+			remote.insert(url.clone(), Arc::clone(&document));
+		}
+		Ok(document)
+	}
+
+	pub fn resolve_url(&self, url: &Url) -> Result<Arc<Json>, VerifierError> {
+		if url.scheme() == SCHEME {
+			let path = url.path().trim_start_matches('/');
+			return self.documents.local.get(path).cloned().ok_or_else(|| {
+				VerifierError::BadSchemaLocation(format!("{} is not a document of the repository", path))
+			});
+		}
+		match self.local_copy(url) {
+			Some(document) => Ok(document),
+			None => self.fetch(url),
+		}
+	}
+
+	/// Fetches every remote document the documents of the repository reference, directly or not, keeping them in
+	/// the cache, returning their number
+	pub fn prefetch(&self) -> Result<usize, VerifierError> {
+		let mut pending = self
+			.documents
+			.local
+			.iter()
+			.filter_map(|(path, json)| Some((Url::parse(&format!("{}:///{}", SCHEME, path)).ok()?, Arc::clone(json))))
+			.collect::<Vec<_>>();
+		let mut fetched = BTreeSet::new();
+		while let Some((base, json)) = pending.pop() {
+			let mut references = Vec::new();
+			references_of(&json, &mut references);
+			for reference in references {
+				let mut url = match base.join(&reference) {
+					Ok(url) => url,
+					Err(_) => continue,
+				};
+				url.set_fragment(None);
+				let remote = matches!(url.scheme(), "http" | "https");
+				if remote && self.local_copy(&url).is_none() && fetched.insert(url.clone()) {
+					let document = self.fetch(&url)?;
+					pending.push((url, document));
+				}
+			}
+		}
+		Ok(fetched.len())
+	}
+}
+
+/// Collects the `$ref`s of `json`
+fn references_of(json: &Json, references: &mut Vec<String>) {
+	match json {
+		Json::Object(object) => {
+			if let Some(Json::String(reference)) = object.get("$ref") {
+				references.push(reference.clone());
+			}
+			object.values().for_each(|value| references_of(value, references));
+		},
+		Json::Array(values) => values.iter().for_each(|value| references_of(value, references)),
+		_ => (),
+	}
+}
+
+impl SchemaResolver for SchemaStore {
+	fn resolve(&self, _root_schema: &Json, url: &Url, _reference: &str) -> Result<Arc<Json>, SchemaResolverError> {
+		Ok(self.resolve_url(url)?)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use std::collections::BTreeMap;
+	use std::path::Path;
+
+	use serde_json::json;
+	use url::Url;
+	use wiremock::ResponseTemplate;
+
+	use crate::cache::SchemaCache;
+	use crate::formats::Formats;
+	use crate::resolver::SchemaStore;
+	use crate::testing::{Behavior, MockBeacon};
+	use crate::utils;
+
+	const LOCATION: &str = "https://github.com/ga4gh-beacon/beacon-v2/models/json/beacon-v2-default-model";
+
+	#[test]
+	fn test_references() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		beacon.mount(
+			"/schemas/age.json",
+			ResponseTemplate::new(200).set_body_json(json!({ "type": "integer" })),
+		);
+		let root = Path::new("/model");
+		let mut files = BTreeMap::new();
+		files.insert(
+			root.join("individuals/defaultSchema.json"),
+			json!({
+				"$schema": "http://json-schema.org/draft-07/schema",
+				"properties": {
+					"sex": { "$ref": "../common/ontologyTerm.json" },
+					"diseases": {
+						"items": {
+							"$ref": "https://raw.githubusercontent.com/ga4gh-beacon/beacon-v2/main/models/json/\
+									 beacon-v2-default-model/common/ontologyTerm.json"
+						}
+					},
+					"age": { "$ref": beacon.url().join("schemas/age.json").unwrap() },
+					"info": {
+						"$ref": "https://raw.githubusercontent.com/ga4gh-beacon/beacon-framework-v2/main/\
+								 common/ontologyTerm.json"
+					}
+				}
+			}),
+		);
+		files.insert(
+			root.join("common/ontologyTerm.json"),
+			json!({ "type": "object", "required": ["id"] }),
+		);
+		let location = Url::parse(LOCATION).unwrap();
+		let store = SchemaStore::new(root, &mut files, &location, &SchemaCache::default());
+		assert_eq!(
+			files[&root.join("common/ontologyTerm.json")]["$id"],
+			"beacon-schema:///common/ontologyTerm.json"
+		);
+
+		let schema = files[&root.join("individuals/defaultSchema.json")].clone();
+		let compiled = utils::compile_schema(&schema, &Formats::default(), &store).unwrap();
+		assert!(compiled.is_valid(&json!({ "sex": { "id": "NCIT:C16576" }, "diseases": [{ "id": "x" }], "age": 42 })));
+		assert!(!compiled.is_valid(&json!({ "sex": { "label": "female" } })));
+		assert!(!compiled.is_valid(&json!({ "diseases": [{ "label": "asthma" }] })));
+		assert!(!compiled.is_valid(&json!({ "age": "42" })));
+
+		// Offline, only the documents of the repository: a document of another repository is not mapped to them
+		let store = SchemaStore::new(root, &mut files, &location, &SchemaCache::default().offline());
+		let compiled = utils::compile_schema(&schema, &Formats::default(), &store).unwrap();
+		for instance in [json!({ "age": 42 }), json!({ "info": { "id": "x" } })] {
+			let errors = compiled
+				.validate(&instance)
+				.unwrap_err()
+				.map(|e| e.to_string())
+				.collect::<Vec<_>>();
+			assert!(errors[0].contains("not in the cache"), "{:?}", errors);
+		}
+		assert!(compiled.is_valid(&json!({ "diseases": [{ "id": "x" }] })));
+	}
+
+	#[test]
+	fn test_cached_references() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		beacon.mount(
+			"/schemas/age.json",
+			ResponseTemplate::new(200).set_body_json(json!({ "$ref": "range.json" })),
+		);
+		beacon.mount(
+			"/schemas/range.json",
+			ResponseTemplate::new(200).set_body_json(json!({ "type": "integer" })),
+		);
+		let root = Path::new("/model");
+		let mut files = BTreeMap::new();
+		files.insert(
+			root.join("individuals/defaultSchema.json"),
+			json!({ "properties": { "age": { "$ref": beacon.url().join("schemas/age.json").unwrap() } } }),
+		);
+		let location = Url::parse(LOCATION).unwrap();
+		let dir = tempfile::tempdir().unwrap();
+		let cache = SchemaCache::new(dir.path().to_path_buf());
+
+		// Filled by cache-schemas, with the documents referenced by the remote ones
+		let store = SchemaStore::new(root, &mut files, &location, &cache);
+		assert_eq!(store.prefetch().unwrap(), 2);
+		let host = beacon.url().host_str().unwrap().to_string();
+		assert!(dir
+			.path()
+			.join("documents")
+			.join(host)
+			.join("schemas/range.json")
+			.is_file());
+
+		let store = SchemaStore::new(root, &mut files, &location, &cache.offline());
+		let schema = files[&root.join("individuals/defaultSchema.json")].clone();
+		let compiled = utils::compile_schema(&schema, &Formats::default(), &store).unwrap();
+		assert!(compiled.is_valid(&json!({ "age": 42 })));
+		assert!(!compiled.is_valid(&json!({ "age": "42" })));
+	}
+}
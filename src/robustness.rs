@@ -0,0 +1,217 @@
+//! Robustness of the beacon against the encodings of the POST bodies
+//!
+//! Clients send the same request body in different ways: with or without a
+//! `charset` parameter, with a UTF-8 byte order mark (as some Windows tools
+//! do)... A beacon must accept all of them, and answer a body that is not
+//! UTF-8 or not JSON with a client error (4xx) rather than crashing or
+//! ignoring it. The error responses must not show the internals of the beacon
+//! (see [`leaks`](crate::leaks)) nor echo its credentials (see
+//! [`echoes`](crate::echoes)).
+
+use std::path::Path;
+
+use url::Url;
+
+use crate::client::Client;
+use crate::error::VerifierError;
+use crate::output::{Category, EndpointReport};
+use crate::{echoes, leaks, Json};
+
+/// Entity the reports of the robustness checks are grouped under
+pub const ENTITY_NAME: &str = "Robustness";
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Body of the requests, a boolean query without filters
+fn query(api_version: &[u8]) -> Vec<u8> {
+	[
+		br#"{"meta":{"apiVersion":""#,
+		api_version,
+		br#""},"query":{"requestedGranularity":"boolean","filters":[]}}"#,
+	]
+	.concat()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BodyEncoding {
+	/// `application/json`, UTF-8 being the default of JSON
+	NoCharset,
+	/// `application/json; charset=utf-8`
+	Charset,
+	/// UTF-8 with a byte order mark
+	Bom,
+	/// Bytes that are not UTF-8, that must be rejected
+	Invalid,
+	/// Truncated JSON, that must be rejected
+	Malformed,
+}
+
+impl BodyEncoding {
+	pub const ALL: [Self; 5] = [
+		Self::NoCharset,
+		Self::Charset,
+		Self::Bom,
+		Self::Invalid,
+		Self::Malformed,
+	];
+
+	/// Identifier of the check in the catalog
+	pub fn id(self) -> &'static str {
+		match self {
+			Self::NoCharset => "no-charset",
+			Self::Charset => "charset",
+			Self::Bom => "bom",
+			Self::Invalid => "invalid",
+			Self::Malformed => "malformed",
+		}
+	}
+
+	pub fn name(self) -> &'static str {
+		match self {
+			Self::NoCharset => "POST body without charset",
+			Self::Charset => "POST body with charset",
+			Self::Bom => "POST body with a byte order mark",
+			Self::Invalid => "POST body that is not UTF-8",
+			Self::Malformed => "POST body that is not JSON",
+		}
+	}
+
+	fn content_type(self) -> &'static str {
+		match self {
+			Self::NoCharset => "application/json",
+			Self::Charset | Self::Bom | Self::Invalid | Self::Malformed => "application/json; charset=utf-8",
+		}
+	}
+
+	fn body(self) -> Vec<u8> {
+		match self {
+			Self::NoCharset | Self::Charset => query(b"v2.0"),
+			Self::Bom => [UTF8_BOM, &query(b"v2.0")].concat(),
+			// An `é` in Latin-1
+			Self::Invalid => query(b"v2.0\xE9"),
+			Self::Malformed => br#"{"meta":{"apiVersion":"v2.0"},"query":{"#.to_vec(),
+		}
+	}
+
+	fn rejected(self) -> bool {
+		matches!(self, Self::Invalid | Self::Malformed)
+	}
+
+	/// Whether the beacon answered as expected, or why not
+	fn expect(self, status: reqwest::StatusCode, is_json: bool) -> Result<(), String> {
+		match self {
+			_ if self.rejected() && status.is_client_error() => Ok(()),
+			_ if self.rejected() && status.is_success() => Err("accepted the body instead of rejecting it".into()),
+			_ if self.rejected() => Err(format!("did not reject the body gracefully (status {})", status)),
+			_ if !status.is_success() => Err(format!("rejected the body (status {})", status)),
+			_ if !is_json => Err("accepted the body, but the response is not JSON".into()),
+			_ => Ok(()),
+		}
+	}
+}
+
+/// Posts a query with each encoding of the body to the `/info` endpoint
+pub fn check(client: &Client, root_url: &Url, beacon_name: &str) -> Vec<EndpointReport> {
+	let mut url = root_url.clone();
+	url.set_path(Path::new(root_url.path()).join("info").to_str().unwrap_or(""));
+	BodyEncoding::ALL
+		.into_iter()
+		.map(|encoding| {
+			log::info!("Validating {:?}", encoding.name());
+			let report = EndpointReport::new(
+				ENTITY_NAME,
+				&format!("{} {}", beacon_name, encoding.name()),
+				url.clone(),
+			)
+			.category(Category::Framework)
+			.check(&format!("framework.post-body.{}", encoding.id()));
+			match client.post_raw(&url, encoding.content_type(), encoding.body()) {
+				Ok((status, body)) => {
+					let is_json = serde_json::from_slice::<Json>(&body).is_ok();
+					let mut warnings = leaks::warnings(status, &body);
+					warnings.extend(echoes::error_response(status, &body, &client.credentials(), &url));
+					let report = report.warnings(warnings);
+					match encoding.expect(status, is_json) {
+						Ok(()) => report.ok(None),
+						Err(e) => report.error(VerifierError::BadResponse(format!("The beacon {}", e))),
+					}
+				},
+				Err(e) => report.error(e),
+			}
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+
+	use wiremock::{Request, ResponseTemplate};
+
+	use crate::client::Client;
+	use crate::output::EndpointOutput;
+	use crate::robustness::{self, BodyEncoding, ENTITY_NAME};
+	use crate::testing::{Behavior, MockBeacon};
+	use crate::Json;
+
+	/// Parses the body like most JSON parsers: UTF-8 without a byte order mark
+	fn strict(request: &Request) -> ResponseTemplate {
+		match serde_json::from_slice::<Json>(&request.body) {
+			Ok(_) => ResponseTemplate::new(200).set_body_json(serde_json::json!({ "response": {} })),
+			Err(_) => ResponseTemplate::new(400),
+		}
+	}
+
+	fn valid(reports: &[EndpointOutput], encoding: BodyEncoding) -> Option<bool> {
+		reports
+			.iter()
+			.find(|report| report.name.ends_with(encoding.name()))
+			.and_then(|report| report.valid)
+	}
+
+	#[test]
+	fn test_robustness() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		beacon.mount_method("POST", "/info", strict);
+		let output = beacon.beacon().robustness().validate();
+		let reports = &output.entities[ENTITY_NAME];
+		assert_eq!(reports.len(), BodyEncoding::ALL.len());
+		assert_eq!(valid(reports, BodyEncoding::NoCharset), Some(true));
+		assert_eq!(valid(reports, BodyEncoding::Charset), Some(true));
+		assert_eq!(valid(reports, BodyEncoding::Bom), Some(false));
+		assert_eq!(valid(reports, BodyEncoding::Invalid), Some(true));
+		assert_eq!(valid(reports, BodyEncoding::Malformed), Some(true));
+		assert!(!beacon.verify().entities.contains_key(ENTITY_NAME));
+
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		beacon.mount_method("POST", "/info", ResponseTemplate::new(500));
+		let reports = robustness::check(&Client::new(), &beacon.url(), "Mock");
+		assert!(reports.iter().all(|report| report.valid == Some(false)));
+		let e = reports[3].error.as_ref().unwrap().to_string();
+		assert!(e.contains("did not reject the body gracefully"), "{}", e);
+		assert!(reports.iter().all(|report| report.warnings.is_empty()));
+	}
+
+	#[test]
+	fn test_leaking_error_responses() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let traceback = "Traceback (most recent call last):\n  File \"beacon/info.py\", line 8\nJSONDecodeError";
+		// Crashes on the bodies it cannot parse
+		beacon.mount_method("POST", "/info", move |request: &Request| {
+			match serde_json::from_slice::<Json>(&request.body) {
+				Ok(_) => strict(request),
+				Err(_) => ResponseTemplate::new(500).set_body_string(traceback),
+			}
+		});
+		let reports = robustness::check(&Client::new(), &beacon.url(), "Mock");
+		let leaking = reports
+			.iter()
+			.filter(|report| !report.warnings.is_empty())
+			.collect::<Vec<_>>();
+		assert_eq!(leaking.len(), 3);
+		assert!(leaking.iter().all(|report| report.valid == Some(false)));
+		assert_eq!(
+			reports.last().unwrap().warnings,
+			vec!["Security: the error response (500) shows a Python traceback"]
+		);
+	}
+}
@@ -0,0 +1,334 @@
+//! Limits on the schemas referenced by untrusted documents
+//!
+//! The schemas of the framework and of the model are trusted: their `$ref`s
+//! are read from the files of the model or fetched as they are. A schema
+//! declared by a beacon is not, since its references could make the verifier
+//! query the hosts of its own network (SSRF), read its files or exhaust its
+//! memory. Its references are only fetched over HTTP(S) from public hosts,
+//! without following redirects, and within limits on the number, the size and
+//! the depth of the documents. The address a name was checked at is the one
+//! connected to, without a proxy, so the name cannot resolve to a local address
+//! in between. The references are resolved the first time a response is
+//! validated, where a refused one is a validation error.
+//!
+//! The responses returning a schema the model does not have, identified by its
+//! url, are validated against the schema fetched and compiled this way.
+
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use jsonschema::{JSONSchema, SchemaResolver, SchemaResolverError};
+use url::{Host, Url};
+
+use crate::error::VerifierError;
+use crate::formats::Formats;
+use crate::Json;
+
+/// Time to fetch a referenced document
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SchemaLimits {
+	/// Largest document, in bytes
+	pub max_bytes: usize,
+	/// Deepest nesting of the arrays and objects of a document
+	pub max_depth: usize,
+	/// Most documents fetched for the references of a schema
+	pub max_documents: usize,
+	/// Whether the `file://` references and the local hosts (loopback, private networks) are allowed
+	pub allow_local: bool,
+}
+
+impl Default for SchemaLimits {
+	fn default() -> Self {
+		Self {
+			max_bytes: 1024 * 1024,
+			max_depth: 64,
+			max_documents: 32,
+			allow_local: false,
+		}
+	}
+}
+
+fn refused(reason: String) -> VerifierError {
+	VerifierError::UntrustedSchema(reason)
+}
+
+/// Whether the address is not reachable from the internet (loopback, private, link-local, ...)
+fn is_local(ip: IpAddr) -> bool {
+	match ip {
+		IpAddr::V4(ip) => {
+			let [first, second, ..] = ip.octets();
+			ip.is_loopback()
+				|| ip.is_private()
+				|| ip.is_link_local()
+				|| ip.is_unspecified()
+				|| ip.is_broadcast()
+				// Shared address space (carrier-grade NAT)
+				|| (first == 100 && second & 0xc0 == 64)
+		},
+		IpAddr::V6(ip) => {
+			let first = ip.segments()[0];
+			ip.is_loopback()
+				|| ip.is_unspecified()
+				// Unique local and link-local addresses
+				|| first & 0xfe00 == 0xfc00
+				|| first & 0xffc0 == 0xfe80
+				|| ip.to_ipv4().map_or(false, |ip| is_local(IpAddr::V4(ip)))
+		},
+	}
+}
+
+/// Refuses the urls of other schemes than HTTP(S) and, unless allowed, the local files and hosts, returning the
+/// address the name of the host was checked at, to connect to
+pub fn check_url(url: &Url, allow_local: bool) -> Result<Option<SocketAddr>, VerifierError> {
+	match url.scheme() {
+		"http" | "https" => (),
+		"file" if allow_local => return Ok(None),
+		scheme => return Err(refused(format!("{} references are not allowed ({})", scheme, url))),
+	}
+	if allow_local {
+		return Ok(None);
+	}
+	let port = url.port_or_known_default().unwrap_or(80);
+	// The addresses a name resolves to, so a public name of a local address is refused too
+	let addresses = match url.host() {
+		Some(Host::Ipv4(ip)) => vec![SocketAddr::new(IpAddr::V4(ip), port)],
+		Some(Host::Ipv6(ip)) => vec![SocketAddr::new(IpAddr::V6(ip), port)],
+		Some(Host::Domain(domain)) if domain == "localhost" || domain.ends_with(".localhost") => {
+			return Err(refused(format!("{} is a local host", url)));
+		},
+		Some(Host::Domain(domain)) => (domain, port).to_socket_addrs()?.collect(),
+		None => return Err(refused(format!("{} has no host", url))),
+	};
+	if let Some(address) = addresses.iter().find(|address| is_local(address.ip())) {
+		return Err(refused(format!("{} is a local address ({})", address.ip(), url)));
+	}
+	match url.host() {
+		Some(Host::Domain(_)) => Ok(addresses.first().copied()),
+		_ => Ok(None),
+	}
+}
+
+/// Deepest nesting of the arrays and objects of the document
+fn depth(json: &Json) -> usize {
+	match json {
+		Json::Array(items) => 1 + items.iter().map(depth).max().unwrap_or(0),
+		Json::Object(fields) => 1 + fields.values().map(depth).max().unwrap_or(0),
+		_ => 0,
+	}
+}
+
+fn check_document(json: &Json, size: usize, limits: &SchemaLimits) -> Result<(), VerifierError> {
+	if size > limits.max_bytes {
+		return Err(refused(format!("larger than {} bytes", limits.max_bytes)));
+	}
+	if depth(json) > limits.max_depth {
+		return Err(refused(format!("nested deeper than {} levels", limits.max_depth)));
+	}
+	Ok(())
+}
+
+/// Resolver of the references of an untrusted schema
+struct SandboxedResolver {
+	limits: SchemaLimits,
+	fetched: AtomicUsize,
+}
+
+impl SandboxedResolver {
+	fn new(limits: SchemaLimits) -> Self {
+		Self {
+			limits,
+			fetched: AtomicUsize::new(0),
+		}
+	}
+
+	/// Client connecting to `address` for the name of the host of `url`, if given
+	fn http(url: &Url, address: Option<SocketAddr>) -> Result<reqwest::blocking::Client, VerifierError> {
+		let mut builder = reqwest::blocking::Client::builder()
+			.timeout(TIMEOUT)
+			.redirect(reqwest::redirect::Policy::none());
+		if let (Some(domain), Some(address)) = (url.domain(), address) {
+			builder = builder.no_proxy().resolve(domain, address);
+		}
+		Ok(builder.build()?)
+	}
+
+	fn fetch(&self, url: &Url) -> Result<Json, VerifierError> {
+		if self.fetched.fetch_add(1, Ordering::Relaxed) >= self.limits.max_documents {
+			return Err(refused(format!(
+				"more than {} referenced documents",
+				self.limits.max_documents
+			)));
+		}
+		let address = check_url(url, self.limits.allow_local)?;
+		log::debug!("Fetching the referenced schema {}", url);
+		// One byte more than the limit tells a document that is too large
+		let limit = self.limits.max_bytes as u64 + 1;
+		let mut bytes = Vec::new();
+		if url.scheme() == "file" {
+			let path = url
+				.to_file_path()
+				.map_err(|()| refused(format!("{} is not a path", url)))?;
+			std::fs::File::open(path)?.take(limit).read_to_end(&mut bytes)?;
+		}
+		else {
+			let response = Self::http(url, address)?.get(url.clone()).send()?;
+			if !response.status().is_success() {
+				return Err(refused(format!("{} answered {}", url, response.status())));
+			}
+			response.take(limit).read_to_end(&mut bytes)?;
+		}
+		let json = serde_json::from_slice(&bytes)?;
+		check_document(&json, bytes.len(), &self.limits).map_err(|e| refused(format!("{}: {}", url, e)))?;
+		Ok(json)
+	}
+}
+
+impl SchemaResolver for SandboxedResolver {
+	fn resolve(&self, _root_schema: &Json, url: &Url, _reference: &str) -> Result<Arc<Json>, SchemaResolverError> {
+		Ok(Arc::new(self.fetch(url)?))
+	}
+}
+
+/// Compiles an untrusted schema, whose references are resolved within `limits`
+pub fn compile(schema: &Json, formats: &Formats, limits: SchemaLimits) -> Result<JSONSchema, VerifierError> {
+	check_document(schema, serde_json::to_vec(schema)?.len(), &limits)?;
+	compile_with(schema, formats, SandboxedResolver::new(limits))
+}
+
+fn compile_with(schema: &Json, formats: &Formats, resolver: SandboxedResolver) -> Result<JSONSchema, VerifierError> {
+	let mut options = JSONSchema::options();
+	formats.register(&mut options);
+	options.with_resolver(resolver);
+	options.with_meta_schemas().compile(schema).map_err(|e| {
+		log::error!("{:?}", e);
+		VerifierError::BadSchema(e.to_string())
+	})
+}
+
+/// Fetches the untrusted schema at `url` and compiles it, the schema and its references within `limits`
+pub fn compile_url(url: &Url, formats: &Formats, limits: SchemaLimits) -> Result<JSONSchema, VerifierError> {
+	let resolver = SandboxedResolver::new(limits);
+	let schema = resolver.fetch(url)?;
+	compile_with(&schema, formats, resolver)
+}
+
+/// Schema compiled from a url, or why it could not be
+type Compiled = Result<Arc<JSONSchema>, String>;
+
+/// Schemas returned by the beacon that the model does not have, compiled once per url for all the endpoints
+#[derive(Clone, Default)]
+pub struct BeaconSchemas {
+	limits: SchemaLimits,
+	compiled: Arc<Mutex<BTreeMap<Url, Compiled>>>,
+}
+
+impl BeaconSchemas {
+	pub fn new(limits: SchemaLimits) -> Self {
+		Self {
+			limits,
+			compiled: Arc::default(),
+		}
+	}
+
+	/// Schema at `url`, fetched and compiled the first time it is returned
+	pub fn get(&self, url: &Url, formats: &Formats) -> Result<Arc<JSONSchema>, VerifierError> {
+		// One fetch per url, even when several workers need it
+		let mut compiled = self.compiled.lock().unwrap_or_else(|e| e.into_inner());
+		let schema = compiled.entry(url.clone()).or_insert_with(|| {
+			log::info!("Compiling the schema {} returned by the beacon", url);
+			compile_url(url, formats, self.limits)
+				.map(Arc::new)
+				.map_err(|e| e.to_string())
+		});
+		schema.clone().map_err(VerifierError::UntrustedSchema)
+	}
+}
+
+impl std::fmt::Debug for BeaconSchemas {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("BeaconSchemas").field("limits", &self.limits).finish()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use serde_json::json;
+	use url::Url;
+	use wiremock::ResponseTemplate;
+
+	use crate::formats::Formats;
+	use crate::sandbox::{self, SchemaLimits};
+	use crate::testing::{Behavior, MockBeacon};
+
+	#[test]
+	fn test_check_url() {
+		let check = |url: &str| sandbox::check_url(&Url::parse(url).unwrap(), false).map_err(|e| e.to_string());
+		assert!(check("https://93.184.216.34/schema.json").is_ok());
+		for url in [
+			"http://localhost/schema.json",
+			"http://127.0.0.1:8080/schema.json",
+			"http://10.1.2.3/schema.json",
+			"http://169.254.169.254/latest/meta-data",
+			"http://[::1]/schema.json",
+			"http://[fd00::1]/schema.json",
+			"http://[::ffff:192.168.0.1]/schema.json",
+		] {
+			assert!(check(url).unwrap_err().contains("local"), "{}", url);
+		}
+		assert_eq!(
+			check("file:///etc/passwd").unwrap_err(),
+			"Untrusted schema refused: file references are not allowed (file:///etc/passwd)"
+		);
+		assert!(sandbox::check_url(&Url::parse("file:///schemas/a.json").unwrap(), true).is_ok());
+		assert!(check("ftp://example.org/schema.json").is_err());
+	}
+
+	#[test]
+	fn test_compile() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		beacon.mount(
+			"/schemas/name.json",
+			ResponseTemplate::new(200).set_body_json(json!({ "type": "string" })),
+		);
+		let schema = json!({ "properties": { "name": { "$ref": beacon.url().join("schemas/name.json").unwrap() } } });
+		let local = SchemaLimits {
+			allow_local: true,
+			..SchemaLimits::default()
+		};
+		let compiled = sandbox::compile(&schema, &Formats::default(), local).unwrap();
+		assert!(compiled.is_valid(&json!({ "name": "Jane" })));
+		assert!(!compiled.is_valid(&json!({ "name": 1 })));
+
+		// The mock beacon is local
+		let compiled = sandbox::compile(&schema, &Formats::default(), SchemaLimits::default()).unwrap();
+		let errors = compiled
+			.validate(&json!({ "name": "Jane" }))
+			.unwrap_err()
+			.map(|e| e.to_string())
+			.collect::<Vec<_>>();
+		assert!(errors[0].contains("local address"), "{:?}", errors);
+
+		// Larger than the limit, but not the schema referencing it
+		let names = (0..100).map(|i| format!("name{}", i)).collect::<Vec<_>>();
+		beacon.mount(
+			"/schemas/names.json",
+			ResponseTemplate::new(200).set_body_json(json!({ "enum": names })),
+		);
+		let names = json!({ "$ref": beacon.url().join("schemas/names.json").unwrap() });
+		let small = SchemaLimits {
+			max_bytes: 200,
+			..local
+		};
+		let compiled = sandbox::compile(&names, &Formats::default(), small).unwrap();
+		assert!(!compiled.is_valid(&json!("name1")));
+		let deep = SchemaLimits { max_depth: 2, ..local };
+		assert!(sandbox::compile(&schema, &Formats::default(), deep).is_err());
+	}
+}
@@ -0,0 +1,184 @@
+//! Report of a run in the SARIF 2.1.0 format
+//!
+//! Code scanning dashboards (e.g. GitHub code scanning) read the results of
+//! static analyzers in this format and track them from a run to the next. The
+//! checks of the catalog are the rules of the tool, and each check that failed
+//! is a result of its rule, at the level of its severity, located at the url of
+//! the endpoint. The results carry a fingerprint of the check (its rule, entity,
+//! name and layer), so the dashboards tell a regression from a known failure.
+//! The scope of the run (what it did not verify) is in the properties of the run.
+
+use serde_json::json;
+
+use crate::catalog::{self, Severity};
+use crate::output::{BeaconOutput, EndpointOutput};
+use crate::{utils, Json};
+
+const SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+/// Rule of the checks without an id in the catalog (e.g. an internal error of the verifier)
+const UNCLASSIFIED: &str = "verifier.unclassified";
+
+fn level(severity: Severity) -> &'static str {
+	match severity {
+		Severity::Error => "error",
+		Severity::Warning => "warning",
+		Severity::Info => "note",
+	}
+}
+
+fn rules() -> Vec<Json> {
+	let unclassified = json!({
+		"id": UNCLASSIFIED,
+		"shortDescription": { "text": "Check without an id in the catalog" },
+		"defaultConfiguration": { "level": "error" },
+	});
+	catalog::checks()
+		.into_iter()
+		.map(|check| {
+			json!({
+				"id": check.id,
+				"shortDescription": { "text": check.description },
+				"defaultConfiguration": { "level": level(check.severity) },
+				"properties": { "category": check.category, "specReference": check.spec_reference },
+			})
+		})
+		.chain(std::iter::once(unclassified))
+		.collect()
+}
+
+fn result(entity_name: &str, report: &EndpointOutput) -> Json {
+	let rule = report.check.as_deref().unwrap_or(UNCLASSIFIED);
+	let error = report.error.as_deref().unwrap_or("the check could not run").trim_end();
+	let check = format!("{}|{}|{}|{}", rule, entity_name, report.key(), report.category);
+	json!({
+		"ruleId": rule,
+		"level": level(report.severity.unwrap_or(Severity::Error)),
+		"message": { "text": format!("{} ({}, {}): {}", report.name, entity_name, report.category, error) },
+		"locations": [{
+			"physicalLocation": {
+				"artifactLocation": { "uri": report.url },
+				"region": { "startLine": 1 },
+			},
+			"logicalLocations": [{
+				"name": report.name,
+				"fullyQualifiedName": format!("{}/{}", entity_name, report.name),
+			}],
+		}],
+		"partialFingerprints": { "beaconCheck/v1": format!("{:016x}", utils::fnv1a(check.as_bytes())) },
+		"properties": { "outcome": report.outcome() },
+	})
+}
+
+/// Renders the checks of the output that failed as the results of a SARIF log, with the catalog as the rules
+pub fn sarif(output: &BeaconOutput) -> Json {
+	log(vec![run(output)])
+}
+
+/// Renders the members of a network as the runs of a SARIF log
+pub fn network(outputs: &[BeaconOutput]) -> Json {
+	log(outputs.iter().map(run).collect())
+}
+
+fn log(runs: Vec<Json>) -> Json {
+	json!({
+		"$schema": SCHEMA,
+		"version": "2.1.0",
+		"runs": runs,
+	})
+}
+
+fn run(output: &BeaconOutput) -> Json {
+	let results = output
+		.entities
+		.iter()
+		.flat_map(|(entity_name, reports)| reports.iter().map(move |report| (entity_name, report)))
+		.filter(|(_, report)| !report.outcome().is_success())
+		.map(|(entity_name, report)| result(entity_name, report))
+		.collect::<Vec<_>>();
+	json!({
+		"tool": {
+			"driver": {
+				"name": env!("CARGO_PKG_NAME"),
+				"version": env!("CARGO_PKG_VERSION"),
+				"informationUri": env!("CARGO_PKG_REPOSITORY"),
+				"rules": rules(),
+			},
+		},
+		"automationDetails": { "id": format!("{}/", output.url) },
+		"results": results,
+		"properties": { "scope": output.scope },
+	})
+}
+
+#[cfg(test)]
+mod tests {
+
+	use serde_json::json;
+	use wiremock::ResponseTemplate;
+
+	use crate::testing::{self, Behavior, MockBeacon};
+	use crate::{sarif, Json};
+
+	#[test]
+	fn test_sarif() {
+		let beacon = MockBeacon::start(Behavior::MissingEndpoints);
+		let mut response = beacon.response("/individuals");
+		response["response"]["resultSets"][0]["results"][0]["sex"] = json!("female");
+		beacon.mount("/individuals", ResponseTemplate::new(200).set_body_json(response));
+		let log = sarif::sarif(&beacon.verify());
+		assert_eq!(log["version"], "2.1.0");
+		let run = &log["runs"][0];
+		let rules = run["tool"]["driver"]["rules"].as_array().unwrap();
+		assert!(rules.iter().any(|rule| rule["id"] == "model.entity"));
+
+		let results = run["results"].as_array().unwrap();
+		let schema = results.iter().find(|result| result["level"] == "error").unwrap();
+		assert_eq!(schema["ruleId"], "model.entity");
+		let message = schema["message"]["text"].as_str().unwrap();
+		assert!(
+			message.starts_with("Individual all entries (Individual, model): Response does not"),
+			"{}",
+			message
+		);
+		assert!(schema["locations"][0]["physicalLocation"]["artifactLocation"]["uri"]
+			.as_str()
+			.unwrap()
+			.ends_with("/individuals"));
+		let configuration = results
+			.iter()
+			.find(|result| result["ruleId"] == "framework.configuration")
+			.unwrap();
+		assert_eq!(configuration["level"], "warning");
+		assert_eq!(configuration["properties"]["outcome"], "error");
+
+		// The same check has the same fingerprint from a run to the next
+		let fingerprints = |log: &Json| {
+			log["runs"][0]["results"]
+				.as_array()
+				.unwrap()
+				.iter()
+				.map(|result| result["partialFingerprints"]["beaconCheck/v1"].clone())
+				.collect::<Vec<_>>()
+		};
+		let again = sarif::sarif(&beacon.verify());
+		assert_eq!(fingerprints(&again), fingerprints(&log));
+		assert!(
+			sarif::sarif(&MockBeacon::start(Behavior::Conformant).verify())["runs"][0]["results"]
+				.as_array()
+				.unwrap()
+				.is_empty()
+		);
+
+		let output = beacon
+			.beacon()
+			.configuration(testing::configuration(&beacon))
+			.validate();
+		let scope = &sarif::sarif(&output)["runs"][0]["properties"]["scope"];
+		assert_eq!(scope["access"], "anonymous");
+		assert!(scope["not_verified"]
+			.as_array()
+			.unwrap()
+			.contains(&json!("authenticated access (no credentials)")));
+	}
+}
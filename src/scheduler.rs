@@ -0,0 +1,135 @@
+//! Order in which the endpoints of the model are checked
+//!
+//! The endpoints are taken round-robin across the entities (the endpoint with
+//! all the entries of each entity first, then their single entry endpoints, ...)
+//! instead of one entity after the other. The first results cover the whole
+//! beacon, and a run cut short by its time budget remains representative.
+
+use std::collections::VecDeque;
+use std::time::Instant;
+
+use crate::endpoint::BeaconEndpoint;
+use crate::interrupt::Interrupt;
+
+pub struct Scheduler {
+	/// Pending endpoints of each entity, in the order the entities first appear
+	queues: VecDeque<VecDeque<BeaconEndpoint>>,
+	deadline: Option<Instant>,
+	interrupt: Interrupt,
+}
+
+impl Scheduler {
+	pub fn new(endpoints: Vec<BeaconEndpoint>) -> Self {
+		let mut queues: Vec<(String, VecDeque<BeaconEndpoint>)> = Vec::new();
+		for endpoint in endpoints {
+			match queues
+				.iter_mut()
+				.find(|(entity_name, _)| *entity_name == endpoint.entity_name)
+			{
+				Some((_, queue)) => queue.push_back(endpoint),
+				None => queues.push((endpoint.entity_name.clone(), VecDeque::from(vec![endpoint]))),
+			}
+		}
+		Self {
+			queues: queues.into_iter().map(|(_, queue)| queue).collect(),
+			deadline: None,
+			interrupt: Interrupt::default(),
+		}
+	}
+
+	/// No endpoint is handed out after `deadline`
+	pub fn deadline(mut self, deadline: Instant) -> Self {
+		self.deadline = Some(deadline);
+		self
+	}
+
+	/// No endpoint is handed out once `interrupt` is triggered
+	pub fn interrupt(mut self, interrupt: Interrupt) -> Self {
+		self.interrupt = interrupt;
+		self
+	}
+
+	/// Endpoints that were not handed out (all of them once the deadline passed)
+	pub fn remaining(self) -> Vec<BeaconEndpoint> {
+		self.queues.into_iter().flatten().collect()
+	}
+}
+
+impl Iterator for Scheduler {
+	type Item = BeaconEndpoint;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		if self.deadline.map_or(false, |deadline| Instant::now() >= deadline) || self.interrupt.is_interrupted() {
+			return None;
+		}
+		let mut queue = self.queues.pop_front()?;
+		let endpoint = queue.pop_front();
+		if !queue.is_empty() {
+			self.queues.push_back(queue);
+		}
+		endpoint
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use std::time::Instant;
+
+	use url::Url;
+
+	use crate::endpoint::BeaconEndpoint;
+	use crate::scheduler::Scheduler;
+
+	fn endpoint(entity_name: &str, name: &str) -> BeaconEndpoint {
+		let url = Url::parse("https://example.org/").unwrap();
+		BeaconEndpoint {
+			entity_name: entity_name.into(),
+			entry_type: entity_name.to_lowercase(),
+			entity_schemas: Vec::new(),
+			name: name.into(),
+			url: url.clone(),
+			template_url: url,
+			record_id: None,
+			skipped: None,
+			no_ids: None,
+			outside_root: None,
+			declared_schema: None,
+			beacon_schemas: None,
+		}
+	}
+
+	#[test]
+	fn test_round_robin() {
+		let endpoints = vec![
+			endpoint("Individual", "all"),
+			endpoint("Individual", "single"),
+			endpoint("Individual", "biosamples"),
+			endpoint("Biosample", "all"),
+			endpoint("Dataset", "all"),
+			endpoint("Dataset", "single"),
+		];
+		let order = Scheduler::new(endpoints)
+			.map(|endpoint| format!("{} {}", endpoint.entity_name, endpoint.name))
+			.collect::<Vec<_>>();
+		assert_eq!(
+			order,
+			vec![
+				"Individual all",
+				"Biosample all",
+				"Dataset all",
+				"Individual single",
+				"Dataset single",
+				"Individual biosamples"
+			]
+		);
+	}
+
+	#[test]
+	fn test_deadline() {
+		let mut scheduler =
+			Scheduler::new(vec![endpoint("Individual", "all"), endpoint("Biosample", "all")]).deadline(Instant::now());
+		assert!(scheduler.next().is_none());
+		assert_eq!(scheduler.remaining().len(), 2);
+	}
+}
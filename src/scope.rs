@@ -0,0 +1,352 @@
+//! Statement of what a run verified, and above all of what it did not
+//!
+//! The results alone do not tell an auditor which parts of the specification
+//! were left out: layers excluded by the options, entities that were not
+//! reached, record-level checks that only sampled a few records, formats that
+//! were not enforced or the access tier the beacon was queried with.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::catalog;
+use crate::client::MethodPolicy;
+use crate::output::{BeaconOutput, Category, RunConfiguration};
+
+/// Credentials the beacon was queried with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Access {
+	/// No credentials, only the public tier
+	Anonymous,
+	/// Headers of the configuration file
+	Headers,
+	/// Static bearer token
+	Token,
+	/// Basic authentication
+	Basic,
+	/// API key of the configuration file, in a query parameter, a cookie or a header
+	#[serde(rename = "api-key")]
+	ApiKey,
+	/// OAuth2 access tokens
+	OAuth2,
+}
+
+impl Default for Access {
+	fn default() -> Self {
+		Self::Anonymous
+	}
+}
+
+impl std::fmt::Display for Access {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Anonymous => write!(f, "anonymous"),
+			Self::Headers => write!(f, "headers"),
+			Self::Token => write!(f, "token"),
+			Self::Basic => write!(f, "basic"),
+			Self::ApiKey => write!(f, "api-key"),
+			Self::OAuth2 => write!(f, "oauth2"),
+		}
+	}
+}
+
+/// How deep the checks of an entity went
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Depth {
+	/// The endpoint with all the entries, and the record-level endpoints with the sampled records
+	Sampled,
+	/// Only the endpoint with all the entries, there was no record to check the others
+	AllEntriesOnly,
+	/// The endpoint with all the entries, the others were skipped since the entity has no data (or returns no records)
+	NoData,
+	/// No check ran
+	NotVerified,
+}
+
+impl Depth {
+	pub const ALL: [Self; 4] = [Self::Sampled, Self::AllEntriesOnly, Self::NoData, Self::NotVerified];
+}
+
+impl std::fmt::Display for Depth {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Sampled => write!(f, "sampled"),
+			Self::AllEntriesOnly => write!(f, "all-entries-only"),
+			Self::NoData => write!(f, "no-data"),
+			Self::NotVerified => write!(f, "not-verified"),
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Scope {
+	/// Layers of the specification that were checked
+	pub categories: Vec<Category>,
+	pub access: Access,
+	/// Depth of the checks of each entity of the model
+	pub entities: BTreeMap<String, Depth>,
+	/// What was not verified, and why
+	pub not_verified: Vec<String>,
+}
+
+impl Scope {
+	pub fn new(configuration: &RunConfiguration, output: &BeaconOutput) -> Self {
+		let categories = Category::ALL
+			.into_iter()
+			.filter(|category| configuration.only.map_or(true, |only| only == *category))
+			.filter(|category| *category != Category::Model || configuration.model.is_some())
+			.collect::<Vec<_>>();
+
+		let entities = configuration
+			.entities
+			.iter()
+			.map(|entity_name| (entity_name.clone(), Self::depth(output, entity_name)))
+			.collect::<BTreeMap<_, _>>();
+
+		let mut not_verified = Vec::new();
+		for category in Category::ALL.iter().filter(|category| !categories.contains(category)) {
+			not_verified.push(format!("{} checks (excluded by the options)", category));
+		}
+		if configuration.model.is_none() {
+			not_verified.push("entities (no model loaded)".into());
+		}
+		let unreached = entities
+			.iter()
+			.filter(|(_, depth)| **depth == Depth::NotVerified)
+			.map(|(entity_name, _)| entity_name.as_str())
+			.collect::<Vec<_>>();
+		if !unreached.is_empty() {
+			let reason = match &output.incremental {
+				Some(incremental) if !incremental.changed => "the data did not change since the previous run",
+				_ => "not reached",
+			};
+			not_verified.push(format!("entities {} ({})", unreached.join(", "), reason));
+		}
+		if entities.values().any(|depth| *depth == Depth::Sampled) {
+			let sampled = match (configuration.max_instances.unwrap_or(1), configuration.sample_seed) {
+				(1, None) => "the first record".to_string(),
+				(max_instances, None) => format!("the first {} records", max_instances),
+				(1, Some(seed)) => format!("the record sampled with the seed {}", seed),
+				(max_instances, Some(seed)) => format!("the {} records sampled with the seed {}", max_instances, seed),
+			};
+			not_verified.push(format!("record-level endpoints beyond {} of each entity", sampled));
+		}
+		if !configuration.disabled_formats.is_empty() {
+			not_verified.push(format!(
+				"formats {} (disabled)",
+				configuration.disabled_formats.join(", ")
+			));
+		}
+		match configuration.method_policy {
+			MethodPolicy::Get => not_verified.push("POST requests (method policy 'get')".into()),
+			MethodPolicy::Post => not_verified.push("GET requests (method policy 'post')".into()),
+			MethodPolicy::GetThenPost => (),
+		}
+		// The checks enabled by an option, all of them on the entities of the model but the encodings of the bodies
+		let with_model = configuration.model.is_some();
+		let options = [
+			(
+				configuration.robustness,
+				Category::Framework,
+				true,
+				"encodings of the POST bodies (use --robustness)",
+			),
+			(
+				configuration.pagination,
+				Category::Framework,
+				with_model,
+				"pagination of the entities (use --pagination)",
+			),
+			(
+				configuration.limits,
+				Category::Framework,
+				with_model,
+				"boundaries of the declared limits (use --limits)",
+			),
+			(
+				configuration.adaptive,
+				Category::Framework,
+				with_model,
+				"findability of the records with queries derived from them (use --adaptive)",
+			),
+			(
+				configuration.filters,
+				Category::Framework,
+				with_model,
+				"filtering terms and the consistency of the results with them (use --filters)",
+			),
+			(
+				configuration.post_queries,
+				Category::Framework,
+				with_model,
+				"queries POSTed with a request body (use --post-queries)",
+			),
+			(
+				configuration.negative,
+				Category::Framework,
+				with_model,
+				"error responses to malformed queries (use --negative)",
+			),
+			(
+				configuration.variant_queries,
+				Category::Framework,
+				with_model,
+				"queries of the genomic variants by position and gene (use --variant-queries)",
+			),
+			(
+				configuration.resolve_ontologies,
+				Category::Model,
+				with_model,
+				"existence and labels of the ontology terms (use --resolve-ontologies)",
+			),
+			(
+				configuration.references,
+				Category::Model,
+				with_model,
+				"records referenced by the records of the other entities (use --references)",
+			),
+		];
+		for (enabled, category, applies, what) in options {
+			if !enabled && applies && categories.contains(&category) {
+				not_verified.push(what.to_string());
+			}
+		}
+		if configuration.access == Access::Anonymous {
+			not_verified.push("authenticated access (no credentials)".into());
+		}
+		not_verified.push("OpenAPI definition of the endpoints".into());
+
+		Self {
+			categories,
+			access: configuration.access,
+			entities,
+			not_verified,
+		}
+	}
+
+	fn depth(output: &BeaconOutput, entity_name: &str) -> Depth {
+		let reports = match output.entities.get(entity_name) {
+			Some(reports) if !reports.is_empty() => reports,
+			_ => return Depth::NotVerified,
+		};
+		// e.g. the entities the beacon does not advertise
+		if reports.iter().all(|report| report.skipped) {
+			return Depth::NotVerified;
+		}
+		// The checks of the sampled records, and the checks of the endpoints that needed a record skipped without
+		// one, but not the checks of the entity as a whole enabled by an option of the catalog (e.g. the pagination)
+		let options = catalog::checks()
+			.into_iter()
+			.filter(|check| check.option.is_some())
+			.map(|check| check.id)
+			.collect::<BTreeSet<_>>();
+		let record_level = reports
+			.iter()
+			.filter(|report| {
+				report.record.is_some()
+					|| (report.skipped && report.check.as_ref().map_or(false, |check| !options.contains(check)))
+			})
+			.collect::<Vec<_>>();
+		if record_level.is_empty() {
+			Depth::AllEntriesOnly
+		}
+		else if record_level.iter().all(|report| report.skipped) {
+			Depth::NoData
+		}
+		else {
+			Depth::Sampled
+		}
+	}
+
+	/// Layers of the specification that were checked, e.g. `framework and model`
+	pub fn layers(&self) -> String {
+		self.categories
+			.iter()
+			.map(ToString::to_string)
+			.collect::<Vec<_>>()
+			.join(" and ")
+	}
+
+	pub fn summary(&self) {
+		log::info!("Scope: {} checks, {} access", self.layers(), self.access);
+		for not_verified in &self.not_verified {
+			log::info!("Not verified: {}", not_verified);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use serde_json::json;
+	use wiremock::ResponseTemplate;
+
+	use crate::network::NetworkEntry;
+	use crate::output::Category;
+	use crate::scope::{Access, Depth, Scope};
+	use crate::testing::{self, Behavior, MockBeacon};
+
+	#[test]
+	fn test_scope() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let configuration = testing::configuration(&beacon);
+		let output = beacon.beacon().configuration(configuration.clone()).validate();
+		let scope = output.scope.clone().unwrap();
+		assert_eq!(NetworkEntry::from(&output).not_verified, scope.not_verified);
+		assert_eq!(scope.categories, Category::ALL.to_vec());
+		assert_eq!(scope.access, Access::Anonymous);
+		assert_eq!(scope.entities["Individual"], Depth::Sampled);
+		assert!(scope
+			.not_verified
+			.iter()
+			.any(|item| item.starts_with("authenticated access")));
+		assert!(beacon.verify().scope.is_none());
+
+		let mut configuration = configuration;
+		configuration.only = Some(Category::Framework);
+		configuration.access = Access::OAuth2;
+		configuration.entities.push("Cohort".into());
+		let scope = Scope::new(&configuration, &beacon.beacon().only(Category::Framework).validate());
+		assert_eq!(scope.categories, vec![Category::Framework]);
+		assert_eq!(scope.entities["Cohort"], Depth::NotVerified);
+		assert!(scope
+			.not_verified
+			.contains(&"model checks (excluded by the options)".to_string()));
+		assert!(scope
+			.not_verified
+			.iter()
+			.any(|item| item.starts_with("entities Cohort")));
+		assert!(!scope
+			.not_verified
+			.iter()
+			.any(|item| item.starts_with("authenticated access")));
+	}
+
+	#[test]
+	fn test_depth() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let mut response = beacon.response("/biosamples");
+		response["response"]["resultSets"][0]["results"] = json!([]);
+		response["response"]["resultSets"][0]["resultsCount"] = json!(0);
+		response["response"]["resultSets"][0]["exists"] = json!(false);
+		response["responseSummary"] = json!({ "exists": false, "numTotalResults": 0 });
+		beacon.mount("/biosamples", ResponseTemplate::new(200).set_body_json(response));
+		let mut configuration = testing::configuration(&beacon);
+		configuration.pagination = true;
+		configuration.filters = true;
+		let output = beacon
+			.beacon()
+			.pagination()
+			.filters()
+			.configuration(configuration)
+			.validate();
+		let scope = output.scope.unwrap();
+		// The follow-up checks of the entities are not record-level
+		assert_eq!(scope.entities["Individual"], Depth::Sampled);
+		assert_eq!(scope.entities["Biosample"], Depth::NoData);
+		assert!(!scope.not_verified.iter().any(|item| item.contains("pagination")));
+		assert!(!scope.not_verified.iter().any(|item| item.contains("filtering terms")));
+	}
+}
@@ -0,0 +1,121 @@
+//! Serialization mistakes in the shape of the responses
+//!
+//! A response serialized twice (a JSON string holding the JSON of the
+//! response) or wrapped in an extra envelope (e.g. `{"data": {...}}`, or the
+//! `body` of a serverless function left as a string) fails the schemas of the
+//! framework with a wall of errors that do not tell what happened. Before the
+//! validation, the responses without a `meta` are searched for the response
+//! they hide, which is reported as the one problem to fix.
+
+use crate::error::VerifierError;
+use crate::Json;
+
+/// Whether `json` looks like a response of the framework, which always has a `meta`
+fn is_response(json: &Json) -> bool {
+	json.get("meta").map_or(false, Json::is_object)
+}
+
+/// JSON held by a string, if it is an object or an array
+fn parse_string(string: &str) -> Option<Json> {
+	serde_json::from_str::<Json>(string)
+		.ok()
+		.filter(|json| json.is_object() || json.is_array())
+}
+
+/// What hides the response in `json`, if it is a serialization mistake
+pub fn diagnose(json: &Json) -> Option<String> {
+	if is_response(json) {
+		return None;
+	}
+	match json {
+		Json::String(string) => {
+			let inner = parse_string(string)?;
+			Some(match diagnose(&inner) {
+				Some(problem) => format!("the body is a JSON string holding JSON, in which {}", problem),
+				None => "the body is a JSON string holding the response (serialized twice)".into(),
+			})
+		},
+		Json::Array(values) if values.iter().any(is_response) => Some("the response is wrapped in an array".into()),
+		Json::Object(object) => object.iter().find_map(|(property, value)| match value {
+			value if is_response(value) => Some(format!(
+				"the response is wrapped in an extra envelope, under '{}'",
+				property
+			)),
+			Json::String(string) if parse_string(string).map_or(false, |inner| is_response(&inner)) => Some(format!(
+				"the response is a JSON string under '{}' of an extra envelope (serialized twice)",
+				property
+			)),
+			_ => None,
+		}),
+		_ => None,
+	}
+}
+
+/// Fails with the serialization mistake of `json`, if it has one
+pub fn check(json: &Json) -> Result<(), VerifierError> {
+	match diagnose(json) {
+		Some(problem) => Err(VerifierError::BadShape(problem)),
+		None => Ok(()),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use serde_json::json;
+	use wiremock::ResponseTemplate;
+
+	use crate::shape;
+	use crate::testing::{Behavior, MockBeacon};
+
+	#[test]
+	fn test_diagnose() {
+		let response = json!({ "meta": { "apiVersion": "v2.0" }, "response": {} });
+		assert_eq!(shape::diagnose(&response), None);
+		let stringified = json!(response.to_string());
+		assert_eq!(
+			shape::diagnose(&stringified).unwrap(),
+			"the body is a JSON string holding the response (serialized twice)"
+		);
+		let wrapped = json!({ "data": response });
+		assert_eq!(
+			shape::diagnose(&wrapped).unwrap(),
+			"the response is wrapped in an extra envelope, under 'data'"
+		);
+		let lambda = json!({ "statusCode": 200, "body": response.to_string() });
+		assert!(shape::diagnose(&lambda).unwrap().contains("under 'body'"));
+		assert!(shape::diagnose(&json!(wrapped.to_string()))
+			.unwrap()
+			.ends_with("under 'data'"));
+		assert!(shape::diagnose(&json!([response])).is_some());
+		// Nothing hidden, left to the schemas
+		assert_eq!(shape::diagnose(&json!({ "response": {} })), None);
+		assert_eq!(shape::diagnose(&json!("not found")), None);
+	}
+
+	#[test]
+	fn test_wrapped_response() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let info = beacon.response("/info");
+		beacon.mount("/info", ResponseTemplate::new(200).set_body_json(info.to_string()));
+		let individuals = beacon.response("/individuals");
+		beacon.mount(
+			"/individuals",
+			ResponseTemplate::new(200).set_body_json(json!({ "data": individuals })),
+		);
+		let output = beacon.verify();
+		let error = output.entities["Info"][0].error.clone().unwrap();
+		assert_eq!(
+			error,
+			"Bad response shape: the body is a JSON string holding the response (serialized twice)"
+		);
+		let all_entries = output.entities["Individual"]
+			.iter()
+			.find(|report| report.name == "Individual all entries")
+			.unwrap();
+		assert_eq!(
+			all_entries.error.as_deref(),
+			Some("Bad response shape: the response is wrapped in an extra envelope, under 'data'")
+		);
+	}
+}
@@ -0,0 +1,138 @@
+//! Versions of the Beacon v2 specification
+//!
+//! The schemas changed between the releases of the specification, so a beacon
+//! verified against the schemas of another version fails checks it passes.
+//! The version is read from the `apiVersion` of `/info`, or given with
+//! `--spec-version`, and selects the framework and the model to load when
+//! they are not given with `--framework` and `--model`.
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::client::Client;
+use crate::Json;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, clap::ArgEnum)]
+pub enum SpecVersion {
+	#[clap(name = "2.0")]
+	#[serde(rename = "2.0")]
+	V2_0,
+	#[clap(name = "2.1")]
+	#[serde(rename = "2.1")]
+	V2_1,
+}
+
+impl SpecVersion {
+	/// Version of the beacons that do not tell theirs
+	pub const DEFAULT: Self = Self::V2_0;
+
+	/// Parses an `apiVersion` (e.g. `v2.1.0`, `2.0`), the later minor versions of v2 being verified as the last known
+	pub fn parse(api_version: &str) -> Option<Self> {
+		let version = api_version.trim().trim_start_matches(|c| c == 'v' || c == 'V');
+		let mut parts = version.split(|c: char| !c.is_ascii_digit());
+		let major = parts.next()?.parse::<u32>().ok()?;
+		let minor = parts.next().and_then(|minor| minor.parse::<u32>().ok()).unwrap_or(0);
+		match (major, minor) {
+			(2, 0) => Some(Self::V2_0),
+			(2, _) => Some(Self::V2_1),
+			_ => None,
+		}
+	}
+
+	/// Version declared by the response of `/info`, in its `meta` or else in the information of the beacon
+	pub fn declared(info: &Json) -> Option<Self> {
+		["/meta/apiVersion", "/response/apiVersion"]
+			.iter()
+			.filter_map(|pointer| info.pointer(pointer).and_then(Json::as_str))
+			.find_map(Self::parse)
+	}
+
+	/// Version of the beacon at `url`, read from its `/info`
+	pub fn detect(client: &Client, url: &Url) -> Option<Self> {
+		let mut info_url = url.clone();
+		info_url.set_path(Path::new(url.path()).join("info").to_str().unwrap_or(""));
+		let version = match client.query("Info", &info_url) {
+			Ok(response) => Self::declared(&response.json),
+			Err(e) => {
+				log::warn!("Could not detect the version of the specification of the beacon: {}", e);
+				return None;
+			},
+		};
+		match version {
+			Some(version) => log::info!("The beacon implements the version {} of the specification", version),
+			None => log::warn!("The beacon does not declare a known version of the specification in /info"),
+		}
+		version
+	}
+
+	/// Location of the framework of this version
+	pub fn framework(self) -> Url {
+		let location = match self {
+			Self::V2_0 => "https://github.com/MrRobb/beacon-framework-v2",
+			Self::V2_1 => "https://github.com/ga4gh-beacon/beacon-v2/framework/json",
+		};
+		Url::parse(location).expect("Bad location of a framework")
+	}
+
+	/// Location of the default model of this version
+	pub fn model(self) -> Url {
+		let location = match self {
+			Self::V2_0 => "https://github.com/MrRobb/beacon-v2-Models/BEACON-V2-draft4-Model",
+			Self::V2_1 => "https://github.com/ga4gh-beacon/beacon-v2/models/json/beacon-v2-default-model",
+		};
+		Url::parse(location).expect("Bad location of a model")
+	}
+}
+
+impl std::fmt::Display for SpecVersion {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::V2_0 => write!(f, "2.0"),
+			Self::V2_1 => write!(f, "2.1"),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use serde_json::json;
+	use wiremock::ResponseTemplate;
+
+	use crate::client::Client;
+	use crate::spec::SpecVersion;
+	use crate::testing::{Behavior, MockBeacon};
+
+	#[test]
+	fn test_parse() {
+		assert_eq!(SpecVersion::parse("v2.0.0"), Some(SpecVersion::V2_0));
+		assert_eq!(SpecVersion::parse("2.0.0-draft.4"), Some(SpecVersion::V2_0));
+		assert_eq!(SpecVersion::parse("v2"), Some(SpecVersion::V2_0));
+		assert_eq!(SpecVersion::parse("v2.1.1"), Some(SpecVersion::V2_1));
+		assert_eq!(SpecVersion::parse("2.2"), Some(SpecVersion::V2_1));
+		assert_eq!(SpecVersion::parse("v1.1.0"), None);
+		assert_eq!(SpecVersion::parse("latest"), None);
+	}
+
+	#[test]
+	fn test_detect() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		assert_eq!(
+			SpecVersion::detect(&Client::new(), &beacon.url()),
+			Some(SpecVersion::V2_0)
+		);
+
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let mut info = beacon.response("/info");
+		info["meta"]["apiVersion"] = json!("unknown");
+		info["response"]["apiVersion"] = json!("v2.1.0");
+		beacon.mount("/info", ResponseTemplate::new(200).set_body_json(info));
+		assert_eq!(
+			SpecVersion::detect(&Client::new(), &beacon.url()),
+			Some(SpecVersion::V2_1)
+		);
+		assert_ne!(SpecVersion::V2_0.framework(), SpecVersion::V2_1.framework());
+	}
+}
@@ -0,0 +1,292 @@
+//! Files written by hand: the configuration file and the policy files
+//!
+//! They are written in TOML, YAML or JSON, after their extension, and checked
+//! against the JSON Schema of their content before they are read, so that
+//! every mistake is reported at once, with the key it is under and its line
+//! and column in the file (e.g. `beacons.production.method-policy: "put" is
+//! not one of ["get","post","get-then-post"] (line 3, column 1)`). The
+//! positions of the keys are read from the events of the YAML parser (which
+//! reads JSON too), and from the tables and keys of the lines of TOML.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use jsonschema::error::ValidationErrorKind;
+use jsonschema::JSONSchema;
+use serde::de::DeserializeOwned;
+use yaml_rust::parser::{Event, MarkedEventReceiver, Parser};
+use yaml_rust::scanner::Marker;
+
+use crate::error::VerifierError;
+use crate::Json;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Syntax {
+	Toml,
+	Yaml,
+	Json,
+}
+
+impl Syntax {
+	/// Syntax of the file at `path` after its extension, `default` if it has none of the known ones
+	pub fn of(path: &Path, default: Self) -> Self {
+		match path.extension().and_then(|extension| extension.to_str()) {
+			Some("toml") => Self::Toml,
+			Some("yaml" | "yml") => Self::Yaml,
+			Some("json") => Self::Json,
+			_ => default,
+		}
+	}
+
+	/// Reads `content`, the syntax errors coming with their line
+	pub fn deserialize<T: DeserializeOwned>(self, content: &str) -> Result<T, String> {
+		match self {
+			Self::Toml => toml::from_str(content).map_err(|e| e.to_string()),
+			Self::Yaml => serde_yaml::from_str(content).map_err(|e| e.to_string()),
+			Self::Json => serde_json::from_str(content).map_err(|e| e.to_string()),
+		}
+	}
+
+	/// Line and column of each key of `content`, by its path
+	fn positions(self, content: &str) -> BTreeMap<Vec<String>, (usize, usize)> {
+		match self {
+			Self::Toml => toml_positions(content),
+			Self::Yaml | Self::Json => {
+				let mut receiver = Receiver::default();
+				match Parser::new(content.chars()).load(&mut receiver, false) {
+					Ok(()) => receiver.positions,
+					Err(_) => BTreeMap::new(),
+				}
+			},
+		}
+	}
+}
+
+/// Problems of `content` against `schema`, each one after the key it is under, with its line and column
+pub fn problems(content: &str, syntax: Syntax, schema: &Json) -> Result<Vec<String>, String> {
+	let json = syntax.deserialize::<Json>(content)?;
+	let schema = JSONSchema::options()
+		.should_validate_formats(true)
+		.compile(schema)
+		.map_err(|e| format!("invalid schema: {}", e))?;
+	let errors = match schema.validate(&json) {
+		Ok(()) => return Ok(Vec::new()),
+		Err(errors) => errors,
+	};
+	let positions = syntax.positions(content);
+	let problems = errors
+		.map(|e| {
+			let mut path = e
+				.instance_path
+				.to_string()
+				.split('/')
+				.skip(1)
+				.map(String::from)
+				.collect::<Vec<_>>();
+			let key = path.join(".");
+			let problem = if key.is_empty() {
+				e.to_string()
+			}
+			else {
+				format!("{}: {}", key, e)
+			};
+			// At the first unexpected key rather than at the object
+			if let ValidationErrorKind::AdditionalProperties { unexpected } = &e.kind {
+				path.extend(unexpected.first().cloned());
+			}
+			match locate(&positions, &path) {
+				Some((line, column)) => format!("{} (line {}, column {})", problem, line, column),
+				None => problem,
+			}
+		})
+		.collect();
+	Ok(problems)
+}
+
+/// Position of the deepest key of `path` in the file
+fn locate(positions: &BTreeMap<Vec<String>, (usize, usize)>, path: &[String]) -> Option<(usize, usize)> {
+	(1..=path.len())
+		.rev()
+		.find_map(|end| positions.get(&path[..end]).copied())
+}
+
+/// Keys of a dotted TOML key (e.g. `beacons."production".api-key`)
+fn toml_keys(dotted: &str) -> Vec<String> {
+	dotted
+		.split('.')
+		.map(|key| key.trim().trim_matches('"').trim_matches('\'').to_string())
+		.collect()
+}
+
+/// Positions of the tables and of the keys of the TOML `content`, the tables of an array numbered in their order
+fn toml_positions(content: &str) -> BTreeMap<Vec<String>, (usize, usize)> {
+	let mut positions = BTreeMap::new();
+	let mut arrays = BTreeMap::<Vec<String>, usize>::new();
+	let mut table = Vec::new();
+	for (number, line) in content.lines().enumerate() {
+		let trimmed = line.trim_start();
+		let position = (number + 1, line.len() - trimmed.len() + 1);
+		if let Some(header) = trimmed.strip_prefix("[[") {
+			let array = toml_keys(header.split("]]").next().unwrap_or_default());
+			positions.entry(array.clone()).or_insert(position);
+			let index = arrays.entry(array.clone()).or_insert(0);
+			table = array;
+			table.push(index.to_string());
+			*index += 1;
+			positions.entry(table.clone()).or_insert(position);
+		}
+		else if let Some(header) = trimmed.strip_prefix('[') {
+			table = toml_keys(header.split(']').next().unwrap_or_default());
+			positions.entry(table.clone()).or_insert(position);
+		}
+		else if let Some((key, _)) = trimmed.split_once('=').filter(|_| !trimmed.starts_with('#')) {
+			let mut path = table.clone();
+			path.extend(toml_keys(key));
+			positions.entry(path).or_insert(position);
+		}
+	}
+	positions
+}
+
+/// Collection the YAML parser is in: a mapping with the key of its next value, or a sequence with its index
+enum Frame {
+	Mapping(Option<String>),
+	Sequence(usize),
+}
+
+/// Positions of the keys and of the values of a YAML document
+#[derive(Default)]
+struct Receiver {
+	path: Vec<String>,
+	frames: Vec<Frame>,
+	/// Collections started, at the position of their first node (the parser marks a block mapping after its first key)
+	pending: Vec<(Vec<String>, Marker)>,
+	positions: BTreeMap<Vec<String>, (usize, usize)>,
+}
+
+impl Receiver {
+	/// Key of the next value in the collection, none at the root
+	fn segment(&self) -> Option<String> {
+		match self.frames.last()? {
+			Frame::Mapping(key) => Some(key.clone().unwrap_or_default()),
+			Frame::Sequence(index) => Some(index.to_string()),
+		}
+	}
+
+	fn record(&mut self, segment: Option<String>, mark: Marker) {
+		let mut path = self.path.clone();
+		path.extend(segment);
+		for (collection, _) in std::mem::take(&mut self.pending) {
+			self.positions
+				.entry(collection)
+				.or_insert((mark.line(), mark.col() + 1));
+		}
+		self.positions.entry(path).or_insert((mark.line(), mark.col() + 1));
+	}
+
+	/// Moves past the value of the collection
+	fn advance(&mut self) {
+		match self.frames.last_mut() {
+			Some(Frame::Mapping(key)) => *key = None,
+			Some(Frame::Sequence(index)) => *index += 1,
+			None => (),
+		}
+	}
+}
+
+impl MarkedEventReceiver for Receiver {
+	fn on_event(&mut self, event: Event, mark: Marker) {
+		match event {
+			Event::Scalar(value, ..) => {
+				if let Some(Frame::Mapping(key @ None)) = self.frames.last_mut() {
+					*key = Some(value);
+					self.record(self.segment(), mark);
+				}
+				else {
+					self.record(self.segment(), mark);
+					self.advance();
+				}
+			},
+			Event::Alias(_) => {
+				self.record(self.segment(), mark);
+				self.advance();
+			},
+			Event::MappingStart(_) | Event::SequenceStart(_) => {
+				let segment = self.segment();
+				let mut path = self.path.clone();
+				path.extend(segment.clone());
+				self.pending.push((path, mark));
+				self.path.extend(segment);
+				self.frames.push(match event {
+					Event::MappingStart(_) => Frame::Mapping(None),
+					_ => Frame::Sequence(0),
+				});
+			},
+			Event::MappingEnd | Event::SequenceEnd => {
+				// Empty, at its start
+				for (collection, mark) in std::mem::take(&mut self.pending) {
+					self.positions
+						.entry(collection)
+						.or_insert((mark.line(), mark.col() + 1));
+				}
+				self.frames.pop();
+				if !self.frames.is_empty() {
+					self.path.pop();
+				}
+				self.advance();
+			},
+			_ => (),
+		}
+	}
+}
+
+/// Reads the file at `path` (of `default` syntax if its extension is none of the known ones), first checking it
+/// against `schema`
+pub fn load<T: DeserializeOwned>(path: &Path, default: Syntax, schema: &Json) -> Result<T, VerifierError> {
+	let content = std::fs::read_to_string(path)?;
+	let syntax = Syntax::of(path, default);
+	let problems = problems(&content, syntax, schema).map_err(VerifierError::BadConfig)?;
+	if !problems.is_empty() {
+		return Err(VerifierError::BadConfig(problems.join("\n")));
+	}
+	syntax.deserialize(&content).map_err(VerifierError::BadConfig)
+}
+
+#[cfg(test)]
+mod tests {
+
+	use serde_json::json;
+
+	use crate::syntax::{self, Syntax};
+
+	#[test]
+	fn test_positions() {
+		let schema = json!({
+			"type": "object",
+			"additionalProperties": false,
+			"properties": {
+				"name": { "type": "string" },
+				"checks": { "type": "array", "items": { "type": "object", "required": ["entity"] } }
+			}
+		});
+		let yaml = "name: network\nchecks:\n  - entity: Individual\n  - category: framework\nunknown: 1\n";
+		let problems = syntax::problems(yaml, Syntax::Yaml, &schema).unwrap();
+		assert_eq!(problems.len(), 2, "{:?}", problems);
+		assert!(problems[0].starts_with("checks.1: "), "{:?}", problems);
+		assert!(problems[0].ends_with("(line 4, column 5)"), "{:?}", problems);
+		assert!(problems[1].ends_with("(line 5, column 1)"), "{:?}", problems);
+
+		let json = "{\n  \"name\": 42,\n  \"checks\": []\n}";
+		let problems = syntax::problems(json, Syntax::Json, &schema).unwrap();
+		assert_eq!(problems, vec!["name: 42 is not of type \"string\" (line 2, column 3)"]);
+
+		let toml = "name = \"network\"\n\n[[checks]]\nentity = \"Individual\"\n\n[[checks]]\n  category = \"model\"\n";
+		let problems = syntax::problems(toml, Syntax::Toml, &schema).unwrap();
+		assert_eq!(problems.len(), 1, "{:?}", problems);
+		assert!(problems[0].ends_with("(line 6, column 1)"), "{:?}", problems);
+
+		assert!(syntax::problems("name: a: b", Syntax::Yaml, &schema)
+			.unwrap_err()
+			.contains("line 1"));
+	}
+}
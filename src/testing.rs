@@ -0,0 +1,324 @@
+//! Test support: a mock beacon served with `wiremock` and canned behaviors.
+//!
+//! Every behavior serves the same beacon (individuals, biosamples and datasets)
+//! and breaks it in one specific way, so each check can be exercised both
+//! against a conformant implementation and against a faulty one.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use serde_json::json;
+use tokio::runtime::Runtime;
+use url::Url;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, Request, Respond, ResponseTemplate};
+
+use crate::beacon::Beacon;
+use crate::cache::SchemaCache;
+use crate::client::Client;
+use crate::formats::Formats;
+use crate::framework::Framework;
+use crate::model::Model;
+use crate::output::{BeaconOutput, RunConfiguration, SpecSource};
+use crate::scope::Access;
+use crate::{render, Json};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Behavior {
+	/// Every endpoint responds following the framework and the model
+	Conformant,
+	/// Entity endpoints respond with a `meta` without `returnedGranularity`
+	BadMeta,
+	/// Entity endpoints respond with a count that is not a valid number of results
+	WrongCounts,
+	/// `/configuration` and the biosamples endpoints are not implemented
+	MissingEndpoints,
+}
+
+pub struct MockBeacon {
+	behavior: Behavior,
+	server: MockServer,
+	runtime: Runtime,
+}
+
+impl MockBeacon {
+	pub fn start(behavior: Behavior) -> Self {
+		let runtime = tokio::runtime::Builder::new_multi_thread()
+			.enable_all()
+			.build()
+			.expect("Could not start the mock beacon runtime");
+		let server = runtime.block_on(MockServer::start());
+		let beacon = Self {
+			behavior,
+			server,
+			runtime,
+		};
+		for (route, response) in routes(behavior) {
+			beacon.mount_with_priority("GET", route, ResponseTemplate::new(200).set_body_json(response), 5);
+		}
+		beacon
+	}
+
+	/// Serves `template` on `route`, overriding the canned response of the behavior
+	pub fn mount(&self, route: &str, template: impl Respond + 'static) {
+		self.mount_method("GET", route, template);
+	}
+
+	/// Serves `template` on `route` for requests with the HTTP method `http_method`
+	pub fn mount_method(&self, http_method: &str, route: &str, template: impl Respond + 'static) {
+		self.mount_with_priority(http_method, route, template, 1);
+	}
+
+	/// Mounts a mock with its own matchers (e.g. on headers)
+	pub fn mount_mock(&self, mock: Mock) {
+		self.runtime.block_on(mock.mount(&self.server));
+	}
+
+	fn mount_with_priority(&self, http_method: &str, route: &str, template: impl Respond + 'static, priority: u8) {
+		self.runtime.block_on(
+			Mock::given(method(http_method))
+				.and(path(route))
+				.respond_with(template)
+				.with_priority(priority)
+				.mount(&self.server),
+		);
+	}
+
+	/// Requests received so far
+	pub fn received_requests(&self) -> Vec<Request> {
+		self.runtime
+			.block_on(self.server.received_requests())
+			.unwrap_or_default()
+	}
+
+	/// Canned response served on `route` by the behavior of this beacon
+	pub fn response(&self, route: &str) -> Json {
+		routes(self.behavior).remove(route).unwrap_or(Json::Null)
+	}
+
+	pub fn url(&self) -> Url {
+		Url::parse(&self.server.uri()).expect("Mock beacon uri is not a valid url")
+	}
+
+	/// Mock beacon ready to be verified against the fixture framework and model
+	pub fn beacon(&self) -> Beacon {
+		self.beacon_with_client(Client::new())
+	}
+
+	pub fn beacon_with_client(&self, client: Client) -> Beacon {
+		Beacon::new(Some(model()), framework(), client, &self.url()).expect("Unable to reach the mock beacon")
+	}
+
+	pub fn verify(&self) -> BeaconOutput {
+		self.beacon().validate()
+	}
+}
+
+pub fn fixture_url(name: &str) -> Url {
+	let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+		.join("tests")
+		.join("fixtures")
+		.join(name);
+	Url::from_directory_path(dir).expect("Fixture path is not absolute")
+}
+
+pub fn framework() -> Framework {
+	Framework::load(&fixture_url("framework"), &SchemaCache::default()).expect("Loading fixture framework failed")
+}
+
+pub fn model() -> Model {
+	Model::load(&fixture_url("model"), &Formats::default(), &SchemaCache::default())
+		.expect("Loading fixture model failed")
+}
+
+/// Configuration of a default run against the mock beacon
+pub fn configuration(beacon: &MockBeacon) -> RunConfiguration {
+	RunConfiguration {
+		version: env!("CARGO_PKG_VERSION").into(),
+		target: None,
+		url: beacon.url(),
+		framework: SpecSource {
+			location: fixture_url("framework"),
+			revision: None,
+		},
+		model: Some(SpecSource {
+			location: fixture_url("model"),
+			revision: None,
+		}),
+		spec_version: None,
+		entities: model().entities.into_iter().map(|entity| entity.name).collect(),
+		only: None,
+		method_policy: Default::default(),
+		endpoint_method_policies: BTreeMap::new(),
+		access: Access::Anonymous,
+		disabled_formats: Vec::new(),
+		observed_data: false,
+		public_report: false,
+		timings: false,
+		incremental: false,
+		history: false,
+		retries: 0,
+		time_budget_secs: None,
+		robustness: false,
+		pagination: false,
+		limits: false,
+		adaptive: false,
+		filters: false,
+		post_queries: false,
+		negative: false,
+		references: false,
+		resolve_ontologies: false,
+		variant_queries: false,
+		max_instances: None,
+		sample_seed: None,
+		head: false,
+		discover: false,
+		min_tls_version: None,
+		min_http_version: None,
+		slow_ms: None,
+		timeout_ms: None,
+		request_retries: 0,
+		request_backoff_ms: None,
+		output_format: render::DEFAULT.into(),
+	}
+}
+
+fn routes(behavior: Behavior) -> BTreeMap<&'static str, Json> {
+	let mut routes = BTreeMap::new();
+	routes.insert("/info", info());
+	routes.insert("/configuration", framework_response(json!({ "entryTypes": {} })));
+	routes.insert("/map", framework_response(json!({ "endpointSets": {} })));
+	routes.insert("/entry_types", framework_response(json!({ "entryTypes": {} })));
+	routes.insert(
+		"/individuals",
+		resultsets("individual", vec![individual("ind1"), individual("ind2")]),
+	);
+	routes.insert("/individuals/ind1", resultsets("individual", vec![individual("ind1")]));
+	routes.insert(
+		"/individuals/ind1/biosamples",
+		resultsets("biosample", vec![biosample("bio1")]),
+	);
+	routes.insert(
+		"/biosamples",
+		resultsets("biosample", vec![biosample("bio1"), biosample("bio2")]),
+	);
+	routes.insert("/biosamples/bio1", resultsets("biosample", vec![biosample("bio1")]));
+	routes.insert("/datasets", collections(vec![dataset("ds1")]));
+	routes.insert("/datasets/ds1", collections(vec![dataset("ds1")]));
+
+	match behavior {
+		Behavior::Conformant => (),
+		Behavior::BadMeta => {
+			for (_, response) in routes.iter_mut().filter(|(route, _)| is_entity_route(route)) {
+				response["meta"] = json!({ "apiVersion": "v2.0.0" });
+			}
+		},
+		Behavior::WrongCounts => {
+			for (_, response) in routes.iter_mut().filter(|(route, _)| is_entity_route(route)) {
+				response["meta"]["returnedGranularity"] = json!("count");
+				response["responseSummary"]["numTotalResults"] = json!(-1);
+			}
+		},
+		Behavior::MissingEndpoints => {
+			routes.retain(|route, _| *route != "/configuration" && !route.contains("biosamples"));
+		},
+	}
+
+	routes
+}
+
+fn is_entity_route(route: &str) -> bool {
+	!matches!(route, "/info" | "/configuration" | "/map" | "/entry_types")
+}
+
+fn meta(granularity: &str, entity_type: &str) -> Json {
+	json!({
+		"beaconId": "org.example.beacon",
+		"apiVersion": "v2.0.0",
+		"returnedGranularity": granularity,
+		"returnedSchemas": [{
+			"entityType": entity_type,
+			"schema": format!("beacon-{}-v2.0.0", entity_type),
+		}],
+	})
+}
+
+fn info() -> Json {
+	framework_response(json!({
+		"id": "org.example.beacon",
+		"name": "Mock Beacon",
+		"apiVersion": "v2.0.0",
+		"updateDateTime": "2022-03-01T12:00:00Z",
+		"organization": {
+			"id": "org.example",
+			"name": "Example Organization",
+			"logoUrl": "https://example.org/logo.png",
+		},
+	}))
+}
+
+fn framework_response(response: Json) -> Json {
+	json!({
+		"meta": {
+			"beaconId": "org.example.beacon",
+			"apiVersion": "v2.0.0",
+			"returnedSchemas": [],
+		},
+		"response": response,
+	})
+}
+
+fn resultsets(entity_type: &str, results: Vec<Json>) -> Json {
+	json!({
+		"meta": meta("record", entity_type),
+		"responseSummary": {
+			"exists": !results.is_empty(),
+			"numTotalResults": results.len(),
+		},
+		"response": {
+			"resultSets": [{
+				"id": "ds1",
+				"setType": "dataset",
+				"exists": !results.is_empty(),
+				"resultsCount": results.len(),
+				"results": results,
+			}],
+		},
+	})
+}
+
+fn collections(collections: Vec<Json>) -> Json {
+	json!({
+		"meta": meta("record", "dataset"),
+		"responseSummary": {
+			"exists": !collections.is_empty(),
+			"numTotalResults": collections.len(),
+		},
+		"response": {
+			"collections": collections,
+		},
+	})
+}
+
+fn individual(id: &str) -> Json {
+	json!({
+		"id": id,
+		"sex": { "id": "NCIT:C16576", "label": "female" },
+	})
+}
+
+fn biosample(id: &str) -> Json {
+	json!({
+		"id": id,
+		"individualId": "ind1",
+		"biosampleStatus": { "id": "EFO:0009654", "label": "reference sample" },
+	})
+}
+
+fn dataset(id: &str) -> Json {
+	json!({
+		"id": id,
+		"name": "Mock dataset",
+		"updateDateTime": "2022-03-01T12:00:00Z",
+	})
+}
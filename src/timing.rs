@@ -0,0 +1,117 @@
+//! Timing breakdown of the requests
+//!
+//! `reqwest` does not expose the phases of a request, so the connection phases
+//! (name resolution, TCP connect and TLS handshake) are estimated on a separate,
+//! fresh and direct connection to the same host, which the report flags with
+//! `connection_estimated`. Through a proxy, that connection would not take the
+//! path of the requests, so the connection phases are left out. Time to first
+//! byte and body download are measured on the request itself.
+
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::client;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Timings {
+	/// Name resolution
+	pub dns_ms: Option<u128>,
+	/// TCP connect
+	pub connect_ms: Option<u128>,
+	/// TLS handshake (only for https)
+	pub tls_ms: Option<u128>,
+	/// Whether the connection phases are estimates, measured on another connection than the one of the request
+	#[serde(default)]
+	pub connection_estimated: bool,
+	/// From sending the request to receiving the headers of the response
+	pub ttfb_ms: u128,
+	/// Reading the body of the response
+	pub download_ms: u128,
+}
+
+impl Timings {
+	/// Estimates the connection phases on a fresh connection to the host of `url`, unless the requests to it go
+	/// through a proxy (`proxy` of the client, else the one of the environment)
+	///
+	/// Phases that cannot be measured (e.g. the host does not resolve) are left empty.
+	pub fn connection(url: &Url, timeout: Duration, proxy: Option<&Url>) -> Self {
+		let mut timings = Self::default();
+		let (host, port) = match (url.host_str(), url.port_or_known_default()) {
+			(Some(host), Some(port)) => (host, port),
+			_ => return timings,
+		};
+		if client::proxy_of(proxy, url.scheme(), host).is_some() {
+			log::debug!(
+				"Not estimating the connection phases to {}, reached through a proxy",
+				host
+			);
+			return timings;
+		}
+		timings.connection_estimated = true;
+
+		let start = Instant::now();
+		let addr = match (host, port).to_socket_addrs().ok().and_then(|mut addrs| addrs.next()) {
+			Some(addr) => addr,
+			None => {
+				log::debug!("Unable to resolve {}", host);
+				return timings;
+			},
+		};
+		timings.dns_ms = Some(start.elapsed().as_millis());
+
+		let start = Instant::now();
+		let stream = match TcpStream::connect_timeout(&addr, timeout) {
+			Ok(stream) => stream,
+			Err(e) => {
+				log::debug!("Unable to connect to {}: {}", addr, e);
+				return timings;
+			},
+		};
+		timings.connect_ms = Some(start.elapsed().as_millis());
+
+		if url.scheme() == "https" {
+			let start = Instant::now();
+			let _ = stream.set_read_timeout(Some(timeout));
+			let _ = stream.set_write_timeout(Some(timeout));
+			match native_tls::TlsConnector::new().map(|connector| connector.connect(host, stream)) {
+				Ok(Ok(_)) => timings.tls_ms = Some(start.elapsed().as_millis()),
+				Ok(Err(e)) => log::debug!("TLS handshake with {} failed: {}", host, e),
+				Err(e) => log::debug!("Unable to set up TLS: {}", e),
+			}
+		}
+
+		timings
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use std::time::Duration;
+
+	use url::Url;
+
+	use crate::testing::{Behavior, MockBeacon};
+	use crate::timing::Timings;
+
+	#[test]
+	fn test_connection_phases() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let timings = Timings::connection(&beacon.url(), Duration::from_secs(5), None);
+		assert!(timings.dns_ms.is_some());
+		assert!(timings.connect_ms.is_some());
+		// The mock beacon is served over plain http
+		assert!(timings.tls_ms.is_none());
+	}
+
+	#[test]
+	fn test_unreachable_host() {
+		let url = Url::parse("http://127.0.0.1:9/").unwrap();
+		let timings = Timings::connection(&url, Duration::from_secs(1), None);
+		assert!(timings.dns_ms.is_some());
+		assert!(timings.connect_ms.is_none());
+	}
+}
@@ -0,0 +1,305 @@
+//! Certificates of the TLS connections to the beacons
+//!
+//! The beacons of federated deployments are often served with the certificates
+//! of a private authority, and some of them ask for a certificate of the client
+//! (mutual TLS). `--ca-cert` trusts the certificates of a file (a PEM bundle of
+//! one or more certificates, or a single DER one) besides the ones of the
+//! system, and `--client-cert` presents a certificate: a PKCS #12 file, or a
+//! PEM certificate (followed by its chain, if any) with its PEM key given with
+//! `--client-key`, which is only supported where OpenSSL is the TLS backend.
+//! `--client-key-password` decrypts the PKCS #12 file or the key.
+//!
+//! The certificates are kept encoded next to the ones of `reqwest`, for the
+//! connections the verifier opens itself (the negotiation of the protocols).
+
+use std::path::Path;
+
+use crate::error::VerifierError;
+
+const PEM_BEGIN: &str = "-----BEGIN CERTIFICATE-----";
+const PEM_END: &str = "-----END CERTIFICATE-----";
+
+/// Certificate of an authority trusted besides the ones of the system
+#[derive(Debug, Clone)]
+pub struct Certificate {
+	pub certificate: reqwest::Certificate,
+	/// PEM, or else DER
+	pub encoded: Vec<u8>,
+	pub pem: bool,
+}
+
+/// Certificate of the client with its key
+#[derive(Clone)]
+pub struct Identity {
+	pub identity: reqwest::Identity,
+	/// PKCS #12 archive of the certificate (with its chain) and of the key
+	pub pkcs12: Vec<u8>,
+	pub password: String,
+}
+
+impl std::fmt::Debug for Identity {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("Identity").finish_non_exhaustive()
+	}
+}
+
+fn bad(path: &Path, e: impl std::fmt::Display) -> VerifierError {
+	VerifierError::BadTls(format!("{}: {}", path.display(), e))
+}
+
+/// Certificates of the file at `path`, in PEM or else in DER
+pub fn ca_certificates(path: &Path) -> Result<Vec<Certificate>, VerifierError> {
+	let content = std::fs::read(path)?;
+	let text = String::from_utf8_lossy(&content);
+	let pems = text
+		.split_inclusive(PEM_END)
+		.filter_map(|block| block.find(PEM_BEGIN).map(|start| &block[start..]))
+		.filter(|block| block.ends_with(PEM_END))
+		.collect::<Vec<_>>();
+	if pems.is_empty() {
+		return Ok(vec![Certificate {
+			certificate: reqwest::Certificate::from_der(&content).map_err(|e| bad(path, e))?,
+			encoded: content,
+			pem: false,
+		}]);
+	}
+	pems.iter()
+		.map(|pem| {
+			Ok(Certificate {
+				certificate: reqwest::Certificate::from_pem(pem.as_bytes()).map_err(|e| bad(path, e))?,
+				encoded: pem.as_bytes().to_vec(),
+				pem: true,
+			})
+		})
+		.collect()
+}
+
+/// Certificate of the client with its key: the PKCS #12 file at `cert`, or the PEM certificate at `cert` with the PEM
+/// key at `key`
+pub fn identity(cert: &Path, key: Option<&Path>, password: Option<&str>) -> Result<Identity, VerifierError> {
+	let password = password.unwrap_or_default();
+	let der = match key {
+		Some(key) => pkcs12(cert, key, password)?,
+		None => std::fs::read(cert)?,
+	};
+	Ok(Identity {
+		identity: reqwest::Identity::from_pkcs12_der(&der, password).map_err(|e| bad(cert, e))?,
+		pkcs12: der,
+		password: password.to_string(),
+	})
+}
+
+/// PKCS #12 archive of the PEM certificate at `cert` (with its chain) and the PEM key at `key`
+#[cfg(not(any(target_os = "windows", target_vendor = "apple")))]
+fn pkcs12(cert: &Path, key: &Path, password: &str) -> Result<Vec<u8>, VerifierError> {
+	use openssl::pkcs12::Pkcs12;
+	use openssl::pkey::PKey;
+	use openssl::stack::Stack;
+	use openssl::x509::X509;
+
+	let mut certs = X509::stack_from_pem(&std::fs::read(cert)?)
+		.map_err(|e| bad(cert, e))?
+		.into_iter();
+	let leaf = certs.next().ok_or_else(|| bad(cert, "no certificate in the file"))?;
+	let key_pem = std::fs::read(key)?;
+	let pkey = if password.is_empty() {
+		PKey::private_key_from_pem(&key_pem)
+	}
+	else {
+		PKey::private_key_from_pem_passphrase(&key_pem, password.as_bytes())
+	}
+	.map_err(|e| bad(key, e))?;
+	let mut builder = Pkcs12::builder();
+	let chain = certs.collect::<Vec<_>>();
+	if !chain.is_empty() {
+		let mut stack = Stack::new().map_err(|e| bad(cert, e))?;
+		for chain_cert in chain {
+			stack.push(chain_cert).map_err(|e| bad(cert, e))?;
+		}
+		builder.ca(stack);
+	}
+	builder
+		.build(password, "beacon-verifier", &pkey, &leaf)
+		.and_then(|pkcs12| pkcs12.to_der())
+		.map_err(|e| bad(cert, e))
+}
+
+#[cfg(any(target_os = "windows", target_vendor = "apple"))]
+fn pkcs12(cert: &Path, _key: &Path, _password: &str) -> Result<Vec<u8>, VerifierError> {
+	Err(bad(
+		cert,
+		"PEM client certificates are only supported with OpenSSL, use a PKCS #12 file",
+	))
+}
+
+#[cfg(all(test, not(any(target_os = "windows", target_vendor = "apple"))))]
+mod tests {
+
+	use std::io::{Read, Write};
+	use std::net::{TcpListener, TcpStream};
+	use std::path::Path;
+	use std::sync::atomic::{AtomicUsize, Ordering};
+	use std::sync::Arc;
+
+	use openssl::asn1::Asn1Time;
+	use openssl::hash::MessageDigest;
+	use openssl::pkey::{PKey, Private};
+	use openssl::rsa::Rsa;
+	use openssl::ssl::{SslAcceptor, SslMethod, SslVerifyMode};
+	use openssl::x509::extension::SubjectAlternativeName;
+	use openssl::x509::{X509Name, X509};
+	use url::Url;
+
+	use crate::client::Client;
+	use crate::error::VerifierError;
+	use crate::{protocol, tls};
+
+	/// Self-signed certificate of `localhost`, with its key
+	fn self_signed(name: &str) -> (X509, PKey<Private>) {
+		let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+		let mut subject = X509Name::builder().unwrap();
+		subject.append_entry_by_text("CN", name).unwrap();
+		let subject = subject.build();
+		let mut cert = X509::builder().unwrap();
+		cert.set_version(2).unwrap();
+		cert.set_subject_name(&subject).unwrap();
+		cert.set_issuer_name(&subject).unwrap();
+		cert.set_pubkey(&key).unwrap();
+		cert.set_not_before(&Asn1Time::days_from_now(0).unwrap()).unwrap();
+		cert.set_not_after(&Asn1Time::days_from_now(1).unwrap()).unwrap();
+		let san = SubjectAlternativeName::new()
+			.dns("localhost")
+			.build(&cert.x509v3_context(None, None))
+			.unwrap();
+		cert.append_extension(san).unwrap();
+		cert.sign(&key, MessageDigest::sha256()).unwrap();
+		(cert.build(), key)
+	}
+
+	/// Beacon answering `/info` over TLS with `server`, to the clients presenting `client` only
+	fn mutual_tls_beacon(server: (X509, PKey<Private>), client: X509) -> Url {
+		let mut acceptor = SslAcceptor::mozilla_intermediate(SslMethod::tls()).unwrap();
+		acceptor.set_certificate(&server.0).unwrap();
+		acceptor.set_private_key(&server.1).unwrap();
+		acceptor.cert_store_mut().add_cert(client).unwrap();
+		acceptor.set_verify(SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT);
+		let acceptor = acceptor.build();
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let port = listener.local_addr().unwrap().port();
+		std::thread::spawn(move || {
+			for stream in listener.incoming().flatten() {
+				if let Ok(mut stream) = acceptor.accept(stream) {
+					let mut request = [0; 4096];
+					let _ = stream.read(&mut request);
+					let body = r#"{"meta": {}, "response": {}}"#;
+					let response = format!(
+						"HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{}",
+						body.len(),
+						body
+					);
+					let _ = stream.write_all(response.as_bytes());
+				}
+			}
+		});
+		Url::parse(&format!("https://localhost:{}/info", port)).unwrap()
+	}
+
+	/// Proxy tunnelling the `CONNECT` requests, counting them
+	fn tunnelling_proxy(tunnels: Arc<AtomicUsize>) -> Url {
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let port = listener.local_addr().unwrap().port();
+		std::thread::spawn(move || {
+			for mut client in listener.incoming().flatten() {
+				let mut head = Vec::new();
+				let mut byte = [0];
+				while !head.ends_with(b"\r\n\r\n") && client.read(&mut byte).unwrap_or(0) == 1 {
+					head.push(byte[0]);
+				}
+				let head = String::from_utf8_lossy(&head).into_owned();
+				let target = head
+					.split_whitespace()
+					.nth(1)
+					.unwrap_or_default()
+					.replace("localhost", "127.0.0.1");
+				let server = match TcpStream::connect(target) {
+					Ok(server) => server,
+					Err(_) => continue,
+				};
+				tunnels.fetch_add(1, Ordering::SeqCst);
+				client
+					.write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")
+					.unwrap();
+				let (mut client_reader, mut server_writer) = (client.try_clone().unwrap(), server.try_clone().unwrap());
+				std::thread::spawn(move || std::io::copy(&mut client_reader, &mut server_writer));
+				let (mut server_reader, mut client_writer) = (server, client);
+				std::thread::spawn(move || std::io::copy(&mut server_reader, &mut client_writer));
+			}
+		});
+		Url::parse(&format!("http://127.0.0.1:{}", port)).unwrap()
+	}
+
+	fn write(dir: &Path, name: &str, content: &[u8]) -> std::path::PathBuf {
+		let path = dir.join(name);
+		std::fs::write(&path, content).unwrap();
+		path
+	}
+
+	#[test]
+	fn test_certificates() {
+		let dir = tempfile::tempdir().unwrap();
+		let (server_cert, server_key) = self_signed("beacon");
+		let (client_cert, client_key) = self_signed("verifier");
+		let url = mutual_tls_beacon((server_cert.clone(), server_key), client_cert.clone());
+
+		let bundle = [server_cert.to_pem().unwrap(), client_cert.to_pem().unwrap()].concat();
+		let ca = write(dir.path(), "ca.pem", &bundle);
+		assert_eq!(tls::ca_certificates(&ca).unwrap().len(), 2);
+		let der = write(dir.path(), "ca.der", &server_cert.to_der().unwrap());
+		assert_eq!(tls::ca_certificates(&der).unwrap().len(), 1);
+		let garbage = write(dir.path(), "garbage.pem", b"not a certificate");
+		assert!(matches!(tls::ca_certificates(&garbage), Err(VerifierError::BadTls(_))));
+
+		let cert = write(dir.path(), "client.pem", &client_cert.to_pem().unwrap());
+		let key = write(
+			dir.path(),
+			"client.key",
+			&client_key.private_key_to_pem_pkcs8().unwrap(),
+		);
+		let identity = tls::identity(&cert, Some(&key), None).unwrap();
+
+		// The private authority and the client certificate are both needed
+		let client = Client::new()
+			.root_certificates(tls::ca_certificates(&ca).unwrap())
+			.identity(identity.clone());
+		assert!(client.query("info", &url).is_ok());
+		assert!(Client::new().identity(identity).query("info", &url).is_err());
+		let client = Client::new().root_certificates(tls::ca_certificates(&ca).unwrap());
+		assert!(client.query("info", &url).is_err());
+
+		// PKCS #12
+		let pkcs12 = openssl::pkcs12::Pkcs12::builder()
+			.build("s3cr3t", "verifier", &client_key, &client_cert)
+			.unwrap();
+		let p12 = write(dir.path(), "client.p12", &pkcs12.to_der().unwrap());
+		let identity = tls::identity(&p12, None, Some("s3cr3t")).unwrap();
+		let client = Client::new()
+			.root_certificates(tls::ca_certificates(&ca).unwrap())
+			.identity(identity);
+		assert!(client.query("info", &url).is_ok());
+		assert!(tls::identity(&p12, None, Some("wrong")).is_err());
+
+		// The protocols are negotiated the way of the requests, through the proxy
+		let tunnels = Arc::new(AtomicUsize::new(0));
+		let client = Client::new()
+			.proxy(tunnelling_proxy(tunnels.clone()))
+			.root_certificates(tls::ca_certificates(&ca).unwrap())
+			.identity(tls::identity(&p12, None, Some("s3cr3t")).unwrap());
+		assert!(client.query("info", &url).is_ok());
+		let requests = tunnels.load(Ordering::SeqCst);
+		let protocols = client.protocols();
+		assert_eq!(tunnels.load(Ordering::SeqCst), requests + 1);
+		let (_, protocol) = &protocols[&protocol::host(&url)];
+		assert!(protocol.tls_version.is_some());
+		assert_eq!(protocol.alpn, None);
+	}
+}
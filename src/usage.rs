@@ -0,0 +1,130 @@
+//! Resources used by the verifier during a run
+//!
+//! The peak memory and the CPU time of the process, with the requests sent by
+//! the client and the bytes of their bodies, are reported with the output of
+//! every run and kept in the history, to size the verification service and to
+//! catch the regressions of the verifier itself. The memory and the CPU time
+//! are only measured on Unix.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use serde::{Deserialize, Serialize};
+
+use crate::humanize::TimeStyle;
+
+/// Requests of a client and the bytes of their bodies, counted across its threads
+#[derive(Debug, Default)]
+pub struct Traffic {
+	requests: AtomicU64,
+	bytes_sent: AtomicU64,
+	bytes_received: AtomicU64,
+}
+
+impl Traffic {
+	/// Counts a request (every attempt of a retried one) with a body of `bytes`
+	pub fn sent(&self, bytes: usize) {
+		self.requests.fetch_add(1, Ordering::Relaxed);
+		self.bytes_sent.fetch_add(bytes as u64, Ordering::Relaxed);
+	}
+
+	/// Counts the body of a response
+	pub fn received(&self, bytes: usize) {
+		self.bytes_received.fetch_add(bytes as u64, Ordering::Relaxed);
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResourceUsage {
+	/// Peak resident set size of the process
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub peak_rss_bytes: Option<u64>,
+	/// User and system CPU time of the process
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub cpu_ms: Option<u64>,
+	pub requests: u64,
+	/// Bytes of the bodies of the requests and of the responses, without the headers
+	pub bytes_sent: u64,
+	pub bytes_received: u64,
+}
+
+impl ResourceUsage {
+	/// Resources used by the process so far, with the traffic of its client
+	pub fn measure(traffic: &Traffic) -> Self {
+		let (peak_rss_bytes, cpu_ms) = process();
+		Self {
+			peak_rss_bytes,
+			cpu_ms,
+			requests: traffic.requests.load(Ordering::Relaxed),
+			bytes_sent: traffic.bytes_sent.load(Ordering::Relaxed),
+			bytes_received: traffic.bytes_received.load(Ordering::Relaxed),
+		}
+	}
+
+	pub fn summary(&self, time_style: TimeStyle) {
+		let peak_rss = self.peak_rss_bytes.map_or_else(
+			|| "unknown".into(),
+			|bytes| format!("{:.1} MiB", bytes as f64 / 1024.0 / 1024.0),
+		);
+		let cpu = self.cpu_ms.map_or_else(|| "unknown".into(), |ms| time_style.millis(ms));
+		log::info!(
+			"Resource usage: peak RSS {}, CPU time {}, {} requests, {} bytes sent, {} bytes received",
+			peak_rss,
+			cpu,
+			self.requests,
+			self.bytes_sent,
+			self.bytes_received
+		);
+	}
+}
+
+/// Peak resident set size (in bytes) and CPU time (in milliseconds) of the process
+#[cfg(unix)]
+fn process() -> (Option<u64>, Option<u64>) {
+	let mut usage = std::mem::MaybeUninit::<libc::rusage>::uninit();
+	if unsafe { libc::getrusage(libc::RUSAGE_SELF, usage.as_mut_ptr()) } != 0 {
+		return (None, None);
+	}
+	let usage = unsafe { usage.assume_init() };
+	// Kilobytes, but bytes on macOS
+	let unit = if cfg!(target_vendor = "apple") { 1 } else { 1024 };
+	let milliseconds = |time: libc::timeval| time.tv_sec as u64 * 1000 + time.tv_usec as u64 / 1000;
+	(
+		Some(usage.ru_maxrss as u64 * unit),
+		Some(milliseconds(usage.ru_utime) + milliseconds(usage.ru_stime)),
+	)
+}
+
+#[cfg(not(unix))]
+fn process() -> (Option<u64>, Option<u64>) {
+	(None, None)
+}
+
+#[cfg(test)]
+mod tests {
+
+	use crate::testing::{Behavior, MockBeacon};
+	use crate::usage::{ResourceUsage, Traffic};
+
+	#[test]
+	fn test_measure() {
+		let traffic = Traffic::default();
+		traffic.sent(0);
+		traffic.sent(120);
+		traffic.received(2048);
+		let usage = ResourceUsage::measure(&traffic);
+		assert_eq!((usage.requests, usage.bytes_sent, usage.bytes_received), (2, 120, 2048));
+		if cfg!(unix) {
+			assert!(usage.peak_rss_bytes.unwrap() > 1024 * 1024);
+			assert!(usage.cpu_ms.is_some());
+		}
+	}
+
+	#[test]
+	fn test_resource_usage() {
+		let output = MockBeacon::start(Behavior::Conformant).verify();
+		let usage = output.resource_usage.unwrap();
+		assert!(usage.requests >= 8, "{:?}", usage);
+		assert_eq!(usage.bytes_sent, 0);
+		assert!(usage.bytes_received > 1000, "{:?}", usage);
+	}
+}
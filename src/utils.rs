@@ -1,14 +1,17 @@
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
-use std::rc::Rc;
+use std::sync::Arc;
 
-use jsonschema::JSONSchema;
+use git2::Repository;
+use jsonschema::{JSONSchema, ValidationError};
 use url::Url;
 
+use crate::client::Client;
 use crate::error::VerifierError;
-use crate::interface::{BeaconResultSetResponse, EntityResult};
-// TODO: Use filtering terms
-// use crate::interface::FilteringTerm;
-use crate::{error, Json};
+use crate::formats::Formats;
+use crate::interface::Granularity;
+use crate::resolver::{self, SchemaStore};
+use crate::Json;
 
 pub fn copy_dir_recursively<U: AsRef<Path>, V: AsRef<Path>>(from: U, to: V) -> Result<(), VerifierError> {
 	let mut stack = vec![PathBuf::from(from.as_ref())];
@@ -58,45 +61,37 @@ pub fn copy_dir_recursively<U: AsRef<Path>, V: AsRef<Path>>(from: U, to: V) -> R
 	Ok(())
 }
 
-pub fn ping_url(endpoint_url: &Url) -> Result<Json, VerifierError> {
-	// Query endpoint
-	let client = reqwest::blocking::Client::new();
+/// Commit checked out in the git repository containing `path`, if any
+pub fn git_revision<P: AsRef<Path>>(path: P) -> Option<String> {
+	let repository = Repository::discover(path).ok()?;
+	let commit = repository.head().ok()?.peel_to_commit().ok()?;
+	Some(commit.id().to_string())
+}
 
-	let response = match client.get(endpoint_url.clone()).send() {
-		Ok(response) if response.status().is_success() => response,
-		Ok(response) => {
-			if response.status().as_u16() == 405 {
-				match client.post(endpoint_url.clone()).send() {
-					Ok(response) if response.status().is_success() => response,
-					Ok(_) => return Err(VerifierError::UnresponsiveEndpoint(endpoint_url.clone())),
-					Err(e) => return Err(VerifierError::RequestError(e)),
-				}
-			}
-			else {
-				return Err(VerifierError::UnresponsiveEndpoint(endpoint_url.clone()));
-			}
-		},
-		Err(e) => {
-			return if e.is_status() {
-				log::error!("{:?}", e);
-				Err(error::VerifierError::BadStatus)
-			}
-			else {
-				log::error!("{:?}", e);
-				Err(error::VerifierError::RequestError(e))
-			};
-		},
-	};
+/// Stable hash (64-bit FNV-1a, hex) of a response, ignoring its `meta`, to detect changes of the data
+pub fn content_hash(response_json: &Json) -> String {
+	let mut normalized = response_json.clone();
+	if let Some(response) = normalized.as_object_mut() {
+		response.remove("meta");
+	}
+	// The keys of the objects are sorted, so equal documents always serialize the same way
+	format!("{:016x}", fnv1a(normalized.to_string().as_bytes()))
+}
 
-	let response_json = match response.json() {
-		Ok(response_json) => response_json,
-		Err(e) => {
-			log::error!("{:?}", e);
-			return Err(VerifierError::ResponseIsNotJson);
-		},
-	};
+/// 64-bit FNV-1a hash
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+	bytes.iter().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+		(hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+	})
+}
 
-	Ok(response_json)
+/// Letters and digits of `name` in lowercase, the other characters making dashes (e.g. the key of a TOML table)
+pub fn slug(name: &str) -> String {
+	name.split(|c: char| !c.is_ascii_alphanumeric())
+		.filter(|part| !part.is_empty())
+		.collect::<Vec<_>>()
+		.join("-")
+		.to_lowercase()
 }
 
 pub fn url_join(url1: &Url, url2: &Url) -> Url {
@@ -109,89 +104,228 @@ pub fn url_join(url1: &Url, url2: &Url) -> Url {
 	replaced_url
 }
 
+/// Names of the variables of a url template (e.g. `id` in `/individuals/{id}`)
+pub fn template_vars(url: &Url) -> Vec<String> {
+	url.as_str()
+		.split("%7B")
+		.skip(1)
+		.filter_map(|part| part.split_once("%7D").map(|(name, _)| name.to_string()))
+		.collect()
+}
+
 pub fn replace_vars(url: &Url, vars: Vec<(&str, &str)>) -> Url {
 	let mut url_string = url.to_string();
 	for (var_key, var_val) in vars {
-		url_string = url.to_string().replace(&format!("%7B{}%7D", var_key), var_val);
+		url_string = url_string.replace(&format!("%7B{}%7D", var_key), var_val);
 	}
 	Url::parse(&url_string).unwrap()
 }
 
-// TODO: Filtering terms
-// pub fn get_filtering_terms(url: &Url) -> Vec<FilteringTerm> {
-// 	// Query endpoint
-// 	match reqwest::blocking::get(url.as_str()) {
-// 		Ok(response) => {
-// 			let j = response.json().unwrap();
-// 			serde_json::from_value(j).unwrap()
-// 		},
-// 		Err(_) => Vec::new(),
-// 	}
-// }
+/// Ids harvested from the endpoint with all the entries of an entity
+#[derive(Debug, Clone)]
+pub enum Ids {
+	Found(Vec<String>),
+	/// The endpoint answered that it has no records, with its response as evidence
+	NoData(Json),
+	/// The endpoint answered without records (e.g. at count granularity), why and the response as evidence
+	NoRecords(String, Json),
+}
+
+/// Whether a response states that there are no records (`exists` is false or zero results)
+pub fn has_no_data(response_json: &Json) -> bool {
+	let summary = response_json.get("responseSummary");
+	let exists = summary
+		.and_then(|summary| summary.get("exists"))
+		.and_then(Json::as_bool);
+	let num_total_results = summary
+		.and_then(|summary| summary.get("numTotalResults"))
+		.and_then(Json::as_u64);
+	exists == Some(false) || num_total_results == Some(0)
+}
+
+/// Records (or collections) of a response, failing on the first missing or malformed part of the envelope
+pub fn records(response_json: &Json) -> Result<Vec<Json>, VerifierError> {
+	if let Some(collections) = response_json.pointer("/response/collections").and_then(Json::as_array) {
+		return Ok(collections.clone());
+	}
+	let result_sets = match response_json.pointer("/response/resultSets") {
+		Some(Json::Array(result_sets)) => result_sets,
+		Some(_) => return Err(VerifierError::MalformedResultSets),
+		None => return Err(VerifierError::MissingResultSets),
+	};
+	let mut records = Vec::new();
+	for (i, result_set) in result_sets.iter().enumerate() {
+		match result_set.get("results") {
+			Some(Json::Array(results)) => records.extend(results.iter().cloned()),
+			Some(_) => return Err(VerifierError::MalformedResults(i)),
+			None => return Err(VerifierError::MissingResults(i)),
+		}
+	}
+	Ok(records)
+}
+
+/// Id of a record, read from the property `id_field`
+pub fn record_id(record: &Json, id_field: &str) -> Option<String> {
+	match record.get(id_field)? {
+		Json::String(id) => Some(id.clone()),
+		Json::Number(id) => Some(id.to_string()),
+		_ => None,
+	}
+}
 
-pub fn get_ids(root_url: &Url, entity_url: &Url) -> Result<Vec<String>, VerifierError> {
+/// Ids of the records (or collections) of a response, read from the property `id_field`, failing when the endpoint
+/// does not answer, when its records cannot be read or when none of them has an id
+pub fn get_ids(
+	client: &Client,
+	entity_name: &str,
+	root_url: &Url,
+	entity_url: &Url,
+	id_field: &str,
+	declared: Option<Granularity>,
+) -> Result<Ids, VerifierError> {
 	let endpoint_url = url_join(root_url, entity_url);
-	match ping_url(&endpoint_url) {
+	match client.query(entity_name, &endpoint_url) {
+		Ok(response) if has_no_data(&response.json) => Ok(Ids::NoData(response.json)),
 		Ok(response) => {
-			let resultset_response: BeaconResultSetResponse =
-				serde_json::from_value(response).map_err(|e| -> VerifierError { e.into() })?;
-			Ok(resultset_response
-				.response
-				.result_sets
+			// The response tells its granularity, the declared one is only a fallback
+			let granularity = Granularity::returned(&response.json).or(declared);
+			if let Some(granularity) = granularity.filter(|granularity| !granularity.has_individual_records()) {
+				let reason = format!("{} granularity", granularity);
+				return Ok(Ids::NoRecords(reason, response.json));
+			}
+			let records = records(&response.json)?;
+			let ids = records
 				.iter()
-				.flat_map(|rs| rs.results.iter().map(EntityResult::id))
-				.collect())
-		},
-		Err(e) => {
-			log::error!("Error endpoint with the ids: {:?}", e);
-			Ok(Vec::new())
+				.filter_map(|record| record_id(record, id_field))
+				.collect::<Vec<_>>();
+			if ids.is_empty() && !records.is_empty() {
+				return Err(VerifierError::MissingIdProperty(id_field.into()));
+			}
+			Ok(Ids::Found(ids))
 		},
+		Err(e) => Err(e),
 	}
 }
 
-pub fn valid_schema(json_schema: &JSONSchema, instance: &Json) -> Result<Json, VerifierError> {
-	match json_schema.validate(instance) {
-		Ok(_) => {
-			log::info!("VALID");
-			Ok(instance.clone())
-		},
-		Err(errors) => {
-			log::error!("NOT VALID:");
-			let mut er = String::new();
-			errors.into_iter().for_each(|e| {
-				log::error!(
-					"   ERROR: {:?} - {} ({})",
-					e.kind,
-					e.to_string(),
-					e.instance_path.to_string(),
-				);
-				er.push_str(&format!("{} ({})", e, e.instance_path));
-				er.push('\n');
-			});
-			Err(VerifierError::BadResponse(er))
-		},
+/// Schema validation errors grouped by message and path (with the array indexes replaced by `*`)
+#[derive(Debug, Clone, Default)]
+pub struct SchemaErrors {
+	groups: BTreeMap<(String, String), usize>,
+}
+
+impl SchemaErrors {
+	pub fn push(&mut self, e: &ValidationError, prefix: &str) {
+		// Drop the offending value from the message so that identical errors group together
+		let instance = e.instance.to_string();
+		let message = e.to_string();
+		let message = match message.strip_prefix(&instance) {
+			Some(rest) if instance.len() > 1 => format!("value{}", rest),
+			_ => message,
+		};
+		let path = e
+			.instance_path
+			.to_string()
+			.split('/')
+			.map(|segment| {
+				if segment.parse::<usize>().is_ok() {
+					"*"
+				}
+				else {
+					segment
+				}
+			})
+			.collect::<Vec<_>>()
+			.join("/");
+		*self.groups.entry((message, format!("{}{}", prefix, path))).or_insert(0) += 1;
+	}
+
+	/// Records an error found outside of the schema validation (e.g. a malformed HGVS expression)
+	pub fn push_message(&mut self, message: String, path: &str) {
+		*self.groups.entry((message, path.to_string())).or_insert(0) += 1;
+	}
+
+	pub fn merge(&mut self, other: Self) {
+		for (group, occurrences) in other.groups {
+			*self.groups.entry(group).or_insert(0) += occurrences;
+		}
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.groups.is_empty()
 	}
 }
 
-pub fn compile_schema(schema: &Json) -> Rc<JSONSchema> {
-	let result_sets_schema = match jsonschema::JSONSchema::options().with_meta_schemas().compile(schema) {
-		Ok(schema) => schema,
+impl std::fmt::Display for SchemaErrors {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		for ((message, path), occurrences) in &self.groups {
+			if *occurrences == 1 {
+				writeln!(f, "{} ({})", message, path)?;
+			}
+			else {
+				writeln!(f, "{} at {} ({} occurrences)", message, path, occurrences)?;
+			}
+		}
+		Ok(())
+	}
+}
+
+/// Validates `instance`, prefixing the paths of the errors with `prefix`
+pub fn schema_errors(json_schema: &JSONSchema, instance: &Json, prefix: &str) -> SchemaErrors {
+	let mut schema_errors = SchemaErrors::default();
+	if let Err(errors) = json_schema.validate(instance) {
+		errors.for_each(|e| schema_errors.push(&e, prefix));
+	}
+	schema_errors
+}
+
+pub fn check_schema_errors(errors: SchemaErrors) -> Result<(), VerifierError> {
+	if errors.is_empty() {
+		log::info!("VALID");
+		Ok(())
+	}
+	else {
+		log::error!("NOT VALID:");
+		for line in errors.to_string().lines() {
+			log::error!("   ERROR: {}", line);
+		}
+		Err(VerifierError::BadResponse(errors.to_string()))
+	}
+}
+
+pub fn valid_schema(json_schema: &JSONSchema, instance: &Json) -> Result<Json, VerifierError> {
+	check_schema_errors(schema_errors(json_schema, instance, ""))?;
+	Ok(instance.clone())
+}
+
+/// Compiles a schema, whose references are resolved to the documents of `store`, the error naming the schema by its
+/// path in its repository (or its id)
+pub fn compile_schema(schema: &Json, formats: &Formats, store: &SchemaStore) -> Result<Arc<JSONSchema>, VerifierError> {
+	let mut options = jsonschema::JSONSchema::options();
+	formats.register(&mut options);
+	options.with_resolver(store.clone());
+	match options.with_meta_schemas().compile(schema) {
+		Ok(compiled) => Ok(Arc::new(compiled)),
 		Err(e) => {
-			log::error!("{:?}", e);
-			// TODO: Proper return
-			// return Err(VerifierError::BadSchema);
-			panic!("")
+			let id = schema.get("$id").or_else(|| schema.get("id")).and_then(Json::as_str);
+			let location = id.map_or("schema", |id| {
+				id.trim_start_matches(&format!("{}:///", resolver::SCHEME))
+			});
+			log::debug!("Compiling {} failed: {:?}", location, e);
+			Err(VerifierError::BadSchema(format!("{}: {}", location, e)))
 		},
-	};
-	Rc::new(result_sets_schema)
+	}
 }
 
 #[cfg(test)]
 mod tests {
 
+	use serde_json::json;
 	use url::Url;
 
-	use crate::utils::replace_vars;
+	use crate::error::VerifierError;
+	use crate::formats::Formats;
+	use crate::resolver::SchemaStore;
+	use crate::utils::{compile_schema, content_hash, records, replace_vars, schema_errors, SchemaErrors};
 
 	#[test]
 	fn test_replace_vars() {
@@ -201,4 +335,74 @@ mod tests {
 		);
 		assert_eq!(replaced.to_string(), "https://google.com/biosamples/my_id");
 	}
+
+	#[test]
+	fn test_records() {
+		let response = json!({ "response": { "resultSets": [{ "results": [{ "id": "ind1" }] }, { "results": [] }] } });
+		assert_eq!(records(&response).unwrap(), vec![json!({ "id": "ind1" })]);
+		let collections = json!({ "response": { "collections": [{ "id": "ds1" }] } });
+		assert_eq!(records(&collections).unwrap().len(), 1);
+		let missing = |response| records(&response).unwrap_err();
+		assert!(matches!(
+			missing(json!({ "meta": {} })),
+			VerifierError::MissingResultSets
+		));
+		assert!(matches!(
+			missing(json!({ "response": { "resultSets": {} } })),
+			VerifierError::MalformedResultSets
+		));
+		assert!(matches!(
+			missing(json!({ "response": { "resultSets": [{ "results": [] }, { "id": "ds1" }] } })),
+			VerifierError::MissingResults(1)
+		));
+		assert!(matches!(
+			missing(json!({ "response": { "resultSets": [{ "results": "ind1" }] } })),
+			VerifierError::MalformedResults(0)
+		));
+	}
+
+	#[test]
+	fn test_content_hash() {
+		let response = json!({
+			"meta": { "apiVersion": "v2.0.0" },
+			"responseSummary": { "exists": true, "numTotalResults": 1 },
+			"response": { "resultSets": [{ "id": "ds1", "results": [{ "id": "ind1" }] }] },
+		});
+		let same_data = json!({
+			"response": { "resultSets": [{ "results": [{ "id": "ind1" }], "id": "ds1" }] },
+			"responseSummary": { "numTotalResults": 1, "exists": true },
+			"meta": { "apiVersion": "v2.0.1" },
+		});
+		let mut other_data = response.clone();
+		other_data["response"]["resultSets"][0]["results"][0]["id"] = json!("ind2");
+		assert_eq!(content_hash(&response), content_hash(&same_data));
+		assert_ne!(content_hash(&response), content_hash(&other_data));
+		assert_eq!(content_hash(&response).len(), 16);
+	}
+
+	#[test]
+	fn test_group_schema_errors() {
+		let schema = compile_schema(
+			&json!({
+			"type": "object",
+			"required": ["id"],
+			"properties": { "age": { "type": "integer" } },
+			}),
+			&Formats::default(),
+			&SchemaStore::default(),
+		)
+		.unwrap();
+		let mut errors = SchemaErrors::default();
+		for record in [
+			json!({ "age": "1" }),
+			json!({ "age": "2" }),
+			json!({ "id": "x", "age": 3 }),
+		] {
+			errors.merge(schema_errors(&schema, &record, "/results/*"));
+		}
+		let errors = errors.to_string();
+		assert_eq!(errors.lines().count(), 2);
+		assert!(errors.contains("value is not of type \"integer\" at /results/*/age (2 occurrences)"));
+		assert!(errors.contains("\"id\" is a required property at /results/* (2 occurrences)"));
+	}
 }
@@ -0,0 +1,369 @@
+//! Conformance of the genomic variant queries
+//!
+//! Beacon v2 queries the genomic variants by their parameters: a sequence
+//! query (`referenceName`, `start`, `referenceBases` and `alternateBases`), a
+//! range query (`referenceName`, `start` and `end`), a bracket query (a `start`
+//! and an `end` that are each a pair of positions) and a `geneId` query. With
+//! `--variant-queries`, the entities of genomic variants are sent each type of
+//! query with the parameters of their first variant (those it does not have
+//! being the ones of a variant of BRCA1 on GRCh38), and the responses go
+//! through the checks of the endpoint. Each type of query is also sent at a
+//! position beyond the end of any chromosome, or with a gene that does not
+//! exist, and must find nothing.
+
+use url::Url;
+
+use crate::client::Client;
+use crate::error::VerifierError;
+use crate::formats::Formats;
+use crate::framework::ResponseSchemas;
+use crate::model::{Entity, Model};
+use crate::output::{Category, EndpointReport};
+use crate::{adaptive, utils, Json};
+
+/// Entry type of the entities queried
+pub const ENTRY_TYPE: &str = "genomicVariation";
+
+/// Name of the checks, after the name of the entity
+pub const CHECK_NAMES: [&str; 8] = [
+	"sequence query",
+	"range query",
+	"bracket query",
+	"geneId query",
+	"impossible sequence query",
+	"impossible range query",
+	"impossible bracket query",
+	"impossible geneId query",
+];
+
+/// Id of the checks in the catalog, of the queries and of the impossible queries
+const CHECK_IDS: [&str; 2] = ["framework.variants.query", "framework.variants.no-results"];
+
+/// Position beyond the end of any chromosome
+const IMPOSSIBLE_START: u64 = 1_000_000_000_000;
+
+/// Gene that does not exist
+const IMPOSSIBLE_GENE: &str = "BEACON-VERIFIER-NO-GENE";
+
+/// Positions around the variant of the brackets of a bracket query
+const BRACKET: u64 = 100;
+
+/// Parameters of the queries, taken from a variant
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Variant {
+	reference_name: String,
+	start: u64,
+	end: u64,
+	reference_bases: String,
+	alternate_bases: String,
+	gene_id: String,
+}
+
+impl Default for Variant {
+	/// A variant of BRCA1 on GRCh38
+	fn default() -> Self {
+		Self {
+			reference_name: "17".into(),
+			start: 43_045_703,
+			end: 43_045_704,
+			reference_bases: "G".into(),
+			alternate_bases: "A".into(),
+			gene_id: "BRCA1".into(),
+		}
+	}
+}
+
+impl Variant {
+	/// Parameters of a record of genomic variant, the ones it does not have being the default ones
+	fn from_record(record: &Json) -> Self {
+		let default = Self::default();
+		let text = |pointers: &[&str]| {
+			pointers
+				.iter()
+				.find_map(|pointer| record.pointer(pointer).and_then(Json::as_str))
+				.map(String::from)
+		};
+		let (reference_name, start, end) =
+			adaptive::location(record).unwrap_or((default.reference_name, default.start, default.end));
+		Self {
+			reference_name,
+			start,
+			end,
+			reference_bases: text(&["/variation/referenceBases"]).unwrap_or(default.reference_bases),
+			alternate_bases: text(&["/variation/alternateBases", "/variation/state/sequence"])
+				.unwrap_or(default.alternate_bases),
+			gene_id: text(&["/molecularAttributes/geneIds/0"]).unwrap_or(default.gene_id),
+		}
+	}
+
+	/// The same variant, beyond the end of any chromosome and in a gene that does not exist
+	fn impossible(&self) -> Self {
+		Self {
+			start: IMPOSSIBLE_START,
+			end: IMPOSSIBLE_START + (self.end - self.start.min(self.end)).max(1),
+			gene_id: IMPOSSIBLE_GENE.into(),
+			..self.clone()
+		}
+	}
+
+	/// Parameters of the query of the type `query` (the index of its check name)
+	fn params(&self, query: usize) -> Vec<(&'static str, String)> {
+		match query % 4 {
+			0 => vec![
+				("referenceName", self.reference_name.clone()),
+				("start", self.start.to_string()),
+				("referenceBases", self.reference_bases.clone()),
+				("alternateBases", self.alternate_bases.clone()),
+			],
+			1 => vec![
+				("referenceName", self.reference_name.clone()),
+				("start", self.start.to_string()),
+				("end", self.end.to_string()),
+			],
+			2 => vec![
+				("referenceName", self.reference_name.clone()),
+				(
+					"start",
+					format!("{},{}", self.start.saturating_sub(BRACKET), self.start),
+				),
+				("end", format!("{},{}", self.end, self.end + BRACKET)),
+			],
+			_ => vec![("geneId", self.gene_id.clone())],
+		}
+	}
+}
+
+/// Sends each type of variant query to `entity`, then the impossible ones, which must find nothing
+pub fn check(
+	client: &Client,
+	root_url: &Url,
+	entity: &Entity,
+	schemas: &ResponseSchemas,
+	formats: &Formats,
+	only: Option<Category>,
+) -> Vec<EndpointReport> {
+	let url = utils::url_join(root_url, &entity.url);
+	let variant = match client
+		.query(&entity.name, &url)
+		.and_then(|response| utils::records(&response.json))
+	{
+		Ok(records) => records.first().map(Variant::from_record).unwrap_or_default(),
+		Err(e) => {
+			log::warn!("No variant of {} to query: {}", entity.name, e);
+			Variant::default()
+		},
+	};
+	let impossible = variant.impossible();
+
+	let mut reports = Vec::new();
+	for (query, check_name) in CHECK_NAMES.iter().enumerate() {
+		let name = format!("{} {}", entity.name, check_name);
+		let mut url = url.clone();
+		let params = if query < 4 {
+			variant.params(query)
+		}
+		else {
+			impossible.params(query)
+		};
+		for (key, value) in params {
+			url.query_pairs_mut().append_pair(key, &value);
+		}
+		log::info!("Validating {:?}", name);
+		if query < 4 {
+			reports.extend(answer(client, root_url, entity, &name, &url, schemas, formats, only));
+		}
+		else if only != Some(Category::Model) {
+			reports.push(find_nothing(client, entity, &name, &url));
+		}
+	}
+	reports
+}
+
+/// Checks the response to a query like the ones of the endpoint
+#[allow(clippy::too_many_arguments)]
+fn answer(
+	client: &Client,
+	root_url: &Url,
+	entity: &Entity,
+	name: &str,
+	url: &Url,
+	schemas: &ResponseSchemas,
+	formats: &Formats,
+	only: Option<Category>,
+) -> Vec<EndpointReport> {
+	let response = match client.query(&entity.name, url) {
+		Ok(response) => response,
+		Err(e) => {
+			let report = EndpointReport::new(&entity.name, name, url.clone())
+				.category(Category::Framework)
+				.check(CHECK_IDS[0]);
+			return vec![report.null(e)];
+		},
+	};
+	let endpoint = Model::build_endpoint(
+		entity.name.clone(),
+		entity.entry_type.clone(),
+		entity.schemas.clone(),
+		name.to_string(),
+		&entity.url,
+		None,
+	);
+	endpoint
+		.validate(response, root_url, schemas, formats, only)
+		.into_iter()
+		.map(|report| match report.category {
+			Category::Framework => report.check(CHECK_IDS[0]),
+			Category::Model => report,
+		})
+		// With the parameters of the query
+		.map(|report| report.url(url.clone()))
+		.collect()
+}
+
+/// Sends an impossible query, which must be answered without results
+fn find_nothing(client: &Client, entity: &Entity, name: &str, url: &Url) -> EndpointReport {
+	let report = EndpointReport::new(&entity.name, name, url.clone())
+		.category(Category::Framework)
+		.check(CHECK_IDS[1]);
+	let response = match client.query(&entity.name, url) {
+		Ok(response) => response,
+		Err(e) => return report.null(e),
+	};
+	let report = report.elapsed(response.elapsed);
+	if utils::has_no_data(&response.json) {
+		return report.ok(None);
+	}
+	let total = response
+		.json
+		.pointer("/responseSummary/numTotalResults")
+		.and_then(Json::as_u64);
+	match (total, utils::records(&response.json)) {
+		(Some(total), _) => report.error(VerifierError::UnexpectedResults(format!("{} results", total))),
+		(None, Ok(records)) if !records.is_empty() => {
+			report.error(VerifierError::UnexpectedResults(format!("{} records", records.len())))
+		},
+		_ => report.error(VerifierError::UnexpectedResults(
+			"the response tells that some record exists".into(),
+		)),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use serde_json::json;
+	use url::Url;
+	use wiremock::{Request, ResponseTemplate};
+
+	use crate::client::Client;
+	use crate::formats::Formats;
+	use crate::model::Entity;
+	use crate::output::{Category, EndpointReport};
+	use crate::testing::{self, Behavior, MockBeacon};
+	use crate::variants::{self, Variant, CHECK_NAMES, ENTRY_TYPE};
+	use crate::Json;
+
+	fn variant() -> Json {
+		json!({
+			"variantInternalId": "var1",
+			"variation": {
+				"location": {
+					"sequence_id": "refseq:NC_000013.11",
+					"interval": { "start": { "value": 32338103 }, "end": { "value": 32338104 } },
+				},
+				"state": { "sequence": "T" },
+			},
+			"molecularAttributes": { "geneIds": ["BRCA2"] },
+		})
+	}
+
+	fn entity() -> Entity {
+		let mut entity = testing::model().entities.remove(0);
+		entity.name = "GenomicVariation".into();
+		entity.entry_type = ENTRY_TYPE.into();
+		entity.url = Url::parse("https://example.org/g_variants").unwrap();
+		entity.url_single = None;
+		entity
+	}
+
+	/// Serves the variant, but for the queries beyond its chromosome unless `any_gene`
+	fn serve(beacon: &MockBeacon, any_gene: bool) {
+		let mut found = beacon.response("/biosamples");
+		found["responseSummary"] = json!({ "exists": true, "numTotalResults": 1 });
+		found["response"]["resultSets"][0]["results"] = json!([variant()]);
+		beacon.mount("/g_variants", move |request: &Request| {
+			let impossible = request.url.query_pairs().any(|(key, value)| match key.as_ref() {
+				"start" => value
+					.split(',')
+					.any(|start| start.parse::<u64>().unwrap() > 1_000_000_000),
+				"geneId" => !any_gene && value != "BRCA2",
+				_ => false,
+			});
+			let mut response = found.clone();
+			if impossible {
+				response["responseSummary"] = json!({ "exists": false, "numTotalResults": 0 });
+				response["response"]["resultSets"][0]["results"] = json!([]);
+			}
+			ResponseTemplate::new(200).set_body_json(response)
+		});
+	}
+
+	#[test]
+	fn test_variant_params() {
+		let variant = Variant::from_record(&variant());
+		assert_eq!(variant.reference_name, "NC_000013.11");
+		assert_eq!((variant.start, variant.end), (32338103, 32338104));
+		assert_eq!(variant.reference_bases, Variant::default().reference_bases);
+		assert_eq!(variant.alternate_bases, "T");
+		assert_eq!(variant.gene_id, "BRCA2");
+		assert_eq!(variant.params(2)[1], ("start", "32338003,32338103".into()));
+		assert_eq!(variant.impossible().params(1)[2], ("end", "1000000000001".into()));
+		assert_eq!(Variant::from_record(&json!({ "id": "var1" })), Variant::default());
+	}
+
+	fn check(beacon: &MockBeacon) -> Vec<EndpointReport> {
+		let schemas = testing::framework().response_schemas(&Formats::default()).unwrap();
+		let only = Some(Category::Framework);
+		variants::check(
+			&Client::new(),
+			&beacon.url(),
+			&entity(),
+			&schemas,
+			&Formats::default(),
+			only,
+		)
+	}
+
+	#[test]
+	fn test_variant_queries() {
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		serve(&beacon, false);
+		let reports = check(&beacon);
+		assert_eq!(reports.len(), CHECK_NAMES.len());
+		for (report, name) in reports.iter().zip(CHECK_NAMES) {
+			assert_eq!(report.name, format!("GenomicVariation {}", name));
+			assert_eq!(report.valid, Some(true), "{}: {:?}", name, report.error);
+		}
+		let requests = beacon.received_requests();
+		let queries = requests
+			.iter()
+			.filter_map(|request| request.url.query())
+			.collect::<Vec<_>>();
+		assert!(queries.contains(&"referenceName=NC_000013.11&start=32338103&end=32338104"));
+		assert!(queries.contains(&"geneId=BRCA2"));
+
+		// Finds the variant whatever the gene
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		serve(&beacon, true);
+		let reports = check(&beacon);
+		let gene = reports.last().unwrap();
+		assert_eq!(gene.name, "GenomicVariation impossible geneId query");
+		assert_eq!(
+			gene.error.as_ref().map(ToString::to_string).as_deref(),
+			Some("The query cannot match any record, yet it returned results: 1 results")
+		);
+		let url = gene.url.as_ref().unwrap();
+		assert!(url.query().unwrap().contains("geneId=BEACON-VERIFIER-NO-GENE"));
+		assert_eq!(reports[5].valid, Some(true), "{:?}", reports[5].error);
+	}
+}
@@ -0,0 +1,247 @@
+//! Verification of a beacon, for the services embedding the verifier
+//!
+//! [`Verifier`] does what the command does for a single beacon, with the same
+//! defaults: the version of the specification is read from `/info` unless it
+//! is given, and selects the framework and the default model to load unless
+//! their locations are given. The schemas are loaded at every verification, so
+//! a service verifying several beacons with the same schemas can load them
+//! once and build the [`Beacon`] of each with [`Verifier::beacon_with`] instead.
+//! The verifier and its report are `Send` and `Sync`, so the verifications can
+//! run on the threads of the service.
+
+use url::Url;
+
+use crate::beacon::Beacon;
+use crate::cache::SchemaCache;
+use crate::client::Client;
+use crate::error::VerifierError;
+use crate::formats::Formats;
+use crate::framework::Framework;
+use crate::model::Model;
+use crate::output::BeaconOutput;
+use crate::spec::SpecVersion;
+
+pub struct Verifier {
+	url: Url,
+	client: Client,
+	spec_version: Option<SpecVersion>,
+	framework: Option<Url>,
+	model: Option<Url>,
+	only_framework: bool,
+	cache: SchemaCache,
+	formats: Formats,
+	jobs: Option<usize>,
+}
+
+impl Verifier {
+	/// Verifier of the beacon at `url`
+	pub fn new(url: &Url) -> Self {
+		Self {
+			url: url.clone(),
+			client: Client::new(),
+			spec_version: None,
+			framework: None,
+			model: None,
+			only_framework: false,
+			cache: SchemaCache::default(),
+			formats: Formats::default(),
+			jobs: None,
+		}
+	}
+
+	/// Client of the requests to the beacon (with its credentials, method policies, timeouts...)
+	pub fn client(mut self, client: Client) -> Self {
+		self.client = client;
+		self
+	}
+
+	/// Version of the specification of the beacon, instead of the one declared by its `/info`
+	pub fn spec_version(mut self, spec_version: SpecVersion) -> Self {
+		self.spec_version = Some(spec_version);
+		self
+	}
+
+	/// Location of the framework (a git repository or a directory)
+	pub fn framework(mut self, location: Url) -> Self {
+		self.framework = Some(location);
+		self
+	}
+
+	/// Location of the model (a git repository or a directory)
+	pub fn model(mut self, location: Url) -> Self {
+		self.model = Some(location);
+		self
+	}
+
+	/// Only run the checks of the framework, without loading a model
+	pub fn only_framework(mut self) -> Self {
+		self.only_framework = true;
+		self
+	}
+
+	/// Cache of the repositories of the schemas
+	pub fn schema_cache(mut self, cache: SchemaCache) -> Self {
+		self.cache = cache;
+		self
+	}
+
+	/// Formats of the values checked in the records
+	pub fn formats(mut self, formats: Formats) -> Self {
+		self.formats = formats;
+		self
+	}
+
+	/// Endpoints checked at the same time
+	pub fn jobs(mut self, jobs: usize) -> Self {
+		self.jobs = Some(jobs);
+		self
+	}
+
+	fn version(&self) -> SpecVersion {
+		self.spec_version
+			.or_else(|| SpecVersion::detect(&self.client, &self.url))
+			.unwrap_or(SpecVersion::DEFAULT)
+	}
+
+	/// Framework and model of the verification, loaded from their locations
+	pub fn load_schemas(&self) -> Result<(Framework, Option<Model>), VerifierError> {
+		let needs_version = self.framework.is_none() || (self.model.is_none() && !self.only_framework);
+		let version = if needs_version {
+			self.version()
+		}
+		else {
+			SpecVersion::DEFAULT
+		};
+		let framework_location = self.framework.clone().unwrap_or_else(|| version.framework());
+		log::debug!("Loading framework from: {}", framework_location);
+		let framework = Framework::load(&framework_location, &self.cache);
+		let model = (!self.only_framework).then(|| {
+			let model_location = self.model.clone().unwrap_or_else(|| version.model());
+			log::debug!("Loading model from: {}", model_location);
+			Model::load(&model_location, &self.formats, &self.cache)
+		});
+		loaded(framework, model)
+	}
+
+	/// Beacon to verify with the framework and the model already loaded, to enable more checks before validating it
+	pub fn beacon_with(&self, framework: Framework, model: Option<Model>) -> Result<Beacon, VerifierError> {
+		let beacon = Beacon::new(model, framework, self.client.fork(), &self.url)?.formats(self.formats.clone());
+		Ok(match self.jobs {
+			Some(jobs) => beacon.jobs(jobs),
+			None => beacon,
+		})
+	}
+
+	/// Loads the schemas and verifies the beacon with them
+	pub fn verify(self) -> Result<BeaconOutput, VerifierError> {
+		let (framework, model) = self.load_schemas()?;
+		Ok(self.beacon_with(framework, model)?.validate())
+	}
+}
+
+/// Framework and model once both are loaded, the broken schemas of both failing together
+pub fn loaded(
+	framework: Result<Framework, VerifierError>,
+	model: Option<Result<Model, VerifierError>>,
+) -> Result<(Framework, Option<Model>), VerifierError> {
+	match (framework, model.transpose()) {
+		(Ok(framework), Ok(model)) => Ok((framework, model)),
+		(Err(VerifierError::BrokenSchemas(framework)), Err(VerifierError::BrokenSchemas(model))) => {
+			Err(VerifierError::BrokenSchemas(format!("{}\n{}", framework, model)))
+		},
+		(Err(e), _) | (_, Err(e)) => Err(e),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use url::Url;
+
+	use crate::cache::SchemaCache;
+	use crate::error::VerifierError;
+	use crate::formats::Formats;
+	use crate::framework::Framework;
+	use crate::model::Model;
+	use crate::output::{BeaconOutput, Category};
+	use crate::spec::SpecVersion;
+	use crate::testing::{self, Behavior, MockBeacon};
+	use crate::utils;
+	use crate::verifier::{self, Verifier};
+
+	fn send_sync<T: Send + Sync>() {}
+
+	#[test]
+	fn test_verifier() {
+		send_sync::<Verifier>();
+		send_sync::<BeaconOutput>();
+
+		let beacon = MockBeacon::start(Behavior::Conformant);
+		let verifier = Verifier::new(&beacon.url())
+			.spec_version(SpecVersion::V2_0)
+			.framework(testing::fixture_url("framework"))
+			.model(testing::fixture_url("model"))
+			.jobs(2);
+		let output = std::thread::spawn(move || verifier.verify().unwrap()).join().unwrap();
+		let expected = beacon.verify();
+		assert_eq!(output.name, expected.name);
+		assert_eq!(
+			output.entities.keys().collect::<Vec<_>>(),
+			expected.entities.keys().collect::<Vec<_>>()
+		);
+		assert!(output
+			.entities
+			.values()
+			.flatten()
+			.all(|report| report.outcome().is_success()));
+
+		let output = Verifier::new(&beacon.url())
+			.framework(testing::fixture_url("framework"))
+			.only_framework()
+			.verify()
+			.unwrap();
+		assert!(output
+			.entities
+			.values()
+			.flatten()
+			.all(|report| report.category != Category::Model));
+	}
+
+	#[test]
+	fn test_broken_schemas() {
+		let dir = tempfile::tempdir().unwrap();
+		for name in ["framework", "model"] {
+			let fixture = testing::fixture_url(name).to_file_path().unwrap();
+			utils::copy_dir_recursively(fixture, dir.path().join(name)).unwrap();
+		}
+		let location = |name: &str| Url::from_directory_path(dir.path().join(name)).unwrap();
+		let cache = SchemaCache::default().offline();
+		let (framework, model) = verifier::loaded(
+			Framework::load(&location("framework"), &cache),
+			Some(Model::load(&location("model"), &Formats::default(), &cache)),
+		)
+		.unwrap();
+		assert!(framework.response_schemas(&Formats::default()).is_ok());
+		assert!(model.is_some());
+
+		// All the broken schemas are reported at once
+		let broken = r#"{ "type": 12 }"#;
+		std::fs::write(dir.path().join("framework/responses/beaconCountResponse.json"), broken).unwrap();
+		std::fs::write(dir.path().join("model/individuals/karyotypicSexSchema.json"), broken).unwrap();
+		std::fs::remove_file(dir.path().join("model/biosamples/defaultSchema.json")).unwrap();
+		let framework = Framework::load(&location("framework"), &cache);
+		let model = Model::load(&location("model"), &Formats::default(), &cache);
+		let e = verifier::loaded(framework, Some(model)).map(|_| ()).unwrap_err();
+		let message = e.to_string();
+		assert!(matches!(e, VerifierError::BrokenSchemas(_)), "{}", message);
+		let lines = message.lines().skip(1).collect::<Vec<_>>();
+		assert_eq!(lines.len(), 3, "{}", message);
+		assert!(lines[0].starts_with("Unable to compile the schema: responses/beaconCountResponse.json: "));
+		assert!(lines
+			.iter()
+			.any(|line| line.starts_with("Unable to compile the schema: individuals/karyotypicSexSchema.json: ")));
+		assert!(lines
+			.iter()
+			.any(|line| line.starts_with("Bad location of the schemas: ./biosamples/defaultSchema.json: ")));
+	}
+}